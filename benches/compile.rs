@@ -0,0 +1,141 @@
+//! Compile time and code size benchmark over a corpus of Cranelift IR files.
+//!
+//! Run it with `cargo bench --bench compile`. By default, the checked-in `filetests` directory is
+//! used as the corpus. Other directories can be given on the command line:
+//!
+//! ```text
+//! cargo bench --bench compile -- path/to/corpus
+//! ```
+//!
+//! Every function is compiled for each target ISA listed in its file. Files without a `target`
+//! line are skipped, as are verifier tests and `test compile unsupported` files, whose functions
+//! are expected to fail. For each ISA, the total compile time, the size of the emitted code, and
+//! the time spent in each pass are reported. Functions that fail to compile are counted, but they
+//! don't contribute to the totals. The file and function are printed when the compiler panics.
+
+use cranelift_codegen::timing;
+use cranelift_codegen::Context;
+use cranelift_reader::{parse_test, IsaSpec, TestOption};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The accumulated results for one target ISA.
+#[derive(Default)]
+struct IsaReport {
+    functions: usize,
+    failures: usize,
+    time: Duration,
+    code_size: u64,
+    passes: timing::PassTimes,
+}
+
+fn main() {
+    // Cargo passes `--bench` to the benchmark; everything else is a corpus directory.
+    let mut dirs: Vec<String> = env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with('-'))
+        .collect();
+    if dirs.is_empty() {
+        dirs.push(String::from("filetests"));
+    }
+
+    let mut files = Vec::new();
+    for dir in &dirs {
+        collect_files(Path::new(dir), &mut files);
+    }
+    files.sort();
+
+    let mut reports = BTreeMap::new();
+    for path in &files {
+        compile_file(path, &mut reports);
+    }
+
+    for (isa, report) in &reports {
+        println!(
+            "{}: {} functions ({} failed), {} bytes of code, compiled in {}.{:03} s",
+            isa,
+            report.functions,
+            report.failures,
+            report.code_size,
+            report.time.as_secs(),
+            report.time.subsec_millis()
+        );
+        print!("{}", report.passes);
+    }
+}
+
+/// Collect the `.clif` files in `path` and its subdirectories.
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries.filter_map(Result::ok) {
+                collect_files(&entry.path(), files);
+            }
+        }
+    } else if path.extension().map_or(false, |ext| ext == "clif") {
+        files.push(path.to_path_buf());
+    }
+}
+
+/// Compile the functions in the file at `path` for each of its target ISAs.
+fn compile_file(path: &Path, reports: &mut BTreeMap<&'static str, IsaReport>) {
+    let buffer = match fs::read_to_string(path) {
+        Ok(buffer) => buffer,
+        Err(_) => return,
+    };
+    let test_file = match parse_test(&buffer, None, None) {
+        Ok(test_file) => test_file,
+        Err(_) => return,
+    };
+    let isas = match test_file.isa_spec {
+        IsaSpec::Some(ref isas) => isas,
+        IsaSpec::None(_) => return,
+    };
+    if test_file.commands.iter().any(|command| {
+        command.command == "verifier"
+            || (command.command == "compile"
+                && command.options.contains(&TestOption::Flag("unsupported")))
+    }) {
+        return;
+    }
+
+    for (func, _) in &test_file.functions {
+        for isa in isas {
+            let report = reports.entry(isa.name()).or_default();
+            report.functions += 1;
+
+            let mut context = Context::new();
+            context.func = func.clone();
+
+            timing::take_current();
+            let start = Instant::now();
+            let result = panic::catch_unwind(AssertUnwindSafe(|| context.compile(&**isa)));
+            let time = start.elapsed();
+            let passes = timing::take_current();
+
+            match result {
+                Ok(Ok(code_size)) => {
+                    report.time += time;
+                    report.code_size += u64::from(code_size);
+                    timing::add_to_current(&report.passes);
+                    timing::add_to_current(&passes);
+                    report.passes = timing::take_current();
+                }
+                Ok(Err(_)) => report.failures += 1,
+                Err(_) => {
+                    eprintln!(
+                        "{}: compiling {} for {} panicked",
+                        path.display(),
+                        func.name,
+                        isa.name()
+                    );
+                    report.failures += 1;
+                }
+            }
+        }
+    }
+}
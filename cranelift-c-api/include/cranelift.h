@@ -0,0 +1,157 @@
+/*
+ * C API for Cranelift.
+ *
+ * All objects are opaque pointers which must be freed with the matching
+ * _free function. Functions that fail return NULL, -1, or CRANELIFT_INVALID,
+ * and cranelift_last_error() returns a message describing the error.
+ *
+ * Values, blocks, and callees of a function are identified by uint32_t
+ * handles which are only meaningful for the function that created them.
+ */
+
+#ifndef CRANELIFT_H
+#define CRANELIFT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define CRANELIFT_INVALID UINT32_MAX
+
+typedef struct CraneliftIsaBuilder cranelift_isa_builder;
+typedef struct CraneliftIsa cranelift_isa;
+typedef struct CraneliftFunction cranelift_function;
+typedef struct CraneliftCode cranelift_code;
+
+/* Value types. */
+typedef enum {
+    CRANELIFT_TYPE_I8 = 0,
+    CRANELIFT_TYPE_I16 = 1,
+    CRANELIFT_TYPE_I32 = 2,
+    CRANELIFT_TYPE_I64 = 3,
+    CRANELIFT_TYPE_F32 = 4,
+    CRANELIFT_TYPE_F64 = 5,
+    CRANELIFT_TYPE_B1 = 6,
+} cranelift_type;
+
+/* Binary operations for cranelift_binary. */
+typedef enum {
+    CRANELIFT_IADD = 0,
+    CRANELIFT_ISUB = 1,
+    CRANELIFT_IMUL = 2,
+    CRANELIFT_UDIV = 3,
+    CRANELIFT_SDIV = 4,
+    CRANELIFT_UREM = 5,
+    CRANELIFT_SREM = 6,
+    CRANELIFT_BAND = 7,
+    CRANELIFT_BOR = 8,
+    CRANELIFT_BXOR = 9,
+    CRANELIFT_ISHL = 10,
+    CRANELIFT_USHR = 11,
+    CRANELIFT_SSHR = 12,
+    CRANELIFT_FADD = 13,
+    CRANELIFT_FSUB = 14,
+    CRANELIFT_FMUL = 15,
+    CRANELIFT_FDIV = 16,
+} cranelift_binop;
+
+/* Integer condition codes for cranelift_icmp. */
+typedef enum {
+    CRANELIFT_EQ = 0,
+    CRANELIFT_NE = 1,
+    CRANELIFT_SLT = 2,
+    CRANELIFT_SGE = 3,
+    CRANELIFT_SGT = 4,
+    CRANELIFT_SLE = 5,
+    CRANELIFT_ULT = 6,
+    CRANELIFT_UGE = 7,
+    CRANELIFT_UGT = 8,
+    CRANELIFT_ULE = 9,
+} cranelift_intcc;
+
+/* Relocation kinds. */
+typedef enum {
+    CRANELIFT_RELOC_ABS4 = 0,
+    CRANELIFT_RELOC_ABS8 = 1,
+    CRANELIFT_RELOC_X86_PCREL4 = 2,
+    CRANELIFT_RELOC_X86_CALL_PCREL4 = 3,
+    CRANELIFT_RELOC_X86_CALL_PLTREL4 = 4,
+    CRANELIFT_RELOC_X86_GOTPCREL4 = 5,
+    CRANELIFT_RELOC_ELF_X86_64_TLSGD = 6,
+    CRANELIFT_RELOC_ELF_X86_64_GOTTPOFF = 7,
+    CRANELIFT_RELOC_ARM32_CALL = 8,
+    CRANELIFT_RELOC_ARM64_CALL = 9,
+    CRANELIFT_RELOC_RISCV_CALL = 10,
+} cranelift_reloc_kind;
+
+/* A relocation in the machine code of a compiled function. */
+typedef struct {
+    uint32_t offset;    /* Offset of the relocated bytes in the code. */
+    uint32_t kind;      /* A cranelift_reloc_kind. */
+    const char *name;   /* Name of the referenced symbol. */
+    int64_t addend;     /* Addend to add to the address of the symbol. */
+} cranelift_reloc;
+
+/* Errors. */
+const char *cranelift_last_error(void);
+
+/* Target ISAs. A NULL triple selects the host. */
+cranelift_isa_builder *cranelift_isa_builder_new(const char *triple);
+int cranelift_isa_builder_set(cranelift_isa_builder *builder, const char *name,
+                              const char *value);
+cranelift_isa *cranelift_isa_builder_finish(cranelift_isa_builder *builder);
+void cranelift_isa_builder_free(cranelift_isa_builder *builder);
+void cranelift_isa_free(cranelift_isa *isa);
+
+/* Functions. */
+cranelift_function *cranelift_function_new(const cranelift_isa *isa,
+                                           const uint32_t *params, size_t num_params,
+                                           const uint32_t *returns, size_t num_returns);
+void cranelift_function_free(cranelift_function *func);
+uint32_t cranelift_function_param(cranelift_function *func, size_t index);
+uint32_t cranelift_function_import(cranelift_function *func, const cranelift_isa *isa,
+                                   const char *name,
+                                   const uint32_t *params, size_t num_params,
+                                   const uint32_t *returns, size_t num_returns);
+char *cranelift_function_display(const cranelift_function *func, const cranelift_isa *isa);
+void cranelift_string_free(char *s);
+
+/* Blocks. */
+uint32_t cranelift_block_new(cranelift_function *func);
+uint32_t cranelift_block_append_param(cranelift_function *func, uint32_t block, uint32_t type);
+int cranelift_block_switch(cranelift_function *func, uint32_t block);
+
+/* Instructions, appended to the current block. */
+uint32_t cranelift_iconst(cranelift_function *func, uint32_t type, int64_t imm);
+uint32_t cranelift_f32const(cranelift_function *func, float imm);
+uint32_t cranelift_f64const(cranelift_function *func, double imm);
+uint32_t cranelift_binary(cranelift_function *func, uint32_t op, uint32_t x, uint32_t y);
+uint32_t cranelift_icmp(cranelift_function *func, uint32_t cond, uint32_t x, uint32_t y);
+uint32_t cranelift_load(cranelift_function *func, uint32_t type, uint32_t addr, int32_t offset);
+int cranelift_store(cranelift_function *func, uint32_t value, uint32_t addr, int32_t offset);
+int cranelift_call(cranelift_function *func, uint32_t callee,
+                   const uint32_t *args, size_t num_args,
+                   uint32_t *results, size_t max_results);
+int cranelift_jump(cranelift_function *func, uint32_t block,
+                   const uint32_t *args, size_t num_args);
+int cranelift_brz(cranelift_function *func, uint32_t cond, uint32_t block,
+                  const uint32_t *args, size_t num_args);
+int cranelift_brnz(cranelift_function *func, uint32_t cond, uint32_t block,
+                   const uint32_t *args, size_t num_args);
+int cranelift_return(cranelift_function *func, const uint32_t *values, size_t num_values);
+
+/* Compilation. */
+cranelift_code *cranelift_compile(const cranelift_isa *isa, const cranelift_function *func);
+const uint8_t *cranelift_code_bytes(const cranelift_code *code, size_t *len);
+size_t cranelift_code_reloc_count(const cranelift_code *code);
+const cranelift_reloc *cranelift_code_relocs(const cranelift_code *code);
+void cranelift_code_free(cranelift_code *code);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* CRANELIFT_H */
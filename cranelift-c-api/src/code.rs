@@ -0,0 +1,183 @@
+//! Compiling functions and retrieving their machine code.
+
+use crate::error::{guard, handle};
+use crate::function::CraneliftFunction;
+use crate::isa::CraneliftIsa;
+use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
+use cranelift_codegen::ir::{ExternalName, JumpTable};
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::Context;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::ptr;
+
+/// A relocation in the machine code of a compiled function.
+///
+/// This is the `cranelift_reloc` struct in `cranelift.h`.
+#[repr(C)]
+pub struct CraneliftReloc {
+    /// The offset of the relocated bytes in the code.
+    pub offset: u32,
+    /// The kind of relocation, a `cranelift_reloc_kind`.
+    pub kind: u32,
+    /// The name of the referenced symbol.
+    pub name: *const c_char,
+    /// The addend to add to the address of the symbol.
+    pub addend: i64,
+}
+
+/// The machine code of a compiled function, and its relocations.
+pub struct CraneliftCode {
+    bytes: Vec<u8>,
+    relocs: Vec<CraneliftReloc>,
+
+    /// The symbol names referenced by `relocs`.
+    names: Vec<CString>,
+}
+
+/// Translate a `Reloc` to a `cranelift_reloc_kind` from `cranelift.h`.
+fn reloc_kind(reloc: Reloc) -> u32 {
+    match reloc {
+        Reloc::Abs4 => 0,
+        Reloc::Abs8 => 1,
+        Reloc::X86PCRel4 => 2,
+        Reloc::X86CallPCRel4 => 3,
+        Reloc::X86CallPLTRel4 => 4,
+        Reloc::X86GOTPCRel4 => 5,
+        Reloc::ElfX86_64TlsGd => 6,
+        Reloc::ElfX86_64GotTpOff => 7,
+        Reloc::Arm32Call => 8,
+        Reloc::Arm64Call => 9,
+        Reloc::RiscvCall => 10,
+    }
+}
+
+/// A `RelocSink` collecting the relocations of a function in a `CraneliftCode`.
+///
+/// Relocations referencing EBBs or jump tables can't be represented in a `CraneliftReloc`, so
+/// the first one is recorded in `unsupported` and the compilation fails.
+struct CodeRelocSink<'a> {
+    code: &'a mut CraneliftCode,
+    callees: &'a [CString],
+    unsupported: Option<String>,
+}
+
+impl<'a> CodeRelocSink<'a> {
+    /// Record an unsupported relocation at `offset`, unless one has been found already.
+    fn unsupported(&mut self, offset: CodeOffset, reloc: Reloc, target: String) {
+        if self.unsupported.is_none() {
+            self.unsupported = Some(format!(
+                "unsupported {} relocation to {} at offset {}",
+                reloc, target, offset
+            ));
+        }
+    }
+}
+
+impl<'a> RelocSink for CodeRelocSink<'a> {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.unsupported(offset, reloc, format!("EBB at offset {}", ebb_offset));
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        let name = match *name {
+            ExternalName::User {
+                namespace: 0,
+                index,
+            } => self.callees[index as usize].clone(),
+            ref name => CString::new(name.to_string()).expect("C string"),
+        };
+        // The string data doesn't move when `names` grows.
+        self.code.relocs.push(CraneliftReloc {
+            offset,
+            kind: reloc_kind(reloc),
+            name: name.as_ptr(),
+            addend,
+        });
+        self.code.names.push(name);
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.unsupported(offset, reloc, jt.to_string());
+    }
+}
+
+/// Compile `func` for `isa`.
+///
+/// The function itself is left unchanged, so it can be compiled again for another ISA. Returns
+/// null if the function is invalid or can't be compiled, or if its code needs relocations which
+/// can't be represented as a `cranelift_reloc`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_compile(
+    isa: *const CraneliftIsa,
+    func: *const CraneliftFunction,
+) -> *mut CraneliftCode {
+    guard(ptr::null_mut(), || {
+        let isa = &*handle(isa)?.isa;
+        let f = handle(func)?;
+        let mut ctx = Context::for_function(f.func.clone());
+        let mut code = CraneliftCode {
+            bytes: Vec::new(),
+            relocs: Vec::new(),
+            names: Vec::new(),
+        };
+        let mut bytes = Vec::new();
+        let mut relocs = CodeRelocSink {
+            code: &mut code,
+            callees: &f.callees,
+            unsupported: None,
+        };
+        ctx.compile_and_emit(isa, &mut bytes, &mut relocs, &mut NullTrapSink {})
+            .map_err(|err| pretty_error(&ctx.func, Some(isa), err))?;
+        if let Some(msg) = relocs.unsupported {
+            return Err(msg);
+        }
+        code.bytes = bytes;
+        Ok(Box::into_raw(Box::new(code)))
+    })
+}
+
+/// Get the machine code of a compiled function, storing its size in `len`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_code_bytes(
+    code: *const CraneliftCode,
+    len: *mut usize,
+) -> *const u8 {
+    guard(ptr::null(), || {
+        let code = handle(code)?;
+        if !len.is_null() {
+            *len = code.bytes.len();
+        }
+        Ok(code.bytes.as_ptr())
+    })
+}
+
+/// Get the number of relocations in the machine code of a compiled function.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_code_reloc_count(code: *const CraneliftCode) -> usize {
+    guard(0, || Ok(handle(code)?.relocs.len()))
+}
+
+/// Get the array of relocations in the machine code of a compiled function.
+///
+/// The relocations are ordered by offset, and stay valid until the code is freed.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_code_relocs(
+    code: *const CraneliftCode,
+) -> *const CraneliftReloc {
+    guard(ptr::null(), || Ok(handle(code)?.relocs.as_ptr()))
+}
+
+/// Free a compiled function.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_code_free(code: *mut CraneliftCode) {
+    if !code.is_null() {
+        drop(Box::from_raw(code));
+    }
+}
@@ -0,0 +1,94 @@
+//! Error reporting across the C API.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+thread_local! {
+    /// The message of the last error on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Get the message describing the last error on the calling thread.
+///
+/// Returns a null pointer if no error has happened yet. The string stays valid until the next
+/// failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn cranelift_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |msg| msg.as_ptr()))
+}
+
+/// Record `msg` as the last error on this thread.
+fn set_error(msg: &str) {
+    let msg = CString::new(msg.replace('\0', "")).expect("no nul bytes left");
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+/// Get a message from the payload of a panic.
+fn panic_message(payload: &(Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        format!("panic: {}", s)
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        format!("panic: {}", s)
+    } else {
+        String::from("panic")
+    }
+}
+
+/// Run `f` for an API function.
+///
+/// If `f` fails or panics, the error is recorded for `cranelift_last_error`, and `error` is
+/// returned to the caller instead.
+pub fn guard<T, F>(error: T, f: F) -> T
+where
+    F: FnOnce() -> Result<T, String>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(msg)) => {
+            set_error(&msg);
+            error
+        }
+        Err(payload) => {
+            set_error(&panic_message(&*payload));
+            error
+        }
+    }
+}
+
+/// Get a reference to the object behind a handle passed in by the caller.
+pub unsafe fn handle<'a, T>(ptr: *const T) -> Result<&'a T, String> {
+    ptr.as_ref().ok_or_else(|| String::from("null handle"))
+}
+
+/// Get a mutable reference to the object behind a handle passed in by the caller.
+pub unsafe fn handle_mut<'a, T>(ptr: *mut T) -> Result<&'a mut T, String> {
+    ptr.as_mut().ok_or_else(|| String::from("null handle"))
+}
+
+/// Get a string passed in by the caller.
+pub unsafe fn string<'a>(ptr: *const c_char) -> Result<&'a str, String> {
+    if ptr.is_null() {
+        return Err(String::from("null string"));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| String::from("string is not valid UTF-8"))
+}
+
+/// Get an array passed in by the caller.
+///
+/// The pointer may be null when `len` is 0.
+pub unsafe fn array<'a, T>(ptr: *const T, len: usize) -> Result<&'a [T], String> {
+    if len == 0 {
+        Ok(&[])
+    } else if ptr.is_null() {
+        Err(String::from("null array"))
+    } else {
+        Ok(slice::from_raw_parts(ptr, len))
+    }
+}
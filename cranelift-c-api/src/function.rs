@@ -0,0 +1,477 @@
+//! Building functions.
+//!
+//! The builder works directly on SSA form: values are defined once, and values flowing between
+//! blocks are passed as block parameters. Values, blocks, and callees are identified by `u32`
+//! handles which are only meaningful for the function that created them.
+
+use crate::error::{array, guard, handle, handle_mut, string};
+use crate::isa::CraneliftIsa;
+use crate::CRANELIFT_INVALID;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::{
+    types, AbiParam, Ebb, ExtFuncData, ExternalName, FuncRef, Function, InstBuilder, MemFlags,
+    Signature, Type, Value,
+};
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+
+/// A function being built.
+pub struct CraneliftFunction {
+    pub(crate) func: Function,
+
+    /// The block where new instructions are appended.
+    block: Ebb,
+
+    /// The names of the imported functions, indexed by the index of their `ExternalName::User`.
+    pub(crate) callees: Vec<CString>,
+}
+
+impl CraneliftFunction {
+    fn value(&self, handle: u32) -> Result<Value, String> {
+        if handle != CRANELIFT_INVALID && self.func.dfg.value_is_valid(Value::from_u32(handle)) {
+            Ok(Value::from_u32(handle))
+        } else {
+            Err(format!("invalid value handle {}", handle))
+        }
+    }
+
+    unsafe fn values(&self, handles: *const u32, len: usize) -> Result<Vec<Value>, String> {
+        array(handles, len)?
+            .iter()
+            .map(|&v| self.value(v))
+            .collect()
+    }
+
+    fn block(&self, handle: u32) -> Result<Ebb, String> {
+        if handle != CRANELIFT_INVALID && self.func.dfg.ebb_is_valid(Ebb::from_u32(handle)) {
+            Ok(Ebb::from_u32(handle))
+        } else {
+            Err(format!("invalid block handle {}", handle))
+        }
+    }
+
+    fn callee(&self, handle: u32) -> Result<FuncRef, String> {
+        if (handle as usize) < self.callees.len() {
+            Ok(FuncRef::from_u32(handle))
+        } else {
+            Err(format!("invalid callee handle {}", handle))
+        }
+    }
+
+    /// Get a cursor for appending instructions to the current block.
+    fn cursor(&mut self) -> FuncCursor {
+        let block = self.block;
+        FuncCursor::new(&mut self.func).at_bottom(block)
+    }
+}
+
+/// Translate a `cranelift_type` from `cranelift.h`.
+fn value_type(ty: u32) -> Result<Type, String> {
+    Ok(match ty {
+        0 => types::I8,
+        1 => types::I16,
+        2 => types::I32,
+        3 => types::I64,
+        4 => types::F32,
+        5 => types::F64,
+        6 => types::B1,
+        _ => return Err(format!("invalid type {}", ty)),
+    })
+}
+
+/// Translate a `cranelift_intcc` from `cranelift.h`.
+fn intcc(cond: u32) -> Result<IntCC, String> {
+    Ok(match cond {
+        0 => IntCC::Equal,
+        1 => IntCC::NotEqual,
+        2 => IntCC::SignedLessThan,
+        3 => IntCC::SignedGreaterThanOrEqual,
+        4 => IntCC::SignedGreaterThan,
+        5 => IntCC::SignedLessThanOrEqual,
+        6 => IntCC::UnsignedLessThan,
+        7 => IntCC::UnsignedGreaterThanOrEqual,
+        8 => IntCC::UnsignedGreaterThan,
+        9 => IntCC::UnsignedLessThanOrEqual,
+        _ => return Err(format!("invalid condition code {}", cond)),
+    })
+}
+
+unsafe fn signature(
+    isa: &CraneliftIsa,
+    params: *const u32,
+    num_params: usize,
+    returns: *const u32,
+    num_returns: usize,
+) -> Result<Signature, String> {
+    let mut sig = Signature::new(isa.isa.default_call_conv());
+    for &ty in array(params, num_params)? {
+        sig.params.push(AbiParam::new(value_type(ty)?));
+    }
+    for &ty in array(returns, num_returns)? {
+        sig.returns.push(AbiParam::new(value_type(ty)?));
+    }
+    Ok(sig)
+}
+
+/// Run `build` on a function, returning the handle of the value it defines.
+unsafe fn build_value<F>(func: *mut CraneliftFunction, build: F) -> u32
+where
+    F: FnOnce(&mut CraneliftFunction) -> Result<Value, String>,
+{
+    guard(CRANELIFT_INVALID, || Ok(build(handle_mut(func)?)?.as_u32()))
+}
+
+/// Run `build` on a function, returning 0 on success.
+unsafe fn build_inst<F>(func: *mut CraneliftFunction, build: F) -> c_int
+where
+    F: FnOnce(&mut CraneliftFunction) -> Result<(), String>,
+{
+    guard(-1, || build(handle_mut(func)?).map(|()| 0))
+}
+
+/// Create a function with the given parameter and return types, using the default calling
+/// convention of `isa`.
+///
+/// The new function has an entry block, which is the current block, and whose parameters are the
+/// function's parameters.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_function_new(
+    isa: *const CraneliftIsa,
+    params: *const u32,
+    num_params: usize,
+    returns: *const u32,
+    num_returns: usize,
+) -> *mut CraneliftFunction {
+    guard(ptr::null_mut(), || {
+        let sig = signature(handle(isa)?, params, num_params, returns, num_returns)?;
+        let param_types: Vec<Type> = sig.params.iter().map(|p| p.value_type).collect();
+        let mut func = Function::with_name_signature(ExternalName::default(), sig);
+        let block = func.dfg.make_ebb();
+        for ty in param_types {
+            func.dfg.append_ebb_param(block, ty);
+        }
+        func.layout.append_ebb(block);
+        Ok(Box::into_raw(Box::new(CraneliftFunction {
+            func,
+            block,
+            callees: Vec::new(),
+        })))
+    })
+}
+
+/// Free a function.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_function_free(func: *mut CraneliftFunction) {
+    if !func.is_null() {
+        drop(Box::from_raw(func));
+    }
+}
+
+/// Get the value of the function parameter `index`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_function_param(
+    func: *mut CraneliftFunction,
+    index: usize,
+) -> u32 {
+    build_value(func, |f| {
+        let entry = f.func.layout.entry_block().expect("entry block");
+        f.func
+            .dfg
+            .ebb_params(entry)
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("no function parameter {}", index))
+    })
+}
+
+/// Declare an external function named `name` with the given signature, so it can be called with
+/// `cranelift_call`.
+///
+/// Returns a callee handle. Calls to the function are reported as relocations referencing `name`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_function_import(
+    func: *mut CraneliftFunction,
+    isa: *const CraneliftIsa,
+    name: *const c_char,
+    params: *const u32,
+    num_params: usize,
+    returns: *const u32,
+    num_returns: usize,
+) -> u32 {
+    guard(CRANELIFT_INVALID, || {
+        let f = handle_mut(func)?;
+        let name = CString::new(string(name)?).expect("C string");
+        let sig = signature(handle(isa)?, params, num_params, returns, num_returns)?;
+        let signature = f.func.import_signature(sig);
+        let index = f.callees.len() as u32;
+        f.callees.push(name);
+        let callee = f.func.import_function(ExtFuncData {
+            name: ExternalName::user(0, index),
+            signature,
+            colocated: false,
+        });
+        Ok(callee.as_u32())
+    })
+}
+
+/// Get the function in the text format, for debugging.
+///
+/// The string must be freed with `cranelift_string_free`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_function_display(
+    func: *const CraneliftFunction,
+    isa: *const CraneliftIsa,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let f = handle(func)?;
+        let isa = isa.as_ref().map(|isa| &*isa.isa);
+        let text = f.func.display(isa).to_string();
+        Ok(CString::new(text).expect("C string").into_raw())
+    })
+}
+
+/// Free a string returned by `cranelift_function_display`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Create a new block at the end of the function.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_block_new(func: *mut CraneliftFunction) -> u32 {
+    guard(CRANELIFT_INVALID, || {
+        let f = handle_mut(func)?;
+        let block = f.func.dfg.make_ebb();
+        f.func.layout.append_ebb(block);
+        Ok(block.as_u32())
+    })
+}
+
+/// Append a parameter of type `ty` to `block`, returning its value.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_block_append_param(
+    func: *mut CraneliftFunction,
+    block: u32,
+    ty: u32,
+) -> u32 {
+    build_value(func, |f| {
+        let block = f.block(block)?;
+        Ok(f.func.dfg.append_ebb_param(block, value_type(ty)?))
+    })
+}
+
+/// Make `block` the current block, so new instructions are appended to it.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_block_switch(func: *mut CraneliftFunction, block: u32) -> c_int {
+    build_inst(func, |f| {
+        f.block = f.block(block)?;
+        Ok(())
+    })
+}
+
+/// Append an integer constant of type `ty`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_iconst(func: *mut CraneliftFunction, ty: u32, imm: i64) -> u32 {
+    build_value(func, |f| {
+        let ty = value_type(ty)?;
+        Ok(f.cursor().ins().iconst(ty, imm))
+    })
+}
+
+/// Append a 32-bit floating point constant.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_f32const(func: *mut CraneliftFunction, imm: f32) -> u32 {
+    build_value(func, |f| {
+        Ok(f.cursor().ins().f32const(Ieee32::with_float(imm)))
+    })
+}
+
+/// Append a 64-bit floating point constant.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_f64const(func: *mut CraneliftFunction, imm: f64) -> u32 {
+    build_value(func, |f| {
+        Ok(f.cursor().ins().f64const(Ieee64::with_float(imm)))
+    })
+}
+
+/// Append the binary operation `op`, a `cranelift_binop` from `cranelift.h`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_binary(
+    func: *mut CraneliftFunction,
+    op: u32,
+    x: u32,
+    y: u32,
+) -> u32 {
+    build_value(func, |f| {
+        let x = f.value(x)?;
+        let y = f.value(y)?;
+        let mut pos = f.cursor();
+        Ok(match op {
+            0 => pos.ins().iadd(x, y),
+            1 => pos.ins().isub(x, y),
+            2 => pos.ins().imul(x, y),
+            3 => pos.ins().udiv(x, y),
+            4 => pos.ins().sdiv(x, y),
+            5 => pos.ins().urem(x, y),
+            6 => pos.ins().srem(x, y),
+            7 => pos.ins().band(x, y),
+            8 => pos.ins().bor(x, y),
+            9 => pos.ins().bxor(x, y),
+            10 => pos.ins().ishl(x, y),
+            11 => pos.ins().ushr(x, y),
+            12 => pos.ins().sshr(x, y),
+            13 => pos.ins().fadd(x, y),
+            14 => pos.ins().fsub(x, y),
+            15 => pos.ins().fmul(x, y),
+            16 => pos.ins().fdiv(x, y),
+            _ => return Err(format!("invalid binary operation {}", op)),
+        })
+    })
+}
+
+/// Append an integer comparison with the condition `cond`, a `cranelift_intcc` from
+/// `cranelift.h`. The result has type `CRANELIFT_TYPE_B1`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_icmp(
+    func: *mut CraneliftFunction,
+    cond: u32,
+    x: u32,
+    y: u32,
+) -> u32 {
+    build_value(func, |f| {
+        let cond = intcc(cond)?;
+        let x = f.value(x)?;
+        let y = f.value(y)?;
+        Ok(f.cursor().ins().icmp(cond, x, y))
+    })
+}
+
+/// Append a load of a value of type `ty` from `addr + offset`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_load(
+    func: *mut CraneliftFunction,
+    ty: u32,
+    addr: u32,
+    offset: i32,
+) -> u32 {
+    build_value(func, |f| {
+        let ty = value_type(ty)?;
+        let addr = f.value(addr)?;
+        Ok(f.cursor().ins().load(ty, MemFlags::new(), addr, offset))
+    })
+}
+
+/// Append a store of `value` to `addr + offset`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_store(
+    func: *mut CraneliftFunction,
+    value: u32,
+    addr: u32,
+    offset: i32,
+) -> c_int {
+    build_inst(func, |f| {
+        let value = f.value(value)?;
+        let addr = f.value(addr)?;
+        f.cursor().ins().store(MemFlags::new(), value, addr, offset);
+        Ok(())
+    })
+}
+
+/// Append a call to `callee`, which was declared by `cranelift_function_import`.
+///
+/// Up to `max_results` of the call's results are stored in `results`. Returns the number of
+/// results of the call, or -1 on failure.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_call(
+    func: *mut CraneliftFunction,
+    callee: u32,
+    args: *const u32,
+    num_args: usize,
+    results: *mut u32,
+    max_results: usize,
+) -> c_int {
+    guard(-1, || {
+        let f = handle_mut(func)?;
+        let callee = f.callee(callee)?;
+        let args = f.values(args, num_args)?;
+        let inst = f.cursor().ins().call(callee, &args);
+        let values = f.func.dfg.inst_results(inst);
+        if !results.is_null() {
+            for (i, value) in values.iter().take(max_results).enumerate() {
+                *results.add(i) = value.as_u32();
+            }
+        }
+        Ok(values.len() as c_int)
+    })
+}
+
+/// Append a jump to `block`, passing `args` as the block's parameters.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_jump(
+    func: *mut CraneliftFunction,
+    block: u32,
+    args: *const u32,
+    num_args: usize,
+) -> c_int {
+    build_inst(func, |f| {
+        let block = f.block(block)?;
+        let args = f.values(args, num_args)?;
+        f.cursor().ins().jump(block, &args);
+        Ok(())
+    })
+}
+
+/// Append a branch to `block` which is taken when `cond` is zero or false.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_brz(
+    func: *mut CraneliftFunction,
+    cond: u32,
+    block: u32,
+    args: *const u32,
+    num_args: usize,
+) -> c_int {
+    build_inst(func, |f| {
+        let cond = f.value(cond)?;
+        let block = f.block(block)?;
+        let args = f.values(args, num_args)?;
+        f.cursor().ins().brz(cond, block, &args);
+        Ok(())
+    })
+}
+
+/// Append a branch to `block` which is taken when `cond` is non-zero or true.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_brnz(
+    func: *mut CraneliftFunction,
+    cond: u32,
+    block: u32,
+    args: *const u32,
+    num_args: usize,
+) -> c_int {
+    build_inst(func, |f| {
+        let cond = f.value(cond)?;
+        let block = f.block(block)?;
+        let args = f.values(args, num_args)?;
+        f.cursor().ins().brnz(cond, block, &args);
+        Ok(())
+    })
+}
+
+/// Append a return of `values` from the function.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_return(
+    func: *mut CraneliftFunction,
+    values: *const u32,
+    num_values: usize,
+) -> c_int {
+    build_inst(func, |f| {
+        let values = f.values(values, num_values)?;
+        f.cursor().ins().return_(&values);
+        Ok(())
+    })
+}
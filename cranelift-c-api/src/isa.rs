@@ -0,0 +1,104 @@
+//! Configuring the target ISA.
+
+use crate::error::{guard, handle, handle_mut, string};
+use cranelift_codegen::isa::{self, TargetIsa};
+use cranelift_codegen::settings::{self, Configurable, SetError};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+/// A builder for a `CraneliftIsa`, holding the target and its settings.
+pub struct CraneliftIsaBuilder {
+    isa: isa::Builder,
+    flags: settings::Builder,
+}
+
+/// A target ISA with its settings, used to compile functions.
+pub struct CraneliftIsa {
+    pub(crate) isa: Box<TargetIsa>,
+}
+
+/// Create a builder for the ISA described by the target `triple`, like "x86_64-unknown-linux".
+///
+/// If `triple` is null, the builder targets the host, with the ISA settings detected from the
+/// host CPU. Returns null if the target isn't supported.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_isa_builder_new(
+    triple: *const c_char,
+) -> *mut CraneliftIsaBuilder {
+    guard(ptr::null_mut(), || {
+        let isa = if triple.is_null() {
+            cranelift_native::builder()?
+        } else {
+            let triple = Triple::from_str(string(triple)?).map_err(|e| e.to_string())?;
+            isa::lookup(triple).map_err(|e| e.to_string())?
+        };
+        Ok(Box::into_raw(Box::new(CraneliftIsaBuilder {
+            isa,
+            flags: settings::builder(),
+        })))
+    })
+}
+
+/// Change the setting `name` to `value`.
+///
+/// Both the shared settings and the settings of the ISA can be changed. Boolean settings can be
+/// given a null `value` to enable them. Returns 0 on success, or -1 if the setting doesn't exist
+/// or the value isn't valid.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_isa_builder_set(
+    builder: *mut CraneliftIsaBuilder,
+    name: *const c_char,
+    value: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let builder = handle_mut(builder)?;
+        let name = string(name)?;
+        let result = if value.is_null() {
+            match builder.isa.enable(name) {
+                Err(SetError::BadName(_)) => builder.flags.enable(name),
+                result => result,
+            }
+        } else {
+            let value = string(value)?;
+            match builder.isa.set(name, value) {
+                Err(SetError::BadName(_)) => builder.flags.set(name, value),
+                result => result,
+            }
+        };
+        result.map_err(|e| format!("{}: {}", name, e))?;
+        Ok(0)
+    })
+}
+
+/// Create the ISA configured by `builder`.
+///
+/// The builder is consumed, even if this fails, so it must not be used or freed afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_isa_builder_finish(
+    builder: *mut CraneliftIsaBuilder,
+) -> *mut CraneliftIsa {
+    guard(ptr::null_mut(), || {
+        handle(builder)?;
+        let builder = Box::from_raw(builder);
+        let isa = builder.isa.finish(settings::Flags::new(builder.flags));
+        Ok(Box::into_raw(Box::new(CraneliftIsa { isa })))
+    })
+}
+
+/// Free an ISA builder which wasn't passed to `cranelift_isa_builder_finish`.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_isa_builder_free(builder: *mut CraneliftIsaBuilder) {
+    if !builder.is_null() {
+        drop(Box::from_raw(builder));
+    }
+}
+
+/// Free an ISA.
+#[no_mangle]
+pub unsafe extern "C" fn cranelift_isa_free(isa: *mut CraneliftIsa) {
+    if !isa.is_null() {
+        drop(Box::from_raw(isa));
+    }
+}
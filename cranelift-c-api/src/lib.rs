@@ -0,0 +1,61 @@
+//! C API for Cranelift.
+//!
+//! This crate exposes an `extern "C"` interface for embedding Cranelift in programs that aren't
+//! written in Rust. The declarations for C and C++ are in `include/cranelift.h`.
+//!
+//! All of the objects created through the API are opaque pointers which must be freed with the
+//! matching `_free` function. Functions that fail return a null pointer, `-1`, or
+//! `CRANELIFT_INVALID`, and `cranelift_last_error` describes the error. Panics are caught before
+//! they reach the caller and are reported in the same way.
+
+#![deny(
+    missing_docs,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unstable_features
+)]
+#![warn(unused_import_braces)]
+#![cfg_attr(feature = "clippy", plugin(clippy(conf_file = "../../clippy.toml")))]
+#![cfg_attr(feature = "cargo-clippy", allow(clippy::new_without_default))]
+#![cfg_attr(
+    feature = "cargo-clippy",
+    warn(
+        clippy::float_arithmetic,
+        clippy::mut_mut,
+        clippy::nonminimal_bool,
+        clippy::option_map_unwrap_or,
+        clippy::option_map_unwrap_or_else,
+        clippy::print_stdout,
+        clippy::unicode_not_nfc,
+        clippy::use_self
+    )
+)]
+
+mod code;
+mod error;
+mod function;
+mod isa;
+
+pub use crate::code::{
+    cranelift_code_bytes, cranelift_code_free, cranelift_code_reloc_count, cranelift_code_relocs,
+    cranelift_compile, CraneliftCode, CraneliftReloc,
+};
+pub use crate::error::cranelift_last_error;
+pub use crate::function::{
+    cranelift_binary, cranelift_block_append_param, cranelift_block_new, cranelift_block_switch,
+    cranelift_brnz, cranelift_brz, cranelift_call, cranelift_f32const, cranelift_f64const,
+    cranelift_function_display, cranelift_function_free, cranelift_function_import,
+    cranelift_function_new, cranelift_function_param, cranelift_icmp, cranelift_iconst,
+    cranelift_jump, cranelift_load, cranelift_return, cranelift_store, cranelift_string_free,
+    CraneliftFunction,
+};
+pub use crate::isa::{
+    cranelift_isa_builder_finish, cranelift_isa_builder_free, cranelift_isa_builder_new,
+    cranelift_isa_builder_set, cranelift_isa_free, CraneliftIsa, CraneliftIsaBuilder,
+};
+
+/// The handle returned for a value, block, or callee that couldn't be created.
+pub const CRANELIFT_INVALID: u32 = ::std::u32::MAX;
+
+/// Version number of this crate.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
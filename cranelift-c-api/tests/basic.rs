@@ -0,0 +1,134 @@
+use cranelift_c_api::*;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::slice;
+
+const I32: u32 = 2;
+const IADD: u32 = 0;
+
+unsafe fn isa() -> *mut CraneliftIsa {
+    let triple = CString::new("x86_64").unwrap();
+    let builder = cranelift_isa_builder_new(triple.as_ptr());
+    assert!(!builder.is_null());
+    let isa = cranelift_isa_builder_finish(builder);
+    assert!(!isa.is_null());
+    isa
+}
+
+unsafe fn last_error() -> String {
+    CStr::from_ptr(cranelift_last_error())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[test]
+fn compile_call() {
+    unsafe {
+        let isa = isa();
+        let func = cranelift_function_new(isa, [I32, I32].as_ptr(), 2, [I32].as_ptr(), 1);
+        assert!(!func.is_null());
+
+        let name = CString::new("callee").unwrap();
+        let callee = cranelift_function_import(
+            func,
+            isa,
+            name.as_ptr(),
+            [I32].as_ptr(),
+            1,
+            [I32].as_ptr(),
+            1,
+        );
+        assert_ne!(callee, CRANELIFT_INVALID);
+
+        let x = cranelift_function_param(func, 0);
+        let y = cranelift_function_param(func, 1);
+        let sum = cranelift_binary(func, IADD, x, y);
+        assert_ne!(sum, CRANELIFT_INVALID);
+        let mut result = CRANELIFT_INVALID;
+        assert_eq!(cranelift_call(func, callee, &sum, 1, &mut result, 1), 1);
+        assert_eq!(cranelift_return(func, &result, 1), 0);
+
+        let code = cranelift_compile(isa, func);
+        assert!(!code.is_null(), "{}", last_error());
+        let mut len = 0;
+        assert!(!cranelift_code_bytes(code, &mut len).is_null());
+        assert!(len > 0);
+
+        let relocs = slice::from_raw_parts(
+            cranelift_code_relocs(code),
+            cranelift_code_reloc_count(code),
+        );
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(CStr::from_ptr(relocs[0].name).to_str(), Ok("callee"));
+        assert!((relocs[0].offset as usize) < len);
+
+        cranelift_code_free(code);
+        cranelift_function_free(func);
+        cranelift_isa_free(isa);
+    }
+}
+
+#[test]
+fn compile_loop() {
+    unsafe {
+        let isa = isa();
+        let func = cranelift_function_new(isa, [I32].as_ptr(), 1, [I32].as_ptr(), 1);
+        let n = cranelift_function_param(func, 0);
+        let zero = cranelift_iconst(func, I32, 0);
+        let header = cranelift_block_new(func);
+        let i = cranelift_block_append_param(func, header, I32);
+        let exit = cranelift_block_new(func);
+        assert_eq!(cranelift_jump(func, header, &zero, 1), 0);
+
+        assert_eq!(cranelift_block_switch(func, header), 0);
+        let one = cranelift_iconst(func, I32, 1);
+        let next = cranelift_binary(func, IADD, i, one);
+        let done = cranelift_icmp(func, 0, next, n);
+        assert_eq!(cranelift_brnz(func, done, exit, ptr::null(), 0), 0);
+        assert_eq!(cranelift_jump(func, header, &next, 1), 0);
+
+        assert_eq!(cranelift_block_switch(func, exit), 0);
+        assert_eq!(cranelift_return(func, &next, 1), 0);
+
+        let code = cranelift_compile(isa, func);
+        assert!(!code.is_null(), "{}", last_error());
+        assert_eq!(cranelift_code_reloc_count(code), 0);
+
+        cranelift_code_free(code);
+        cranelift_function_free(func);
+        cranelift_isa_free(isa);
+    }
+}
+
+#[test]
+fn errors() {
+    unsafe {
+        let triple = CString::new("x86_64").unwrap();
+        let builder = cranelift_isa_builder_new(triple.as_ptr());
+        let name = CString::new("opt_level").unwrap();
+        let value = CString::new("speed").unwrap();
+        assert_eq!(
+            cranelift_isa_builder_set(builder, name.as_ptr(), value.as_ptr()),
+            0
+        );
+        let name = CString::new("no_such_setting").unwrap();
+        assert_eq!(
+            cranelift_isa_builder_set(builder, name.as_ptr(), ptr::null()),
+            -1
+        );
+        assert!(last_error().contains("no_such_setting"));
+        let isa = cranelift_isa_builder_finish(builder);
+
+        let func = cranelift_function_new(isa, ptr::null(), 0, [I32].as_ptr(), 1);
+        assert_eq!(cranelift_binary(func, IADD, 7, 8), CRANELIFT_INVALID);
+        assert_eq!(last_error(), "invalid value handle 7");
+
+        // The function doesn't return a value, so it fails verification.
+        assert_eq!(cranelift_return(func, ptr::null(), 0), 0);
+        assert!(cranelift_compile(isa, func).is_null());
+        assert!(!last_error().is_empty());
+
+        cranelift_function_free(func);
+        cranelift_isa_free(isa);
+    }
+}
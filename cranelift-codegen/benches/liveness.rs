@@ -0,0 +1,93 @@
+//! Benchmarks for the liveness analysis on synthetic control flow graphs.
+//!
+//! This benchmark needs the "testing_hooks" feature:
+//!
+//! ```text
+//! cargo bench -p cranelift-codegen --features testing_hooks
+//! ```
+
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::types::I32;
+use cranelift_codegen::ir::{AbiParam, ExternalName, Function, InstBuilder, Signature};
+use cranelift_codegen::isa::{self, CallConv, TargetIsa};
+use cranelift_codegen::{settings, Context, Liveness};
+use criterion::{criterion_group, criterion_main, Criterion};
+use target_lexicon::triple;
+
+/// The number of values defined in the entry block which stay live through the whole function.
+const LIVE_VALUES: usize = 16;
+
+/// Build a function with a loop around a chain of `diamonds` if-then-else diamonds.
+///
+/// Every diamond uses one of the values defined in the entry block, so those values are live-in
+/// to all of the EBBs in the loop.
+fn synthetic(diamonds: usize) -> Function {
+    let mut sig = Signature::new(CallConv::SystemV);
+    sig.params.push(AbiParam::new(I32));
+    sig.returns.push(AbiParam::new(I32));
+    let mut func = Function::with_name_signature(ExternalName::testcase("synthetic"), sig);
+
+    let entry = func.dfg.make_ebb();
+    let arg = func.dfg.append_ebb_param(entry, I32);
+    let header = func.dfg.make_ebb();
+    let mut x = func.dfg.append_ebb_param(header, I32);
+    let exit = func.dfg.make_ebb();
+
+    let mut cur = FuncCursor::new(&mut func);
+    cur.insert_ebb(entry);
+    let live: Vec<_> = (0..LIVE_VALUES)
+        .map(|i| cur.ins().iadd_imm(arg, i as i64))
+        .collect();
+    cur.ins().jump(header, &[arg]);
+
+    cur.insert_ebb(header);
+    for d in 0..diamonds {
+        let then_ebb = cur.func.dfg.make_ebb();
+        let else_ebb = cur.func.dfg.make_ebb();
+        let join = cur.func.dfg.make_ebb();
+        let param = cur.func.dfg.append_ebb_param(join, I32);
+
+        cur.ins().brz(x, else_ebb, &[]);
+        cur.ins().jump(then_ebb, &[]);
+
+        cur.insert_ebb(then_ebb);
+        let t = cur.ins().iadd(x, live[d % LIVE_VALUES]);
+        cur.ins().jump(join, &[t]);
+
+        cur.insert_ebb(else_ebb);
+        let e = cur.ins().isub(x, live[(d + 1) % LIVE_VALUES]);
+        cur.ins().jump(join, &[e]);
+
+        cur.insert_ebb(join);
+        x = param;
+    }
+    cur.ins().brnz(x, header, &[x]);
+    cur.ins().jump(exit, &[]);
+
+    cur.insert_ebb(exit);
+    let sum = live.iter().fold(x, |sum, &v| cur.ins().iadd(sum, v));
+    cur.ins().return_(&[sum]);
+
+    func
+}
+
+fn liveness(c: &mut Criterion) {
+    c.bench_function_over_inputs(
+        "liveness",
+        |b, &diamonds: &usize| {
+            let isa: Box<TargetIsa> = isa::lookup(triple!("x86_64"))
+                .unwrap()
+                .finish(settings::Flags::new(settings::builder()));
+            let mut ctx = Context::for_function(synthetic(diamonds));
+            ctx.legalize(&*isa).unwrap();
+            ctx.compute_cfg();
+
+            let mut liveness = Liveness::new();
+            b.iter(|| liveness.compute(&*isa, &mut ctx.func, &ctx.cfg));
+        },
+        vec![10, 100, 1000],
+    );
+}
+
+criterion_group!(benches, liveness);
+criterion_main!(benches);
@@ -10,6 +10,7 @@ from cdsl.formats import InstructionFormat
 from cdsl.operands import VALUE, VARIABLE_ARGS
 from .immediates import imm64, uimm8, uimm32, ieee32, ieee64, offset32
 from .immediates import boolean, intcc, floatcc, memflags, regunit, trapcode
+from .immediates import atomic_rmw_op
 from . import entities
 from .entities import ebb, sig_ref, func_ref, stack_slot, heap, table
 
@@ -57,6 +58,7 @@ IndirectJump = InstructionFormat(VALUE, entities.jump_table)
 
 Call = InstructionFormat(func_ref, VARIABLE_ARGS)
 CallIndirect = InstructionFormat(sig_ref, VALUE, VARIABLE_ARGS)
+TryCall = InstructionFormat(func_ref, ebb, VARIABLE_ARGS)
 FuncAddr = InstructionFormat(func_ref)
 
 Load = InstructionFormat(memflags, VALUE, offset32)
@@ -64,6 +66,16 @@ LoadComplex = InstructionFormat(memflags, VARIABLE_ARGS, offset32)
 Store = InstructionFormat(memflags, VALUE, VALUE, offset32)
 StoreComplex = InstructionFormat(memflags, VALUE, VARIABLE_ARGS, offset32)
 
+# Memory accesses at `base + index * scale + offset`.
+LoadScaled = InstructionFormat(memflags, VALUE, VALUE, uimm8, offset32)
+StoreScaled = InstructionFormat(memflags, VALUE, VALUE, VALUE, uimm8, offset32)
+
+# Atomic memory accesses only support a bare address with no offset.
+LoadNoOffset = InstructionFormat(memflags, VALUE)
+StoreNoOffset = InstructionFormat(memflags, VALUE, VALUE)
+AtomicRmw = InstructionFormat(memflags, atomic_rmw_op, VALUE, VALUE)
+AtomicCas = InstructionFormat(memflags, VALUE, VALUE, VALUE)
+
 StackLoad = InstructionFormat(stack_slot, offset32)
 StackStore = InstructionFormat(VALUE, stack_slot, offset32)
 
@@ -74,6 +86,8 @@ HeapAddr = InstructionFormat(heap, VALUE, uimm32)
 TableAddr = InstructionFormat(table, VALUE, offset32)
 
 RegMove = InstructionFormat(VALUE, ('src', regunit), ('dst', regunit))
+RegSwap = InstructionFormat(
+        VALUE, VALUE, ('src', regunit), ('dst', regunit))
 CopySpecial = InstructionFormat(('src', regunit), ('dst', regunit))
 RegSpill = InstructionFormat(
         VALUE, ('src', regunit), ('dst', entities.stack_slot))
@@ -11,7 +11,7 @@ from cdsl.instructions import Instruction, InstructionGroup
 from base.types import f32, f64, b1, iflags, fflags
 from base.immediates import imm64, uimm8, uimm32, ieee32, ieee64, offset32
 from base.immediates import boolean, intcc, floatcc, memflags, regunit
-from base.immediates import trapcode
+from base.immediates import trapcode, atomic_rmw_op
 from base import entities
 from cdsl.ti import WiderOrEq
 import base.formats  # noqa
@@ -30,11 +30,13 @@ TxN = TypeVar(
         'TxN', 'A SIMD vector type',
         ints=True, floats=True, bools=True, scalars=False, simd=True)
 Any = TypeVar(
-        'Any', 'Any integer, float, or boolean scalar or vector type',
-        ints=True, floats=True, bools=True, scalars=True, simd=True)
+        'Any', 'Any integer, float, boolean, or reference type',
+        ints=True, floats=True, bools=True, scalars=True, simd=True,
+        refs=True)
 Mem = TypeVar(
         'Mem', 'Any type that can be stored in memory',
-        ints=True, floats=True, simd=True)
+        ints=True, floats=True, simd=True, refs=True)
+Ref = TypeVar('Ref', 'A scalar reference type', refs=True)
 MemTo = TypeVar(
         'MemTo', 'Any type that can be stored in memory',
         ints=True, floats=True, simd=True)
@@ -269,6 +271,24 @@ call = Instruction(
         """,
         ins=(FN, args), outs=rvals, is_call=True)
 
+LPAD = Operand('LPAD', entities.ebb, doc='landing pad')
+
+try_call = Instruction(
+        'try_call', r"""
+        Direct function call which may unwind.
+
+        Call a function like :inst:`call`. When the call returns normally,
+        execution continues with the next instruction. When the callee unwinds
+        with an exception, control is transferred to the landing pad ``LPAD``
+        instead, and the results of the call are not defined.
+
+        The landing pad must not have any EBB parameters, and it is the only
+        place where the exceptional edge can be observed. The address of the
+        landing pad is reported for each call site through the
+        :class:`ExceptionSink` when the function is emitted.
+        """,
+        ins=(FN, LPAD, args), outs=rvals, is_call=True, is_branch=True)
+
 SIG = Operand('SIG', entities.sig_ref, doc='function signature')
 callee = Operand('callee', iAddr, doc='address of function to call')
 
@@ -298,6 +318,41 @@ func_addr = Instruction(
         """,
         ins=FN, outs=addr)
 
+values = Operand('values', VARIABLE_ARGS, doc='abstract frame slots')
+
+safepoint = Instruction(
+        'safepoint', r"""
+        Capture the state of an abstract frame for deoptimization.
+
+        The argument at index ``i`` is the value of abstract frame slot ``i``,
+        typically a local variable or an operand stack entry of the source
+        interpreter. The instruction doesn't generate any code, but the
+        locations of its arguments at this point in the function are
+        reported through the :class:`DeoptSink` when the function is emitted,
+        so the embedder can reconstruct the interpreter state and bail out of
+        the compiled code.
+
+        The arguments stay live up to the safepoint, but register allocation
+        is free to leave them in stack slots.
+        """,
+        ins=values, other_side_effects=True)
+
+refs = Operand('refs', VARIABLE_ARGS, doc='references live across a call')
+
+stackmap = Instruction(
+        'stackmap', r"""
+        Record the stack slots holding references during the preceding call.
+
+        The register allocator inserts a stack map after each call when the
+        ``enable_safepoints`` setting is enabled, with all the reference values
+        that are live across the call as arguments. Those values are always
+        spilled to stack slots. The instruction doesn't generate any code, but
+        the stack offsets of its arguments are reported through the
+        :class:`StackmapSink` when the function is emitted, so a garbage
+        collector can find and update the references while the callee runs.
+        """,
+        ins=refs, other_side_effects=True)
+
 #
 # Memory operations
 #
@@ -509,6 +564,74 @@ istore32_complex = Instruction(
         """,
         ins=(MemFlags, x, args, Offset), can_store=True)
 
+#
+# Atomic memory operations
+#
+
+AtomicMem = TypeVar(
+        'AtomicMem', 'An integer type that can be accessed atomically',
+        ints=(8, 64))
+x = Operand('x', AtomicMem, doc='Value to be stored')
+a = Operand('a', AtomicMem, doc='Value loaded')
+e = Operand('e', AtomicMem, doc='Expected value')
+AtomicOp = Operand('AtomicOp', atomic_rmw_op)
+
+atomic_load = Instruction(
+        'atomic_load', r"""
+        Atomically load from memory at ``p``.
+
+        All atomic memory operations are sequentially consistent: they are
+        never reordered with respect to each other or to other memory
+        accesses. The address must be aligned to the size of the loaded type.
+        """,
+        ins=(MemFlags, p), outs=a, can_load=True, other_side_effects=True)
+
+atomic_store = Instruction(
+        'atomic_store', r"""
+        Atomically store ``x`` to memory at ``p``.
+
+        This is a sequentially consistent store, see :inst:`atomic_load`. The
+        address must be aligned to the size of the stored type.
+        """,
+        ins=(MemFlags, x, p), can_store=True, other_side_effects=True)
+
+atomic_rmw = Instruction(
+        'atomic_rmw', r"""
+        Atomically read-modify-write memory at ``p``.
+
+        Load the value at ``p``, combine it with ``x`` using ``AtomicOp``, and
+        store the result back to ``p`` as a single sequentially consistent
+        atomic operation. Returns the value that was loaded.
+
+        Targets without a native instruction for ``AtomicOp`` expand it into a
+        loop around :inst:`atomic_cas`.
+        """,
+        ins=(MemFlags, AtomicOp, p, x), outs=a,
+        can_load=True, can_store=True, other_side_effects=True)
+
+atomic_cas = Instruction(
+        'atomic_cas', r"""
+        Atomically compare and swap memory at ``p``.
+
+        If the value at ``p`` equals ``e``, replace it with ``x``. In either
+        case, return the value that was loaded from ``p``; the swap succeeded
+        if and only if that value equals ``e``. This is a single sequentially
+        consistent atomic operation.
+        """,
+        ins=(MemFlags, p, e, x), outs=a,
+        can_load=True, can_store=True, other_side_effects=True)
+
+fence = Instruction(
+        'fence', r"""
+        A full memory barrier.
+
+        All memory accesses that precede the fence in program order are
+        completed and made visible to other threads before any memory access
+        that follows it. Passes that move memory accesses must not move them
+        across a fence.
+        """,
+        can_load=True, can_store=True, other_side_effects=True)
+
 x = Operand('x', Mem, doc='Value to be stored')
 a = Operand('a', Mem, doc='Value loaded')
 Offset = Operand('Offset', offset32, 'In-bounds offset into stack slot')
@@ -569,6 +692,15 @@ symbol_value = Instruction(
         """,
         ins=GV, outs=a)
 
+tls_value = Instruction(
+        'tls_value', r"""
+        Compute the address of the current thread's instance of the
+        thread-local symbol GV.
+
+        The code sequence used depends on the ``tls_model`` setting.
+        """,
+        ins=GV, outs=a)
+
 #
 # WebAssembly bounds-checked heap accesses.
 #
@@ -666,6 +798,27 @@ bconst = Instruction(
         """,
         ins=N, outs=a)
 
+#
+# Reference types.
+#
+
+a = Operand('a', Ref, doc='A null reference')
+null = Instruction(
+        'null', r"""
+        Null reference constant.
+
+        Create a reference SSA value which doesn't point to any object.
+        """,
+        ins=(), outs=a)
+
+x = Operand('x', Ref)
+a = Operand('a', b1)
+is_null = Instruction(
+        'is_null', r"""
+        Test if a reference is null.
+        """,
+        ins=x, outs=a)
+
 #
 # Generics.
 #
@@ -752,6 +905,26 @@ regmove = Instruction(
         ins=(x, src, dst),
         other_side_effects=True)
 
+AnyY = TypeVar(
+        'AnyY', 'Any integer, float, or boolean scalar or vector type',
+        ints=True, floats=True, bools=True, scalars=True, simd=True)
+y = Operand('y', AnyY)
+
+regswap = Instruction(
+        'regswap', r"""
+        Temporarily divert ``x`` from ``src`` to ``dst`` and ``y`` from
+        ``dst`` to ``src``.
+
+        This instruction exchanges the locations of two values in registers
+        without creating new SSA values. It is used by the register allocator
+        to break cycles when rearranging register assignments and no scratch
+        register is available.
+
+        See also :inst:`regmove`.
+        """,
+        ins=(x, y, src, dst),
+        other_side_effects=True)
+
 copy_special = Instruction(
         'copy_special', r"""
         Copies the contents of ''src'' register to ''dst'' register.
@@ -810,6 +983,55 @@ ifcmp_sp = Instruction(
     """,
     ins=addr, outs=f)
 
+get_stack_pointer = Instruction(
+    'get_stack_pointer', r"""
+    Get the current value of the stack pointer register.
+    """,
+    outs=addr, other_side_effects=True)
+
+set_stack_pointer = Instruction(
+    'set_stack_pointer', r"""
+    Set the stack pointer register to ``addr``.
+
+    The stack slots of the current function are addressed relative to the
+    stack pointer, so they can't be accessed, and no calls can be made, until
+    the original stack pointer has been restored.
+    """,
+    ins=addr, other_side_effects=True)
+
+store_context = Operand(
+        'store_context', iAddr,
+        doc='context where the current stack is saved')
+load_context = Operand(
+        'load_context', iAddr, doc='context of the stack to resume')
+in_payload = Operand(
+        'in_payload', iAddr, doc='value passed to the resumed stack')
+out_payload = Operand(
+        'out_payload', iAddr,
+        doc='value passed when switching back to this stack')
+
+stack_switch = Instruction(
+    'stack_switch', r"""
+    Suspend the current stack and resume execution on another stack.
+
+    A stack context is three pointer-sized words holding the stack pointer,
+    the frame pointer, and the address where execution resumes. The current
+    stack is saved in the context at ``store_context``, and execution resumes
+    on the stack described by the context at ``load_context``.
+
+    When the resumed stack was suspended by another :inst:`stack_switch`, that
+    instruction returns ``in_payload`` as its ``out_payload``. A new stack
+    must be initialized so that its resume address is entry code which
+    receives ``in_payload`` in a platform-dependent register.
+
+    All registers are clobbered, including the callee-saved registers. Values
+    that are live across a :inst:`stack_switch` are kept in the stack frame
+    of the current function.
+    """,
+    ins=(store_context, load_context, in_payload), outs=out_payload,
+    can_load=True, can_store=True, other_side_effects=True,
+    clobbers_all_regs=True)
+
 regspill = Instruction(
         'regspill', r"""
         Temporarily divert ``x`` from ``src`` to ``SS``.
@@ -925,6 +1147,94 @@ extractlane = Instruction(
         """,
         ins=(x, Idx), outs=a)
 
+I8xN = TypeVar(
+        'I8xN', 'A SIMD vector of 8-bit integers',
+        ints=(8, 8), scalars=False, simd=True)
+x = Operand('x', I8xN, doc='Vector to select lanes from')
+y = Operand('y', I8xN, doc='Vector of lane indices')
+a = Operand('a', I8xN)
+
+swizzle = Instruction(
+        'swizzle', r"""
+        Vector swizzle.
+
+        Lane ``i`` of ``a`` is the lane of ``x`` selected by lane ``i`` of
+        ``y``. The lanes of ``y`` are interpreted as unsigned, and lane
+        indices out of range select zero.
+        """,
+        ins=(x, y), outs=a)
+
+x = Operand('x', I8xN, doc='Low-numbered lanes to select from')
+y = Operand('y', I8xN, doc='High-numbered lanes to select from')
+mask = Operand('mask', I8xN, doc='Vector of lane indices')
+
+shuffle = Instruction(
+        'shuffle', r"""
+        Vector shuffle.
+
+        Lane ``i`` of ``a`` is the lane selected by lane ``i`` of ``mask`` in
+        the concatenation of ``x`` and ``y``, where the lanes of ``x`` are
+        numbered 0 to N-1 and the lanes of ``y`` are numbered N to 2N-1. The
+        lanes of ``mask`` are interpreted as unsigned, and lane indices out of
+        range select zero.
+        """,
+        ins=(x, y, mask), outs=a)
+
+I16or32xN = TypeVar(
+        'I16or32xN', 'A SIMD vector of 16-bit or 32-bit integers',
+        ints=(16, 32), scalars=False, simd=(2, 128))
+x = Operand('x', I16or32xN, doc='Low-numbered lanes to narrow')
+y = Operand('y', I16or32xN, doc='High-numbered lanes to narrow')
+a = Operand('a', I16or32xN.split_lanes())
+
+snarrow = Instruction(
+        'snarrow', r"""
+        Narrow two vectors with signed saturation.
+
+        The lanes of ``x`` and ``y`` are interpreted as signed integers and
+        clamped to the signed range of half their width. The narrowed lanes
+        of ``x`` become the low-numbered lanes of ``a``, and the narrowed
+        lanes of ``y`` become the high-numbered lanes.
+
+        The result has twice as many lanes as the inputs, each half as wide.
+        """,
+        ins=(x, y), outs=a)
+
+unarrow = Instruction(
+        'unarrow', r"""
+        Narrow two vectors with unsigned saturation.
+
+        The lanes of ``x`` and ``y`` are interpreted as signed integers and
+        clamped to the unsigned range of half their width. The narrowed lanes
+        of ``x`` become the low-numbered lanes of ``a``, and the narrowed
+        lanes of ``y`` become the high-numbered lanes.
+
+        The result has twice as many lanes as the inputs, each half as wide.
+        """,
+        ins=(x, y), outs=a)
+
+IntOrBoolxN = TypeVar(
+        'IntOrBoolxN', 'A SIMD vector of integers or booleans',
+        ints=True, bools=True, scalars=False, simd=True)
+x = Operand('x', IntOrBoolxN)
+a = Operand('a', b1)
+
+vany_true = Instruction(
+        'vany_true', r"""
+        Reduce a vector to a scalar boolean.
+
+        Return true if any lane of ``x`` is non-zero.
+        """,
+        ins=x, outs=a)
+
+vall_true = Instruction(
+        'vall_true', r"""
+        Reduce a vector to a scalar boolean.
+
+        Return true if all the lanes of ``x`` are non-zero.
+        """,
+        ins=x, outs=a)
+
 #
 # Integer arithmetic
 #
@@ -1017,6 +1327,42 @@ isub = Instruction(
         """,
         ins=(x, y), outs=a)
 
+sadd_sat = Instruction(
+        'sadd_sat', r"""
+        Add with signed saturation.
+
+        The result is clamped to the range of the signed integer type instead
+        of wrapping around. Vector lanes are clamped independently.
+        """,
+        ins=(x, y), outs=a)
+
+uadd_sat = Instruction(
+        'uadd_sat', r"""
+        Add with unsigned saturation.
+
+        The result is clamped to the range of the unsigned integer type
+        instead of wrapping around. Vector lanes are clamped independently.
+        """,
+        ins=(x, y), outs=a)
+
+ssub_sat = Instruction(
+        'ssub_sat', r"""
+        Subtract with signed saturation.
+
+        The result is clamped to the range of the signed integer type instead
+        of wrapping around. Vector lanes are clamped independently.
+        """,
+        ins=(x, y), outs=a)
+
+usub_sat = Instruction(
+        'usub_sat', r"""
+        Subtract with unsigned saturation.
+
+        The result is clamped to the range of the unsigned integer type
+        instead of wrapping around. Vector lanes are clamped independently.
+        """,
+        ins=(x, y), outs=a)
+
 imul = Instruction(
         'imul', r"""
         Wrapping integer multiplication: :math:`a := x y \pmod{2^B}`.
@@ -1474,6 +1820,23 @@ bitrev = Instruction(
         """,
         ins=x, outs=a)
 
+Bswap = TypeVar(
+        'Bswap', 'An integer type with at least two bytes', ints=(16, 128))
+x = Operand('x', Bswap)
+a = Operand('a', Bswap)
+
+bswap = Instruction(
+        'bswap', r"""
+        Reverse the bytes of an integer.
+
+        Reverses the order of the bytes in ``x``, converting between little-
+        endian and big-endian representations.
+        """,
+        ins=x, outs=a)
+
+x = Operand('x', iB)
+a = Operand('a', iB)
+
 clz = Instruction(
         'clz', r"""
         Count leading zero bits.
@@ -1697,6 +2060,33 @@ fmax = Instruction(
         """,
         ins=(x, y), outs=a)
 
+a = Operand('a', Float, 'The smaller of ``x`` and ``y``')
+
+fmin_pseudo = Instruction(
+        'fmin_pseudo', r"""
+        Floating point pseudo-minimum.
+
+        This is equivalent to the C ternary operator ``x < y ? x : y``, so it
+        returns ``y`` unchanged when either operand is NaN. When comparing
+        zeroes, ``y`` is returned since -0.0 and 0.0 compare equal.
+
+        This is the minimum operation required by WebAssembly and JavaScript
+        frontends, and it maps to a single instruction on x86.
+        """,
+        ins=(x, y), outs=a)
+
+a = Operand('a', Float, 'The larger of ``x`` and ``y``')
+
+fmax_pseudo = Instruction(
+        'fmax_pseudo', r"""
+        Floating point pseudo-maximum.
+
+        This is equivalent to the C ternary operator ``x > y ? x : y``, so it
+        returns ``y`` unchanged when either operand is NaN. When comparing
+        zeroes, ``y`` is returned since -0.0 and 0.0 compare equal.
+        """,
+        ins=(x, y), outs=a)
+
 a = Operand('a', Float, '``x`` rounded to integral value')
 
 ceil = Instruction(
@@ -2002,7 +2392,7 @@ fcvt_from_sint = Instruction(
 
 WideInt = TypeVar(
         'WideInt', 'An integer type with lanes from `i16` upwards',
-        ints=(16, 64), simd=True)
+        ints=(16, 128), simd=True)
 x = Operand('x', WideInt)
 lo = Operand(
         'lo', WideInt.half_width(), 'The low bits of `x`')
@@ -2023,8 +2413,8 @@ isplit = Instruction(
 
 
 NarrowInt = TypeVar(
-        'NarrowInt', 'An integer type with lanes type to `i32`',
-        ints=(8, 32), simd=True)
+        'NarrowInt', 'An integer type with lanes type to `i64`',
+        ints=(8, 64), simd=True)
 lo = Operand('lo', NarrowInt)
 hi = Operand('hi', NarrowInt)
 a = Operand(
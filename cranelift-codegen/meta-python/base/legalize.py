@@ -7,7 +7,7 @@ patterns that describe how base instructions can be transformed to other base
 instructions that are legal.
 """
 from __future__ import absolute_import
-from .immediates import intcc, imm64, ieee32, ieee64
+from .immediates import intcc, floatcc, imm64, ieee32, ieee64
 from . import instructions as insts
 from . import types
 from .instructions import uextend, sextend, ireduce
@@ -26,7 +26,7 @@ from .instructions import rotl, rotl_imm, rotr, rotr_imm
 from .instructions import f32const, f64const
 from .instructions import store, load
 from .instructions import br_table
-from .instructions import bitrev
+from .instructions import bitrev, bswap
 from cdsl.ast import Var
 from cdsl.xform import Rtl, XFormGroup
 
@@ -47,6 +47,16 @@ narrow = XFormGroup('narrow', """
         operations are expressed in terms of smaller integer types.
         """)
 
+narrow_wide = XFormGroup('narrow_wide', """
+        Legalize instructions on integer types wider than the registers by
+        narrowing.
+
+        This is the 'narrow' group plus the splitting of wide integer
+        constants. 32-bit targets use the plain 'narrow' group for `i64`, so
+        the `iconst.i64` that `f64const` is expanded to stays intact for the
+        `bitcast` using it.
+        """, chain=narrow)
+
 widen = XFormGroup('widen', """
         Legalize instructions by widening.
 
@@ -82,18 +92,43 @@ expand.custom_legalize(insts.call, 'expand_call')
 # TODO: Add sufficient XForm syntax that we don't need to hand-code these.
 expand.custom_legalize(insts.trapz, 'expand_cond_trap')
 expand.custom_legalize(insts.trapnz, 'expand_cond_trap')
+expand.custom_legalize(insts.trapif, 'expand_cond_trap')
+expand.custom_legalize(insts.trapff, 'expand_cond_trap')
 expand.custom_legalize(insts.br_table, 'expand_br_table')
 expand.custom_legalize(insts.select, 'expand_select')
+expand.custom_legalize(insts.atomic_rmw, 'expand_atomic_rmw')
+# Narrow atomic operations can't be widened without changing the accessed
+# memory, so they are expanded into an `atomic_cas` loop of the same width.
+widen.custom_legalize(insts.atomic_rmw, 'expand_atomic_rmw')
 
 # Custom expansions for floating point constants.
 # These expansions require bit-casting or creating constant pool entries.
 expand.custom_legalize(insts.f32const, 'expand_fconst')
 expand.custom_legalize(insts.f64const, 'expand_fconst')
 
+# Custom expansion for fused multiply-add, which depends on `enable_strict_fp`.
+expand.custom_legalize(insts.fma, 'expand_fma')
+
 # Custom expansions for stack memory accesses.
 expand.custom_legalize(insts.stack_load, 'expand_stack_load')
 expand.custom_legalize(insts.stack_store, 'expand_stack_store')
 
+# Custom expansions for vector lanes, which go through memory.
+expand.custom_legalize(insts.splat, 'expand_lane_access')
+expand.custom_legalize(insts.insertlane, 'expand_lane_access')
+expand.custom_legalize(insts.extractlane, 'expand_lane_access')
+expand.custom_legalize(insts.shuffle, 'expand_shuffle')
+expand.custom_legalize(insts.vany_true, 'expand_vector_test')
+expand.custom_legalize(insts.vall_true, 'expand_vector_test')
+
+# The remaining instructions without an encoding become calls to the runtime
+# library functions listed in `LibCall::for_inst()`.
+for ty in [types.f32, types.f64]:
+    expand.libcall(*(inst.bind(ty) for inst in [
+        insts.ceil, insts.floor, insts.trunc, insts.nearest,
+        insts.fadd, insts.fsub, insts.fmul, insts.fdiv, insts.sqrt]))
+expand.libcall(imul.i32, imul.i64)
+
 x = Var('x')
 y = Var('y')
 z = Var('z')
@@ -182,6 +217,182 @@ narrow.legalize(
             a << iconcat(al, ah)
         ))
 
+# The low half of the product only needs the low halves of the cross terms.
+narrow.legalize(
+        a << imul(x, y),
+        Rtl(
+            (xl, xh) << isplit(x),
+            (yl, yh) << isplit(y),
+            a1 << imul(xh, yl),
+            a2 << imul(xl, yh),
+            a3 << iadd(a1, a2),
+            a4 << insts.umulhi(xl, yl),
+            ah << iadd(a3, a4),
+            al << imul(xl, yl),
+            a << iconcat(al, ah)
+        ))
+
+# The high half of the product adds up the high parts of the four partial
+# products, plus the carries out of the middle column.
+narrow.legalize(
+        a << insts.umulhi(x, y),
+        Rtl(
+            (xl, xh) << isplit(x),
+            (yl, yh) << isplit(y),
+            b1 << insts.umulhi(xl, yl),
+            b2 << imul(xl, yh),
+            b3 << insts.umulhi(xl, yh),
+            c1 << imul(xh, yl),
+            c2 << insts.umulhi(xh, yl),
+            d1 << imul(xh, yh),
+            d2 << insts.umulhi(xh, yh),
+            (e1, c3) << iadd_cout(b1, b2),
+            (e2, c4) << iadd_cout(e1, c1),
+            (e3, f1) << iadd_carry(d1, b3, c3),
+            (al, f2) << iadd_carry(e3, c2, c4),
+            z << iconst(imm64(0)),
+            e4 << iadd_cin(d2, z, f1),
+            ah << iadd_cin(e4, z, f2),
+            a << iconcat(al, ah)
+        ))
+
+# The signed high half is the unsigned one minus each operand that was
+# multiplied by a negative number.
+for ty, sign_bit in [(types.i64, 31), (types.i128, 63)]:
+    narrow.legalize(
+            a << insts.smulhi.bind(ty)(x, y),
+            Rtl(
+                (xl, xh) << isplit(x),
+                (yl, yh) << isplit(y),
+                a1 << insts.umulhi(x, y),
+                b1 << sshr_imm(xh, imm64(sign_bit)),
+                b2 << sshr_imm(yh, imm64(sign_bit)),
+                c1 << band(b1, yl),
+                c2 << band(b1, yh),
+                c3 << iconcat(c1, c2),
+                d1 << band(b2, xl),
+                d2 << band(b2, xh),
+                d3 << iconcat(d1, d2),
+                a2 << isub(a1, c3),
+                a << isub(a2, d3)
+            ))
+
+narrow.legalize(
+        a << bnot(x),
+        Rtl(
+            (xl, xh) << isplit(x),
+            al << bnot(xl),
+            ah << bnot(xh),
+            a << iconcat(al, ah)
+        ))
+
+# Reversing the bytes or bits of a wide integer also swaps its halves.
+for rev in [bswap, bitrev]:
+    narrow.legalize(
+            a << rev(x),
+            Rtl(
+                (xl, xh) << isplit(x),
+                al << rev(xh),
+                ah << rev(xl),
+                a << iconcat(al, ah)
+            ))
+
+narrow.legalize(
+        a << icmp(intcc.eq, x, y),
+        Rtl(
+            (xl, xh) << isplit(x),
+            (yl, yh) << isplit(y),
+            b1 << icmp(intcc.eq, xl, yl),
+            b2 << icmp(intcc.eq, xh, yh),
+            a << band(b1, b2)
+        ))
+
+narrow.legalize(
+        a << icmp(intcc.ne, x, y),
+        Rtl(
+            (xl, xh) << isplit(x),
+            (yl, yh) << isplit(y),
+            b1 << icmp(intcc.ne, xl, yl),
+            b2 << icmp(intcc.ne, xh, yh),
+            a << bor(b1, b2)
+        ))
+
+# An ordered comparison is decided by the high halves unless they are equal,
+# in which case the low halves are compared as unsigned numbers.
+for (ord_cc, high_cc, low_cc) in [
+        (intcc.slt, intcc.slt, intcc.ult),
+        (intcc.sle, intcc.slt, intcc.ule),
+        (intcc.sgt, intcc.sgt, intcc.ugt),
+        (intcc.sge, intcc.sgt, intcc.uge),
+        (intcc.ult, intcc.ult, intcc.ult),
+        (intcc.ule, intcc.ult, intcc.ule),
+        (intcc.ugt, intcc.ugt, intcc.ugt),
+        (intcc.uge, intcc.ugt, intcc.uge)]:
+    narrow.legalize(
+            a << icmp(ord_cc, x, y),
+            Rtl(
+                (xl, xh) << isplit(x),
+                (yl, yh) << isplit(y),
+                b1 << icmp(high_cc, xh, yh),
+                b2 << icmp(intcc.eq, xh, yh),
+                b3 << icmp(low_cc, xl, yl),
+                c1 << band(b2, b3),
+                a << bor(b1, c1)
+            ))
+
+# Materialize the immediate so the binary form can be narrowed.
+for inst_imm, inst in [
+        (iadd_imm, iadd),
+        (band_imm, band),
+        (bor_imm, bor),
+        (bxor_imm, bxor)]:
+    narrow.legalize(
+            a << inst_imm(x, y),
+            Rtl(
+                a1 << iconst(y),
+                a << inst(x, a1)
+            ))
+
+# The shift amount is a separate type, so its constant gets a fixed one.
+for inst_imm, inst in [
+        (ishl_imm, ishl),
+        (ushr_imm, ushr),
+        (sshr_imm, sshr),
+        (rotl_imm, rotl),
+        (rotr_imm, rotr)]:
+    narrow.legalize(
+            a << inst_imm(x, y),
+            Rtl(
+                a1 << iconst.i32(y),
+                a << inst(x, a1)
+            ))
+
+narrow.legalize(
+        a << irsub_imm(y, x),
+        Rtl(
+            a1 << iconst(x),
+            a << isub(a1, y)
+        ))
+
+narrow.legalize(
+        a << icmp_imm(cc, x, y),
+        Rtl(
+            a1 << iconst(y),
+            a << icmp(cc, x, a1)
+        ))
+
+# Custom narrowing for memory accesses.
+narrow.custom_legalize(insts.load, 'narrow_load')
+narrow.custom_legalize(insts.store, 'narrow_store')
+
+# Wide shifts and rotates select between the results for small and large
+# amounts, which is easier to write by hand.
+for inst in [ishl, ushr, sshr, rotl, rotr]:
+    narrow.custom_legalize(inst, 'narrow_shift')
+
+# Custom narrowing for wide integer constants.
+narrow_wide.custom_legalize(insts.iconst, 'narrow_iconst')
+
 
 def widen_one_arg(signed, op):
     # type: (bool, Instruction) -> None
@@ -607,14 +818,7 @@ expand.legalize(
             c3 << band_imm(c, imm64(0x0f0f0f0f)),
             c4 << ishl_imm(c3, imm64(4)),
             d << bor(c2, c4),
-            d1 << band_imm(d, imm64(0xff00ff00)),
-            d2 << ushr_imm(d1, imm64(8)),
-            d3 << band_imm(d, imm64(0x00ff00ff)),
-            d4 << ishl_imm(d3, imm64(8)),
-            e << bor(d2, d4),
-            e1 << ushr_imm(e, imm64(16)),
-            e2 << ishl_imm(e, imm64(16)),
-            a << bor(e1, e2),
+            a << bswap(d),
         ))
 
 expand.legalize(
@@ -635,19 +839,48 @@ expand.legalize(
             c3 << band_imm(c, imm64(0x0f0f0f0f0f0f0f0f)),
             c4 << ishl_imm(c3, imm64(4)),
             d << bor(c2, c4),
-            d1 << band_imm(d, imm64(0xff00ff00ff00ff00)),
-            d2 << ushr_imm(d1, imm64(8)),
-            d3 << band_imm(d, imm64(0x00ff00ff00ff00ff)),
-            d4 << ishl_imm(d3, imm64(8)),
-            e << bor(d2, d4),
-            e1 << band_imm(e, imm64(0xffff0000ffff0000)),
-            e2 << ushr_imm(e1, imm64(16)),
-            e3 << band_imm(e, imm64(0x0000ffff0000ffff)),
-            e4 << ishl_imm(e3, imm64(16)),
-            f << bor(e2, e4),
-            f1 << ushr_imm(f, imm64(32)),
-            f2 << ishl_imm(f, imm64(32)),
-            a << bor(f1, f2),
+            a << bswap(d),
+        ))
+
+# Expand bswap
+widen.legalize(
+        a << bswap.i16(x),
+        Rtl(
+            a1 << uextend.i32(x),
+            a2 << bswap(a1),
+            a3 << ushr_imm(a2, imm64(16)),
+            a << ireduce.i16(a3),
+        ))
+
+expand.legalize(
+        a << bswap.i32(x),
+        Rtl(
+            a1 << band_imm(x, imm64(0xff00ff00)),
+            a2 << ushr_imm(a1, imm64(8)),
+            a3 << band_imm(x, imm64(0x00ff00ff)),
+            a4 << ishl_imm(a3, imm64(8)),
+            b << bor(a2, a4),
+            b1 << ushr_imm(b, imm64(16)),
+            b2 << ishl_imm(b, imm64(16)),
+            a << bor(b1, b2),
+        ))
+
+expand.legalize(
+        a << bswap.i64(x),
+        Rtl(
+            a1 << band_imm(x, imm64(0xff00ff00ff00ff00)),
+            a2 << ushr_imm(a1, imm64(8)),
+            a3 << band_imm(x, imm64(0x00ff00ff00ff00ff)),
+            a4 << ishl_imm(a3, imm64(8)),
+            b << bor(a2, a4),
+            b1 << band_imm(b, imm64(0xffff0000ffff0000)),
+            b2 << ushr_imm(b1, imm64(16)),
+            b3 << band_imm(b, imm64(0x0000ffff0000ffff)),
+            b4 << ishl_imm(b3, imm64(16)),
+            c << bor(b2, b4),
+            c1 << ushr_imm(c, imm64(32)),
+            c2 << ishl_imm(c, imm64(32)),
+            a << bor(c1, c2),
         ))
 
 # Floating-point sign manipulations.
@@ -675,6 +908,49 @@ for ty,             minus_zero in [
                 a << bor(a1, a2)
             ))
 
+# Floating-point minimum and maximum.
+#
+# The pseudo variants are a single compare and select. The IEEE variants also
+# need to propagate NaNs and order -0.0 before 0.0, which is done with a
+# bitwise operation when the operands compare equal.
+for ty in [types.f32, types.f64]:
+    expand.legalize(
+            a << insts.fmin_pseudo.bind(ty)(x, y),
+            Rtl(
+                c << insts.fcmp(floatcc.lt, x, y),
+                a << select(c, x, y),
+            ))
+    expand.legalize(
+            a << insts.fmax_pseudo.bind(ty)(x, y),
+            Rtl(
+                c << insts.fcmp(floatcc.gt, x, y),
+                a << select(c, x, y),
+            ))
+    expand.legalize(
+            a << insts.fmin.bind(ty)(x, y),
+            Rtl(
+                a1 << bor(x, y),
+                c1 << insts.fcmp(floatcc.gt, x, y),
+                a2 << select(c1, y, a1),
+                c2 << insts.fcmp(floatcc.lt, x, y),
+                a3 << select(c2, x, a2),
+                a4 << insts.fadd(x, y),
+                c3 << insts.fcmp(floatcc.uno, x, y),
+                a << select(c3, a4, a3),
+            ))
+    expand.legalize(
+            a << insts.fmax.bind(ty)(x, y),
+            Rtl(
+                a1 << band(x, y),
+                c1 << insts.fcmp(floatcc.lt, x, y),
+                a2 << select(c1, y, a1),
+                c2 << insts.fcmp(floatcc.gt, x, y),
+                a3 << select(c2, x, a2),
+                a4 << insts.fadd(x, y),
+                c3 << insts.fcmp(floatcc.uno, x, y),
+                a << select(c3, a4, a3),
+            ))
+
 expand.custom_legalize(insts.br_icmp, 'expand_br_icmp')
 
 # Expansions using CPU flags.
@@ -12,11 +12,15 @@ opt_level = EnumSetting(
         """
         Optimization level:
 
-        - default: Very profitable optimizations enabled, none slow.
-        - best: Enable all optimizations
-        - fastest: Optimize for compile time by disabling most optimizations.
+        - speed: Generate fast code with the IR optimizations and inlining
+          thresholds which pay off for most functions. This is the default.
+        - none: Minimize compile time by disabling most optimizations. This is
+          meant for baseline JIT compilation.
+        - speed_and_size: Like "speed", but also run loop invariant code
+          motion and global value numbering, and perform transformations
+          aimed at reducing code size.
         """,
-        'default', 'best', 'fastest')
+        'speed', 'none', 'speed_and_size')
 
 enable_verifier = BoolSetting(
         """
@@ -32,6 +36,18 @@ enable_verifier = BoolSetting(
 # `colocated` flag on external functions and global values.
 is_pic = BoolSetting("Enable Position-Independent Code generation")
 
+tls_model = EnumSetting(
+        """
+        Thread-local storage model:
+
+        - none: Thread-local global values are not supported.
+        - elf_gd: ELF general-dynamic model. The address is computed by a
+          call to ``__tls_get_addr``.
+        - elf_ie: ELF initial-exec model. The offset from the thread pointer
+          is loaded from the GOT.
+        """,
+        'none', 'elf_gd', 'elf_ie')
+
 colocated_libcalls = BoolSetting(
         """
         Use colocated libcalls.
@@ -53,6 +69,28 @@ avoid_div_traps = BoolSetting(
         this setting has no effect - explicit checks are always inserted.
         """)
 
+elide_null_checks = BoolSetting(
+        """
+        Replace explicit null pointer checks with faulting memory accesses.
+
+        A ``trapz`` instruction followed by a ``notrap`` load or store from the
+        same pointer near address 0 is removed, and its trap code is reported
+        when the memory access faults instead.
+
+        This requires the embedder to handle the resulting faults as traps,
+        like it does for heap accesses protected by guard pages.
+        """)
+
+out_of_line_traps = BoolSetting(
+        """
+        Emit the traps of conditional trap instructions out of line.
+
+        Each conditional trap becomes a branch to a trap instruction at the
+        end of the function, so the hot path only contains a branch which is
+        not taken. Without this setting, the hot path branches over an inline
+        trap instruction.
+        """)
+
 enable_float = BoolSetting(
         """
         Enable the use of floating-point instructions
@@ -71,6 +109,17 @@ enable_nan_canonicalization = BoolSetting(
         """,
         default=False)
 
+enable_strict_fp = BoolSetting(
+        """
+        Preserve the exact rounding of floating point operations.
+
+        When a fused multiply-add can't be encoded for the target, it is
+        expanded into a library call that rounds only once. Disabling this
+        setting expands it into a multiplication and an addition instead,
+        which is faster but rounds twice.
+        """,
+        default=True)
+
 enable_simd = BoolSetting(
         """Enable the use of SIMD instructions.""",
         default=True)
@@ -79,6 +128,39 @@ enable_atomics = BoolSetting(
         """Enable the use of atomic instructions""",
         default=True)
 
+enable_safepoints = BoolSetting(
+        """
+        Record the locations of the reference values live across each call.
+
+        When enabled, the register allocator inserts a ``stackmap``
+        instruction after each call with the reference values that are live
+        across it, and the stack slots holding them are reported through the
+        ``StackmapSink`` when the function is emitted. This is needed by
+        garbage collectors which trace the references on the stack.
+        """,
+        default=False)
+
+enable_critical_edge_splitting = BoolSetting(
+        """
+        Split critical edges in the control flow graph during legalization.
+
+        A critical edge goes from an EBB with multiple successors to an EBB
+        with multiple predecessors. Splitting it inserts an empty EBB on the
+        edge, which gives the register allocator a place to put the copies
+        for the EBB arguments passed along that edge.
+        """,
+        default=False)
+
+enable_scheduling = BoolSetting(
+        """
+        Reorder the instructions within each EBB after register allocation.
+
+        The scheduler moves independent instructions between a long-latency
+        instruction like a load and the first use of its result. This helps
+        in-order cores, but it is not needed for out-of-order ones.
+        """,
+        default=False)
+
 #
 # Settings specific to the `baldrdash` calling convention.
 #
@@ -105,6 +187,69 @@ allones_funcaddrs = BoolSetting(
         Emit not-yet-relocated function addresses as all-ones bit patterns.
         """)
 
+#
+# Code patching options.
+#
+patchable_calls = BoolSetting(
+        """
+        Emit direct calls so they can be patched after compilation.
+
+        Each direct call is padded with nops so its 4-byte displacement is
+        naturally aligned and can be rewritten with a single atomic store. The
+        range of the call instruction is reported to the code sink, so a JIT
+        can later re-point the call, for example to update an inline cache,
+        without recompiling the function.
+        """)
+
+patchable_branches = BoolSetting(
+        """
+        Emit unconditional jumps so they can be patched after compilation.
+
+        Each `jump` uses the longest branch encoding, padded with nops so its
+        4-byte displacement is naturally aligned, and its range is reported to
+        the code sink like a patchable call. Jumps to the next EBB in the
+        layout are kept instead of becoming fall-throughs, so a JIT can
+        re-point any jump without recompiling the function. Conditional
+        branches are not affected.
+        """)
+
+#
+# Code layout options.
+#
+function_alignment_log2 = NumSetting(
+        """
+        The log2 of the alignment of the start of the function's code.
+
+        The embedder must place the code at an address with this alignment.
+        Code placement inside the function, like loop header alignment, can't
+        use a larger alignment.
+
+        The default is 4, which translates to 16 bytes.
+        """,
+        default=4)
+
+loop_alignment_log2 = NumSetting(
+        """
+        The log2 of the alignment of loop headers.
+
+        EBBs which are the target of a backward branch are padded with nops
+        so they start at an offset with this alignment, limited by the
+        function alignment. The default is 0, which disables the padding.
+        """,
+        default=0)
+
+nop_style = EnumSetting(
+        """
+        The instructions used for padding:
+
+        - multi_byte: Use the longest available nop instructions, so the
+          padding executes as few instructions as possible.
+        - single_byte: Only use the shortest nop instruction.
+
+        ISAs with a single nop instruction ignore this setting.
+        """,
+        'multi_byte', 'single_byte')
+
 #
 # Stack probing options.
 #
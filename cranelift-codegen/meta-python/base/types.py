@@ -3,6 +3,7 @@ The base.types module predefines all the Cranelift scalar types.
 """
 from __future__ import absolute_import
 from cdsl.types import IntType, FloatType, BoolType, FlagsType
+from cdsl.types import ReferenceType
 
 #: Abstract boolean (can't be stored in memory, use bint to convert to 0 or 1).
 b1 = BoolType(1)    #: 1-bit bool.
@@ -12,12 +13,14 @@ b8 = BoolType(8)    #: 8-bit bool.
 b16 = BoolType(16)  #: 16-bit bool.
 b32 = BoolType(32)  #: 32-bit bool.
 b64 = BoolType(64)  #: 64-bit bool.
+b128 = BoolType(128)  #: 128-bit bool.
 
 # Integers.
 i8 = IntType(8)     #: 8-bit int.
 i16 = IntType(16)   #: 16-bit int.
 i32 = IntType(32)   #: 32-bit int.
 i64 = IntType(64)   #: 64-bit int.
+i128 = IntType(128)  #: 128-bit int.
 
 #: IEEE single precision.
 f32 = FloatType(
@@ -34,6 +37,20 @@ f64 = FloatType(
         *binary64* interchange format. This corresponds to the :c:type:`double`
         type in most C implementations.
         """)
+
+#: Reference types.
+r32 = ReferenceType(
+        32, """
+        An opaque reference type with 32 bits, pointing to an object managed
+        by a garbage collector.
+        """)
+
+r64 = ReferenceType(
+        64, """
+        An opaque reference type with 64 bits, pointing to an object managed
+        by a garbage collector.
+        """)
+
 #: CPU flags from an integer comparison.
 iflags = FlagsType(
         'iflags', """
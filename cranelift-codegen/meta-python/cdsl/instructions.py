@@ -98,6 +98,8 @@ class Instruction(object):
     :param can_load: This instruction can load from memory.
     :param can_store: This instruction can store to memory.
     :param other_side_effects: Instruction has other side effects.
+    :param clobbers_all_regs: Instruction clobbers all registers, including the
+                              callee-saved ones.
     """
 
     # Boolean instruction attributes that can be passed as keyword arguments to
@@ -117,6 +119,8 @@ class Instruction(object):
             'other_side_effects':
             'Does this instruction have other side effects besides can_*',
             'writes_cpu_flags': 'Does this instruction write to CPU flags?',
+            'clobbers_all_regs':
+            'Does this instruction clobber all registers?',
             }
 
     def __init__(self, name, doc, ins=(), outs=(), constraints=(), **kwargs):
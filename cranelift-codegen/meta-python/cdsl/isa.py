@@ -1,11 +1,12 @@
 """Defining instruction set architectures."""
 from __future__ import absolute_import
 from collections import OrderedDict
-from .predicates import And, TypePredicate
+from itertools import product
+from .predicates import And, Or, Not, TypePredicate
 from .registers import RegClass, Register, Stack
 from .ast import Apply
 from .types import ValueType
-from .instructions import InstructionGroup
+from .instructions import InstructionGroup, BoundInstruction
 
 # The typing module is only required by mypy, and we don't use these imports
 # outside type comments.
@@ -13,7 +14,8 @@ try:
     from typing import Tuple, Union, Any, Iterable, Sequence, List, Set, Dict, TYPE_CHECKING  # noqa
     if TYPE_CHECKING:
         from .instructions import MaybeBoundInst, InstructionFormat  # noqa
-        from .predicates import PredNode, PredKey  # noqa
+        from .instructions import Instruction  # noqa
+        from .predicates import PredNode, PredKey, PredLeaf  # noqa
         from .settings import SettingGroup  # noqa
         from .registers import RegBank  # noqa
         from .xform import XFormGroup  # noqa
@@ -74,6 +76,7 @@ class TargetISA(object):
         self._collect_encoding_recipes()
         self._collect_predicates()
         self._collect_legalize_codes()
+        self._check_feature_fallbacks()
         return self
 
     def _collect_encoding_recipes(self):
@@ -130,6 +133,39 @@ class TargetISA(object):
             for x in cpumode.type_legalize.values():
                 self.legalize_code(x)
 
+    def _check_feature_fallbacks(self):
+        # type: () -> None
+        """
+        Check that the instructions with encodings that depend on ISA
+        predicates can still be compiled when the predicates are false.
+
+        When no encoding applies, the encoding lookup falls through to the
+        legalization action for the controlling type, so that action must be
+        able to legalize the instruction. This is what allows one backend to
+        serve CPUs with and without optional features.
+
+        Instructions that are only supported with some features are declared
+        with `CPUMode.requires_feature()`.
+        """
+        for cpumode in self.cpumodes:
+            isaps = OrderedDict()  # type: OrderedDict[Tuple[Instruction, ValueType], List[PredNode]]  # noqa
+            for enc in cpumode.encodings:
+                key = (enc.inst, enc.ctrl_typevar())
+                isap = And.combine(enc.isap, enc.recipe.isap)
+                isaps.setdefault(key, []).append(isap)
+
+            for (inst, ty), preds in isaps.items():
+                if None in preds or (inst, ty) in cpumode.feature_insts:
+                    continue
+                if cpumode.get_legalize_action(ty).can_legalize(inst, ty):
+                    continue
+                settings = uncovered_settings(preds)
+                assert settings is None, (
+                        "{}.{} has no encoding or legalization in {} with {}"
+                        .format(inst.name, ty, cpumode, ', '.join(
+                            '{}={}'.format(setting, value)
+                            for setting, value in settings.items())))
+
     def legalize_code(self, xgrp):
         # type: (XFormGroup) -> int
         """
@@ -188,6 +224,9 @@ class CPUMode(object):
         self.default_legalize = None  # type: XFormGroup
         self.type_legalize = OrderedDict()  # type: OrderedDict[ValueType, XFormGroup]  # noqa
 
+        # Instructions that are only supported with some ISA features.
+        self.feature_insts = set()  # type: Set[Tuple[Instruction, ValueType]]  # noqa
+
     def __str__(self):
         # type: () -> str
         return self.name
@@ -202,6 +241,26 @@ class CPUMode(object):
         """
         self.encodings.append(Encoding(self, *args, **kwargs))
 
+    def requires_feature(self, *insts):
+        # type: (*MaybeBoundInst) -> None
+        """
+        Declare that `insts` can only be compiled in this CPU mode when the
+        ISA predicate of one of their encodings is satisfied.
+
+        The instructions are bound to their controlling type variable only:
+
+            RV32.requires_feature(base.fpromote.f64)
+
+        Without this declaration, `TargetISA.finish()` requires a legalization
+        to fall back to when none of the encodings apply. With it, the
+        legalizer reports the instructions as unsupported instead.
+        """
+        for inst in insts:
+            if isinstance(inst, BoundInstruction):
+                self.feature_insts.add((inst.inst, inst.typevars[0]))
+            else:
+                self.feature_insts.add((inst, None))
+
     def legalize_type(self, default=None, **kwargs):
         # type: (XFormGroup, **XFormGroup) -> None
         """
@@ -268,6 +327,11 @@ class EncRecipe(object):
     `clobbers_flags` is used to indicate instruction encodings that clobbers
     the CPU flags, so they can't be used where a flag value is live.
 
+    The `latency` is the number of cycles before the results of an instruction
+    encoded with this recipe can be used by another instruction on a typical
+    in-order implementation of the ISA. It is used by the instruction
+    scheduler.
+
     :param name: Short mnemonic name for this recipe.
     :param format: All encoded instructions must have this
             :py:class:`InstructionFormat`.
@@ -277,6 +341,7 @@ class EncRecipe(object):
     :param outs: Tuple of register constraints for results.
     :param branch_range: `(origin, bits)` range for branches.
     :param clobbers_flags: This instruction clobbers `iflags` and `fflags`.
+    :param latency: Cycles before the results are available.
     :param instp: Instruction predicate.
     :param isap: ISA predicate.
     :param emit: Rust code for binary emission.
@@ -292,6 +357,7 @@ class EncRecipe(object):
             compute_size=None,        # type: str
             branch_range=None,        # type: BranchRange
             clobbers_flags=True,      # type: bool
+            latency=1,                # type: int
             instp=None,               # type: PredNode
             isap=None,                # type: PredNode
             emit=None                 # type: str
@@ -305,6 +371,8 @@ class EncRecipe(object):
             else 'base_size'
         self.branch_range = branch_range
         self.clobbers_flags = clobbers_flags
+        assert 0 < latency < 256
+        self.latency = latency
         self.instp = instp
         self.isap = isap
         self.emit = emit
@@ -428,7 +496,10 @@ class Encoding(object):
                 "Format {} must match recipe: {}".format(
                     self.inst.format, recipe.format))
 
-        if self.inst.is_branch and not self.inst.is_indirect_branch:
+        # The landing pad of a call is not encoded in the instruction, so it
+        # doesn't need a branch range.
+        if (self.inst.is_branch and not self.inst.is_indirect_branch and
+                not self.inst.is_call):
             assert recipe.branch_range, (
                     'Recipe {} for {} must have a branch_range'
                     .format(recipe, self.inst.name))
@@ -453,3 +524,37 @@ class Encoding(object):
             return self.typevars[0]
         else:
             return None
+
+
+def uncovered_settings(preds):
+    # type: (Sequence[PredNode]) -> OrderedDict[PredLeaf, bool]
+    """
+    Find values of the settings used by the ISA predicates `preds` for which
+    none of the predicates are true.
+
+    Returns a dictionary mapping the settings to their values, or `None` if
+    one of the predicates is true for all the values.
+    """
+    leafs = set()  # type: Set[PredLeaf]
+    for pred in preds:
+        pred.predicate_leafs(leafs)
+    ordered = sorted(leafs, key=str)
+    for values in product([False, True], repeat=len(ordered)):
+        settings = OrderedDict(zip(ordered, values))
+        if not any(eval_isap(pred, settings) for pred in preds):
+            return settings
+    return None
+
+
+def eval_isap(pred, settings):
+    # type: (PredNode, Dict[PredLeaf, bool]) -> bool
+    """
+    Evaluate the ISA predicate `pred` for the values of its settings.
+    """
+    if isinstance(pred, And):
+        return all(eval_isap(p, settings) for p in pred.parts)
+    if isinstance(pred, Or):
+        return any(eval_isap(p, settings) for p in pred.parts)
+    if isinstance(pred, Not):
+        return not eval_isap(pred.parts[0], settings)
+    return settings[pred]
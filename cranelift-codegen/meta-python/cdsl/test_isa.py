@@ -0,0 +1,20 @@
+from __future__ import absolute_import
+from unittest import TestCase
+from base.settings import is_pic, allones_funcaddrs
+from .isa import uncovered_settings
+from .predicates import And, Not
+
+
+class TestUncoveredSettings(TestCase):
+    def test_complement(self):
+        self.assertIsNone(uncovered_settings([is_pic, Not(is_pic)]))
+
+    def test_single(self):
+        settings = uncovered_settings([is_pic])
+        self.assertEqual(list(settings.items()), [(is_pic, False)])
+
+    def test_partial(self):
+        settings = uncovered_settings([
+            And(Not(allones_funcaddrs), Not(is_pic)),
+            And(allones_funcaddrs, Not(is_pic))])
+        self.assertEqual(settings[is_pic], True)
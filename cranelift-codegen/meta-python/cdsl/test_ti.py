@@ -355,9 +355,9 @@ class TestRTL(TypeCheckingBaseTest):
             ti = TypeEnv()
             typing = ti_rtl(r, ti).extract()
 
-            # The number of possible typings is 9 * (3+ 2*2 + 3) = 90
+            # The number of possible typings is 9 * (5 + 4 + 3 + 2 + 1) = 135
             lst = [(t[self.v0], t[self.v1]) for t in typing.concrete_typings()]
-            assert (len(lst) == len(set(lst)) and len(lst) == 90)
+            assert (len(lst) == len(set(lst)) and len(lst) == 135)
             for (tv0, tv1) in lst:
                 typ0, typ1 = (tv0.singleton_type(), tv1.singleton_type())
                 if (op == ireduce):
@@ -480,10 +480,11 @@ class TestXForm(TypeCheckingBaseTest):
         # 8 cases for v0 = i16xN times 2 options for v3 - i16, b16 = 16
         # 8 cases for v0 = i32xN times 3 options for v3 - i32, b32, f32 = 24
         # 8 cases for v0 = i64xN times 3 options for v3 - i64, b64, f64 = 24
+        # 8 cases for v0 = i128xN times 2 options for v3 - i128, b128 = 16
         #
         # (Note we have 8 cases for lanes since vselect prevents scalars)
-        # Total: 2*16 + 2*24 = 80
-        assert len(concrete_var_assigns) == 80
+        # Total: 3*16 + 2*24 = 96
+        assert len(concrete_var_assigns) == 96
 
     def test_base_legalizations_enumeration(self):
         # type: () -> None
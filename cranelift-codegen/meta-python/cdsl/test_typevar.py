@@ -79,7 +79,13 @@ class TestTypeSet(TestCase):
                          TypeSet(ints=(16, 64)))
 
         self.assertEqual(TypeSet(ints=(32, 64)).double_width(),
-                         TypeSet(ints=(64, 64)))
+                         TypeSet(ints=(64, 128)))
+
+        self.assertEqual(TypeSet(ints=(64, 128)).double_width(),
+                         TypeSet(ints=(128, 128)))
+
+        self.assertEqual(TypeSet(lanes=(1, 8), ints=(16, 32)).split_lanes(),
+                         TypeSet(lanes=(4, 16), ints=(8, 16)))
 
         # Should produce an empty ts
         self.assertEqual(TypeSet(floats=(32, 32)).half_width(),
@@ -107,7 +113,10 @@ class TestTypeSet(TestCase):
         self.assertEqual(TypeSet(bools=(1, 16)).double_width(), t)
 
         self.assertEqual(TypeSet(bools=(32, 64)).double_width(),
-                         TypeSet(bools=(64, 64)))
+                         TypeSet(bools=(64, 128)))
+
+        self.assertEqual(TypeSet(bools=(64, 128)).double_width(),
+                         TypeSet(bools=(128, 128)))
 
     def test_get_singleton(self):
         # Raise error when calling get_singleton() on non-singleton TS
@@ -138,7 +147,7 @@ class TestTypeSet(TestCase):
         # ASBOOL
         t = TypeSet(lanes=(1, 4), bools=(1, 64))
         self.assertEqual(t.preimage(TypeVar.ASBOOL),
-                         TypeSet(lanes=(1, 4), ints=True, bools=True,
+                         TypeSet(lanes=(1, 4), ints=True, bools=(1, 64),
                                  floats=True))
 
         # Half/Double Vector
@@ -157,7 +166,7 @@ class TestTypeSet(TestCase):
 
         # Half/Double Width
         t = TypeSet(ints=(8, 8), floats=(32, 32), bools=(1, 8))
-        t1 = TypeSet(ints=(64, 64), floats=(64, 64), bools=(64, 64))
+        t1 = TypeSet(ints=(128, 128), floats=(64, 64), bools=(128, 128))
         self.assertEqual(t.preimage(TypeVar.DOUBLEWIDTH).size(), 0)
         self.assertEqual(t1.preimage(TypeVar.HALFWIDTH).size(), 0)
 
@@ -167,7 +176,7 @@ class TestTypeSet(TestCase):
         self.assertEqual(t.preimage(TypeVar.DOUBLEWIDTH),
                          TypeSet(lanes=(1, 16), ints=(8, 8), floats=(32, 32)))
         self.assertEqual(t1.preimage(TypeVar.HALFWIDTH),
-                         TypeSet(lanes=(64, 256), bools=(16, 64)))
+                         TypeSet(lanes=(64, 256), bools=(16, 128)))
 
 
 def has_non_bijective_derived_f(iterable):
@@ -182,14 +191,15 @@ class TestTypeVar(TestCase):
         with self.assertRaises(AssertionError):
             x.half_width()
 
-        x2 = TypeVar('x2', 'i16 and up', ints=(16, 64))
+        x2 = TypeVar('x2', 'i16 and up', ints=(16, 128))
         with self.assertRaises(AssertionError):
             x2.double_width()
         self.assertEqual(str(x2.half_width()), '`half_width(x2)`')
-        self.assertEqual(x2.half_width().rust_expr(), 'x2.half_width()')
+        self.assertEqual(
+                x2.half_width().rust_expr(), 'x2.half_width().unwrap()')
         self.assertEqual(
                 x2.half_width().double_width().rust_expr(),
-                'x2.half_width().double_width()')
+                'x2.half_width().unwrap().double_width().unwrap()')
 
         x3 = TypeVar('x3', 'up to i32', ints=(8, 32))
         self.assertEqual(str(x3.double_width()), '`double_width(x3)`')
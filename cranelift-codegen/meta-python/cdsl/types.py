@@ -13,13 +13,17 @@ except ImportError:
 #
 # 0: Void
 # 0x01-0x6f: Special types
-# 0x70-0x7f: Lane types
+# 0x70-0x7d: Lane types
+# 0x7e-0x7f: Reference types
 # 0x80-0xff: Vector types
 #
 # Vector types are encoded with the lane type in the low 4 bits and log2(lanes)
 # in the high 4 bits, giving a range of 2-256 lanes.
 LANE_BASE = 0x70
 
+# Reference types are numbered after the lane types, but can't form vectors.
+REFERENCE_BASE = 0x7e
+
 
 # ValueType instances (i8, i32, ...) are provided in the `base.types` module.
 class ValueType(object):
@@ -39,6 +43,9 @@ class ValueType(object):
     # List of all the special types (neither lanes nor vectors).
     all_special_types = list()  # type: List[SpecialType]
 
+    # List of all the reference types.
+    all_reference_types = list()  # type: List[ReferenceType]
+
     def __init__(self, name, membytes, doc):
         # type: (str, int, str) -> None
         self.name = name
@@ -105,7 +112,7 @@ class LaneType(ValueType):
         # Assign numbers starting from LANE_BASE.
         n = len(ValueType.all_lane_types)
         ValueType.all_lane_types.append(self)
-        assert n < 16, 'Too many lane types'
+        assert LANE_BASE + n < REFERENCE_BASE, 'Too many lane types'
         self.number = LANE_BASE + n
 
     def __repr__(self):
@@ -308,6 +315,51 @@ class FlagsType(SpecialType):
         return 'FlagsType({})'.format(self.name)
 
 
+class ReferenceType(ValueType):
+    """
+    A concrete reference type, an opaque pointer to an object managed by a
+    garbage collector.
+
+    Reference types can't form vectors, and they can only be converted to
+    other types by storing them in memory.
+    """
+
+    def __init__(self, bits, doc):
+        # type: (int, str) -> None
+        super(ReferenceType, self).__init__(
+                name='r{:d}'.format(bits),
+                membytes=bits // 8,
+                doc=doc)
+        self.bits = bits
+        n = len(ValueType.all_reference_types)
+        ValueType.all_reference_types.append(self)
+        self.number = REFERENCE_BASE + n
+        assert self.number < LANE_BASE + 16, 'Too many reference types'
+
+    def __repr__(self):
+        # type: () -> str
+        return 'ReferenceType(bits={})'.format(self.bits)
+
+    @staticmethod
+    def with_bits(bits):
+        # type: (int) -> ReferenceType
+        typ = ValueType.by_name('r{:d}'.format(bits))
+        if TYPE_CHECKING:
+            return cast(ReferenceType, typ)
+        else:
+            return typ
+
+    def lane_bits(self):
+        # type: () -> int
+        """Return the number of bits in a lane."""
+        return self.bits
+
+    def lane_count(self):
+        # type: () -> int
+        """Return the number of lanes."""
+        return 1
+
+
 class BVType(ValueType):
     """A flat bitvector type. Used for semantics description only."""
 
@@ -22,7 +22,9 @@ except ImportError:
     pass
 
 MAX_LANES = 256
-MAX_BITS = 64
+MAX_BITS = 128
+MAX_FLOAT_BITS = 64
+MAX_REF_BITS = 64
 MAX_BITVEC = MAX_BITS * MAX_LANES
 
 
@@ -152,17 +154,23 @@ class TypeSet(object):
     Passing `True` instead of a range selects all available scalar types:
 
     >>> TypeSet(ints=True)
-    TypeSet(lanes={1}, ints={8, 16, 32, 64})
+    TypeSet(lanes={1}, ints={8, 16, 32, 64, 128})
     >>> TypeSet(floats=True)
     TypeSet(lanes={1}, floats={32, 64})
     >>> TypeSet(bools=True)
-    TypeSet(lanes={1}, bools={1, 8, 16, 32, 64})
+    TypeSet(lanes={1}, bools={1, 8, 16, 32, 64, 128})
 
     Similarly, passing `True` for the lanes selects all possible scalar and
     vector types:
 
     >>> TypeSet(lanes=True, ints=True)
-    TypeSet(lanes={1, 2, 4, 8, 16, 32, 64, 128, 256}, ints={8, 16, 32, 64})
+    TypeSet(lanes={1, 2, 4, 8, 16, 32, 64, 128, 256}, ints={8, 16, 32, 64, 128})
+
+    Reference types are only scalars, so they only appear in type sets with a
+    single lane:
+
+    >>> TypeSet(refs=True)
+    TypeSet(lanes={1}, refs={32, 64})
 
     Finally, a type set can contain special types (derived from `SpecialType`)
     which can't appear as lane types.
@@ -176,6 +184,7 @@ class TypeSet(object):
                   widths.
     :param bitvecs : `(min, max)` inclusive range of permitted bitvector
                   widths.
+    :param refs: `(min, max)` inclusive range of permitted reference widths.
     :param specials: Sequence of special types to appear in the set.
     """
 
@@ -186,16 +195,19 @@ class TypeSet(object):
             floats=None,    # type: BoolInterval
             bools=None,     # type: BoolInterval
             bitvecs=None,   # type: BoolInterval
+            refs=None,      # type: BoolInterval
             specials=None   # type: SpecialSpec
             ):
         # type: (...) -> None
         self.lanes = interval_to_set(decode_interval(lanes, (1, MAX_LANES), 1))
         self.ints = interval_to_set(decode_interval(ints, (8, MAX_BITS)))
-        self.floats = interval_to_set(decode_interval(floats, (32, 64)))
+        self.floats = interval_to_set(decode_interval(floats,
+                                                      (32, MAX_FLOAT_BITS)))
         self.bools = interval_to_set(decode_interval(bools, (1, MAX_BITS)))
         self.bools = set(filter(legal_bool, self.bools))
         self.bitvecs = interval_to_set(decode_interval(bitvecs,
                                                        (1, MAX_BITVEC)))
+        self.refs = interval_to_set(decode_interval(refs, (32, MAX_REF_BITS)))
         # Allow specials=None, specials=True, specials=(...)
         self.specials = set()  # type: Set[types.SpecialType]
         if isinstance(specials, bool):
@@ -215,17 +227,19 @@ class TypeSet(object):
         n.floats = copy(self.floats)
         n.bools = copy(self.bools)
         n.bitvecs = copy(self.bitvecs)
+        n.refs = copy(self.refs)
         n.specials = copy(self.specials)
         return n
 
     def typeset_key(self):
-        # type: () -> Tuple[Tuple, Tuple, Tuple, Tuple, Tuple, Tuple]
+        # type: () -> Tuple[Tuple, Tuple, Tuple, Tuple, Tuple, Tuple, Tuple]
         """Key tuple used for hashing and equality."""
         return (tuple(sorted(list(self.lanes))),
                 tuple(sorted(list(self.ints))),
                 tuple(sorted(list(self.floats))),
                 tuple(sorted(list(self.bools))),
                 tuple(sorted(list(self.bitvecs))),
+                tuple(sorted(list(self.refs))),
                 tuple(sorted(s.name for s in self.specials)))
 
     def __hash__(self):
@@ -257,6 +271,8 @@ class TypeSet(object):
             s += ', bools={}'.format(pp_set(self.bools))
         if len(self.bitvecs) > 0:
             s += ', bitvecs={}'.format(pp_set(self.bitvecs))
+        if len(self.refs) > 0:
+            s += ', refs={}'.format(pp_set(self.refs))
         if len(self.specials) > 0:
             s += ', specials=[{}]'.format(pp_set(self.specials))
         return s + ')'
@@ -270,7 +286,8 @@ class TypeSet(object):
         fields = (('lanes', 16),
                   ('ints', 8),
                   ('floats', 8),
-                  ('bools', 8))
+                  ('bools', 8),
+                  ('refs', 8))
 
         for (field, bits) in fields:
             vals = [int_log2(x) for x in getattr(self, field)]
@@ -301,6 +318,7 @@ class TypeSet(object):
         self.floats.intersection_update(other.floats)
         self.bools.intersection_update(other.bools)
         self.bitvecs.intersection_update(other.bitvecs)
+        self.refs.intersection_update(other.refs)
         self.specials.intersection_update(other.specials)
 
         return self
@@ -315,6 +333,7 @@ class TypeSet(object):
             self.floats.issubset(other.floats) and \
             self.bools.issubset(other.bools) and \
             self.bitvecs.issubset(other.bitvecs) and \
+            self.refs.issubset(other.refs) and \
             self.specials.issubset(other.specials)
 
     def lane_of(self):
@@ -336,6 +355,7 @@ class TypeSet(object):
         new.ints = set()
         new.floats = set()
         new.bitvecs = set()
+        new.refs = set()
 
         if len(self.lanes.difference(set([1]))) > 0:
             new.bools = self.ints.union(self.floats).union(self.bools)
@@ -354,6 +374,7 @@ class TypeSet(object):
         new.floats = set([x//2 for x in self.floats if x > 32])
         new.bools = set([x//2 for x in self.bools if x > 8])
         new.bitvecs = set([x//2 for x in self.bitvecs if x > 1])
+        new.refs = set()
         new.specials = set()
 
         return new
@@ -365,10 +386,11 @@ class TypeSet(object):
         """
         new = self.copy()
         new.ints = set([x*2 for x in self.ints if x < MAX_BITS])
-        new.floats = set([x*2 for x in self.floats if x < MAX_BITS])
+        new.floats = set([x*2 for x in self.floats if x < MAX_FLOAT_BITS])
         new.bools = set(filter(legal_bool,
                                set([x*2 for x in self.bools if x < MAX_BITS])))
         new.bitvecs = set([x*2 for x in self.bitvecs if x < MAX_BITVEC])
+        new.refs = set()
         new.specials = set()
 
         return new
@@ -381,6 +403,7 @@ class TypeSet(object):
         new = self.copy()
         new.bitvecs = set()
         new.lanes = set([x//2 for x in self.lanes if x > 1])
+        new.refs = set()
         new.specials = set()
 
         return new
@@ -393,10 +416,21 @@ class TypeSet(object):
         new = self.copy()
         new.bitvecs = set()
         new.lanes = set([x*2 for x in self.lanes if x < MAX_LANES])
+        new.refs = set()
         new.specials = set()
 
         return new
 
+    def split_lanes(self):
+        # type: () -> TypeSet
+        """
+        Return a TypeSet describing the image of self across split_lanes
+        """
+        new = self.half_width().double_vector()
+        new.lanes = set([x*2 for x in self.lanes if x > 1 and x < MAX_LANES])
+
+        return new
+
     def to_bitvec(self):
         # type: () -> TypeSet
         """
@@ -412,6 +446,7 @@ class TypeSet(object):
         new.floats = set()
         new.bitvecs = set([lane_w * nlanes for lane_w in all_scalars
                            for nlanes in self.lanes])
+        new.refs = set()
         new.specials = set()
 
         return new
@@ -433,6 +468,8 @@ class TypeSet(object):
             return self.half_vector()
         elif (func == TypeVar.DOUBLEVECTOR):
             return self.double_vector()
+        elif (func == TypeVar.SPLITLANES):
+            return self.split_lanes()
         elif (func == TypeVar.TOBITVEC):
             return self.to_bitvec()
         else:
@@ -455,16 +492,18 @@ class TypeSet(object):
         elif (func == TypeVar.ASBOOL):
             new = self.copy()
             new.bitvecs = set()
+            new.refs = set()
 
             if 1 not in self.bools:
                 new.ints = self.bools.difference(set([1]))
-                new.floats = self.bools.intersection(set([32, 64]))
+                new.floats = self.bools.intersection(
+                        interval_to_set((32, MAX_FLOAT_BITS)))
                 # If b1 is not in our typeset, than lanes=1 cannot be in the
                 # pre-image, as as_bool() of scalars is always b1.
                 new.lanes = self.lanes.difference(set([1]))
             else:
-                new.ints = set([2**x for x in range(3, 7)])
-                new.floats = set([32, 64])
+                new.ints = interval_to_set((8, MAX_BITS))
+                new.floats = interval_to_set((32, MAX_FLOAT_BITS))
 
             return new
         elif (func == TypeVar.HALFWIDTH):
@@ -475,13 +514,18 @@ class TypeSet(object):
             return self.double_vector()
         elif (func == TypeVar.DOUBLEVECTOR):
             return self.half_vector()
+        elif (func == TypeVar.SPLITLANES):
+            new = self.double_width().half_vector()
+            new.lanes = set([x//2 for x in self.lanes if x > 2])
+            return new
         elif (func == TypeVar.TOBITVEC):
             new = TypeSet()
 
             # Start with all possible lanes/ints/floats/bools
             lanes = interval_to_set(decode_interval(True, (1, MAX_LANES), 1))
             ints = interval_to_set(decode_interval(True, (8, MAX_BITS)))
-            floats = interval_to_set(decode_interval(True, (32, 64)))
+            floats = interval_to_set(decode_interval(True,
+                                                     (32, MAX_FLOAT_BITS)))
             bools = interval_to_set(decode_interval(True, (1, MAX_BITS)))
 
             # See which combinations have a size that appears in self.bitvecs
@@ -518,7 +562,15 @@ class TypeSet(object):
         """
         return (len(self.lanes) * (len(self.ints) + len(self.floats) +
                                    len(self.bools) + len(self.bitvecs)) +
-                len(self.specials))
+                self.scalar_refs_count() + len(self.specials))
+
+    def scalar_refs_count(self):
+        # type: () -> int
+        """
+        Return the number of reference types in self. They only exist as
+        scalars.
+        """
+        return len(self.refs) if 1 in self.lanes else 0
 
     def concrete_types(self):
         # type: () -> Iterable[types.ValueType]
@@ -539,6 +591,9 @@ class TypeSet(object):
             for bits in self.bitvecs:
                 assert nlanes == 1
                 yield types.BVType.with_bits(bits)
+            if nlanes == 1:
+                for bits in self.refs:
+                    yield types.ReferenceType.with_bits(bits)
 
         for spec in self.specials:
             yield spec
@@ -558,7 +613,10 @@ class TypeSet(object):
         """ Return a set of the widths of all possible types in self"""
         scalar_w = self.ints.union(self.floats.union(self.bools))
         scalar_w = scalar_w.union(self.bitvecs)
-        return set(w * l for l in self.lanes for w in scalar_w)
+        widths = set(w * l for l in self.lanes for w in scalar_w)
+        if 1 in self.lanes:
+            widths.update(self.refs)
+        return widths
 
 
 class TypeVar(object):
@@ -581,6 +639,7 @@ class TypeVar(object):
     :param simd: Allow type variable to assume vector types, or `(min, max)`
                  lane count range.
     :param bitvecs: Allow all BitVec base types, or `(min, max)` bit-range.
+    :param refs: Allow all reference types, or `(min, max)` bit-range.
     """
 
     def __init__(
@@ -593,6 +652,7 @@ class TypeVar(object):
             scalars=True,           # type: bool
             simd=False,             # type: BoolInterval
             bitvecs=False,          # type: BoolInterval
+            refs=False,             # type: BoolInterval
             base=None,              # type: TypeVar
             derived_func=None,      # type: str
             specials=None           # type: SpecialSpec
@@ -616,6 +676,7 @@ class TypeVar(object):
                     floats=floats,
                     bools=bools,
                     bitvecs=bitvecs,
+                    refs=refs,
                     specials=specials)
 
     @staticmethod
@@ -631,6 +692,9 @@ class TypeVar(object):
             lanes = (1, 1)
         elif isinstance(typ, types.SpecialType):
             return TypeVar(typ.name, typ.__doc__, specials=[typ])
+        elif isinstance(typ, types.ReferenceType):
+            return TypeVar(
+                    typ.name, typ.__doc__, refs=(typ.bits, typ.bits))
         else:
             assert isinstance(typ, types.BVType)
             scalar = typ
@@ -703,6 +767,7 @@ class TypeVar(object):
     DOUBLEWIDTH = 'double_width'
     HALFVECTOR = 'half_vector'
     DOUBLEVECTOR = 'double_vector'
+    SPLITLANES = 'split_lanes'
     TOBITVEC = 'to_bitvec'
 
     @staticmethod
@@ -746,7 +811,7 @@ class TypeVar(object):
                 assert max(ts.ints) < MAX_BITS,\
                     "Can't double all integer types."
             if len(ts.floats) > 0:
-                assert max(ts.floats) < MAX_BITS,\
+                assert max(ts.floats) < MAX_FLOAT_BITS,\
                     "Can't double all float types."
             if len(ts.bools) > 0:
                 assert max(ts.bools) < MAX_BITS, "Can't double all bool types."
@@ -754,6 +819,15 @@ class TypeVar(object):
             assert min(ts.lanes) > 1, "Can't halve a scalar type"
         elif derived_func == TypeVar.DOUBLEVECTOR:
             assert max(ts.lanes) < MAX_LANES, "Can't double 256 lanes."
+        elif derived_func == TypeVar.SPLITLANES:
+            assert min(ts.lanes) > 1, "Can't split the lanes of a scalar"
+            assert max(ts.lanes) < MAX_LANES, "Can't double 256 lanes."
+            if len(ts.ints) > 0:
+                assert min(ts.ints) > 8, "Can't halve all integer types"
+            if len(ts.floats) > 0:
+                assert min(ts.floats) > 32, "Can't halve all float types"
+            if len(ts.bools) > 0:
+                assert min(ts.bools) > 8, "Can't halve all boolean types"
 
         return TypeVar(None, None, base=base, derived_func=derived_func)
 
@@ -816,6 +890,14 @@ class TypeVar(object):
         """
         return TypeVar.derived(self, self.DOUBLEVECTOR)
 
+    def split_lanes(self):
+        # type: () -> TypeVar
+        """
+        Return a derived type variable that has twice the number of vector
+        lanes as this one, each half the width. The total size is unchanged.
+        """
+        return TypeVar.derived(self, self.SPLITLANES)
+
     def to_bitvec(self):
         # type: () -> TypeVar
         """
@@ -855,8 +937,12 @@ class TypeVar(object):
         Get a Rust expression that computes the type of this type variable.
         """
         if self.is_derived:
-            return '{}.{}()'.format(
-                    self.base.rust_expr(), self.derived_func)
+            expr = '{}.{}()'.format(self.base.rust_expr(), self.derived_func)
+            # The width changing methods return an `Option<Type>`. Generated
+            # code only gets here after checking that the type exists.
+            if self.derived_func in (TypeVar.HALFWIDTH, TypeVar.DOUBLEWIDTH):
+                expr += '.unwrap()'
+            return expr
         elif self.singleton_type():
             return self.singleton_type().rust_name()
         else:
@@ -9,11 +9,13 @@ from functools import reduce
 
 try:
     from typing import Union, Iterator, Sequence, Iterable, List, Dict  # noqa
-    from typing import Optional, Set # noqa
+    from typing import Optional, Set, Tuple # noqa
     from .ast import Expr, VarAtomMap  # noqa
     from .isa import TargetISA  # noqa
     from .typevar import TypeVar  # noqa
     from .instructions import ConstrList, Instruction # noqa
+    from .instructions import BoundInstruction  # noqa
+    from .types import ValueType  # noqa
     DefApply = Union[Def, Apply]
 except ImportError:
     pass
@@ -372,6 +374,7 @@ class XFormGroup(object):
         # type: (str, str, TargetISA, XFormGroup) -> None
         self.xforms = list()  # type: List[XForm]
         self.custom = OrderedDict()  # type: OrderedDict[Instruction, str]
+        self.libcalls = set()  # type: Set[Tuple[Instruction, ValueType]]
         self.name = name
         self.__doc__ = doc
         self.isa = isa
@@ -421,3 +424,36 @@ class XFormGroup(object):
         """
         assert inst not in self.custom, "Duplicate custom_legalize"
         self.custom[inst] = funcname
+
+    def libcall(self, *insts):
+        # type: (*BoundInstruction) -> None
+        """
+        Declare that `insts`, bound to their controlling type, are legalized
+        as runtime library calls when this group has no transformation for
+        them.
+
+        The library calls are inserted by `expand_as_libcall()`, so
+        `LibCall::for_inst()` must have an entry for the instructions. This
+        declaration is only used by `can_legalize()`.
+        """
+        for inst in insts:
+            self.libcalls.add((inst.inst, inst.typevars[0]))
+
+    def can_legalize(self, inst, ty):
+        # type: (Instruction, ValueType) -> bool
+        """
+        Can this group or the groups chained after it legalize `inst` with
+        the controlling type `ty`?
+
+        The type constraints of the patterns are not considered, so this is
+        only an approximation.
+        """
+        xgrp = self  # type: XFormGroup
+        while xgrp is not None:
+            if inst in xgrp.custom or (inst, ty) in xgrp.libcalls:
+                return True
+            for xform in xgrp.xforms:
+                if xform.src.rtl[0].expr.inst == inst:
+                    return True
+            xgrp = xgrp.chain
+        return False
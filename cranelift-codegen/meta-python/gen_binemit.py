@@ -32,7 +32,8 @@ def gen_recipe(recipe, fmt):
                     for o in recipe.outs)
 
     # Regmove instructions get special treatment.
-    is_regmove = (recipe.format.name in ('RegMove', 'RegSpill', 'RegFill'))
+    is_regmove = (recipe.format.name in
+                  ('RegMove', 'RegSwap', 'RegSpill', 'RegFill'))
 
     # First unpack the instruction.
     with fmt.indented(
@@ -75,6 +76,8 @@ def gen_recipe(recipe, fmt):
         # diversion tracker.
         if recipe.format.name == 'RegMove':
             fmt.line('divert.regmove(arg, src, dst);')
+        elif recipe.format.name == 'RegSwap':
+            fmt.line('divert.regswap(args[0], args[1], src, dst);')
         elif recipe.format.name == 'RegSpill':
             fmt.line('divert.regspill(arg, src, dst);')
         elif recipe.format.name == 'RegFill':
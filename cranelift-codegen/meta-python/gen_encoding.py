@@ -845,6 +845,18 @@ def emit_recipe_sizing(isa, fmt):
                     fmt.line('branch_range: None,')
 
 
+def emit_recipe_latencies(isa, fmt):
+    # type: (TargetISA, srcgen.Formatter) -> None
+    """
+    Emit a table of encoding recipe latencies.
+    """
+    with fmt.indented(
+            'static RECIPE_LATENCIES: [u8; {}] = ['
+            .format(len(isa.all_recipes)), '];'):
+        for r in isa.all_recipes:
+            fmt.format('{}, // {}', r.latency, r.name)
+
+
 def gen_isa(isa, fmt):
     # type: (TargetISA, srcgen.Formatter) -> None
 
@@ -886,11 +898,13 @@ def gen_isa(isa, fmt):
     emit_recipe_names(isa, fmt)
     emit_recipe_constraints(isa, fmt)
     emit_recipe_sizing(isa, fmt)
+    emit_recipe_latencies(isa, fmt)
 
     # Finally, tie it all together in an `EncInfo`.
     with fmt.indented('pub static INFO: isa::EncInfo = isa::EncInfo {', '};'):
         fmt.line('constraints: &RECIPE_CONSTRAINTS,')
         fmt.line('sizing: &RECIPE_SIZING,')
+        fmt.line('latencies: &RECIPE_LATENCIES,')
         fmt.line('names: &RECIPE_NAMES,')
 
 
@@ -148,6 +148,8 @@ def gen_instruction_data_impl(fmt):
     - `pub fn put_value_list(&mut self, args: ir::ValueList>`
     - `pub fn eq(&self, &other: Self, &pool) -> bool`
     - `pub fn hash<H: Hasher>(&self, state: &mut H, &pool)`
+    - `pub(crate) fn encode_operands(&self, &mut enc, &pool)`
+    - `pub(crate) fn decode_operands(opcode, &mut dec, &mut pool)`
     """
 
     # The `opcode` method simply reads the `opcode` members. This is really a
@@ -331,6 +333,81 @@ def gen_instruction_data_impl(fmt):
                                      .format(field.member))
                         fmt.line('::core::hash::Hash::hash({}, state);'
                                  .format(args))
+        fmt.line()
+
+        fmt.doc_comment(
+                """
+                Encode the immediate fields and value arguments of this
+                instruction.
+
+                The opcode is not included. This requires a reference to a
+                `ValueListPool` to encode the contents of any `ValueLists`.
+                """)
+        with fmt.indented(
+                'pub(crate) fn encode_operands'
+                '(&self, enc: &mut Encoder, pool: &ir::ValueListPool) {',
+                '}'):
+            with fmt.indented('match *self {', '}'):
+                for f in InstructionFormat.all_formats:
+                    n = 'InstructionData::' + f.name
+                    members = []
+                    if f.typevar_operand is None:
+                        args = None
+                    elif f.has_value_list:
+                        members.append('ref args')
+                        args = 'args.as_slice(pool)'
+                    elif f.num_value_operands == 1:
+                        members.append('arg')
+                        args = '&arg'
+                    else:
+                        members.append('ref args')
+                        args = '&args[..]'
+                    for field in f.imm_fields:
+                        members.append(field.member)
+                    pat = n + ' { ' + ', '.join(members + ['..']) + ' }'
+                    if not members:
+                        fmt.line(pat + ' => {}')
+                        continue
+                    with fmt.indented(pat + ' => {', '}'):
+                        for field in f.imm_fields:
+                            fmt.line('enc.put(&{});'.format(field.member))
+                        if args is not None:
+                            fmt.line('enc.put({});'.format(args))
+        fmt.line()
+
+        fmt.doc_comment(
+                """
+                Decode the operands of an `opcode` instruction, as encoded by
+                `encode_operands`.
+
+                Any value list is allocated from `pool`.
+                """)
+        with fmt.indented(
+                'pub(crate) fn decode_operands(opcode: Opcode, '
+                'dec: &mut Decoder, pool: &mut ir::ValueListPool) '
+                '-> DeserializeResult<Self> {',
+                '}'):
+            with fmt.indented('Ok(match opcode.format() {', '})'):
+                for f in InstructionFormat.all_formats:
+                    n = 'InstructionData::' + f.name
+                    # The fields are decoded in the order they are written.
+                    fields = ['opcode']
+                    for field in f.imm_fields:
+                        fields.append('{}: dec.get()?'.format(field.member))
+                    if f.typevar_operand is None:
+                        pass
+                    elif f.has_value_list:
+                        fields.append('args: dec.get_value_list(pool)?')
+                    elif f.num_value_operands == 1:
+                        fields.append('arg: dec.get()?')
+                    else:
+                        fields.append('args: [{}]'.format(', '.join(
+                            ['dec.get()?'] * f.num_value_operands)))
+                    with fmt.indented(
+                            'InstructionFormat::{} => {} {{'
+                            .format(f.name, n), '},'):
+                        for field in fields:
+                            fmt.line(field + ',')
 
 
 def collect_instr_groups(isas):
@@ -403,7 +480,7 @@ def gen_opcodes(groups, fmt):
 
     # Generate a private opcode_format table.
     with fmt.indented(
-            'const OPCODE_FORMAT: [InstructionFormat; {}] = ['
+            'static OPCODE_FORMAT: [InstructionFormat; {}] = ['
             .format(len(instrs)),
             '];'):
         for i in instrs:
@@ -412,6 +489,13 @@ def gen_opcodes(groups, fmt):
                     i.format.name, i.name)
     fmt.line()
 
+    # Generate a private table of opcodes, indexed by opcode number.
+    with fmt.indented(
+            'static OPCODES: [Opcode; {}] = ['.format(len(instrs)), '];'):
+        for i in instrs:
+            fmt.format('Opcode::{},', i.camel_name)
+    fmt.line()
+
     # Generate a private opcode_name function.
     with fmt.indented('fn opcode_name(opc: Opcode) -> &\'static str {', '}'):
         m = srcgen.Match('opc')
@@ -425,7 +509,7 @@ def gen_opcodes(groups, fmt):
             instrs,
             lambda i: constant_hash.simple_hash(i.name))
     with fmt.indented(
-            'const OPCODE_HASH_TABLE: [Option<Opcode>; {}] = ['
+            'static OPCODE_HASH_TABLE: [Option<Opcode>; {}] = ['
             .format(len(hash_table)), '];'):
         for i in hash_table:
             if i is None:
@@ -481,7 +565,7 @@ def gen_typesets_table(fmt, type_sets):
     fmt.comment('Table of value type sets.')
     assert len(type_sets.table) <= typeset_limit, "Too many type sets"
     with fmt.indented(
-            'const TYPE_SETS: [ir::instructions::ValueTypeSet; {}] = ['
+            'static TYPE_SETS: [ir::instructions::ValueTypeSet; {}] = ['
             .format(len(type_sets.table)), '];'):
         for ts in type_sets.table:
             with fmt.indented('ir::instructions::ValueTypeSet {', '},'):
@@ -514,7 +598,7 @@ def gen_type_constraints(fmt, instrs):
 
     fmt.comment('Table of opcode constraints.')
     with fmt.indented(
-            'const OPCODE_CONSTRAINTS: [OpcodeConstraints; {}] = ['
+            'static OPCODE_CONSTRAINTS: [OpcodeConstraints; {}] = ['
             .format(len(instrs)), '];'):
         for i in instrs:
             # Collect constraints for the value results, not including
@@ -573,7 +657,7 @@ def gen_type_constraints(fmt, instrs):
 
     fmt.comment('Table of operand constraint sequences.')
     with fmt.indented(
-            'const OPERAND_CONSTRAINTS: [OperandConstraint; {}] = ['
+            'static OPERAND_CONSTRAINTS: [OperandConstraint; {}] = ['
             .format(len(operand_seqs.table)), '];'):
         for c in operand_seqs.table:
             fmt.line('OperandConstraint::{},'.format(c))
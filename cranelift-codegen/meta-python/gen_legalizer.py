@@ -118,6 +118,9 @@ def emit_runtime_typecheck(check, fmt, type_sets):
                 .format(base_exp)
         elif (tv.derived_func == TypeVar.DOUBLEVECTOR):
             return "{}.and_then(|t: crate::ir::Type| t.by(2))".format(base_exp)
+        elif (tv.derived_func == TypeVar.SPLITLANES):
+            return "{}.and_then(|t: crate::ir::Type| t.split_lanes())"\
+                .format(base_exp)
         else:
             assert False, "Unknown derived function {}".format(tv.derived_func)
 
@@ -6,7 +6,7 @@ Commonly used definitions.
 from __future__ import absolute_import
 from cdsl.isa import TargetISA, CPUMode
 import base.instructions
-from base.legalize import narrow
+from base.legalize import narrow, narrow_wide
 
 ISA = TargetISA('arm32', [base.instructions.GROUP])  # type: TargetISA
 
@@ -14,6 +14,7 @@ ISA = TargetISA('arm32', [base.instructions.GROUP])  # type: TargetISA
 A32 = CPUMode('A32', ISA)
 T32 = CPUMode('T32', ISA)
 
-# TODO: Refine these.
-A32.legalize_type(narrow)
-T32.legalize_type(narrow)
+# TODO: Refine this. The T32 legalization is set up in `encodings.py`.
+A32.legalize_type(
+        default=narrow_wide,
+        i64=narrow)
@@ -0,0 +1,110 @@
+"""
+ARM32 Encodings.
+"""
+from __future__ import absolute_import
+from base import instructions as base
+from base import types
+from .defs import T32
+from .recipes import COND
+from .recipes import Nrrr, Nrr, Nr, Nri3, Nri8, Nshamt, Niz, Ncopy, Nrmov
+from .recipes import Nld, Nst, Nret, Ncalli, Njump
+from .recipes import Wrrr, Wr, Wmul, Wri12, Wri8, Wiz, Wiz32, Wld, Wst
+from .recipes import Wjump, Wcall, Wicmp, Wsel
+from .recipes import Bz4, Bz6, Bz8, Bz10, Bicmp4, Bicmp6, Bicmp8, Bicmp10
+from base.legalize import narrow, narrow_wide, expand
+
+T32.legalize_monomorphic(expand)
+T32.legalize_type(
+        default=narrow_wide,
+        i32=expand,
+        i64=narrow)
+
+# The 32-bit encodings come first so the legalizer picks an encoding that can
+# use all the registers. The `shrink_instructions` pass replaces them with the
+# 16-bit encodings when the allocated registers permit it.
+
+# Basic arithmetic and logical binary instructions.
+for inst,              w,      n16,    tied16 in [
+        (base.iadd,    0xeb00, 0x1800, None),
+        (base.isub,    0xeba0, 0x1a00, None),
+        (base.band,    0xea00, None,   0x4000),
+        (base.bor,     0xea40, None,   0x4300),
+        (base.bxor,    0xea80, None,   0x4040),
+        (base.band_not, 0xea20, None,  0x4380),
+        (base.bor_not, 0xea60, None,   None),
+        ]:
+    T32.enc(inst.i32, Wrrr, w)
+    if n16:
+        T32.enc(inst.i32, Nrrr, n16)
+    if tied16:
+        T32.enc(inst.i32, Nrr, tied16)
+
+T32.enc(base.imul.i32, Wmul, 0xfb00)
+T32.enc(base.imul.i32, Nrr, 0x4340)
+
+# Immediate operands.
+T32.enc(base.iadd_imm.i32, Wri12, 0xf200)
+T32.enc(base.iadd_imm.i32, Nri3, 0x1c00)
+T32.enc(base.iadd_imm.i32, Nri8, 0x3000)
+T32.enc(base.band_imm.i32, Wri8, 0xf000)
+T32.enc(base.bor_imm.i32, Wri8, 0xf040)
+T32.enc(base.bxor_imm.i32, Wri8, 0xf080)
+
+# The register-controlled shifts don't mask the shift amount like the clif
+# base instructions do, so only the immediate shifts are encoded.
+T32.enc(base.ishl_imm.i32, Nshamt, 0x0000)
+T32.enc(base.ushr_imm.i32, Nshamt, 0x0800)
+T32.enc(base.sshr_imm.i32, Nshamt, 0x1000)
+
+# Unary operations.
+T32.enc(base.bnot.i32, Wr, 0xea6f)
+T32.enc(base.bnot.i32, Nr, 0x43c0)
+T32.enc(base.bswap.i32, Nr, 0xba00)
+T32.enc(base.sextend.i32.i8, Nr, 0xb240)
+T32.enc(base.sextend.i32.i16, Nr, 0xb200)
+T32.enc(base.uextend.i32.i8, Nr, 0xb2c0)
+T32.enc(base.uextend.i32.i16, Nr, 0xb280)
+
+# Integer constants.
+T32.enc(base.iconst.i32, Wiz, 0xf240)
+T32.enc(base.iconst.i32, Wiz32, 0xf240)
+T32.enc(base.iconst.i32, Niz, 0x2000)
+
+# Comparisons and selects without IT blocks.
+T32.enc(base.icmp.i32, Wicmp, 0)
+T32.enc(base.select.i32.b1, Wsel, 0)
+T32.enc(base.select.i32.i32, Wsel, 0)
+
+# Memory access.
+T32.enc(base.load.i32.any, Wld, 0xf8d0)
+T32.enc(base.load.i32.any, Nld, 0x6800)
+T32.enc(base.store.i32.any, Wst, 0xf8c0)
+T32.enc(base.store.i32.any, Nst, 0x6000)
+
+# Register copies. The 16-bit `mov` can access all registers.
+for ty in [types.i32, types.b1]:
+    T32.enc(base.copy.bind(ty), Ncopy, 0x4600)
+    T32.enc(base.regmove.bind(ty), Nrmov, 0x4600)
+
+# Control flow.
+#
+# Unconditional branches are listed with the shortest range first. Branch
+# relaxation picks the smallest encoding that reaches the destination.
+T32.enc(base.jump, Njump, 0xe000)
+T32.enc(base.jump, Wjump, 0xf000)
+
+for inst, cond in [(base.brz, 'eq'), (base.brnz, 'ne')]:
+    for ty in [types.i32, types.b1]:
+        T32.enc(inst.bind(ty), Bz8, COND(cond))
+        T32.enc(inst.bind(ty), Bz10, COND(cond))
+        T32.enc(inst.bind(ty), Bz6, COND(cond))
+        T32.enc(inst.bind(ty), Bz4, COND(cond))
+
+T32.enc(base.br_icmp.i32, Bicmp8, 0)
+T32.enc(base.br_icmp.i32, Bicmp10, 0)
+T32.enc(base.br_icmp.i32, Bicmp6, 0)
+T32.enc(base.br_icmp.i32, Bicmp4, 0)
+
+T32.enc(base.x_return, Nret, 0x4770)
+T32.enc(base.call, Wcall, 0xf000)
+T32.enc(base.call_indirect.i32, Ncalli, 0x4780)
@@ -0,0 +1,325 @@
+"""
+ARM32 Encoding recipes.
+
+Only the Thumb-2 (T32) instruction set has encodings so far. T32 mixes 16-bit
+and 32-bit instructions. The encoding recipes are named after the ARM `.n` and
+`.w` assembler suffixes:
+
+- `N*` recipes are single 16-bit instructions. The encoding bits are the
+  complete instruction with the register and immediate fields cleared. Most of
+  them can only access the low registers r0-r7.
+- `W*` recipes are single 32-bit instructions. The encoding bits are the
+  first halfword with the register and immediate fields cleared. The recipe
+  supplies the fixed bits of the second halfword.
+
+Conditional operations are implemented with short sequences of a comparison
+and a conditional branch instead of IT blocks, so they are valid on every
+Thumb-2 core and don't depend on the IT state.
+"""
+from __future__ import absolute_import
+from cdsl.isa import EncRecipe
+from cdsl.predicates import IsUnsignedInt
+from base.formats import Binary, BinaryImm, MultiAry, IntCompare, Unary
+from base.formats import UnaryImm, BranchIcmp, Branch, Jump, Call
+from base.formats import CallIndirect, RegMove, Load, Store, Ternary
+from .registers import GPR, GPR8
+
+
+def COND(cond):
+    # type: (str) -> int
+    """
+    Encoding bits for the `brz` and `brnz` recipes hold the condition code of
+    the branch taken after comparing the operand with zero.
+    """
+    return {'eq': 0b0000, 'ne': 0b0001}[cond]
+
+
+# 16-bit three-register arithmetic: `adds rd, rn, rm`.
+Nrrr = EncRecipe(
+        'Nrrr', Binary, base_size=2, ins=(GPR8, GPR8), outs=GPR8,
+        emit='put_n_rrr(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# 16-bit data processing with a tied destination: `ands rdn, rm`.
+Nrr = EncRecipe(
+        'Nrr', Binary, base_size=2, ins=(GPR8, GPR8), outs=0,
+        emit='put_n_rr(bits, in_reg0, in_reg1, sink);')
+
+# 16-bit unary operation: `mvns rd, rm`.
+Nr = EncRecipe(
+        'Nr', Unary, base_size=2, ins=GPR8, outs=GPR8,
+        emit='put_n_rr(bits, out_reg0, in_reg0, sink);')
+
+# 16-bit add with a 3-bit immediate: `adds rd, rn, #imm3`.
+Nri3 = EncRecipe(
+        'Nri3', BinaryImm, base_size=2, ins=GPR8, outs=GPR8,
+        instp=IsUnsignedInt(BinaryImm.imm, 3),
+        emit='put_n_imm5(bits, in_reg0, imm.into(), out_reg0, sink);')
+
+# 16-bit add with an 8-bit immediate and a tied destination: `adds rdn, #imm8`.
+Nri8 = EncRecipe(
+        'Nri8', BinaryImm, base_size=2, ins=GPR8, outs=0,
+        instp=IsUnsignedInt(BinaryImm.imm, 8),
+        emit='put_n_ri8(bits, in_reg0, imm.into(), sink);')
+
+# 16-bit shift by an immediate: `lsls rd, rm, #imm5`.
+Nshamt = EncRecipe(
+        'Nshamt', BinaryImm, base_size=2, ins=GPR8, outs=GPR8,
+        emit='''
+        let shamt = i64::from(imm) & 0x1f;
+        // The `lsrs` and `asrs` encodings interpret a 0 shift amount as 32,
+        // so shifts by 0 are encoded as `lsls`.
+        let bits = if shamt == 0 { 0x0000 } else { bits };
+        put_n_imm5(bits, in_reg0, shamt, out_reg0, sink);
+        ''')
+
+# 16-bit move of an 8-bit immediate: `movs rd, #imm8`.
+Niz = EncRecipe(
+        'Niz', UnaryImm, base_size=2, ins=(), outs=GPR8,
+        instp=IsUnsignedInt(UnaryImm.imm, 8),
+        emit='put_n_ri8(bits, out_reg0, imm.into(), sink);')
+
+# 16-bit register move which can access all registers and doesn't set the
+# flags: `mov rd, rm`.
+Ncopy = EncRecipe(
+        'Ncopy', Unary, base_size=2, ins=GPR, outs=GPR,
+        emit='put_n_mov(bits, in_reg0, out_reg0, sink);')
+
+# Same for a regmove.
+Nrmov = EncRecipe(
+        'Nrmov', RegMove, base_size=2, ins=GPR, outs=(),
+        emit='put_n_mov(bits, src, dst, sink);')
+
+# 16-bit load with a scaled 5-bit offset: `ldr rt, [rn, #imm]`.
+Nld = EncRecipe(
+        'Nld', Load, base_size=2, ins=GPR8, outs=GPR8,
+        instp=IsUnsignedInt(Load.offset, 7, 2),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        let offset: i32 = offset.into();
+        put_n_imm5(bits, in_reg0, (offset >> 2).into(), out_reg0, sink);
+        ''')
+
+# 16-bit store with a scaled 5-bit offset: `str rt, [rn, #imm]`.
+Nst = EncRecipe(
+        'Nst', Store, base_size=2, ins=(GPR8, GPR8), outs=(),
+        instp=IsUnsignedInt(Store.offset, 7, 2),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        let offset: i32 = offset.into();
+        put_n_imm5(bits, in_reg1, (offset >> 2).into(), in_reg0, sink);
+        ''')
+
+# Return is `bx lr`.
+# The variable return values are not encoded.
+Nret = EncRecipe(
+        'Nret', MultiAry, base_size=2, ins=(), outs=(),
+        emit='sink.put2(bits);')
+
+# Indirect call: `blx rm`.
+Ncalli = EncRecipe(
+        'Ncalli', CallIndirect, base_size=2, ins=GPR, outs=(),
+        emit='put_n_bx(bits, in_reg0, sink);')
+
+# 16-bit unconditional branch: `b.n label`.
+Njump = EncRecipe(
+        'Njump', Jump, base_size=2, ins=(), outs=(), branch_range=(4, 12),
+        emit='put_n_b(bits, func.offsets[destination], sink);')
+
+# 32-bit data processing with a register operand: `add.w rd, rn, rm`.
+# These encodings never set the flags.
+Wrrr = EncRecipe(
+        'Wrrr', Binary, base_size=4, ins=(GPR, GPR), outs=GPR,
+        emit='put_w_rrr(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# 32-bit unary data processing with the `rn` field fixed: `mvn.w rd, rm`.
+Wr = EncRecipe(
+        'Wr', Unary, base_size=4, ins=GPR, outs=GPR,
+        emit='put_w_rrr(bits, 0, in_reg0, out_reg0, sink);')
+
+# 32-bit multiply: `mul rd, rn, rm`.
+Wmul = EncRecipe(
+        'Wmul', Binary, base_size=4, ins=(GPR, GPR), outs=GPR,
+        emit='put_w_mul(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# 32-bit add with a plain 12-bit immediate: `addw rd, rn, #imm12`.
+Wri12 = EncRecipe(
+        'Wri12', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 12),
+        emit='put_w_ri12(bits, in_reg0, imm.into(), out_reg0, sink);')
+
+# 32-bit logical operation with a modified immediate. Only the immediates that
+# encode as themselves are supported: `and rd, rn, #imm8`.
+Wri8 = EncRecipe(
+        'Wri8', BinaryImm, base_size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(BinaryImm.imm, 8),
+        emit='put_w_ri12(bits, in_reg0, imm.into(), out_reg0, sink);')
+
+# 32-bit move of a 16-bit immediate: `movw rd, #imm16`.
+Wiz = EncRecipe(
+        'Wiz', UnaryImm, base_size=4, ins=(), outs=GPR,
+        instp=IsUnsignedInt(UnaryImm.imm, 16),
+        emit='put_w_movw(bits, imm.into(), out_reg0, sink);')
+
+# Any 32-bit constant: `movw rd, #lo16; movt rd, #hi16`.
+Wiz32 = EncRecipe(
+        'Wiz32', UnaryImm, base_size=8, ins=(), outs=GPR,
+        emit='''
+        let imm: i64 = imm.into();
+        put_w_movw(bits, imm & 0xffff, out_reg0, sink);
+        // The `movt` encoding differs from `movw` in bit 7.
+        put_w_movw(bits | 0x0080, (imm >> 16) & 0xffff, out_reg0, sink);
+        ''')
+
+# 32-bit load with an unsigned 12-bit offset: `ldr.w rt, [rn, #imm12]`.
+Wld = EncRecipe(
+        'Wld', Load, base_size=4, ins=GPR, outs=GPR,
+        instp=IsUnsignedInt(Load.offset, 12),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        let offset: i32 = offset.into();
+        put_w_ldst(bits, in_reg0, offset.into(), out_reg0, sink);
+        ''')
+
+# 32-bit store with an unsigned 12-bit offset: `str.w rt, [rn, #imm12]`.
+Wst = EncRecipe(
+        'Wst', Store, base_size=4, ins=(GPR, GPR), outs=(),
+        instp=IsUnsignedInt(Store.offset, 12),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        let offset: i32 = offset.into();
+        put_w_ldst(bits, in_reg1, offset.into(), in_reg0, sink);
+        ''')
+
+# 32-bit unconditional branch: `b.w label`.
+Wjump = EncRecipe(
+        'Wjump', Jump, base_size=4, ins=(), outs=(), branch_range=(4, 25),
+        emit='put_w_b(bits, func.offsets[destination], sink);')
+
+# Direct call: `bl func`.
+Wcall = EncRecipe(
+        'Wcall', Call, base_size=4, ins=(), outs=(),
+        emit='''
+        // The `bl` displacement is relative to the instruction address + 4.
+        sink.reloc_external(Reloc::Arm32Call,
+                            &func.dfg.ext_funcs[func_ref].name,
+                            -4);
+        put_w_bl(bits, sink);
+        ''')
+
+# Integer comparison producing a 0/1 result without an IT block:
+#
+#     cmp.w   rn, rm
+#     mov.w   rd, #1
+#     b<cond> 1f
+#     mov.w   rd, #0
+# 1:
+#
+# The `mov.w` instructions don't touch the flags.
+Wicmp = EncRecipe(
+        'Wicmp', IntCompare, base_size=14, ins=(GPR, GPR), outs=GPR,
+        emit='''
+        let cond = icc2cond(cond);
+        put_w_cmp(in_reg0, in_reg1, sink);
+        put_w_ri12(0xf04f, 0, 1, out_reg0, sink);
+        put_n_bcond(cond, sink.offset() + 6, sink);
+        put_w_ri12(0xf04f, 0, 0, out_reg0, sink);
+        ''')
+
+# Select without an IT block. The result is tied to the false operand:
+#
+#     cmp.w rc, #0
+#     beq   1f
+#     mov   rd, rt
+# 1:
+Wsel = EncRecipe(
+        'Wsel', Ternary, base_size=8, ins=(GPR, GPR, GPR), outs=2,
+        emit='''
+        put_w_cmp0(in_reg0, sink);
+        put_n_bcond(0b0000, sink.offset() + 4, sink);
+        put_n_mov(0x4600, in_reg1, in_reg2, sink);
+        ''')
+
+# `brz` and `brnz` are implemented as a comparison with 0 followed by a
+# conditional branch. The recipes come in four sizes with increasing range.
+#
+# 4 bytes: `cmp rn, #0; b<cond>.n label`.
+Bz4 = EncRecipe(
+        'Bz4', Branch, base_size=4, ins=GPR8, outs=(), branch_range=(6, 9),
+        emit='''
+        put_n_ri8(0x2800, in_reg0, 0, sink);
+        put_n_bcond(bits, func.offsets[destination], sink);
+        ''')
+
+# 6 bytes: `cmp.w rn, #0; b<cond>.n label`.
+Bz6 = EncRecipe(
+        'Bz6', Branch, base_size=6, ins=GPR, outs=(), branch_range=(8, 9),
+        emit='''
+        put_w_cmp0(in_reg0, sink);
+        put_n_bcond(bits, func.offsets[destination], sink);
+        ''')
+
+# 8 bytes: `cmp.w rn, #0; b<cond>.w label`.
+Bz8 = EncRecipe(
+        'Bz8', Branch, base_size=8, ins=GPR, outs=(), branch_range=(8, 21),
+        emit='''
+        put_w_cmp0(in_reg0, sink);
+        put_w_bcond(bits, func.offsets[destination], sink);
+        ''')
+
+# 10 bytes: `cmp.w rn, #0; b<!cond>.n 1f; b.w label; 1:`.
+Bz10 = EncRecipe(
+        'Bz10', Branch, base_size=10, ins=GPR, outs=(),
+        branch_range=(10, 25),
+        emit='''
+        put_w_cmp0(in_reg0, sink);
+        put_n_bcond(bits ^ 1, sink.offset() + 6, sink);
+        put_w_b(0xf000, func.offsets[destination], sink);
+        ''')
+
+# `br_icmp` is a register comparison followed by a conditional branch. It
+# comes in the same four sizes as `brz`.
+#
+# 4 bytes: `cmp rn, rm; b<cond>.n label`.
+Bicmp4 = EncRecipe(
+        'Bicmp4', BranchIcmp, base_size=4, ins=(GPR8, GPR8), outs=(),
+        branch_range=(6, 9),
+        emit='''
+        put_n_rr(0x4280, in_reg0, in_reg1, sink);
+        put_n_bcond(icc2cond(cond), func.offsets[destination], sink);
+        ''')
+
+# 6 bytes: `cmp.w rn, rm; b<cond>.n label`.
+Bicmp6 = EncRecipe(
+        'Bicmp6', BranchIcmp, base_size=6, ins=(GPR, GPR), outs=(),
+        branch_range=(8, 9),
+        emit='''
+        put_w_cmp(in_reg0, in_reg1, sink);
+        put_n_bcond(icc2cond(cond), func.offsets[destination], sink);
+        ''')
+
+# 8 bytes: `cmp.w rn, rm; b<cond>.w label`.
+Bicmp8 = EncRecipe(
+        'Bicmp8', BranchIcmp, base_size=8, ins=(GPR, GPR), outs=(),
+        branch_range=(8, 21),
+        emit='''
+        put_w_cmp(in_reg0, in_reg1, sink);
+        put_w_bcond(icc2cond(cond), func.offsets[destination], sink);
+        ''')
+
+# 10 bytes: `cmp.w rn, rm; b<!cond>.n 1f; b.w label; 1:`.
+Bicmp10 = EncRecipe(
+        'Bicmp10', BranchIcmp, base_size=10, ins=(GPR, GPR), outs=(),
+        branch_range=(10, 25),
+        emit='''
+        put_w_cmp(in_reg0, in_reg1, sink);
+        put_n_bcond(icc2cond(cond) ^ 1, sink.offset() + 6, sink);
+        put_w_b(0xf000, func.offsets[destination], sink);
+        ''')
@@ -37,6 +37,8 @@ FlagRegs = RegBank(
         names=['nzcv'])
 
 GPR = RegClass(IntRegs)
+# The low registers accessible from most 16-bit Thumb instructions.
+GPR8 = GPR[0:8]
 S = RegClass(FloatRegs, count=32)
 D = RegClass(FloatRegs, width=2)
 Q = RegClass(FloatRegs, width=4)
@@ -2,10 +2,14 @@
 ARM32 settings.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup
+from cdsl.settings import SettingGroup, BoolSetting
 import base.settings as shared
 from .defs import ISA
 
 ISA.settings = SettingGroup('arm32', parent=shared.group)
 
+thumb2 = BoolSetting(
+        "Generate Thumb-2 (T32) code instead of A32 code. Thumb-only targets "
+        "like Cortex-M always generate T32 code.")
+
 ISA.settings.close(globals())
@@ -2,10 +2,13 @@
 ARM64 settings.
 """
 from __future__ import absolute_import
-from cdsl.settings import SettingGroup
+from cdsl.settings import SettingGroup, BoolSetting
 import base.settings as shared
 from .defs import ISA
 
 ISA.settings = SettingGroup('arm64', parent=shared.group)
 
+# Extension availability, as probed by cranelift-native.
+has_lse = BoolSetting("LSE: Large System Extensions atomics (ARMv8.1)")
+
 ISA.settings.close(globals())
@@ -3,28 +3,40 @@ RISC-V Encodings.
 """
 from __future__ import absolute_import
 from base import instructions as base
-from base.immediates import intcc
+from base import types
+from base.immediates import intcc, floatcc, atomic_rmw_op
 from .defs import RV32, RV64
+from . import instructions as riscv
 from .recipes import OPIMM, OPIMM32, OP, OP32, LUI, BRANCH, JALR, JAL
-from .recipes import LOAD, STORE
+from .recipes import LOAD, STORE, MISC_MEM, AMO
 from .recipes import R, Rshamt, Ricmp, Ii, Iz, Iicmp, Iret, Icall, Icopy
-from .recipes import U, UJ, UJcall, SB, SBzero, GPsp, GPfi, Irmov
-from .settings import use_m
+from .recipes import U, UJ, UJcall, SB, SBzero, GPsp, GPfi, Irmov, Ramo
+from .recipes import Ifence, Iald, Sast, Rcas
+from .recipes import LOAD_FP, STORE_FP, FMADD, OPFP
+from .recipes import Rf, Rfu, Rfi2f, Rff2i, Rfsgn, Rfrmov, Rfcmp, Rfcmpr
+from .recipes import R4f, Ifld, Sfst, FPsp, FPfi, null
+from .recipes import CR, CI, CA, CB, CJ
+from .recipes import CRr, CRcopy, CRret, CRcall, CAr, CIi, CIshamt, CIz
+from .recipes import CBi, CBshamt, CBzero, CJump
+from .settings import use_m, use_a, use_f, use_d, soft_f, soft_d
+from .settings import supports_c
 from cdsl.ast import Var
-from base.legalize import narrow, expand
+from base.legalize import narrow, narrow_wide, expand
+from .legalize import riscv_expand
 
 RV32.legalize_monomorphic(expand)
 RV32.legalize_type(
-        default=narrow,
-        i32=expand,
+        default=narrow_wide,
+        i32=riscv_expand,
+        i64=narrow,
         f32=expand,
         f64=expand)
 
 RV64.legalize_monomorphic(expand)
 RV64.legalize_type(
-        default=narrow,
+        default=narrow_wide,
         i32=expand,
-        i64=expand,
+        i64=riscv_expand,
         f32=expand,
         f64=expand)
 
@@ -33,6 +45,7 @@ x = Var('x')
 y = Var('y')
 dest = Var('dest')
 args = Var('args')
+flags = Var('flags')
 
 # Basic arithmetic binary instructions are encoded in an R-type instruction.
 for inst,           inst_imm,      f3,    f7 in [
@@ -102,6 +115,48 @@ RV32.enc(base.imul.i32, R, OP(0b000, 0b0000001), isap=use_m)
 RV64.enc(base.imul.i64, R, OP(0b000, 0b0000001), isap=use_m)
 RV64.enc(base.imul.i32, R, OP32(0b000, 0b0000001), isap=use_m)
 
+# Memory ordering.
+RV32.enc(base.fence, Ifence, MISC_MEM(0b000))
+RV64.enc(base.fence, Ifence, MISC_MEM(0b000))
+
+# "A" Standard Extension for Atomic Instructions.
+# Gated by the `use_a` flag. Subtraction is expanded into an addition of the
+# negated operand. The operation names `and` and `or` are Python keywords, so
+# they are looked up with `getattr`.
+for op_name,  f5 in [
+        ('add',  0b00000),
+        ('xchg', 0b00001),
+        ('xor',  0b00100),
+        ('and',  0b01100),
+        ('or',   0b01000)
+        ]:
+    op = getattr(atomic_rmw_op, op_name)
+    RV32.enc(
+            base.atomic_rmw.i32.i32(flags, op, x, y), Ramo, AMO(0b010, f5),
+            isap=use_a)
+    RV64.enc(
+            base.atomic_rmw.i32.i64(flags, op, x, y), Ramo, AMO(0b010, f5),
+            isap=use_a)
+    RV64.enc(
+            base.atomic_rmw.i64.i64(flags, op, x, y), Ramo, AMO(0b011, f5),
+            isap=use_a)
+
+# Atomic loads and stores are plain accesses between fences, and
+# compare-and-swap is an LR/SC loop. The LR/SC loop compares the full register,
+# so it is only used for machine words.
+RV32.enc(base.atomic_load.i32.i32, Iald, LOAD(0b010))
+RV64.enc(base.atomic_load.i32.i64, Iald, LOAD(0b010))
+RV64.enc(base.atomic_load.i64.i64, Iald, LOAD(0b011))
+RV32.enc(base.atomic_store.i32.i32, Sast, STORE(0b010))
+RV64.enc(base.atomic_store.i32.i64, Sast, STORE(0b010))
+RV64.enc(base.atomic_store.i64.i64, Sast, STORE(0b011))
+RV32.enc(riscv.cas.i32, Rcas, AMO(0b010, 0b00010), isap=use_a)
+RV64.enc(riscv.cas.i64, Rcas, AMO(0b011, 0b00010), isap=use_a)
+
+# Without the "A" extension, compare-and-swap is reported as unsupported.
+RV32.requires_feature(riscv.cas.i32)
+RV64.requires_feature(riscv.cas.i64)
+
 # Control flow.
 
 # Unconditional branches.
@@ -160,3 +215,204 @@ RV32.enc(base.copy.b1, Icopy, OPIMM(0b000))
 RV64.enc(base.copy.b1, Icopy, OPIMM(0b000))
 RV32.enc(base.regmove.b1, Irmov, OPIMM(0b000))
 RV64.enc(base.regmove.b1, Irmov, OPIMM(0b000))
+
+# "F" and "D" Standard Extensions for Single and Double-Precision Floating
+# Point. Gated by the `use_f` and `use_d` flags.
+for ty, fmt, ld, isap in [
+        (types.f32, 0b00, 0b010, use_f),
+        (types.f64, 0b01, 0b011, use_d)
+        ]:
+    for inst,           f5 in [
+            (base.fadd, 0b00000),
+            (base.fsub, 0b00001),
+            (base.fmul, 0b00010),
+            (base.fdiv, 0b00011)
+            ]:
+        RV32.enc(inst.bind(ty), Rf, OPFP(f5, fmt, 0b111), isap=isap)
+        RV64.enc(inst.bind(ty), Rf, OPFP(f5, fmt, 0b111), isap=isap)
+
+    RV32.enc(base.sqrt.bind(ty), Rfu, OPFP(0b01011, fmt, 0b111), isap=isap)
+    RV64.enc(base.sqrt.bind(ty), Rfu, OPFP(0b01011, fmt, 0b111), isap=isap)
+    RV32.enc(base.fma.bind(ty), R4f, FMADD(fmt), isap=isap)
+    RV64.enc(base.fma.bind(ty), R4f, FMADD(fmt), isap=isap)
+
+    # Sign injection.
+    RV32.enc(base.fcopysign.bind(ty), Rf, OPFP(0b00100, fmt, 0b000),
+             isap=isap)
+    RV64.enc(base.fcopysign.bind(ty), Rf, OPFP(0b00100, fmt, 0b000),
+             isap=isap)
+    RV32.enc(base.fneg.bind(ty), Rfsgn, OPFP(0b00100, fmt, 0b001), isap=isap)
+    RV64.enc(base.fneg.bind(ty), Rfsgn, OPFP(0b00100, fmt, 0b001), isap=isap)
+    RV32.enc(base.fabs.bind(ty), Rfsgn, OPFP(0b00100, fmt, 0b010), isap=isap)
+    RV64.enc(base.fabs.bind(ty), Rfsgn, OPFP(0b00100, fmt, 0b010), isap=isap)
+
+    # Comparisons. The `gt` and `ge` conditions swap the operands of `flt`
+    # and `fle`.
+    for cond,           recipe, f3 in [
+            (floatcc.eq, Rfcmp,  0b010),
+            (floatcc.lt, Rfcmp,  0b001),
+            (floatcc.le, Rfcmp,  0b000),
+            (floatcc.gt, Rfcmpr, 0b001),
+            (floatcc.ge, Rfcmpr, 0b000)
+            ]:
+        RV32.enc(
+                base.fcmp.bind(ty)(cond, x, y), recipe,
+                OPFP(0b10100, fmt, f3), isap=isap)
+        RV64.enc(
+                base.fcmp.bind(ty)(cond, x, y), recipe,
+                OPFP(0b10100, fmt, f3), isap=isap)
+
+    # Loads and stores.
+    RV32.enc(base.load.bind(ty).any, Ifld, LOAD_FP(ld), isap=isap)
+    RV64.enc(base.load.bind(ty).any, Ifld, LOAD_FP(ld), isap=isap)
+    RV32.enc(base.store.bind(ty).any, Sfst, STORE_FP(ld), isap=isap)
+    RV64.enc(base.store.bind(ty).any, Sfst, STORE_FP(ld), isap=isap)
+
+    # Spill, fill, and register copies.
+    RV32.enc(base.spill.bind(ty), FPsp, STORE_FP(ld), isap=isap)
+    RV64.enc(base.spill.bind(ty), FPsp, STORE_FP(ld), isap=isap)
+    RV32.enc(base.fill.bind(ty), FPfi, LOAD_FP(ld), isap=isap)
+    RV64.enc(base.fill.bind(ty), FPfi, LOAD_FP(ld), isap=isap)
+    RV32.enc(base.copy.bind(ty), Rfsgn, OPFP(0b00100, fmt, 0b000), isap=isap)
+    RV64.enc(base.copy.bind(ty), Rfsgn, OPFP(0b00100, fmt, 0b000), isap=isap)
+    RV32.enc(base.regmove.bind(ty), Rfrmov, OPFP(0b00100, fmt, 0b000),
+             isap=isap)
+    RV64.enc(base.regmove.bind(ty), Rfrmov, OPFP(0b00100, fmt, 0b000),
+             isap=isap)
+
+# Conversions between f32 and f64.
+RV32.enc(base.fpromote.f64.f32, Rfu, OPFP(0b01000, 0b01, 0b000, 0),
+         isap=use_d)
+RV64.enc(base.fpromote.f64.f32, Rfu, OPFP(0b01000, 0b01, 0b000, 0),
+         isap=use_d)
+RV32.enc(base.fdemote.f32.f64, Rfu, OPFP(0b01000, 0b00, 0b111, 1),
+         isap=use_d)
+RV64.enc(base.fdemote.f32.f64, Rfu, OPFP(0b01000, 0b00, 0b111, 1),
+         isap=use_d)
+
+# Integer to float conversions. The rs2 field selects the integer type: 0 for
+# signed 32-bit, 1 for unsigned 32-bit, 2 and 3 for 64-bit integers in RV64.
+# Conversions from i32 to f64 are exact and don't need a rounding mode.
+for ty, fmt, f3, isap in [
+        (types.f32, 0b00, 0b111, use_f),
+        (types.f64, 0b01, 0b000, use_d)
+        ]:
+    for inst,                     rs2 in [
+            (base.fcvt_from_sint, 0),
+            (base.fcvt_from_uint, 1)
+            ]:
+        RV32.enc(inst.bind(ty).i32, Rfi2f, OPFP(0b11010, fmt, f3, rs2),
+                 isap=isap)
+        RV64.enc(inst.bind(ty).i32, Rfi2f, OPFP(0b11010, fmt, f3, rs2),
+                 isap=isap)
+        RV64.enc(inst.bind(ty).i64, Rfi2f, OPFP(0b11010, fmt, 0b111, rs2 + 2),
+                 isap=isap)
+
+# Moves between integer and float registers.
+RV32.enc(base.bitcast.i32.f32, Rff2i, OPFP(0b11100, 0b00, 0b000), isap=use_f)
+RV64.enc(base.bitcast.i32.f32, Rff2i, OPFP(0b11100, 0b00, 0b000), isap=use_f)
+RV32.enc(base.bitcast.f32.i32, Rfi2f, OPFP(0b11110, 0b00, 0b000), isap=use_f)
+RV64.enc(base.bitcast.f32.i32, Rfi2f, OPFP(0b11110, 0b00, 0b000), isap=use_f)
+RV64.enc(base.bitcast.i64.f64, Rff2i, OPFP(0b11100, 0b01, 0b000), isap=use_d)
+RV64.enc(base.bitcast.f64.i64, Rfi2f, OPFP(0b11110, 0b01, 0b000), isap=use_d)
+
+# Without the "F" and "D" extensions, floats are kept in integer registers.
+# Bit-casts are free, and arithmetic is legalized into library calls. This is
+# not supported for f64 in RV32 where it would need a register pair.
+RV32.enc(base.bitcast.i32.f32, null, 0, isap=soft_f)
+RV64.enc(base.bitcast.i32.f32, null, 0, isap=soft_f)
+RV32.enc(base.bitcast.f32.i32, null, 0, isap=soft_f)
+RV64.enc(base.bitcast.f32.i32, null, 0, isap=soft_f)
+RV64.enc(base.bitcast.i64.f64, null, 0, isap=soft_d)
+RV64.enc(base.bitcast.f64.i64, null, 0, isap=soft_d)
+
+RV32.enc(base.spill.f32, GPsp, STORE(0b010), isap=soft_f)
+RV64.enc(base.spill.f32, GPsp, STORE(0b010), isap=soft_f)
+RV64.enc(base.spill.f64, GPsp, STORE(0b011), isap=soft_d)
+RV32.enc(base.fill.f32, GPfi, LOAD(0b010), isap=soft_f)
+RV64.enc(base.fill.f32, GPfi, LOAD(0b010), isap=soft_f)
+RV64.enc(base.fill.f64, GPfi, LOAD(0b011), isap=soft_d)
+RV32.enc(base.copy.f32, Icopy, OPIMM(0b000), isap=soft_f)
+RV64.enc(base.copy.f32, Icopy, OPIMM(0b000), isap=soft_f)
+RV64.enc(base.copy.f64, Icopy, OPIMM(0b000), isap=soft_d)
+RV32.enc(base.regmove.f32, Irmov, OPIMM(0b000), isap=soft_f)
+RV64.enc(base.regmove.f32, Irmov, OPIMM(0b000), isap=soft_f)
+RV64.enc(base.regmove.f64, Irmov, OPIMM(0b000), isap=soft_d)
+
+# The other float instructions have no soft-float legalization, so they are
+# reported as unsupported without the "F" and "D" extensions.
+for ty in [types.f32, types.f64]:
+    for cpumode in [RV32, RV64]:
+        cpumode.requires_feature(
+                base.fcmp.bind(ty), base.load.bind(ty), base.store.bind(ty),
+                base.fcvt_from_sint.bind(ty), base.fcvt_from_uint.bind(ty))
+for cpumode in [RV32, RV64]:
+    cpumode.requires_feature(base.fpromote.f64, base.fdemote.f32)
+RV32.requires_feature(
+        base.spill.f64, base.fill.f64, base.copy.f64, base.regmove.f64)
+
+# "C" Standard Extension for Compressed Instructions.
+# Gated by the `supports_c` flag.
+#
+# The compressed encodings are listed after the 32-bit encodings above, so
+# they are never chosen before register allocation. The `shrink_instructions`
+# pass picks them when the operands fit, and branch relaxation switches back
+# to the 32-bit forms when a compressed branch is out of range.
+RV32.enc(base.iadd.i32, CRr, CR(0b1001, 0b10), isap=supports_c)
+RV64.enc(base.iadd.i64, CRr, CR(0b1001, 0b10), isap=supports_c)
+
+for inst,           f6,       f2 in [
+        (base.isub, 0b100011, 0b00),
+        (base.bxor, 0b100011, 0b01),
+        (base.bor,  0b100011, 0b10),
+        (base.band, 0b100011, 0b11)
+        ]:
+    RV32.enc(inst.i32, CAr, CA(f6, f2, 0b01), isap=supports_c)
+    RV64.enc(inst.i64, CAr, CA(f6, f2, 0b01), isap=supports_c)
+
+# 32-bit ops in RV64.
+RV64.enc(base.isub.i32, CAr, CA(0b100111, 0b00, 0b01), isap=supports_c)
+RV64.enc(base.iadd.i32, CAr, CA(0b100111, 0b01, 0b01), isap=supports_c)
+RV64.enc(base.iadd_imm.i32, CIi, CI(0b001, 0b01), isap=supports_c)
+
+RV32.enc(base.iadd_imm.i32, CIi, CI(0b000, 0b01), isap=supports_c)
+RV64.enc(base.iadd_imm.i64, CIi, CI(0b000, 0b01), isap=supports_c)
+RV32.enc(base.band_imm.i32, CBi, CB(0b100, 0b01, 0b10), isap=supports_c)
+RV64.enc(base.band_imm.i64, CBi, CB(0b100, 0b01, 0b10), isap=supports_c)
+
+RV32.enc(base.ishl_imm.i32, CIshamt, CI(0b000, 0b10), isap=supports_c)
+RV64.enc(base.ishl_imm.i64, CIshamt, CI(0b000, 0b10), isap=supports_c)
+for inst,               f2 in [
+        (base.ushr_imm, 0b00),
+        (base.sshr_imm, 0b01)
+        ]:
+    RV32.enc(inst.i32, CBshamt, CB(0b100, 0b01, f2), isap=supports_c)
+    RV64.enc(inst.i64, CBshamt, CB(0b100, 0b01, f2), isap=supports_c)
+
+RV32.enc(base.iconst.i32, CIz, CI(0b010, 0b01), isap=supports_c)
+RV64.enc(base.iconst.i32, CIz, CI(0b010, 0b01), isap=supports_c)
+RV64.enc(base.iconst.i64, CIz, CI(0b010, 0b01), isap=supports_c)
+
+# There is no compressed equivalent of `addiw x, 0` for copying an i32 in
+# RV64.
+RV32.enc(base.copy.i32, CRcopy, CR(0b1000, 0b10), isap=supports_c)
+RV64.enc(base.copy.i64, CRcopy, CR(0b1000, 0b10), isap=supports_c)
+RV32.enc(base.copy.b1, CRcopy, CR(0b1000, 0b10), isap=supports_c)
+RV64.enc(base.copy.b1, CRcopy, CR(0b1000, 0b10), isap=supports_c)
+
+RV32.enc(base.jump, CJump, CJ(0b101, 0b01), isap=supports_c)
+RV64.enc(base.jump, CJump, CJ(0b101, 0b01), isap=supports_c)
+
+for inst,           f3 in [
+        (base.brz,  0b110),
+        (base.brnz, 0b111)
+        ]:
+    RV32.enc(inst.i32, CBzero, CB(f3, 0b01), isap=supports_c)
+    RV64.enc(inst.i64, CBzero, CB(f3, 0b01), isap=supports_c)
+    RV32.enc(inst.b1, CBzero, CB(f3, 0b01), isap=supports_c)
+    RV64.enc(inst.b1, CBzero, CB(f3, 0b01), isap=supports_c)
+
+RV32.enc(base.x_return, CRret, CR(0b1000, 0b10), isap=supports_c)
+RV64.enc(base.x_return, CRret, CR(0b1000, 0b10), isap=supports_c)
+RV32.enc(base.call_indirect.i32, CRcall, CR(0b1001, 0b10), isap=supports_c)
+RV64.enc(base.call_indirect.i64, CRcall, CR(0b1001, 0b10), isap=supports_c)
@@ -0,0 +1,39 @@
+"""
+Supplementary instruction definitions for RISC-V.
+
+This module defines additional instructions that are useful only to the RISC-V
+target ISA.
+"""
+
+from base.immediates import memflags
+from cdsl.operands import Operand
+from cdsl.typevar import TypeVar
+from cdsl.instructions import Instruction, InstructionGroup
+
+
+GROUP = InstructionGroup("riscv", "RISC-V-specific instruction set")
+
+iWord = TypeVar('iWord', 'A scalar integer machine word', ints=(32, 64))
+
+MemFlags = Operand('MemFlags', memflags)
+p = Operand('p', iWord, doc='Address')
+e = Operand('e', iWord, doc='Expected value')
+x = Operand('x', iWord, doc='Value to be stored')
+a = Operand('a', iWord, doc='Value loaded')
+p2 = Operand('p2', iWord, doc='Copy of `p`')
+e2 = Operand('e2', iWord, doc='Copy of `e`')
+x2 = Operand('x2', iWord, doc='Copy of `x`')
+
+cas = Instruction(
+        'riscv_cas', r"""
+        Atomically compare and swap memory at ``p`` with an LR/SC loop.
+
+        This is :inst:`atomic_cas` of a machine word, with the operands
+        returned unchanged as ``p2``, ``e2``, and ``x2``. The loop reads the
+        operands after ``a`` is written, so tying them to outputs keeps them
+        out of the register of ``a``.
+        """,
+        ins=(MemFlags, p, e, x), outs=(a, p2, e2, x2),
+        can_load=True, can_store=True, other_side_effects=True)
+
+GROUP.close()
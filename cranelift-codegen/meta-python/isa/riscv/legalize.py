@@ -0,0 +1,38 @@
+"""
+Custom legalization patterns for RISC-V.
+"""
+from __future__ import absolute_import
+from cdsl.ast import Var
+from cdsl.xform import Rtl, XFormGroup
+from base import legalize as shared
+from base import types
+from base import instructions as insts
+from . import instructions as riscv
+from .defs import ISA
+
+riscv_expand = XFormGroup(
+        'riscv_expand',
+        """
+        Legalize instructions by expansion.
+
+        Use RISC-V-specific instructions if needed.
+        """,
+        isa=ISA, chain=shared.expand)
+
+a = Var('a')
+flags = Var('flags')
+p = Var('p')
+e = Var('e')
+x = Var('x')
+p2 = Var('p2')
+e2 = Var('e2')
+x2 = Var('x2')
+
+# Compare-and-swap is an LR/SC loop which reads its operands after writing the
+# result. The address must have the width of the machine word.
+for ty in [types.i32, types.i64]:
+    riscv_expand.legalize(
+            a << insts.atomic_cas.bind(ty).bind(ty)(flags, p, e, x),
+            Rtl(
+                (a, p2, e2, x2) << riscv.cas(flags, p, e, x)
+            ))
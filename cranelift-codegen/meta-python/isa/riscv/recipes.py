@@ -10,12 +10,14 @@ instruction formats described in the reference:
 """
 from __future__ import absolute_import
 from cdsl.isa import EncRecipe
-from cdsl.predicates import IsSignedInt
+from cdsl.predicates import IsSignedInt, IsUnsignedInt
 from cdsl.registers import Stack
 from base.formats import Binary, BinaryImm, MultiAry, IntCompare, IntCompareImm
 from base.formats import Unary, UnaryImm, BranchIcmp, Branch, Jump
-from base.formats import Call, CallIndirect, RegMove
-from .registers import GPR
+from base.formats import Call, CallIndirect, RegMove, AtomicRmw, NullAry
+from base.formats import LoadNoOffset, StoreNoOffset, AtomicCas
+from base.formats import FloatCompare, Ternary, Load, Store
+from .registers import GPR, GPRC, FPR
 
 # The low 7 bits of a RISC-V instruction is the base opcode. All 32-bit
 # instructions have 11 as the two low bits, with bits 6:2 determining the base
@@ -37,6 +39,12 @@ def STORE(funct3):
     return 0b01000 | (funct3 << 5)
 
 
+def MISC_MEM(funct3):
+    # type: (int) -> int
+    assert funct3 <= 0b111
+    return 0b00011 | (funct3 << 5)
+
+
 def BRANCH(funct3):
     # type: (int) -> int
     assert funct3 <= 0b111
@@ -80,6 +88,17 @@ def OP32(funct3, funct7):
     return 0b01110 | (funct3 << 5) | (funct7 << 8)
 
 
+def AMO(funct3, funct5):
+    # type: (int, int) -> int
+    """
+    Atomic memory operations are always sequentially consistent, so both the
+    `aq` and `rl` bits are set in funct7.
+    """
+    assert funct3 <= 0b111
+    assert funct5 <= 0b11111
+    return 0b01011 | (funct3 << 5) | (((funct5 << 2) | 0b11) << 8)
+
+
 def AIUPC():
     # type: () -> int
     return 0b00101
@@ -90,6 +109,87 @@ def LUI():
     return 0b01101
 
 
+def LOAD_FP(funct3):
+    # type: (int) -> int
+    assert funct3 <= 0b111
+    return 0b00001 | (funct3 << 5)
+
+
+def STORE_FP(funct3):
+    # type: (int) -> int
+    assert funct3 <= 0b111
+    return 0b01001 | (funct3 << 5)
+
+
+def FMADD(fmt, funct3=0b111):
+    # type: (int, int) -> int
+    assert fmt <= 0b11
+    assert funct3 <= 0b111
+    return 0b10000 | (funct3 << 5) | (fmt << 8)
+
+
+def OPFP(funct5, fmt, funct3, rs2=0):
+    # type: (int, int, int, int) -> int
+    """
+    All the OP-FP instructions share the same opcode, so it is not part of the
+    encbits. Instead, unary instructions that select a variant with the rs2
+    field encode it here.
+
+    Encbits: `funct3 | (funct7 << 3) | (rs2 << 10)` where `funct7` is
+    `(funct5 << 2) | fmt`.
+    """
+    assert funct5 <= 0b11111
+    assert fmt <= 0b11
+    assert funct3 <= 0b111
+    assert rs2 <= 0b11111
+    return funct3 | (((funct5 << 2) | fmt) << 3) | (rs2 << 10)
+
+
+# The 16-bit compressed instructions from the 'C' extension have one of 00,
+# 01, or 10 in the two low bits. The remaining fields vary by format.
+#
+# Encbits for the 16-bit recipes are the complete instruction with the
+# register and immediate fields cleared. The functions below encode the
+# encbits.
+
+
+def CR(funct4, op):
+    # type: (int, int) -> int
+    assert funct4 <= 0b1111
+    assert op <= 0b10
+    return (funct4 << 12) | op
+
+
+def CI(funct3, op):
+    # type: (int, int) -> int
+    assert funct3 <= 0b111
+    assert op <= 0b10
+    return (funct3 << 13) | op
+
+
+def CA(funct6, funct2, op):
+    # type: (int, int, int) -> int
+    assert funct6 <= 0b111111
+    assert funct2 <= 0b11
+    assert op <= 0b10
+    return (funct6 << 10) | (funct2 << 5) | op
+
+
+def CB(funct3, op, funct2=0):
+    # type: (int, int, int) -> int
+    assert funct3 <= 0b111
+    assert funct2 <= 0b11
+    assert op <= 0b10
+    return (funct3 << 13) | (funct2 << 10) | op
+
+
+def CJ(funct3, op):
+    # type: (int, int) -> int
+    assert funct3 <= 0b111
+    assert op <= 0b10
+    return (funct3 << 13) | op
+
+
 # R-type 32-bit instructions: These are mostly binary arithmetic instructions.
 # The encbits are `opcode[6:2] | (funct3 << 5) | (funct7 << 8)
 R = EncRecipe(
@@ -106,6 +206,62 @@ Ricmp = EncRecipe(
         'Ricmp', IntCompare, base_size=4, ins=(GPR, GPR), outs=GPR,
         emit='put_r(bits, in_reg0, in_reg1, out_reg0, sink);')
 
+# R-type encoding of an atomic memory operation. The address is in rs1, the
+# operand in rs2, and rd receives the old memory value.
+Ramo = EncRecipe(
+        'Ramo', AtomicRmw, base_size=4, ins=(GPR, GPR), outs=GPR,
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        put_r(bits, in_reg0, in_reg1, out_reg0, sink);
+        ''')
+
+# I-type atomic load with no offset, between fences. The leading `FENCE rw, rw`
+# orders it after earlier accesses, and the trailing `FENCE r, rw` orders later
+# accesses after it.
+Iald = EncRecipe(
+        'Iald', LoadNoOffset, base_size=12, ins=GPR, outs=GPR,
+        emit='''
+        put_fence(0b0011, 0b0011, sink);
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        put_i(bits, in_reg0, 0, out_reg0, sink);
+        put_fence(0b0010, 0b0011, sink);
+        ''')
+
+# S-type atomic store with no offset, after a `FENCE rw, w`. Atomic loads are
+# preceded by a full fence, so no fence is needed after the store.
+Sast = EncRecipe(
+        'Sast', StoreNoOffset, base_size=8, ins=(GPR, GPR), outs=(),
+        emit='''
+        put_fence(0b0011, 0b0001, sink);
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        put_s(bits, 0, in_reg1, in_reg0, sink);
+        ''')
+
+# Compare-and-swap as an LR/SC loop. The encoding bits are for the LR
+# instruction. The inputs are tied to outputs so the loaded value in rd
+# doesn't clobber any of them.
+Rcas = EncRecipe(
+        'Rcas', AtomicCas, base_size=20, ins=(GPR, GPR, GPR),
+        outs=(GPR, 0, 1, 2),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        put_lr_sc_cas(bits, in_reg0, in_reg1, in_reg2, out_reg0, sink);
+        ''')
+
+# I-type encoding of a full `FENCE rw, rw`. The predecessor and successor sets
+# are both in the immediate field, and rs1 and rd are unused.
+Ifence = EncRecipe(
+        'Ifence', NullAry, base_size=4, ins=(), outs=(),
+        emit='put_i(bits, 0, 0b0011_0011, 0, sink);')
+
 Ii = EncRecipe(
         'Ii', BinaryImm, base_size=4, ins=GPR, outs=GPR,
         instp=IsSignedInt(BinaryImm.imm, 12),
@@ -221,5 +377,186 @@ GPsp = EncRecipe(
 # Fill of a GPR.
 GPfi = EncRecipe(
         'GPfi', Unary, base_size=4,
-        ins=Stack(GPR), outs=GPR,
+        ins=Stack(GPR), outs=GPR, latency=3,
         emit='unimplemented!();')
+
+# OP-FP instructions from the 'F' and 'D' extensions. The encbits are
+# computed by `OPFP()` above.
+Rf = EncRecipe(
+        'Rf', Binary, base_size=4, ins=(FPR, FPR), outs=FPR,
+        emit='put_rfp(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# Unary OP-FP instruction with the rs2 field taken from the encbits.
+Rfu = EncRecipe(
+        'Rfu', Unary, base_size=4, ins=FPR, outs=FPR,
+        emit='put_rfp(bits, in_reg0, 0, out_reg0, sink);')
+
+# Unary OP-FP instruction converting an integer register to a float.
+Rfi2f = EncRecipe(
+        'Rfi2f', Unary, base_size=4, ins=GPR, outs=FPR,
+        emit='put_rfp(bits, in_reg0, 0, out_reg0, sink);')
+
+# Unary OP-FP instruction converting a float to an integer register.
+Rff2i = EncRecipe(
+        'Rff2i', Unary, base_size=4, ins=FPR, outs=GPR,
+        emit='put_rfp(bits, in_reg0, 0, out_reg0, sink);')
+
+# Sign injection with both sources the same register. This implements copies,
+# `fneg`, and `fabs`.
+Rfsgn = EncRecipe(
+        'Rfsgn', Unary, base_size=4, ins=FPR, outs=FPR,
+        emit='put_rfp(bits, in_reg0, in_reg0, out_reg0, sink);')
+
+# Same for an FPR regmove.
+Rfrmov = EncRecipe(
+        'Rfrmov', RegMove, base_size=4, ins=FPR, outs=(),
+        emit='put_rfp(bits, src, src, dst, sink);')
+
+# Floating point comparison producing a 0/1 result in an integer register.
+Rfcmp = EncRecipe(
+        'Rfcmp', FloatCompare, base_size=4, ins=(FPR, FPR), outs=GPR,
+        emit='put_rfp(bits, in_reg0, in_reg1, out_reg0, sink);')
+
+# Same with swapped operands, so `gt` and `ge` can use `flt` and `fle`.
+Rfcmpr = EncRecipe(
+        'Rfcmpr', FloatCompare, base_size=4, ins=(FPR, FPR), outs=GPR,
+        emit='put_rfp(bits, in_reg1, in_reg0, out_reg0, sink);')
+
+# R4-type fused multiply-add.
+R4f = EncRecipe(
+        'R4f', Ternary, base_size=4, ins=(FPR, FPR, FPR), outs=FPR,
+        emit='put_r4(bits, in_reg0, in_reg1, in_reg2, out_reg0, sink);')
+
+# I-type float load with a 12-bit signed offset.
+Ifld = EncRecipe(
+        'Ifld', Load, base_size=4, ins=GPR, outs=FPR,
+        instp=IsSignedInt(Load.offset, 12),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        let offset: i32 = offset.into();
+        put_i(bits, in_reg0, offset.into(), out_reg0, sink);
+        ''')
+
+# S-type float store with a 12-bit signed offset.
+Sfst = EncRecipe(
+        'Sfst', Store, base_size=4, ins=(FPR, GPR), outs=(),
+        instp=IsSignedInt(Store.offset, 12),
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        let offset: i32 = offset.into();
+        put_s(bits, offset.into(), in_reg1, in_reg0, sink);
+        ''')
+
+# Spill of an FPR with an S-type store relative to the stack pointer. The
+# frame must be small enough for the slot offset to fit in 12 bits.
+FPsp = EncRecipe(
+        'FPsp', Unary, base_size=4,
+        ins=FPR, outs=Stack(FPR),
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        let base = stk_base(out_stk0.base);
+        put_s(bits, out_stk0.offset.into(), base, in_reg0, sink);
+        ''')
+
+# Fill of an FPR with an I-type load relative to the stack pointer.
+FPfi = EncRecipe(
+        'FPfi', Unary, base_size=4,
+        ins=Stack(FPR), outs=FPR, latency=3,
+        emit='''
+        let base = stk_base(in_stk0.base);
+        put_i(bits, base, in_stk0.offset.into(), out_reg0, sink);
+        ''')
+
+# Bit-casts between floats and integers of the same size when the floats are
+# kept in integer registers.
+null = EncRecipe('null', Unary, base_size=0, ins=GPR, outs=0, emit='')
+
+# Compressed 16-bit instructions from the 'C' extension.
+#
+# These encodings are always added after the corresponding 32-bit encoding, so
+# the register allocator only sees the unconstrained 32-bit forms. The
+# `shrink_instructions` pass switches to a compressed encoding when the
+# allocated registers and the immediate fit.
+
+# CR-type register-register instruction with a tied destination: `c.add`.
+CRr = EncRecipe(
+        'CRr', Binary, base_size=2, ins=(GPR, GPR), outs=0,
+        emit='put_cr(bits, in_reg0, in_reg1, sink);')
+
+# CR-type copy: `c.mv`.
+CRcopy = EncRecipe(
+        'CRcopy', Unary, base_size=2, ins=GPR, outs=GPR,
+        emit='put_cr(bits, out_reg0, in_reg0, sink);')
+
+# CR-type `c.jr %x1` as a return instruction.
+CRret = EncRecipe(
+        'CRret', MultiAry, base_size=2, ins=(), outs=(),
+        emit='''
+        // Return instructions are always a jump to %x1.
+        put_cr(bits, 1, 0, sink);
+        ''')
+
+# CR-type `c.jalr` as a call_indirect. The link register is implicitly %x1.
+CRcall = EncRecipe(
+        'CRcall', CallIndirect, base_size=2, ins=GPR, outs=(),
+        emit='put_cr(bits, in_reg0, 0, sink);')
+
+# CA-type arithmetic with a tied destination. Both operands must be in the
+# `x8`-`x15` range addressable by the 3-bit register fields.
+CAr = EncRecipe(
+        'CAr', Binary, base_size=2, ins=(GPRC, GPRC), outs=0,
+        emit='put_ca(bits, in_reg0, in_reg1, sink);')
+
+# CI-type instruction with a 6-bit signed immediate and a tied destination.
+CIi = EncRecipe(
+        'CIi', BinaryImm, base_size=2, ins=GPR, outs=0,
+        instp=IsSignedInt(BinaryImm.imm, 6),
+        emit='put_ci(bits, in_reg0, imm.into(), sink);')
+
+# CI-type immediate shift with a tied destination.
+CIshamt = EncRecipe(
+        'CIshamt', BinaryImm, base_size=2, ins=GPR, outs=0,
+        instp=IsUnsignedInt(BinaryImm.imm, 5),
+        emit='put_ci(bits, in_reg0, imm.into(), sink);')
+
+# CI-type `c.li` materializing a 6-bit signed constant.
+CIz = EncRecipe(
+        'CIz', UnaryImm, base_size=2, ins=(), outs=GPR,
+        instp=IsSignedInt(UnaryImm.imm, 6),
+        emit='put_ci(bits, out_reg0, imm.into(), sink);')
+
+# CB-type `c.andi` with a 6-bit signed immediate.
+CBi = EncRecipe(
+        'CBi', BinaryImm, base_size=2, ins=GPRC, outs=0,
+        instp=IsSignedInt(BinaryImm.imm, 6),
+        emit='put_cb_imm(bits, in_reg0, imm.into(), sink);')
+
+# CB-type immediate shift.
+CBshamt = EncRecipe(
+        'CBshamt', BinaryImm, base_size=2, ins=GPRC, outs=0,
+        instp=IsUnsignedInt(BinaryImm.imm, 5),
+        emit='put_cb_imm(bits, in_reg0, imm.into(), sink);')
+
+# CB-type branch comparing a register against zero.
+CBzero = EncRecipe(
+        'CBzero', Branch, base_size=2,
+        ins=(GPRC), outs=(),
+        branch_range=(0, 9),
+        emit='''
+        let dest = i64::from(func.offsets[destination]);
+        let disp = dest - i64::from(sink.offset());
+        put_cb_branch(bits, disp, in_reg0, sink);
+        ''')
+
+# CJ-type unconditional jump.
+CJump = EncRecipe(
+        'CJump', Jump, base_size=2, ins=(), outs=(), branch_range=(0, 12),
+        emit='''
+        let dest = i64::from(func.offsets[destination]);
+        let disp = dest - i64::from(sink.offset());
+        put_cj(bits, disp, sink);
+        ''')
@@ -20,4 +20,8 @@ FloatRegs = RegBank(
 GPR = RegClass(IntRegs)
 FPR = RegClass(FloatRegs)
 
+# The compressed instruction formats with 3-bit register fields can only
+# address `x8`-`x15`.
+GPRC = GPR[8:16]
+
 RegClass.extract_names(globals())
@@ -3,7 +3,7 @@ RISC-V settings.
 """
 from __future__ import absolute_import
 from cdsl.settings import SettingGroup, BoolSetting
-from cdsl.predicates import And
+from cdsl.predicates import And, Not
 import base.settings as shared
 from .defs import ISA
 
@@ -13,6 +13,7 @@ supports_m = BoolSetting("CPU supports the 'M' extension (mul/div)")
 supports_a = BoolSetting("CPU supports the 'A' extension (atomics)")
 supports_f = BoolSetting("CPU supports the 'F' extension (float)")
 supports_d = BoolSetting("CPU supports the 'D' extension (double)")
+supports_c = BoolSetting("CPU supports the 'C' extension (compressed)")
 
 enable_m = BoolSetting(
         "Enable the use of 'M' instructions if available",
@@ -26,6 +27,11 @@ use_a = And(supports_a, shared.enable_atomics)
 use_f = And(supports_f, shared.enable_float)
 use_d = And(supports_d, shared.enable_float)
 
+# Without the 'F' or 'D' extension, floating point values of that size live in
+# integer registers and arithmetic is expanded into library calls.
+soft_f = Not(use_f)
+soft_d = Not(use_d)
+
 full_float = And(shared.enable_simd, supports_f, supports_d)
 
 ISA.settings.close(globals())
@@ -3,20 +3,24 @@ x86 Encodings.
 """
 from __future__ import absolute_import
 from cdsl.predicates import IsZero32BitFloat, IsZero64BitFloat
-from cdsl.predicates import IsUnsignedInt, Not, And
+from cdsl.predicates import IsUnsignedInt, Not, And, IsEqual
 from base.predicates import IsColocatedFunc, IsColocatedData, LengthEquals
 from base import instructions as base
 from base import types
 from base.formats import UnaryIeee32, UnaryIeee64, UnaryImm
-from base.formats import FuncAddr, Call, LoadComplex, StoreComplex
+from base.formats import FuncAddr, Call, TryCall, LoadComplex, StoreComplex
+from base.formats import AtomicRmw
 from .defs import X86_64, X86_32
 from . import recipes as r
 from . import settings as cfg
 from . import instructions as x86
-from .legalize import x86_expand
-from base.legalize import narrow, widen, expand_flags
-from base.settings import allones_funcaddrs, is_pic
-from .settings import use_sse41
+from .legalize import x86_expand, x86_simd
+from base.legalize import narrow, narrow_wide, widen, expand_flags
+from base.settings import allones_funcaddrs, is_pic, patchable_calls
+from base.settings import patchable_branches
+from base.settings import out_of_line_traps
+from base.immediates import atomic_rmw_op
+from .settings import use_ssse3, use_sse41
 
 try:
     from typing import TYPE_CHECKING, Any  # noqa
@@ -29,17 +33,18 @@ except ImportError:
 
 X86_32.legalize_monomorphic(expand_flags)
 X86_32.legalize_type(
-    default=narrow,
+    default=narrow_wide,
     b1=expand_flags,
     i8=widen,
     i16=widen,
     i32=x86_expand,
+    i64=narrow,
     f32=x86_expand,
     f64=x86_expand)
 
 X86_64.legalize_monomorphic(expand_flags)
 X86_64.legalize_type(
-    default=narrow,
+    default=narrow_wide,
     b1=expand_flags,
     i8=widen,
     i16=widen,
@@ -186,6 +191,15 @@ X86_64.enc(base.regmove.i64, *r.rmov.rex(0x89, w=1))
 enc_both(base.regmove.b1, r.rmov, 0x89)
 enc_both(base.regmove.i8, r.rmov, 0x89)
 
+# `xchg` always exchanges the full registers, so the same encoding works for
+# any combination of value types.
+for ty in [types.b1, types.i8, types.i16, types.i32]:
+    for ty2 in [types.b1, types.i8, types.i16, types.i32]:
+        X86_32.enc(base.regswap.bind(ty, ty2), *r.rswap(0x87))
+for ty in [types.b1, types.i8, types.i16, types.i32, types.i64]:
+    for ty2 in [types.b1, types.i8, types.i16, types.i32, types.i64]:
+        X86_64.enc(base.regswap.bind(ty, ty2), *r.rswap.rex(0x87, w=1))
+
 # Immediate instructions with sign-extended 8-bit and 32-bit immediate.
 for inst,               rrr in [
         (base.iadd_imm, 0),
@@ -238,6 +252,9 @@ for inst,           rrr in [
         (base.sshr_imm, 7)]:
     enc_i32_i64(inst, r.r_ib, 0xc1, rrr=rrr)
 
+# Byte swap.
+enc_i32_i64(base.bswap, r.bswap, 0x0f, 0xc8)
+
 # Population count.
 X86_32.enc(base.popcnt.i32, *r.urm(0xf3, 0x0f, 0xb8), isap=cfg.use_popcnt)
 X86_64.enc(base.popcnt.i64, *r.urm.rex(0xf3, 0x0f, 0xb8, w=1),
@@ -281,6 +298,19 @@ for recipe in [r.stWithIndex, r.stWithIndexDisp8, r.stWithIndexDisp32]:
     enc_both_instp(base.istore16_complex.i32, recipe, stcomplexp, 0x66, 0x89)
     enc_x86_64_instp(base.istore16_complex.i64, recipe, stcomplexp, 0x66, 0x89)
 
+# Scaled index addressing. The base and index registers have the width of the
+# address size.
+for inst, opc, recipes in [
+        (x86.load_scaled, 0x8b,
+            [r.ldScaled, r.ldScaledDisp8, r.ldScaledDisp32]),
+        (x86.store_scaled, 0x89,
+            [r.stScaled, r.stScaledDisp8, r.stScaledDisp32])]:
+    for recipe in recipes:
+        X86_32.enc(inst.i32.i32.i32, *recipe(opc))
+        X86_64.enc(inst.i32.i64.i64, *recipe.rex(opc))
+        X86_64.enc(inst.i32.i64.i64, *recipe(opc))
+        X86_64.enc(inst.i64.i64.i64, *recipe.rex(opc, w=1))
+
 for recipe in [r.stWithIndex_abcd,
                r.stWithIndexDisp8_abcd,
                r.stWithIndexDisp32_abcd]:
@@ -309,6 +339,27 @@ for ty in [types.b1, types.i8, types.i16]:
     enc_both(base.spill.bind(ty), r.spillSib32, 0x89)
     enc_both(base.regspill.bind(ty), r.regspill32, 0x89)
 
+# MOVBE loads and stores swap the bytes of the value.
+for inst, opc, recipes in [
+        (x86.movbe_load, 0xf0, [r.ld, r.ldDisp8, r.ldDisp32]),
+        (x86.movbe_store, 0xf1, [r.st, r.stDisp8, r.stDisp32])]:
+    for recipe in recipes:
+        X86_32.enc(inst.i32.i32, *recipe(0x0f, 0x38, opc),
+                   isap=cfg.use_movbe)
+        X86_64.enc(inst.i32.i64, *recipe.rex(0x0f, 0x38, opc),
+                   isap=cfg.use_movbe)
+        X86_64.enc(inst.i32.i64, *recipe(0x0f, 0x38, opc),
+                   isap=cfg.use_movbe)
+        X86_64.enc(inst.i64.i64, *recipe.rex(0x0f, 0x38, opc, w=1),
+                   isap=cfg.use_movbe)
+
+# The MOVBE instructions are only formed by the post-opt pass when the CPU
+# supports them.
+X86_32.requires_feature(x86.movbe_load.i32, x86.movbe_store.i32)
+X86_64.requires_feature(
+        x86.movbe_load.i32, x86.movbe_load.i64,
+        x86.movbe_store.i32, x86.movbe_store.i64)
+
 for recipe in [r.ld, r.ldDisp8, r.ldDisp32]:
     enc_i32_i64_ld_st(base.load, True, recipe, 0x8b)
     enc_x86_64(base.uload32.i64, recipe, 0x8b)
@@ -318,6 +369,47 @@ for recipe in [r.ld, r.ldDisp8, r.ldDisp32]:
     enc_i32_i64_ld_st(base.uload8, True, recipe, 0x0f, 0xb6)
     enc_i32_i64_ld_st(base.sload8, True, recipe, 0x0f, 0xbe)
 
+# Atomic memory operations. Atomic stores are followed by MFENCE, so plain
+# loads are sequentially consistent.
+enc_i32_i64_ld_st(base.atomic_load, True, r.ldAtomic, 0x8b)
+enc_i32_i64_ld_st(base.atomic_store, True, r.stAtomic, 0x89)
+enc_i32_i64_ld_st(base.atomic_cas, True, r.lockCasAtomic, 0x0f, 0xb1)
+
+# Narrow atomic accesses. Loads zero-extend into the full register, and byte
+# operands are restricted to ABCD without a REX prefix.
+enc_both(base.atomic_load.i8.any, r.ldAtomic, 0x0f, 0xb6)
+enc_both(base.atomic_load.i16.any, r.ldAtomic, 0x0f, 0xb7)
+enc_both(base.atomic_store.i8.any, r.stAtomic_abcd, 0x88)
+enc_both(base.atomic_store.i16.any, r.stAtomic, 0x66, 0x89)
+enc_both(base.atomic_cas.i8.any, r.lockCasAtomic_abcd, 0x0f, 0xb0)
+enc_both(base.atomic_cas.i16.any, r.lockCasAtomic, 0x66, 0x0f, 0xb1)
+
+# LOCK XADD and XCHG. The remaining `atomic_rmw` operations are expanded into
+# an `atomic_cas` loop.
+for recipe, op, opc in [
+        (r.lockRmwAtomic, atomic_rmw_op.add, (0x0f, 0xc1)),
+        (r.rmwAtomic, atomic_rmw_op.xchg, (0x87,))]:
+    instp = IsEqual(AtomicRmw.op, op)
+    X86_32.enc(base.atomic_rmw.i32.any, *recipe(*opc), instp=instp)
+    X86_64.enc(base.atomic_rmw.i32.any, *recipe.rex(*opc), instp=instp)
+    X86_64.enc(base.atomic_rmw.i32.any, *recipe(*opc), instp=instp)
+    X86_64.enc(
+        base.atomic_rmw.i64.any, *recipe.rex(*opc, w=1), instp=instp)
+
+for recipe, op, opc in [
+        (r.lockRmwAtomic_abcd, atomic_rmw_op.add, (0x0f, 0xc0)),
+        (r.rmwAtomic_abcd, atomic_rmw_op.xchg, (0x86,))]:
+    enc_both_instp(
+        base.atomic_rmw.i8.any, recipe, IsEqual(AtomicRmw.op, op), *opc)
+for recipe, op, opc in [
+        (r.lockRmwAtomic, atomic_rmw_op.add, (0x66, 0x0f, 0xc1)),
+        (r.rmwAtomic, atomic_rmw_op.xchg, (0x66, 0x87))]:
+    enc_both_instp(
+        base.atomic_rmw.i16.any, recipe, IsEqual(AtomicRmw.op, op), *opc)
+
+X86_32.enc(base.fence, r.mfence, 0)
+X86_64.enc(base.fence, r.mfence, 0)
+
 enc_i32_i64(base.fill, r.fillSib32, 0x8b)
 enc_i32_i64(base.regfill, r.regfill32, 0x8b)
 
@@ -333,6 +425,33 @@ enc_x86_64(x86.push.i64, r.pushq, 0x50)
 X86_32.enc(x86.pop.i32, *r.popq(0x58))
 enc_x86_64(x86.pop.i64, r.popq, 0x58)
 
+# Reference types live in general purpose registers, like pointers.
+X86_32.enc(base.null.r32, *r.pu_id_ref(0xb8))
+X86_64.enc(base.null.r64, *r.pu_id_ref.rex(0xb8))
+X86_64.enc(base.null.r64, *r.pu_id_ref(0xb8))
+X86_32.enc(base.is_null.r32, *r.is_zero(0x85))
+X86_64.enc(base.is_null.r64, *r.is_zero.rex(0x85, w=1))
+
+X86_32.enc(base.copy.r32, *r.umr(0x89))
+X86_64.enc(base.copy.r64, *r.umr.rex(0x89, w=1))
+X86_32.enc(base.regmove.r32, *r.rmov(0x89))
+X86_64.enc(base.regmove.r64, *r.rmov.rex(0x89, w=1))
+X86_32.enc(base.spill.r32, *r.spillSib32(0x89))
+X86_64.enc(base.spill.r64, *r.spillSib32.rex(0x89, w=1))
+X86_32.enc(base.regspill.r32, *r.regspill32(0x89))
+X86_64.enc(base.regspill.r64, *r.regspill32.rex(0x89, w=1))
+X86_32.enc(base.fill.r32, *r.fillSib32(0x8b))
+X86_64.enc(base.fill.r64, *r.fillSib32.rex(0x8b, w=1))
+X86_32.enc(base.regfill.r32, *r.regfill32(0x8b))
+X86_64.enc(base.regfill.r64, *r.regfill32.rex(0x8b, w=1))
+
+for recipe in [r.ld, r.ldDisp8, r.ldDisp32]:
+    X86_32.enc(base.load.r32.any, *recipe(0x8b))
+    X86_64.enc(base.load.r64.any, *recipe.rex(0x8b, w=1))
+for recipe in [r.st, r.stDisp8, r.stDisp32]:
+    X86_32.enc(base.store.r32.any, *recipe(0x89))
+    X86_64.enc(base.store.r64.any, *recipe.rex(0x89, w=1))
+
 # Copy Special
 # For x86-64, only define REX forms for now, since we can't describe the
 # special regunit immediate operands with the current constraint language.
@@ -355,6 +474,15 @@ X86_32.enc(base.adjust_sp_down_imm, *r.adjustsp_id(0x81, rrr=5))
 X86_64.enc(base.adjust_sp_down_imm, *r.adjustsp_ib.rex(0x83, rrr=5, w=1))
 X86_64.enc(base.adjust_sp_down_imm, *r.adjustsp_id.rex(0x81, rrr=5, w=1))
 
+# Read and write SP.
+X86_32.enc(base.get_stack_pointer.i32, *r.get_sp(0x89))
+X86_64.enc(base.get_stack_pointer.i64, *r.get_sp.rex(0x89, w=1))
+X86_32.enc(base.set_stack_pointer.i32, *r.adjustsp(0x89))
+X86_64.enc(base.set_stack_pointer.i64, *r.adjustsp.rex(0x89, w=1))
+
+# Stack switching needs RIP-relative addressing for the resume address.
+X86_64.enc(base.stack_switch.i64, r.stack_switch, 0)
+
 #
 # Float loads and stores.
 #
@@ -445,6 +573,13 @@ X86_64.enc(base.symbol_value.i64, *r.pcrel_gvaddr8.rex(0x8d, w=1),
 X86_64.enc(base.symbol_value.i64, *r.got_gvaddr8.rex(0x8b, w=1),
            isap=is_pic)
 
+# PIC is not supported in 32-bit mode.
+X86_32.requires_feature(base.func_addr.i32, base.symbol_value.i32)
+
+# Thread-local symbols. ELF TLS is only supported on x86-64.
+X86_64.enc(x86.elf_tls_get_addr.i64, r.elf_tls_get_addr, 0)
+X86_64.enc(x86.elf_tls_initial_exec.i64, r.elf_tls_initial_exec, 0)
+
 #
 # Stack addresses.
 #
@@ -459,17 +594,33 @@ X86_64.enc(base.stack_addr.i64, *r.spaddr8_id.rex(0x8d, w=1))
 #
 
 # 32-bit, both PIC and non-PIC.
-X86_32.enc(base.call, *r.call_id(0xe8))
+X86_32.enc(base.call, *r.call_id(0xe8), isap=Not(patchable_calls))
 
 # 64-bit, colocated, both PIC and non-PIC. Use the call instruction's
 # pc-relative field.
 X86_64.enc(base.call, *r.call_id(0xe8),
-           instp=IsColocatedFunc(Call.func_ref))
+           instp=IsColocatedFunc(Call.func_ref),
+           isap=Not(patchable_calls))
 
 # 64-bit, non-colocated, PIC. There is no 64-bit non-colocated non-PIC version,
 # since non-PIC is currently using the large model, which requires calls be
 # lowered to func_addr+call_indirect.
-X86_64.enc(base.call, *r.call_plt_id(0xe8), isap=is_pic)
+X86_64.enc(base.call, *r.call_plt_id(0xe8),
+           isap=And(is_pic, Not(patchable_calls)))
+
+# The same calls padded with nops, so they can be patched.
+X86_32.enc(base.call, *r.call_id_patchable(0xe8), isap=patchable_calls)
+X86_64.enc(base.call, *r.call_id_patchable(0xe8),
+           instp=IsColocatedFunc(Call.func_ref),
+           isap=patchable_calls)
+X86_64.enc(base.call, *r.call_plt_id_patchable(0xe8),
+           isap=And(is_pic, patchable_calls))
+
+# Calls with a landing pad have the same encodings as direct calls.
+X86_32.enc(base.try_call, *r.try_call_id(0xe8))
+X86_64.enc(base.try_call, *r.try_call_id(0xe8),
+           instp=IsColocatedFunc(TryCall.func_ref))
+X86_64.enc(base.try_call, *r.try_call_plt_id(0xe8), isap=is_pic)
 
 X86_32.enc(base.call_indirect.i32, *r.call_r(0xff, rrr=2))
 X86_64.enc(base.call_indirect.i64, *r.call_r.rex(0xff, rrr=2))
@@ -481,8 +632,14 @@ X86_64.enc(base.x_return, *r.ret(0xc3))
 #
 # Branches
 #
-enc_both(base.jump, r.jmpb, 0xeb)
-enc_both(base.jump, r.jmpd, 0xe9)
+for recipe, op in [(r.jmpb, 0xeb), (r.jmpd, 0xe9)]:
+    X86_32.enc(base.jump, *recipe(op), isap=Not(patchable_branches))
+    X86_64.enc(base.jump, *recipe.rex(op), isap=Not(patchable_branches))
+    X86_64.enc(base.jump, *recipe(op), isap=Not(patchable_branches))
+
+# Jumps padded with nops, so they can be patched.
+X86_32.enc(base.jump, *r.jmpd_patchable(0xe9), isap=patchable_branches)
+X86_64.enc(base.jump, *r.jmpd_patchable(0xe9), isap=patchable_branches)
 
 enc_both(base.brif, r.brib, 0x70)
 enc_both(base.brif, r.brid, 0x0f, 0x80)
@@ -532,11 +689,18 @@ X86_64.enc(base.trap, *r.trap(0x0f, 0x0b))
 X86_32.enc(base.debugtrap, r.debugtrap, 0)
 X86_64.enc(base.debugtrap, r.debugtrap, 0)
 
-# Using a standard EncRecipe, not the TailRecipe.
-X86_32.enc(base.trapif, r.trapif, 0)
-X86_64.enc(base.trapif, r.trapif, 0)
-X86_32.enc(base.trapff, r.trapff, 0)
-X86_64.enc(base.trapff, r.trapff, 0)
+# Safepoints and stack maps don't generate any code.
+X86_32.enc(base.safepoint, r.safepoint, 0)
+X86_64.enc(base.safepoint, r.safepoint, 0)
+X86_32.enc(base.stackmap, r.stackmap, 0)
+X86_64.enc(base.stackmap, r.stackmap, 0)
+
+# Using a standard EncRecipe, not the TailRecipe. Out-of-line traps are
+# legalized into a branch to a trap at the end of the function instead.
+X86_32.enc(base.trapif, r.trapif, 0, isap=Not(out_of_line_traps))
+X86_64.enc(base.trapif, r.trapif, 0, isap=Not(out_of_line_traps))
+X86_32.enc(base.trapff, r.trapff, 0, isap=Not(out_of_line_traps))
+X86_64.enc(base.trapff, r.trapff, 0, isap=Not(out_of_line_traps))
 
 #
 # Comparisons
@@ -714,6 +878,16 @@ for inst in [
     enc_both(inst.f64, r.furmi_rnd, 0x66, 0x0f, 0x3a, 0x0b, isap=use_sse41)
 
 
+# Three-operand AVX forms of the basic arithmetic ops. These come before the
+# SSE forms so they are preferred when AVX is available.
+for inst,           opc in [
+        (base.fadd, 0x58),
+        (base.fsub, 0x5c),
+        (base.fmul, 0x59),
+        (base.fdiv, 0x5e)]:
+    X86_64.enc(inst.f32, *r.vfa.vex(0xf3, 0x0f, opc), isap=cfg.use_avx)
+    X86_64.enc(inst.f64, *r.vfa.vex(0xf2, 0x0f, opc), isap=cfg.use_avx)
+
 # Binary arithmetic ops.
 for inst,           opc in [
         (base.fadd, 0x58),
@@ -721,10 +895,18 @@ for inst,           opc in [
         (base.fmul, 0x59),
         (base.fdiv, 0x5e),
         (x86.fmin,  0x5d),
-        (x86.fmax,  0x5f)]:
+        (x86.fmax,  0x5f),
+        (base.fmin_pseudo, 0x5d),
+        (base.fmax_pseudo, 0x5f)]:
     enc_both(inst.f32, r.fa, 0xf3, 0x0f, opc)
     enc_both(inst.f64, r.fa, 0xf2, 0x0f, opc)
 
+# Fused multiply-add: VFMADD213SS and VFMADD213SD.
+X86_64.enc(base.fma.f32, *r.fma.vex(0x66, 0x0f, 0x38, 0xa9),
+           isap=cfg.use_fma)
+X86_64.enc(base.fma.f64, *r.fma.vex(0x66, 0x0f, 0x38, 0xa9, w=1),
+           isap=cfg.use_fma)
+
 # Binary bitwise ops.
 for inst,               opc in [
         (base.band,     0x54),
@@ -746,3 +928,141 @@ enc_both(base.fcmp.f64, r.fcscc, 0x66, 0x0f, 0x2e)
 
 enc_both(base.ffcmp.f32, r.fcmp, 0x0f, 0x2e)
 enc_both(base.ffcmp.f64, r.fcmp, 0x66, 0x0f, 0x2e)
+
+#
+# SIMD
+#
+# Only the 128-bit vector types are supported, and they live in the XMM
+# registers like the scalar floats. The three-operand AVX forms come before
+# the SSE forms so they are preferred when AVX is available.
+
+VECTOR_INTS = [types.i8.by(16), types.i16.by(8),
+               types.i32.by(4), types.i64.by(2)]
+VECTOR_FLOATS = [types.f32.by(4), types.f64.by(2)]
+VECTOR_TYPES = VECTOR_INTS + VECTOR_FLOATS
+
+# The vector types that fit in a register are legalized by expansion, instead
+# of the default narrowing into smaller vectors.
+for mode in (X86_32, X86_64):
+    mode.legalize_type(**{str(ty): x86_simd for ty in VECTOR_TYPES})
+
+for ty in VECTOR_TYPES:
+    # movups for loads, stores, spills and fills, since stack slots and heap
+    # addresses are not necessarily 16-byte aligned.
+    enc_both(base.load.bind(ty).any, r.fld, 0x0f, 0x10)
+    enc_both(base.load.bind(ty).any, r.fldDisp8, 0x0f, 0x10)
+    enc_both(base.load.bind(ty).any, r.fldDisp32, 0x0f, 0x10)
+
+    enc_both(base.store.bind(ty).any, r.fst, 0x0f, 0x11)
+    enc_both(base.store.bind(ty).any, r.fstDisp8, 0x0f, 0x11)
+    enc_both(base.store.bind(ty).any, r.fstDisp32, 0x0f, 0x11)
+
+    enc_both(base.fill.bind(ty), r.ffillSib32, 0x0f, 0x10)
+    enc_both(base.regfill.bind(ty), r.fregfill32, 0x0f, 0x10)
+    enc_both(base.spill.bind(ty), r.fspillSib32, 0x0f, 0x11)
+    enc_both(base.regspill.bind(ty), r.fregspill32, 0x0f, 0x11)
+
+    # movaps
+    enc_both(base.copy.bind(ty), r.furm, 0x0f, 0x28)
+    X86_32.enc(base.regmove.bind(ty), *r.frmov(0x0f, 0x28))
+    X86_64.enc(base.regmove.bind(ty), *r.frmov.rex(0x0f, 0x28))
+
+    # Bitwise ops don't care about the lane type, so use the shorter
+    # andps/orps/xorps/andnps forms for all of them.
+    for inst,               opc in [
+            (base.band,     0x54),
+            (base.bor,      0x56),
+            (base.bxor,     0x57)]:
+        X86_64.enc(inst.bind(ty), *r.vfa.vex(0x0f, opc), isap=cfg.use_avx)
+        enc_both(inst.bind(ty), r.fa, 0x0f, opc)
+
+    X86_64.enc(base.band_not.bind(ty), *r.vfax.vex(0x0f, 0x55),
+               isap=cfg.use_avx)
+    enc_both(base.band_not.bind(ty), r.fax, 0x0f, 0x55)
+
+# Packed integer arithmetic: padd*, psub*, pmullw and pmulld.
+for inst, opcs in [
+        (base.iadd, (0xfc, 0xfd, 0xfe, 0xd4)),
+        (base.isub, (0xf8, 0xf9, 0xfa, 0xfb))]:
+    for ty, opc in zip(VECTOR_INTS, opcs):
+        X86_64.enc(inst.bind(ty), *r.vfa.vex(0x66, 0x0f, opc),
+                   isap=cfg.use_avx)
+        enc_both(inst.bind(ty), r.fa, 0x66, 0x0f, opc)
+
+X86_64.enc(base.imul.bind(types.i16.by(8)), *r.vfa.vex(0x66, 0x0f, 0xd5),
+           isap=cfg.use_avx)
+enc_both(base.imul.bind(types.i16.by(8)), r.fa, 0x66, 0x0f, 0xd5)
+X86_64.enc(base.imul.bind(types.i32.by(4)),
+           *r.vfa.vex(0x66, 0x0f, 0x38, 0x40), isap=cfg.use_avx)
+X86_32.enc(base.imul.bind(types.i32.by(4)), *r.fa(0x66, 0x0f, 0x38, 0x40),
+           isap=use_sse41)
+X86_64.enc(base.imul.bind(types.i32.by(4)),
+           *r.fa.rex(0x66, 0x0f, 0x38, 0x40), isap=use_sse41)
+X86_64.enc(base.imul.bind(types.i32.by(4)), *r.fa(0x66, 0x0f, 0x38, 0x40),
+           isap=use_sse41)
+
+# Packed float arithmetic: the *ps forms for f32x4 and the *pd forms for
+# f64x2.
+for inst,           opc in [
+        (base.fadd, 0x58),
+        (base.fsub, 0x5c),
+        (base.fmul, 0x59),
+        (base.fdiv, 0x5e),
+        (x86.fmin,  0x5d),
+        (x86.fmax,  0x5f)]:
+    X86_64.enc(inst.bind(types.f32.by(4)), *r.vfa.vex(0x0f, opc),
+               isap=cfg.use_avx)
+    enc_both(inst.bind(types.f32.by(4)), r.fa, 0x0f, opc)
+    X86_64.enc(inst.bind(types.f64.by(2)), *r.vfa.vex(0x66, 0x0f, opc),
+               isap=cfg.use_avx)
+    enc_both(inst.bind(types.f64.by(2)), r.fa, 0x66, 0x0f, opc)
+
+# Packed square roots.
+enc_both(base.sqrt.bind(types.f32.by(4)), r.furm, 0x0f, 0x51)
+enc_both(base.sqrt.bind(types.f64.by(2)), r.furm, 0x66, 0x0f, 0x51)
+
+# No-op conversions between vector types.
+for ty in VECTOR_TYPES:
+    for from_ty in VECTOR_TYPES:
+        if ty != from_ty:
+            X86_32.enc(base.bitcast.bind(ty, from_ty), r.null_fpr, 0)
+            X86_64.enc(base.bitcast.bind(ty, from_ty), r.null_fpr, 0)
+
+# Saturating arithmetic: padds*, paddus*, psubs* and psubus*.
+for inst, opcs in [
+        (base.sadd_sat, (0xec, 0xed)),
+        (base.uadd_sat, (0xdc, 0xdd)),
+        (base.ssub_sat, (0xe8, 0xe9)),
+        (base.usub_sat, (0xd8, 0xd9))]:
+    for ty, opc in zip(VECTOR_INTS, opcs):
+        X86_64.enc(inst.bind(ty), *r.vfa.vex(0x66, 0x0f, opc),
+                   isap=cfg.use_avx)
+        enc_both(inst.bind(ty), r.fa, 0x66, 0x0f, opc)
+
+# Saturating narrows: packsswb, packssdw, packuswb and packusdw.
+for inst,          ty,              opc in [
+        (base.snarrow, types.i16.by(8), (0x63,)),
+        (base.snarrow, types.i32.by(4), (0x6b,)),
+        (base.unarrow, types.i16.by(8), (0x67,))]:
+    X86_64.enc(inst.bind(ty), *r.vfa.vex(0x66, 0x0f, *opc),
+               isap=cfg.use_avx)
+    enc_both(inst.bind(ty), r.fa, 0x66, 0x0f, *opc)
+
+packusdw = base.unarrow.bind(types.i32.by(4))
+X86_64.enc(packusdw, *r.vfa.vex(0x66, 0x0f, 0x38, 0x2b), isap=cfg.use_avx)
+X86_32.enc(packusdw, *r.fa(0x66, 0x0f, 0x38, 0x2b), isap=use_sse41)
+X86_64.enc(packusdw, *r.fa.rex(0x66, 0x0f, 0x38, 0x2b), isap=use_sse41)
+X86_64.enc(packusdw, *r.fa(0x66, 0x0f, 0x38, 0x2b), isap=use_sse41)
+
+# Byte shuffles.
+pshufb = x86.pshufb
+X86_64.enc(pshufb, *r.vfa.vex(0x66, 0x0f, 0x38, 0x00), isap=cfg.use_avx)
+X86_32.enc(pshufb, *r.fa(0x66, 0x0f, 0x38, 0x00), isap=use_ssse3)
+X86_64.enc(pshufb, *r.fa.rex(0x66, 0x0f, 0x38, 0x00), isap=use_ssse3)
+X86_64.enc(pshufb, *r.fa(0x66, 0x0f, 0x38, 0x00), isap=use_ssse3)
+
+# There are no expansions for these SIMD instructions on older CPUs, so they
+# are reported as unsupported without SSE4.1 or SSSE3.
+for cpumode in [X86_32, X86_64]:
+    cpumode.requires_feature(
+            base.imul.bind(types.i32.by(4)), packusdw, pshufb)
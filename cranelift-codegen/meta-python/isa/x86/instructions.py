@@ -6,6 +6,8 @@ target ISA.
 """
 
 from base.types import iflags
+from base.immediates import memflags, offset32, uimm8
+from base import entities
 from cdsl.operands import Operand
 from cdsl.typevar import TypeVar
 from cdsl.instructions import Instruction, InstructionGroup
@@ -122,6 +124,79 @@ fmax = Instruction(
         """,
         ins=(x, y), outs=a)
 
+I8x16 = TypeVar(
+        'I8x16', 'A vector of 16 8-bit integers',
+        ints=(8, 8), scalars=False, simd=(16, 16))
+x = Operand('x', I8x16, doc='Vector to select lanes from')
+y = Operand('y', I8x16, doc='Vector of lane indices')
+a = Operand('a', I8x16)
+
+pshufb = Instruction(
+        'x86_pshufb', r"""
+        Shuffle bytes with x86 semantics.
+
+        Lane ``i`` of ``a`` is zero if the most significant bit of lane ``i``
+        of ``y`` is set. Otherwise it is the lane of ``x`` selected by the low
+        four bits of lane ``i`` of ``y``. This differs from :inst:`swizzle`
+        for indices between 16 and 127.
+        """,
+        ins=(x, y), outs=a)
+
+
+iAddr = TypeVar('iAddr', 'An integer address type', ints=(32, 64))
+iIndex = TypeVar('iIndex', 'An integer index type', ints=(32, 64))
+Mem = TypeVar(
+        'Mem', 'Any type that can be stored in memory',
+        ints=True, floats=True, simd=True)
+
+MemFlags = Operand('MemFlags', memflags)
+p = Operand('p', iAddr, doc='Base address')
+idx = Operand('idx', iIndex, doc='Index scaled by ``Scale``')
+Scale = Operand('Scale', uimm8, doc='Scale factor: 1, 2, 4, or 8')
+Offset = Operand('Offset', offset32, 'Byte offset from the scaled address')
+x = Operand('x', Mem, doc='Value to be stored')
+a = Operand('a', Mem, doc='Value loaded')
+
+load_scaled = Instruction(
+        'x86_load_scaled', r"""
+        Load from memory at ``p + idx * Scale + Offset``.
+
+        This matches the x86 base + index * scale + displacement addressing
+        mode. It is formed from address arithmetic after legalization.
+        """,
+        ins=(MemFlags, p, idx, Scale, Offset), outs=a, can_load=True)
+
+store_scaled = Instruction(
+        'x86_store_scaled', r"""
+        Store ``x`` to memory at ``p + idx * Scale + Offset``.
+
+        This matches the x86 base + index * scale + displacement addressing
+        mode. It is formed from address arithmetic after legalization.
+        """,
+        ins=(MemFlags, x, p, idx, Scale, Offset), can_store=True)
+
+x = Operand('x', iWord, doc='Value to be stored')
+a = Operand('a', iWord, doc='Value loaded')
+
+movbe_load = Instruction(
+        'x86_movbe_load', r"""
+        Load from memory at ``p + Offset`` and reverse the order of the bytes
+        of the loaded value.
+
+        This is formed from a :inst:`load` followed by a :inst:`bswap` when
+        the MOVBE instruction is available.
+        """,
+        ins=(MemFlags, p, Offset), outs=a, can_load=True)
+
+movbe_store = Instruction(
+        'x86_movbe_store', r"""
+        Reverse the order of the bytes of ``x`` and store the result to memory
+        at ``p + Offset``.
+
+        This is formed from a :inst:`bswap` followed by a :inst:`store` when
+        the MOVBE instruction is available.
+        """,
+        ins=(MemFlags, x, p, Offset), can_store=True)
 
 x = Operand('x', iWord)
 
@@ -170,4 +245,28 @@ bsf = Instruction(
     """,
     ins=x, outs=(y, rflags))
 
+GV = Operand('GV', entities.global_value)
+addr = Operand('addr', iWord)
+
+elf_tls_get_addr = Instruction(
+    'x86_elf_tls_get_addr', r"""
+    Compute the address of the thread-local symbol GV with the ELF
+    general-dynamic TLS model.
+
+    This calls ``__tls_get_addr`` with the address of the symbol's TLS
+    descriptor in %rdi, and returns the address in %rax. Like any call, it
+    clobbers the caller-saved registers.
+    """,
+    ins=GV, outs=addr, clobbers_all_regs=True)
+
+elf_tls_initial_exec = Instruction(
+    'x86_elf_tls_initial_exec', r"""
+    Compute the address of the thread-local symbol GV with the ELF
+    initial-exec TLS model.
+
+    The thread pointer is read from %fs:0, and the symbol's offset from it is
+    loaded from the GOT.
+    """,
+    ins=GV, outs=addr, can_load=True)
+
 GROUP.close()
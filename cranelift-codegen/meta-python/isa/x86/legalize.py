@@ -19,6 +19,15 @@ x86_expand = XFormGroup(
         """,
         isa=ISA, chain=shared.expand_flags)
 
+x86_simd = XFormGroup(
+        'x86_simd',
+        """
+        Legalize SIMD instructions by expansion.
+
+        Use x86-specific instructions if needed.
+        """,
+        isa=ISA, chain=shared.expand)
+
 a = Var('a')
 dead = Var('dead')
 x = Var('x')
@@ -100,6 +109,9 @@ x86_expand.custom_legalize(insts.fcvt_to_uint, 'expand_fcvt_to_uint')
 x86_expand.custom_legalize(insts.fcvt_to_sint_sat, 'expand_fcvt_to_sint_sat')
 x86_expand.custom_legalize(insts.fcvt_to_uint_sat, 'expand_fcvt_to_uint_sat')
 
+# The thread-local access sequence depends on the TLS model setting.
+x86_expand.custom_legalize(insts.tls_value, 'expand_tls_value')
+
 # Count leading and trailing zeroes, for baseline x86_64
 c_minus_one = Var('c_minus_one')
 c_thirty_one = Var('c_thirty_one')
@@ -227,3 +239,6 @@ x86_expand.legalize(
         lv15 << insts.imul(lv14, lc01),
         lv16 << insts.ushr_imm(lv15, imm64(24))
     ))
+
+# Swizzles become `pshufb` with the out of range indices adjusted.
+x86_simd.custom_legalize(insts.swizzle, 'expand_swizzle')
@@ -3,22 +3,23 @@ x86 Encoding recipes.
 """
 from __future__ import absolute_import
 from cdsl.isa import EncRecipe
-from cdsl.predicates import IsSignedInt, IsEqual, Or
+from cdsl.predicates import IsSignedInt, IsEqual, Or, And
 from cdsl.predicates import IsZero32BitFloat, IsZero64BitFloat
 from cdsl.registers import RegClass
 from base.formats import Unary, UnaryIeee32, UnaryIeee64, UnaryImm, UnaryBool
 from base.formats import Binary, BinaryImm
 from base.formats import MultiAry, NullAry
-from base.formats import Trap, Call, CallIndirect, Store, Load
+from base.formats import Trap, Call, CallIndirect, TryCall, Store, Load
 from base.formats import IntCompare, IntCompareImm, FloatCompare
 from base.formats import IntCond, FloatCond
 from base.formats import IntSelect, IntCondTrap, FloatCondTrap
 from base.formats import Jump, Branch, BranchInt, BranchFloat
 from base.formats import BranchTableEntry, BranchTableBase, IndirectJump
 from base.formats import Ternary, FuncAddr, UnaryGlobalValue
-from base.formats import RegMove, RegSpill, RegFill, CopySpecial
-from base.formats import LoadComplex, StoreComplex
+from base.formats import RegMove, RegSwap, RegSpill, RegFill, CopySpecial
+from base.formats import LoadComplex, StoreComplex, LoadScaled, StoreScaled
 from base.formats import StackLoad
+from base.formats import LoadNoOffset, StoreNoOffset, AtomicRmw, AtomicCas
 from .registers import GPR, ABCD, FPR
 from .registers import GPR8, FPR8, FLAG
 from .registers import StackGPR32, StackFPR32
@@ -61,8 +62,9 @@ OPCODE_PREFIX = {
         }
 
 # The table above does not include the REX prefix which goes after the
-# mandatory prefix. VEX/XOP and EVEX prefixes are not yet supported. Encodings
-# using any of these prefixes are represented by separate recipes.
+# mandatory prefix. Three-byte VEX prefixes are generated by `TailRecipe.vex`
+# for the opcodes in the 0F, 0F 38, and 0F 3A maps. XOP and EVEX prefixes are
+# not yet supported.
 #
 # The encoding bits are:
 #
@@ -262,6 +264,40 @@ class TailRecipe:
 
         return (self.recipes[name], bits)
 
+    def vex(self, *ops, **kwargs):
+        # type: (*int, **int) -> Tuple[EncRecipe, int]
+        """
+        Create a VEX encoding recipe and encoding bits for the opcode bytes in
+        `ops`.
+
+        Only the three-byte VEX prefix is supported, and the `0F`, `0F 38`,
+        and `0F 3A` opcode escapes are folded into it. The `emit` code is
+        passed the `vvvv` register as an extra argument to `PUT_OP`.
+        """
+        rrr = kwargs.get('rrr', 0)
+        w = kwargs.get('w', 0)
+        name, bits = decode_ops(ops, rrr, w)
+        assert name in ('Op2', 'Mp2', 'Mp3'), \
+            "VEX encodings need an opcode map."
+        name = 'Vex' + name
+        base_size = 4 + self.base_size
+
+        if name not in self.recipes:
+            recipe = EncRecipe(
+                name + self.name,
+                self.format,
+                base_size,
+                ins=self.ins,
+                outs=self.outs,
+                clobbers_flags=self.clobbers_flags,
+                instp=self.instp,
+                isap=self.isap,
+                emit=replace_put_op(self.emit, name),
+                compute_size=self.compute_size)
+            self.recipes[name] = recipe
+
+        return (self.recipes[name], bits)
+
     @staticmethod
     def check_names(globs):
         # type: (Dict[str, Any]) -> None
@@ -295,11 +331,28 @@ def valid_scale(iform):
 # copies and no-op conversions.
 null = EncRecipe('null', Unary, base_size=0, ins=GPR, outs=0, emit='')
 
+# The FPR version of `null`, for no-op conversions between vector types.
+null_fpr = EncRecipe('null_fpr', Unary, base_size=0, ins=FPR, outs=0, emit='')
+
 debugtrap = EncRecipe('debugtrap', NullAry, base_size=1, ins=(), outs=(),
                       emit='''
                       sink.put1(0xcc);
                       ''')
 
+safepoint = EncRecipe(
+        'safepoint', MultiAry, base_size=0, ins=(), outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.safepoint(func.srclocs[inst], &deopt_locations(func, inst, divert));
+        ''')
+
+stackmap = EncRecipe(
+        'stackmap', MultiAry, base_size=0, ins=(), outs=(),
+        clobbers_flags=False,
+        emit='''
+        sink.stackmap(&stackmap_offsets(func, inst, divert));
+        ''')
+
 # XX opcode, no ModR/M.
 trap = TailRecipe(
         'trap', Trap, base_size=0, ins=(), outs=(),
@@ -361,6 +414,17 @@ fa = TailRecipe(
         modrm_rr(in_reg1, in_reg0, sink);
         ''')
 
+# VEX.NDS XX /r with three FPR ins, used for FMA. The output is tied to the
+# first input, which is multiplied by the second input in `vvvv` and then added
+# to the third input in r/m.
+fma = TailRecipe(
+        'fma', Ternary, base_size=1, ins=(FPR, FPR, FPR), outs=0,
+        requires_prefix=True, clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg2, in_reg0), in_reg1, sink);
+        modrm_rr(in_reg2, in_reg0, sink);
+        ''')
+
 # XX /r with FPR ins and outs. A form with input operands swapped.
 fax = TailRecipe(
         'fax', Binary, base_size=1, ins=(FPR, FPR), outs=1,
@@ -369,6 +433,26 @@ fax = TailRecipe(
         modrm_rr(in_reg0, in_reg1, sink);
         ''')
 
+# VEX.NDS XX /r with FPR ins and outs. The non-destructive AVX form of `fa`,
+# with the first input in `vvvv` and the second input in r/m.
+vfa = TailRecipe(
+        'vfa', Binary, base_size=1, ins=(FPR, FPR), outs=FPR,
+        requires_prefix=True, clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg1, out_reg0), in_reg0, sink);
+        modrm_rr(in_reg1, out_reg0, sink);
+        ''')
+
+# VEX.NDS XX /r with FPR ins and outs. The non-destructive AVX form of `fax`,
+# with the input operands swapped.
+vfax = TailRecipe(
+        'vfax', Binary, base_size=1, ins=(FPR, FPR), outs=FPR,
+        requires_prefix=True, clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(in_reg0, out_reg0), in_reg1, sink);
+        modrm_rr(in_reg0, out_reg0, sink);
+        ''')
+
 # XX /n for a unary operation with extension bits.
 ur = TailRecipe(
         'ur', Unary, base_size=1, ins=GPR, outs=0,
@@ -476,6 +560,15 @@ rmov = TailRecipe(
         modrm_rr(dst, src, sink);
         ''')
 
+# XX /r, for regswap instructions.
+rswap = TailRecipe(
+        'rswap', RegSwap, base_size=1, ins=(GPR, GPR), outs=(),
+        clobbers_flags=False,
+        emit='''
+        PUT_OP(bits, rex2(dst, src), sink);
+        modrm_rr(dst, src, sink);
+        ''')
+
 # XX /r, for regmove instructions (FPR version, RM encoded).
 frmov = TailRecipe(
         'frmov', RegMove, base_size=1, ins=FPR, outs=(),
@@ -567,6 +660,16 @@ pu_id_bool = TailRecipe(
         sink.put4(imm);
         ''')
 
+# XX+rd id nullary with a zero immediate, for null references.
+pu_id_ref = TailRecipe(
+        'pu_id_ref', NullAry, base_size=4, ins=(), outs=GPR,
+        emit='''
+        // The destination register is encoded in the low bits of the opcode.
+        // No ModR/M.
+        PUT_OP(bits | (out_reg0 & 7), rex1(out_reg0), sink);
+        sink.put4(0);
+        ''')
+
 # XX+rd iq unary with 64-bit immediate.
 pu_iq = TailRecipe(
         'pu_iq', UnaryImm, base_size=8, ins=(), outs=GPR,
@@ -607,6 +710,14 @@ popq = TailRecipe(
     PUT_OP(bits | (out_reg0 & 7), rex1(out_reg0), sink);
     ''')
 
+# XX+rd unary operation on a register in place, like BSWAP. No ModR/M.
+bswap = TailRecipe(
+    'bswap', Unary, base_size=0, ins=GPR, outs=0,
+    clobbers_flags=False,
+    emit='''
+    PUT_OP(bits | (in_reg0 & 7), rex1(in_reg0), sink);
+    ''')
+
 # XX /r, for regmove instructions.
 copysp = TailRecipe(
         'copysp', CopySpecial, base_size=1, ins=(), outs=(),
@@ -623,6 +734,15 @@ adjustsp = TailRecipe(
     modrm_rr(RU::rsp.into(), in_reg0, sink);
     ''')
 
+# XX /r, copying the stack pointer into a register.
+get_sp = TailRecipe(
+    'get_sp', NullAry, base_size=1, ins=(), outs=GPR,
+    clobbers_flags=False,
+    emit='''
+    PUT_OP(bits, rex2(out_reg0, RU::rsp.into()), sink);
+    modrm_rr(out_reg0, RU::rsp.into(), sink);
+    ''')
+
 adjustsp_ib = TailRecipe(
     'adjustsp_ib', UnaryImm, base_size=2, ins=(), outs=(),
     instp=IsSignedInt(UnaryImm.imm, 8),
@@ -726,9 +846,10 @@ gvaddr4 = TailRecipe(
         'gvaddr4', UnaryGlobalValue, base_size=4, ins=(), outs=GPR,
         emit='''
         PUT_OP(bits | (out_reg0 & 7), rex1(out_reg0), sink);
+        let gv = &func.global_values[global_value];
         sink.reloc_external(Reloc::Abs4,
-                            &func.global_values[global_value].symbol_name(),
-                            0);
+                            gv.symbol_name(),
+                            gv.symbol_offset());
         sink.put4(0);
         ''')
 
@@ -737,9 +858,10 @@ gvaddr8 = TailRecipe(
         'gvaddr8', UnaryGlobalValue, base_size=8, ins=(), outs=GPR,
         emit='''
         PUT_OP(bits | (out_reg0 & 7), rex1(out_reg0), sink);
+        let gv = &func.global_values[global_value];
         sink.reloc_external(Reloc::Abs8,
-                            &func.global_values[global_value].symbol_name(),
-                            0);
+                            gv.symbol_name(),
+                            gv.symbol_offset());
         sink.put8(0);
         ''')
 
@@ -751,13 +873,15 @@ pcrel_gvaddr8 = TailRecipe(
         modrm_rm(5, out_reg0, sink);
         // The addend adjusts for the difference between the end of the
         // instruction and the beginning of the immediate field.
+        let gv = &func.global_values[global_value];
         sink.reloc_external(Reloc::X86PCRel4,
-                            &func.global_values[global_value].symbol_name(),
-                            -4);
+                            gv.symbol_name(),
+                            gv.symbol_offset() - 4);
         sink.put4(0);
         ''')
 
-# XX+rd iq with Abs8 globalsym relocation.
+# XX+rd iq with GOTPCRel4 globalsym relocation. The GOT entry holds the
+# address of the symbol itself, so the legalizer adds any offset separately.
 got_gvaddr8 = TailRecipe(
         'got_gvaddr8', UnaryGlobalValue, base_size=5, ins=(), outs=GPR,
         emit='''
@@ -771,6 +895,54 @@ got_gvaddr8 = TailRecipe(
         sink.put4(0);
         ''')
 
+# ELF general-dynamic TLS access. The linker recognizes this exact sequence,
+# including the redundant prefixes, so it can relax it to a cheaper model.
+elf_tls_get_addr = EncRecipe(
+        'elf_tls_get_addr', UnaryGlobalValue, base_size=16,
+        ins=(), outs=GPR.rax,
+        emit='''
+        // data16 lea gv@tlsgd(%rip), %rdi
+        sink.put1(0x66);
+        sink.put1(0x48);
+        sink.put1(0x8d);
+        modrm_riprel(RU::rdi.into(), sink);
+        sink.reloc_external(Reloc::ElfX86_64TlsGd,
+                            &func.global_values[global_value].symbol_name(),
+                            -4);
+        sink.put4(0);
+        // data16 data16 call __tls_get_addr@plt
+        sink.put1(0x66);
+        sink.put1(0x66);
+        sink.put1(0x48);
+        sink.put1(0xe8);
+        sink.reloc_external(Reloc::X86CallPLTRel4,
+                            &ExternalName::LibCall(LibCall::ElfTlsGetAddr),
+                            -4);
+        sink.put4(0);
+        ''')
+
+# ELF initial-exec TLS access.
+elf_tls_initial_exec = EncRecipe(
+        'elf_tls_initial_exec', UnaryGlobalValue, base_size=16,
+        ins=(), outs=GPR,
+        emit='''
+        // mov %fs:0, %out
+        sink.put1(0x64);
+        sink.put1(rex2(0, out_reg0) | 0x08);
+        sink.put1(0x8b);
+        modrm_sib(out_reg0, sink);
+        sib_noindex(0b101, sink);
+        sink.put4(0);
+        // add gv@gottpoff(%rip), %out
+        sink.put1(rex2(0, out_reg0) | 0x08);
+        sink.put1(0x03);
+        modrm_riprel(out_reg0, sink);
+        sink.reloc_external(Reloc::ElfX86_64GotTpOff,
+                            &func.global_values[global_value].symbol_name(),
+                            -4);
+        sink.put4(0);
+        ''')
+
 #
 # Stack addresses.
 #
@@ -814,7 +986,7 @@ st = TailRecipe(
         compute_size="size_plus_maybe_sib_or_offset_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -838,7 +1010,7 @@ stWithIndex = TailRecipe(
     compute_size="size_plus_maybe_offset_for_in_reg_1",
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     // The else branch always inserts an SIB byte.
@@ -862,7 +1034,7 @@ st_abcd = TailRecipe(
         compute_size="size_plus_maybe_sib_or_offset_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -887,7 +1059,7 @@ stWithIndex_abcd = TailRecipe(
     compute_size="size_plus_maybe_offset_for_in_reg_1",
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     // The else branch always inserts an SIB byte.
@@ -909,7 +1081,7 @@ fst = TailRecipe(
         compute_size="size_plus_maybe_sib_or_offset_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -931,7 +1103,7 @@ fstWithIndex = TailRecipe(
         compute_size="size_plus_maybe_offset_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
         // The else branch always inserts an SIB byte.
@@ -953,7 +1125,7 @@ stDisp8 = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -975,7 +1147,7 @@ stWithIndexDisp8 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp8(in_reg0, sink);
@@ -994,7 +1166,7 @@ stDisp8_abcd = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -1017,7 +1189,7 @@ stWithIndexDisp8_abcd = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp8(in_reg0, sink);
@@ -1034,7 +1206,7 @@ fstDisp8 = TailRecipe(
         compute_size='size_plus_maybe_sib_for_in_reg_1',
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -1056,7 +1228,7 @@ fstWithIndexDisp8 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp8(in_reg0, sink);
@@ -1072,7 +1244,7 @@ stDisp32 = TailRecipe(
         compute_size='size_plus_maybe_sib_for_in_reg_1',
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -1094,7 +1266,7 @@ stWithIndexDisp32 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp32(in_reg0, sink);
@@ -1103,6 +1275,66 @@ stWithIndexDisp32 = TailRecipe(
     sink.put4(offset as u32);
     ''')
 
+# XX /r store with scaled index and no offset.
+stScaled = TailRecipe(
+    'stScaled', StoreScaled, base_size=2,
+    ins=(GPR, GPR, GPR),
+    outs=(),
+    instp=And(valid_scale(StoreScaled), IsEqual(StoreScaled.offset, 0)),
+    clobbers_flags=False,
+    compute_size="size_plus_maybe_offset_for_in_reg_1",
+    emit='''
+    if !flags.notrap() {
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+    }
+    PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
+    // The else branch always inserts an SIB byte.
+    if needs_offset(in_reg1) {
+        modrm_sib_disp8(in_reg0, sink);
+        sib(imm.trailing_zeros() as u8, in_reg2, in_reg1, sink);
+        sink.put1(0);
+    } else {
+        modrm_sib(in_reg0, sink);
+        sib(imm.trailing_zeros() as u8, in_reg2, in_reg1, sink);
+    }
+    ''')
+
+# XX /r store with scaled index and 8-bit offset.
+stScaledDisp8 = TailRecipe(
+    'stScaledDisp8', StoreScaled, base_size=3,
+    ins=(GPR, GPR, GPR),
+    outs=(),
+    instp=And(valid_scale(StoreScaled), IsSignedInt(StoreScaled.offset, 8)),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+    }
+    PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
+    modrm_sib_disp8(in_reg0, sink);
+    sib(imm.trailing_zeros() as u8, in_reg2, in_reg1, sink);
+    let offset: i32 = offset.into();
+    sink.put1(offset as u8);
+    ''')
+
+# XX /r store with scaled index and 32-bit offset.
+stScaledDisp32 = TailRecipe(
+    'stScaledDisp32', StoreScaled, base_size=6,
+    ins=(GPR, GPR, GPR),
+    outs=(),
+    instp=And(valid_scale(StoreScaled), IsSignedInt(StoreScaled.offset, 32)),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+    }
+    PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
+    modrm_sib_disp32(in_reg0, sink);
+    sib(imm.trailing_zeros() as u8, in_reg2, in_reg1, sink);
+    let offset: i32 = offset.into();
+    sink.put4(offset as u32);
+    ''')
+
 # XX /r register-indirect store with 32-bit offset.
 # Only ABCD allowed for stored value. This is for byte stores with no REX.
 stDisp32_abcd = TailRecipe(
@@ -1112,7 +1344,7 @@ stDisp32_abcd = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_1",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -1135,7 +1367,7 @@ stWithIndexDisp32_abcd = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp32(in_reg0, sink);
@@ -1151,7 +1383,7 @@ fstDisp32 = TailRecipe(
         compute_size='size_plus_maybe_sib_for_in_reg_1',
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
         if needs_sib_byte(in_reg1) {
@@ -1173,7 +1405,7 @@ fstWithIndexDisp32 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg1, in_reg0, in_reg2), sink);
     modrm_sib_disp32(in_reg0, sink);
@@ -1248,7 +1480,7 @@ ld = TailRecipe(
         compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1272,7 +1504,7 @@ ldWithIndex = TailRecipe(
     compute_size="size_plus_maybe_offset_for_in_reg_0",
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     // The else branch always inserts an SIB byte.
@@ -1294,7 +1526,7 @@ fld = TailRecipe(
         compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1318,7 +1550,7 @@ fldWithIndex = TailRecipe(
     compute_size="size_plus_maybe_offset_for_in_reg_0",
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     // The else branch always inserts an SIB byte.
@@ -1340,7 +1572,7 @@ ldDisp8 = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1362,7 +1594,7 @@ ldWithIndexDisp8 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     modrm_sib_disp8(out_reg0, sink);
@@ -1379,7 +1611,7 @@ fldDisp8 = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1401,7 +1633,7 @@ fldWithIndexDisp8 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     modrm_sib_disp8(out_reg0, sink);
@@ -1418,7 +1650,7 @@ ldDisp32 = TailRecipe(
         compute_size='size_plus_maybe_sib_for_in_reg_0',
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1440,7 +1672,7 @@ ldWithIndexDisp32 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     modrm_sib_disp32(out_reg0, sink);
@@ -1449,6 +1681,66 @@ ldWithIndexDisp32 = TailRecipe(
     sink.put4(offset as u32);
     ''')
 
+# XX /r load with scaled index and no offset.
+ldScaled = TailRecipe(
+    'ldScaled', LoadScaled, base_size=2,
+    ins=(GPR, GPR),
+    outs=(GPR),
+    instp=And(valid_scale(LoadScaled), IsEqual(LoadScaled.offset, 0)),
+    clobbers_flags=False,
+    compute_size="size_plus_maybe_offset_for_in_reg_0",
+    emit='''
+    if !flags.notrap() {
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+    }
+    PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
+    // The else branch always inserts an SIB byte.
+    if needs_offset(in_reg0) {
+        modrm_sib_disp8(out_reg0, sink);
+        sib(imm.trailing_zeros() as u8, in_reg1, in_reg0, sink);
+        sink.put1(0);
+    } else {
+        modrm_sib(out_reg0, sink);
+        sib(imm.trailing_zeros() as u8, in_reg1, in_reg0, sink);
+    }
+    ''')
+
+# XX /r load with scaled index and 8-bit offset.
+ldScaledDisp8 = TailRecipe(
+    'ldScaledDisp8', LoadScaled, base_size=3,
+    ins=(GPR, GPR),
+    outs=(GPR),
+    instp=And(valid_scale(LoadScaled), IsSignedInt(LoadScaled.offset, 8)),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+    }
+    PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
+    modrm_sib_disp8(out_reg0, sink);
+    sib(imm.trailing_zeros() as u8, in_reg1, in_reg0, sink);
+    let offset: i32 = offset.into();
+    sink.put1(offset as u8);
+    ''')
+
+# XX /r load with scaled index and 32-bit offset.
+ldScaledDisp32 = TailRecipe(
+    'ldScaledDisp32', LoadScaled, base_size=6,
+    ins=(GPR, GPR),
+    outs=(GPR),
+    instp=And(valid_scale(LoadScaled), IsSignedInt(LoadScaled.offset, 32)),
+    clobbers_flags=False,
+    emit='''
+    if !flags.notrap() {
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+    }
+    PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
+    modrm_sib_disp32(out_reg0, sink);
+    sib(imm.trailing_zeros() as u8, in_reg1, in_reg0, sink);
+    let offset: i32 = offset.into();
+    sink.put4(offset as u32);
+    ''')
+
 # XX /r float load with 32-bit offset.
 fldDisp32 = TailRecipe(
         'fldDisp32', Load, base_size=5, ins=(GPR), outs=(FPR),
@@ -1457,7 +1749,7 @@ fldDisp32 = TailRecipe(
         compute_size="size_plus_maybe_sib_for_in_reg_0",
         emit='''
         if !flags.notrap() {
-            sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
         }
         PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
         if needs_sib_byte(in_reg0) {
@@ -1479,7 +1771,7 @@ fldWithIndexDisp32 = TailRecipe(
     clobbers_flags=False,
     emit='''
     if !flags.notrap() {
-        sink.trap(TrapCode::HeapOutOfBounds, func.srclocs[inst]);
+        sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
     }
     PUT_OP(bits, rex3(in_reg0, out_reg0, in_reg1), sink);
     modrm_sib_disp32(out_reg0, sink);
@@ -1488,6 +1780,236 @@ fldWithIndexDisp32 = TailRecipe(
     sink.put4(offset as u32);
     ''')
 
+#
+# Atomic memory recipes
+#
+
+# XX /r atomic load with no offset.
+# Aligned loads are atomic, and atomic stores are followed by a full fence, so
+# a plain load is sequentially consistent.
+ldAtomic = TailRecipe(
+        'ldAtomic', LoadNoOffset, base_size=1, ins=(GPR), outs=(GPR),
+        clobbers_flags=False,
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        PUT_OP(bits, rex2(in_reg0, out_reg0), sink);
+        if needs_sib_byte(in_reg0) {
+            modrm_sib(out_reg0, sink);
+            sib_noindex(in_reg0, sink);
+        } else if needs_offset(in_reg0) {
+            modrm_disp8(in_reg0, out_reg0, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg0, out_reg0, sink);
+        }
+        ''')
+
+# MFENCE.
+mfence = EncRecipe('mfence', NullAry, base_size=3, ins=(), outs=(),
+                   emit='''
+                   sink.put1(0x0f);
+                   sink.put1(0xae);
+                   sink.put1(0xf0);
+                   ''')
+
+# XX /r atomic store with no offset, followed by MFENCE.
+stAtomic = TailRecipe(
+        'stAtomic', StoreNoOffset, base_size=4, ins=(GPR, GPR), outs=(),
+        clobbers_flags=False,
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_1",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
+        if needs_sib_byte(in_reg1) {
+            modrm_sib(in_reg0, sink);
+            sib_noindex(in_reg1, sink);
+        } else if needs_offset(in_reg1) {
+            modrm_disp8(in_reg1, in_reg0, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg1, in_reg0, sink);
+        }
+        // MFENCE.
+        sink.put1(0x0f);
+        sink.put1(0xae);
+        sink.put1(0xf0);
+        ''')
+
+# XX /r atomic store with no offset, followed by MFENCE.
+# Only ABCD allowed for stored value. This is for byte stores with no REX.
+stAtomic_abcd = TailRecipe(
+        'stAtomic_abcd', StoreNoOffset, base_size=4, ins=(ABCD, GPR), outs=(),
+        when_prefixed=stAtomic,
+        clobbers_flags=False,
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_1",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        PUT_OP(bits, rex2(in_reg1, in_reg0), sink);
+        if needs_sib_byte(in_reg1) {
+            modrm_sib(in_reg0, sink);
+            sib_noindex(in_reg1, sink);
+        } else if needs_offset(in_reg1) {
+            modrm_disp8(in_reg1, in_reg0, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg1, in_reg0, sink);
+        }
+        // MFENCE.
+        sink.put1(0x0f);
+        sink.put1(0xae);
+        sink.put1(0xf0);
+        ''')
+
+# XX /r read-modify-write with no offset. The register operand receives the
+# old memory value. Only used for XCHG which is implicitly locked.
+rmwAtomic = TailRecipe(
+        'rmwAtomic', AtomicRmw, base_size=1, ins=(GPR, GPR), outs=1,
+        clobbers_flags=False,
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
+        if needs_sib_byte(in_reg0) {
+            modrm_sib(in_reg1, sink);
+            sib_noindex(in_reg0, sink);
+        } else if needs_offset(in_reg0) {
+            modrm_disp8(in_reg0, in_reg1, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg0, in_reg1, sink);
+        }
+        ''')
+
+# XX /r read-modify-write with no offset.
+# Only ABCD allowed for the register operand. This is for byte XCHG with no
+# REX.
+rmwAtomic_abcd = TailRecipe(
+        'rmwAtomic_abcd', AtomicRmw, base_size=1, ins=(GPR, ABCD), outs=1,
+        when_prefixed=rmwAtomic,
+        clobbers_flags=False,
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
+        if needs_sib_byte(in_reg0) {
+            modrm_sib(in_reg1, sink);
+            sib_noindex(in_reg0, sink);
+        } else if needs_offset(in_reg0) {
+            modrm_disp8(in_reg0, in_reg1, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg0, in_reg1, sink);
+        }
+        ''')
+
+# LOCK XX /r read-modify-write with no offset. The register operand receives
+# the old memory value.
+lockRmwAtomic = TailRecipe(
+        'lockRmwAtomic', AtomicRmw, base_size=2, ins=(GPR, GPR), outs=1,
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        // LOCK prefix.
+        sink.put1(0xf0);
+        PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
+        if needs_sib_byte(in_reg0) {
+            modrm_sib(in_reg1, sink);
+            sib_noindex(in_reg0, sink);
+        } else if needs_offset(in_reg0) {
+            modrm_disp8(in_reg0, in_reg1, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg0, in_reg1, sink);
+        }
+        ''')
+
+# LOCK XX /r read-modify-write with no offset.
+# Only ABCD allowed for the register operand. This is for byte XADD with no
+# REX.
+lockRmwAtomic_abcd = TailRecipe(
+        'lockRmwAtomic_abcd', AtomicRmw, base_size=2, ins=(GPR, ABCD), outs=1,
+        when_prefixed=lockRmwAtomic,
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        // LOCK prefix.
+        sink.put1(0xf0);
+        PUT_OP(bits, rex2(in_reg0, in_reg1), sink);
+        if needs_sib_byte(in_reg0) {
+            modrm_sib(in_reg1, sink);
+            sib_noindex(in_reg0, sink);
+        } else if needs_offset(in_reg0) {
+            modrm_disp8(in_reg0, in_reg1, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg0, in_reg1, sink);
+        }
+        ''')
+
+# LOCK XX /r compare-and-swap with no offset. The expected value is passed in
+# %rax which receives the old memory value.
+lockCasAtomic = TailRecipe(
+        'lockCasAtomic', AtomicCas, base_size=2,
+        ins=(GPR, GPR.rax, GPR), outs=(GPR.rax),
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        // LOCK prefix.
+        sink.put1(0xf0);
+        PUT_OP(bits, rex2(in_reg0, in_reg2), sink);
+        if needs_sib_byte(in_reg0) {
+            modrm_sib(in_reg2, sink);
+            sib_noindex(in_reg0, sink);
+        } else if needs_offset(in_reg0) {
+            modrm_disp8(in_reg0, in_reg2, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg0, in_reg2, sink);
+        }
+        ''')
+
+# LOCK XX /r compare-and-swap with no offset.
+# Only ABCD allowed for the new value. This is for byte CMPXCHG with no REX.
+lockCasAtomic_abcd = TailRecipe(
+        'lockCasAtomic_abcd', AtomicCas, base_size=2,
+        ins=(GPR, GPR.rax, ABCD), outs=(GPR.rax),
+        when_prefixed=lockCasAtomic,
+        compute_size="size_plus_maybe_sib_or_offset_for_in_reg_0",
+        emit='''
+        if !flags.notrap() {
+            sink.trap(func.memory_trap_code(inst), func.srclocs[inst]);
+        }
+        // LOCK prefix.
+        sink.put1(0xf0);
+        PUT_OP(bits, rex2(in_reg0, in_reg2), sink);
+        if needs_sib_byte(in_reg0) {
+            modrm_sib(in_reg2, sink);
+            sib_noindex(in_reg0, sink);
+        } else if needs_offset(in_reg0) {
+            modrm_disp8(in_reg0, in_reg2, sink);
+            sink.put1(0);
+        } else {
+            modrm_rm(in_reg0, in_reg2, sink);
+        }
+        ''')
+
 # Unary fill with SIB and 32-bit displacement.
 fillSib32 = TailRecipe(
         'fillSib32', Unary, base_size=6, ins=StackGPR32, outs=GPR,
@@ -1565,6 +2087,75 @@ call_plt_id = TailRecipe(
         sink.put4(0);
         ''')
 
+# Patchable calls are padded with nops so the displacement following the
+# 1-byte opcode is 4-byte aligned, and the call instruction is reported as a
+# patchable range. The padding is split around the call to keep a fixed size.
+call_id_patchable = TailRecipe(
+        'call_id_patchable', Call, base_size=7, ins=(), outs=(),
+        emit='''
+        let pad = 3u32.wrapping_sub(sink.offset()) & 3;
+        for _ in 0..pad {
+            sink.put1(0x90);
+        }
+        let start = sink.offset();
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        PUT_OP(bits, BASE_REX, sink);
+        sink.reloc_external(Reloc::X86CallPCRel4,
+                            &func.dfg.ext_funcs[func_ref].name,
+                            -4);
+        sink.put4(0);
+        sink.patchable_range(start);
+        for _ in pad..3 {
+            sink.put1(0x90);
+        }
+        ''')
+
+call_plt_id_patchable = TailRecipe(
+        'call_plt_id_patchable', Call, base_size=7, ins=(), outs=(),
+        emit='''
+        let pad = 3u32.wrapping_sub(sink.offset()) & 3;
+        for _ in 0..pad {
+            sink.put1(0x90);
+        }
+        let start = sink.offset();
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        PUT_OP(bits, BASE_REX, sink);
+        sink.reloc_external(Reloc::X86CallPLTRel4,
+                            &func.dfg.ext_funcs[func_ref].name,
+                            -4);
+        sink.put4(0);
+        sink.patchable_range(start);
+        for _ in pad..3 {
+            sink.put1(0x90);
+        }
+        ''')
+
+# Calls with a landing pad report the offset of the landing pad after the
+# return address.
+try_call_id = TailRecipe(
+        'try_call_id', TryCall, base_size=4, ins=(), outs=(),
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        PUT_OP(bits, BASE_REX, sink);
+        sink.reloc_external(Reloc::X86CallPCRel4,
+                            &func.dfg.ext_funcs[func_ref].name,
+                            -4);
+        sink.put4(0);
+        sink.landing_pad(func.offsets[destination]);
+        ''')
+
+try_call_plt_id = TailRecipe(
+        'try_call_plt_id', TryCall, base_size=4, ins=(), outs=(),
+        emit='''
+        sink.trap(TrapCode::StackOverflow, func.srclocs[inst]);
+        PUT_OP(bits, BASE_REX, sink);
+        sink.reloc_external(Reloc::X86CallPLTRel4,
+                            &func.dfg.ext_funcs[func_ref].name,
+                            -4);
+        sink.put4(0);
+        sink.landing_pad(func.offsets[destination]);
+        ''')
+
 call_r = TailRecipe(
         'call_r', CallIndirect, base_size=1, ins=GPR, outs=(),
         emit='''
@@ -1573,6 +2164,48 @@ call_r = TailRecipe(
         modrm_r_bits(in_reg0, bits, sink);
         ''')
 
+# Save %rsp, %rbp and the resume address in the context at %rsi, then load
+# them from the context at %rdx. The payload is passed in %rdi both ways.
+# %rbp is used as a scratch register once it has been saved.
+stack_switch = EncRecipe(
+        'stack_switch', Ternary, base_size=28,
+        ins=(GPR.rsi, GPR.rdx, GPR.rdi), outs=GPR.rdi,
+        emit='''
+        // mov %rsp, (%rsi)
+        sink.put1(0x48);
+        sink.put1(0x89);
+        sink.put1(0x26);
+        // mov %rbp, 8(%rsi)
+        sink.put1(0x48);
+        sink.put1(0x89);
+        sink.put1(0x6e);
+        sink.put1(0x08);
+        // lea resume(%rip), %rbp
+        sink.put1(0x48);
+        sink.put1(0x8d);
+        sink.put1(0x2d);
+        sink.put4(14);
+        // mov %rbp, 16(%rsi)
+        sink.put1(0x48);
+        sink.put1(0x89);
+        sink.put1(0x6e);
+        sink.put1(0x10);
+        // mov (%rdx), %rsp
+        sink.put1(0x48);
+        sink.put1(0x8b);
+        sink.put1(0x22);
+        // mov 8(%rdx), %rbp
+        sink.put1(0x48);
+        sink.put1(0x8b);
+        sink.put1(0x6a);
+        sink.put1(0x08);
+        // jmp *16(%rdx)
+        sink.put1(0xff);
+        sink.put1(0x62);
+        sink.put1(0x10);
+        // resume:
+        ''')
+
 ret = TailRecipe(
         'ret', MultiAry, base_size=0, ins=(), outs=(),
         emit='''
@@ -1600,6 +2233,26 @@ jmpd = TailRecipe(
         disp4(destination, func, sink);
         ''')
 
+# A patchable jump is padded like a patchable call, so its displacement is
+# 4-byte aligned and the instruction has a fixed size.
+jmpd_patchable = TailRecipe(
+        'jmpd_patchable', Jump, base_size=7, ins=(), outs=(),
+        branch_range=32,
+        clobbers_flags=False,
+        emit='''
+        let pad = 3u32.wrapping_sub(sink.offset()) & 3;
+        for _ in 0..pad {
+            sink.put1(0x90);
+        }
+        let start = sink.offset();
+        PUT_OP(bits, BASE_REX, sink);
+        disp4(destination, func, sink);
+        sink.patchable_range(start);
+        for _ in pad..3 {
+            sink.put1(0x90);
+        }
+        ''')
+
 brib = TailRecipe(
         'brib', BranchInt, base_size=1, ins=FLAG.rflags, outs=(),
         branch_range=8,
@@ -1952,6 +2605,20 @@ icscc = TailRecipe(
         modrm_rr(out_reg0, 0, sink);
         ''')
 
+# Test a register for zero, and set a boolean result with `sete`. The same
+# restrictions as `icscc` apply to the output register.
+is_zero = TailRecipe(
+        'is_zero', Unary, base_size=1 + 3, ins=GPR, outs=ABCD,
+        emit='''
+        // Test instruction.
+        PUT_OP(bits, rex2(in_reg0, in_reg0), sink);
+        modrm_rr(in_reg0, in_reg0, sink);
+        // `sete` instruction, no REX.
+        sink.put1(0x0f);
+        sink.put1(0x94);
+        modrm_rr(out_reg0, 0, sink);
+        ''')
+
 icscc_ib = TailRecipe(
         'icscc_ib', IntCompareImm, base_size=2 + 3, ins=GPR, outs=ABCD,
         instp=IsSignedInt(IntCompareImm.imm, 8),
@@ -14,8 +14,10 @@ ISA.settings = SettingGroup('x86', parent=shared.group)
 # CPUID.01H:ECX
 has_sse3 = BoolSetting("SSE3: CPUID.01H:ECX.SSE3[bit 0]")
 has_ssse3 = BoolSetting("SSSE3: CPUID.01H:ECX.SSSE3[bit 9]")
+has_fma = BoolSetting("FMA: CPUID.01H:ECX.FMA[bit 12]")
 has_sse41 = BoolSetting("SSE4.1: CPUID.01H:ECX.SSE4_1[bit 19]")
 has_sse42 = BoolSetting("SSE4.2: CPUID.01H:ECX.SSE4_2[bit 20]")
+has_movbe = BoolSetting("MOVBE: CPUID.01H:ECX.MOVBE[bit 22]")
 has_popcnt = BoolSetting("POPCNT: CPUID.01H:ECX.POPCNT[bit 23]")
 has_avx = BoolSetting("AVX: CPUID.01H:ECX.AVX[bit 28]")
 
@@ -29,11 +31,15 @@ has_lzcnt = BoolSetting("LZCNT: CPUID.EAX=80000001H:ECX.LZCNT[bit 5]")
 
 # The use_* settings here are used to determine if a feature can be used.
 
+use_ssse3 = And(has_ssse3)
 use_sse41 = And(has_sse41)
 use_sse42 = And(has_sse42, use_sse41)
 use_popcnt = And(has_popcnt, has_sse42)
 use_bmi1 = And(has_bmi1)
 use_lzcnt = And(has_lzcnt)
+use_movbe = And(has_movbe)
+use_fma = And(has_fma, has_avx)
+use_avx = And(has_avx)
 
 # Presets corresponding to x86 CPUs.
 
@@ -41,14 +47,15 @@ baseline = Preset()
 
 nehalem = Preset(
         has_sse3, has_ssse3, has_sse41, has_sse42, has_popcnt)
-haswell = Preset(nehalem, has_bmi1, has_bmi2, has_lzcnt)
+haswell = Preset(
+        nehalem, has_movbe, has_avx, has_fma, has_bmi1, has_bmi2, has_lzcnt)
 broadwell = Preset(haswell)
 skylake = Preset(broadwell)
 cannonlake = Preset(skylake)
 icelake = Preset(cannonlake)
 
 znver1 = Preset(
-        has_sse3, has_ssse3, has_sse41, has_sse42, has_popcnt,
-        has_bmi1, has_bmi2, has_lzcnt)
+        has_sse3, has_ssse3, has_sse41, has_sse42, has_movbe, has_popcnt,
+        has_avx, has_fma, has_bmi1, has_bmi2, has_lzcnt)
 
 ISA.settings.close(globals())
@@ -11,6 +11,9 @@ pub mod types;
 /// A macro that converts boolean settings into predicates to look more natural.
 #[macro_export]
 macro_rules! predicate {
+    (!($($a:tt)*)) => {
+        PredicateNode::Not(Box::new(predicate!($($a)*)))
+    };
     ($a:ident && $($b:tt)*) => {
         PredicateNode::And(Box::new($a.into()), Box::new(predicate!($($b)*)))
     };
@@ -170,6 +170,7 @@ pub enum PredicateNode {
     OwnedBool(BoolSettingIndex),
     SharedBool(&'static str, &'static str),
     And(Box<PredicateNode>, Box<PredicateNode>),
+    Not(Box<PredicateNode>),
 }
 
 impl Into<PredicateNode> for BoolSettingIndex {
@@ -198,6 +199,7 @@ impl PredicateNode {
             PredicateNode::And(ref lhs, ref rhs) => {
                 format!("{} && {}", lhs.render(group), rhs.render(group))
             }
+            PredicateNode::Not(ref node) => format!("!({})", node.render(group)),
         }
     }
 }
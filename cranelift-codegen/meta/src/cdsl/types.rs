@@ -11,13 +11,17 @@ use crate::shared::types as shared_types;
 //
 // 0: Void
 // 0x01-0x6f: Special types
-// 0x70-0x7f: Lane types
+// 0x70-0x7d: Lane types
+// 0x7e-0x7f: Reference types
 // 0x80-0xff: Vector types
 //
 // Vector types are encoded with the lane type in the low 4 bits and log2(lanes)
 // in the high 4 bits, giving a range of 2-256 lanes.
 static LANE_BASE: u8 = 0x70;
 
+// Reference types are numbered after the lane types, but can't form vectors.
+static REFERENCE_BASE: u8 = 0x7e;
+
 // Rust name prefix used for the `rust_name` method.
 static _RUST_NAME_PREFIX: &'static str = "ir::types::";
 
@@ -31,6 +35,7 @@ static _RUST_NAME_PREFIX: &'static str = "ir::types::";
 pub enum ValueType {
     BV(BVType),
     Lane(LaneType),
+    Reference(ReferenceType),
     Special(SpecialType),
     Vector(VectorType),
 }
@@ -41,6 +46,11 @@ impl ValueType {
         LaneTypeIterator::new()
     }
 
+    /// Iterate through all of the reference types.
+    pub fn all_reference_types() -> ReferenceTypeIterator {
+        ReferenceTypeIterator::new()
+    }
+
     /// Iterate through all of the special types (neither lanes nor vectors).
     pub fn all_special_types() -> SpecialTypeIterator {
         SpecialTypeIterator::new()
@@ -51,6 +61,7 @@ impl ValueType {
         match *self {
             ValueType::BV(ref b) => b.doc(),
             ValueType::Lane(l) => l.doc(),
+            ValueType::Reference(r) => r.doc(),
             ValueType::Special(s) => s.doc(),
             ValueType::Vector(ref v) => v.doc(),
         }
@@ -61,6 +72,7 @@ impl ValueType {
         match *self {
             ValueType::BV(ref b) => b.lane_bits(),
             ValueType::Lane(l) => l.lane_bits(),
+            ValueType::Reference(r) => r.lane_bits(),
             ValueType::Special(s) => s.lane_bits(),
             ValueType::Vector(ref v) => v.lane_bits(),
         }
@@ -84,6 +96,7 @@ impl ValueType {
         match *self {
             ValueType::BV(_) => None,
             ValueType::Lane(l) => Some(l.number()),
+            ValueType::Reference(r) => Some(r.number()),
             ValueType::Special(s) => Some(s.number()),
             ValueType::Vector(ref v) => Some(v.number()),
         }
@@ -112,6 +125,7 @@ impl fmt::Display for ValueType {
         match *self {
             ValueType::BV(ref b) => b.fmt(f),
             ValueType::Lane(l) => l.fmt(f),
+            ValueType::Reference(r) => r.fmt(f),
             ValueType::Special(s) => s.fmt(f),
             ValueType::Vector(ref v) => v.fmt(f),
         }
@@ -132,6 +146,13 @@ impl From<LaneType> for ValueType {
     }
 }
 
+/// Create a ValueType from a given reference type.
+impl From<ReferenceType> for ValueType {
+    fn from(reference: ReferenceType) -> Self {
+        ValueType::Reference(reference)
+    }
+}
+
 /// Create a ValueType from a given special type.
 impl From<SpecialType> for ValueType {
     fn from(spec: SpecialType) -> Self {
@@ -197,12 +218,14 @@ impl LaneType {
                 LaneType::BoolType(shared_types::Bool::B16) => 2,
                 LaneType::BoolType(shared_types::Bool::B32) => 3,
                 LaneType::BoolType(shared_types::Bool::B64) => 4,
-                LaneType::IntType(shared_types::Int::I8) => 5,
-                LaneType::IntType(shared_types::Int::I16) => 6,
-                LaneType::IntType(shared_types::Int::I32) => 7,
-                LaneType::IntType(shared_types::Int::I64) => 8,
-                LaneType::FloatType(shared_types::Float::F32) => 9,
-                LaneType::FloatType(shared_types::Float::F64) => 10,
+                LaneType::BoolType(shared_types::Bool::B128) => 5,
+                LaneType::IntType(shared_types::Int::I8) => 6,
+                LaneType::IntType(shared_types::Int::I16) => 7,
+                LaneType::IntType(shared_types::Int::I32) => 8,
+                LaneType::IntType(shared_types::Int::I64) => 9,
+                LaneType::IntType(shared_types::Int::I128) => 10,
+                LaneType::FloatType(shared_types::Float::F32) => 11,
+                LaneType::FloatType(shared_types::Float::F64) => 12,
             }
     }
 }
@@ -286,6 +309,77 @@ impl Iterator for LaneTypeIterator {
     }
 }
 
+/// A concrete reference type, an opaque pointer to an object managed by a garbage collector.
+///
+/// Reference types can't be used to form vectors.
+#[derive(Clone, Copy)]
+pub struct ReferenceType(shared_types::Reference);
+
+impl ReferenceType {
+    /// Return a string containing the documentation comment for this reference type.
+    pub fn doc(self) -> String {
+        format!(
+            "An opaque reference type with {} bits, pointing to an object managed
+            by a garbage collector.",
+            self.lane_bits()
+        )
+    }
+
+    /// Return the number of bits in a lane.
+    pub fn lane_bits(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// Find the unique number associated with this reference type.
+    pub fn number(self) -> u8 {
+        REFERENCE_BASE
+            + match self.0 {
+                shared_types::Reference::R32 => 0,
+                shared_types::Reference::R64 => 1,
+            }
+    }
+}
+
+impl fmt::Display for ReferenceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "r{}", self.lane_bits())
+    }
+}
+
+impl fmt::Debug for ReferenceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ReferenceType(bits={})", self.lane_bits())
+    }
+}
+
+/// Create a ReferenceType from a given reference variant.
+impl From<shared_types::Reference> for ReferenceType {
+    fn from(r: shared_types::Reference) -> Self {
+        ReferenceType(r)
+    }
+}
+
+/// An iterator for different reference types.
+pub struct ReferenceTypeIterator {
+    reference_iter: shared_types::ReferenceIterator,
+}
+
+impl ReferenceTypeIterator {
+    /// Create a new reference type iterator.
+    fn new() -> Self {
+        Self {
+            reference_iter: shared_types::ReferenceIterator::new(),
+        }
+    }
+}
+
+impl Iterator for ReferenceTypeIterator {
+    type Item = ReferenceType;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reference_iter.next().map(ReferenceType::from)
+    }
+}
+
 /// A concrete SIMD vector type.
 ///
 /// A vector type has a lane type which is an instance of `LaneType`,
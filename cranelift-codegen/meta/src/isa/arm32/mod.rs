@@ -3,7 +3,12 @@ use crate::cdsl::regs::{IsaRegs, IsaRegsBuilder, RegBankBuilder, RegClassBuilder
 use crate::cdsl::settings::{SettingGroup, SettingGroupBuilder};
 
 fn define_settings(_shared: &SettingGroup) -> SettingGroup {
-    let setting = SettingGroupBuilder::new("arm32");
+    let mut setting = SettingGroupBuilder::new("arm32");
+    setting.add_bool(
+        "thumb2",
+        "Generate Thumb-2 (T32) code instead of A32 code. Thumb-only targets like Cortex-M always generate T32 code.",
+        false,
+    );
     setting.finish()
 }
 
@@ -36,6 +41,9 @@ fn define_regs() -> IsaRegs {
     regs.add_class(builder);
 
     let builder = RegClassBuilder::new_toplevel("GPR", int_regs);
+    let gpr = regs.add_class(builder);
+
+    let builder = RegClassBuilder::subclass_of("GPR8", gpr, 0, 8);
     regs.add_class(builder);
 
     let builder = RegClassBuilder::new_toplevel("FLAG", flag_reg);
@@ -3,7 +3,12 @@ use crate::cdsl::regs::{IsaRegs, IsaRegsBuilder, RegBankBuilder, RegClassBuilder
 use crate::cdsl::settings::{SettingGroup, SettingGroupBuilder};
 
 fn define_settings(_shared: &SettingGroup) -> SettingGroup {
-    let setting = SettingGroupBuilder::new("arm64");
+    let mut setting = SettingGroupBuilder::new("arm64");
+    setting.add_bool(
+        "has_lse",
+        "LSE: Large System Extensions atomics (ARMv8.1)",
+        false,
+    );
     setting.finish()
 }
 
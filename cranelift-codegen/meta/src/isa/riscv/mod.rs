@@ -25,6 +25,11 @@ fn define_settings(shared: &SettingGroup) -> SettingGroup {
         "CPU supports the 'D' extension (double)",
         false,
     );
+    setting.add_bool(
+        "supports_c",
+        "CPU supports the 'C' extension (compressed)",
+        false,
+    );
 
     let enable_m = setting.add_bool(
         "enable_m",
@@ -46,6 +51,8 @@ fn define_settings(shared: &SettingGroup) -> SettingGroup {
     setting.add_predicate("use_a", predicate!(supports_a && shared_enable_atomics));
     setting.add_predicate("use_f", predicate!(supports_f && shared_enable_float));
     setting.add_predicate("use_d", predicate!(supports_d && shared_enable_float));
+    setting.add_predicate("soft_f", predicate!(!(supports_f && shared_enable_float)));
+    setting.add_predicate("soft_d", predicate!(!(supports_d && shared_enable_float)));
     setting.add_predicate(
         "full_float",
         predicate!(shared_enable_simd && supports_f && supports_d),
@@ -68,11 +75,14 @@ fn define_registers() -> IsaRegs {
     let float_regs = regs.add_bank(builder);
 
     let builder = RegClassBuilder::new_toplevel("GPR", int_regs);
-    regs.add_class(builder);
+    let gpr = regs.add_class(builder);
 
     let builder = RegClassBuilder::new_toplevel("FPR", float_regs);
     regs.add_class(builder);
 
+    let builder = RegClassBuilder::subclass_of("GPRC", gpr, 8, 16);
+    regs.add_class(builder);
+
     regs.finish()
 }
 
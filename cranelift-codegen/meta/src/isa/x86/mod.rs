@@ -8,10 +8,12 @@ fn define_settings(_shared: &SettingGroup) -> SettingGroup {
     // CPUID.01H:ECX
     let has_sse3 = settings.add_bool("has_sse3", "SSE3: CPUID.01H:ECX.SSE3[bit 0]", false);
     let has_ssse3 = settings.add_bool("has_ssse3", "SSSE3: CPUID.01H:ECX.SSSE3[bit 9]", false);
+    let has_fma = settings.add_bool("has_fma", "FMA: CPUID.01H:ECX.FMA[bit 12]", false);
     let has_sse41 = settings.add_bool("has_sse41", "SSE4.1: CPUID.01H:ECX.SSE4_1[bit 19]", false);
     let has_sse42 = settings.add_bool("has_sse42", "SSE4.2: CPUID.01H:ECX.SSE4_2[bit 20]", false);
+    let has_movbe = settings.add_bool("has_movbe", "MOVBE: CPUID.01H:ECX.MOVBE[bit 22]", false);
     let has_popcnt = settings.add_bool("has_popcnt", "POPCNT: CPUID.01H:ECX.POPCNT[bit 23]", false);
-    settings.add_bool("has_avx", "AVX: CPUID.01H:ECX.AVX[bit 28]", false);
+    let has_avx = settings.add_bool("has_avx", "AVX: CPUID.01H:ECX.AVX[bit 28]", false);
 
     // CPUID.(EAX=07H, ECX=0H):EBX
     let has_bmi1 = settings.add_bool(
@@ -37,6 +39,9 @@ fn define_settings(_shared: &SettingGroup) -> SettingGroup {
     settings.add_predicate("use_popcnt", predicate!(has_popcnt && has_sse42));
     settings.add_predicate("use_bmi1", predicate!(has_bmi1));
     settings.add_predicate("use_lznct", predicate!(has_lzcnt));
+    settings.add_predicate("use_movbe", predicate!(has_movbe));
+    settings.add_predicate("use_fma", predicate!(has_fma && has_avx));
+    settings.add_predicate("use_avx", predicate!(has_avx));
 
     settings.add_preset("baseline", preset!());
     let nehalem = settings.add_preset(
@@ -45,7 +50,7 @@ fn define_settings(_shared: &SettingGroup) -> SettingGroup {
     );
     let haswell = settings.add_preset(
         "haswell",
-        preset!(nehalem && has_bmi1 && has_bmi2 && has_lzcnt),
+        preset!(nehalem && has_movbe && has_avx && has_fma && has_bmi1 && has_bmi2 && has_lzcnt),
     );
     let broadwell = settings.add_preset("broadwell", preset!(haswell));
     let skylake = settings.add_preset("skylake", preset!(broadwell));
@@ -58,7 +63,10 @@ fn define_settings(_shared: &SettingGroup) -> SettingGroup {
                 && has_ssse3
                 && has_sse41
                 && has_sse42
+                && has_movbe
                 && has_popcnt
+                && has_avx
+                && has_fma
                 && has_bmi1
                 && has_bmi2
                 && has_lzcnt
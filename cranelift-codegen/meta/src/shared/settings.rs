@@ -8,11 +8,15 @@ pub fn generate() -> SettingGroup {
         r#"
         Optimization level:
 
-        - default: Very profitable optimizations enabled, none slow.
-        - best: Enable all optimizations
-        - fastest: Optimize for compile time by disabling most optimizations.
+        - speed: Generate fast code with the IR optimizations and inlining
+          thresholds which pay off for most functions. This is the default.
+        - none: Minimize compile time by disabling most optimizations. This is
+          meant for baseline JIT compilation.
+        - speed_and_size: Like "speed", but also run loop invariant code
+          motion and global value numbering, and perform transformations
+          aimed at reducing code size.
         "#,
-        vec!["default", "best", "fastest"],
+        vec!["speed", "none", "speed_and_size"],
     );
 
     settings.add_bool(
@@ -35,6 +39,20 @@ pub fn generate() -> SettingGroup {
         false,
     );
 
+    settings.add_enum(
+        "tls_model",
+        r#"
+        Thread-local storage model:
+
+        - none: Thread-local global values are not supported.
+        - elf_gd: ELF general-dynamic model. The address is computed by a
+          call to ``__tls_get_addr``.
+        - elf_ie: ELF initial-exec model. The offset from the thread pointer
+          is loaded from the GOT.
+        "#,
+        vec!["none", "elf_gd", "elf_ie"],
+    );
+
     settings.add_bool(
         "colocated_libcalls",
         r#"
@@ -62,6 +80,34 @@ pub fn generate() -> SettingGroup {
         false,
     );
 
+    settings.add_bool(
+        "elide_null_checks",
+        r#"
+            Replace explicit null pointer checks with faulting memory accesses.
+
+            A ``trapz`` instruction followed by a ``notrap`` load or store from the
+            same pointer near address 0 is removed, and its trap code is reported
+            when the memory access faults instead.
+
+            This requires the embedder to handle the resulting faults as traps,
+            like it does for heap accesses protected by guard pages.
+            "#,
+        false,
+    );
+
+    settings.add_bool(
+        "out_of_line_traps",
+        r#"
+            Emit the traps of conditional trap instructions out of line.
+
+            Each conditional trap becomes a branch to a trap instruction at the
+            end of the function, so the hot path only contains a branch which is
+            not taken. Without this setting, the hot path branches over an inline
+            trap instruction.
+            "#,
+        false,
+    );
+
     settings.add_bool(
         "enable_float",
         r#"
@@ -84,6 +130,19 @@ pub fn generate() -> SettingGroup {
         false,
     );
 
+    settings.add_bool(
+        "enable_strict_fp",
+        r#"
+            Preserve the exact rounding of floating point operations.
+
+            When a fused multiply-add can't be encoded for the target, it is
+            expanded into a library call that rounds only once. Disabling this
+            setting expands it into a multiplication and an addition instead,
+            which is faster but rounds twice.
+            "#,
+        true,
+    );
+
     settings.add_bool("enable_simd", "Enable the use of SIMD instructions.", true);
 
     settings.add_bool(
@@ -119,6 +178,76 @@ pub fn generate() -> SettingGroup {
         false,
     );
 
+    // Code patching options.
+
+    settings.add_bool(
+        "patchable_calls",
+        r#"
+            Emit direct calls so they can be patched after compilation.
+
+            Each direct call is padded with nops so its 4-byte displacement is naturally aligned
+            and can be rewritten with a single atomic store. The range of the call instruction is
+            reported to the code sink, so a JIT can later re-point the call, for example to update
+            an inline cache, without recompiling the function.
+            "#,
+        false,
+    );
+    settings.add_bool(
+        "patchable_branches",
+        r#"
+            Emit unconditional jumps so they can be patched after compilation.
+
+            Each `jump` uses the longest branch encoding, padded with nops so its 4-byte
+            displacement is naturally aligned, and its range is reported to the code sink like a
+            patchable call. Jumps to the next EBB in the layout are kept instead of becoming
+            fall-throughs, so a JIT can re-point any jump without recompiling the function.
+            Conditional branches are not affected.
+            "#,
+        false,
+    );
+
+    // Code layout options.
+
+    settings.add_num(
+        "function_alignment_log2",
+        r#"
+            The log2 of the alignment of the start of the function's code.
+
+            The embedder must place the code at an address with this alignment.
+            Code placement inside the function, like loop header alignment, can't
+            use a larger alignment.
+
+            The default is 4, which translates to 16 bytes.
+            "#,
+        4,
+    );
+
+    settings.add_num(
+        "loop_alignment_log2",
+        r#"
+            The log2 of the alignment of loop headers.
+
+            EBBs which are the target of a backward branch are padded with nops
+            so they start at an offset with this alignment, limited by the
+            function alignment. The default is 0, which disables the padding.
+            "#,
+        0,
+    );
+
+    settings.add_enum(
+        "nop_style",
+        r#"
+        The instructions used for padding:
+
+        - multi_byte: Use the longest available nop instructions, so the
+          padding executes as few instructions as possible.
+        - single_byte: Only use the shortest nop instruction.
+
+        ISAs with a single nop instruction ignore this setting.
+        "#,
+        vec!["multi_byte", "single_byte"],
+    );
+
     // Stack probing options.
 
     settings.add_bool(
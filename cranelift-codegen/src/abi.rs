@@ -159,10 +159,15 @@ pub fn legalize_abi_value(have: Type, arg: &AbiParam) -> ValueConversion {
         }
         // We have the same number of bits as the argument.
         Ordering::Equal => {
-            // This must be an integer vector that is split and then extended.
             debug_assert!(arg.value_type.is_int());
-            debug_assert!(have.is_vector(), "expected vector type, got {}", have);
-            ValueConversion::VectorSplit
+            if have.is_float() {
+                // A float passed in an integer register, as in a soft-float ABI.
+                ValueConversion::IntBits
+            } else {
+                // This must be an integer vector that is split and then extended.
+                debug_assert!(have.is_vector(), "expected vector type, got {}", have);
+                ValueConversion::VectorSplit
+            }
         }
         // We have more bits than the argument.
         Ordering::Greater => {
@@ -218,5 +223,11 @@ mod tests {
             legalize_abi_value(types::F64, &arg),
             ValueConversion::IntBits
         );
+
+        // 32-bit float is passed as an integer of the same size.
+        assert_eq!(
+            legalize_abi_value(types::F32, &arg),
+            ValueConversion::IntBits
+        );
     }
 }
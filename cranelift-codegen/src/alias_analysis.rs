@@ -0,0 +1,240 @@
+//! Alias analysis.
+//!
+//! This module answers conservative questions about memory accesses: can two instructions access
+//! the same memory, and can a load observe the value written by a store? Addresses are compared
+//! by splitting them into a base and a constant offset. The base is either a stack slot or an
+//! SSA value, looking through `iadd_imm` and `stack_addr` instructions.
+//!
+//! Two accesses don't alias when they use the same base with disjoint byte ranges, or when they
+//! access different stack slots. Loads with the `readonly` flag never observe stores. Any other
+//! pair of accesses, and any instruction whose memory accesses are not known, like calls and
+//! atomic operations, may alias.
+
+use crate::ir::dfg::ValueDef;
+use crate::ir::{Function, Inst, InstructionData, Opcode, StackSlot, Value};
+use std::vec::Vec;
+
+/// How many `iadd_imm` instructions to look through when computing the base of an address.
+const MAX_BASE_DEPTH: usize = 8;
+
+/// The base of an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Base {
+    /// An address in a stack slot.
+    Stack(StackSlot),
+    /// An address computed from an SSA value.
+    Value(Value),
+}
+
+/// The bytes accessed by a load or store instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// The base of the address.
+    pub base: Base,
+    /// The offset of the first byte from the base.
+    pub offset: i64,
+    /// The number of bytes accessed.
+    pub size: u32,
+}
+
+impl MemoryAccess {
+    /// Get the memory accessed by `inst`, if it is a simple load or store.
+    pub fn of(func: &Function, inst: Inst) -> Option<Self> {
+        let dfg = &func.dfg;
+        let (addr, offset): (Base, i64) = match dfg[inst] {
+            InstructionData::Load { arg, offset, .. } => (Base::Value(arg), offset.into()),
+            InstructionData::Store { args, offset, .. } => (Base::Value(args[1]), offset.into()),
+            InstructionData::StackLoad {
+                opcode: Opcode::StackLoad,
+                stack_slot,
+                offset,
+            }
+            | InstructionData::StackStore {
+                stack_slot, offset, ..
+            } => (Base::Stack(stack_slot), offset.into()),
+            _ => return None,
+        };
+        let size = match dfg[inst].opcode() {
+            Opcode::Uload8 | Opcode::Sload8 | Opcode::Istore8 => 1,
+            Opcode::Uload16 | Opcode::Sload16 | Opcode::Istore16 => 2,
+            Opcode::Uload32 | Opcode::Sload32 | Opcode::Istore32 => 4,
+            Opcode::Store | Opcode::StackStore => dfg.value_type(dfg.inst_args(inst)[0]).bytes(),
+            _ => dfg.value_type(dfg.first_result(inst)).bytes(),
+        };
+        let (base, base_offset) = match addr {
+            Base::Value(value) => resolve_base(func, value),
+            stack => (stack, 0),
+        };
+        Some(Self {
+            base,
+            offset: base_offset.checked_add(offset)?,
+            size,
+        })
+    }
+
+    /// Can these two accesses overlap?
+    pub fn overlaps(&self, other: &Self) -> bool {
+        match (self.base, other.base) {
+            (Base::Stack(a), Base::Stack(b)) if a != b => false,
+            (a, b) if a == b => {
+                let end = self.offset.checked_add(i64::from(self.size));
+                let other_end = other.offset.checked_add(i64::from(other.size));
+                match (end, other_end) {
+                    (Some(end), Some(other_end)) => self.offset < other_end && other.offset < end,
+                    _ => true,
+                }
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Split the address `value` into a base and a constant offset.
+fn resolve_base(func: &Function, value: Value) -> (Base, i64) {
+    let mut value = func.dfg.resolve_aliases(value);
+    let mut offset: i64 = 0;
+    for _ in 0..MAX_BASE_DEPTH {
+        let inst = match func.dfg.value_def(value) {
+            ValueDef::Result(inst, _) => inst,
+            ValueDef::Param(..) => break,
+        };
+        match func.dfg[inst] {
+            InstructionData::BinaryImm {
+                opcode: Opcode::IaddImm,
+                arg,
+                imm,
+            } => match offset.checked_add(imm.into()) {
+                Some(sum) => {
+                    offset = sum;
+                    value = func.dfg.resolve_aliases(arg);
+                }
+                None => break,
+            },
+            InstructionData::StackLoad {
+                opcode: Opcode::StackAddr,
+                stack_slot,
+                offset: slot_offset,
+            } => {
+                let slot_offset: i64 = slot_offset.into();
+                if let Some(sum) = offset.checked_add(slot_offset) {
+                    return (Base::Stack(stack_slot), sum);
+                }
+                break;
+            }
+            _ => break,
+        }
+    }
+    (Base::Value(value), offset)
+}
+
+/// Can `inst` write to memory?
+///
+/// This includes calls and instructions with other side effects, which may write to any memory.
+pub fn writes_memory(func: &Function, inst: Inst) -> bool {
+    let opcode = func.dfg[inst].opcode();
+    opcode.can_store() || opcode.is_call() || opcode.other_side_effects()
+}
+
+/// Can the two instructions `a` and `b` access the same memory?
+pub fn may_alias(func: &Function, a: Inst, b: Inst) -> bool {
+    match (MemoryAccess::of(func, a), MemoryAccess::of(func, b)) {
+        (Some(a), Some(b)) => a.overlaps(&b),
+        _ => true,
+    }
+}
+
+/// Can the load `load` observe the memory written by `store`?
+pub fn can_observe(func: &Function, load: Inst, store: Inst) -> bool {
+    if !writes_memory(func, store) {
+        return false;
+    }
+    match func.dfg[load] {
+        InstructionData::Load { flags, .. } | InstructionData::LoadComplex { flags, .. }
+            if flags.readonly() =>
+        {
+            false
+        }
+        _ => may_alias(func, load, store),
+    }
+}
+
+/// Get the value read by `load` if it is the value written by `store`.
+///
+/// This is the case when both instructions access the same bytes with the same type, and `store`
+/// is the last instruction which may write to memory before `load`.
+pub fn stored_value(func: &Function, load: Inst, store: Inst) -> Option<Value> {
+    let dfg = &func.dfg;
+    let same_flags = match (&dfg[load], &dfg[store]) {
+        (
+            InstructionData::Load {
+                opcode: Opcode::Load,
+                flags: load_flags,
+                ..
+            },
+            InstructionData::Store {
+                opcode: Opcode::Store,
+                flags: store_flags,
+                ..
+            },
+        ) => load_flags.explicit_endianness() == store_flags.explicit_endianness(),
+        (
+            InstructionData::StackLoad {
+                opcode: Opcode::StackLoad,
+                ..
+            },
+            InstructionData::StackStore {
+                opcode: Opcode::StackStore,
+                ..
+            },
+        ) => true,
+        _ => false,
+    };
+    let value = dfg.inst_args(store)[0];
+    if same_flags
+        && dfg.value_type(value) == dfg.value_type(dfg.first_result(load))
+        && MemoryAccess::of(func, load) == MemoryAccess::of(func, store)
+    {
+        Some(value)
+    } else {
+        None
+    }
+}
+
+/// Tracking of the instructions writing to memory in an EBB.
+///
+/// The instructions of an EBB are given to `update` in order, and `last_store` finds the last
+/// one which may write to the memory read by a load.
+pub struct LastStores {
+    stores: Vec<Inst>,
+}
+
+impl LastStores {
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Self { stores: Vec::new() }
+    }
+
+    /// Forget the instructions seen so far, at the start of a new EBB.
+    pub fn clear(&mut self) {
+        self.stores.clear();
+    }
+
+    /// Record `inst` if it writes to memory.
+    pub fn update(&mut self, func: &Function, inst: Inst) {
+        if writes_memory(func, inst) {
+            self.stores.push(inst);
+        }
+    }
+
+    /// Get the last instruction seen in the current EBB which may write to the memory read by
+    /// `load`.
+    ///
+    /// Returns `None` if the load observes the memory as it was at the start of the EBB.
+    pub fn last_store(&self, func: &Function, load: Inst) -> Option<Inst> {
+        self.stores
+            .iter()
+            .rev()
+            .find(|&&store| can_observe(func, load, store))
+            .cloned()
+    }
+}
@@ -14,8 +14,8 @@
 //! relocations to a `RelocSink` trait object. Relocations are less frequent than the
 //! `CodeSink::put*` methods, so the performance impact of the virtual callbacks is less severe.
 
-use super::{Addend, CodeOffset, CodeSink, Reloc};
-use crate::ir::{ExternalName, JumpTable, SourceLoc, TrapCode};
+use super::{Addend, CodeOffset, CodeSink, DeoptLocation, Reloc};
+use crate::ir::{ExternalName, JumpTable, SourceLoc, StackOffset, TrapCode};
 use core::ptr::write_unaligned;
 
 /// A `CodeSink` that writes binary machine code directly into memory.
@@ -25,7 +25,11 @@ use core::ptr::write_unaligned;
 /// sure to allocate enough memory for the whole function. The number of bytes required is returned
 /// by the `Context::compile()` function.
 ///
-/// Any relocations in the function are forwarded to the `RelocSink` trait object.
+/// Any relocations in the function are forwarded to the `RelocSink` trait object, the landing
+/// pads of calls are forwarded to the optional `ExceptionSink` trait object, safepoints are
+/// forwarded to the optional `DeoptSink` trait object, stack maps are forwarded to the optional
+/// `StackmapSink` trait object, and patchable ranges are forwarded to the optional `PatchSink`
+/// trait object.
 ///
 /// Note that `MemoryCodeSink` writes multi-byte values in the native byte order of the host. This
 /// is not the right thing to do for cross compilation.
@@ -36,6 +40,10 @@ pub struct MemoryCodeSink<'a> {
     pub code_size: isize,
     relocs: &'a mut RelocSink,
     traps: &'a mut TrapSink,
+    exceptions: Option<&'a mut ExceptionSink>,
+    deopts: Option<&'a mut DeoptSink>,
+    stackmaps: Option<&'a mut StackmapSink>,
+    patches: Option<&'a mut PatchSink>,
 }
 
 impl<'a> MemoryCodeSink<'a> {
@@ -50,6 +58,73 @@ impl<'a> MemoryCodeSink<'a> {
             code_size: 0,
             relocs,
             traps,
+            exceptions: None,
+            deopts: None,
+            stackmaps: None,
+            patches: None,
+        }
+    }
+
+    /// Create a new memory code sink that also reports the landing pads of calls to
+    /// `exceptions`.
+    ///
+    /// This function is unsafe for the same reasons as `new`.
+    pub unsafe fn with_exceptions(
+        data: *mut u8,
+        relocs: &'a mut RelocSink,
+        traps: &'a mut TrapSink,
+        exceptions: &'a mut ExceptionSink,
+    ) -> Self {
+        Self {
+            exceptions: Some(exceptions),
+            ..Self::new(data, relocs, traps)
+        }
+    }
+
+    /// Create a new memory code sink that also reports the deoptimization maps of safepoints to
+    /// `deopts`.
+    ///
+    /// This function is unsafe for the same reasons as `new`.
+    pub unsafe fn with_deopts(
+        data: *mut u8,
+        relocs: &'a mut RelocSink,
+        traps: &'a mut TrapSink,
+        deopts: &'a mut DeoptSink,
+    ) -> Self {
+        Self {
+            deopts: Some(deopts),
+            ..Self::new(data, relocs, traps)
+        }
+    }
+
+    /// Create a new memory code sink that also reports the stack maps of calls to `stackmaps`.
+    ///
+    /// This function is unsafe for the same reasons as `new`.
+    pub unsafe fn with_stackmaps(
+        data: *mut u8,
+        relocs: &'a mut RelocSink,
+        traps: &'a mut TrapSink,
+        stackmaps: &'a mut StackmapSink,
+    ) -> Self {
+        Self {
+            stackmaps: Some(stackmaps),
+            ..Self::new(data, relocs, traps)
+        }
+    }
+
+    /// Create a new memory code sink that also reports the patchable ranges of the code to
+    /// `patches`.
+    ///
+    /// This function is unsafe for the same reasons as `new`.
+    pub unsafe fn with_patches(
+        data: *mut u8,
+        relocs: &'a mut RelocSink,
+        traps: &'a mut TrapSink,
+        patches: &'a mut PatchSink,
+    ) -> Self {
+        Self {
+            patches: Some(patches),
+            ..Self::new(data, relocs, traps)
         }
     }
 }
@@ -75,6 +150,59 @@ pub trait TrapSink {
     fn trap(&mut self, _: CodeOffset, _: SourceLoc, _: TrapCode);
 }
 
+/// A trait for receiving the exception tables of a function.
+///
+/// Each call that can unwind, like `try_call`, is reported with the offset of the end of the call
+/// instruction, which is the return address, and the offset of its landing pad.
+///
+/// If the function doesn't need to catch exceptions, you can use the
+/// [`NullExceptionSink`](binemit/trait.ExceptionSink.html) implementation.
+pub trait ExceptionSink {
+    /// Add a landing pad for the call ending at `call_end`.
+    fn landing_pad(&mut self, call_end: CodeOffset, landing_pad: CodeOffset);
+}
+
+/// A trait for receiving the deoptimization maps of a function.
+///
+/// Each `safepoint` instruction is reported with its offset and the locations of its abstract
+/// frame slots, so the embedder can reconstruct the interpreter state when it bails out of the
+/// compiled code at that offset.
+///
+/// If the function has no safepoints, you can use the
+/// [`NullDeoptSink`](binemit/trait.DeoptSink.html) implementation.
+pub trait DeoptSink {
+    /// Add a safepoint at `offset` with the locations of its abstract frame slots.
+    fn safepoint(&mut self, offset: CodeOffset, srcloc: SourceLoc, locations: &[DeoptLocation]);
+}
+
+/// A trait for receiving the stack maps of a function.
+///
+/// When the `enable_safepoints` setting is enabled, each call with references live across it is
+/// reported with the offset of the end of the call instruction, which is the return address, and
+/// the offsets from the stack pointer of the stack slots holding the references. A garbage
+/// collector walking the stack can find and update all the references held by the function.
+///
+/// If the function doesn't use reference types, you can use the
+/// [`NullStackmapSink`](binemit/trait.StackmapSink.html) implementation.
+pub trait StackmapSink {
+    /// Add a stack map for the call ending at `call_end`.
+    fn add_stackmap(&mut self, call_end: CodeOffset, ref_offsets: &[StackOffset]);
+}
+
+/// A trait for receiving the patchable ranges of a function.
+///
+/// When the `patchable_calls` setting is enabled, each direct call is reported with the offsets of
+/// the start and the end of the call instruction, and when the `patchable_branches` setting is
+/// enabled, each unconditional jump is reported the same way. The displacement is the last 4 bytes
+/// of the range, and it is 4-byte aligned when the function is.
+///
+/// If the code is never patched, you can use the
+/// [`NullPatchSink`](binemit/trait.PatchSink.html) implementation.
+pub trait PatchSink {
+    /// Add a patchable range from `start` to `end`.
+    fn patchable_range(&mut self, start: CodeOffset, end: CodeOffset);
+}
+
 impl<'a> CodeSink for MemoryCodeSink<'a> {
     fn offset(&self) -> CodeOffset {
         self.offset as CodeOffset
@@ -131,6 +259,34 @@ impl<'a> CodeSink for MemoryCodeSink<'a> {
         self.traps.trap(ofs, srcloc, code);
     }
 
+    fn landing_pad(&mut self, landing_pad: CodeOffset) {
+        let ofs = self.offset();
+        if let Some(ref mut exceptions) = self.exceptions {
+            exceptions.landing_pad(ofs, landing_pad);
+        }
+    }
+
+    fn safepoint(&mut self, srcloc: SourceLoc, locations: &[DeoptLocation]) {
+        let ofs = self.offset();
+        if let Some(ref mut deopts) = self.deopts {
+            deopts.safepoint(ofs, srcloc, locations);
+        }
+    }
+
+    fn stackmap(&mut self, ref_offsets: &[StackOffset]) {
+        let ofs = self.offset();
+        if let Some(ref mut stackmaps) = self.stackmaps {
+            stackmaps.add_stackmap(ofs, ref_offsets);
+        }
+    }
+
+    fn patchable_range(&mut self, start: CodeOffset) {
+        let ofs = self.offset();
+        if let Some(ref mut patches) = self.patches {
+            patches.patchable_range(start, ofs);
+        }
+    }
+
     fn begin_rodata(&mut self) {
         self.code_size = self.offset;
     }
@@ -143,3 +299,36 @@ pub struct NullTrapSink {}
 impl TrapSink for NullTrapSink {
     fn trap(&mut self, _offset: CodeOffset, _srcloc: SourceLoc, _code: TrapCode) {}
 }
+
+/// An `ExceptionSink` implementation that does nothing, which is convenient when
+/// compiling code that does not catch exceptions.
+pub struct NullExceptionSink {}
+
+impl ExceptionSink for NullExceptionSink {
+    fn landing_pad(&mut self, _call_end: CodeOffset, _landing_pad: CodeOffset) {}
+}
+
+/// A `DeoptSink` implementation that does nothing, which is convenient when
+/// compiling code that does not deoptimize.
+pub struct NullDeoptSink {}
+
+impl DeoptSink for NullDeoptSink {
+    fn safepoint(&mut self, _offset: CodeOffset, _srcloc: SourceLoc, _locations: &[DeoptLocation]) {
+    }
+}
+
+/// A `StackmapSink` implementation that does nothing, which is convenient when
+/// compiling code that does not use reference types.
+pub struct NullStackmapSink {}
+
+impl StackmapSink for NullStackmapSink {
+    fn add_stackmap(&mut self, _call_end: CodeOffset, _ref_offsets: &[StackOffset]) {}
+}
+
+/// A `PatchSink` implementation that does nothing, which is convenient when
+/// compiling code that is never patched.
+pub struct NullPatchSink {}
+
+impl PatchSink for NullPatchSink {
+    fn patchable_range(&mut self, _start: CodeOffset, _end: CodeOffset) {}
+}
@@ -4,16 +4,25 @@
 //! binary machine code.
 
 mod memorysink;
+mod offsets;
 mod relaxation;
 mod shrink;
 
-pub use self::memorysink::{MemoryCodeSink, NullTrapSink, RelocSink, TrapSink};
-pub use self::relaxation::relax_branches;
+pub use self::memorysink::{
+    DeoptSink, ExceptionSink, MemoryCodeSink, NullDeoptSink, NullExceptionSink, NullPatchSink,
+    NullStackmapSink, NullTrapSink, PatchSink, RelocSink, StackmapSink, TrapSink,
+};
+pub use self::offsets::CodeOffsets;
+pub use self::relaxation::{code_size, relax_branches, relax_branches_with_stats};
 pub use self::shrink::shrink_instructions;
 pub use crate::regalloc::RegDiversions;
 
-use crate::ir::{ExternalName, Function, Inst, JumpTable, SourceLoc, TrapCode};
+use crate::ir::{
+    ExternalName, Function, Inst, JumpTable, SourceLoc, StackOffset, TrapCode, ValueLoc,
+};
+use crate::isa::{RegUnit, StackRef};
 use core::fmt;
+use std::vec::Vec;
 
 /// Offset in bytes from the beginning of the function.
 ///
@@ -39,6 +48,12 @@ pub enum Reloc {
     X86CallPLTRel4,
     /// x86 GOT PC-relative 4-byte
     X86GOTPCRel4,
+    /// ELF x86-64 PC-relative 4-byte offset to the GOT entry holding the TLS descriptor of a
+    /// symbol, for the general-dynamic model
+    ElfX86_64TlsGd,
+    /// ELF x86-64 PC-relative 4-byte offset to the GOT entry holding the thread pointer offset of
+    /// a symbol, for the initial-exec model
+    ElfX86_64GotTpOff,
     /// Arm32 call target
     Arm32Call,
     /// Arm64 call target
@@ -58,11 +73,61 @@ impl fmt::Display for Reloc {
             Reloc::X86CallPCRel4 => write!(f, "CallPCRel4"),
             Reloc::X86CallPLTRel4 => write!(f, "CallPLTRel4"),
             Reloc::X86GOTPCRel4 => write!(f, "GOTPCRel4"),
+            Reloc::ElfX86_64TlsGd => write!(f, "TlsGd"),
+            Reloc::ElfX86_64GotTpOff => write!(f, "GotTpOff"),
             Reloc::Arm32Call | Reloc::Arm64Call | Reloc::RiscvCall => write!(f, "Call"),
         }
     }
 }
 
+/// The location of a value captured by a `safepoint` instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeoptLocation {
+    /// The value is in a register.
+    Reg(RegUnit),
+    /// The value is in a stack slot at this offset from the stack pointer.
+    Stack(StackOffset),
+}
+
+impl fmt::Display for DeoptLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeoptLocation::Reg(ru) => write!(f, "reg{}", ru),
+            DeoptLocation::Stack(offset) => write!(f, "sp{:+}", offset),
+        }
+    }
+}
+
+/// Get the locations of the values captured by the `safepoint` instruction `inst`.
+///
+/// The location of the argument at index `i` is the location of abstract frame slot `i`.
+pub fn deopt_locations(func: &Function, inst: Inst, divert: &RegDiversions) -> Vec<DeoptLocation> {
+    func.dfg
+        .inst_args(inst)
+        .iter()
+        .map(|&arg| match divert.get(arg, &func.locations) {
+            ValueLoc::Reg(ru) => DeoptLocation::Reg(ru),
+            ValueLoc::Stack(ss) => DeoptLocation::Stack(StackRef::sp(ss, &func.stack_slots).offset),
+            ValueLoc::Unassigned => panic!("Safepoint argument {} has no location", arg),
+        })
+        .collect()
+}
+
+/// Get the stack offsets of the references recorded by the `stackmap` instruction `inst`.
+///
+/// The references live across a call are always spilled, so each of them is in a stack slot that
+/// the garbage collector can find and update while the callee runs.
+pub fn stackmap_offsets(func: &Function, inst: Inst, divert: &RegDiversions) -> Vec<StackOffset> {
+    func.dfg
+        .inst_args(inst)
+        .iter()
+        .map(|&arg| match divert.get(arg, &func.locations) {
+            ValueLoc::Stack(ss) => StackRef::sp(ss, &func.stack_slots).offset,
+            _ => panic!("Reference value {} live across a call must be spilled", arg),
+        })
+        .collect()
+}
+
 /// Abstract interface for adding bytes to the code segment.
 ///
 /// A `CodeSink` will receive all of the machine code for a function. It also accepts relocations
@@ -95,6 +160,19 @@ pub trait CodeSink {
     /// Add trap information for the current offset.
     fn trap(&mut self, _: TrapCode, _: SourceLoc);
 
+    /// Add the landing pad at `landing_pad` for the call ending at the current offset.
+    fn landing_pad(&mut self, landing_pad: CodeOffset);
+
+    /// Add a safepoint at the current offset, with the locations of its abstract frame slots.
+    fn safepoint(&mut self, _: SourceLoc, _: &[DeoptLocation]);
+
+    /// Add a stack map for the call ending at the current offset, with the stack offsets of the
+    /// live references.
+    fn stackmap(&mut self, _: &[StackOffset]);
+
+    /// Add a patchable range from `start` to the current offset.
+    fn patchable_range(&mut self, start: CodeOffset);
+
     /// Code output is complete, read-only data may follow.
     fn begin_rodata(&mut self);
 }
@@ -112,15 +190,21 @@ pub fn bad_encoding(func: &Function, inst: Inst) -> ! {
 /// Emit a function to `sink`, given an instruction emitter function.
 ///
 /// This function is called from the `TargetIsa::emit_function()` implementations with the
-/// appropriate instruction emitter.
-pub fn emit_function<CS, EI>(func: &Function, emit_inst: EI, sink: &mut CS)
+/// appropriate instruction emitter, and a padding emitter which fills the given number of bytes
+/// with nops before aligned EBBs.
+pub fn emit_function<CS, EI, EP>(func: &Function, emit_inst: EI, emit_padding: EP, sink: &mut CS)
 where
     CS: CodeSink,
     EI: Fn(&Function, Inst, &mut RegDiversions, &mut CS),
+    EP: Fn(CodeOffset, &mut CS),
 {
     let mut divert = RegDiversions::new();
     for ebb in func.layout.ebbs() {
         divert.clear();
+        let offset = sink.offset();
+        if offset < func.offsets[ebb] {
+            emit_padding(func.offsets[ebb] - offset, sink);
+        }
         debug_assert_eq!(func.offsets[ebb], sink.offset());
         for inst in func.layout.ebb_insts(ebb) {
             emit_inst(func, inst, &mut divert, sink);
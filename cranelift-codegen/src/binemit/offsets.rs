@@ -0,0 +1,173 @@
+//! Code offsets of the EBBs of a compiled function.
+//!
+//! Binary emission happens in two passes: branch relaxation computes the final offset of every
+//! EBB header and jump table in `Function::offsets` and `Function::jt_offsets`, and then
+//! `emit_function` emits the code, padding it so every EBB starts at its recorded offset. A
+//! `CodeOffsets` table is a snapshot of the offsets from the first pass, which remain valid for
+//! the emitted code.
+//!
+//! Embedders use it to find OSR entry points and exception landing pads in the emitted code, and
+//! to map profiler samples back to IR blocks.
+
+use crate::binemit::CodeOffset;
+use crate::entity::SecondaryMap;
+use crate::ir::{Ebb, Function, JumpTable};
+use std::vec::Vec;
+
+/// The code offsets of the EBBs and jump tables of a compiled function.
+#[derive(Clone, Debug)]
+pub struct CodeOffsets {
+    /// The EBBs in layout order, with their offsets.
+    ebbs: Vec<(CodeOffset, Ebb)>,
+
+    /// Offset of every EBB in the layout.
+    ebb_offsets: SecondaryMap<Ebb, Option<CodeOffset>>,
+
+    /// Offset of every jump table in the read-only data.
+    jt_offsets: SecondaryMap<JumpTable, Option<CodeOffset>>,
+
+    /// Offset of the end of the code, where the read-only data starts.
+    code_end: CodeOffset,
+}
+
+impl CodeOffsets {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self {
+            ebbs: Vec::new(),
+            ebb_offsets: SecondaryMap::new(),
+            jt_offsets: SecondaryMap::new(),
+            code_end: 0,
+        }
+    }
+
+    /// Clear the table.
+    pub fn clear(&mut self) {
+        self.ebbs.clear();
+        self.ebb_offsets.clear();
+        self.jt_offsets.clear();
+        self.code_end = 0;
+    }
+
+    /// Record the offsets of `func` after branch relaxation.
+    ///
+    /// `code_size` is the total size of the function returned by `relax_branches`, including the
+    /// jump tables.
+    pub fn record(&mut self, func: &Function, code_size: CodeOffset) {
+        self.clear();
+        for ebb in func.layout.ebbs() {
+            let offset = func.offsets[ebb];
+            self.ebbs.push((offset, ebb));
+            self.ebb_offsets[ebb] = Some(offset);
+        }
+        self.code_end = code_size;
+        for jt in func.jump_tables.keys() {
+            let offset = func.jt_offsets[jt];
+            self.jt_offsets[jt] = Some(offset);
+            self.code_end = self.code_end.min(offset);
+        }
+    }
+
+    /// Is the table empty? It is until a function has been compiled.
+    pub fn is_empty(&self) -> bool {
+        self.ebbs.is_empty()
+    }
+
+    /// Get the offset of `ebb` from the beginning of the function, or `None` if `ebb` is not in
+    /// the layout of the compiled function.
+    pub fn ebb_offset(&self, ebb: Ebb) -> Option<CodeOffset> {
+        self.ebb_offsets.get(ebb).cloned().unwrap_or(None)
+    }
+
+    /// Get the offset of the jump table `jt` from the beginning of the function.
+    pub fn jump_table_offset(&self, jt: JumpTable) -> Option<CodeOffset> {
+        self.jt_offsets.get(jt).cloned().unwrap_or(None)
+    }
+
+    /// Get the offset of the end of the code, where the jump tables start.
+    pub fn code_end(&self) -> CodeOffset {
+        self.code_end
+    }
+
+    /// Iterate over the EBBs in layout order, with their offsets.
+    pub fn ebbs<'a>(&'a self) -> impl Iterator<Item = (Ebb, CodeOffset)> + 'a {
+        self.ebbs.iter().map(|&(offset, ebb)| (ebb, offset))
+    }
+
+    /// Get the EBB containing the code at `offset`.
+    ///
+    /// Returns `None` if `offset` is outside the code of the function. The padding before an
+    /// aligned EBB belongs to the EBB before it.
+    pub fn ebb_at(&self, offset: CodeOffset) -> Option<Ebb> {
+        if offset >= self.code_end {
+            return None;
+        }
+        // The EBBs are in increasing offset order. Empty EBBs share their offset with the next
+        // one, so pick the last EBB starting at or before `offset`.
+        let idx = match self
+            .ebbs
+            .binary_search_by(|&(ebb_offset, _)| ebb_offset.cmp(&offset))
+        {
+            Ok(mut idx) => {
+                while idx + 1 < self.ebbs.len() && self.ebbs[idx + 1].0 == offset {
+                    idx += 1;
+                }
+                idx
+            }
+            Err(0) => return None,
+            Err(idx) => idx - 1,
+        };
+        Some(self.ebbs[idx].1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CodeOffsets;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{Function, InstBuilder};
+    use std::vec::Vec;
+
+    #[test]
+    fn lookup() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            pos.ins().jump(ebb1, &[]);
+            pos.insert_ebb(ebb1);
+            pos.insert_ebb(ebb2);
+            pos.ins().return_(&[]);
+        }
+        func.offsets[ebb0] = 0;
+        func.offsets[ebb1] = 4;
+        func.offsets[ebb2] = 4;
+
+        let mut offsets = CodeOffsets::new();
+        assert!(offsets.is_empty());
+        offsets.record(&func, 8);
+        assert!(!offsets.is_empty());
+        assert_eq!(offsets.code_end(), 8);
+        assert_eq!(offsets.ebb_offset(ebb1), Some(4));
+        assert_eq!(offsets.ebb_offset(ebb3), None);
+        assert_eq!(
+            offsets.ebbs().collect::<Vec<_>>(),
+            [(ebb0, 0), (ebb1, 4), (ebb2, 4)]
+        );
+
+        assert_eq!(offsets.ebb_at(0), Some(ebb0));
+        assert_eq!(offsets.ebb_at(3), Some(ebb0));
+        // `ebb1` is empty, so the code at its offset belongs to `ebb2`.
+        assert_eq!(offsets.ebb_at(4), Some(ebb2));
+        assert_eq!(offsets.ebb_at(7), Some(ebb2));
+        assert_eq!(offsets.ebb_at(8), None);
+
+        offsets.clear();
+        assert!(offsets.is_empty());
+        assert_eq!(offsets.ebb_offset(ebb0), None);
+    }
+}
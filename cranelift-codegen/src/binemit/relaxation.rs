@@ -26,13 +26,22 @@
 //!     jump ebb17
 //! ebb23:
 //! ```
+//!
+//! # Loop header alignment
+//!
+//! When the `loop_alignment_log2` setting is enabled, the EBBs which are the target of a backward
+//! branch in the layout are aligned by inserting padding before them. The padding is emitted as
+//! nops by `emit_function`.
 
 use crate::binemit::CodeOffset;
 use crate::cursor::{Cursor, FuncCursor};
-use crate::ir::{Function, InstructionData, Opcode};
+use crate::entity::EntitySet;
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Ebb, Function, InstructionData, Opcode};
 use crate::isa::{EncInfo, TargetIsa};
 use crate::iterators::IteratorExtras;
 use crate::regalloc::RegDiversions;
+use crate::stats::PassStats;
 use crate::timing;
 use crate::CodegenResult;
 use log::debug;
@@ -41,6 +50,18 @@ use log::debug;
 ///
 /// Fill in the `func.offsets` table so the function is ready for binary emission.
 pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<CodeOffset> {
+    relax_branches_with_stats(func, isa, &mut PassStats::default())
+}
+
+/// Relax branches and compute the final layout of EBB headers in `func`, and count the relaxed
+/// branches in `stats`.
+///
+/// This works like `relax_branches`.
+pub fn relax_branches_with_stats(
+    func: &mut Function,
+    isa: &TargetIsa,
+    stats: &mut PassStats,
+) -> CodegenResult<CodeOffset> {
     let _tt = timing::relax_branches();
 
     let encinfo = isa.encoding_info();
@@ -49,9 +70,11 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<Cod
     func.offsets.clear();
     func.offsets.resize(func.dfg.num_ebbs());
 
-    // Start by inserting fall through instructions.
-    fallthroughs(func);
+    // Start by inserting fall through instructions. Patchable jumps must stay in the code, even
+    // when they jump to the next EBB.
+    fallthroughs(func, !isa.flags().patchable_branches());
 
+    let padding = LoopPadding::new(func, isa);
     let mut offset = 0;
     let mut divert = RegDiversions::new();
 
@@ -60,8 +83,10 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<Cod
         let mut cur = FuncCursor::new(func);
         while let Some(ebb) = cur.next_ebb() {
             divert.clear();
+            offset = padding.align(offset, ebb);
             cur.func.offsets[ebb] = offset;
             while let Some(inst) = cur.next_inst() {
+                divert.apply(&cur.func.dfg[inst]);
                 let enc = cur.func.encodings[inst];
                 offset += encinfo.byte_size(enc, inst, &divert, &cur.func);
             }
@@ -78,6 +103,7 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<Cod
         let mut cur = FuncCursor::new(func);
         while let Some(ebb) = cur.next_ebb() {
             divert.clear();
+            offset = padding.align(offset, ebb);
 
             // Record the offset for `ebb` and make sure we iterate until offsets are stable.
             if cur.func.offsets[ebb] != offset {
@@ -102,6 +128,7 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<Cod
                         if !range.contains(offset, dest_offset) {
                             offset +=
                                 relax_branch(&mut cur, &divert, offset, dest_offset, &encinfo, isa);
+                            stats.relaxed_branches += 1;
                             continue;
                         }
                     }
@@ -114,17 +141,112 @@ pub fn relax_branches(func: &mut Function, isa: &TargetIsa) -> CodegenResult<Cod
 
     for (jt, jt_data) in func.jump_tables.iter() {
         func.jt_offsets[jt] = offset;
-        // TODO: this should be computed based on the min size needed to hold
-        //        the furthest branch.
-        offset += jt_data.len() as u32 * 4;
+        offset += jump_table_size(jt_data.len());
     }
 
     Ok(offset)
 }
 
-/// Convert `jump` instructions to `fallthrough` instructions where possible and verify that any
-/// existing `fallthrough` instructions are correct.
-fn fallthroughs(func: &mut Function) {
+/// Compute the size of the code that `emit_function` generates for `func` with its current
+/// encodings, including the padding before loop headers and the jump tables.
+///
+/// The instruction sizes come from the encoding recipes, so nothing is emitted and `func` is not
+/// modified. After `relax_branches`, the result is the exact size of the function, which lets an
+/// embedder decide whether a function fits in its code cache before emitting it. Before, it is an
+/// estimate which doesn't account for relaxed branches and fallthroughs.
+pub fn code_size(func: &Function, isa: &TargetIsa) -> CodeOffset {
+    let encinfo = isa.encoding_info();
+    let padding = LoopPadding::new(func, isa);
+    let mut offset = 0;
+    let mut divert = RegDiversions::new();
+
+    for ebb in func.layout.ebbs() {
+        divert.clear();
+        offset = padding.align(offset, ebb);
+        for inst in func.layout.ebb_insts(ebb) {
+            divert.apply(&func.dfg[inst]);
+            offset += encinfo.byte_size(func.encodings[inst], inst, &divert, func);
+        }
+    }
+
+    for jt_data in func.jump_tables.values() {
+        offset += jump_table_size(jt_data.len());
+    }
+
+    offset
+}
+
+/// Get the size of a jump table with `len` entries in the read-only data after the code.
+fn jump_table_size(len: usize) -> CodeOffset {
+    // TODO: this should be computed based on the min size needed to hold
+    //        the furthest branch.
+    len as CodeOffset * 4
+}
+
+/// The padding inserted before loop headers when the `loop_alignment_log2` setting is enabled.
+struct LoopPadding {
+    headers: EntitySet<Ebb>,
+    align_log2: u8,
+}
+
+impl LoopPadding {
+    fn new(func: &Function, isa: &TargetIsa) -> Self {
+        let flags = isa.flags();
+        let align_log2 = flags
+            .loop_alignment_log2()
+            .min(flags.function_alignment_log2());
+        let headers = if align_log2 > 0 {
+            loop_headers(func)
+        } else {
+            EntitySet::new()
+        };
+        Self {
+            headers,
+            align_log2,
+        }
+    }
+
+    /// Get the offset of `ebb` when the code before it ends at `offset`.
+    fn align(&self, offset: CodeOffset, ebb: Ebb) -> CodeOffset {
+        if self.headers.contains(ebb) {
+            let mask = (1 << self.align_log2) - 1;
+            (offset + mask) & !mask
+        } else {
+            offset
+        }
+    }
+}
+
+/// Get the EBBs in `func` which are the target of a branch at or after them in the layout.
+fn loop_headers(func: &Function) -> EntitySet<Ebb> {
+    let mut seen = EntitySet::new();
+    let mut headers = EntitySet::new();
+    for ebb in func.layout.ebbs() {
+        seen.insert(ebb);
+        for inst in func.layout.ebb_insts(ebb) {
+            match func.dfg.analyze_branch(inst) {
+                BranchInfo::NotABranch => {}
+                BranchInfo::SingleDest(dest, _) => {
+                    if seen.contains(dest) {
+                        headers.insert(dest);
+                    }
+                }
+                BranchInfo::Table(jt, dest) => {
+                    for &dest in func.jump_tables[jt].iter().chain(dest.as_ref()) {
+                        if seen.contains(dest) {
+                            headers.insert(dest);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    headers
+}
+
+/// Convert `jump` instructions to `fallthrough` instructions where possible if `convert_jumps` is
+/// set, and verify that any existing `fallthrough` instructions are correct.
+fn fallthroughs(func: &mut Function, convert_jumps: bool) {
     for (ebb, succ) in func.layout.ebbs().adjacent_pairs() {
         let term = func.layout.last_inst(ebb).expect("EBB has no terminator.");
         if let InstructionData::Jump {
@@ -141,7 +263,7 @@ fn fallthroughs(func: &mut Function) {
                 }
                 Opcode::Jump => {
                     // If this is a jump to the successor EBB, change it to a fall-through.
-                    if destination == succ {
+                    if convert_jumps && destination == succ {
                         *opcode = Opcode::Fallthrough;
                         func.encodings[term] = Default::default();
                     }
@@ -173,34 +295,45 @@ fn relax_branch(
         dest_offset
     );
 
-    // Pick the first encoding that can handle the branch range.
+    // Pick the smallest encoding that can handle the branch range. ISAs like Thumb-2 mix 16-bit
+    // and 32-bit branch encodings, so the first encoding in range is not necessarily the
+    // smallest. Never pick an encoding smaller than the current one, or the relaxation might not
+    // converge.
     let dfg = &cur.func.dfg;
     let ctrl_type = dfg.ctrl_typevar(inst);
-    if let Some(enc) = isa
-        .legal_encodings(cur.func, &dfg[inst], ctrl_type)
-        .find(|&enc| {
-            let range = encinfo.branch_range(enc).expect("Branch with no range");
-            if !range.contains(offset, dest_offset) {
-                debug!("  trying [{}]: out of range", encinfo.display(enc));
-                false
-            } else if encinfo.operand_constraints(enc)
-                != encinfo.operand_constraints(cur.func.encodings[inst])
-            {
-                // Conservatively give up if the encoding has different constraints
-                // than the original, so that we don't risk picking a new encoding
-                // which the existing operands don't satisfy. We can't check for
-                // validity directly because we don't have a RegDiversions active so
-                // we don't know which registers are actually in use.
-                debug!("  trying [{}]: constraints differ", encinfo.display(enc));
-                false
-            } else {
-                debug!("  trying [{}]: OK", encinfo.display(enc));
-                true
-            }
-        })
-    {
+    let cur_size = encinfo.byte_size(cur.func.encodings[inst], inst, &divert, &cur.func);
+    let mut best = None;
+    for enc in isa.legal_encodings(cur.func, &dfg[inst], ctrl_type) {
+        let range = encinfo.branch_range(enc).expect("Branch with no range");
+        if !range.contains(offset, dest_offset) {
+            debug!("  trying [{}]: out of range", encinfo.display(enc));
+            continue;
+        }
+        if encinfo.operand_constraints(enc) != encinfo.operand_constraints(cur.func.encodings[inst])
+            && !encinfo.constraints[enc.recipe()].satisfied(inst, divert, &cur.func)
+        {
+            // The new encoding may have different constraints than the original, for
+            // example when relaxing a compressed RISC-V branch that only accepts a subset
+            // of the registers. Make sure the existing operands satisfy them.
+            debug!(
+                "  trying [{}]: constraints not satisfied",
+                encinfo.display(enc)
+            );
+            continue;
+        }
+        let size = encinfo.byte_size(enc, inst, &divert, &cur.func);
+        if size < cur_size {
+            debug!("  trying [{}]: smaller than current", encinfo.display(enc));
+            continue;
+        }
+        debug!("  trying [{}]: OK", encinfo.display(enc));
+        if best.map_or(true, |(_, best_size)| size < best_size) {
+            best = Some((enc, size));
+        }
+    }
+    if let Some((enc, size)) = best {
         cur.func.encodings[inst] = enc;
-        return encinfo.byte_size(enc, inst, &divert, &cur.func);
+        return size;
     }
 
     // Note: On some RISC ISAs, conditional branches have shorter range than unconditional
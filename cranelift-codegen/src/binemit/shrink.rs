@@ -24,7 +24,7 @@ pub fn shrink_instructions(func: &mut Function, isa: &TargetIsa) {
         for inst in func.layout.ebb_insts(ebb) {
             let enc = func.encodings[inst];
             if enc.is_legal() {
-                // regmove/regfill/regspill are special instructions with register immediates
+                // regmove/regswap/regfill/regspill are special instructions with register immediates
                 // that represented as normal operands, so the normal predicates below don't
                 // handle them correctly.
                 //
@@ -35,6 +35,7 @@ pub fn shrink_instructions(func: &mut Function, isa: &TargetIsa) {
                 // instructions behind, but for now, just temporarily avoid trying to shrink them.
                 match func.dfg[inst] {
                     InstructionData::RegMove { .. }
+                    | InstructionData::RegSwap { .. }
                     | InstructionData::RegFill { .. }
                     | InstructionData::RegSpill { .. } => {
                         divert.apply(&func.dfg[inst]);
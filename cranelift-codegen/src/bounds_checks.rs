@@ -0,0 +1,399 @@
+//! Bounds check elimination.
+//!
+//! This pass removes the bounds checks which are proven redundant by the checks dominating them.
+//! It runs before legalization, so the checks are the `heap_addr` instructions and explicit
+//! `trapz` and `trapnz` instructions testing an integer comparison.
+//!
+//! The EBBs are visited in dominator tree order while keeping track of the facts established by
+//! the dominating checks: upper bounds on values, the results of comparisons, and the offsets and
+//! access sizes that were checked against each heap. A simple range analysis over the
+//! definitions of values combines these facts with the ranges of constants, masks, extensions
+//! and remainders.
+//!
+//! A `heap_addr` is replaced when:
+//!
+//! - A dominating `heap_addr` checked the same offset in the same heap for an access at least as
+//!   large. Its result is reused.
+//! - The offset is `iadd_imm x, c` and a dominating `heap_addr` checked `x` for an access covering
+//!   `c` plus the access size. The address is computed from the earlier one.
+//! - The offset is known to be small enough for the heap. The address is computed without a
+//!   check.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::dominator_tree::DominatorTree;
+use crate::ir::condcodes::{CondCode, IntCC};
+use crate::ir::dfg::ValueDef;
+use crate::ir::{Function, Heap, HeapStyle, Inst, InstBuilder, InstructionData, Opcode, Value};
+use crate::scoped_hash_map::{Entry, ScopedHashMap};
+use crate::timing;
+use std::vec::Vec;
+
+/// How many definitions to look through when computing the range of a value.
+const MAX_DEPTH: usize = 4;
+
+/// Get the largest unsigned value of a type with `bits` bits.
+fn type_max(bits: u16) -> u64 {
+    if bits < 64 {
+        (1 << bits) - 1
+    } else {
+        core::u64::MAX
+    }
+}
+
+/// The right-hand side of a comparison.
+#[derive(Clone, Copy)]
+enum Rhs {
+    Value(Value),
+    Imm(u64),
+}
+
+/// Get the integer comparison computing `value`.
+fn comparison(func: &Function, value: Value) -> Option<(IntCC, Value, Rhs)> {
+    let value = func.dfg.resolve_aliases(value);
+    let inst = match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(..) => return None,
+    };
+    match func.dfg[inst] {
+        InstructionData::IntCompare {
+            opcode: Opcode::Icmp,
+            cond,
+            args,
+        } => Some((
+            cond,
+            func.dfg.resolve_aliases(args[0]),
+            Rhs::Value(func.dfg.resolve_aliases(args[1])),
+        )),
+        InstructionData::IntCompareImm {
+            opcode: Opcode::IcmpImm,
+            cond,
+            arg,
+            imm,
+        } => {
+            let imm: i64 = imm.into();
+            let bits = func.dfg.value_type(arg).bits();
+            Some((
+                cond,
+                func.dfg.resolve_aliases(arg),
+                Rhs::Imm(imm as u64 & type_max(bits)),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// The facts established by the checks dominating a program point.
+struct Facts {
+    /// Upper bounds of values.
+    bounds: ScopedHashMap<Value, u64>,
+    /// Known results of comparisons.
+    conditions: ScopedHashMap<(IntCC, Value, Value), bool>,
+    /// The access size that was checked for an offset in a heap, and the resulting address.
+    checks: ScopedHashMap<(Heap, Value), (u64, Value)>,
+}
+
+impl Facts {
+    fn new() -> Self {
+        Self {
+            bounds: ScopedHashMap::new(),
+            conditions: ScopedHashMap::new(),
+            checks: ScopedHashMap::new(),
+        }
+    }
+
+    fn increment_depth(&mut self) {
+        self.bounds.increment_depth();
+        self.conditions.increment_depth();
+        self.checks.increment_depth();
+    }
+
+    fn decrement_depth(&mut self) {
+        self.bounds.decrement_depth();
+        self.conditions.decrement_depth();
+        self.checks.decrement_depth();
+    }
+
+    /// Record that `value` is at most `bound`.
+    ///
+    /// Only the first bound of a value in a scope is kept.
+    fn add_bound(&mut self, value: Value, bound: u64) {
+        if let Entry::Vacant(entry) = self.bounds.entry(value) {
+            entry.insert(bound);
+        }
+    }
+
+    /// Get an upper bound of the unsigned value of `value`.
+    fn max_value(&self, func: &Function, value: Value, depth: usize) -> u64 {
+        let value = func.dfg.resolve_aliases(value);
+        let ty = func.dfg.value_type(value);
+        let mut max = type_max(ty.bits());
+        if let Some(&bound) = self.bounds.get(&value) {
+            max = max.min(bound);
+        }
+        if depth == MAX_DEPTH || !ty.is_int() || ty.is_vector() {
+            return max;
+        }
+
+        let inst = match func.dfg.value_def(value) {
+            ValueDef::Result(inst, _) => inst,
+            ValueDef::Param(..) => return max,
+        };
+        let derived = match func.dfg[inst] {
+            InstructionData::UnaryImm {
+                opcode: Opcode::Iconst,
+                imm,
+            } => {
+                let imm: i64 = imm.into();
+                imm as u64 & type_max(ty.bits())
+            }
+            InstructionData::Unary {
+                opcode: Opcode::Uextend,
+                arg,
+            } => self.max_value(func, arg, depth + 1),
+            InstructionData::Binary {
+                opcode: Opcode::Band,
+                args,
+            } => self.max_value(func, args[0], depth + 1).min(self.max_value(
+                func,
+                args[1],
+                depth + 1,
+            )),
+            InstructionData::BinaryImm { opcode, arg, imm } => {
+                let imm: i64 = imm.into();
+                let imm = imm as u64 & type_max(ty.bits());
+                let arg_max = self.max_value(func, arg, depth + 1);
+                match opcode {
+                    Opcode::BandImm => arg_max.min(imm),
+                    Opcode::UshrImm => arg_max >> (imm & u64::from(ty.bits() - 1)),
+                    Opcode::UremImm if imm != 0 => arg_max.min(imm - 1),
+                    Opcode::IaddImm => match arg_max.checked_add(imm) {
+                        Some(sum) if sum <= max => sum,
+                        _ => max,
+                    },
+                    _ => max,
+                }
+            }
+            InstructionData::Ternary {
+                opcode: Opcode::Select,
+                args,
+            } => self.max_value(func, args[1], depth + 1).max(self.max_value(
+                func,
+                args[2],
+                depth + 1,
+            )),
+            _ => max,
+        };
+        max.min(derived)
+    }
+
+    /// Get the result of the comparison computing `value`, if it is known.
+    fn evaluate(&self, func: &Function, value: Value) -> Option<bool> {
+        let (cond, lhs, rhs) = comparison(func, value)?;
+        match rhs {
+            Rhs::Value(rhs) => self.conditions.get(&(cond, lhs, rhs)).cloned(),
+            Rhs::Imm(imm) => {
+                let max = self.max_value(func, lhs, 0);
+                match cond {
+                    IntCC::UnsignedGreaterThan if max <= imm => Some(false),
+                    IntCC::UnsignedGreaterThanOrEqual if max < imm => Some(false),
+                    IntCC::UnsignedLessThan if max < imm => Some(true),
+                    IntCC::UnsignedLessThanOrEqual if max <= imm => Some(true),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Record that the comparison computing `value` has the result `known`.
+    fn assume(&mut self, func: &Function, value: Value, known: bool) {
+        let (cond, lhs, rhs) = match comparison(func, value) {
+            Some(comparison) => comparison,
+            None => return,
+        };
+        let rhs_max = match rhs {
+            Rhs::Value(rhs) => {
+                if let Entry::Vacant(entry) = self.conditions.entry((cond, lhs, rhs)) {
+                    entry.insert(known);
+                }
+                self.max_value(func, rhs, 0)
+            }
+            Rhs::Imm(imm) => imm,
+        };
+
+        // Turn the comparison into an upper bound of `lhs`.
+        let cond = if known { cond } else { cond.inverse() };
+        match cond {
+            IntCC::UnsignedLessThanOrEqual => self.add_bound(lhs, rhs_max),
+            IntCC::UnsignedLessThan if rhs_max > 0 => self.add_bound(lhs, rhs_max - 1),
+            _ => {}
+        }
+    }
+}
+
+/// Get the size of the addressable part of `heap` that is known without a check.
+fn known_heap_size(func: &Function, heap: Heap) -> u64 {
+    match func.heaps[heap].style {
+        HeapStyle::Static { bound } => bound.into(),
+        HeapStyle::Dynamic { .. } => func.heaps[heap].min_size.into(),
+    }
+}
+
+/// Get the largest offset from which `heap` can be accessed with `size` bytes after a check.
+fn heap_limit(func: &Function, heap: Heap, offset_bits: u16, size: u64) -> Option<u64> {
+    let bound = match func.heaps[heap].style {
+        HeapStyle::Static { bound } => bound.into(),
+        // The bound of a dynamic heap is a value of the offset type.
+        HeapStyle::Dynamic { .. } => type_max(offset_bits),
+    };
+    bound.checked_sub(size)
+}
+
+/// Try to remove the bounds check of the `heap_addr` instruction `inst`.
+///
+/// Returns true if `inst` was removed.
+fn simplify_heap_addr(pos: &mut FuncCursor, facts: &mut Facts, inst: Inst) -> bool {
+    let (heap, offset, size) = match pos.func.dfg[inst] {
+        InstructionData::HeapAddr {
+            opcode: Opcode::HeapAddr,
+            heap,
+            arg,
+            imm,
+        } => {
+            let size: u32 = imm.into();
+            (heap, pos.func.dfg.resolve_aliases(arg), u64::from(size))
+        }
+        _ => return false,
+    };
+    let result = pos.func.dfg.first_result(inst);
+    let addr_ty = pos.func.dfg.value_type(result);
+    let offset_ty = pos.func.dfg.value_type(offset);
+
+    // The same offset was already checked for a larger access.
+    if let Some(&(checked, addr)) = facts.checks.get(&(heap, offset)) {
+        if checked >= size && pos.func.dfg.value_type(addr) == addr_ty {
+            pos.func.dfg.clear_results(inst);
+            pos.func.dfg.change_to_alias(result, addr);
+            pos.remove_inst_and_step_back();
+            return true;
+        }
+    }
+
+    // A smaller offset was checked for an access covering this one. It is only reused if the
+    // addition can't wrap around.
+    let mut replaced = false;
+    if let ValueDef::Result(def, _) = pos.func.dfg.value_def(offset) {
+        if let InstructionData::BinaryImm {
+            opcode: Opcode::IaddImm,
+            arg,
+            imm,
+        } = pos.func.dfg[def]
+        {
+            let imm: i64 = imm.into();
+            let arg = pos.func.dfg.resolve_aliases(arg);
+            if let Some(&(checked, addr)) = facts.checks.get(&(heap, arg)) {
+                let covered = imm >= 0
+                    && (imm as u64)
+                        .checked_add(size)
+                        .map_or(false, |end| end <= checked);
+                let no_wrap = facts
+                    .max_value(pos.func, arg, 0)
+                    .checked_add(imm as u64)
+                    .map_or(false, |max| max <= type_max(offset_ty.bits()));
+                if covered && no_wrap && pos.func.dfg.value_type(addr) == addr_ty {
+                    pos.func.dfg.replace(inst).iadd_imm(addr, imm);
+                    replaced = true;
+                }
+            }
+        }
+    }
+
+    // The offset is small enough for the heap.
+    if !replaced {
+        let max = facts.max_value(pos.func, offset, 0);
+        let end = max.checked_add(size);
+        if end.map_or(false, |end| end <= known_heap_size(pos.func, heap)) {
+            let mut offset = offset;
+            if offset_ty != addr_ty {
+                offset = pos.ins().uextend(addr_ty, offset);
+            }
+            let base = pos.ins().global_value(addr_ty, pos.func.heaps[heap].base);
+            pos.func.dfg.replace(inst).iadd(base, offset);
+        }
+    }
+
+    // Later checks can rely on this one, whether or not it was replaced.
+    if let Entry::Vacant(entry) = facts.checks.entry((heap, offset)) {
+        entry.insert((size, result));
+    }
+    if let Some(limit) = heap_limit(pos.func, heap, offset_ty.bits(), size) {
+        facts.add_bound(offset, limit);
+    }
+    false
+}
+
+/// Remove the bounds checks in `func` which are made redundant by the checks dominating them.
+pub fn do_bounds_check_elimination(func: &mut Function, domtree: &DominatorTree) {
+    let _tt = timing::bounds_checks();
+    debug_assert!(domtree.is_valid());
+
+    let mut pos = FuncCursor::new(func);
+    let mut facts = Facts::new();
+    let mut scope_stack: Vec<Inst> = Vec::new();
+
+    for &ebb in domtree.cfg_postorder().iter().rev() {
+        // Pop any scopes that we just exited.
+        while let Some(&current) = scope_stack.last() {
+            if domtree.dominates(current, ebb, &pos.func.layout) {
+                break;
+            }
+            scope_stack.pop();
+            facts.decrement_depth();
+        }
+
+        // Push a scope for the current block.
+        scope_stack.push(pos.func.layout.first_inst(ebb).unwrap());
+        facts.increment_depth();
+
+        pos.goto_top(ebb);
+        while let Some(inst) = pos.next_inst() {
+            // Resolve aliases, particularly aliases we created earlier.
+            pos.func.dfg.resolve_aliases_in_arguments(inst);
+
+            let next = pos.func.layout.next_inst(inst);
+            let opcode = pos.func.dfg[inst].opcode();
+            if opcode.is_branch() && !opcode.is_terminator() {
+                // The facts established after the branch don't hold at its destination.
+                scope_stack.push(next.unwrap());
+                facts.increment_depth();
+            }
+
+            let removed = match pos.func.dfg[inst] {
+                InstructionData::HeapAddr { .. } => simplify_heap_addr(&mut pos, &mut facts, inst),
+                InstructionData::CondTrap { opcode, arg, .. } => {
+                    let trap_if = opcode == Opcode::Trapnz;
+                    if facts.evaluate(pos.func, arg) == Some(!trap_if) {
+                        pos.remove_inst_and_step_back();
+                        true
+                    } else {
+                        facts.assume(pos.func, arg, !trap_if);
+                        false
+                    }
+                }
+                InstructionData::Branch { opcode, .. }
+                    if opcode == Opcode::Brz || opcode == Opcode::Brnz =>
+                {
+                    // The condition is only known when the branch falls through.
+                    let cond = pos.func.dfg.inst_args(inst)[0];
+                    facts.assume(pos.func, cond, opcode == Opcode::Brz);
+                    false
+                }
+                _ => false,
+            };
+            // If the instruction representing the current scope was removed, pick a new
+            // representative.
+            if removed && scope_stack.last() == Some(&inst) {
+                *scope_stack.last_mut().unwrap() = next.unwrap();
+            }
+        }
+    }
+}
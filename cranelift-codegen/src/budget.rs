@@ -0,0 +1,101 @@
+//! Compilation budgets.
+//!
+//! A JIT compiler can't afford to spend seconds optimizing a huge machine-generated function. A
+//! `CompileBudget` limits the effort spent by `Context::compile` on a function: when it is
+//! exceeded, the remaining optimization passes are skipped and the function is finished with the
+//! baseline path, which only runs the passes needed to generate correct code. The generated code
+//! is still correct, only less optimized.
+
+use crate::ir::Function;
+use core::time::Duration;
+
+/// Limits on the effort spent optimizing a function.
+///
+/// The default budget is unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompileBudget {
+    /// Functions with more instructions than this are compiled with the baseline path from the
+    /// start.
+    ///
+    /// This counts all the instructions created in the function's data flow graph, including the
+    /// ones that have been removed from the layout.
+    pub max_insts: Option<usize>,
+
+    /// Stop optimizing once the compilation of a function has taken longer than this.
+    ///
+    /// The time is checked between passes, so a single slow pass can still exceed it. This is
+    /// ignored when the crate is built without the `std` feature.
+    pub max_time: Option<Duration>,
+}
+
+impl CompileBudget {
+    /// Create an unlimited budget.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking the compilation of `func` against this budget.
+    pub(crate) fn start(&self, func: &Function) -> BudgetTracker {
+        BudgetTracker {
+            exceeded: self
+                .max_insts
+                .map_or(false, |max_insts| func.dfg.num_insts() > max_insts),
+            deadline: details::Deadline::new(self.max_time),
+        }
+    }
+}
+
+/// The state of a compilation against its budget.
+pub(crate) struct BudgetTracker {
+    exceeded: bool,
+    deadline: details::Deadline,
+}
+
+impl BudgetTracker {
+    /// Has the budget been exceeded? Once it has, it stays exceeded.
+    pub fn exceeded(&mut self) -> bool {
+        if !self.exceeded && self.deadline.has_passed() {
+            self.exceeded = true;
+        }
+        self.exceeded
+    }
+}
+
+/// Implementation details.
+///
+/// Like the `timing` module, time can only be measured with the standard library, so a dummy
+/// deadline which never passes is used without it.
+#[cfg(feature = "std")]
+mod details {
+    use core::time::Duration;
+    use std::time::Instant;
+
+    pub struct Deadline(Option<Instant>);
+
+    impl Deadline {
+        pub fn new(max_time: Option<Duration>) -> Self {
+            Deadline(max_time.map(|max_time| Instant::now() + max_time))
+        }
+
+        pub fn has_passed(&self) -> bool {
+            self.0.map_or(false, |deadline| Instant::now() >= deadline)
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod details {
+    use core::time::Duration;
+
+    pub struct Deadline;
+
+    impl Deadline {
+        pub fn new(_max_time: Option<Duration>) -> Self {
+            Deadline
+        }
+
+        pub fn has_passed(&self) -> bool {
+            false
+        }
+    }
+}
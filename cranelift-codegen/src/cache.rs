@@ -0,0 +1,498 @@
+//! Caching of compiled functions.
+//!
+//! Compiling the same function with the same target ISA and settings always produces the same
+//! machine code. Embedders that compile the same functions again and again, like a JIT that is
+//! restarted or an ahead-of-time compiler driven by a build system, can skip the compilation of
+//! unchanged functions by caching the results.
+//!
+//! The cache itself is provided by the embedder through the `CodeCache` trait, which is a simple
+//! key-value store. `Context::compile_and_emit_cached` computes the `CacheKey` of the function
+//! and looks it up. On a miss, the function is compiled and the machine code is stored in the
+//! cache along with its relocations and traps. On a hit, the cached code is copied out and the
+//! relocations and traps are replayed into the sinks without compiling the function.
+
+use crate::binemit::{Addend, CodeOffset, Reloc, RelocSink, TrapSink};
+use crate::ir::{ExternalName, Function, JumpTable, SourceLoc, TrapCode};
+use crate::isa::TargetIsa;
+use crate::serialize::{
+    serialize_function, Decode, Decoder, DeserializeError, DeserializeResult, Encode, Encoder,
+};
+use core::fmt;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// The key identifying a compiled function in a `CodeCache`.
+///
+/// This is a 128-bit hash of the function's IR, the target ISA and its settings, and the version
+/// of Cranelift. The IR is hashed in the binary format of the `serialize` module, so the key
+/// depends on the entity numbering but not on the memory layout of the function.
+///
+/// The key is computed with the FNV-1a hash function which is fast, but not cryptographically
+/// secure. A cache shouldn't be shared with untrusted parties that could craft colliding functions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey([u8; 16]);
+
+impl CacheKey {
+    /// Compute the key for compiling `func` for `isa`.
+    pub fn new(func: &Function, isa: &TargetIsa) -> Self {
+        let mut hash = Fnv128::new();
+        hash.write(&serialize_function(func));
+        hash.write(isa.name().as_bytes());
+        hash.write(&[0]);
+        hash.write(isa.triple().to_string().as_bytes());
+        hash.write(&[0]);
+        // The `Display` implementation shows all the shared and ISA-specific settings.
+        hash.write(isa.to_string().as_bytes());
+        CacheKey(hash.0.to_le_bytes())
+    }
+
+    /// Get the bytes of this key.
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+/// Display a key as 32 hexadecimal digits, which is suitable as a file name.
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// The 128-bit FNV-1a hash function.
+struct Fnv128(u128);
+
+const FNV128_OFFSET_BASIS: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+const FNV128_PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+impl Fnv128 {
+    fn new() -> Self {
+        Fnv128(FNV128_OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u128::from(byte)).wrapping_mul(FNV128_PRIME);
+        }
+    }
+}
+
+/// A key-value store for compiled functions, implemented by the embedder.
+///
+/// The stored data is opaque. It contains the machine code of a function along with its
+/// relocations and traps. A cache can be kept in memory or persisted between runs, and it is
+/// free to evict entries at any time.
+pub trait CodeCache {
+    /// Get the data stored under `key`, if any.
+    fn get(&mut self, key: &CacheKey) -> Option<Vec<u8>>;
+
+    /// Store `data` under `key`, replacing any previous data.
+    fn insert(&mut self, key: CacheKey, data: Vec<u8>);
+}
+
+/// The machine code of a function along with its relocations and traps.
+pub(crate) struct CachedCode {
+    code: Vec<u8>,
+    relocs: Vec<CachedReloc>,
+    traps: Vec<CachedTrap>,
+}
+
+impl CachedCode {
+    /// Collect the machine code `code` and the relocations and traps recorded by the sinks.
+    pub fn new(code: &[u8], relocs: RecordingRelocSink, traps: RecordingTrapSink) -> Self {
+        Self {
+            code: code.to_vec(),
+            relocs: relocs.relocs,
+            traps: traps.traps,
+        }
+    }
+
+    /// Decode cached code, or return `None` if `data` is corrupted.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        let mut dec = Decoder::new(data);
+        let code = Self {
+            code: dec.get().ok()?,
+            relocs: dec.get().ok()?,
+            traps: dec.get().ok()?,
+        };
+        if dec.is_done() {
+            Some(code)
+        } else {
+            None
+        }
+    }
+
+    /// Encode this code for storing in a cache.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut enc = Encoder::new();
+        enc.put(&self.code[..]);
+        enc.put(&self.relocs[..]);
+        enc.put(&self.traps[..]);
+        enc.finish()
+    }
+
+    /// Append the machine code to `mem` and send the relocations and traps to the sinks.
+    pub fn replay(&self, mem: &mut Vec<u8>, relocs: &mut RelocSink, traps: &mut TrapSink) {
+        mem.extend_from_slice(&self.code);
+        for reloc in &self.relocs {
+            match *reloc {
+                CachedReloc::Ebb(offset, reloc, ebb_offset) => {
+                    relocs.reloc_ebb(offset, reloc, ebb_offset)
+                }
+                CachedReloc::External(offset, reloc, ref name, addend) => {
+                    relocs.reloc_external(offset, reloc, name, addend)
+                }
+                CachedReloc::JumpTable(offset, reloc, jt) => relocs.reloc_jt(offset, reloc, jt),
+            }
+        }
+        for trap in &self.traps {
+            traps.trap(trap.offset, trap.srcloc, trap.code);
+        }
+    }
+}
+
+/// A relocation received by a `RelocSink`.
+enum CachedReloc {
+    Ebb(CodeOffset, Reloc, CodeOffset),
+    External(CodeOffset, Reloc, ExternalName, Addend),
+    JumpTable(CodeOffset, Reloc, JumpTable),
+}
+
+/// A trap received by a `TrapSink`.
+struct CachedTrap {
+    offset: CodeOffset,
+    srcloc: SourceLoc,
+    code: TrapCode,
+}
+
+/// A `RelocSink` that records the relocations before forwarding them.
+pub(crate) struct RecordingRelocSink<'a> {
+    sink: &'a mut RelocSink,
+    relocs: Vec<CachedReloc>,
+}
+
+impl<'a> RecordingRelocSink<'a> {
+    /// Create a sink forwarding the relocations to `sink`.
+    pub fn new(sink: &'a mut RelocSink) -> Self {
+        Self {
+            sink,
+            relocs: Vec::new(),
+        }
+    }
+}
+
+impl<'a> RelocSink for RecordingRelocSink<'a> {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.relocs
+            .push(CachedReloc::Ebb(offset, reloc, ebb_offset));
+        self.sink.reloc_ebb(offset, reloc, ebb_offset);
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.relocs
+            .push(CachedReloc::External(offset, reloc, name.clone(), addend));
+        self.sink.reloc_external(offset, reloc, name, addend);
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.relocs.push(CachedReloc::JumpTable(offset, reloc, jt));
+        self.sink.reloc_jt(offset, reloc, jt);
+    }
+}
+
+/// A `TrapSink` that records the traps before forwarding them.
+pub(crate) struct RecordingTrapSink<'a> {
+    sink: &'a mut TrapSink,
+    traps: Vec<CachedTrap>,
+}
+
+impl<'a> RecordingTrapSink<'a> {
+    /// Create a sink forwarding the traps to `sink`.
+    pub fn new(sink: &'a mut TrapSink) -> Self {
+        Self {
+            sink,
+            traps: Vec::new(),
+        }
+    }
+}
+
+impl<'a> TrapSink for RecordingTrapSink<'a> {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode) {
+        self.traps.push(CachedTrap {
+            offset,
+            srcloc,
+            code,
+        });
+        self.sink.trap(offset, srcloc, code);
+    }
+}
+
+/// All the relocation kinds, in declaration order.
+const RELOCS: [Reloc; 11] = [
+    Reloc::Abs4,
+    Reloc::Abs8,
+    Reloc::X86PCRel4,
+    Reloc::X86CallPCRel4,
+    Reloc::X86CallPLTRel4,
+    Reloc::X86GOTPCRel4,
+    Reloc::ElfX86_64TlsGd,
+    Reloc::ElfX86_64GotTpOff,
+    Reloc::Arm32Call,
+    Reloc::Arm64Call,
+    Reloc::RiscvCall,
+];
+
+impl Encode for Reloc {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(&(*self as u8))
+    }
+}
+
+impl Decode for Reloc {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        RELOCS
+            .get(usize::from(dec.get::<u8>()?))
+            .cloned()
+            .ok_or(DeserializeError::Invalid("relocation"))
+    }
+}
+
+impl Encode for CachedReloc {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            CachedReloc::Ebb(offset, reloc, ebb_offset) => {
+                enc.put(&0u8);
+                enc.put(&offset);
+                enc.put(&reloc);
+                enc.put(&ebb_offset);
+            }
+            CachedReloc::External(offset, reloc, ref name, addend) => {
+                enc.put(&1u8);
+                enc.put(&offset);
+                enc.put(&reloc);
+                enc.put(name);
+                enc.put(&addend);
+            }
+            CachedReloc::JumpTable(offset, reloc, jt) => {
+                enc.put(&2u8);
+                enc.put(&offset);
+                enc.put(&reloc);
+                enc.put(&jt);
+            }
+        }
+    }
+}
+
+impl Decode for CachedReloc {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        match dec.get::<u8>()? {
+            0 => Ok(CachedReloc::Ebb(dec.get()?, dec.get()?, dec.get()?)),
+            1 => Ok(CachedReloc::External(
+                dec.get()?,
+                dec.get()?,
+                dec.get()?,
+                dec.get()?,
+            )),
+            2 => Ok(CachedReloc::JumpTable(dec.get()?, dec.get()?, dec.get()?)),
+            _ => Err(DeserializeError::Invalid("relocation")),
+        }
+    }
+}
+
+impl Encode for CachedTrap {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(&self.offset);
+        enc.put(&self.srcloc);
+        enc.put(&self.code);
+    }
+}
+
+impl Decode for CachedTrap {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        Ok(Self {
+            offset: dec.get()?,
+            srcloc: dec.get()?,
+            code: dec.get()?,
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "x86")]
+mod tests {
+    use super::{CacheKey, CodeCache};
+    use crate::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::I32;
+    use crate::ir::{
+        AbiParam, ExtFuncData, ExternalName, Function, InstBuilder, JumpTable, Signature,
+    };
+    use crate::isa::{self, CallConv};
+    use crate::settings::{self, Configurable};
+    use crate::Context;
+    use core::str::FromStr;
+    use std::string::ToString;
+    use std::vec::Vec;
+    use target_lexicon::triple;
+
+    #[derive(Default)]
+    struct MapCache(Vec<(CacheKey, Vec<u8>)>, usize);
+
+    impl CodeCache for MapCache {
+        fn get(&mut self, key: &CacheKey) -> Option<Vec<u8>> {
+            let entry = self.0.iter().find(|entry| entry.0 == *key)?;
+            self.1 += 1;
+            Some(entry.1.clone())
+        }
+
+        fn insert(&mut self, key: CacheKey, data: Vec<u8>) {
+            self.0.push((key, data));
+        }
+    }
+
+    #[derive(Default)]
+    struct ExternalRelocs(Vec<(CodeOffset, ExternalName)>);
+
+    impl RelocSink for ExternalRelocs {
+        fn reloc_ebb(&mut self, _: CodeOffset, _: Reloc, _: CodeOffset) {}
+
+        fn reloc_external(&mut self, offset: CodeOffset, _: Reloc, name: &ExternalName, _: Addend) {
+            self.0.push((offset, name.clone()));
+        }
+
+        fn reloc_jt(&mut self, _: CodeOffset, _: Reloc, _: JumpTable) {}
+    }
+
+    /// Make a function calling an external function.
+    fn make_function(imm: i64) -> Function {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.returns.push(AbiParam::new(I32));
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), sig.clone());
+        let sig0 = func.import_signature(sig);
+        let fn0 = func.import_function(ExtFuncData {
+            name: ExternalName::user(0, 1),
+            signature: sig0,
+            colocated: false,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_ebb(ebb0);
+        let call = cur.ins().call(fn0, &[]);
+        let v0 = cur.func.dfg.first_result(call);
+        let v1 = cur.ins().iadd_imm(v0, imm);
+        cur.ins().return_(&[v1]);
+        func
+    }
+
+    #[test]
+    fn cached() {
+        let mut flag_builder = settings::builder();
+        flag_builder.enable("is_pic").unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+        let mut cache = MapCache::default();
+
+        let compile = |func: Function, cache: &mut MapCache| {
+            let mut mem = Vec::new();
+            let mut relocs = ExternalRelocs::default();
+            Context::for_function(func)
+                .compile_and_emit_cached(&*isa, &mut mem, &mut relocs, &mut NullTrapSink {}, cache)
+                .unwrap();
+            (mem, relocs.0)
+        };
+
+        let (code, relocs) = compile(make_function(1), &mut cache);
+        assert_eq!(relocs.len(), 1);
+        assert_eq!(relocs[0].1, ExternalName::user(0, 1));
+        assert_eq!(cache.0.len(), 1);
+        assert_eq!(cache.1, 0);
+
+        // The same function is found in the cache.
+        assert_eq!(
+            compile(make_function(1), &mut cache),
+            (code.clone(), relocs)
+        );
+        assert_eq!(cache.0.len(), 1);
+        assert_eq!(cache.1, 1);
+
+        // A different function is compiled.
+        let (other_code, _) = compile(make_function(2), &mut cache);
+        assert_ne!(other_code, code);
+        assert_eq!(cache.0.len(), 2);
+        assert_eq!(cache.1, 1);
+    }
+
+    #[test]
+    fn budget_exceeded_not_cached() {
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let mut cache = MapCache::default();
+
+        let compile = |max_insts: Option<usize>, cache: &mut MapCache| {
+            let mut mem = Vec::new();
+            let mut ctx = Context::for_function(make_function(1));
+            ctx.budget.max_insts = max_insts;
+            ctx.compile_and_emit_cached(
+                &*isa,
+                &mut mem,
+                &mut ExternalRelocs::default(),
+                &mut NullTrapSink {},
+                cache,
+            )
+            .unwrap();
+            (mem, ctx.budget_exceeded())
+        };
+
+        // The code compiled with the reduced pipeline isn't cached.
+        let (_, exceeded) = compile(Some(1), &mut cache);
+        assert!(exceeded);
+        assert_eq!(cache.0.len(), 0);
+
+        // A compilation with an unlimited budget doesn't get the reduced code.
+        let (code, exceeded) = compile(None, &mut cache);
+        assert!(!exceeded);
+        assert_eq!(cache.1, 0);
+        assert_eq!(cache.0.len(), 1);
+        let mut full_code = Vec::new();
+        Context::for_function(make_function(1))
+            .compile_and_emit(
+                &*isa,
+                &mut full_code,
+                &mut ExternalRelocs::default(),
+                &mut NullTrapSink {},
+            )
+            .unwrap();
+        assert_eq!(code, full_code);
+    }
+
+    #[test]
+    fn key_depends_on_settings() {
+        let func = make_function(1);
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        let mut flag_builder = settings::builder();
+        flag_builder.set("opt_level", "none").unwrap();
+        let opt_isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder));
+        assert_eq!(CacheKey::new(&func, &*isa), CacheKey::new(&func, &*isa));
+        assert_ne!(CacheKey::new(&func, &*isa), CacheKey::new(&func, &*opt_isa));
+        assert_ne!(
+            CacheKey::new(&func, &*isa),
+            CacheKey::new(&make_function(2), &*isa)
+        );
+        assert_eq!(CacheKey::new(&func, &*isa).to_string().len(), 32);
+    }
+}
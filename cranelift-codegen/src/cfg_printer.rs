@@ -2,14 +2,18 @@
 
 use core::fmt::{Display, Formatter, Result, Write};
 
+use crate::dominator_tree::DominatorTree;
 use crate::flowgraph::{BasicBlock, ControlFlowGraph};
 use crate::ir::instructions::BranchInfo;
-use crate::ir::Function;
+use crate::ir::{Ebb, Function};
+use crate::loop_analysis::{Loop, LoopAnalysis};
 
 /// A utility for pretty-printing the CFG of a `Function`.
 pub struct CFGPrinter<'a> {
     func: &'a Function,
     cfg: ControlFlowGraph,
+    domtree: Option<DominatorTree>,
+    loop_analysis: Option<LoopAnalysis>,
 }
 
 /// A utility for pretty-printing the CFG of a `Function`.
@@ -19,14 +23,32 @@ impl<'a> CFGPrinter<'a> {
         Self {
             func,
             cfg: ControlFlowGraph::with_function(func),
+            domtree: None,
+            loop_analysis: None,
         }
     }
 
+    /// Also print the edges of the dominator tree, as dashed lines.
+    pub fn with_domtree(mut self) -> Self {
+        self.domtree = Some(DominatorTree::with_function(self.func, &self.cfg));
+        self
+    }
+
+    /// Group the EBBs of each loop in a cluster, nested like the loops.
+    pub fn with_loops(mut self) -> Self {
+        let domtree = DominatorTree::with_function(self.func, &self.cfg);
+        let mut loop_analysis = LoopAnalysis::new();
+        loop_analysis.compute(self.func, &self.cfg, &domtree);
+        self.loop_analysis = Some(loop_analysis);
+        self
+    }
+
     /// Write the CFG for this function to `w`.
     pub fn write(&self, w: &mut Write) -> Result {
         self.header(w)?;
         self.ebb_nodes(w)?;
         self.cfg_connections(w)?;
+        self.domtree_connections(w)?;
         writeln!(w, "}}")
     }
 
@@ -39,29 +61,79 @@ impl<'a> CFGPrinter<'a> {
     }
 
     fn ebb_nodes(&self, w: &mut Write) -> Result {
-        for ebb in &self.func.layout {
-            write!(w, "    {} [shape=record, label=\"{{{}", ebb, ebb)?;
-            // Add all outgoing branch instructions to the label.
-            for inst in self.func.layout.ebb_insts(ebb) {
-                let idata = &self.func.dfg[inst];
-                match idata.analyze_branch(&self.func.dfg.value_lists) {
-                    BranchInfo::SingleDest(dest, _) => {
-                        write!(w, " | <{}>{} {}", inst, idata.opcode(), dest)?
+        match self.loop_analysis {
+            Some(ref loop_analysis) => {
+                for ebb in &self.func.layout {
+                    if loop_analysis.innermost_loop(ebb).is_none() {
+                        self.ebb_node(w, ebb, 1)?;
                     }
-                    BranchInfo::Table(table, dest) => {
-                        write!(w, " | <{}>{} {}", inst, idata.opcode(), table)?;
-                        if let Some(dest) = dest {
-                            write!(w, " {}", dest)?
-                        }
+                }
+                for lp in loop_analysis.loops() {
+                    if loop_analysis.loop_parent(lp).is_none() {
+                        self.loop_cluster(w, loop_analysis, lp, 1)?;
                     }
-                    BranchInfo::NotABranch => {}
                 }
             }
-            writeln!(w, "}}\"]")?
+            None => {
+                for ebb in &self.func.layout {
+                    self.ebb_node(w, ebb, 1)?;
+                }
+            }
         }
         Ok(())
     }
 
+    fn loop_cluster(
+        &self,
+        w: &mut Write,
+        loop_analysis: &LoopAnalysis,
+        lp: Loop,
+        depth: usize,
+    ) -> Result {
+        let indent = depth * 4;
+        writeln!(w, "{:2$}subgraph cluster_{} {{", "", lp, indent)?;
+        writeln!(w, "{:2$}    label=\"{}\"", "", lp, indent)?;
+        for ebb in &self.func.layout {
+            if loop_analysis.innermost_loop(ebb) == Some(lp) {
+                self.ebb_node(w, ebb, depth + 1)?;
+            }
+        }
+        for child in loop_analysis.loops() {
+            if loop_analysis.loop_parent(child) == Some(lp) {
+                self.loop_cluster(w, loop_analysis, child, depth + 1)?;
+            }
+        }
+        writeln!(w, "{:1$}}}", "", indent)
+    }
+
+    fn ebb_node(&self, w: &mut Write, ebb: Ebb, depth: usize) -> Result {
+        write!(
+            w,
+            "{:3$}{} [shape=record, label=\"{{{}",
+            "",
+            ebb,
+            ebb,
+            depth * 4
+        )?;
+        // Add all outgoing branch instructions to the label.
+        for inst in self.func.layout.ebb_insts(ebb) {
+            let idata = &self.func.dfg[inst];
+            match idata.analyze_branch(&self.func.dfg.value_lists) {
+                BranchInfo::SingleDest(dest, _) => {
+                    write!(w, " | <{}>{} {}", inst, idata.opcode(), dest)?
+                }
+                BranchInfo::Table(table, dest) => {
+                    write!(w, " | <{}>{} {}", inst, idata.opcode(), table)?;
+                    if let Some(dest) = dest {
+                        write!(w, " {}", dest)?
+                    }
+                }
+                BranchInfo::NotABranch => {}
+            }
+        }
+        writeln!(w, "}}\"]")
+    }
+
     fn cfg_connections(&self, w: &mut Write) -> Result {
         for ebb in &self.func.layout {
             for BasicBlock { ebb: parent, inst } in self.cfg.pred_iter(ebb) {
@@ -70,6 +142,18 @@ impl<'a> CFGPrinter<'a> {
         }
         Ok(())
     }
+
+    fn domtree_connections(&self, w: &mut Write) -> Result {
+        if let Some(ref domtree) = self.domtree {
+            for ebb in &self.func.layout {
+                if let Some(idom) = domtree.idom(ebb) {
+                    let parent = self.func.layout.pp_ebb(idom);
+                    writeln!(w, "    {} -> {} [style=dashed, color=blue]", parent, ebb)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a> Display for CFGPrinter<'a> {
@@ -10,23 +10,36 @@
 //! single ISA instance.
 
 use crate::binemit::{
-    relax_branches, shrink_instructions, CodeOffset, MemoryCodeSink, RelocSink, TrapSink,
+    code_size, relax_branches_with_stats, shrink_instructions, CodeOffset, CodeOffsets, DeoptSink,
+    ExceptionSink, MemoryCodeSink, PatchSink, RelocSink, StackmapSink, TrapSink,
 };
+use crate::bounds_checks::do_bounds_check_elimination;
+use crate::budget::CompileBudget;
+use crate::cache::{CacheKey, CachedCode, CodeCache, RecordingRelocSink, RecordingTrapSink};
 use crate::dce::do_dce;
+use crate::dead_ebb_params::remove_dead_ebb_params;
 use crate::dominator_tree::DominatorTree;
+use crate::ebb_order::order_ebbs;
 use crate::flowgraph::ControlFlowGraph;
-use crate::ir::Function;
+use crate::inline::{inline_calls, inline_size_limit};
+use crate::ir::{ExternalName, Function};
 use crate::isa::TargetIsa;
 use crate::legalize_function;
+use crate::legalizer::check_encodings;
 use crate::licm::do_licm;
 use crate::loop_analysis::LoopAnalysis;
 use crate::nan_canonicalization::do_nan_canonicalization;
+use crate::null_checks::do_null_check_elision;
 use crate::postopt::do_postopt;
+use crate::redundant_moves::remove_redundant_moves;
 use crate::regalloc;
 use crate::result::CodegenResult;
+use crate::schedule::schedule_function;
 use crate::settings::{FlagsOrIsa, OptLevel};
 use crate::simple_gvn::do_simple_gvn;
 use crate::simple_preopt::do_preopt;
+use crate::simplify_cfg::simplify_cfg;
+use crate::stats::PassStats;
 use crate::timing;
 use crate::unreachable_code::eliminate_unreachable_code;
 use crate::verifier::{verify_context, verify_locations, VerifierErrors, VerifierResult};
@@ -48,6 +61,18 @@ pub struct Context {
 
     /// Loop analysis of `func`.
     pub loop_analysis: LoopAnalysis,
+
+    /// Limits on the effort spent optimizing `func` in `compile`.
+    pub budget: CompileBudget,
+
+    /// Counts of the transformations made by the passes since the context was cleared.
+    pub pass_stats: PassStats,
+
+    /// Did the last `compile` exceed the budget?
+    budget_exceeded: bool,
+
+    /// Code offsets of the EBBs of the compiled function.
+    code_offsets: CodeOffsets,
 }
 
 impl Context {
@@ -70,16 +95,42 @@ impl Context {
             domtree: DominatorTree::new(),
             regalloc: regalloc::Context::new(),
             loop_analysis: LoopAnalysis::new(),
+            budget: CompileBudget::unlimited(),
+            pass_stats: PassStats::default(),
+            budget_exceeded: false,
+            code_offsets: CodeOffsets::new(),
         }
     }
 
     /// Clear all data structures in this context.
+    ///
+    /// The budget is kept, so it applies to all the functions compiled with this context.
     pub fn clear(&mut self) {
         self.func.clear();
         self.cfg.clear();
         self.domtree.clear();
         self.regalloc.clear();
         self.loop_analysis.clear();
+        self.pass_stats = PassStats::default();
+        self.budget_exceeded = false;
+        self.code_offsets.clear();
+    }
+
+    /// Did the last call to `compile` exceed `self.budget`?
+    ///
+    /// The function was then finished with the baseline path, so a JIT compiler may want to
+    /// compile it again later with a larger budget.
+    pub fn budget_exceeded(&self) -> bool {
+        self.budget_exceeded
+    }
+
+    /// Get the code offsets of the EBBs of the compiled function.
+    ///
+    /// The table is recorded by the branch relaxation pass at the end of `compile`, and it stays
+    /// valid for the code generated by the emission functions. It is empty until the function
+    /// has been compiled, and after `compile_and_emit_cached` found the function in its cache.
+    pub fn code_offsets(&self) -> &CodeOffsets {
+        &self.code_offsets
     }
 
     /// Compile the function, and emit machine code into a `Vec<u8>`.
@@ -105,29 +156,96 @@ impl Context {
         Ok(())
     }
 
+    /// Compile the function and emit machine code into a `Vec<u8>`, reusing the results of an
+    /// earlier compilation when possible.
+    ///
+    /// This works like `compile_and_emit`, but the function is first looked up in `cache` with a
+    /// `CacheKey` computed from the function, `isa` and its settings. If it is found, the cached
+    /// machine code is appended to `mem` and the cached relocations and traps are sent to
+    /// `relocs` and `traps`. The function isn't compiled in that case, so it is left unchanged.
+    ///
+    /// Otherwise, the function is compiled and the results are stored in `cache`, unless the
+    /// compilation exceeded `self.budget`. The cache key doesn't depend on the budget, so the
+    /// code produced by the reduced pipeline isn't cached where a later compilation with a larger
+    /// budget would find it.
+    pub fn compile_and_emit_cached(
+        &mut self,
+        isa: &TargetIsa,
+        mem: &mut Vec<u8>,
+        relocs: &mut RelocSink,
+        traps: &mut TrapSink,
+        cache: &mut CodeCache,
+    ) -> CodegenResult<()> {
+        let key = {
+            let _tt = timing::code_cache();
+            let key = CacheKey::new(&self.func, isa);
+            if let Some(code) = cache.get(&key).and_then(|data| CachedCode::decode(&data)) {
+                code.replay(mem, relocs, traps);
+                self.code_offsets.clear();
+                return Ok(());
+            }
+            key
+        };
+
+        let old_len = mem.len();
+        let mut recording_relocs = RecordingRelocSink::new(relocs);
+        let mut recording_traps = RecordingTrapSink::new(traps);
+        self.compile_and_emit(isa, mem, &mut recording_relocs, &mut recording_traps)?;
+        if !self.budget_exceeded {
+            let code = CachedCode::new(&mem[old_len..], recording_relocs, recording_traps);
+            cache.insert(key, code.encode());
+        }
+        Ok(())
+    }
+
     /// Compile the function.
     ///
     /// Run the function through all the passes necessary to generate code for the target ISA
     /// represented by `isa`. This does not include the final step of emitting machine code into a
     /// code sink.
     ///
-    /// Returns the size of the function's code.
+    /// The `opt_level` setting selects the optimization passes: `none` only runs the passes needed
+    /// to generate correct code, `speed` adds the IR optimizations, and `speed_and_size` also runs
+    /// loop invariant code motion and global value numbering, and shrinks the instruction
+    /// encodings.
+    ///
+    /// When `self.budget` is exceeded, the remaining optimization passes are skipped and the
+    /// function is finished with the baseline path: only the passes needed to generate correct
+    /// code are run, and registers are allocated with `regalloc_baseline`. The generated code then
+    /// depends on the compilation time, unless only the instruction count is limited.
+    ///
+    /// Compilation is deterministic: the generated code only depends on the function and the
+    /// settings of `isa`, so compiling the same function twice produces the same code, even on
+    /// different hosts.
+    ///
+    /// Returns the size of the function's code, or an error describing why the function can't be
+    /// compiled, like an `Unsupported` instruction for the target ISA.
     pub fn compile(&mut self, isa: &TargetIsa) -> CodegenResult<CodeOffset> {
         let _tt = timing::compile();
         self.verify_if(isa)?;
 
+        let opt_level = isa.flags().opt_level();
+        let mut budget = self.budget.start(&self.func);
+        self.budget_exceeded = false;
+
         self.compute_cfg();
-        if isa.flags().opt_level() != OptLevel::Fastest {
+        if opt_level != OptLevel::None && !budget.exceeded() {
             self.preopt(isa)?;
+            self.simplify_cfg(isa)?;
+            self.compute_domtree();
+            self.eliminate_bounds_checks(isa)?;
         }
         if isa.flags().enable_nan_canonicalization() {
             self.canonicalize_nans(isa)?;
         }
+        if isa.flags().elide_null_checks() {
+            self.elide_null_checks(isa)?;
+        }
         self.legalize(isa)?;
-        if isa.flags().opt_level() != OptLevel::Fastest {
+        if opt_level != OptLevel::None && !budget.exceeded() {
             self.postopt(isa)?;
         }
-        if isa.flags().opt_level() == OptLevel::Best {
+        if opt_level == OptLevel::SpeedAndSize && !budget.exceeded() {
             self.compute_domtree();
             self.compute_loop_analysis();
             self.licm(isa)?;
@@ -135,14 +253,26 @@ impl Context {
         }
         self.compute_domtree();
         self.eliminate_unreachable_code(isa)?;
-        if isa.flags().opt_level() != OptLevel::Fastest {
+        if opt_level != OptLevel::None && !budget.exceeded() {
+            self.dead_ebb_params(isa)?;
             self.dce(isa)?;
         }
-        self.regalloc(isa)?;
+        check_encodings(&self.func, isa)?;
+        if budget.exceeded() {
+            self.regalloc_baseline(isa)?;
+        } else {
+            self.regalloc(isa)?;
+            self.remove_redundant_moves(isa)?;
+            if isa.flags().enable_scheduling() {
+                self.schedule(isa)?;
+            }
+        }
         self.prologue_epilogue(isa)?;
-        if isa.flags().opt_level() == OptLevel::Best {
+        if opt_level == OptLevel::SpeedAndSize && !budget.exceeded() {
             self.shrink_instructions(isa)?;
         }
+        self.budget_exceeded = budget.exceeded();
+        self.order_ebbs(isa)?;
         self.relax_branches(isa)
     }
 
@@ -166,6 +296,112 @@ impl Context {
         isa.emit_function_to_memory(&self.func, &mut MemoryCodeSink::new(mem, relocs, traps));
     }
 
+    /// Emit machine code directly into raw memory, and report the landing pads of calls.
+    ///
+    /// This works like `emit_to_memory`, but the landing pad of every call that can unwind is
+    /// also sent to `exceptions`, so the caller can build the function's exception tables.
+    ///
+    /// This function is unsafe for the same reasons as `emit_to_memory`.
+    pub unsafe fn emit_to_memory_with_exceptions(
+        &self,
+        isa: &TargetIsa,
+        mem: *mut u8,
+        relocs: &mut RelocSink,
+        traps: &mut TrapSink,
+        exceptions: &mut ExceptionSink,
+    ) {
+        let _tt = timing::binemit();
+        isa.emit_function_to_memory(
+            &self.func,
+            &mut MemoryCodeSink::with_exceptions(mem, relocs, traps, exceptions),
+        );
+    }
+
+    /// Emit machine code directly into raw memory, and report the deoptimization maps of
+    /// safepoints.
+    ///
+    /// This works like `emit_to_memory`, but the locations of the values captured by every
+    /// `safepoint` instruction are also sent to `deopts`.
+    ///
+    /// This function is unsafe for the same reasons as `emit_to_memory`.
+    pub unsafe fn emit_to_memory_with_deopts(
+        &self,
+        isa: &TargetIsa,
+        mem: *mut u8,
+        relocs: &mut RelocSink,
+        traps: &mut TrapSink,
+        deopts: &mut DeoptSink,
+    ) {
+        let _tt = timing::binemit();
+        isa.emit_function_to_memory(
+            &self.func,
+            &mut MemoryCodeSink::with_deopts(mem, relocs, traps, deopts),
+        );
+    }
+
+    /// Emit machine code directly into raw memory, and report the stack maps of calls.
+    ///
+    /// This works like `emit_to_memory`, but the stack offsets of the references live across every
+    /// call are also sent to `stackmaps`. The function must have been compiled with the
+    /// `enable_safepoints` setting for the stack maps to be recorded.
+    ///
+    /// This function is unsafe for the same reasons as `emit_to_memory`.
+    pub unsafe fn emit_to_memory_with_stackmaps(
+        &self,
+        isa: &TargetIsa,
+        mem: *mut u8,
+        relocs: &mut RelocSink,
+        traps: &mut TrapSink,
+        stackmaps: &mut StackmapSink,
+    ) {
+        let _tt = timing::binemit();
+        isa.emit_function_to_memory(
+            &self.func,
+            &mut MemoryCodeSink::with_stackmaps(mem, relocs, traps, stackmaps),
+        );
+    }
+
+    /// Emit machine code directly into raw memory, and report the patchable ranges.
+    ///
+    /// This works like `emit_to_memory`, but the ranges of the instructions which can be patched
+    /// after compilation, like the direct calls and jumps emitted with the `patchable_calls` and
+    /// `patchable_branches` settings, are also sent to `patches`.
+    ///
+    /// This function is unsafe for the same reasons as `emit_to_memory`.
+    pub unsafe fn emit_to_memory_with_patches(
+        &self,
+        isa: &TargetIsa,
+        mem: *mut u8,
+        relocs: &mut RelocSink,
+        traps: &mut TrapSink,
+        patches: &mut PatchSink,
+    ) {
+        let _tt = timing::binemit();
+        isa.emit_function_to_memory(
+            &self.func,
+            &mut MemoryCodeSink::with_patches(mem, relocs, traps, patches),
+        );
+    }
+
+    /// Get the size of the code that the emission functions will generate for the function.
+    ///
+    /// The size is computed from the encoding recipes without emitting anything. Once the
+    /// function has been compiled, it is the same size as the one returned by `compile`.
+    /// Between register allocation and branch relaxation, it is an estimate which doesn't
+    /// account for the branches that will be relaxed, see `binemit::code_size`.
+    pub fn code_size(&self, isa: &TargetIsa) -> CodeOffset {
+        code_size(&self.func, isa)
+    }
+
+    /// Emit the unwind information of the compiled function into `mem`.
+    ///
+    /// For `windows_fastcall` functions on x86-64, this is the Windows `UNWIND_INFO` structure
+    /// describing the prologue. It must be placed at a 4-byte aligned address. Nothing is emitted
+    /// for functions without unwind information.
+    pub fn emit_unwind_info(&self, isa: &TargetIsa, mem: &mut Vec<u8>) {
+        isa.emit_unwind_info(&self.func, mem);
+    }
+
     /// Run the verifier on the function.
     ///
     /// Also check that the dominator tree and control flow graph are consistent with the function.
@@ -216,6 +452,16 @@ impl Context {
         Ok(())
     }
 
+    /// Remove EBB parameters that are unused or always receive the same value.
+    pub fn dead_ebb_params<'a, FOI: Into<FlagsOrIsa<'a>>>(
+        &mut self,
+        fisa: FOI,
+    ) -> CodegenResult<()> {
+        remove_dead_ebb_params(&mut self.func, &self.cfg);
+        self.verify_if(fisa)?;
+        Ok(())
+    }
+
     /// Perform pre-legalization rewrites on the function.
     pub fn preopt(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
         do_preopt(&mut self.func);
@@ -223,6 +469,55 @@ impl Context {
         Ok(())
     }
 
+    /// Inline the direct calls to the functions returned by `callees`.
+    ///
+    /// The `callees` function is given the name of each called function and returns its IR, if it
+    /// is available. The `opt_level` setting limits the size of the inlined callees: nothing is
+    /// inlined at `none`, and `speed` inlines larger callees than `speed_and_size`. Neither the
+    /// callees nor this function can have been legalized.
+    pub fn inline<'a, 'b, FOI, F>(&mut self, fisa: FOI, callees: F) -> CodegenResult<()>
+    where
+        FOI: Into<FlagsOrIsa<'a>>,
+        F: FnMut(&ExternalName) -> Option<&'b Function>,
+    {
+        let fisa = fisa.into();
+        let size_limit = inline_size_limit(fisa.flags.opt_level());
+        if size_limit > 0 && inline_calls(&mut self.func, callees, size_limit) {
+            // Inlining changes the CFG, so it invalidates the analyses.
+            self.cfg.clear();
+            self.domtree.clear();
+            self.loop_analysis.clear();
+        }
+        self.verify_if(fisa)
+    }
+
+    /// Merge EBBs and thread jumps to simplify the control flow graph.
+    pub fn simplify_cfg<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        // Simplification changes the CFG, so it invalidates the domtree and loop analysis.
+        self.domtree.clear();
+        self.loop_analysis.clear();
+        simplify_cfg(&mut self.func, &mut self.cfg);
+        self.verify_if(fisa)
+    }
+
+    /// Remove the bounds checks which are made redundant by dominating checks.
+    pub fn eliminate_bounds_checks<'a, FOI>(&mut self, fisa: FOI) -> CodegenResult<()>
+    where
+        FOI: Into<FlagsOrIsa<'a>>,
+    {
+        do_bounds_check_elimination(&mut self.func, &self.domtree);
+        self.verify_if(fisa)
+    }
+
+    /// Replace the null checks in the function with faulting memory accesses.
+    pub fn elide_null_checks<'a, FOI: Into<FlagsOrIsa<'a>>>(
+        &mut self,
+        fisa: FOI,
+    ) -> CodegenResult<()> {
+        do_null_check_elision(&mut self.func);
+        self.verify_if(fisa)
+    }
+
     /// Perform NaN canonicalizing rewrites on the function.
     pub fn canonicalize_nans(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
         do_nan_canonicalization(&mut self.func);
@@ -235,7 +530,7 @@ impl Context {
         // TODO: Avoid doing this when legalization doesn't actually mutate the CFG.
         self.domtree.clear();
         self.loop_analysis.clear();
-        legalize_function(&mut self.func, &mut self.cfg, isa);
+        legalize_function(&mut self.func, &mut self.cfg, isa)?;
         self.verify_if(isa)
     }
 
@@ -270,7 +565,7 @@ impl Context {
 
     /// Perform simple GVN on the function.
     pub fn simple_gvn<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
-        do_simple_gvn(&mut self.func, &mut self.domtree);
+        do_simple_gvn(&mut self.func, &mut self.domtree, &mut self.pass_stats);
         self.verify_if(fisa)
     }
 
@@ -297,8 +592,42 @@ impl Context {
 
     /// Run the register allocator.
     pub fn regalloc(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
-        self.regalloc
-            .run(isa, &mut self.func, &self.cfg, &mut self.domtree)
+        self.regalloc.run(
+            isa,
+            &mut self.func,
+            &self.cfg,
+            &mut self.domtree,
+            &mut self.pass_stats,
+        )
+    }
+
+    /// Run the register allocator without the passes that only improve the code.
+    ///
+    /// This is used to finish a function when the compile budget is exceeded.
+    pub fn regalloc_baseline(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
+        self.regalloc.run_baseline(
+            isa,
+            &mut self.func,
+            &self.cfg,
+            &mut self.domtree,
+            &mut self.pass_stats,
+        )
+    }
+
+    /// Delete the no-op moves left behind by the register allocator.
+    pub fn remove_redundant_moves(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
+        remove_redundant_moves(&mut self.func, isa);
+        self.verify_if(isa)?;
+        self.verify_locations_if(isa)?;
+        Ok(())
+    }
+
+    /// Reorder the instructions within each EBB to hide instruction latencies.
+    pub fn schedule(&mut self, isa: &TargetIsa) -> CodegenResult<()> {
+        schedule_function(&mut self.func, isa);
+        self.verify_if(isa)?;
+        self.verify_locations_if(isa)?;
+        Ok(())
     }
 
     /// Insert prologue and epilogues after computing the stack frame layout.
@@ -317,11 +646,280 @@ impl Context {
         Ok(())
     }
 
-    /// Run the branch relaxation pass and return the final code size.
+    /// Run the EBB ordering pass which moves cold EBBs to the end of the function and chooses
+    /// the fallthrough direction of likely branches.
+    pub fn order_ebbs<'a, FOI: Into<FlagsOrIsa<'a>>>(&mut self, fisa: FOI) -> CodegenResult<()> {
+        let fisa = fisa.into();
+        order_ebbs(&mut self.func, fisa.isa);
+        self.verify_if(fisa)
+    }
+
+    /// Run the branch relaxation pass, record the code offsets of the EBBs and return the final
+    /// code size.
     pub fn relax_branches(&mut self, isa: &TargetIsa) -> CodegenResult<CodeOffset> {
-        let code_size = relax_branches(&mut self.func, isa)?;
+        let code_size = relax_branches_with_stats(&mut self.func, isa, &mut self.pass_stats)?;
+        self.code_offsets.record(&self.func, code_size);
         self.verify_if(isa)?;
         self.verify_locations_if(isa)?;
         Ok(code_size)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Context;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::I32;
+    use crate::ir::{ExternalName, Function, InstBuilder, JumpTableData};
+    use crate::isa::TargetIsa;
+    use crate::settings::Flags;
+    use std::boxed::Box;
+    use std::string::ToString;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn thread_safety() {
+        // A context can be moved to a compilation thread, and the ISA shared between them.
+        assert_send::<Context>();
+        assert_send::<Box<TargetIsa>>();
+        assert_sync::<Box<TargetIsa>>();
+        assert_send::<Flags>();
+        assert_sync::<Flags>();
+    }
+
+    #[test]
+    fn clear() {
+        let mut ctx = Context::new();
+        ctx.func.name = ExternalName::testcase("cleared");
+        let ebb0 = ctx.func.dfg.make_ebb();
+        let mut jt = JumpTableData::new();
+        jt.push_entry(ebb0);
+        let jt = ctx.func.create_jump_table(jt);
+        ctx.func.jt_offsets[jt] = 8;
+        {
+            let mut cur = FuncCursor::new(&mut ctx.func);
+            cur.insert_ebb(ebb0);
+            let v0 = cur.ins().iconst(I32, 0);
+            cur.ins().br_table(v0, ebb0, jt);
+        }
+        ctx.flowgraph();
+
+        ctx.clear();
+        assert_eq!(
+            ctx.func.display(None).to_string(),
+            Function::new().display(None).to_string()
+        );
+        assert_eq!(ctx.func.jt_offsets[jt], 0);
+        assert!(!ctx.cfg.is_valid());
+    }
+
+    /// Make a function with two groups of values live across two different calls, so more
+    /// registers are needed than available, but the spill slots of the groups can be shared.
+    #[cfg(feature = "x86")]
+    fn make_spilling_function() -> Function {
+        use crate::ir::{AbiParam, ExtFuncData, Signature};
+        use crate::isa::CallConv;
+        use std::vec::Vec;
+
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), sig.clone());
+        let sig0 = func.import_signature(sig);
+        let fn0 = func.import_function(ExtFuncData {
+            name: ExternalName::user(0, 1),
+            signature: sig0,
+            colocated: true,
+        });
+        let ebb0 = func.dfg.make_ebb();
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_ebb(ebb0);
+        let mut sum = cur.func.dfg.append_ebb_param(ebb0, I32);
+        for _ in 0..2 {
+            let values: Vec<_> = (0..16).map(|i| cur.ins().iadd_imm(sum, i)).collect();
+            let call = cur.ins().call(fn0, &[sum]);
+            sum = cur.func.dfg.first_result(call);
+            for value in values {
+                sum = cur.ins().iadd(sum, value);
+            }
+        }
+        cur.ins().return_(&[sum]);
+        func
+    }
+
+    #[cfg(feature = "x86")]
+    fn num_spill_slots(ctx: &Context) -> usize {
+        use crate::ir::StackSlotKind;
+        ctx.func
+            .stack_slots
+            .values()
+            .filter(|ss| ss.kind == StackSlotKind::SpillSlot)
+            .count()
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn code_size() {
+        use crate::isa;
+        use crate::settings;
+        use target_lexicon::triple;
+
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(Flags::new(settings::builder()));
+
+        let mut ctx = Context::for_function(make_spilling_function());
+        let size = ctx.compile(&*isa).unwrap();
+        assert!(size > 0);
+        assert_eq!(ctx.code_size(&*isa), size);
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn code_offsets() {
+        use crate::isa;
+        use crate::settings;
+        use target_lexicon::triple;
+
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(Flags::new(settings::builder()));
+
+        let mut ctx = Context::for_function(make_spilling_function());
+        assert!(ctx.code_offsets().is_empty());
+        let size = ctx.compile(&*isa).unwrap();
+
+        let offsets = ctx.code_offsets();
+        assert_eq!(offsets.code_end(), size);
+        let entry = ctx.func.layout.entry_block().unwrap();
+        assert_eq!(offsets.ebb_offset(entry), Some(0));
+        for (ebb, offset) in offsets.ebbs() {
+            assert_eq!(ctx.func.offsets[ebb], offset);
+        }
+        assert_eq!(
+            offsets.ebb_at(size - 1),
+            offsets.ebbs().last().map(|(ebb, _)| ebb)
+        );
+
+        ctx.clear();
+        assert!(ctx.code_offsets().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn compile_budget() {
+        use crate::isa;
+        use crate::settings::{self, Configurable};
+        use target_lexicon::triple;
+
+        let mut flag_builder = settings::builder();
+        flag_builder.set("opt_level", "speed").unwrap();
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(Flags::new(flag_builder));
+
+        let mut ctx = Context::for_function(make_spilling_function());
+        ctx.budget.max_insts = Some(1000);
+        ctx.compile(&*isa).unwrap();
+        assert!(!ctx.budget_exceeded());
+
+        // The function is too large, so it is finished with the baseline path, which doesn't
+        // share spill slots.
+        let mut limited_ctx = Context::for_function(make_spilling_function());
+        limited_ctx.budget.max_insts = Some(10);
+        limited_ctx.compile(&*isa).unwrap();
+        assert!(limited_ctx.budget_exceeded());
+        limited_ctx.verify_locations(&*isa).unwrap();
+        assert!(num_spill_slots(&limited_ctx) > num_spill_slots(&ctx));
+
+        // The budget is kept when the context is cleared.
+        limited_ctx.clear();
+        assert!(!limited_ctx.budget_exceeded());
+        assert_eq!(limited_ctx.budget.max_insts, Some(10));
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn pass_stats() {
+        use crate::isa;
+        use crate::settings;
+        use crate::stats::PassStats;
+        use target_lexicon::triple;
+
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(Flags::new(settings::builder()));
+        let mut ctx = Context::for_function(make_spilling_function());
+        ctx.compile(&*isa).unwrap();
+        let stats = ctx.pass_stats;
+        assert!(stats.spills > 0);
+        assert!(stats.fills > 0);
+        assert!(stats
+            .to_string()
+            .contains(&format!("spills: {}\n", stats.spills)));
+
+        // The statistics of several functions can be added up.
+        let mut total = PassStats::default();
+        total.add(&stats);
+        total.add(&stats);
+        assert_eq!(total.fills, 2 * stats.fills);
+
+        ctx.clear();
+        assert_eq!(ctx.pass_stats, PassStats::default());
+    }
+
+    #[test]
+    #[cfg(feature = "riscv")]
+    fn unsupported_inst() {
+        use crate::ir::{AbiParam, Signature, SourceLoc};
+        use crate::isa::{self, CallConv};
+        use crate::print_errors::pretty_error;
+        use crate::result::CodegenError;
+        use crate::settings;
+        use target_lexicon::triple;
+
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("bsf"), sig);
+        let ebb0 = func.dfg.make_ebb();
+        let bsf = {
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_ebb(ebb0);
+            let v0 = cur.func.dfg.append_ebb_param(ebb0, I32);
+            cur.set_srcloc(SourceLoc::new(42));
+            let (v1, _) = cur.ins().x86_bsf(v0);
+            let bsf = cur.func.dfg.value_def(v1).unwrap_inst();
+            cur.ins().return_(&[v1]);
+            bsf
+        };
+
+        // RISC-V has no encoding for the x86 instructions.
+        let isa = isa::lookup(triple!("riscv32"))
+            .unwrap()
+            .finish(Flags::new(settings::builder()));
+        let mut ctx = Context::for_function(func);
+        let err = ctx.compile(&*isa).unwrap_err();
+        match err {
+            CodegenError::Unsupported {
+                inst,
+                srcloc,
+                ref message,
+            } => {
+                assert_eq!(inst, bsf);
+                assert_eq!(srcloc, SourceLoc::new(42));
+                assert!(
+                    message.starts_with("x86_bsf.i32 is not supported by riscv"),
+                    "{}",
+                    message
+                );
+                assert!(message.ends_with("v1, v2 = x86_bsf v0"), "{}", message);
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(pretty_error(&ctx.func, Some(&*isa), err)
+            .contains("; error: inst0: x86_bsf.i32 is not supported by riscv"));
+    }
+}
@@ -0,0 +1,192 @@
+//! Dead EBB parameter removal.
+//!
+//! The translation of structured control flow creates EBB parameters for every value that may be
+//! different on the incoming edges of a join point, and many of them end up unused, or receiving
+//! the same value from all predecessors. Every EBB parameter costs an argument shuffle on the
+//! incoming branches, so this pass removes them:
+//!
+//! - A parameter that is only used as a branch argument for dead parameters is dead itself, and it
+//!   is removed along with the branch arguments passed to it.
+//! - A parameter that receives the same value from all predecessors, ignoring branches that pass
+//!   the parameter back to itself, is replaced by that value.
+//!
+//! Parameters of the entry block and of jump table targets are left alone.
+
+use crate::entity::EntityRef;
+use crate::flowgraph::{BasicBlock, ControlFlowGraph};
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Ebb, Function, Value, ValueDef};
+use crate::timing;
+use log::debug;
+use std::vec::Vec;
+
+/// Remove the dead and redundant EBB parameters in `func`.
+pub fn remove_dead_ebb_params(func: &mut Function, cfg: &ControlFlowGraph) {
+    let _tt = timing::dead_ebb_params();
+    debug_assert!(cfg.is_valid());
+
+    // Replacing a parameter can make another one redundant, so iterate to a fixed point.
+    while remove_params(func, cfg) {}
+}
+
+/// Can the parameters of `ebb` be changed?
+///
+/// This requires rewriting the arguments of all the predecessor branches, which is not possible
+/// for the entry block or jump tables.
+fn is_removable(func: &Function, cfg: &ControlFlowGraph, ebb: Ebb) -> bool {
+    if func.layout.entry_block() == Some(ebb) {
+        return false;
+    }
+    cfg.pred_iter(ebb)
+        .all(|pred| match func.dfg.analyze_branch(pred.inst) {
+            BranchInfo::SingleDest(..) => true,
+            _ => false,
+        })
+}
+
+/// Compute the live values in `func`.
+///
+/// A value is live if it is used by an instruction other than as an EBB argument, or if it is
+/// passed as an EBB argument to a live parameter.
+fn compute_live(func: &Function, cfg: &ControlFlowGraph) -> Vec<bool> {
+    let mut live = vec![false; func.dfg.num_values()];
+    let mut worklist = Vec::new();
+
+    {
+        let mut mark = |value: Value, worklist: &mut Vec<Value>| {
+            let value = func.dfg.resolve_aliases(value);
+            if !live[value.index()] {
+                live[value.index()] = true;
+                worklist.push(value);
+            }
+        };
+
+        for ebb in func.layout.ebbs() {
+            if !is_removable(func, cfg, ebb) {
+                for &param in func.dfg.ebb_params(ebb) {
+                    mark(param, &mut worklist);
+                }
+            }
+            for inst in func.layout.ebb_insts(ebb) {
+                let args = match func.dfg.analyze_branch(inst) {
+                    BranchInfo::SingleDest(..) if !func.dfg[inst].opcode().is_call() => {
+                        func.dfg.inst_fixed_args(inst)
+                    }
+                    _ => func.dfg.inst_args(inst),
+                };
+                for &arg in args {
+                    mark(arg, &mut worklist);
+                }
+            }
+        }
+
+        while let Some(value) = worklist.pop() {
+            if let ValueDef::Param(ebb, num) = func.dfg.value_def(value) {
+                for BasicBlock { inst, .. } in cfg.pred_iter(ebb) {
+                    if let BranchInfo::SingleDest(_, args) = func.dfg.analyze_branch(inst) {
+                        mark(args[num], &mut worklist);
+                    }
+                }
+            }
+        }
+    }
+
+    live
+}
+
+/// Get the single value other than `param` itself that is passed to `param` by all the
+/// predecessors of `ebb`.
+fn single_incoming_value(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    ebb: Ebb,
+    num: usize,
+    param: Value,
+) -> Option<Value> {
+    let mut incoming = None;
+    for BasicBlock { inst, .. } in cfg.pred_iter(ebb) {
+        let arg = match func.dfg.analyze_branch(inst) {
+            BranchInfo::SingleDest(_, args) => func.dfg.resolve_aliases(args[num]),
+            _ => return None,
+        };
+        if arg == param || incoming == Some(arg) {
+            continue;
+        }
+        if incoming.is_some() {
+            return None;
+        }
+        incoming = Some(arg);
+    }
+
+    // A value defined in `ebb` itself can only reach `ebb` through back edges, so it can't
+    // replace the parameter. This only happens in unreachable code.
+    let value = incoming?;
+    let def_ebb = match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => func.layout.inst_ebb(inst),
+        ValueDef::Param(def_ebb, _) => Some(def_ebb),
+    };
+    if def_ebb == Some(ebb) {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Remove the parameter number `num` of `ebb` and the corresponding branch arguments. If
+/// `replacement` is given, the parameter becomes an alias of it.
+fn remove_param(
+    func: &mut Function,
+    cfg: &ControlFlowGraph,
+    ebb: Ebb,
+    num: usize,
+    replacement: Option<Value>,
+) {
+    for BasicBlock { inst, .. } in cfg.pred_iter(ebb) {
+        let num_fixed = func.dfg[inst]
+            .opcode()
+            .constraints()
+            .num_fixed_value_arguments();
+        let mut vlist = func.dfg[inst]
+            .take_value_list()
+            .expect("Branches must have value lists.");
+        vlist.remove(num_fixed + num, &mut func.dfg.value_lists);
+        func.dfg[inst].put_value_list(vlist);
+    }
+
+    let param = func.dfg.ebb_params(ebb)[num];
+    func.dfg.remove_ebb_param(param);
+    if let Some(value) = replacement {
+        debug!("Replacing {} with {}", param, value);
+        func.dfg.change_to_alias(param, value);
+    } else {
+        debug!("Removing dead {}", param);
+    }
+}
+
+/// Make one pass over `func` removing parameters. Returns true if anything was changed.
+fn remove_params(func: &mut Function, cfg: &ControlFlowGraph) -> bool {
+    let live = compute_live(func, cfg);
+    let mut changed = false;
+
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        if !is_removable(func, cfg, ebb) {
+            continue;
+        }
+
+        // Visit the parameters in reverse order so removals don't change the numbers of the
+        // parameters we haven't seen yet.
+        for num in (0..func.dfg.num_ebb_params(ebb)).rev() {
+            let param = func.dfg.ebb_params(ebb)[num];
+            if !live[param.index()] {
+                remove_param(func, cfg, ebb, num, None);
+                changed = true;
+            } else if let Some(value) = single_incoming_value(func, cfg, ebb, num, param) {
+                remove_param(func, cfg, ebb, num, Some(value));
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
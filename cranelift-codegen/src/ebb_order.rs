@@ -0,0 +1,215 @@
+//! EBB ordering.
+//!
+//! The order of EBBs in the layout doesn't affect the semantics of a function, but it does affect
+//! the quality of the generated code: a jump to the next EBB becomes a fallthrough which emits no
+//! code, and keeping the frequently executed code together improves the instruction cache
+//! density.
+//!
+//! This pass runs right before branch relaxation. It moves the EBBs that have been marked as cold
+//! and the landing pads of `try_call` instructions to the end of the function, keeping their
+//! relative order. EBBs that are tied to their layout
+//! neighbors by a `fallthrough` or `fallthrough_return` instruction are not moved.
+//!
+//! Then it uses the branch probabilities to choose the fallthrough direction: when a conditional
+//! branch that is likely taken goes to the next EBB and is followed by a `jump`, the branch
+//! condition is inverted and the destinations are swapped. The likely path then falls through
+//! while the unlikely path takes the branch.
+
+use crate::entity::EntitySet;
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Ebb, Function, Inst, InstructionData, Opcode, Value};
+use crate::isa::TargetIsa;
+use crate::iterators::IteratorExtras;
+use crate::timing;
+use log::debug;
+use std::vec::Vec;
+
+/// Reorder the EBBs in `func`.
+///
+/// If `func` has been encoded, `isa` is needed to encode the inverted branches.
+pub fn order_ebbs(func: &mut Function, isa: Option<&TargetIsa>) {
+    let _tt = timing::ebb_order();
+    sink_cold_ebbs(func);
+    choose_fallthroughs(func, isa);
+}
+
+/// Move the cold EBBs to the end of `func`.
+fn sink_cold_ebbs(func: &mut Function) {
+    let entry = func.layout.entry_block();
+    let pads = landing_pads(func);
+    let cold: Vec<Ebb> = func
+        .layout
+        .ebbs()
+        .filter(|&ebb| {
+            Some(ebb) != entry
+                && (func.layout.is_cold(ebb) || pads.contains(ebb))
+                && is_movable(func, ebb)
+        })
+        .collect();
+    if cold.is_empty() {
+        return;
+    }
+
+    let before = cold_insertion_point(func);
+    for ebb in cold {
+        debug!("Sinking cold {}", ebb);
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for &inst in &insts {
+            func.layout.remove_inst(inst);
+        }
+        func.layout.remove_ebb(ebb);
+        match before {
+            Some(before) => func.layout.insert_ebb(ebb, before),
+            None => func.layout.append_ebb(ebb),
+        }
+        for inst in insts {
+            func.layout.append_inst(inst, ebb);
+        }
+    }
+}
+
+/// Get the EBB that cold EBBs should be inserted before, or `None` to append them to the layout.
+///
+/// EBBs ending in a `fallthrough_return` must stay at the end, along with the EBBs falling through
+/// to them. The cold EBBs go right before them.
+pub fn cold_insertion_point(func: &Function) -> Option<Ebb> {
+    let mut before = match func.layout.last_ebb() {
+        Some(last) if terminator(func, last) == Some(Opcode::FallthroughReturn) => Some(last),
+        _ => None,
+    };
+    while let Some(prev) = before.and_then(|ebb| func.layout.prev_ebb(ebb)) {
+        if terminator(func, prev) != Some(Opcode::Fallthrough) {
+            break;
+        }
+        before = Some(prev);
+    }
+    before
+}
+
+/// Get the landing pads of the calls in `func`.
+fn landing_pads(func: &Function) -> EntitySet<Ebb> {
+    let mut pads = EntitySet::new();
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if func.dfg[inst].opcode().is_call() {
+                if let Some(pad) = func.dfg[inst].branch_destination() {
+                    pads.insert(pad);
+                }
+            }
+        }
+    }
+    pads
+}
+
+/// Can `ebb` be moved without breaking a `fallthrough` or `fallthrough_return` instruction?
+fn is_movable(func: &Function, ebb: Ebb) -> bool {
+    match terminator(func, ebb) {
+        Some(Opcode::Fallthrough) | Some(Opcode::FallthroughReturn) => return false,
+        _ => {}
+    }
+    match func.layout.prev_ebb(ebb) {
+        Some(prev) => terminator(func, prev) != Some(Opcode::Fallthrough),
+        None => true,
+    }
+}
+
+/// Get the opcode of the last instruction in `ebb`.
+fn terminator(func: &Function, ebb: Ebb) -> Option<Opcode> {
+    func.layout
+        .last_inst(ebb)
+        .map(|inst| func.dfg[inst].opcode())
+}
+
+/// Invert the likely conditional branches to the next EBB so the likely path falls through.
+fn choose_fallthroughs(func: &mut Function, isa: Option<&TargetIsa>) {
+    let pairs: Vec<(Ebb, Ebb)> = func.layout.ebbs().adjacent_pairs().collect();
+    for (ebb, next) in pairs {
+        let jump = match func.layout.last_inst(ebb) {
+            Some(jump) if func.dfg[jump].opcode() == Opcode::Jump => jump,
+            _ => continue,
+        };
+        let branch = match func.layout.prev_inst(jump) {
+            Some(branch) if func.branch_probabilities[branch].is_likely() => branch,
+            _ => continue,
+        };
+        let (branch_dest, branch_args) = match func.dfg.analyze_branch(branch) {
+            BranchInfo::SingleDest(dest, args) if dest == next => (dest, args.to_vec()),
+            _ => continue,
+        };
+        let (jump_dest, jump_args) = match func.dfg.analyze_branch(jump) {
+            BranchInfo::SingleDest(dest, args) if dest != next => (dest, args.to_vec()),
+            _ => continue,
+        };
+        if !invert_condition(func, branch, isa) {
+            continue;
+        }
+
+        debug!("Inverting {} so {} falls through", branch, next);
+        set_destination(func, branch, jump_dest, &jump_args);
+        set_destination(func, jump, branch_dest, &branch_args);
+        func.branch_probabilities[branch] = func.branch_probabilities[branch].inverse();
+    }
+}
+
+/// Invert the condition of the branch `inst`, keeping its destination.
+///
+/// If `inst` is encoded, it gets an encoding with the same recipe so the register constraints
+/// don't change. Returns false if this isn't possible.
+fn invert_condition(func: &mut Function, inst: Inst, isa: Option<&TargetIsa>) -> bool {
+    let mut data = func.dfg[inst].clone();
+    match data {
+        InstructionData::Branch { ref mut opcode, .. } => {
+            *opcode = match *opcode {
+                Opcode::Brz => Opcode::Brnz,
+                Opcode::Brnz => Opcode::Brz,
+                _ => return false,
+            }
+        }
+        InstructionData::BranchInt { ref mut cond, .. }
+        | InstructionData::BranchIcmp { ref mut cond, .. } => *cond = cond.inverse(),
+        InstructionData::BranchFloat { ref mut cond, .. } => *cond = cond.inverse(),
+        _ => return false,
+    }
+
+    let encoding = func.encodings[inst];
+    if encoding.is_legal() {
+        let isa = match isa {
+            Some(isa) => isa,
+            None => return false,
+        };
+        let ctrl_type = func.dfg.ctrl_typevar(inst);
+        match isa
+            .legal_encodings(func, &data, ctrl_type)
+            .find(|enc| enc.recipe() == encoding.recipe())
+        {
+            Some(enc) => func.encodings[inst] = enc,
+            None => return false,
+        }
+    }
+
+    func.dfg[inst] = data;
+    true
+}
+
+/// Change the destination of the branch `inst` to `dest` with `args`.
+fn set_destination(func: &mut Function, inst: Inst, dest: Ebb, args: &[Value]) {
+    let num_fixed = func.dfg[inst]
+        .opcode()
+        .constraints()
+        .num_fixed_value_arguments();
+    let mut vlist = func.dfg[inst]
+        .take_value_list()
+        .expect("Branches must have value lists.");
+    {
+        let pool = &mut func.dfg.value_lists;
+        while vlist.len(pool) > num_fixed {
+            let last = vlist.len(pool) - 1;
+            vlist.remove(last, pool);
+        }
+        vlist.extend(args.iter().cloned(), pool);
+    }
+    func.dfg[inst].put_value_list(vlist);
+    *func.dfg[inst]
+        .branch_destination_mut()
+        .expect("Expected a branch.") = dest;
+}
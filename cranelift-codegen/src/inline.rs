@@ -0,0 +1,300 @@
+//! Function inlining.
+//!
+//! This pass replaces direct `call` instructions with a copy of the callee's body, made with
+//! `Function::clone_ebbs_into`.
+//!
+//! The EBB containing the `call` is split after the `call`, which becomes a `jump` to the copy of
+//! the callee's entry block. The results of the `call` become the parameters of the new EBB, and
+//! the `return` instructions in the copied body jump there.
+//!
+//! Only functions that haven't been legalized can be inlined, and the caller must not have been
+//! legalized either. The inliner doesn't look at the calls in the inlined bodies again, so the
+//! callees should be processed first: inlining bottom-up in the call graph gives the same result
+//! without the risk of unrolling a recursive function forever.
+
+use crate::ir::{
+    self, ArgumentPurpose, Ebb, EntityMap, ExternalName, Function, GlobalValueData, Inst,
+    InstBuilder, InstructionData, Opcode, StackSlotKind, Value,
+};
+use crate::settings::OptLevel;
+use crate::timing;
+use log::debug;
+use std::vec::Vec;
+
+/// Get the size limit of the inlined callees at `opt_level`, in instructions.
+///
+/// Nothing is inlined at `none`, and `speed` inlines larger callees than `speed_and_size`, which
+/// only inlines the callees small enough not to grow the code much.
+pub fn inline_size_limit(opt_level: OptLevel) -> usize {
+    match opt_level {
+        OptLevel::None => 0,
+        OptLevel::Speed => 100,
+        OptLevel::SpeedAndSize => 20,
+    }
+}
+
+/// Inline the direct calls in `func` to the functions returned by `callees`.
+///
+/// The `callees` function is given the name of each called function, and it returns the body to
+/// inline, if any. Callees with more than `size_limit` instructions are not inlined.
+///
+/// Returns true if any calls were inlined.
+pub fn inline_calls<'a, F>(func: &mut Function, mut callees: F, size_limit: usize) -> bool
+where
+    F: FnMut(&ExternalName) -> Option<&'a Function>,
+{
+    let _tt = timing::inline();
+
+    let calls: Vec<Inst> = func
+        .layout
+        .ebbs()
+        .flat_map(|ebb| func.layout.ebb_insts(ebb))
+        .filter(|&inst| func.dfg[inst].opcode() == Opcode::Call)
+        .collect();
+
+    let mut changed = false;
+    for call in calls {
+        let name = match func.dfg[call] {
+            InstructionData::Call { func_ref, .. } => func.dfg.ext_funcs[func_ref].name.clone(),
+            _ => continue,
+        };
+        if name == func.name {
+            continue;
+        }
+        let callee = match callees(&name) {
+            Some(callee) => callee,
+            None => continue,
+        };
+        if can_inline(func, call, callee, size_limit) {
+            debug!("Inlining {} at {}", name, call);
+            inline_call(func, call, callee);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Can the `call` instruction in `func` be replaced by the body of `callee`?
+fn can_inline(func: &Function, call: Inst, callee: &Function, size_limit: usize) -> bool {
+    if callee.layout.entry_block().is_none() {
+        return false;
+    }
+
+    // The callee must not have been legalized.
+    if callee
+        .signature
+        .params
+        .iter()
+        .chain(&callee.signature.returns)
+        .any(|param| param.location.is_assigned())
+        || callee
+            .stack_slots
+            .values()
+            .any(|slot| slot.kind != StackSlotKind::ExplicitSlot)
+    {
+        return false;
+    }
+
+    // The signature at the call site must match the callee.
+    let sig = &func.dfg.signatures[func.dfg.call_signature(call).expect("Expected a call.")];
+    let types = |params: &[ir::AbiParam]| params.iter().map(|p| p.value_type).collect::<Vec<_>>();
+    if types(&sig.params) != types(&callee.signature.params)
+        || types(&sig.returns) != types(&callee.signature.returns)
+    {
+        return false;
+    }
+
+    // A struct argument is a copy owned by the callee, which may write to it.
+    if callee.signature.params.iter().any(|p| match p.purpose {
+        ArgumentPurpose::StructArgument(_) => true,
+        _ => false,
+    }) {
+        return false;
+    }
+
+    // A `vmctx` global value in the callee can only be copied if the caller passes its own
+    // `vmctx` parameter.
+    if callee.global_values.values().any(|gv| match *gv {
+        GlobalValueData::VMContext => true,
+        _ => false,
+    }) {
+        let index = match callee
+            .signature
+            .special_param_index(ArgumentPurpose::VMContext)
+        {
+            Some(index) => index,
+            None => return false,
+        };
+        let arg = func
+            .dfg
+            .resolve_aliases(func.dfg.inst_variable_args(call)[index]);
+        if func.special_param(ArgumentPurpose::VMContext) != Some(arg) {
+            return false;
+        }
+    }
+
+    let mut size = 0;
+    for ebb in callee.layout.ebbs() {
+        for inst in callee.layout.ebb_insts(ebb) {
+            let opcode = callee.dfg[inst].opcode();
+            // `fallthrough_return` and the other special returns only appear after legalization.
+            if opcode.is_return() && opcode != Opcode::Return {
+                return false;
+            }
+            size += 1;
+        }
+    }
+    size <= size_limit
+}
+
+/// Replace the `call` instruction in `func` with the body of `callee`.
+fn inline_call(func: &mut Function, call: Inst, callee: &Function) {
+    // Split the EBB after the call. The call results become the parameters of the new EBB.
+    let call_ebb = func.layout.inst_ebb(call).expect("Call not in the layout");
+    let return_ebb = func.dfg.make_ebb();
+    let next = func
+        .layout
+        .next_inst(call)
+        .expect("A call can't terminate an EBB");
+    func.layout.split_ebb(return_ebb, next);
+    func.layout
+        .set_cold(return_ebb, func.layout.is_cold(call_ebb));
+    let results = func.dfg.detach_results(call);
+    for i in 0..results.len(&func.dfg.value_lists) {
+        let result = results.get(i, &func.dfg.value_lists).unwrap();
+        func.dfg.attach_ebb_param(return_ebb, result);
+    }
+
+    let ebbs: Vec<Ebb> = callee.layout.ebbs().collect();
+    let mut map = EntityMap::new();
+    callee.clone_ebbs_into(&ebbs, func, Some(return_ebb), &mut map);
+
+    // The copied returns jump to the EBB after the call instead, and the copied instructions
+    // without a source location get the one of the call. The OSR entries of the callee are not
+    // entries of the caller.
+    let call_srcloc = func.srclocs[call];
+    for &ebb in &ebbs {
+        func.layout.set_osr_entry(map.ebb(ebb).unwrap(), false);
+        for inst in callee.layout.ebb_insts(ebb) {
+            let new_inst = map.inst(inst).unwrap();
+            if func.srclocs[new_inst].is_default() {
+                func.srclocs[new_inst] = call_srcloc;
+            }
+            if callee.dfg[inst].opcode() == Opcode::Return {
+                let args: Vec<Value> = func.dfg.inst_args(new_inst).to_vec();
+                func.dfg.replace(new_inst).jump(return_ebb, &args);
+            }
+        }
+    }
+
+    // Finally, turn the call into a jump to the inlined entry block.
+    let entry = map.ebb(ebbs[0]).unwrap();
+    let args: Vec<Value> = func.dfg.inst_variable_args(call).to_vec();
+    func.dfg.replace(call).jump(entry, &args);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{inline_calls, inline_size_limit};
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::I32;
+    use crate::ir::{AbiParam, ExtFuncData, ExternalName, Function, InstBuilder, Signature};
+    use crate::isa::CallConv;
+    use crate::settings::OptLevel;
+    use crate::verifier::verify_function;
+    use std::string::ToString;
+
+    fn signature() -> Signature {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        sig
+    }
+
+    #[test]
+    fn inline_call() {
+        // function u0:1(i32) -> i32 {
+        // ebb0(v0: i32):
+        //     brz v0, ebb1(v0)
+        //     v2 = iadd_imm v0, 1
+        //     return v2
+        // ebb1(v1: i32):
+        //     return v1
+        // }
+        let mut callee = Function::with_name_signature(ExternalName::user(0, 1), signature());
+        {
+            let ebb0 = callee.dfg.make_ebb();
+            let ebb1 = callee.dfg.make_ebb();
+            let v0 = callee.dfg.append_ebb_param(ebb0, I32);
+            let v1 = callee.dfg.append_ebb_param(ebb1, I32);
+            let mut cur = FuncCursor::new(&mut callee);
+            cur.insert_ebb(ebb0);
+            cur.ins().brz(v0, ebb1, &[v0]);
+            let v2 = cur.ins().iadd_imm(v0, 1);
+            cur.ins().return_(&[v2]);
+            cur.insert_ebb(ebb1);
+            cur.ins().return_(&[v1]);
+        }
+
+        // function u0:0(i32) -> i32 {
+        // ebb0(v0: i32):
+        //     v1 = call fn0(v0)
+        //     v2 = imul v1, v0
+        //     return v2
+        // }
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), signature());
+        {
+            let sig = func.import_signature(signature());
+            let fn0 = func.import_function(ExtFuncData {
+                name: ExternalName::user(0, 1),
+                signature: sig,
+                colocated: true,
+            });
+            let ebb0 = func.dfg.make_ebb();
+            let v0 = func.dfg.append_ebb_param(ebb0, I32);
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_ebb(ebb0);
+            let call = cur.ins().call(fn0, &[v0]);
+            let v1 = cur.func.dfg.first_result(call);
+            let v2 = cur.ins().imul(v1, v0);
+            cur.ins().return_(&[v2]);
+        }
+
+        // The callee is too big.
+        assert!(!inline_calls(&mut func, |_| Some(&callee), 2));
+
+        assert!(inline_calls(&mut func, |_| Some(&callee), 3));
+        verify_function(&func, None).unwrap();
+        let text = func.display(None).to_string();
+        assert!(!text.contains("call"), "{}", text);
+        assert_eq!(
+            text,
+            "function u0:0(i32) -> i32 system_v {
+    sig0 = (i32) -> i32 system_v
+    fn0 = colocated u0:1 sig0
+
+ebb0(v0: i32):
+    jump ebb2(v0)
+
+ebb2(v3: i32):
+    brz v3, ebb3(v3)
+    v5 = iadd_imm v3, 1
+    jump ebb1(v5)
+
+ebb3(v4: i32):
+    jump ebb1(v4)
+
+ebb1(v1: i32):
+    v2 = imul v1, v0
+    return v2
+}
+"
+        );
+    }
+
+    #[test]
+    fn size_limits() {
+        assert_eq!(inline_size_limit(OptLevel::None), 0);
+        assert!(inline_size_limit(OptLevel::Speed) > inline_size_limit(OptLevel::SpeedAndSize));
+    }
+}
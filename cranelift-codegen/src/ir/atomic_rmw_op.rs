@@ -0,0 +1,79 @@
+//! Atomic read-modify-write operations.
+
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+
+/// The operation performed by an `atomic_rmw` instruction.
+///
+/// Each operation combines the value currently in memory with the instruction's argument and
+/// writes the result back, atomically. The instruction produces the old memory value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
+pub enum AtomicRmwOp {
+    /// Wrapping addition.
+    Add,
+    /// Wrapping subtraction.
+    Sub,
+    /// Bitwise and.
+    And,
+    /// Bitwise or.
+    Or,
+    /// Bitwise exclusive or.
+    Xor,
+    /// Replace the memory value with the argument.
+    Xchg,
+}
+
+impl Display for AtomicRmwOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::AtomicRmwOp::*;
+        f.write_str(match *self {
+            Add => "add",
+            Sub => "sub",
+            And => "and",
+            Or => "or",
+            Xor => "xor",
+            Xchg => "xchg",
+        })
+    }
+}
+
+impl FromStr for AtomicRmwOp {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use self::AtomicRmwOp::*;
+        match s {
+            "add" => Ok(Add),
+            "sub" => Ok(Sub),
+            "and" => Ok(And),
+            "or" => Ok(Or),
+            "xor" => Ok(Xor),
+            "xchg" => Ok(Xchg),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    const OPS: [AtomicRmwOp; 6] = [
+        AtomicRmwOp::Add,
+        AtomicRmwOp::Sub,
+        AtomicRmwOp::And,
+        AtomicRmwOp::Or,
+        AtomicRmwOp::Xor,
+        AtomicRmwOp::Xchg,
+    ];
+
+    #[test]
+    fn display() {
+        for r in &OPS {
+            let op = *r;
+            assert_eq!(op.to_string().parse(), Ok(op));
+        }
+        assert_eq!("nand".parse::<AtomicRmwOp>(), Err(()));
+    }
+}
@@ -0,0 +1,102 @@
+//! Branch probabilities.
+//!
+//! Embedders with profile data can annotate conditional branches with the probability that the
+//! branch is taken. The probabilities don't affect the semantics of the program, but the code
+//! generator uses them to improve the code layout and the spill placement.
+
+use core::fmt;
+
+/// The probability that a conditional branch is taken, in percent.
+///
+/// The default value means that the probability is unknown. It uses the all-ones bit pattern
+/// which is not a valid percentage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BranchProbability(u8);
+
+impl BranchProbability {
+    /// Create a probability of `percent` percent that the branch is taken.
+    pub fn from_percent(percent: u8) -> Self {
+        assert!(percent <= 100, "Invalid branch probability {}%", percent);
+        BranchProbability(percent)
+    }
+
+    /// Is this the default unknown probability?
+    pub fn is_default(self) -> bool {
+        self == Default::default()
+    }
+
+    /// Get the probability in percent, or `None` if it is unknown.
+    pub fn percent(self) -> Option<u8> {
+        if self.is_default() {
+            None
+        } else {
+            Some(self.0)
+        }
+    }
+
+    /// Get the probability that the branch is not taken.
+    ///
+    /// This is the probability of the branch after inverting its condition.
+    pub fn inverse(self) -> Self {
+        match self.percent() {
+            Some(percent) => BranchProbability(100 - percent),
+            None => self,
+        }
+    }
+
+    /// Is the branch known to be taken more often than not?
+    pub fn is_likely(self) -> bool {
+        self.percent().map_or(false, |percent| percent > 50)
+    }
+
+    /// Is the branch known to be taken less often than not?
+    pub fn is_unlikely(self) -> bool {
+        self.percent().map_or(false, |percent| percent < 50)
+    }
+}
+
+impl Default for BranchProbability {
+    fn default() -> Self {
+        BranchProbability(!0)
+    }
+}
+
+impl fmt::Display for BranchProbability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.percent() {
+            Some(percent) => write!(f, "{}%", percent),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::BranchProbability;
+    use std::string::ToString;
+
+    #[test]
+    fn display() {
+        assert_eq!(BranchProbability::default().to_string(), "-");
+        assert_eq!(BranchProbability::from_percent(0).to_string(), "0%");
+        assert_eq!(BranchProbability::from_percent(90).to_string(), "90%");
+    }
+
+    #[test]
+    fn inverse() {
+        let unknown = BranchProbability::default();
+        assert_eq!(unknown.inverse(), unknown);
+        assert!(!unknown.is_likely());
+        assert!(!unknown.is_unlikely());
+
+        let prob = BranchProbability::from_percent(90);
+        assert!(prob.is_likely());
+        assert_eq!(prob.inverse(), BranchProbability::from_percent(10));
+        assert!(prob.inverse().is_unlikely());
+        assert_eq!(prob.inverse().inverse(), prob);
+
+        let even = BranchProbability::from_percent(50);
+        assert!(!even.is_likely());
+        assert!(!even.is_unlikely());
+    }
+}
@@ -0,0 +1,438 @@
+//! Copying IR between functions.
+//!
+//! The entity numbers of a function are only meaningful within that function, so copying code
+//! from one function to another requires renumbering all the entity references. An `EntityMap`
+//! records the correspondence between the entities of a source function and the entities created
+//! for them in a destination function.
+//!
+//! The preamble entities (stack slots, global values, heaps, tables, jump tables, signatures and
+//! external functions) of the source are copied in order after the ones already in the
+//! destination. EBBs, values and instructions are created on demand.
+
+use crate::entity::{EntityRef, SecondaryMap};
+use crate::ir::{
+    Ebb, FuncRef, Function, GlobalValue, GlobalValueData, Heap, HeapStyle, Inst, InstructionData,
+    JumpTable, SigRef, StackSlot, Table, Value, ValueList,
+};
+use crate::packed_option::PackedOption;
+use std::vec::Vec;
+
+/// The offsets of the source preamble entities in the destination function.
+#[derive(Clone, Copy)]
+struct PreambleOffsets {
+    stack_slots: usize,
+    global_values: usize,
+    heaps: usize,
+    tables: usize,
+    jump_tables: usize,
+    signatures: usize,
+    ext_funcs: usize,
+}
+
+/// A mapping from the entities of a source function to the entities of a destination function.
+///
+/// When copying only some of the EBBs of a function, the values defined outside them and the EBBs
+/// branched to outside them can be mapped with `set_value` and `set_ebb` before copying.
+pub struct EntityMap {
+    ebbs: SecondaryMap<Ebb, PackedOption<Ebb>>,
+    values: SecondaryMap<Value, PackedOption<Value>>,
+    insts: SecondaryMap<Inst, PackedOption<Inst>>,
+    preamble: Option<PreambleOffsets>,
+}
+
+impl EntityMap {
+    /// Create a new empty mapping.
+    pub fn new() -> Self {
+        Self {
+            ebbs: SecondaryMap::new(),
+            values: SecondaryMap::new(),
+            insts: SecondaryMap::new(),
+            preamble: None,
+        }
+    }
+
+    /// Map the source value `from` to `to` in the destination function.
+    pub fn set_value(&mut self, from: Value, to: Value) {
+        self.values[from] = to.into();
+    }
+
+    /// Map the source EBB `from` to `to` in the destination function.
+    pub fn set_ebb(&mut self, from: Ebb, to: Ebb) {
+        self.ebbs[from] = to.into();
+    }
+
+    /// Get the destination EBB mapped to the source EBB `ebb`, if any.
+    pub fn ebb(&self, ebb: Ebb) -> Option<Ebb> {
+        self.ebbs[ebb].expand()
+    }
+
+    /// Get the destination value mapped to the source value `value`, if any.
+    ///
+    /// Aliases are not resolved, so the source alias itself must have been mapped.
+    pub fn value(&self, value: Value) -> Option<Value> {
+        self.values[value].expand()
+    }
+
+    /// Get the copy of the source instruction `inst`, if it has been copied.
+    pub fn inst(&self, inst: Inst) -> Option<Inst> {
+        self.insts[inst].expand()
+    }
+
+    fn offset<E: EntityRef>(&self, entity: E, offset: fn(&PreambleOffsets) -> usize) -> E {
+        let preamble = self
+            .preamble
+            .as_ref()
+            .expect("The preamble hasn't been copied");
+        E::new(entity.index() + offset(preamble))
+    }
+
+    /// Get the copy of the source stack slot `ss`.
+    pub fn stack_slot(&self, ss: StackSlot) -> StackSlot {
+        self.offset(ss, |p| p.stack_slots)
+    }
+
+    /// Get the copy of the source global value `gv`.
+    pub fn global_value(&self, gv: GlobalValue) -> GlobalValue {
+        self.offset(gv, |p| p.global_values)
+    }
+
+    /// Get the copy of the source heap `heap`.
+    pub fn heap(&self, heap: Heap) -> Heap {
+        self.offset(heap, |p| p.heaps)
+    }
+
+    /// Get the copy of the source table `table`.
+    pub fn table(&self, table: Table) -> Table {
+        self.offset(table, |p| p.tables)
+    }
+
+    /// Get the copy of the source jump table `jt`.
+    pub fn jump_table(&self, jt: JumpTable) -> JumpTable {
+        self.offset(jt, |p| p.jump_tables)
+    }
+
+    /// Get the copy of the source signature `sig`.
+    pub fn sig_ref(&self, sig: SigRef) -> SigRef {
+        self.offset(sig, |p| p.signatures)
+    }
+
+    /// Get the copy of the source external function `func_ref`.
+    pub fn func_ref(&self, func_ref: FuncRef) -> FuncRef {
+        self.offset(func_ref, |p| p.ext_funcs)
+    }
+
+    /// Rewrite the entity references in `data`, except for its value arguments.
+    fn remap_entities(&self, data: &mut InstructionData) {
+        let ebb = |ebb: Ebb| {
+            self.ebb(ebb)
+                .unwrap_or_else(|| panic!("{} hasn't been copied", ebb))
+        };
+        if let Some(dest) = data.branch_destination_mut() {
+            *dest = ebb(*dest);
+        }
+        match *data {
+            InstructionData::UnaryGlobalValue {
+                ref mut global_value,
+                ..
+            } => *global_value = self.global_value(*global_value),
+            InstructionData::BranchTable {
+                ref mut destination,
+                ref mut table,
+                ..
+            } => {
+                *destination = ebb(*destination);
+                *table = self.jump_table(*table);
+            }
+            InstructionData::BranchTableEntry { ref mut table, .. }
+            | InstructionData::BranchTableBase { ref mut table, .. }
+            | InstructionData::IndirectJump { ref mut table, .. } => {
+                *table = self.jump_table(*table)
+            }
+            InstructionData::Call {
+                ref mut func_ref, ..
+            }
+            | InstructionData::TryCall {
+                ref mut func_ref, ..
+            }
+            | InstructionData::FuncAddr {
+                ref mut func_ref, ..
+            } => *func_ref = self.func_ref(*func_ref),
+            InstructionData::CallIndirect {
+                ref mut sig_ref, ..
+            } => *sig_ref = self.sig_ref(*sig_ref),
+            InstructionData::StackLoad {
+                ref mut stack_slot, ..
+            }
+            | InstructionData::StackStore {
+                ref mut stack_slot, ..
+            } => *stack_slot = self.stack_slot(*stack_slot),
+            InstructionData::HeapAddr { ref mut heap, .. } => *heap = self.heap(*heap),
+            InstructionData::TableAddr { ref mut table, .. } => *table = self.table(*table),
+            _ => {}
+        }
+    }
+
+    /// Copy the preamble entities of `src` to the end of the ones in `dest`.
+    fn copy_preamble(&mut self, src: &Function, dest: &mut Function) {
+        self.preamble = Some(PreambleOffsets {
+            stack_slots: dest.stack_slots.next_key().index(),
+            global_values: dest.global_values.next_key().index(),
+            heaps: dest.heaps.next_key().index(),
+            tables: dest.tables.next_key().index(),
+            jump_tables: dest.jump_tables.next_key().index(),
+            signatures: dest.dfg.signatures.next_key().index(),
+            ext_funcs: dest.dfg.ext_funcs.next_key().index(),
+        });
+
+        for slot in src.stack_slots.values() {
+            dest.stack_slots.push(slot.clone());
+        }
+        for gv in src.global_values.values() {
+            let mut gv = gv.clone();
+            match gv {
+                GlobalValueData::Load { ref mut base, .. }
+                | GlobalValueData::IAddImm { ref mut base, .. } => *base = self.global_value(*base),
+                GlobalValueData::VMContext | GlobalValueData::Symbol { .. } => {}
+            }
+            dest.global_values.push(gv);
+        }
+        for heap in src.heaps.values() {
+            let mut heap = heap.clone();
+            heap.base = self.global_value(heap.base);
+            if let HeapStyle::Dynamic { ref mut bound_gv } = heap.style {
+                *bound_gv = self.global_value(*bound_gv);
+            }
+            dest.heaps.push(heap);
+        }
+        for table in src.tables.values() {
+            let mut table = table.clone();
+            table.base_gv = self.global_value(table.base_gv);
+            table.bound_gv = self.global_value(table.bound_gv);
+            dest.tables.push(table);
+        }
+        for jt in src.jump_tables.values() {
+            // The destinations are rewritten once the EBBs have been copied.
+            dest.jump_tables.push(jt.clone());
+        }
+        for sig in src.dfg.signatures.values() {
+            dest.dfg.signatures.push(sig.clone());
+        }
+        for ext_func in src.dfg.ext_funcs.values() {
+            let mut ext_func = ext_func.clone();
+            ext_func.signature = self.sig_ref(ext_func.signature);
+            dest.dfg.ext_funcs.push(ext_func);
+        }
+    }
+}
+
+/// Copy `ebbs` from `src` into `dest`. See `Function::clone_ebbs_into`.
+pub(crate) fn clone_ebbs(
+    src: &Function,
+    ebbs: &[Ebb],
+    dest: &mut Function,
+    before: Option<Ebb>,
+    map: &mut EntityMap,
+) {
+    if map.preamble.is_none() {
+        map.copy_preamble(src, dest);
+    }
+
+    // Create the EBBs and their parameters.
+    for &ebb in ebbs {
+        let new_ebb = dest.dfg.make_ebb();
+        match before {
+            Some(before) => dest.layout.insert_ebb(new_ebb, before),
+            None => dest.layout.append_ebb(new_ebb),
+        }
+        dest.layout.set_cold(new_ebb, src.layout.is_cold(ebb));
+        dest.layout
+            .set_osr_entry(new_ebb, src.layout.is_osr_entry(ebb));
+        for &param in src.dfg.ebb_params(ebb) {
+            let new_param = dest
+                .dfg
+                .append_ebb_param(new_ebb, src.dfg.value_type(param));
+            map.set_value(param, new_param);
+        }
+        map.ebbs[ebb] = new_ebb.into();
+    }
+
+    // Jump tables can only be rewritten once all their destinations have been copied.
+    for (jt, data) in src.jump_tables.iter() {
+        if data.iter().all(|&ebb| map.ebb(ebb).is_some()) {
+            let new_jt = map.jump_table(jt);
+            for dest_ebb in dest.jump_tables[new_jt].iter_mut() {
+                *dest_ebb = map.ebb(*dest_ebb).unwrap();
+            }
+        }
+    }
+
+    // Copy the instructions, leaving the source values in their arguments for now since they may
+    // be defined later in the layout.
+    let mut insts = Vec::new();
+    for &ebb in ebbs {
+        let new_ebb = map.ebb(ebb).unwrap();
+        for inst in src.layout.ebb_insts(ebb) {
+            let args = src.dfg.inst_args(inst);
+            let mut data = src.dfg[inst].clone();
+            map.remap_entities(&mut data);
+            if data.take_value_list().is_some() {
+                data.put_value_list(ValueList::from_slice(args, &mut dest.dfg.value_lists));
+            } else {
+                data.arguments_mut(&mut dest.dfg.value_lists)
+                    .copy_from_slice(args);
+            }
+
+            let new_inst = dest.dfg.make_inst(data);
+            dest.dfg
+                .make_inst_results(new_inst, src.dfg.ctrl_typevar(inst));
+            for (&result, &new_result) in src
+                .dfg
+                .inst_results(inst)
+                .iter()
+                .zip(dest.dfg.inst_results(new_inst))
+            {
+                map.set_value(result, new_result);
+            }
+            dest.layout.append_inst(new_inst, new_ebb);
+            dest.srclocs[new_inst] = src.srclocs[inst];
+            dest.branch_probabilities[new_inst] = src.branch_probabilities[inst];
+            dest.memory_trap_codes[new_inst] = src.memory_trap_codes[inst];
+            map.insts[inst] = new_inst.into();
+            insts.push((inst, new_inst));
+        }
+    }
+
+    for (inst, new_inst) in insts {
+        for (arg, new_arg) in src
+            .dfg
+            .inst_args(inst)
+            .iter()
+            .zip(dest.dfg.inst_args_mut(new_inst))
+        {
+            let value = src.dfg.resolve_aliases(*arg);
+            *new_arg = map
+                .value(value)
+                .unwrap_or_else(|| panic!("{} isn't defined in the copied EBBs", value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EntityMap;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::I32;
+    use crate::ir::{
+        AbiParam, ExternalName, Function, InstBuilder, JumpTableData, Signature, StackSlotData,
+        StackSlotKind,
+    };
+    use crate::isa::CallConv;
+    use crate::verifier::verify_function;
+    use std::string::ToString;
+
+    fn signature() -> Signature {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        sig
+    }
+
+    #[test]
+    fn clone_function() {
+        let mut func = Function::with_name_signature(ExternalName::testcase("src"), signature());
+        {
+            let ss0 = func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 4));
+            let ebb0 = func.dfg.make_ebb();
+            let ebb1 = func.dfg.make_ebb();
+            let ebb2 = func.dfg.make_ebb();
+            let mut jt_data = JumpTableData::new();
+            jt_data.push_entry(ebb2);
+            let jt0 = func.create_jump_table(jt_data);
+            let v0 = func.dfg.append_ebb_param(ebb0, I32);
+            let v1 = func.dfg.append_ebb_param(ebb2, I32);
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_ebb(ebb0);
+            cur.ins().stack_store(v0, ss0, 0);
+            cur.ins().br_table(v0, ebb1, jt0);
+            cur.insert_ebb(ebb1);
+            let v2 = cur.ins().stack_load(I32, ss0, 0);
+            cur.ins().jump(ebb2, &[v2]);
+            cur.insert_ebb(ebb2);
+            cur.ins().return_(&[v1]);
+        }
+        // The destination already has a stack slot, so the copied one gets renumbered.
+        let mut copy = Function::with_name_signature(ExternalName::testcase("copy"), signature());
+        copy.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+
+        let map = func.clone_into(&mut copy);
+        verify_function(&copy, None).unwrap();
+        assert_eq!(
+            copy.display(None).to_string(),
+            "function %copy(i32) -> i32 system_v {
+    ss0 = explicit_slot 8
+    ss1 = explicit_slot 4
+    jt0 = jump_table [ebb2]
+
+ebb0(v0: i32):
+    stack_store v0, ss1
+    br_table v0, ebb1, jt0
+
+ebb1:
+    v2 = stack_load.i32 ss1
+    jump ebb2(v2)
+
+ebb2(v1: i32):
+    return v1
+}
+"
+        );
+        assert_eq!(
+            map.ebb(func.layout.entry_block().unwrap()),
+            copy.layout.entry_block()
+        );
+    }
+
+    #[test]
+    fn clone_ebbs() {
+        let mut func = Function::with_name_signature(ExternalName::testcase("src"), signature());
+        let (ebb1, v0) = {
+            let ebb0 = func.dfg.make_ebb();
+            let ebb1 = func.dfg.make_ebb();
+            let v0 = func.dfg.append_ebb_param(ebb0, I32);
+            let mut cur = FuncCursor::new(&mut func);
+            cur.insert_ebb(ebb0);
+            cur.ins().jump(ebb1, &[]);
+            cur.insert_ebb(ebb1);
+            let v1 = cur.ins().iadd(v0, v0);
+            cur.ins().return_(&[v1]);
+            (ebb1, v0)
+        };
+
+        let mut dest = Function::with_name_signature(ExternalName::testcase("dest"), signature());
+        let ebb0 = dest.dfg.make_ebb();
+        let arg = dest.dfg.append_ebb_param(ebb0, I32);
+        dest.layout.append_ebb(ebb0);
+        let mut map = EntityMap::new();
+        map.set_value(v0, arg);
+        func.clone_ebbs_into(&[ebb1], &mut dest, None, &mut map);
+        let new_ebb1 = map.ebb(ebb1).unwrap();
+        FuncCursor::new(&mut dest)
+            .at_bottom(ebb0)
+            .ins()
+            .jump(new_ebb1, &[]);
+
+        verify_function(&dest, None).unwrap();
+        assert_eq!(
+            dest.display(None).to_string(),
+            "function %dest(i32) -> i32 system_v {
+ebb0(v0: i32):
+    jump ebb1
+
+ebb1:
+    v1 = iadd v0, v0
+    return v1
+}
+"
+        );
+    }
+}
@@ -27,6 +27,12 @@ pub struct Signature {
 
     /// Calling convention.
     pub call_conv: CallConv,
+
+    /// Is the function variadic?
+    ///
+    /// The parameters of a variadic signature include the variable arguments passed at a
+    /// particular call site, so each distinct argument list needs its own signature.
+    pub variadic: bool,
 }
 
 impl Signature {
@@ -36,6 +42,7 @@ impl Signature {
             params: Vec::new(),
             returns: Vec::new(),
             call_conv,
+            variadic: false,
         }
     }
 
@@ -44,6 +51,18 @@ impl Signature {
         self.params.clear();
         self.returns.clear();
         self.call_conv = call_conv;
+        self.variadic = false;
+    }
+
+    /// Get the number of bytes occupied by this parameter when it is passed on the stack.
+    ///
+    /// Struct arguments are rounded up to a non-zero multiple of the size of their pointer type.
+    pub fn stack_size(&self) -> u32 {
+        let bytes = self.value_type.bytes();
+        match self.purpose {
+            ArgumentPurpose::StructArgument(size) => (size.max(1) + bytes - 1) / bytes * bytes,
+            _ => bytes,
+        }
     }
 
     /// Return an object that can display `self` with correct register names.
@@ -82,7 +101,11 @@ impl<'a> fmt::Display for DisplaySignature<'a> {
             write!(f, " -> ")?;
             write_list(f, &self.0.returns, self.1)?;
         }
-        write!(f, " {}", self.0.call_conv)
+        write!(f, " {}", self.0.call_conv)?;
+        if self.0.variadic {
+            write!(f, " varargs")?;
+        }
+        Ok(())
     }
 }
 
@@ -159,6 +182,17 @@ impl AbiParam {
         }
     }
 
+    /// Get the number of bytes occupied by this parameter when it is passed on the stack.
+    ///
+    /// Struct arguments are rounded up to a non-zero multiple of the size of their pointer type.
+    pub fn stack_size(&self) -> u32 {
+        let bytes = self.value_type.bytes();
+        match self.purpose {
+            ArgumentPurpose::StructArgument(size) => (size.max(1) + bytes - 1) / bytes * bytes,
+            _ => bytes,
+        }
+    }
+
     /// Return an object that can display `self` with correct register names.
     pub fn display<'a, R: Into<Option<&'a RegInfo>>>(&'a self, regs: R) -> DisplayAbiParam<'a> {
         DisplayAbiParam(self, regs.into())
@@ -270,10 +304,24 @@ pub enum ArgumentPurpose {
     /// This is a pointer to a stack limit. It is used to check the current stack pointer
     /// against. Can only appear once in a signature.
     StackLimit,
+
+    /// A struct passed by value on the stack.
+    ///
+    /// The argument value is a pointer to the struct. The caller copies the given number of bytes
+    /// from the pointer into the outgoing argument area, and the callee receives the address of
+    /// its copy in the incoming argument area.
+    StructArgument(u32),
+
+    /// The number of vector registers used by a variadic call.
+    ///
+    /// The System V x86-64 ABI passes an upper bound on the number of vector registers used for
+    /// arguments in `%al` when calling a variadic function. The caller provides this argument
+    /// automatically when the call is legalized.
+    VectorCount,
 }
 
 /// Text format names of the `ArgumentPurpose` variants.
-static PURPOSE_NAMES: [&str; 8] = [
+static PURPOSE_NAMES: [&str; 9] = [
     "normal",
     "sret",
     "link",
@@ -282,11 +330,24 @@ static PURPOSE_NAMES: [&str; 8] = [
     "vmctx",
     "sigid",
     "stack_limit",
+    "vcount",
 ];
 
 impl fmt::Display for ArgumentPurpose {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(PURPOSE_NAMES[*self as usize])
+        let index = match *self {
+            ArgumentPurpose::Normal => 0,
+            ArgumentPurpose::StructReturn => 1,
+            ArgumentPurpose::Link => 2,
+            ArgumentPurpose::FramePointer => 3,
+            ArgumentPurpose::CalleeSaved => 4,
+            ArgumentPurpose::VMContext => 5,
+            ArgumentPurpose::SignatureId => 6,
+            ArgumentPurpose::StackLimit => 7,
+            ArgumentPurpose::VectorCount => 8,
+            ArgumentPurpose::StructArgument(size) => return write!(f, "sarg({})", size),
+        };
+        f.write_str(PURPOSE_NAMES[index])
     }
 }
 
@@ -302,7 +363,15 @@ impl FromStr for ArgumentPurpose {
             "vmctx" => Ok(ArgumentPurpose::VMContext),
             "sigid" => Ok(ArgumentPurpose::SignatureId),
             "stack_limit" => Ok(ArgumentPurpose::StackLimit),
-            _ => Err(()),
+            "vcount" => Ok(ArgumentPurpose::VectorCount),
+            _ => {
+                if s.starts_with("sarg(") && s.ends_with(')') {
+                    let size = s["sarg(".len()..s.len() - 1].parse().map_err(|_| ())?;
+                    Ok(ArgumentPurpose::StructArgument(size))
+                } else {
+                    Err(())
+                }
+            }
         }
     }
 }
@@ -334,7 +403,7 @@ impl fmt::Display for ExtFuncData {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ir::types::{B8, F32, I32};
+    use crate::ir::types::{B8, F32, I32, I64};
     use std::string::ToString;
 
     #[test]
@@ -346,6 +415,11 @@ mod tests {
         assert_eq!(t.sext().to_string(), "i32 sext");
         t.purpose = ArgumentPurpose::StructReturn;
         assert_eq!(t.to_string(), "i32 uext sret");
+        assert_eq!(t.stack_size(), 4);
+
+        let t = AbiParam::special(I64, ArgumentPurpose::StructArgument(12));
+        assert_eq!(t.to_string(), "i64 sarg(12)");
+        assert_eq!(t.stack_size(), 16);
     }
 
     #[test]
@@ -359,11 +433,16 @@ mod tests {
             ArgumentPurpose::VMContext,
             ArgumentPurpose::SignatureId,
             ArgumentPurpose::StackLimit,
+            ArgumentPurpose::VectorCount,
         ];
         for (&e, &n) in all_purpose.iter().zip(PURPOSE_NAMES.iter()) {
             assert_eq!(e.to_string(), n);
             assert_eq!(Ok(e), n.parse());
         }
+        let sarg = ArgumentPurpose::StructArgument(24);
+        assert_eq!(sarg.to_string(), "sarg(24)");
+        assert_eq!(Ok(sarg), "sarg(24)".parse());
+        assert_eq!("sarg(x)".parse::<ArgumentPurpose>(), Err(()));
     }
 
     #[test]
@@ -401,5 +480,11 @@ mod tests {
             sig.to_string(),
             "(i32 [24], i32x4 [8]) -> f32, b8 baldrdash"
         );
+
+        sig.variadic = true;
+        assert_eq!(
+            sig.to_string(),
+            "(i32 [24], i32x4 [8]) -> f32, b8 baldrdash varargs"
+        );
     }
 }
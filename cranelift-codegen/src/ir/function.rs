@@ -6,17 +6,19 @@
 use crate::binemit::CodeOffset;
 use crate::entity::{PrimaryMap, SecondaryMap};
 use crate::ir;
-use crate::ir::{DataFlowGraph, ExternalName, Layout, Signature};
+use crate::ir::entity_map::clone_ebbs;
+use crate::ir::{BranchProbabilities, JumpTableOffsets, JumpTables, MemoryTrapCodes};
+use crate::ir::{DataFlowGraph, EntityMap, ExternalName, Layout, Signature};
 use crate::ir::{
-    Ebb, ExtFuncData, FuncRef, GlobalValue, GlobalValueData, Heap, HeapData, JumpTable,
+    Ebb, ExtFuncData, FuncRef, GlobalValue, GlobalValueData, Heap, HeapData, Inst, JumpTable,
     JumpTableData, SigRef, StackSlot, StackSlotData, Table, TableData,
 };
 use crate::ir::{EbbOffsets, InstEncodings, SourceLocs, StackSlots, ValueLocations};
-use crate::ir::{JumpTableOffsets, JumpTables};
 use crate::isa::{CallConv, EncInfo, Encoding, Legalize, TargetIsa};
 use crate::regalloc::RegDiversions;
 use crate::write::write_function;
 use core::fmt;
+use std::vec::Vec;
 
 /// A function.
 ///
@@ -73,6 +75,25 @@ pub struct Function {
     /// Track the original source location for each instruction. The source locations are not
     /// interpreted by Cranelift, only preserved.
     pub srclocs: SourceLocs,
+
+    /// Branch probabilities.
+    ///
+    /// The probability that each conditional branch is taken, when known. This is a hint for the
+    /// code layout and the register allocator, it doesn't affect the semantics of the function.
+    pub branch_probabilities: BranchProbabilities,
+
+    /// Trap codes for memory accesses.
+    ///
+    /// A load or store without the `notrap` flag reports `heap_oob` when it faults. A trap code
+    /// here replaces that, which lets a faulting access stand in for an explicit check, like a
+    /// null pointer check.
+    pub memory_trap_codes: MemoryTrapCodes,
+
+    /// The last instruction of the prologue.
+    ///
+    /// This is set by the prologue insertion of the ISAs that emit unwind information describing
+    /// the prologue. Like `offsets`, it is not included in the textual IR format.
+    pub prologue_end: Option<Inst>,
 }
 
 impl Function {
@@ -93,11 +114,19 @@ impl Function {
             offsets: SecondaryMap::new(),
             jt_offsets: SecondaryMap::new(),
             srclocs: SecondaryMap::new(),
+            branch_probabilities: SecondaryMap::new(),
+            memory_trap_codes: SecondaryMap::new(),
+            prologue_end: None,
         }
     }
 
     /// Clear all data structures in this function.
+    ///
+    /// The entities and value lists are stored in tables that keep their allocated memory when
+    /// cleared, so a JIT can reuse one `Function` for many compilations without reallocating its
+    /// tables. After clearing, the function is the same as one returned by `Function::new()`.
     pub fn clear(&mut self) {
+        self.name = ExternalName::default();
         self.signature.clear(CallConv::Fast);
         self.stack_slots.clear();
         self.global_values.clear();
@@ -109,7 +138,11 @@ impl Function {
         self.encodings.clear();
         self.locations.clear();
         self.offsets.clear();
+        self.jt_offsets.clear();
         self.srclocs.clear();
+        self.branch_probabilities.clear();
+        self.memory_trap_codes.clear();
+        self.prologue_end = None;
     }
 
     /// Create a new empty, anonymous function with a Fast calling convention.
@@ -153,6 +186,33 @@ impl Function {
         self.tables.push(data)
     }
 
+    /// Copy the body of this function into `dest`, after the EBBs already in its layout.
+    ///
+    /// The preamble entities are copied too, and all the entity references are renumbered.
+    /// The signature and name of `dest` are left alone. Returns the mapping from the entities of
+    /// this function to their copies.
+    pub fn clone_into(&self, dest: &mut Function) -> EntityMap {
+        let ebbs: Vec<Ebb> = self.layout.ebbs().collect();
+        let mut map = EntityMap::new();
+        clone_ebbs(self, &ebbs, dest, None, &mut map);
+        map
+    }
+
+    /// Copy `ebbs` from this function into `dest`, inserting them before `before` or at the end
+    /// of the layout.
+    ///
+    /// The preamble entities are copied the first time `map` is used. The values and EBBs that
+    /// `ebbs` refer to must either be part of `ebbs` or already be mapped in `map`.
+    pub fn clone_ebbs_into(
+        &self,
+        ebbs: &[Ebb],
+        dest: &mut Function,
+        before: Option<Ebb>,
+        map: &mut EntityMap,
+    ) {
+        clone_ebbs(self, ebbs, dest, before, map)
+    }
+
     /// Return an object that can display this function with correct ISA-specific annotations.
     pub fn display<'a, I: Into<Option<&'a TargetIsa>>>(&'a self, isa: I) -> DisplayFunction<'a> {
         DisplayFunction(self, isa.into())
@@ -168,6 +228,11 @@ impl Function {
             .map(|i| self.dfg.ebb_params(entry)[i])
     }
 
+    /// Get the trap code to report when the memory access `inst` faults.
+    pub fn memory_trap_code(&self, inst: ir::Inst) -> ir::TrapCode {
+        self.memory_trap_codes[inst].unwrap_or(ir::TrapCode::HeapOutOfBounds)
+    }
+
     /// Get an iterator over the instructions in `ebb`, including offsets and encoded instruction
     /// sizes.
     ///
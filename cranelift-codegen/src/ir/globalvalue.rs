@@ -63,6 +63,11 @@ pub enum GlobalValueData {
         /// away, after linking? If so, references to it can avoid going through a GOT. Note that
         /// symbols meant to be preemptible cannot be colocated.
         colocated: bool,
+
+        /// Does this symbol refer to a thread-local variable? The value is then the address of
+        /// the current thread's instance of the variable, computed according to the
+        /// `tls_model` setting.
+        tls: bool,
     },
 }
 
@@ -75,6 +80,15 @@ impl GlobalValueData {
         }
     }
 
+    /// Assume that `self` is an `GlobalValueData::Symbol` and return its offset, to be used as a
+    /// relocation addend.
+    pub fn symbol_offset(&self) -> i64 {
+        match *self {
+            GlobalValueData::Symbol { offset, .. } => offset.into(),
+            _ => panic!("only symbols have offsets"),
+        }
+    }
+
     /// Return the type of this global.
     pub fn global_type(&self, isa: &TargetIsa) -> Type {
         match *self {
@@ -113,11 +127,13 @@ impl fmt::Display for GlobalValueData {
                 ref name,
                 offset,
                 colocated,
+                tls,
             } => {
                 write!(
                     f,
-                    "symbol {}{}",
+                    "symbol {}{}{}",
                     if colocated { "colocated " } else { "" },
+                    if tls { "tls " } else { "" },
                     name
                 )?;
                 let offset_val: i64 = offset.into();
@@ -22,6 +22,12 @@ pub struct HeapData {
 
     /// The index type for the heap.
     pub index_type: Type,
+
+    /// Whether the heap may be accessed by multiple threads at the same time.
+    ///
+    /// The bound of a shared dynamic heap can change under a running function, so it must not be
+    /// loaded from a `readonly` global value.
+    pub shared: bool,
 }
 
 /// Style of heap including style-specific information.
@@ -57,6 +63,10 @@ impl fmt::Display for HeapData {
             f,
             ", offset_guard {}, index_type {}",
             self.offset_guard_size, self.index_type
-        )
+        )?;
+        if self.shared {
+            f.write_str(", shared")?;
+        }
+        Ok(())
     }
 }
@@ -19,6 +19,7 @@ use crate::isa;
 use crate::bitset::BitSet;
 use crate::entity;
 use crate::ref_slice::{ref_slice, ref_slice_mut};
+use crate::serialize::{Decoder, DeserializeResult, Encoder};
 
 /// Some instructions use an external list of argument values because there is not enough space in
 /// the 16-byte `InstructionData` struct. These value lists are stored in a memory pool in
@@ -33,15 +34,15 @@ pub type ValueListPool = entity::ListPool<Value>;
 // - The `pub enum InstructionFormat` enum with all the instruction formats.
 // - The `pub enum InstructionData` enum with all the instruction data fields.
 // - The `pub enum Opcode` definition with all known opcodes,
-// - The `const OPCODE_FORMAT: [InstructionFormat; N]` table.
+// - The `static OPCODE_FORMAT: [InstructionFormat; N]` table.
 // - The private `fn opcode_name(Opcode) -> &'static str` function, and
-// - The hash table `const OPCODE_HASH_TABLE: [Opcode; N]`.
+// - The hash table `static OPCODE_HASH_TABLE: [Opcode; N]`.
 //
 // For value type constraints:
 //
-// - The `const OPCODE_CONSTRAINTS : [OpcodeConstraints; N]` table.
-// - The `const TYPE_SETS : [ValueTypeSet; N]` table.
-// - The `const OPERAND_CONSTRAINTS : [OperandConstraint; N]` table.
+// - The `static OPCODE_CONSTRAINTS : [OpcodeConstraints; N]` table.
+// - The `static TYPE_SETS : [ValueTypeSet; N]` table.
+// - The `static OPERAND_CONSTRAINTS : [OperandConstraint; N]` table.
 //
 include!(concat!(env!("OUT_DIR"), "/opcodes.rs"));
 
@@ -57,6 +58,11 @@ impl Opcode {
         OPCODE_FORMAT[self as usize - 1]
     }
 
+    /// Get the opcode with the number `number`, as returned by `opcode as u16`.
+    pub(crate) fn from_number(number: u16) -> Option<Self> {
+        OPCODES.get(usize::from(number).wrapping_sub(1)).cloned()
+    }
+
     /// Get the constraint descriptor for this opcode.
     /// Panic if this is called on `NotAnOpcode`.
     pub fn constraints(self) -> OpcodeConstraints {
@@ -194,6 +200,11 @@ impl InstructionData {
                 ref args,
                 ..
             } => BranchInfo::SingleDest(destination, &args.as_slice(pool)[2..]),
+            // The landing pad of a `try_call` has no parameters, so the call arguments are not
+            // branch arguments.
+            InstructionData::TryCall { destination, .. } => {
+                BranchInfo::SingleDest(destination, &[])
+            }
             InstructionData::BranchTable {
                 table, destination, ..
             } => BranchInfo::Table(table, Some(destination)),
@@ -215,7 +226,8 @@ impl InstructionData {
             | InstructionData::Branch { destination, .. }
             | InstructionData::BranchInt { destination, .. }
             | InstructionData::BranchFloat { destination, .. }
-            | InstructionData::BranchIcmp { destination, .. } => Some(destination),
+            | InstructionData::BranchIcmp { destination, .. }
+            | InstructionData::TryCall { destination, .. } => Some(destination),
             InstructionData::BranchTable { .. } | InstructionData::IndirectJump { .. } => None,
             _ => {
                 debug_assert!(!self.opcode().is_branch());
@@ -249,6 +261,10 @@ impl InstructionData {
             | InstructionData::BranchIcmp {
                 ref mut destination,
                 ..
+            }
+            | InstructionData::TryCall {
+                ref mut destination,
+                ..
             } => Some(destination),
             InstructionData::BranchTable { .. } => None,
             _ => {
@@ -265,6 +281,9 @@ impl InstructionData {
         match *self {
             InstructionData::Call {
                 func_ref, ref args, ..
+            }
+            | InstructionData::TryCall {
+                func_ref, ref args, ..
             } => CallInfo::Direct(func_ref, args.as_slice(pool)),
             InstructionData::CallIndirect {
                 sig_ref, ref args, ..
@@ -275,6 +294,25 @@ impl InstructionData {
             }
         }
     }
+
+    /// Get the memory flags of a memory access instruction.
+    ///
+    /// Returns `None` for instructions that don't have memory flags.
+    pub fn memflags(&self) -> Option<ir::MemFlags> {
+        match *self {
+            InstructionData::Load { flags, .. }
+            | InstructionData::LoadComplex { flags, .. }
+            | InstructionData::Store { flags, .. }
+            | InstructionData::StoreComplex { flags, .. }
+            | InstructionData::LoadScaled { flags, .. }
+            | InstructionData::StoreScaled { flags, .. }
+            | InstructionData::LoadNoOffset { flags, .. }
+            | InstructionData::StoreNoOffset { flags, .. }
+            | InstructionData::AtomicRmw { flags, .. }
+            | InstructionData::AtomicCas { flags, .. } => Some(flags),
+            _ => None,
+        }
+    }
 }
 
 /// Information about branch and jump instructions.
@@ -444,6 +482,8 @@ pub struct ValueTypeSet {
     pub floats: BitSet8,
     /// Allowed bool widths
     pub bools: BitSet8,
+    /// Allowed reference widths
+    pub refs: BitSet8,
 }
 
 impl ValueTypeSet {
@@ -458,6 +498,8 @@ impl ValueTypeSet {
             self.floats.contains(l2b)
         } else if scalar.is_bool() {
             self.bools.contains(l2b)
+        } else if scalar.is_ref() {
+            self.refs.contains(l2b)
         } else {
             false
         }
@@ -479,6 +521,9 @@ impl ValueTypeSet {
             types::F32
         } else if self.bools.max().unwrap_or(0) > 5 {
             types::B32
+        } else if self.refs.max().unwrap_or(0) > 5 {
+            // Reference types can't form vectors.
+            return types::R64;
         } else {
             types::B1
         };
@@ -515,6 +560,9 @@ enum OperandConstraint {
 
     /// This operand is `ctrlType.double_vector()`.
     DoubleVector,
+
+    /// This operand is `ctrlType.split_lanes()`.
+    SplitLanes,
 }
 
 impl OperandConstraint {
@@ -541,6 +589,11 @@ impl OperandConstraint {
                     .expect("invalid type for half_vector"),
             ),
             DoubleVector => Bound(ctrl_type.by(2).expect("invalid type for double_vector")),
+            SplitLanes => Bound(
+                ctrl_type
+                    .split_lanes()
+                    .expect("invalid type for split_lanes"),
+            ),
         }
     }
 }
@@ -652,6 +705,7 @@ mod tests {
             ints: BitSet8::from_range(4, 7),
             floats: BitSet8::from_range(0, 0),
             bools: BitSet8::from_range(3, 7),
+            refs: BitSet8::from_range(0, 0),
         };
         assert!(!vts.contains(I8));
         assert!(vts.contains(I32));
@@ -668,6 +722,7 @@ mod tests {
             ints: BitSet8::from_range(0, 0),
             floats: BitSet8::from_range(5, 7),
             bools: BitSet8::from_range(3, 7),
+            refs: BitSet8::from_range(0, 0),
         };
         assert_eq!(vts.example().to_string(), "f32");
 
@@ -676,6 +731,7 @@ mod tests {
             ints: BitSet8::from_range(0, 0),
             floats: BitSet8::from_range(5, 7),
             bools: BitSet8::from_range(3, 7),
+            refs: BitSet8::from_range(0, 0),
         };
         assert_eq!(vts.example().to_string(), "f32x2");
 
@@ -684,6 +740,7 @@ mod tests {
             ints: BitSet8::from_range(0, 0),
             floats: BitSet8::from_range(0, 0),
             bools: BitSet8::from_range(3, 7),
+            refs: BitSet8::from_range(0, 0),
         };
         assert!(!vts.contains(B32X2));
         assert!(vts.contains(B32X4));
@@ -695,8 +752,23 @@ mod tests {
             ints: BitSet8::from_range(3, 7),
             floats: BitSet8::from_range(0, 0),
             bools: BitSet8::from_range(0, 0),
+            refs: BitSet8::from_range(0, 0),
         };
         assert!(vts.contains(I32));
         assert!(vts.contains(I32X4));
+        assert!(!vts.contains(R64));
+
+        let vts = ValueTypeSet {
+            // TypeSet(lanes=(1, 1), refs=(32, 64))
+            lanes: BitSet16::from_range(0, 1),
+            ints: BitSet8::from_range(0, 0),
+            floats: BitSet8::from_range(0, 0),
+            bools: BitSet8::from_range(0, 0),
+            refs: BitSet8::from_range(5, 7),
+        };
+        assert!(vts.contains(R32));
+        assert!(vts.contains(R64));
+        assert!(!vts.contains(I64));
+        assert_eq!(vts.example().to_string(), "r64");
     }
 }
@@ -459,6 +459,34 @@ impl Layout {
     pub fn next_ebb(&self, ebb: Ebb) -> Option<Ebb> {
         self.ebbs[ebb].next.expand()
     }
+
+    /// Mark `ebb` as cold, or not cold.
+    ///
+    /// Cold EBBs are rarely executed, like trap handlers and slow paths. The hint doesn't affect
+    /// the semantics of the program, but the EBB ordering pass moves cold EBBs to the end of the
+    /// function.
+    pub fn set_cold(&mut self, ebb: Ebb, cold: bool) {
+        self.ebbs[ebb].cold = cold;
+    }
+
+    /// Has `ebb` been marked as cold?
+    pub fn is_cold(&self, ebb: Ebb) -> bool {
+        self.ebbs[ebb].cold
+    }
+
+    /// Mark `ebb` as an OSR entry, or not.
+    ///
+    /// An OSR entry is a loop header where a tiering JIT compiler can transfer execution from
+    /// baseline code into the optimized code of the function. The marker doesn't affect the
+    /// compilation of the function, see `osr::make_osr_entry`.
+    pub fn set_osr_entry(&mut self, ebb: Ebb, osr_entry: bool) {
+        self.ebbs[ebb].osr_entry = osr_entry;
+    }
+
+    /// Has `ebb` been marked as an OSR entry?
+    pub fn is_osr_entry(&self, ebb: Ebb) -> bool {
+        self.ebbs[ebb].osr_entry
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -468,6 +496,8 @@ struct EbbNode {
     first_inst: PackedOption<Inst>,
     last_inst: PackedOption<Inst>,
     seq: SequenceNumber,
+    cold: bool,
+    osr_entry: bool,
 }
 
 /// Iterate over EBBs in layout order. See `Layout::ebbs()`.
@@ -33,16 +33,58 @@ pub enum LibCall {
     TruncF32,
     /// frunc.f64
     TruncF64,
+    /// fma.f32
+    FmaF32,
+    /// fma.f64
+    FmaF64,
     /// nearest.f32
     NearestF32,
     /// nearest.f64
     NearestF64,
+    /// fadd.f32
+    FaddF32,
+    /// fadd.f64
+    FaddF64,
+    /// fsub.f32
+    FsubF32,
+    /// fsub.f64
+    FsubF64,
+    /// fmul.f32
+    FmulF32,
+    /// fmul.f64
+    FmulF64,
+    /// fdiv.f32
+    FdivF32,
+    /// fdiv.f64
+    FdivF64,
+    /// sqrt.f32
+    SqrtF32,
+    /// sqrt.f64
+    SqrtF64,
+    /// imul.i32
+    ImulI32,
+    /// imul.i64
+    ImulI64,
     /// libc.memcpy
     Memcpy,
     /// libc.memset
     Memset,
     /// libc.memmove
     Memmove,
+    /// __tls_get_addr, used by the ELF general-dynamic TLS model
+    ElfTlsGetAddr,
+    /// WebAssembly memory.init, copying a passive data segment into a linear memory
+    WasmMemoryInit,
+    /// WebAssembly data.drop, discarding a passive data segment
+    WasmDataDrop,
+    /// WebAssembly table.copy, copying elements between tables
+    WasmTableCopy,
+    /// WebAssembly i32.atomic.wait, waiting for a notification on an address of a linear memory
+    WasmAtomicWait32,
+    /// WebAssembly i64.atomic.wait, waiting for a notification on an address of a linear memory
+    WasmAtomicWait64,
+    /// WebAssembly atomic.notify, waking up the threads waiting on an address of a linear memory
+    WasmAtomicNotify,
 }
 
 impl fmt::Display for LibCall {
@@ -63,11 +105,32 @@ impl FromStr for LibCall {
             "FloorF64" => Ok(LibCall::FloorF64),
             "TruncF32" => Ok(LibCall::TruncF32),
             "TruncF64" => Ok(LibCall::TruncF64),
+            "FmaF32" => Ok(LibCall::FmaF32),
+            "FmaF64" => Ok(LibCall::FmaF64),
             "NearestF32" => Ok(LibCall::NearestF32),
             "NearestF64" => Ok(LibCall::NearestF64),
+            "FaddF32" => Ok(LibCall::FaddF32),
+            "FaddF64" => Ok(LibCall::FaddF64),
+            "FsubF32" => Ok(LibCall::FsubF32),
+            "FsubF64" => Ok(LibCall::FsubF64),
+            "FmulF32" => Ok(LibCall::FmulF32),
+            "FmulF64" => Ok(LibCall::FmulF64),
+            "FdivF32" => Ok(LibCall::FdivF32),
+            "FdivF64" => Ok(LibCall::FdivF64),
+            "SqrtF32" => Ok(LibCall::SqrtF32),
+            "SqrtF64" => Ok(LibCall::SqrtF64),
+            "ImulI32" => Ok(LibCall::ImulI32),
+            "ImulI64" => Ok(LibCall::ImulI64),
             "Memcpy" => Ok(LibCall::Memcpy),
             "Memset" => Ok(LibCall::Memset),
             "Memmove" => Ok(LibCall::Memmove),
+            "ElfTlsGetAddr" => Ok(LibCall::ElfTlsGetAddr),
+            "WasmMemoryInit" => Ok(LibCall::WasmMemoryInit),
+            "WasmDataDrop" => Ok(LibCall::WasmDataDrop),
+            "WasmTableCopy" => Ok(LibCall::WasmTableCopy),
+            "WasmAtomicWait32" => Ok(LibCall::WasmAtomicWait32),
+            "WasmAtomicWait64" => Ok(LibCall::WasmAtomicWait64),
+            "WasmAtomicNotify" => Ok(LibCall::WasmAtomicNotify),
             _ => Err(()),
         }
     }
@@ -84,14 +147,34 @@ impl LibCall {
                 Opcode::Ceil => LibCall::CeilF32,
                 Opcode::Floor => LibCall::FloorF32,
                 Opcode::Trunc => LibCall::TruncF32,
+                Opcode::Fma => LibCall::FmaF32,
                 Opcode::Nearest => LibCall::NearestF32,
+                Opcode::Fadd => LibCall::FaddF32,
+                Opcode::Fsub => LibCall::FsubF32,
+                Opcode::Fmul => LibCall::FmulF32,
+                Opcode::Fdiv => LibCall::FdivF32,
+                Opcode::Sqrt => LibCall::SqrtF32,
                 _ => return None,
             },
             types::F64 => match opcode {
                 Opcode::Ceil => LibCall::CeilF64,
                 Opcode::Floor => LibCall::FloorF64,
                 Opcode::Trunc => LibCall::TruncF64,
+                Opcode::Fma => LibCall::FmaF64,
                 Opcode::Nearest => LibCall::NearestF64,
+                Opcode::Fadd => LibCall::FaddF64,
+                Opcode::Fsub => LibCall::FsubF64,
+                Opcode::Fmul => LibCall::FmulF64,
+                Opcode::Fdiv => LibCall::FdivF64,
+                Opcode::Sqrt => LibCall::SqrtF64,
+                _ => return None,
+            },
+            types::I32 => match opcode {
+                Opcode::Imul => LibCall::ImulI32,
+                _ => return None,
+            },
+            types::I64 => match opcode {
+                Opcode::Imul => LibCall::ImulI64,
                 _ => return None,
             },
             _ => return None,
@@ -201,5 +284,9 @@ mod tests {
     #[test]
     fn parsing() {
         assert_eq!("FloorF32".parse(), Ok(LibCall::FloorF32));
+        assert_eq!("FmaF64".parse(), Ok(LibCall::FmaF64));
+        assert_eq!("FaddF32".parse(), Ok(LibCall::FaddF32));
+        assert_eq!("ImulI64".parse(), Ok(LibCall::ImulI64));
+        assert_eq!("WasmMemoryInit".parse(), Ok(LibCall::WasmMemoryInit));
     }
 }
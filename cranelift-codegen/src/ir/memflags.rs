@@ -6,15 +6,29 @@ enum FlagBit {
     Notrap,
     Aligned,
     Readonly,
+    LittleEndian,
+    BigEndian,
 }
 
-const NAMES: [&str; 3] = ["notrap", "aligned", "readonly"];
+const NAMES: [&str; 5] = ["notrap", "aligned", "readonly", "little", "big"];
+
+/// The byte order of a memory access.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Endianness {
+    /// The least significant byte is stored at the lowest address.
+    Little,
+    /// The most significant byte is stored at the lowest address.
+    Big,
+}
 
 /// Flags for memory operations like load/store.
 ///
 /// Each of these flags introduce a limited form of undefined behavior. The flags each enable
 /// certain optimizations that need to make additional assumptions. Generally, the semantics of a
 /// program does not change when a flag is removed, but adding a flag will.
+///
+/// The exception is the byte order of the access which is native by default, and can be set
+/// explicitly with the `little` and `big` flags.
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub struct MemFlags {
     bits: u8,
@@ -35,6 +49,16 @@ impl MemFlags {
         result
     }
 
+    /// Get the raw bits of this set of flags.
+    pub(crate) fn bits(self) -> u8 {
+        self.bits
+    }
+
+    /// Create a set of flags from raw bits returned by `bits()`.
+    pub(crate) fn with_bits(bits: u8) -> Self {
+        Self { bits }
+    }
+
     /// Read a flag bit.
     fn read(self, bit: FlagBit) -> bool {
         self.bits & (1 << bit as usize) != 0
@@ -45,11 +69,25 @@ impl MemFlags {
         self.bits |= 1 << bit as usize
     }
 
+    /// Clear a flag bit.
+    fn clear(&mut self, bit: FlagBit) {
+        self.bits &= !(1 << bit as usize)
+    }
+
     /// Set a flag bit by name.
     ///
     /// Returns true if the flag was found and set, false for an unknown flag name.
     pub fn set_by_name(&mut self, name: &str) -> bool {
         match NAMES.iter().position(|&s| s == name) {
+            // The two byte orders are mutually exclusive.
+            Some(bit) if bit == FlagBit::LittleEndian as usize => {
+                self.set_endianness(Endianness::Little);
+                true
+            }
+            Some(bit) if bit == FlagBit::BigEndian as usize => {
+                self.set_endianness(Endianness::Big);
+                true
+            }
             Some(bit) => {
                 self.bits |= 1 << bit;
                 true
@@ -76,6 +114,11 @@ impl MemFlags {
         self.set(FlagBit::Notrap)
     }
 
+    /// Clear the `notrap` flag.
+    pub fn clear_notrap(&mut self) {
+        self.clear(FlagBit::Notrap)
+    }
+
     /// Test if the `aligned` flag is set.
     ///
     /// By default, Cranelift memory instructions work with any unaligned effective address. If the
@@ -103,6 +146,38 @@ impl MemFlags {
     pub fn set_readonly(&mut self) {
         self.set(FlagBit::Readonly)
     }
+
+    /// Get the byte order explicitly requested by the `little` or `big` flag, if any.
+    ///
+    /// Memory accesses without an explicit byte order use the native byte order of the target.
+    pub fn explicit_endianness(self) -> Option<Endianness> {
+        if self.read(FlagBit::LittleEndian) {
+            Some(Endianness::Little)
+        } else if self.read(FlagBit::BigEndian) {
+            Some(Endianness::Big)
+        } else {
+            None
+        }
+    }
+
+    /// Get the byte order of the memory access, given the `native` byte order of the target.
+    pub fn endianness(self, native: Endianness) -> Endianness {
+        self.explicit_endianness().unwrap_or(native)
+    }
+
+    /// Set an explicit byte order, replacing any previously requested byte order.
+    pub fn set_endianness(&mut self, endianness: Endianness) {
+        match endianness {
+            Endianness::Little => {
+                self.clear(FlagBit::BigEndian);
+                self.set(FlagBit::LittleEndian);
+            }
+            Endianness::Big => {
+                self.clear(FlagBit::LittleEndian);
+                self.set(FlagBit::BigEndian);
+            }
+        }
+    }
 }
 
 impl fmt::Display for MemFlags {
@@ -115,3 +190,29 @@ impl fmt::Display for MemFlags {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn endianness() {
+        let mut flags = MemFlags::new();
+        assert_eq!(flags.explicit_endianness(), None);
+        assert_eq!(flags.endianness(Endianness::Big), Endianness::Big);
+
+        assert!(flags.set_by_name("little"));
+        assert_eq!(flags.endianness(Endianness::Big), Endianness::Little);
+        assert_eq!(flags.to_string(), " little");
+
+        // Requesting a different byte order replaces the old one.
+        assert!(flags.set_by_name("big"));
+        assert_eq!(flags.explicit_endianness(), Some(Endianness::Big));
+        assert_eq!(flags.to_string(), " big");
+
+        flags.set_notrap();
+        flags.set_endianness(Endianness::Little);
+        assert_eq!(flags.to_string(), " notrap little");
+    }
+}
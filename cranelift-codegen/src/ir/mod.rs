@@ -1,9 +1,12 @@
 //! Representation of Cranelift IR functions.
 
+mod atomic_rmw_op;
+mod branchprob;
 mod builder;
 pub mod condcodes;
 pub mod dfg;
 pub mod entities;
+mod entity_map;
 mod extfunc;
 mod extname;
 pub mod function;
@@ -23,11 +26,14 @@ mod trapcode;
 pub mod types;
 mod valueloc;
 
+pub use crate::ir::atomic_rmw_op::AtomicRmwOp;
+pub use crate::ir::branchprob::BranchProbability;
 pub use crate::ir::builder::{InsertBuilder, InstBuilder, InstBuilderBase, InstInserterBase};
 pub use crate::ir::dfg::{DataFlowGraph, ValueDef};
 pub use crate::ir::entities::{
     Ebb, FuncRef, GlobalValue, Heap, Inst, JumpTable, SigRef, StackSlot, Table, Value,
 };
+pub use crate::ir::entity_map::EntityMap;
 pub use crate::ir::extfunc::{
     AbiParam, ArgumentExtension, ArgumentPurpose, ExtFuncData, Signature,
 };
@@ -41,7 +47,7 @@ pub use crate::ir::instructions::{
 pub use crate::ir::jumptable::JumpTableData;
 pub use crate::ir::layout::Layout;
 pub use crate::ir::libcall::{get_libcall_funcref, get_probestack_funcref, LibCall};
-pub use crate::ir::memflags::MemFlags;
+pub use crate::ir::memflags::{Endianness, MemFlags};
 pub use crate::ir::progpoint::{ExpandedProgramPoint, ProgramOrder, ProgramPoint};
 pub use crate::ir::sourceloc::SourceLoc;
 pub use crate::ir::stackslot::{StackSlotData, StackSlotKind, StackSlots};
@@ -71,3 +77,9 @@ pub type JumpTableOffsets = SecondaryMap<JumpTable, binemit::CodeOffset>;
 
 /// Source locations for instructions.
 pub type SourceLocs = SecondaryMap<Inst, SourceLoc>;
+
+/// Branch probabilities for conditional branch instructions.
+pub type BranchProbabilities = SecondaryMap<Inst, BranchProbability>;
+
+/// Trap codes for memory access instructions.
+pub type MemoryTrapCodes = SecondaryMap<Inst, Option<TrapCode>>;
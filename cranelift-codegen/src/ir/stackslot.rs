@@ -32,7 +32,7 @@ pub type StackOffset = i32;
 const MIN_SPILL_SLOT_SIZE: StackSize = 4;
 
 /// Get the spill slot size to use for `ty`.
-fn spill_size(ty: Type) -> StackSize {
+pub fn spill_size(ty: Type) -> StackSize {
     cmp::max(MIN_SPILL_SLOT_SIZE, ty.bytes())
 }
 
@@ -262,24 +262,22 @@ impl StackSlots {
         self.push(StackSlotData::new(StackSlotKind::SpillSlot, spill_size(ty)))
     }
 
-    /// Create a stack slot representing an incoming function argument.
-    pub fn make_incoming_arg(&mut self, ty: Type, offset: StackOffset) -> StackSlot {
-        let mut data = StackSlotData::new(StackSlotKind::IncomingArg, ty.bytes());
+    /// Create a stack slot of `size` bytes representing an incoming function argument.
+    pub fn make_incoming_arg(&mut self, size: StackSize, offset: StackOffset) -> StackSlot {
+        let mut data = StackSlotData::new(StackSlotKind::IncomingArg, size);
         debug_assert!(offset <= StackOffset::max_value() - data.size as StackOffset);
         data.offset = Some(offset);
         self.push(data)
     }
 
-    /// Get a stack slot representing an outgoing argument.
+    /// Get a stack slot of `size` bytes representing an outgoing argument.
     ///
     /// This may create a new stack slot, or reuse an existing outgoing stack slot with the
     /// requested offset and size.
     ///
     /// The requested offset is relative to this function's stack pointer immediately before making
     /// the call.
-    pub fn get_outgoing_arg(&mut self, ty: Type, offset: StackOffset) -> StackSlot {
-        let size = ty.bytes();
-
+    pub fn get_outgoing_arg(&mut self, size: StackSize, offset: StackOffset) -> StackSlot {
         // Look for an existing outgoing stack slot with the same offset and size.
         let inspos = match self.outgoing.binary_search_by_key(&(offset, size), |&ss| {
             (self[ss].offset.unwrap(), self[ss].size)
@@ -364,9 +362,9 @@ mod tests {
     fn outgoing() {
         let mut sss = StackSlots::new();
 
-        let ss0 = sss.get_outgoing_arg(types::I32, 8);
-        let ss1 = sss.get_outgoing_arg(types::I32, 4);
-        let ss2 = sss.get_outgoing_arg(types::I64, 8);
+        let ss0 = sss.get_outgoing_arg(types::I32.bytes(), 8);
+        let ss1 = sss.get_outgoing_arg(types::I32.bytes(), 4);
+        let ss2 = sss.get_outgoing_arg(types::I64.bytes(), 8);
 
         assert_eq!(sss[ss0].offset, Some(8));
         assert_eq!(sss[ss0].size, 4);
@@ -377,9 +375,9 @@ mod tests {
         assert_eq!(sss[ss2].offset, Some(8));
         assert_eq!(sss[ss2].size, 8);
 
-        assert_eq!(sss.get_outgoing_arg(types::I32, 8), ss0);
-        assert_eq!(sss.get_outgoing_arg(types::I32, 4), ss1);
-        assert_eq!(sss.get_outgoing_arg(types::I64, 8), ss2);
+        assert_eq!(sss.get_outgoing_arg(types::I32.bytes(), 8), ss0);
+        assert_eq!(sss.get_outgoing_arg(types::I32.bytes(), 4), ss1);
+        assert_eq!(sss.get_outgoing_arg(types::I64.bytes(), 8), ss2);
     }
 
     #[test]
@@ -17,7 +17,7 @@ pub fn legalize_signature(
 
 /// Get register class for a type appearing in a legalized signature.
 pub fn regclass_for_abi_type(ty: ir::Type) -> RegClass {
-    if ty.is_int() {
+    if ty.is_int() || ty.is_ref() {
         GPR
     } else {
         match ty.bits() {
@@ -1,7 +1,295 @@
 //! Emitting binary ARM32 machine code.
 
-use crate::binemit::{bad_encoding, CodeSink};
-use crate::ir::{Function, Inst};
+use crate::binemit::{bad_encoding, CodeOffset, CodeSink, Reloc};
+use crate::ir::condcodes::IntCC;
+use crate::ir::{Function, Inst, InstructionData};
+use crate::isa::RegUnit;
+use crate::predicates::is_signed_int;
 use crate::regalloc::RegDiversions;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-arm32.rs"));
+
+/// Emit `size` bytes of A32 `nop` instructions.
+pub fn emit_padding<CS: CodeSink + ?Sized>(size: CodeOffset, sink: &mut CS) {
+    debug_assert_eq!(size % 4, 0);
+    for _ in 0..size / 4 {
+        sink.put4(0xe320_f000);
+    }
+}
+
+/// Emit `size` bytes of 16-bit T32 `nop` instructions.
+pub fn emit_padding_t32<CS: CodeSink + ?Sized>(size: CodeOffset, sink: &mut CS) {
+    debug_assert_eq!(size % 2, 0);
+    for _ in 0..size / 2 {
+        sink.put2(0xbf00);
+    }
+}
+
+/// Convert an integer condition code to the 4-bit ARM condition field.
+fn icc2cond(cond: IntCC) -> u16 {
+    use crate::ir::condcodes::IntCC::*;
+    match cond {
+        Equal => 0b0000,
+        NotEqual => 0b0001,
+        UnsignedGreaterThanOrEqual => 0b0010,
+        UnsignedLessThan => 0b0011,
+        // 0b0100 = Minus.
+        // 0b0101 = Plus.
+        // 0b0110 = Overflow.
+        // 0b0111 = !Overflow.
+        UnsignedGreaterThan => 0b1000,
+        UnsignedLessThanOrEqual => 0b1001,
+        SignedGreaterThanOrEqual => 0b1010,
+        SignedLessThan => 0b1011,
+        SignedGreaterThan => 0b1100,
+        SignedLessThanOrEqual => 0b1101,
+    }
+}
+
+/// Get the 3-bit field for a low register in a 16-bit instruction.
+fn low(ru: RegUnit) -> u16 {
+    debug_assert!(reg(ru) < 8, "r{} is not a low register", reg(ru));
+    ru & 0x7
+}
+
+/// Get the 4-bit field for any register.
+fn reg(ru: RegUnit) -> u16 {
+    ru & 0xf
+}
+
+/// Compute the displacement of a branch to `dest` at the current offset. T32 branches are
+/// relative to the address of the branch instruction + 4.
+fn branch_disp<CS: CodeSink + ?Sized>(dest: CodeOffset, sink: &CS) -> i64 {
+    i64::from(dest) - i64::from(sink.offset() + 4)
+}
+
+/// 16-bit instructions with three 3-bit register fields.
+///
+///   15     8  5  2
+///   opcode rm rn rd
+///       9  6  3  0
+fn put_n_rrr<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    sink.put2(bits | low(rm) << 6 | low(rn) << 3 | low(rd));
+}
+
+/// 16-bit instructions with a 5-bit immediate in place of `rm`. The immediate is a shift amount,
+/// a scaled memory offset, or a 3-bit immediate where the top two bits belong to the opcode.
+///
+///   15     10   5  2
+///   opcode imm5 rn rd
+///       11    6  3  0
+fn put_n_imm5<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, imm: i64, rd: RegUnit, sink: &mut CS) {
+    sink.put2(bits | (imm as u16 & 0x1f) << 6 | low(rn) << 3 | low(rd));
+}
+
+/// 16-bit data processing instructions with a tied destination.
+///
+///   15     5  2
+///   opcode rm rdn
+///       6  3   0
+fn put_n_rr<CS: CodeSink + ?Sized>(bits: u16, rdn: RegUnit, rm: RegUnit, sink: &mut CS) {
+    sink.put2(bits | low(rm) << 3 | low(rdn));
+}
+
+/// 16-bit instructions with an 8-bit immediate.
+///
+///   15     10  7
+///   opcode rdn imm8
+///       11   8    0
+fn put_n_ri8<CS: CodeSink + ?Sized>(bits: u16, rdn: RegUnit, imm: i64, sink: &mut CS) {
+    sink.put2(bits | low(rdn) << 8 | (imm as u16 & 0xff));
+}
+
+/// 16-bit register move which can access all registers.
+///
+///   15     7 6  2
+///   opcode D rm rd
+///        8 7  3  0
+///
+/// The `D` bit is the high bit of `rd`.
+fn put_n_mov<CS: CodeSink + ?Sized>(bits: u16, rm: RegUnit, rd: RegUnit, sink: &mut CS) {
+    let rd = reg(rd);
+    sink.put2(bits | (rd & 0x8) << 4 | reg(rm) << 3 | (rd & 0x7));
+}
+
+/// 16-bit `bx` and `blx` instructions.
+///
+///   15     6  2
+///   opcode rm 000
+///        7  3   0
+fn put_n_bx<CS: CodeSink + ?Sized>(bits: u16, rm: RegUnit, sink: &mut CS) {
+    sink.put2(bits | reg(rm) << 3);
+}
+
+/// 16-bit unconditional branch to `dest`.
+///
+///   15     10
+///   opcode imm11
+///       11     0
+fn put_n_b<CS: CodeSink + ?Sized>(bits: u16, dest: CodeOffset, sink: &mut CS) {
+    let disp = branch_disp(dest, sink);
+    debug_assert!(is_signed_int(disp, 12, 1), "B.N out of range {:#x}", disp);
+    sink.put2(bits | ((disp >> 1) as u16 & 0x7ff));
+}
+
+/// 16-bit conditional branch to `dest`.
+///
+///   15   11   7
+///   1101 cond imm8
+///     12    8    0
+fn put_n_bcond<CS: CodeSink + ?Sized>(cond: u16, dest: CodeOffset, sink: &mut CS) {
+    let disp = branch_disp(dest, sink);
+    debug_assert!(is_signed_int(disp, 9, 1), "B<c>.N out of range {:#x}", disp);
+    sink.put2(0xd000 | cond << 8 | ((disp >> 1) as u16 & 0xff));
+}
+
+/// Emit a 32-bit instruction. The most significant halfword comes first.
+fn put_w<CS: CodeSink + ?Sized>(hw1: u16, hw2: u16, sink: &mut CS) {
+    sink.put2(hw1);
+    sink.put2(hw2);
+}
+
+/// 32-bit data processing instructions with a register operand.
+///
+///   15     3     15 14   11 7    5    3
+///   opcode rn    0  imm3 rd imm2 type rm
+///        4  0       12    8    6    4  0
+///
+/// The shift is always `lsl #0`.
+fn put_w_rrr<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    put_w(bits | reg(rn), reg(rd) << 8 | reg(rm), sink);
+}
+
+/// 32-bit multiply instructions.
+///
+///   15     3     15   11 7    3
+///   opcode rn    1111 rd 0000 rm
+///        4  0      12  8    4  0
+fn put_w_mul<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rn: RegUnit,
+    rm: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    put_w(bits | reg(rn), 0xf000 | reg(rd) << 8 | reg(rm), sink);
+}
+
+/// 32-bit data processing instructions with a 12-bit immediate split into `i:imm3:imm8`.
+///
+///   15     10 9      3     15 14   11 7
+///   opcode i  opcode rn    0  imm3 rd imm8
+///       11 10      4  0       12    8    0
+fn put_w_ri12<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, imm: i64, rd: RegUnit, sink: &mut CS) {
+    let imm = imm as u16 & 0xfff;
+    put_w(
+        bits | (imm >> 11) << 10 | reg(rn),
+        (imm >> 8 & 0x7) << 12 | reg(rd) << 8 | (imm & 0xff),
+        sink,
+    );
+}
+
+/// 32-bit `movw` and `movt` instructions with a 16-bit immediate split into `imm4:i:imm3:imm8`.
+///
+///   15     10 9      3     15 14   11 7
+///   opcode i  opcode imm4  0  imm3 rd imm8
+///       11 10      4    0     12    8    0
+fn put_w_movw<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS) {
+    let imm = imm as u16;
+    put_w(
+        bits | (imm >> 11 & 0x1) << 10 | imm >> 12,
+        (imm >> 8 & 0x7) << 12 | reg(rd) << 8 | (imm & 0xff),
+        sink,
+    );
+}
+
+/// 32-bit loads and stores with an unsigned 12-bit offset.
+///
+///   15     3     15 11
+///   opcode rn    rt imm12
+///        4  0    12     0
+fn put_w_ldst<CS: CodeSink + ?Sized>(bits: u16, rn: RegUnit, imm: i64, rt: RegUnit, sink: &mut CS) {
+    put_w(bits | reg(rn), reg(rt) << 12 | (imm as u16 & 0xfff), sink);
+}
+
+/// 32-bit register comparison: `cmp.w rn, rm`.
+fn put_w_cmp<CS: CodeSink + ?Sized>(rn: RegUnit, rm: RegUnit, sink: &mut CS) {
+    put_w(0xebb0 | reg(rn), 0x0f00 | reg(rm), sink);
+}
+
+/// 32-bit comparison with zero: `cmp.w rn, #0`.
+fn put_w_cmp0<CS: CodeSink + ?Sized>(rn: RegUnit, sink: &mut CS) {
+    put_w(0xf1b0 | reg(rn), 0x0f00, sink);
+}
+
+/// 32-bit conditional branch to `dest`.
+///
+///   15    10 9    5     15 13 12 11 10
+///   11110 S  cond imm6  10 J1 0  J2 imm11
+///      11 10    6    0     13    11     0
+///
+/// The displacement is `S:J2:J1:imm6:imm11:0`.
+fn put_w_bcond<CS: CodeSink + ?Sized>(cond: u16, dest: CodeOffset, sink: &mut CS) {
+    let disp = branch_disp(dest, sink);
+    debug_assert!(
+        is_signed_int(disp, 21, 1),
+        "B<c>.W out of range {:#x}",
+        disp
+    );
+    let disp = disp as u32;
+    let s = (disp >> 20 & 0x1) as u16;
+    let j2 = (disp >> 19 & 0x1) as u16;
+    let j1 = (disp >> 18 & 0x1) as u16;
+    let imm6 = (disp >> 12 & 0x3f) as u16;
+    let imm11 = (disp >> 1 & 0x7ff) as u16;
+    put_w(
+        0xf000 | s << 10 | cond << 6 | imm6,
+        0x8000 | j1 << 13 | j2 << 11 | imm11,
+        sink,
+    );
+}
+
+/// 32-bit unconditional branches and calls with a 25-bit displacement.
+///
+///   15     10 9      15 14 13 12 11 10
+///   opcode S  imm10  1  L  J1 1  J2 imm11
+///       11 10     0        13    11     0
+///
+/// The displacement is `S:I1:I2:imm10:imm11:0` where `I1 = !(J1 ^ S)` and `I2 = !(J2 ^ S)`.
+fn put_w_b24<CS: CodeSink + ?Sized>(bits: u16, hw2: u16, disp: i64, sink: &mut CS) {
+    debug_assert!(is_signed_int(disp, 25, 1), "B.W out of range {:#x}", disp);
+    let disp = disp as u32;
+    let s = (disp >> 24 & 0x1) as u16;
+    let j1 = !((disp >> 23) as u16 ^ s) & 0x1;
+    let j2 = !((disp >> 22) as u16 ^ s) & 0x1;
+    let imm10 = (disp >> 12 & 0x3ff) as u16;
+    let imm11 = (disp >> 1 & 0x7ff) as u16;
+    put_w(
+        bits | s << 10 | imm10,
+        hw2 | j1 << 13 | j2 << 11 | imm11,
+        sink,
+    );
+}
+
+/// 32-bit unconditional branch to `dest`: `b.w label`.
+fn put_w_b<CS: CodeSink + ?Sized>(bits: u16, dest: CodeOffset, sink: &mut CS) {
+    let disp = branch_disp(dest, sink);
+    put_w_b24(bits, 0x9000, disp, sink);
+}
+
+/// 32-bit call with a zero displacement to be filled in by a relocation: `bl func`.
+fn put_w_bl<CS: CodeSink + ?Sized>(bits: u16, sink: &mut CS) {
+    put_w_b24(bits, 0xd000, 0, sink);
+}
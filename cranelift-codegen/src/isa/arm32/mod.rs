@@ -9,7 +9,7 @@ pub mod settings;
 use super::super::settings as shared_settings;
 #[cfg(feature = "testing_hooks")]
 use crate::binemit::CodeSink;
-use crate::binemit::{emit_function, MemoryCodeSink};
+use crate::binemit::{emit_function, CodeOffset, MemoryCodeSink};
 use crate::ir;
 use crate::isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
 use crate::isa::Builder as IsaBuilder;
@@ -24,6 +24,7 @@ struct Isa {
     triple: Triple,
     shared_flags: shared_settings::Flags,
     isa_flags: settings::Flags,
+    thumb: bool,
     cpumode: &'static [shared_enc_tables::Level1Entry<u16>],
 }
 
@@ -41,21 +42,26 @@ fn isa_constructor(
     shared_flags: shared_settings::Flags,
     builder: shared_settings::Builder,
 ) -> Box<TargetIsa> {
-    let level1 = match triple.architecture {
-        Architecture::Thumbv6m | Architecture::Thumbv7em | Architecture::Thumbv7m => {
-            &enc_tables::LEVEL1_T32[..]
-        }
+    let isa_flags = settings::Flags::new(&shared_flags, builder);
+    let thumb = match triple.architecture {
+        Architecture::Thumbv6m | Architecture::Thumbv7em | Architecture::Thumbv7m => true,
         Architecture::Arm
         | Architecture::Armv4t
         | Architecture::Armv5te
         | Architecture::Armv7
-        | Architecture::Armv7s => &enc_tables::LEVEL1_A32[..],
+        | Architecture::Armv7s => isa_flags.thumb2(),
         _ => panic!(),
     };
+    let level1 = if thumb {
+        &enc_tables::LEVEL1_T32[..]
+    } else {
+        &enc_tables::LEVEL1_A32[..]
+    };
     Box::new(Isa {
         triple,
-        isa_flags: settings::Flags::new(&shared_flags, builder),
+        isa_flags,
         shared_flags,
+        thumb,
         cpumode: level1,
     })
 }
@@ -124,8 +130,21 @@ impl TargetIsa for Isa {
         binemit::emit_inst(func, inst, divert, sink)
     }
 
+    #[cfg(feature = "testing_hooks")]
+    fn emit_padding(&self, size: CodeOffset, sink: &mut CodeSink) {
+        if self.thumb {
+            binemit::emit_padding_t32(size, sink)
+        } else {
+            binemit::emit_padding(size, sink)
+        }
+    }
+
     fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
-        emit_function(func, binemit::emit_inst, sink)
+        if self.thumb {
+            emit_function(func, binemit::emit_inst, binemit::emit_padding_t32, sink)
+        } else {
+            emit_function(func, binemit::emit_inst, binemit::emit_padding, sink)
+        }
     }
 }
 
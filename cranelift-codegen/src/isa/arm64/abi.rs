@@ -1,30 +1,239 @@
 //! ARM 64 ABI implementation.
+//!
+//! This module implements the AArch64 procedure call standard (AAPCS64) through the primary
+//! `legalize_signature()` entry point, along with the Apple variant of it used on macOS and iOS.
+//!
+//! The Apple variant differs in the placement of stack arguments: they are packed at their
+//! natural size and alignment instead of each occupying a multiple of 8 bytes. The platform
+//! register `%x18` is also reserved on Apple platforms.
 
 use super::registers::{FPR, GPR};
-use crate::ir;
-use crate::isa::RegClass;
+use crate::abi::{legalize_args, ArgAction, ArgAssigner, ValueConversion};
+use crate::ir::{self, types, AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, Type};
+use crate::isa::{CallConv, RegClass};
 use crate::regalloc::RegisterSet;
 use crate::settings as shared_settings;
+use core::i32;
+use target_lexicon::{OperatingSystem, Triple};
+
+/// Number of argument registers in each register class.
+const ARG_REGS: u32 = 8;
+
+struct Args {
+    gprs: u32,
+    fprs: u32,
+    offset: u32,
+    packed: bool,
+}
+
+impl Args {
+    fn new(call_conv: CallConv) -> Self {
+        Self {
+            gprs: 0,
+            fprs: 0,
+            offset: 0,
+            packed: call_conv == CallConv::AppleAarch64,
+        }
+    }
+
+    /// Assign a stack location to an argument of `size` bytes.
+    fn assign_stack(&mut self, size: u32) -> ArgAction {
+        // AAPCS64 rounds the size of stack arguments up to 8 bytes. Apple packs them with their
+        // natural alignment.
+        let (size, align) = if self.packed {
+            (size, size.min(16))
+        } else {
+            let size = (size + 7) & !7;
+            (size, size.min(16))
+        };
+        self.offset = (self.offset + align - 1) & !(align - 1);
+        let loc = ArgumentLoc::Stack(self.offset as i32);
+        self.offset += size;
+        debug_assert!(self.offset <= i32::MAX as u32);
+        loc.into()
+    }
+}
+
+impl ArgAssigner for Args {
+    fn assign(&mut self, arg: &AbiParam) -> ArgAction {
+        let ty = arg.value_type;
+
+        // Vectors larger than a SIMD register are broken down.
+        if ty.is_vector() && ty.bits() > 128 {
+            return ValueConversion::VectorSplit.into();
+        }
+
+        // Large integers and booleans are broken down to fit in a register. A 128-bit integer is
+        // passed in an even-numbered register pair, or 16-byte aligned on the stack.
+        if !ty.is_vector() && !ty.is_float() && ty.bits() > 64 {
+            self.gprs = (self.gprs + 1) & !1;
+            if self.gprs >= ARG_REGS {
+                self.offset = (self.offset + 15) & !15;
+            }
+            return ValueConversion::IntSplit.into();
+        }
+
+        // Structs passed by value are copied to the stack.
+        if let ArgumentPurpose::StructArgument(_) = arg.purpose {
+            self.offset = (self.offset + 7) & !7;
+            let loc = ArgumentLoc::Stack(self.offset as i32);
+            self.offset += arg.stack_size();
+            debug_assert!(self.offset <= i32::MAX as u32);
+            return loc.into();
+        }
+
+        if ty.is_float() || ty.is_vector() {
+            if self.fprs < ARG_REGS {
+                let reg = FPR.unit(self.fprs as usize);
+                self.fprs += 1;
+                return ArgumentLoc::Reg(reg).into();
+            }
+            return self.assign_stack(ty.bytes());
+        }
+
+        if self.gprs < ARG_REGS {
+            // Small integers are extended to the size of a register. Apple only requires the
+            // extension to 32 bits, which is implied.
+            if ty.is_int() && ty.bits() < 64 {
+                match arg.extension {
+                    ArgumentExtension::None => {}
+                    ArgumentExtension::Uext => return ValueConversion::Uext(types::I64).into(),
+                    ArgumentExtension::Sext => return ValueConversion::Sext(types::I64).into(),
+                }
+            }
+
+            let reg = GPR.unit(self.gprs as usize);
+            self.gprs += 1;
+            return ArgumentLoc::Reg(reg).into();
+        }
+
+        // Small integers on the stack are only extended when they get a full 8-byte slot.
+        if !self.packed && ty.is_int() && ty.bits() < 64 {
+            match arg.extension {
+                ArgumentExtension::None => {}
+                ArgumentExtension::Uext => return ValueConversion::Uext(types::I64).into(),
+                ArgumentExtension::Sext => return ValueConversion::Sext(types::I64).into(),
+            }
+        }
+
+        self.assign_stack(ty.bytes())
+    }
+}
 
 /// Legalize `sig`.
-pub fn legalize_signature(
-    _sig: &mut ir::Signature,
-    _flags: &shared_settings::Flags,
-    _current: bool,
-) {
-    unimplemented!()
+pub fn legalize_signature(sig: &mut ir::Signature, _flags: &shared_settings::Flags, current: bool) {
+    let mut args = Args::new(sig.call_conv);
+    legalize_args(&mut sig.params, &mut args);
+
+    let mut rets = Args::new(sig.call_conv);
+    legalize_args(&mut sig.returns, &mut rets);
+
+    if current {
+        // Add the link register as an argument and return value.
+        let link = AbiParam::special_reg(types::I64, ArgumentPurpose::Link, GPR.unit(30));
+        sig.params.push(link);
+        sig.returns.push(link);
+    }
 }
 
 /// Get register class for a type appearing in a legalized signature.
-pub fn regclass_for_abi_type(ty: ir::Type) -> RegClass {
-    if ty.is_int() {
+pub fn regclass_for_abi_type(ty: Type) -> RegClass {
+    if ty.is_int() || ty.is_bool() || ty.is_ref() {
         GPR
     } else {
         FPR
     }
 }
 
+/// Is `triple` an Apple platform, where the Apple variant of the ABI is used?
+pub fn is_apple(triple: &Triple) -> bool {
+    match triple.operating_system {
+        OperatingSystem::Darwin | OperatingSystem::Ios => true,
+        _ => false,
+    }
+}
+
 /// Get the set of allocatable registers for `func`.
-pub fn allocatable_registers(_func: &ir::Function) -> RegisterSet {
-    unimplemented!()
+pub fn allocatable_registers(func: &ir::Function, triple: &Triple) -> RegisterSet {
+    let mut regs = RegisterSet::new();
+    regs.take(GPR, GPR.unit(29)); // Frame pointer.
+    regs.take(GPR, GPR.unit(31)); // Stack pointer or zero register.
+
+    // %x18 is the platform register which Apple reserves, and which may be clobbered by the OS at
+    // any time.
+    if func.signature.call_conv == CallConv::AppleAarch64 || is_apple(triple) {
+        regs.take(GPR, GPR.unit(18));
+    }
+
+    regs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings;
+    use core::str::FromStr;
+    use std::vec::Vec;
+    use target_lexicon::triple;
+
+    fn legalize(call_conv: CallConv, params: &[AbiParam]) -> Vec<ArgumentLoc> {
+        let mut sig = ir::Signature::new(call_conv);
+        sig.params.extend_from_slice(params);
+        legalize_signature(&mut sig, &settings::Flags::new(settings::builder()), false);
+        sig.params.iter().map(|p| p.location).collect()
+    }
+
+    #[test]
+    fn registers() {
+        let params = [
+            AbiParam::new(types::I64),
+            AbiParam::new(types::F64),
+            AbiParam::new(types::I32),
+            AbiParam::new(types::F32X4),
+        ];
+        let locs = [
+            ArgumentLoc::Reg(GPR.unit(0)),
+            ArgumentLoc::Reg(FPR.unit(0)),
+            ArgumentLoc::Reg(GPR.unit(1)),
+            ArgumentLoc::Reg(FPR.unit(1)),
+        ];
+        assert_eq!(legalize(CallConv::SystemV, &params), locs);
+        assert_eq!(legalize(CallConv::AppleAarch64, &params), locs);
+    }
+
+    #[test]
+    fn stack_args() {
+        // Fill the integer argument registers.
+        let mut params = vec![AbiParam::new(types::I64); 8];
+        params.push(AbiParam::new(types::I8));
+        params.push(AbiParam::new(types::I16));
+        params.push(AbiParam::new(types::I32));
+        params.push(AbiParam::new(types::I64));
+
+        let stack = |offsets: [i32; 4]| {
+            let mut locs: Vec<_> = (0..8).map(|i| ArgumentLoc::Reg(GPR.unit(i))).collect();
+            locs.extend(offsets.iter().map(|&offset| ArgumentLoc::Stack(offset)));
+            locs
+        };
+        assert_eq!(legalize(CallConv::SystemV, &params), stack([0, 8, 16, 24]));
+        assert_eq!(
+            legalize(CallConv::AppleAarch64, &params),
+            stack([0, 2, 4, 8])
+        );
+    }
+
+    #[test]
+    fn reserved_registers() {
+        let mut func = ir::Function::new();
+        let linux = triple!("aarch64-unknown-linux-gnu");
+        let ios = triple!("aarch64-apple-ios");
+
+        func.signature.call_conv = CallConv::SystemV;
+        assert!(allocatable_registers(&func, &linux).is_avail(GPR, GPR.unit(18)));
+        assert!(!allocatable_registers(&func, &ios).is_avail(GPR, GPR.unit(18)));
+
+        func.signature.call_conv = CallConv::AppleAarch64;
+        assert!(!allocatable_registers(&func, &linux).is_avail(GPR, GPR.unit(18)));
+        assert!(!allocatable_registers(&func, &linux).is_avail(GPR, GPR.unit(29)));
+    }
 }
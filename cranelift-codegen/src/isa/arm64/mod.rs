@@ -9,7 +9,7 @@ pub mod settings;
 use super::super::settings as shared_settings;
 #[cfg(feature = "testing_hooks")]
 use crate::binemit::CodeSink;
-use crate::binemit::{emit_function, MemoryCodeSink};
+use crate::binemit::{emit_function, CodeOffset, MemoryCodeSink};
 use crate::ir;
 use crate::isa::enc_tables::{lookup_enclist, Encodings};
 use crate::isa::Builder as IsaBuilder;
@@ -97,7 +97,7 @@ impl TargetIsa for Isa {
     }
 
     fn allocatable_registers(&self, func: &ir::Function) -> regalloc::RegisterSet {
-        abi::allocatable_registers(func)
+        abi::allocatable_registers(func, &self.triple)
     }
 
     #[cfg(feature = "testing_hooks")]
@@ -111,8 +111,13 @@ impl TargetIsa for Isa {
         binemit::emit_inst(func, inst, divert, sink)
     }
 
+    #[cfg(feature = "testing_hooks")]
+    fn emit_padding(&self, size: CodeOffset, sink: &mut CodeSink) {
+        binemit::emit_padding(size, sink)
+    }
+
     fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
-        emit_function(func, binemit::emit_inst, sink)
+        emit_function(func, binemit::emit_inst, binemit::emit_padding, sink)
     }
 }
 
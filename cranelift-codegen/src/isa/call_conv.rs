@@ -1,6 +1,6 @@
 use core::fmt;
 use core::str;
-use target_lexicon::{CallingConvention, Triple};
+use target_lexicon::{Architecture, CallingConvention, OperatingSystem, Triple};
 
 /// Calling convention identifiers.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -13,6 +13,8 @@ pub enum CallConv {
     SystemV,
     /// Windows "fastcall" convention, also used for x64 and ARM
     WindowsFastcall,
+    /// Apple variant of the AArch64 convention used on macOS and iOS
+    AppleAarch64,
     /// SpiderMonkey WebAssembly convention
     Baldrdash,
     /// Specialized convention for the probestack function
@@ -22,6 +24,13 @@ pub enum CallConv {
 impl CallConv {
     /// Return the default calling convention for the given target triple.
     pub fn triple_default(triple: &Triple) -> Self {
+        // Apple platforms use their own variant of the AArch64 convention.
+        if triple.architecture == Architecture::Aarch64
+            && (triple.operating_system == OperatingSystem::Darwin
+                || triple.operating_system == OperatingSystem::Ios)
+        {
+            return CallConv::AppleAarch64;
+        }
         match triple.default_calling_convention() {
             // Default to System V for unknown targets because most everything
             // uses System V.
@@ -38,6 +47,7 @@ impl fmt::Display for CallConv {
             CallConv::Cold => "cold",
             CallConv::SystemV => "system_v",
             CallConv::WindowsFastcall => "windows_fastcall",
+            CallConv::AppleAarch64 => "apple_aarch64",
             CallConv::Baldrdash => "baldrdash",
             CallConv::Probestack => "probestack",
         })
@@ -52,6 +62,7 @@ impl str::FromStr for CallConv {
             "cold" => Ok(CallConv::Cold),
             "system_v" => Ok(CallConv::SystemV),
             "windows_fastcall" => Ok(CallConv::WindowsFastcall),
+            "apple_aarch64" => Ok(CallConv::AppleAarch64),
             "baldrdash" => Ok(CallConv::Baldrdash),
             "probestack" => Ok(CallConv::Probestack),
             _ => Err(()),
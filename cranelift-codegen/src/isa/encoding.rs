@@ -114,6 +114,9 @@ pub struct EncInfo {
     /// Code size information per recipe.
     pub sizing: &'static [RecipeSizing],
 
+    /// Result latency in cycles per recipe.
+    pub latencies: &'static [u8],
+
     /// Names of encoding recipes.
     pub names: &'static [&'static str],
 }
@@ -148,6 +151,16 @@ impl EncInfo {
         })
     }
 
+    /// Get the number of cycles before the results of an instruction encoded with `enc` are
+    /// available to other instructions.
+    ///
+    /// Returns 1 for illegal encodings.
+    pub fn latency(&self, enc: Encoding) -> u32 {
+        self.latencies
+            .get(enc.recipe())
+            .map_or(1, |&latency| u32::from(latency))
+    }
+
     /// Get the branch range that is supported by `enc`, if any.
     ///
     /// This will never return `None` for a legal branch encoding.
@@ -0,0 +1,87 @@
+//! Detection of the host machine, for JIT compilers generating code to run in the same process.
+
+use crate::isa::{lookup, Builder, LookupError};
+use target_lexicon::Triple;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::settings::Configurable;
+
+#[cfg(target_arch = "x86")]
+use core::arch::x86::{__cpuid, __cpuid_count, __get_cpuid_max, has_cpuid};
+#[cfg(target_arch = "x86_64")]
+use core::arch::x86_64::{__cpuid, __cpuid_count, __get_cpuid_max};
+
+/// Return a builder for the machine Cranelift is running on.
+///
+/// The target triple is the one Cranelift itself was compiled for, so the pointer width and the
+/// default calling convention match the host. On x86, the ISA-specific settings describing the
+/// host's optional instruction set extensions are enabled according to what CPUID reports.
+///
+/// Returns `Err(LookupError::Unsupported)` if the host CPU lacks features Cranelift requires,
+/// such as SSE2 on x86.
+pub fn host() -> Result<Builder, LookupError> {
+    let mut isa_builder = lookup(Triple::host())?;
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    parse_x86_cpuid(&mut isa_builder)?;
+
+    Ok(isa_builder)
+}
+
+#[cfg(target_arch = "x86")]
+fn cpuid_available() -> bool {
+    has_cpuid()
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cpuid_available() -> bool {
+    true
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn parse_x86_cpuid(isa_builder: &mut Builder) -> Result<(), LookupError> {
+    if !cpuid_available() {
+        return Err(LookupError::Unsupported);
+    }
+
+    // The CPUID instruction is always safe to execute once we know it exists.
+    let (max_leaf, _) = unsafe { __get_cpuid_max(0) };
+    let leaf1 = unsafe { __cpuid(1) };
+    if leaf1.edx & (1 << 26) == 0 {
+        // x86 support requires SSE2.
+        return Err(LookupError::Unsupported);
+    }
+    for &(bit, name) in &[
+        (0, "has_sse3"),
+        (9, "has_ssse3"),
+        (12, "has_fma"),
+        (19, "has_sse41"),
+        (20, "has_sse42"),
+        (22, "has_movbe"),
+        (23, "has_popcnt"),
+        (28, "has_avx"),
+    ] {
+        if leaf1.ecx & (1 << bit) != 0 {
+            isa_builder.enable(name).unwrap();
+        }
+    }
+
+    if max_leaf >= 7 {
+        let leaf7 = unsafe { __cpuid_count(7, 0) };
+        for &(bit, name) in &[(3, "has_bmi1"), (8, "has_bmi2")] {
+            if leaf7.ebx & (1 << bit) != 0 {
+                isa_builder.enable(name).unwrap();
+            }
+        }
+    }
+
+    let (max_extended_leaf, _) = unsafe { __get_cpuid_max(0x8000_0000) };
+    if max_extended_leaf >= 0x8000_0001 {
+        let leaf = unsafe { __cpuid(0x8000_0001) };
+        if leaf.ecx & (1 << 5) != 0 {
+            isa_builder.enable("has_lzcnt").unwrap();
+        }
+    }
+
+    Ok(())
+}
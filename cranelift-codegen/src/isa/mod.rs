@@ -43,6 +43,12 @@
 //! # }
 //! ```
 //!
+//! When the target is only known by name, as when it comes from a command line or a
+//! configuration file, `TargetIsa::for_triple()` parses a target triple such as
+//! `"x86_64-unknown-linux-gnu"` and looks up its ISA. The triple determines the pointer width and
+//! the default calling convention of the resulting `TargetIsa`. To target the machine Cranelift is
+//! running on, `isa::host()` uses the host's triple and enables the CPU features it detects.
+//!
 //! The configured target ISA trait object is a `Box<TargetIsa>` which can be used for multiple
 //! concurrent function compilations.
 
@@ -51,6 +57,8 @@ pub use crate::isa::constraints::{
     BranchRange, ConstraintKind, OperandConstraint, RecipeConstraints,
 };
 pub use crate::isa::encoding::{base_size, EncInfo, Encoding};
+pub use crate::isa::host::host;
+pub use crate::isa::peephole::{apply_peephole_rules, PeepholeRule};
 pub use crate::isa::registers::{regs_overlap, RegClass, RegClassIndex, RegInfo, RegUnit};
 pub use crate::isa::stack::{StackBase, StackBaseMask, StackRef};
 
@@ -64,8 +72,10 @@ use crate::settings;
 use crate::settings::SetResult;
 use crate::timing;
 use core::fmt;
+use core::str::FromStr;
 use failure_derive::Fail;
 use std::boxed::Box;
+use std::vec::Vec;
 use target_lexicon::{Architecture, PointerWidth, Triple};
 
 #[cfg(feature = "riscv")]
@@ -84,6 +94,8 @@ mod call_conv;
 mod constraints;
 mod enc_tables;
 mod encoding;
+mod host;
+mod peephole;
 pub mod registers;
 mod stack;
 
@@ -124,6 +136,15 @@ pub fn lookup(triple: Triple) -> Result<Builder, LookupError> {
     }
 }
 
+/// Look for a supported ISA with the given target triple `name`, like "x86_64-unknown-linux-gnu".
+/// Return a builder that can create a corresponding `TargetIsa`.
+///
+/// A `name` which isn't a valid target triple is reported as `LookupError::Unsupported`.
+pub fn lookup_by_name(name: &str) -> Result<Builder, LookupError> {
+    let triple = Triple::from_str(name).map_err(|_| LookupError::Unsupported)?;
+    lookup(triple)
+}
+
 /// Describes reason for target lookup failure
 #[derive(Fail, PartialEq, Eq, Copy, Clone, Debug)]
 pub enum LookupError {
@@ -162,6 +183,14 @@ impl settings::Configurable for Builder {
     }
 }
 
+impl TargetIsa {
+    /// Look for a supported ISA with the given target triple `name`, like
+    /// "x86_64-unknown-linux-gnu". This is the same as `isa::lookup_by_name()`.
+    pub fn for_triple(name: &str) -> Result<Builder, LookupError> {
+        lookup_by_name(name)
+    }
+}
+
 /// After determining that an instruction doesn't have an encoding, how should we proceed to
 /// legalize it?
 ///
@@ -199,7 +228,10 @@ impl TargetFrontendConfig {
 
 /// Methods that are specialized to a target ISA. Implies a Display trait that shows the
 /// shared flags, as well as any isa-specific flags.
-pub trait TargetIsa: fmt::Display + Sync {
+///
+/// A `TargetIsa` is immutable once it has been built, so it can be shared by threads compiling
+/// different functions.
+pub trait TargetIsa: fmt::Display + Send + Sync {
     /// Get the name of this ISA.
     fn name(&self) -> &'static str;
 
@@ -234,6 +266,14 @@ pub trait TargetIsa: fmt::Display + Sync {
         self.pointer_width().bytes()
     }
 
+    /// Get the native byte order of this ISA.
+    fn endianness(&self) -> ir::Endianness {
+        match self.triple().endianness() {
+            Ok(target_lexicon::Endianness::Big) => ir::Endianness::Big,
+            _ => ir::Endianness::Little,
+        }
+    }
+
     /// Get the information needed by frontends producing Cranelift IR.
     fn frontend_config(&self) -> TargetFrontendConfig {
         TargetFrontendConfig {
@@ -252,6 +292,11 @@ pub trait TargetIsa: fmt::Display + Sync {
         false
     }
 
+    /// Get the peephole rules applied by the post-opt pass for this ISA.
+    fn peephole_rules(&self) -> &'static [PeepholeRule] {
+        &[]
+    }
+
     /// Get a data structure describing the registers in this ISA.
     fn register_info(&self) -> RegInfo;
 
@@ -370,6 +415,68 @@ pub trait TargetIsa: fmt::Display + Sync {
         sink: &mut binemit::CodeSink,
     );
 
+    /// Emit `size` bytes of nops into the `sink` trait object.
+    ///
+    /// This function is under the "testing_hooks" feature, and is only suitable for use by
+    /// test harnesses.
+    #[cfg(feature = "testing_hooks")]
+    fn emit_padding(&self, size: binemit::CodeOffset, sink: &mut binemit::CodeSink);
+
     /// Emit a whole function into memory.
     fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut binemit::MemoryCodeSink);
+
+    /// Emit the unwind information of the compiled function `func` into `mem`.
+    ///
+    /// Nothing is emitted for the calling conventions that don't have unwind information in the
+    /// target's ABI.
+    fn emit_unwind_info(&self, _func: &ir::Function, _mem: &mut Vec<u8>) {
+        // No unwind information by default.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings;
+
+    #[test]
+    fn invalid_name() {
+        assert_eq!(
+            lookup_by_name("not-a-triple").err(),
+            Some(LookupError::Unsupported)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn x86_by_name() {
+        let flags = settings::Flags::new(settings::builder());
+
+        let isa = lookup_by_name("x86_64-unknown-linux-gnu")
+            .unwrap()
+            .finish(flags.clone());
+        assert_eq!(isa.name(), "x86");
+        assert_eq!(isa.pointer_bits(), 64);
+        assert_eq!(isa.default_call_conv(), CallConv::SystemV);
+
+        let isa = lookup_by_name("x86_64-pc-windows-msvc")
+            .unwrap()
+            .finish(flags.clone());
+        assert_eq!(isa.default_call_conv(), CallConv::WindowsFastcall);
+
+        let isa = TargetIsa::for_triple("i686-unknown-linux-gnu")
+            .unwrap()
+            .finish(flags);
+        assert_eq!(isa.pointer_bits(), 32);
+    }
+
+    #[test]
+    #[cfg(all(feature = "x86", target_arch = "x86_64"))]
+    fn x86_host() {
+        let isa = host()
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+        assert_eq!(isa.name(), "x86");
+        assert_eq!(isa.pointer_bits(), 64);
+    }
 }
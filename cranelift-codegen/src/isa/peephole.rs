@@ -0,0 +1,39 @@
+//! Peephole rules over encoded instructions.
+//!
+//! A peephole rule matches a short sequence of instructions ending at a given instruction and
+//! rewrites it into a cheaper one. The rules only make sense for some targets, so each ISA
+//! declares its own list with `TargetIsa::peephole_rules`, and the post-opt pass tries them on
+//! every instruction after legalization.
+//!
+//! Rules work through an `EncCursor`, so all the instructions they insert or replace must have a
+//! legal encoding. They must not change the control flow graph, which keeps the analyses computed
+//! before the post-opt pass valid.
+
+use crate::cursor::EncCursor;
+use crate::ir::Inst;
+use log::debug;
+
+/// A peephole rewrite rule.
+pub struct PeepholeRule {
+    /// Name of the rule, used in debug output.
+    pub name: &'static str,
+
+    /// Try to rewrite the sequence ending at `inst`, which is the current cursor position.
+    ///
+    /// Returns true if the sequence was rewritten. The cursor must be left at `inst`, or at the
+    /// instruction before it if `inst` was removed.
+    pub apply: fn(&mut EncCursor, Inst) -> bool,
+}
+
+/// Apply the first rule in `rules` that matches the sequence ending at `inst`.
+///
+/// Returns true if a rule was applied.
+pub fn apply_peephole_rules(pos: &mut EncCursor, inst: Inst, rules: &[PeepholeRule]) -> bool {
+    for rule in rules {
+        if (rule.apply)(pos, inst) {
+            debug!("Applied peephole rule {} at {}", rule.name, inst);
+            return true;
+        }
+    }
+    false
+}
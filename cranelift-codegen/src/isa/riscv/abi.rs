@@ -3,7 +3,9 @@
 //! This module implements the RISC-V calling convention through the primary `legalize_signature()`
 //! entry point.
 //!
-//! This doesn't support the soft-float ABI at the moment.
+//! Floating point arguments are passed in `%f10`-`%f17` when the 'F' and 'D' extensions are
+//! enabled. When those registers run out, or without the extensions, floats are passed as
+//! integers of the same size.
 
 use super::registers::{FPR, GPR};
 use super::settings;
@@ -20,17 +22,23 @@ struct Args {
     pointer_type: Type,
     regs: u32,
     reg_limit: u32,
+    fpr_regs: u32,
+    hard_f32: bool,
+    hard_f64: bool,
     offset: u32,
 }
 
 impl Args {
-    fn new(bits: u8, enable_e: bool) -> Self {
+    fn new(bits: u8, isa_flags: &settings::Flags) -> Self {
         Self {
             pointer_bits: bits,
             pointer_bytes: bits / 8,
             pointer_type: Type::int(u16::from(bits)).unwrap(),
             regs: 0,
-            reg_limit: if enable_e { 6 } else { 8 },
+            reg_limit: if isa_flags.enable_e() { 6 } else { 8 },
+            fpr_regs: 0,
+            hard_f32: isa_flags.use_f(),
+            hard_f64: isa_flags.use_d(),
             offset: 0,
         }
     }
@@ -50,6 +58,31 @@ impl ArgAssigner for Args {
             return ValueConversion::VectorSplit.into();
         }
 
+        // Floats without hardware support are passed in integer registers.
+        if ty.is_float() {
+            let hard = if ty.bits() == 32 {
+                self.hard_f32
+            } else {
+                self.hard_f64
+            };
+            if !hard {
+                return ValueConversion::IntBits.into();
+            }
+
+            if self.fpr_regs < 8 {
+                let reg = FPR.unit(10 + self.fpr_regs as usize);
+                self.fpr_regs += 1;
+                return ArgumentLoc::Reg(reg).into();
+            }
+
+            // When the float argument registers are exhausted, floats are passed like integers
+            // of the same size while there are integer registers left. An `f64` on RV32 then
+            // uses a register pair like an `i64`.
+            if self.regs < self.reg_limit {
+                return ValueConversion::IntBits.into();
+            }
+        }
+
         // Large integers and booleans are broken down to fit in a register.
         if !ty.is_float() && ty.bits() > u16::from(self.pointer_bits) {
             // Align registers and stack to a multiple of two pointers.
@@ -67,19 +100,25 @@ impl ArgAssigner for Args {
             }
         }
 
-        if self.regs < self.reg_limit {
+        // Structs passed by value are copied to the stack.
+        if let ArgumentPurpose::StructArgument(_) = arg.purpose {
+            let loc = ArgumentLoc::Stack(self.offset as i32);
+            self.offset += arg.stack_size();
+            debug_assert!(self.offset <= i32::MAX as u32);
+            return loc.into();
+        }
+
+        if self.regs < self.reg_limit && !ty.is_float() {
             // Assign to a register.
-            let reg = if ty.is_float() {
-                FPR.unit(10 + self.regs as usize)
-            } else {
-                GPR.unit(10 + self.regs as usize)
-            };
+            let reg = GPR.unit(10 + self.regs as usize);
             self.regs += 1;
             ArgumentLoc::Reg(reg).into()
         } else {
             // Assign a stack location.
+            let size = u32::from(self.pointer_bytes).max(ty.bytes());
+            self.offset = align(self.offset, size);
             let loc = ArgumentLoc::Stack(self.offset as i32);
-            self.offset += u32::from(self.pointer_bytes);
+            self.offset += size;
             debug_assert!(self.offset <= i32::MAX as u32);
             loc.into()
         }
@@ -95,10 +134,10 @@ pub fn legalize_signature(
 ) {
     let bits = triple.pointer_width().unwrap().bits();
 
-    let mut args = Args::new(bits, isa_flags.enable_e());
+    let mut args = Args::new(bits, isa_flags);
     legalize_args(&mut sig.params, &mut args);
 
-    let mut rets = Args::new(bits, isa_flags.enable_e());
+    let mut rets = Args::new(bits, isa_flags);
     legalize_args(&mut sig.returns, &mut rets);
 
     if current {
@@ -1,14 +1,26 @@
 //! Emitting binary RISC-V machine code.
 
-use crate::binemit::{bad_encoding, CodeSink, Reloc};
-use crate::ir::{Function, Inst, InstructionData};
-use crate::isa::{RegUnit, StackBaseMask, StackRef};
+use super::registers::RU;
+use crate::binemit::{bad_encoding, CodeOffset, CodeSink, Reloc};
+use crate::ir::{Function, Inst, InstructionData, TrapCode};
+use crate::isa::{RegUnit, StackBase, StackBaseMask, StackRef};
 use crate::predicates::is_signed_int;
 use crate::regalloc::RegDiversions;
 use core::u32;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-riscv.rs"));
 
+/// Get the base register for a stack reference.
+///
+/// The recipes only accept SP-relative stack references, so the base is always `x2`.
+fn stk_base(base: StackBase) -> RegUnit {
+    let ru = match base {
+        StackBase::SP => RU::x2,
+        StackBase::FP | StackBase::Zone => unreachable!("RISC-V stack slots are SP-relative"),
+    };
+    ru as RegUnit
+}
+
 /// R-type instructions.
 ///
 ///   31     24  19  14     11 6
@@ -180,3 +192,295 @@ fn put_uj<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rd: RegUnit, sink: &mut CS
 
     sink.put4(i);
 }
+
+/// S-type store instructions.
+///
+///   31  24  19  14     11  6
+///   imm rs2 rs1 funct3 imm opcode
+///    25  20  15     12   7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5)`
+fn put_s<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rs1: RegUnit, rs2: RegUnit, sink: &mut CS) {
+    let bits = u32::from(bits);
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let rs1 = u32::from(rs1) & 0x1f;
+    let rs2 = u32::from(rs2) & 0x1f;
+
+    debug_assert!(is_signed_int(imm, 12, 0), "S out of range {:#x}", imm);
+    let imm = imm as u32;
+
+    // 0-6: opcode
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= (imm & 0x1f) << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= ((imm >> 5) & 0x7f) << 25;
+
+    sink.put4(i);
+}
+
+/// FENCE instructions.
+///
+///   31 27   23   19  14     11 6
+///   fm pred succ rs1 funct3 rd opcode
+///   28   24   20  15     12  7      0
+///
+/// The `pred` and `succ` sets are `iorw` bit masks. The other fields are zero.
+fn put_fence<CS: CodeSink + ?Sized>(pred: u32, succ: u32, sink: &mut CS) {
+    // 0-6: opcode
+    let mut i = 0x0f;
+    i |= (succ & 0xf) << 20;
+    i |= (pred & 0xf) << 24;
+
+    sink.put4(i);
+}
+
+/// Compare-and-swap LR/SC loop.
+///
+///   retry:
+///     lr    rd, (rs1)
+///     bne   rd, e, done
+///     sc    rd, x, (rs1)
+///     bnez  rd, retry
+///     mv    rd, e
+///   done:
+///
+/// Encoding bits are those of the LR instruction. The SC instruction only differs in funct5.
+fn put_lr_sc_cas<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rs1: RegUnit,
+    e: RegUnit,
+    x: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    // BRANCH with funct3 = BNE.
+    const BNE: u16 = 0b11000 | (0b001 << 5);
+    // OP-IMM with funct3 = ADDI.
+    const ADDI: u16 = 0b00100;
+
+    put_r(bits, rs1, 0, rd, sink);
+    put_sb(BNE, 16, rd, e, sink);
+    put_r(bits | (1 << 10), rs1, x, rd, sink);
+    put_sb(BNE, -12, rd, 0, sink);
+    put_i(ADDI, e, 0, rd, sink);
+}
+
+/// R-type OP-FP floating point instructions.
+///
+///   31     24  19  14     11 6
+///   funct7 rs2 rs1 funct3 rd opcode
+///       25  20  15     12  7      0
+///
+/// The opcode is always OP-FP. Unary instructions use the rs2 field to select a variant, and it is
+/// provided in the encoding bits.
+///
+/// Encoding bits: `funct3 | (funct7 << 3) | (rs2 << 10)`.
+fn put_rfp<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rs1: RegUnit,
+    rs2: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let bits = u32::from(bits);
+    let funct3 = bits & 0x7;
+    let funct7 = (bits >> 3) & 0x7f;
+    let rs1 = u32::from(rs1) & 0x1f;
+    let rs2 = (u32::from(rs2) | (bits >> 10)) & 0x1f;
+    let rd = u32::from(rd) & 0x1f;
+
+    // 0-6: opcode
+    let mut i = 0b101_0011;
+    i |= rd << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= funct7 << 25;
+
+    sink.put4(i);
+}
+
+/// R4-type fused multiply-add instructions.
+///
+///   31  26  24  19  14     11 6
+///   rs3 fmt rs2 rs1 funct3 rd opcode
+///    27  25  20  15     12  7      0
+///
+/// Encoding bits: `opcode[6:2] | (funct3 << 5) | (fmt << 8)`.
+fn put_r4<CS: CodeSink + ?Sized>(
+    bits: u16,
+    rs1: RegUnit,
+    rs2: RegUnit,
+    rs3: RegUnit,
+    rd: RegUnit,
+    sink: &mut CS,
+) {
+    let bits = u32::from(bits);
+    let opcode5 = bits & 0x1f;
+    let funct3 = (bits >> 5) & 0x7;
+    let fmt = (bits >> 8) & 0x3;
+    let rs1 = u32::from(rs1) & 0x1f;
+    let rs2 = u32::from(rs2) & 0x1f;
+    let rs3 = u32::from(rs3) & 0x1f;
+    let rd = u32::from(rd) & 0x1f;
+
+    // 0-6: opcode
+    let mut i = 0x3;
+    i |= opcode5 << 2;
+    i |= rd << 7;
+    i |= funct3 << 12;
+    i |= rs1 << 15;
+    i |= rs2 << 20;
+    i |= fmt << 25;
+    i |= rs3 << 27;
+
+    sink.put4(i);
+}
+
+/// CR-type compressed register instructions.
+///
+///   15     11     6   1
+///   funct4 rd/rs1 rs2 op
+///       12      7   2  0
+///
+/// Encoding bits: the complete instruction with `rd` and `rs2` cleared.
+fn put_cr<CS: CodeSink + ?Sized>(bits: u16, rd: RegUnit, rs2: RegUnit, sink: &mut CS) {
+    let rd = rd & 0x1f;
+    let rs2 = rs2 & 0x1f;
+
+    let mut i = bits;
+    i |= rd << 7;
+    i |= rs2 << 2;
+
+    sink.put2(i);
+}
+
+/// CI-type compressed instructions with a 6-bit immediate.
+///
+///   15     12  11     6        1
+///   funct3 imm rd/rs1 imm[4:0] op
+///       13  12      7        2  0
+///
+/// Encoding bits: the complete instruction with `rd` and `imm` cleared.
+fn put_ci<CS: CodeSink + ?Sized>(bits: u16, rd: RegUnit, imm: i64, sink: &mut CS) {
+    let rd = rd & 0x1f;
+    let imm = imm as u16;
+
+    let mut i = bits;
+    i |= ((imm >> 5) & 0x1) << 12;
+    i |= rd << 7;
+    i |= (imm & 0x1f) << 2;
+
+    sink.put2(i);
+}
+
+/// Get the 3-bit register field used by the compressed formats for `x8`-`x15`.
+fn creg(reg: RegUnit) -> u16 {
+    debug_assert!(
+        reg >= 8 && reg < 16,
+        "x{} is not a compressed register",
+        reg
+    );
+    (reg - 8) & 0x7
+}
+
+/// CA-type compressed arithmetic instructions.
+///
+///   15     9        6      4    1
+///   funct6 rd'/rs1' funct2 rs2' op
+///       10        7      5    2  0
+///
+/// Encoding bits: the complete instruction with `rd'` and `rs2'` cleared.
+fn put_ca<CS: CodeSink + ?Sized>(bits: u16, rd: RegUnit, rs2: RegUnit, sink: &mut CS) {
+    let mut i = bits;
+    i |= creg(rd) << 7;
+    i |= creg(rs2) << 2;
+
+    sink.put2(i);
+}
+
+/// CB-type compressed instructions with a 6-bit immediate.
+///
+///   15     12  11     9        6        1
+///   funct3 imm funct2 rd'/rs1' imm[4:0] op
+///       13  12     10        7        2  0
+///
+/// Encoding bits: the complete instruction with `rd'` and `imm` cleared.
+fn put_cb_imm<CS: CodeSink + ?Sized>(bits: u16, rd: RegUnit, imm: i64, sink: &mut CS) {
+    let imm = imm as u16;
+
+    let mut i = bits;
+    i |= ((imm >> 5) & 0x1) << 12;
+    i |= creg(rd) << 7;
+    i |= (imm & 0x1f) << 2;
+
+    sink.put2(i);
+}
+
+/// CB-type compressed branch instructions.
+///
+///   15     12  9    6   1
+///   funct3 off rs1' off op
+///       13  10    7   2  0
+///
+/// Encoding bits: the complete instruction with `rs1'` and the offset cleared.
+fn put_cb_branch<CS: CodeSink + ?Sized>(bits: u16, imm: i64, rs1: RegUnit, sink: &mut CS) {
+    debug_assert!(is_signed_int(imm, 9, 1), "CB out of range {:#x}", imm);
+    let imm = imm as u16;
+
+    let mut i = bits;
+    i |= creg(rs1) << 7;
+
+    // The displacement is completely hashed up.
+    i |= ((imm >> 5) & 0x1) << 2;
+    i |= ((imm >> 1) & 0x3) << 3;
+    i |= ((imm >> 6) & 0x3) << 5;
+    i |= ((imm >> 3) & 0x3) << 10;
+    i |= ((imm >> 8) & 0x1) << 12;
+
+    sink.put2(i);
+}
+
+/// CJ-type compressed jump instructions.
+///
+///   15     12  1
+///   funct3 off op
+///       13   2  0
+///
+/// Encoding bits: the complete instruction with the offset cleared.
+fn put_cj<CS: CodeSink + ?Sized>(bits: u16, imm: i64, sink: &mut CS) {
+    debug_assert!(is_signed_int(imm, 12, 1), "CJ out of range {:#x}", imm);
+    let imm = imm as u16;
+
+    let mut i = bits;
+
+    // The displacement is completely hashed up.
+    i |= ((imm >> 5) & 0x1) << 2;
+    i |= ((imm >> 1) & 0x7) << 3;
+    i |= ((imm >> 7) & 0x1) << 6;
+    i |= ((imm >> 6) & 0x1) << 7;
+    i |= ((imm >> 10) & 0x1) << 8;
+    i |= ((imm >> 8) & 0x3) << 9;
+    i |= ((imm >> 4) & 0x1) << 11;
+    i |= ((imm >> 11) & 0x1) << 12;
+
+    sink.put2(i);
+}
+
+/// Emit `size` bytes of `addi x0, x0, 0` instructions.
+///
+/// When compressed instructions are in use, the padding may be an odd number of half-words, and
+/// a single `c.nop` is emitted first.
+pub fn emit_padding<CS: CodeSink + ?Sized>(size: CodeOffset, sink: &mut CS) {
+    debug_assert_eq!(size % 2, 0);
+    if size % 4 != 0 {
+        sink.put2(0x0001);
+    }
+    for _ in 0..size / 4 {
+        sink.put4(0x0000_0013);
+    }
+}
@@ -6,7 +6,7 @@ include!(concat!(env!("OUT_DIR"), "/registers-riscv.rs"));
 
 #[cfg(test)]
 mod tests {
-    use super::{FPR, GPR, INFO};
+    use super::{FPR, GPR, GPRC, INFO};
     use crate::isa::RegUnit;
     use std::string::{String, ToString};
 
@@ -46,5 +46,11 @@ mod tests {
         assert!(!GPR.contains(FPR.unit(31)));
         assert!(FPR.contains(FPR.unit(0)));
         assert!(FPR.contains(FPR.unit(31)));
+
+        assert!(GPR.has_subclass(GPRC));
+        assert!(GPRC.contains(GPR.unit(8)));
+        assert!(GPRC.contains(GPR.unit(15)));
+        assert!(!GPRC.contains(GPR.unit(7)));
+        assert!(!GPRC.contains(GPR.unit(16)));
     }
 }
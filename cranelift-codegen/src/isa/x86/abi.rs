@@ -15,6 +15,7 @@ use crate::regalloc::RegisterSet;
 use crate::result::CodegenResult;
 use crate::stack_layout::layout_stack;
 use core::i32;
+use std::vec::Vec;
 use target_lexicon::{PointerWidth, Triple};
 
 /// Argument registers for x86-64
@@ -107,6 +108,14 @@ impl ArgAssigner for Args {
             }
         }
 
+        // Structs passed by value are copied to the stack.
+        if let ArgumentPurpose::StructArgument(_) = arg.purpose {
+            let loc = ArgumentLoc::Stack(self.offset as i32);
+            self.offset += arg.stack_size();
+            debug_assert!(self.offset <= i32::MAX as u32);
+            return loc.into();
+        }
+
         // Try to use a GPR.
         if !ty.is_float() && self.gpr_used < self.gpr.len() {
             let reg = self.gpr[self.gpr_used] as RegUnit;
@@ -139,7 +148,7 @@ impl ArgAssigner for Args {
 }
 
 /// Legalize `sig`.
-pub fn legalize_signature(sig: &mut ir::Signature, triple: &Triple, _current: bool) {
+pub fn legalize_signature(sig: &mut ir::Signature, triple: &Triple, current: bool) {
     let bits;
     let mut args;
 
@@ -161,6 +170,34 @@ pub fn legalize_signature(sig: &mut ir::Signature, triple: &Triple, _current: bo
 
     legalize_args(&mut sig.params, &mut args);
 
+    // Calls to variadic functions pass an upper bound on the number of vector registers used in
+    // `%al`. The value is provided by the caller when the call is legalized.
+    if sig.variadic
+        && !current
+        && bits == 64
+        && sig.call_conv == CallConv::SystemV
+        && sig
+            .special_param_index(ArgumentPurpose::VectorCount)
+            .is_none()
+    {
+        sig.params.push(AbiParam::special_reg(
+            ir::types::I32,
+            ArgumentPurpose::VectorCount,
+            RU::rax as RegUnit,
+        ));
+    }
+
+    // The native conventions return the address of a struct return buffer in the first return
+    // register, so callers can use it without keeping their own copy.
+    if sig.call_conv != CallConv::Baldrdash && sig.returns.is_empty() {
+        if let Some(sret) = sig.special_param_index(ArgumentPurpose::StructReturn) {
+            sig.returns.push(AbiParam::special(
+                sig.params[sret].value_type,
+                ArgumentPurpose::StructReturn,
+            ));
+        }
+    }
+
     let (regs, fpr_limit) = if sig.call_conv == CallConv::WindowsFastcall {
         // windows-x64 calling convention only uses XMM0 or RAX for return values
         (&RET_GPRS_WIN_FASTCALL_X64[..], 1)
@@ -174,7 +211,7 @@ pub fn legalize_signature(sig: &mut ir::Signature, triple: &Triple, _current: bo
 
 /// Get register class for a type appearing in a legalized signature.
 pub fn regclass_for_abi_type(ty: ir::Type) -> RegClass {
-    if ty.is_int() || ty.is_bool() {
+    if ty.is_int() || ty.is_bool() || ty.is_ref() {
         GPR
     } else {
         FPR
@@ -226,12 +263,41 @@ fn callee_saved_gprs(isa: &TargetIsa, call_conv: CallConv) -> &'static [RU] {
     }
 }
 
+/// Get the set of callee-saved float registers.
+fn callee_saved_fprs(isa: &TargetIsa, call_conv: CallConv) -> &'static [RU] {
+    match isa.triple().pointer_width().unwrap() {
+        PointerWidth::U64 if call_conv == CallConv::WindowsFastcall => {
+            // "The registers RBX, RBP, RDI, RSI, RSP, R12, R13, R14, R15, and XMM6-15 are
+            //  considered nonvolatile"
+            &[
+                RU::xmm6,
+                RU::xmm7,
+                RU::xmm8,
+                RU::xmm9,
+                RU::xmm10,
+                RU::xmm11,
+                RU::xmm12,
+                RU::xmm13,
+                RU::xmm14,
+                RU::xmm15,
+            ]
+        }
+        _ => &[],
+    }
+}
+
 /// Get the set of callee-saved registers that are used.
-fn callee_saved_gprs_used(isa: &TargetIsa, func: &ir::Function) -> RegisterSet {
+///
+/// The returned set contains both integer and float registers. Use `iter(GPR)` and `iter(FPR)`
+/// to visit them.
+fn callee_saved_regs_used(isa: &TargetIsa, func: &ir::Function) -> RegisterSet {
     let mut all_callee_saved = RegisterSet::empty();
     for reg in callee_saved_gprs(isa, func.signature.call_conv) {
         all_callee_saved.free(GPR, *reg as RegUnit);
     }
+    for reg in callee_saved_fprs(isa, func.signature.call_conv) {
+        all_callee_saved.free(FPR, *reg as RegUnit);
+    }
 
     let mut used = RegisterSet::empty();
     for value_loc in func.locations.values() {
@@ -260,6 +326,15 @@ fn callee_saved_gprs_used(isa: &TargetIsa, func: &ir::Function) -> RegisterSet {
                         used.free(GPR, dst);
                     }
                 }
+                ir::instructions::InstructionData::RegSwap { src, dst, .. } => {
+                    for &reg in &[src, dst] {
+                        if !used.is_avail(GPR, reg) {
+                            used.free(GPR, reg);
+                        }
+                    }
+                }
+                // Instructions like `stack_switch` clobber the callee-saved registers too.
+                ref data if data.opcode().clobbers_all_regs() => return all_callee_saved,
                 _ => (),
             }
         }
@@ -278,6 +353,7 @@ pub fn prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> CodegenRes
         CallConv::WindowsFastcall => fastcall_prologue_epilogue(func, isa),
         CallConv::Baldrdash => baldrdash_prologue_epilogue(func, isa),
         CallConv::Probestack => unimplemented!("probestack calling convention"),
+        CallConv::AppleAarch64 => panic!("apple_aarch64 is an AArch64 calling convention"),
     }
 }
 
@@ -314,7 +390,7 @@ fn fastcall_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> Codeg
     let word_size = isa.pointer_bytes() as usize;
     let reg_type = isa.pointer_type();
 
-    let csrs = callee_saved_gprs_used(isa, func);
+    let csrs = callee_saved_regs_used(isa, func);
 
     // [1] "Space is allocated on the call stack as a shadow store for callees to save"
     // This shadow store contains the parameters which are passed through registers (ARG_GPRS)
@@ -343,10 +419,30 @@ fn fastcall_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> Codeg
         offset: Some(-(SHADOW_STORE_SIZE + csr_stack_size)),
     });
 
+    // The nonvolatile XMM registers can't be pushed, so they are saved with full 128-bit stores
+    // into spill slots in the local area of the frame.
+    let fpr_type = ir::types::F64X2;
+    let fpr_slots: Vec<(RegUnit, ir::StackSlot)> = csrs
+        .iter(FPR)
+        .map(|reg| (reg, func.stack_slots.make_spill_slot(fpr_type)))
+        .collect();
+
+    // [1] "The caller is responsible for allocating space for parameters to the callee, and must
+    // always allocate sufficient space to store four register parameters, even if the callee
+    // doesn't take that many parameters."
+    reserve_shadow_store(func, SHADOW_STORE_SIZE as StackSize);
+
     let total_stack_size = layout_stack(&mut func.stack_slots, stack_align)? as i32;
     let local_stack_size = i64::from(total_stack_size - csr_stack_size);
 
-    // Add CSRs to function signature
+    // Add CSRs to function signature. The XMM registers come first so they are ahead of the
+    // pushed registers in the entry EBB parameters and the return values.
+    for &(reg, _) in &fpr_slots {
+        let csr_arg = ir::AbiParam::special_reg(fpr_type, ir::ArgumentPurpose::CalleeSaved, reg);
+        func.signature.params.push(csr_arg);
+        func.signature.returns.push(csr_arg);
+    }
+
     let fp_arg = ir::AbiParam::special_reg(
         reg_type,
         ir::ArgumentPurpose::FramePointer,
@@ -364,15 +460,65 @@ fn fastcall_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> Codeg
     // Set up the cursor and insert the prologue
     let entry_ebb = func.layout.entry_block().expect("missing entry block");
     let mut pos = EncCursor::new(func, isa).at_first_insertion_point(entry_ebb);
+    let fpr_args: Vec<ir::Value> = fpr_slots
+        .iter()
+        .map(|&(reg, _)| {
+            let csr_arg = pos.func.dfg.append_ebb_param(entry_ebb, fpr_type);
+            pos.func.locations[csr_arg] = ir::ValueLoc::Reg(reg);
+            csr_arg
+        })
+        .collect();
     insert_common_prologue(&mut pos, local_stack_size, reg_type, &csrs, isa);
 
-    // Reset the cursor and insert the epilogue
+    // Save the XMM registers once the stack frame has been allocated.
+    let fpr_saved: Vec<ir::Value> = fpr_args
+        .iter()
+        .zip(&fpr_slots)
+        .map(|(&csr_arg, &(_, ss))| {
+            let saved = pos.ins().spill(csr_arg);
+            pos.func.locations[saved] = ir::ValueLoc::Stack(ss);
+            saved
+        })
+        .collect();
+
+    // Remember where the prologue ends so the unwind information can describe it.
+    pos.func.prologue_end = pos.prev_inst();
+
+    // Reset the cursor and insert the epilogues, restoring the XMM registers before the stack
+    // frame is deallocated.
     let mut pos = pos.at_position(CursorPosition::Nowhere);
-    insert_common_epilogues(&mut pos, local_stack_size, reg_type, &csrs);
+    while let Some(ebb) = pos.next_ebb() {
+        pos.goto_last_inst(ebb);
+        if let Some(inst) = pos.current_inst() {
+            if pos.func.dfg[inst].opcode().is_return() {
+                for (&saved, &(reg, _)) in fpr_saved.iter().zip(&fpr_slots) {
+                    let csr_ret = pos.ins().fill(saved);
+                    pos.func.locations[csr_ret] = ir::ValueLoc::Reg(reg);
+                    pos.func.dfg.append_inst_arg(inst, csr_ret);
+                }
+                insert_common_epilogue(inst, local_stack_size, &mut pos, reg_type, &csrs);
+            }
+        }
+    }
 
     Ok(())
 }
 
+/// Reserve the shadow store of the Win64 calling convention below the outgoing arguments of
+/// every call to a `windows_fastcall` function made by `func`.
+fn reserve_shadow_store(func: &mut ir::Function, size: StackSize) {
+    let calls_fastcall = func.layout.ebbs().any(|ebb| {
+        func.layout.ebb_insts(ebb).any(|inst| {
+            func.dfg.call_signature(inst).map_or(false, |sig| {
+                func.dfg.signatures[sig].call_conv == CallConv::WindowsFastcall
+            })
+        })
+    });
+    if calls_fastcall {
+        func.stack_slots.get_outgoing_arg(size, 0);
+    }
+}
+
 /// Insert a System V-compatible prologue and epilogue.
 fn system_v_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> CodegenResult<()> {
     // The original 32-bit x86 ELF ABI had a 4-byte aligned stack pointer, but
@@ -382,7 +528,7 @@ fn system_v_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> Codeg
     let word_size = pointer_width.bytes() as usize;
     let reg_type = ir::Type::int(u16::from(pointer_width.bits())).unwrap();
 
-    let csrs = callee_saved_gprs_used(isa, func);
+    let csrs = callee_saved_regs_used(isa, func);
 
     // The reserved stack area is composed of:
     //   return address + frame pointer + all callee-saved registers
@@ -398,6 +544,12 @@ fn system_v_prologue_epilogue(func: &mut ir::Function, isa: &TargetIsa) -> Codeg
         offset: Some(-csr_stack_size),
     });
 
+    // A System V function calling a `windows_fastcall` function must still provide the shadow
+    // store that the callee expects.
+    if pointer_width == PointerWidth::U64 {
+        reserve_shadow_store(func, 32);
+    }
+
     let total_stack_size = layout_stack(&mut func.stack_slots, stack_align)? as i32;
     let local_stack_size = i64::from(total_stack_size - csr_stack_size);
 
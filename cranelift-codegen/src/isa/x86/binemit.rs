@@ -2,11 +2,16 @@
 
 use super::enc_tables::{needs_offset, needs_sib_byte};
 use super::registers::RU;
-use crate::binemit::{bad_encoding, CodeSink, Reloc};
+use crate::binemit::{
+    bad_encoding, deopt_locations, stackmap_offsets, CodeOffset, CodeSink, Reloc,
+};
 use crate::ir::condcodes::{CondCode, FloatCC, IntCC};
-use crate::ir::{Ebb, Function, Inst, InstructionData, JumpTable, Opcode, TrapCode};
+use crate::ir::{
+    Ebb, ExternalName, Function, Inst, InstructionData, JumpTable, LibCall, Opcode, TrapCode,
+};
 use crate::isa::{RegUnit, StackBase, StackBaseMask, StackRef};
 use crate::regalloc::RegDiversions;
+use crate::settings::NopStyle;
 
 include!(concat!(env!("OUT_DIR"), "/binemit-x86.rs"));
 
@@ -99,6 +104,26 @@ fn put_rexop2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
     sink.put1(bits as u8);
 }
 
+// Emit three-byte opcode: 0F 3[8A] XX
+fn put_op3<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    debug_assert_eq!(bits & 0x8b00, 0x0800, "Invalid encoding bits for Op3*");
+    debug_assert_eq!(rex, BASE_REX, "Invalid registers for REX-less Op3 encoding");
+    let mm = (bits >> 10) & 3;
+    sink.put1(0x0f);
+    sink.put1(OP3_BYTE2[(mm - 2) as usize]);
+    sink.put1(bits as u8);
+}
+
+// Emit three-byte opcode: 0F 3[8A] XX with REX prefix.
+fn put_rexop3<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
+    debug_assert_eq!(bits & 0x0b00, 0x0800, "Invalid encoding bits for RexOp3*");
+    rex_prefix(bits, rex, sink);
+    let mm = (bits >> 10) & 3;
+    sink.put1(0x0f);
+    sink.put1(OP3_BYTE2[(mm - 2) as usize]);
+    sink.put1(bits as u8);
+}
+
 // Emit single-byte opcode with mandatory prefix.
 fn put_mp1<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
     debug_assert_eq!(bits & 0x8c00, 0, "Invalid encoding bits for Mp1*");
@@ -161,6 +186,45 @@ fn put_rexmp3<CS: CodeSink + ?Sized>(bits: u16, rex: u8, sink: &mut CS) {
     sink.put1(bits as u8);
 }
 
+// Emit a three-byte VEX prefix (C4 RXBmmmmm WvvvvLpp).
+//
+// The R, X, and B bits are taken from a REX prefix computed by the functions above, and the
+// `vvvv` register operand is encoded inverted. The opcode map `mmmmm` has the same values as the
+// `mm` encoding bits. The vector length L is always 0 since only scalar and 128-bit vector
+// operations use this form so far.
+fn vex_prefix<CS: CodeSink + ?Sized>(bits: u16, rex: u8, vvvv: RegUnit, sink: &mut CS) {
+    debug_assert_eq!(rex & 0xf8, BASE_REX);
+    let pp = (bits >> 8) & 3;
+    let mm = (bits >> 10) & 3;
+    let w = ((bits >> 15) & 1) as u8;
+    sink.put1(0xc4);
+    // The R, X, and B bits are stored inverted.
+    sink.put1((!(rex << 5) & 0xe0) | mm as u8);
+    sink.put1((w << 7) | ((!(vvvv as u8) & 0xf) << 3) | pp as u8);
+}
+
+// Emit a VEX-encoded two-byte opcode (0F XX) with no mandatory prefix.
+fn put_vexop2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, vvvv: RegUnit, sink: &mut CS) {
+    debug_assert_eq!(bits & 0x0f00, 0x0400, "Invalid encoding bits for VexOp2*");
+    vex_prefix(bits, rex, vvvv, sink);
+    sink.put1(bits as u8);
+}
+
+// Emit a VEX-encoded two-byte opcode (0F XX) with mandatory prefix.
+fn put_vexmp2<CS: CodeSink + ?Sized>(bits: u16, rex: u8, vvvv: RegUnit, sink: &mut CS) {
+    debug_assert_eq!(bits & 0x0c00, 0x0400, "Invalid encoding bits for VexMp2*");
+    debug_assert_ne!(bits & 0x0300, 0, "Invalid encoding bits for VexMp2*");
+    vex_prefix(bits, rex, vvvv, sink);
+    sink.put1(bits as u8);
+}
+
+// Emit a VEX-encoded three-byte opcode (0F 3[8A] XX) with mandatory prefix.
+fn put_vexmp3<CS: CodeSink + ?Sized>(bits: u16, rex: u8, vvvv: RegUnit, sink: &mut CS) {
+    debug_assert_eq!(bits & 0x0800, 0x0800, "Invalid encoding bits for VexMp3*");
+    vex_prefix(bits, rex, vvvv, sink);
+    sink.put1(bits as u8);
+}
+
 /// Emit a ModR/M byte for reg-reg operands.
 fn modrm_rr<CS: CodeSink + ?Sized>(rm: RegUnit, reg: RegUnit, sink: &mut CS) {
     let reg = reg as u8 & 7;
@@ -340,3 +404,32 @@ fn jt_disp4<CS: CodeSink + ?Sized>(jt: JumpTable, func: &Function, sink: &mut CS
     let delta = func.jt_offsets[jt].wrapping_sub(sink.offset() + 4);
     sink.put4(delta);
 }
+
+/// The recommended multi-byte nop instructions, indexed by their size minus one.
+const NOPS: [&[u8]; 9] = [
+    &[0x90],
+    &[0x66, 0x90],
+    &[0x0f, 0x1f, 0x00],
+    &[0x0f, 0x1f, 0x40, 0x00],
+    &[0x0f, 0x1f, 0x44, 0x00, 0x00],
+    &[0x66, 0x0f, 0x1f, 0x44, 0x00, 0x00],
+    &[0x0f, 0x1f, 0x80, 0x00, 0x00, 0x00, 0x00],
+    &[0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+    &[0x66, 0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+];
+
+/// Emit `size` bytes of nops in the given style.
+pub fn emit_padding<CS: CodeSink + ?Sized>(size: CodeOffset, style: NopStyle, sink: &mut CS) {
+    let max = match style {
+        NopStyle::MultiByte => NOPS.len(),
+        NopStyle::SingleByte => 1,
+    };
+    let mut size = size as usize;
+    while size > 0 {
+        let len = size.min(max);
+        for &byte in NOPS[len - 1] {
+            sink.put1(byte);
+        }
+        size -= len;
+    }
+}
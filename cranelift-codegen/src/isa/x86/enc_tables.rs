@@ -776,3 +776,62 @@ fn expand_fcvt_to_uint_sat(
     cfg.recompute_ebb(pos.func, large);
     cfg.recompute_ebb(pos.func, done);
 }
+
+/// Expand the `tls_value` instruction according to the `tls_model` setting.
+fn expand_tls_value(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    isa: &isa::TargetIsa,
+) {
+    use crate::settings::TlsModel;
+
+    let gv = match func.dfg[inst] {
+        ir::InstructionData::UnaryGlobalValue {
+            opcode: ir::Opcode::TlsValue,
+            global_value,
+        } => global_value,
+        _ => panic!("Expected tls_value: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.ctrl_typevar(inst);
+    assert_eq!(ty, ir::types::I64, "ELF TLS is only supported on x86-64");
+
+    match isa.flags().tls_model() {
+        TlsModel::None => panic!("tls_value requires the tls_model setting"),
+        TlsModel::ElfGd => {
+            func.dfg.replace(inst).x86_elf_tls_get_addr(ty, gv);
+        }
+        TlsModel::ElfIe => {
+            func.dfg.replace(inst).x86_elf_tls_initial_exec(ty, gv);
+        }
+    }
+}
+
+/// Expand the `swizzle` instruction using the `x86_pshufb` instruction.
+///
+/// The `pshufb` instruction only produces zero for indices with the most significant bit set, so
+/// the indices are adjusted with a saturating addition that moves all the out of range indices
+/// into that range, while leaving the low four bits of the valid indices unchanged.
+fn expand_swizzle(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &isa::TargetIsa,
+) {
+    let (x, y) = match func.dfg[inst] {
+        ir::InstructionData::Binary {
+            opcode: ir::Opcode::Swizzle,
+            args,
+        } => (args[0], args[1]),
+        _ => panic!("Expected swizzle: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.ctrl_typevar(inst);
+    assert_eq!(ty, ir::types::I8X16, "Only i8x16 swizzles are supported");
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let bias = pos.ins().iconst(ir::types::I8, 0x70);
+    let bias = pos.ins().splat(ty, bias);
+    let indices = pos.ins().uadd_sat(y, bias);
+    pos.func.dfg.replace(inst).x86_pshufb(x, indices);
+}
@@ -3,22 +3,25 @@
 mod abi;
 mod binemit;
 mod enc_tables;
+mod peephole;
 mod registers;
 pub mod settings;
+mod unwind;
 
 use super::super::settings as shared_settings;
 #[cfg(feature = "testing_hooks")]
 use crate::binemit::CodeSink;
-use crate::binemit::{emit_function, MemoryCodeSink};
+use crate::binemit::{emit_function, CodeOffset, MemoryCodeSink};
 use crate::ir;
 use crate::isa::enc_tables::{self as shared_enc_tables, lookup_enclist, Encodings};
 use crate::isa::Builder as IsaBuilder;
-use crate::isa::{EncInfo, RegClass, RegInfo, TargetIsa};
+use crate::isa::{EncInfo, PeepholeRule, RegClass, RegInfo, TargetIsa};
 use crate::regalloc;
 use crate::result::CodegenResult;
 use crate::timing;
 use core::fmt;
 use std::boxed::Box;
+use std::vec::Vec;
 use target_lexicon::{PointerWidth, Triple};
 
 #[allow(dead_code)]
@@ -77,6 +80,10 @@ impl TargetIsa for Isa {
         true
     }
 
+    fn peephole_rules(&self) -> &'static [PeepholeRule] {
+        &peephole::RULES
+    }
+
     fn register_info(&self) -> RegInfo {
         registers::INFO.clone()
     }
@@ -128,14 +135,31 @@ impl TargetIsa for Isa {
         binemit::emit_inst(func, inst, divert, sink)
     }
 
+    #[cfg(feature = "testing_hooks")]
+    fn emit_padding(&self, size: CodeOffset, sink: &mut CodeSink) {
+        binemit::emit_padding(size, self.shared_flags.nop_style(), sink)
+    }
+
     fn emit_function_to_memory(&self, func: &ir::Function, sink: &mut MemoryCodeSink) {
-        emit_function(func, binemit::emit_inst, sink)
+        let style = self.shared_flags.nop_style();
+        emit_function(
+            func,
+            binemit::emit_inst,
+            |size, sink| binemit::emit_padding(size, style, sink),
+            sink,
+        )
     }
 
     fn prologue_epilogue(&self, func: &mut ir::Function) -> CodegenResult<()> {
         let _tt = timing::prologue_epilogue();
         abi::prologue_epilogue(func, self)
     }
+
+    fn emit_unwind_info(&self, func: &ir::Function, mem: &mut Vec<u8>) {
+        if let Some(info) = unwind::UnwindInfo::try_from_func(func, self) {
+            info.emit(mem);
+        }
+    }
 }
 
 impl fmt::Display for Isa {
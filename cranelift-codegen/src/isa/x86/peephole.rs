@@ -0,0 +1,191 @@
+//! Peephole rules for x86.
+
+use crate::cursor::EncCursor;
+use crate::ir::dfg::ValueDef;
+use crate::ir::{types, Inst, InstructionData, Opcode};
+use crate::isa::PeepholeRule;
+use crate::postopt::{fold_complex_address, fuse_compare_branch};
+
+/// The rules applied by the post-opt pass, in order.
+pub static RULES: [PeepholeRule; 5] = [
+    PeepholeRule {
+        name: "compare+branch fusion",
+        apply: fuse_compare_branch,
+    },
+    // The MOVBE rules must run before complex address folding turns the loads and stores into
+    // forms that have no MOVBE equivalent.
+    PeepholeRule {
+        name: "load+bswap fusion",
+        apply: fuse_load_bswap,
+    },
+    PeepholeRule {
+        name: "bswap+store fusion",
+        apply: fuse_bswap_store,
+    },
+    PeepholeRule {
+        name: "complex address folding",
+        apply: fold_complex_address,
+    },
+    PeepholeRule {
+        name: "shift count mask removal",
+        apply: remove_shift_count_mask,
+    },
+];
+
+/// Remove a `band_imm` masking the count of a shift or rotate.
+///
+/// The shift instructions only use the low 5 bits of the count, or 6 bits for 64-bit operands,
+/// so a mask that keeps all of those bits is redundant. WebAssembly defines shifts with a masked
+/// count, and producers often emit the mask explicitly.
+fn remove_shift_count_mask(pos: &mut EncCursor, inst: Inst) -> bool {
+    let count = match pos.func.dfg[inst] {
+        InstructionData::Binary {
+            opcode: Opcode::Ishl,
+            args,
+        }
+        | InstructionData::Binary {
+            opcode: Opcode::Ushr,
+            args,
+        }
+        | InstructionData::Binary {
+            opcode: Opcode::Sshr,
+            args,
+        }
+        | InstructionData::Binary {
+            opcode: Opcode::Rotl,
+            args,
+        }
+        | InstructionData::Binary {
+            opcode: Opcode::Rotr,
+            args,
+        } => args[1],
+        _ => return false,
+    };
+    let mask = match pos.func.dfg.ctrl_typevar(inst) {
+        types::I32 => 31,
+        types::I64 => 63,
+        _ => return false,
+    };
+
+    if let ValueDef::Result(mask_inst, _) = pos.func.dfg.value_def(count) {
+        if let InstructionData::BinaryImm {
+            opcode: Opcode::BandImm,
+            arg,
+            imm,
+        } = pos.func.dfg[mask_inst]
+        {
+            let imm: i64 = imm.into();
+            if imm & mask == mask {
+                // The unmasked count has the same type, so the encoding doesn't change. The
+                // `band_imm` is left for DCE if it has no other uses.
+                pos.func.dfg.inst_args_mut(inst)[1] = arg;
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Fuse a `load` with a following `bswap` of the loaded value into a MOVBE load.
+///
+/// The `bswap` must come right after the `load`. The loaded value may have other uses, so the
+/// `bswap` is turned around to recover it from the swapped value, and it is left for DCE if there
+/// are none. The rewrite only happens when the MOVBE encodings are enabled.
+fn fuse_load_bswap(pos: &mut EncCursor, inst: Inst) -> bool {
+    let (arg, flags, offset) = match pos.func.dfg[inst] {
+        InstructionData::Load {
+            opcode: Opcode::Load,
+            arg,
+            flags,
+            offset,
+        } => (arg, flags, offset),
+        _ => return false,
+    };
+    let ty = pos.func.dfg.ctrl_typevar(inst);
+    if ty != types::I32 && ty != types::I64 {
+        return false;
+    }
+
+    let loaded = pos.func.dfg.first_result(inst);
+    let bswap_inst = match pos.func.layout.next_inst(inst) {
+        Some(next) => next,
+        None => return false,
+    };
+    match pos.func.dfg[bswap_inst] {
+        InstructionData::Unary {
+            opcode: Opcode::Bswap,
+            arg,
+        } if pos.func.dfg.resolve_aliases(arg) == loaded => {}
+        _ => return false,
+    }
+
+    let data = InstructionData::Load {
+        opcode: Opcode::X86MovbeLoad,
+        arg,
+        flags,
+        offset,
+    };
+    match pos.isa.encode(&pos.func, &data, ty) {
+        Ok(encoding) => {
+            pos.func.dfg[inst] = data;
+            pos.func.encodings[inst] = encoding;
+        }
+        Err(_) => return false,
+    }
+
+    // The MOVBE load defines the swapped value, and the `bswap` now swaps it back for any other
+    // uses of the loaded value. The `bswap` keeps its encoding since the type doesn't change.
+    let new_swapped = pos.func.dfg.replace_result(loaded, ty);
+    pos.func.dfg.inst_args_mut(bswap_inst)[0] = new_swapped;
+    let swapped = pos.func.dfg.first_result(bswap_inst);
+    let new_loaded = pos.func.dfg.replace_result(swapped, ty);
+    pos.func.dfg.change_to_alias(swapped, new_swapped);
+    pos.func.dfg.change_to_alias(loaded, new_loaded);
+    true
+}
+
+/// Fuse a `store` of a `bswap` result into a MOVBE store.
+///
+/// The `bswap` is left for DCE if it has no other uses. The rewrite only happens when the MOVBE
+/// encodings are enabled.
+fn fuse_bswap_store(pos: &mut EncCursor, inst: Inst) -> bool {
+    let (args, flags, offset) = match pos.func.dfg[inst] {
+        InstructionData::Store {
+            opcode: Opcode::Store,
+            args,
+            flags,
+            offset,
+        } => (args, flags, offset),
+        _ => return false,
+    };
+    let ty = pos.func.dfg.ctrl_typevar(inst);
+    if ty != types::I32 && ty != types::I64 {
+        return false;
+    }
+
+    let unswapped = match pos.func.dfg.value_def(args[0]) {
+        ValueDef::Result(def_inst, _) => match pos.func.dfg[def_inst] {
+            InstructionData::Unary {
+                opcode: Opcode::Bswap,
+                arg,
+            } => arg,
+            _ => return false,
+        },
+        ValueDef::Param(_, _) => return false,
+    };
+
+    let data = InstructionData::Store {
+        opcode: Opcode::X86MovbeStore,
+        args: [unswapped, args[1]],
+        flags,
+        offset,
+    };
+    match pos.isa.encode(&pos.func, &data, ty) {
+        Ok(encoding) => {
+            pos.func.dfg[inst] = data;
+            pos.func.encodings[inst] = encoding;
+            true
+        }
+        Err(_) => false,
+    }
+}
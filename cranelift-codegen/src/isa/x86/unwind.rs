@@ -0,0 +1,436 @@
+//! Unwind information for the Windows x64 ABI.
+//!
+//! Functions using the `windows_fastcall` calling convention are described by an `UNWIND_INFO`
+//! structure, which the Windows unwinder uses to restore the caller's registers and stack pointer
+//! from any point in the function. See
+//! https://docs.microsoft.com/en-us/cpp/build/exception-handling-x64 for the format.
+
+use super::registers::RU;
+use crate::ir::{Function, InstructionData, Opcode, ValueLoc};
+use crate::isa::{CallConv, RegUnit, StackRef, TargetIsa};
+use std::vec::Vec;
+
+/// The largest stack allocation described by `UWOP_ALLOC_SMALL`.
+const SMALL_ALLOC_MAX_SIZE: u32 = 128;
+
+/// The largest stack allocation described by the 16-bit form of `UWOP_ALLOC_LARGE`.
+const LARGE_ALLOC_16BIT_MAX_SIZE: u32 = 0xffff * 8;
+
+/// The largest stack offset described by the scaled form `UWOP_SAVE_XMM128`.
+const SAVE_XMM128_MAX_OFFSET: u32 = 0xffff * 16;
+
+/// An unwind operation, describing one instruction of the prologue.
+///
+/// Only the operations needed to describe the prologues generated by `abi.rs` are represented.
+/// The `offset` of each operation is the code offset of the end of the prologue instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum UnwindCode {
+    /// A `push` of a nonvolatile integer register.
+    PushRegister { offset: u8, reg: RegUnit },
+    /// A decrement of the stack pointer by `size` bytes.
+    StackAlloc { offset: u8, size: u32 },
+    /// A 128-bit store of a nonvolatile XMM register at `stack_offset` from the stack pointer.
+    SaveXmm {
+        offset: u8,
+        reg: RegUnit,
+        stack_offset: u32,
+    },
+}
+
+/// Operation codes of the unwind codes.
+const UWOP_PUSH_NONVOL: u8 = 0;
+const UWOP_ALLOC_LARGE: u8 = 1;
+const UWOP_ALLOC_SMALL: u8 = 2;
+const UWOP_SAVE_XMM128: u8 = 8;
+const UWOP_SAVE_XMM128_FAR: u8 = 9;
+
+impl UnwindCode {
+    /// The number of 16-bit slots used by this unwind code.
+    fn node_count(self) -> usize {
+        match self {
+            UnwindCode::PushRegister { .. } => 1,
+            UnwindCode::StackAlloc { size, .. } => {
+                if size <= SMALL_ALLOC_MAX_SIZE {
+                    1
+                } else if size <= LARGE_ALLOC_16BIT_MAX_SIZE {
+                    2
+                } else {
+                    3
+                }
+            }
+            UnwindCode::SaveXmm { stack_offset, .. } => {
+                if stack_offset % 16 == 0 && stack_offset <= SAVE_XMM128_MAX_OFFSET {
+                    2
+                } else {
+                    3
+                }
+            }
+        }
+    }
+
+    fn emit(self, mem: &mut Vec<u8>) {
+        match self {
+            UnwindCode::PushRegister { offset, reg } => {
+                mem.push(offset);
+                mem.push((gpr_number(reg) << 4) | UWOP_PUSH_NONVOL);
+            }
+            UnwindCode::StackAlloc { offset, size } => {
+                // Stack allocations are a multiple of 8 bytes, and at least 8 bytes.
+                debug_assert!(size >= 8 && size % 8 == 0);
+                mem.push(offset);
+                if size <= SMALL_ALLOC_MAX_SIZE {
+                    mem.push(((((size - 8) / 8) as u8) << 4) | UWOP_ALLOC_SMALL);
+                } else if size <= LARGE_ALLOC_16BIT_MAX_SIZE {
+                    mem.push(UWOP_ALLOC_LARGE);
+                    put_u16(mem, (size / 8) as u16);
+                } else {
+                    mem.push((1 << 4) | UWOP_ALLOC_LARGE);
+                    put_u32(mem, size);
+                }
+            }
+            UnwindCode::SaveXmm {
+                offset,
+                reg,
+                stack_offset,
+            } => {
+                mem.push(offset);
+                if stack_offset % 16 == 0 && stack_offset <= SAVE_XMM128_MAX_OFFSET {
+                    mem.push((xmm_number(reg) << 4) | UWOP_SAVE_XMM128);
+                    put_u16(mem, (stack_offset / 16) as u16);
+                } else {
+                    mem.push((xmm_number(reg) << 4) | UWOP_SAVE_XMM128_FAR);
+                    put_u32(mem, stack_offset);
+                }
+            }
+        }
+    }
+}
+
+/// The Windows encoding of an integer register. It is the same as the register unit number.
+fn gpr_number(reg: RegUnit) -> u8 {
+    debug_assert!(reg <= RU::r15 as RegUnit);
+    reg as u8
+}
+
+/// The Windows encoding of an XMM register.
+fn xmm_number(reg: RegUnit) -> u8 {
+    debug_assert!(reg >= RU::xmm0 as RegUnit && reg <= RU::xmm15 as RegUnit);
+    (reg - RU::xmm0 as RegUnit) as u8
+}
+
+fn put_u16(mem: &mut Vec<u8>, x: u16) {
+    mem.push(x as u8);
+    mem.push((x >> 8) as u8);
+}
+
+fn put_u32(mem: &mut Vec<u8>, x: u32) {
+    put_u16(mem, x as u16);
+    put_u16(mem, (x >> 16) as u16);
+}
+
+/// Windows x64 unwind information for a function.
+///
+/// The frame pointer set up by the prologue is not described as a frame register. Cranelift
+/// never adjusts the stack pointer outside of the prologue and epilogues, so the stack pointer
+/// alone is enough to find the saved registers, and the unwind codes can describe the XMM saves
+/// relative to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnwindInfo {
+    prologue_size: u8,
+    unwind_codes: Vec<UnwindCode>,
+}
+
+impl UnwindInfo {
+    /// Compute the unwind information of the compiled function `func`.
+    ///
+    /// Returns `None` if `func` doesn't use the `windows_fastcall` calling convention, or if it
+    /// doesn't have a prologue.
+    ///
+    /// This function can only be used after the code layout has been computed by the
+    /// `binemit::relax_branches()` function.
+    pub fn try_from_func(func: &Function, isa: &TargetIsa) -> Option<Self> {
+        if func.signature.call_conv != CallConv::WindowsFastcall {
+            return None;
+        }
+        let prologue_end = func.prologue_end?;
+        let entry_ebb = func.layout.entry_block()?;
+
+        // The stack size in a register, for the stack probes that adjust the stack pointer with a
+        // register.
+        let mut stack_size = None;
+        let mut prologue_size = 0;
+        let mut unwind_codes = Vec::new();
+
+        for (offset, inst, size) in func.inst_offsets(entry_ebb, &isa.encoding_info()) {
+            // The unwind codes store the prologue offsets in a byte.
+            assert!(
+                offset + size <= 255,
+                "Windows x64 prologues can't be larger than 255 bytes"
+            );
+            prologue_size = (offset + size) as u8;
+            let offset = prologue_size;
+
+            match func.dfg[inst] {
+                InstructionData::Unary {
+                    opcode: Opcode::X86Push,
+                    arg,
+                } => {
+                    let reg = func.locations[arg].unwrap_reg();
+                    unwind_codes.push(UnwindCode::PushRegister { offset, reg });
+                }
+                InstructionData::Unary {
+                    opcode: Opcode::AdjustSpDown,
+                    ..
+                } => {
+                    let size = stack_size.expect("missing stack size for the stack probe");
+                    unwind_codes.push(UnwindCode::StackAlloc { offset, size });
+                }
+                InstructionData::Unary {
+                    opcode: Opcode::Spill,
+                    arg,
+                } => {
+                    if let ValueLoc::Reg(reg) = func.locations[arg] {
+                        let ss = func.locations[func.dfg.first_result(inst)].unwrap_stack();
+                        let stack_offset = StackRef::sp(ss, &func.stack_slots).offset as u32;
+                        unwind_codes.push(UnwindCode::SaveXmm {
+                            offset,
+                            reg,
+                            stack_offset,
+                        });
+                    }
+                }
+                InstructionData::UnaryImm {
+                    opcode: Opcode::AdjustSpDownImm,
+                    imm,
+                } => {
+                    let size: i64 = imm.into();
+                    assert!(size > 0 && size <= i64::from(u32::max_value()));
+                    unwind_codes.push(UnwindCode::StackAlloc {
+                        offset,
+                        size: size as u32,
+                    });
+                }
+                InstructionData::UnaryImm {
+                    opcode: Opcode::Iconst,
+                    imm,
+                } => {
+                    // The only constant in a prologue is the stack size passed to the stack probe.
+                    let size: i64 = imm.into();
+                    assert!(size > 0 && size <= i64::from(u32::max_value()));
+                    stack_size = Some(size as u32);
+                }
+                InstructionData::Call { .. } | InstructionData::CallIndirect { .. } => {
+                    // A stack probe that adjusts the stack pointer itself.
+                    if isa.flags().probestack_func_adjusts_sp() {
+                        let size = stack_size.expect("missing stack size for the stack probe");
+                        unwind_codes.push(UnwindCode::StackAlloc { offset, size });
+                    }
+                }
+                _ => {}
+            }
+
+            if inst == prologue_end {
+                return Some(Self {
+                    prologue_size,
+                    unwind_codes,
+                });
+            }
+        }
+
+        None
+    }
+
+    /// The number of 16-bit unwind code slots.
+    fn node_count(&self) -> usize {
+        self.unwind_codes.iter().map(|code| code.node_count()).sum()
+    }
+
+    /// Get the size of the `UNWIND_INFO` structure in bytes.
+    pub fn size(&self) -> usize {
+        // The 4-byte header is followed by the unwind code slots, padded to an even number.
+        let node_count = self.node_count();
+        4 + 2 * (node_count + (node_count & 1))
+    }
+
+    /// Append the `UNWIND_INFO` structure to `mem`.
+    ///
+    /// The structure must be 4-byte aligned in the image, so `mem` should start at such an
+    /// address and its length should be a multiple of 4.
+    pub fn emit(&self, mem: &mut Vec<u8>) {
+        const UNWIND_INFO_VERSION: u8 = 1;
+
+        let start = mem.len();
+        let node_count = self.node_count();
+        assert!(node_count <= 255, "too many unwind codes");
+
+        // Version and flags (no handlers), prologue size, code count, and frame register (none).
+        mem.push(UNWIND_INFO_VERSION);
+        mem.push(self.prologue_size);
+        mem.push(node_count as u8);
+        mem.push(0);
+
+        // The unwind codes are sorted by descending prologue offset.
+        for code in self.unwind_codes.iter().rev() {
+            code.emit(mem);
+        }
+
+        if node_count & 1 == 1 {
+            put_u16(mem, 0);
+        }
+
+        debug_assert_eq!(mem.len() - start, self.size());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::{types, AbiParam, ExternalName, InstBuilder, Signature};
+    use crate::ir::{StackSlotData, StackSlotKind};
+    use crate::isa;
+    use crate::settings::{self, Configurable};
+    use crate::Context;
+    use core::str::FromStr;
+    use std::boxed::Box;
+    use target_lexicon::triple;
+
+    fn isa() -> Box<TargetIsa> {
+        let mut flag_builder = settings::builder();
+        flag_builder.set("opt_level", "speed_and_size").unwrap();
+        isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(settings::Flags::new(flag_builder))
+    }
+
+    fn make_function(call_conv: CallConv, stack_slot: Option<u32>) -> Function {
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), {
+            let mut sig = Signature::new(call_conv);
+            sig.params.push(AbiParam::new(types::I64));
+            sig
+        });
+        if let Some(size) = stack_slot {
+            func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size));
+        }
+        let ebb = func.dfg.make_ebb();
+        func.dfg.append_ebb_param(ebb, types::I64);
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb);
+        pos.ins().return_(&[]);
+        func
+    }
+
+    #[test]
+    fn not_fastcall() {
+        let isa = isa();
+        let mut context = Context::for_function(make_function(CallConv::SystemV, None));
+        context.compile(&*isa).unwrap();
+        assert_eq!(UnwindInfo::try_from_func(&context.func, &*isa), None);
+    }
+
+    #[test]
+    fn small_alloc() {
+        let isa = isa();
+        let mut context = Context::for_function(make_function(CallConv::WindowsFastcall, None));
+        context.compile(&*isa).unwrap();
+
+        // push %rbp; mov %rsp, %rbp; sub $32, %rsp
+        let unwind = UnwindInfo::try_from_func(&context.func, &*isa).unwrap();
+        assert_eq!(
+            unwind,
+            UnwindInfo {
+                prologue_size: 8,
+                unwind_codes: vec![
+                    UnwindCode::PushRegister {
+                        offset: 1,
+                        reg: RU::rbp as RegUnit,
+                    },
+                    UnwindCode::StackAlloc {
+                        offset: 8,
+                        size: 32,
+                    },
+                ],
+            }
+        );
+
+        let mut mem = Vec::new();
+        unwind.emit(&mut mem);
+        assert_eq!(mem, [0x01, 0x08, 0x02, 0x00, 0x08, 0x32, 0x01, 0x50]);
+    }
+
+    #[test]
+    fn large_alloc() {
+        let isa = isa();
+        let mut context =
+            Context::for_function(make_function(CallConv::WindowsFastcall, Some(10000)));
+        context.compile(&*isa).unwrap();
+
+        // push %rbp; mov %rsp, %rbp; sub $10032, %rsp
+        let unwind = UnwindInfo::try_from_func(&context.func, &*isa).unwrap();
+        assert_eq!(
+            unwind,
+            UnwindInfo {
+                prologue_size: 11,
+                unwind_codes: vec![
+                    UnwindCode::PushRegister {
+                        offset: 1,
+                        reg: RU::rbp as RegUnit,
+                    },
+                    UnwindCode::StackAlloc {
+                        offset: 11,
+                        size: 10032,
+                    },
+                ],
+            }
+        );
+
+        let mut mem = Vec::new();
+        unwind.emit(&mut mem);
+        assert_eq!(
+            mem,
+            [0x01, 0x0b, 0x03, 0x00, 0x0b, 0x01, 0xe6, 0x04, 0x01, 0x50, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn emit_codes() {
+        let unwind = UnwindInfo {
+            prologue_size: 40,
+            unwind_codes: vec![
+                UnwindCode::PushRegister {
+                    offset: 2,
+                    reg: RU::r12 as RegUnit,
+                },
+                UnwindCode::StackAlloc {
+                    offset: 12,
+                    size: 0x10_0000,
+                },
+                UnwindCode::SaveXmm {
+                    offset: 20,
+                    reg: RU::xmm6 as RegUnit,
+                    stack_offset: 32,
+                },
+                UnwindCode::SaveXmm {
+                    offset: 40,
+                    reg: RU::xmm15 as RegUnit,
+                    stack_offset: 0x10_0000,
+                },
+            ],
+        };
+        assert_eq!(unwind.size(), 4 + 2 * 10);
+
+        let mut mem = vec![0xff; 4];
+        unwind.emit(&mut mem);
+        assert_eq!(
+            mem[4..],
+            [
+                0x01, 40, 9, 0x00, // header
+                40, 0xf9, 0x00, 0x00, 0x10, 0x00, // xmm15, far
+                20, 0x68, 0x02, 0x00, // xmm6
+                12, 0x11, 0x00, 0x00, 0x10, 0x00, // 1 MB
+                2, 0xc0, // r12
+                0x00, 0x00, // padding
+            ][..]
+        );
+    }
+}
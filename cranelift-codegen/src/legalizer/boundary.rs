@@ -23,7 +23,7 @@ use crate::flowgraph::ControlFlowGraph;
 use crate::ir::instructions::CallInfo;
 use crate::ir::{
     AbiParam, ArgumentLoc, ArgumentPurpose, DataFlowGraph, Ebb, Function, Inst, InstBuilder,
-    SigRef, Signature, Type, Value, ValueLoc,
+    MemFlags, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind, Type, Value, ValueLoc,
 };
 use crate::isa::TargetIsa;
 use crate::legalizer::split::{isplit, vsplit};
@@ -56,8 +56,66 @@ pub fn legalize_libcall_signature(signature: &mut Signature, isa: &TargetIsa) {
 /// Legalize the given signature.
 ///
 /// `current` is true if this is the signature for the current function.
+///
+/// When the return values don't all fit in registers, they are returned in memory instead. The
+/// caller passes the address of a return area as an extra `sret` parameter, and the callee stores
+/// the return values there. See `return_area_offsets()` for the layout of the return area.
 fn legalize_signature(signature: &mut Signature, current: bool, isa: &TargetIsa) {
+    let original = if signature.returns.len() > 1 {
+        Some(signature.clone())
+    } else {
+        None
+    };
     isa.legalize_signature(signature, current);
+
+    if let Some(mut original) = original {
+        if signature
+            .returns
+            .iter()
+            .any(|rt| rt.purpose == ArgumentPurpose::Normal && !rt.location.is_reg())
+        {
+            debug_assert_eq!(
+                original.special_param_index(ArgumentPurpose::StructReturn),
+                None,
+                "Can't return values in memory with an existing sret parameter"
+            );
+            original.returns.clear();
+            original.params.push(AbiParam::special(
+                isa.pointer_type(),
+                ArgumentPurpose::StructReturn,
+            ));
+            isa.legalize_signature(&mut original, current);
+            *signature = original;
+        }
+    }
+}
+
+/// Does the legalized signature `sig` return values in memory?
+///
+/// The return values of a call or return instruction are stored in memory when the signature has
+/// no normal return values left after legalization.
+fn uses_return_area(sig: &Signature) -> bool {
+    sig.returns
+        .iter()
+        .all(|rt| rt.purpose != ArgumentPurpose::Normal)
+        && sig
+            .special_param_index(ArgumentPurpose::StructReturn)
+            .is_some()
+}
+
+/// Compute the offsets of the `values` in a return area, and the size of the area.
+///
+/// Each value is stored at the next offset aligned to its size.
+fn return_area_offsets(dfg: &DataFlowGraph, values: &[Value]) -> (Vec<u32>, u32) {
+    let mut offsets = Vec::with_capacity(values.len());
+    let mut size = 0;
+    for &value in values {
+        let bytes = dfg.value_type(value).bytes();
+        let offset = (size + bytes - 1) / bytes * bytes;
+        offsets.push(offset);
+        size = offset + bytes;
+    }
+    (offsets, size)
 }
 
 /// Legalize the entry block parameters after `func`'s signature has been legalized.
@@ -116,6 +174,8 @@ fn legalize_entry_params(func: &mut Function, entry: Ebb) {
                     debug_assert!(!has_stack_limit, "Multiple stack_limit arguments found");
                     has_stack_limit = true;
                 }
+                // The struct is copied to the stack, see `spill_entry_params()`.
+                ArgumentPurpose::StructArgument(_) => {}
                 _ => panic!("Unexpected special-purpose arg {}", abi_type),
             }
             abi_arg += 1;
@@ -176,6 +236,14 @@ fn legalize_entry_params(func: &mut Function, entry: Ebb) {
                 debug_assert!(!has_stack_limit, "Multiple stack_limit parameters found");
                 has_stack_limit = true;
             }
+            // Struct arguments are passed by the caller, like normal parameters.
+            ArgumentPurpose::StructArgument(_) => {
+                panic!("Leftover struct arg: {}", arg);
+            }
+            // Only calls to variadic functions pass a vector count.
+            ArgumentPurpose::VectorCount => {
+                panic!("Unexpected vector count arg {}", arg);
+            }
         }
 
         // Just create entry block values to match here. We will use them in `handle_return_abi()`
@@ -242,6 +310,20 @@ where
         }
     }
 
+    // The legalized signature may return special-purpose values, like the `sret` pointer, which
+    // the caller didn't ask for. Add results for them so the call matches its signature.
+    let num_returns = pos
+        .func
+        .dfg
+        .call_signature(call)
+        .map_or(0, |sig| pos.func.dfg.signatures[sig].returns.len());
+    while abi_res < num_returns {
+        let abi_type = get_abi_type(pos.func, abi_res);
+        debug_assert_ne!(abi_type.purpose, ArgumentPurpose::Normal);
+        pos.func.dfg.append_result(call, abi_type.value_type);
+        abi_res += 1;
+    }
+
     call
 }
 
@@ -528,6 +610,20 @@ pub fn handle_call_abi(mut inst: Inst, func: &mut Function, cfg: &ControlFlowGra
         Err(s) => s,
     };
 
+    // Pass a return area for results that are returned in memory.
+    if !pos.func.dfg.inst_results(inst).is_empty()
+        && uses_return_area(&pos.func.dfg.signatures[sig_ref])
+    {
+        legalize_return_area_call(pos, inst);
+    }
+
+    // Pass the number of vector registers used by a call to a variadic function.
+    if let Some(idx) =
+        pos.func.dfg.signatures[sig_ref].special_param_index(ArgumentPurpose::VectorCount)
+    {
+        legalize_vector_count_call(pos, inst, idx);
+    }
+
     // OK, we need to fix the call arguments to match the ABI signature.
     let abi_args = pos.func.dfg.signatures[sig_ref].params.len();
     legalize_inst_arguments(pos, cfg, abi_args, |func, abi_arg| {
@@ -565,6 +661,11 @@ pub fn handle_return_abi(inst: Inst, func: &mut Function, cfg: &ControlFlowGraph
         return false;
     }
 
+    // Store the return values in the return area if they are returned in memory.
+    if !func.dfg.inst_variable_args(inst).is_empty() && uses_return_area(&func.signature) {
+        store_return_area(inst, func);
+    }
+
     // Count the special-purpose return values (`link`, `sret`, and `vmctx`) that were appended to
     // the legalized signature.
     let special_args = func
@@ -644,12 +745,119 @@ pub fn handle_return_abi(inst: Inst, func: &mut Function, cfg: &ControlFlowGraph
 /// Values that are passed into the function on the stack must be assigned to an `IncomingArg`
 /// stack slot already during legalization.
 fn spill_entry_params(func: &mut Function, entry: Ebb) {
+    let mut struct_args = Vec::new();
     for (abi, &arg) in func.signature.params.iter().zip(func.dfg.ebb_params(entry)) {
         if let ArgumentLoc::Stack(offset) = abi.location {
-            let ss = func.stack_slots.make_incoming_arg(abi.value_type, offset);
-            func.locations[arg] = ValueLoc::Stack(ss);
+            let ss = func.stack_slots.make_incoming_arg(abi.stack_size(), offset);
+            if let ArgumentPurpose::StructArgument(_) = abi.purpose {
+                struct_args.push((arg, ss));
+            } else {
+                func.locations[arg] = ValueLoc::Stack(ss);
+            }
         }
     }
+
+    // The entry block parameter of a struct argument holds the first word of the struct, but the
+    // function expects a pointer to it. Replace the uses with the address of the incoming slot.
+    let mut pos = FuncCursor::new(func).at_first_inst(entry);
+    for (arg, ss) in struct_args {
+        let ty = pos.func.dfg.value_type(arg);
+        let word = pos.func.dfg.replace_ebb_param(arg, ty);
+        pos.func.locations[word] = ValueLoc::Stack(ss);
+        pos.ins().with_result(arg).stack_addr(ty, ss, 0);
+    }
+}
+
+/// Pass a return area to the call `inst` whose results are returned in memory.
+///
+/// A stack slot is allocated for the return area, and its address is appended to the call
+/// arguments. The original results are loaded from the return area after the call, and the call
+/// gets new results matching its legalized signature.
+fn legalize_return_area_call(pos: &mut FuncCursor, inst: Inst) {
+    let sig_ref = pos.func.dfg.call_signature(inst).unwrap();
+    let ptr_type = {
+        let sig = &pos.func.dfg.signatures[sig_ref];
+        let idx = sig
+            .special_param_index(ArgumentPurpose::StructReturn)
+            .unwrap();
+        sig.params[idx].value_type
+    };
+    let results = pos.func.dfg.inst_results(inst).to_vec();
+    let (offsets, size) = return_area_offsets(&pos.func.dfg, &results);
+    let ss = pos
+        .func
+        .create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size));
+
+    let area = pos.ins().stack_addr(ptr_type, ss, 0);
+    pos.func.dfg.append_inst_arg(inst, area);
+
+    pos.func.dfg.detach_results(inst);
+    for i in 0..pos.func.dfg.signatures[sig_ref].returns.len() {
+        let ty = pos.func.dfg.signatures[sig_ref].returns[i].value_type;
+        pos.func.dfg.append_result(inst, ty);
+    }
+
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    flags.set_aligned();
+    pos.goto_after_inst(inst);
+    let area = pos.ins().stack_addr(ptr_type, ss, 0);
+    for (&result, &offset) in results.iter().zip(&offsets) {
+        let ty = pos.func.dfg.value_type(result);
+        pos.ins()
+            .with_result(result)
+            .load(ty, flags, area, offset as i32);
+    }
+    pos.goto_inst(inst);
+}
+
+/// Append the number of vector registers used by the variadic call `inst` to its arguments.
+///
+/// The count covers the float and vector parameters passed in registers, which is what the ABI
+/// requires when the callee spills its register arguments with `va_start`. `idx` is the index of
+/// the vector count parameter in the legalized signature.
+fn legalize_vector_count_call(pos: &mut FuncCursor, inst: Inst, idx: usize) {
+    let sig_ref = pos.func.dfg.call_signature(inst).unwrap();
+    let (ty, count) = {
+        let sig = &pos.func.dfg.signatures[sig_ref];
+        let count = sig
+            .params
+            .iter()
+            .filter(|p| {
+                p.purpose == ArgumentPurpose::Normal
+                    && p.location.is_reg()
+                    && (p.value_type.is_float() || p.value_type.is_vector())
+            })
+            .count();
+        (sig.params[idx].value_type, count)
+    };
+    let count = pos.ins().iconst(ty, count as i64);
+    pos.func.dfg.append_inst_arg(inst, count);
+}
+
+/// Store the values returned by `inst` in the return area of the current function, and remove
+/// them from the return instruction.
+fn store_return_area(inst: Inst, func: &mut Function) {
+    let idx = func
+        .signature
+        .special_param_index(ArgumentPurpose::StructReturn)
+        .unwrap();
+    let area = func.dfg.ebb_params(func.layout.entry_block().unwrap())[idx];
+    let values = func.dfg.inst_variable_args(inst).to_vec();
+    let (offsets, _) = return_area_offsets(&func.dfg, &values);
+
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    flags.set_aligned();
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    for (&value, &offset) in values.iter().zip(&offsets) {
+        pos.ins().store(flags, value, area, offset as i32);
+    }
+
+    let mut vlist = pos.func.dfg[inst].take_value_list().unwrap();
+    vlist.clear(&mut pos.func.dfg.value_lists);
+    pos.func.dfg[inst].put_value_list(vlist);
 }
 
 /// Assign stack slots to outgoing function arguments on the stack.
@@ -687,9 +895,9 @@ fn spill_call_arguments(pos: &mut FuncCursor) -> bool {
                         // Assign `arg` to a new stack slot, unless it's already in the correct
                         // slot. The legalization needs to be idempotent, so we should see a
                         // correct outgoing slot on the second pass.
-                        let ss = stack_slots.get_outgoing_arg(abi.value_type, offset);
+                        let ss = stack_slots.get_outgoing_arg(abi.stack_size(), offset);
                         if locations[arg] != ValueLoc::Stack(ss) {
-                            Some((idx, arg, ss))
+                            Some((idx, arg, ss, abi.purpose))
                         } else {
                             None
                         }
@@ -705,8 +913,11 @@ fn spill_call_arguments(pos: &mut FuncCursor) -> bool {
     }
 
     // Insert the spill instructions and rewrite call arguments.
-    for (idx, arg, ss) in arglist {
-        let stack_val = pos.ins().spill(arg);
+    for (idx, arg, ss, purpose) in arglist {
+        let stack_val = match purpose {
+            ArgumentPurpose::StructArgument(size) => copy_struct_argument(pos, arg, ss, size),
+            _ => pos.ins().spill(arg),
+        };
         pos.func.locations[stack_val] = ValueLoc::Stack(ss);
         pos.func.dfg.inst_variable_args_mut(inst)[idx] = stack_val;
     }
@@ -714,3 +925,71 @@ fn spill_call_arguments(pos: &mut FuncCursor) -> bool {
     // We changed stuff.
     true
 }
+
+/// Copy the `size` bytes of a struct argument at the address `src` into the outgoing argument
+/// slot `ss`.
+///
+/// The struct is copied one pointer-sized word at a time. The first word is written by a `spill`
+/// instruction whose result is returned to be passed to the call.
+fn copy_struct_argument(pos: &mut FuncCursor, src: Value, ss: StackSlot, size: u32) -> Value {
+    let ty = pos.func.dfg.value_type(src);
+    let word = ty.bytes();
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+
+    let mut first = None;
+    let mut offset = 0;
+    while offset < size {
+        let value = if size - offset >= word {
+            pos.ins().load(ty, flags, src, offset as i32)
+        } else {
+            load_partial_word(pos, flags, src, offset, size - offset)
+        };
+        if offset == 0 {
+            first = Some(value);
+        } else {
+            pos.ins().stack_store(value, ss, offset as i32);
+        }
+        offset += word;
+    }
+
+    let first = match first {
+        Some(value) => value,
+        None => pos.ins().iconst(ty, 0),
+    };
+    pos.ins().spill(first)
+}
+
+/// Load the last `len` bytes of a struct at `src + offset`, which are fewer than a word, into the
+/// low bits of a pointer-sized value.
+fn load_partial_word(
+    pos: &mut FuncCursor,
+    flags: MemFlags,
+    src: Value,
+    offset: u32,
+    len: u32,
+) -> Value {
+    let ty = pos.func.dfg.value_type(src);
+    let mut value = None;
+    let mut done = 0;
+    for &bytes in &[4, 2, 1] {
+        if len - done < bytes {
+            continue;
+        }
+        let at = (offset + done) as i32;
+        let piece = match bytes {
+            4 => pos.ins().uload32(flags, src, at),
+            2 => pos.ins().uload16(ty, flags, src, at),
+            _ => pos.ins().uload8(ty, flags, src, at),
+        };
+        value = Some(match value {
+            Some(low) => {
+                let high = pos.ins().ishl_imm(piece, i64::from(done * 8));
+                pos.ins().bor(low, high)
+            }
+            None => piece,
+        });
+        done += bytes;
+    }
+    value.expect("Empty partial word")
+}
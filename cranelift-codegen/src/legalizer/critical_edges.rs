@@ -0,0 +1,135 @@
+//! Critical edge splitting.
+//!
+//! A critical edge goes from an EBB with multiple successors to an EBB with multiple predecessors.
+//! Copies for the EBB arguments passed along such an edge can't be placed in the predecessor
+//! without affecting its other successors, nor in the successor without affecting its other
+//! predecessors. This module exports the `split_critical_edges` function which inserts an empty
+//! EBB on every critical edge:
+//!
+//! ```clif
+//! ebb0:
+//!     brnz v1, ebb2(v2)
+//!     jump ebb1
+//! ```
+//!
+//! Becomes:
+//!
+//! ```clif
+//! ebb0:
+//!     brnz v1, ebb3
+//!     jump ebb1
+//!
+//! ebb3:
+//!     jump ebb2(v2)
+//! ```
+//!
+//! Edges from `br_table` and `try_call` instructions are not split. Jump tables and landing pads
+//! can't take EBB arguments, so they never need edge copies.
+
+use crate::cursor::{Cursor, EncCursor};
+use crate::ebb_order::cold_insertion_point;
+use crate::flowgraph::{BasicBlock, ControlFlowGraph};
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{self, InstBuilder, ProgramOrder};
+use crate::isa::TargetIsa;
+use core::cmp;
+use log::debug;
+use std::vec::Vec;
+
+/// Split all the critical edges in `func`, and update `cfg` to match.
+pub fn split_critical_edges(func: &mut ir::Function, cfg: &mut ControlFlowGraph, isa: &TargetIsa) {
+    // Collect the edges first since splitting changes the CFG.
+    let mut edges = Vec::new();
+    for ebb in func.layout.ebbs() {
+        if cfg.pred_iter(ebb).nth(1).is_none() {
+            continue;
+        }
+        for BasicBlock { ebb: pred, inst } in cfg.pred_iter(ebb) {
+            if has_multiple_successors(func, pred) && !func.dfg[inst].opcode().is_call() {
+                if let BranchInfo::SingleDest(..) = func.dfg.analyze_branch(inst) {
+                    edges.push((inst, ebb));
+                }
+            }
+        }
+    }
+
+    for (inst, dest) in edges {
+        split_edge(func, cfg, isa, inst, dest);
+    }
+}
+
+/// Does `ebb` have more than one outgoing CFG edge?
+///
+/// Each branch instruction counts as a separate edge, even if several branches go to the same
+/// destination.
+fn has_multiple_successors(func: &ir::Function, ebb: ir::Ebb) -> bool {
+    let mut edges = 0;
+    for inst in func.layout.ebb_insts(ebb) {
+        match func.dfg.analyze_branch(inst) {
+            BranchInfo::NotABranch => {}
+            BranchInfo::SingleDest(..) => edges += 1,
+            BranchInfo::Table(..) => return true,
+        }
+    }
+    edges > 1
+}
+
+/// Split the edge from the branch `inst` to `dest` by inserting a new EBB that jumps to `dest`
+/// with the branch arguments.
+fn split_edge(
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    isa: &TargetIsa,
+    inst: ir::Inst,
+    dest: ir::Ebb,
+) {
+    let pred = func.layout.pp_ebb(inst);
+    let edge_ebb = func.dfg.make_ebb();
+    debug!("Splitting edge {} -> {} with {}", inst, dest, edge_ebb);
+
+    // Move the EBB arguments from the branch to the new jump.
+    let num_fixed = func.dfg[inst]
+        .opcode()
+        .constraints()
+        .num_fixed_value_arguments();
+    let mut vlist = func.dfg[inst]
+        .take_value_list()
+        .expect("Branches must have value lists.");
+    let pool = &mut func.dfg.value_lists;
+    let args = vlist.as_slice(pool)[num_fixed..].to_vec();
+    while vlist.len(pool) > num_fixed {
+        let last = vlist.len(pool) - 1;
+        vlist.remove(last, pool);
+    }
+    func.dfg[inst].put_value_list(vlist);
+    *func.dfg[inst]
+        .branch_destination_mut()
+        .expect("Expected a branch.") = edge_ebb;
+
+    // Place the new EBB right before its destination so the jump can become a fallthrough. The
+    // EBBs ending the function with a `fallthrough_return` must stay together at the end, so the
+    // new EBB can only go before the first of them.
+    let tail = cold_insertion_point(func);
+    let before = if func.layout.entry_block() == Some(dest) {
+        tail
+    } else {
+        match tail {
+            Some(tail) if func.layout.cmp(tail, dest) == cmp::Ordering::Less => Some(tail),
+            _ => Some(dest),
+        }
+    };
+    match before {
+        Some(before) => func.layout.insert_ebb(edge_ebb, before),
+        None => func.layout.append_ebb(edge_ebb),
+    }
+    // An edge into a cold EBB is just as cold.
+    if func.layout.is_cold(dest) {
+        func.layout.set_cold(edge_ebb, true);
+    }
+    let mut pos = EncCursor::new(func, isa).at_bottom(edge_ebb);
+    pos.use_srcloc(inst);
+    pos.ins().jump(dest, &args);
+
+    cfg.recompute_ebb(pos.func, pred);
+    cfg.recompute_ebb(pos.func, edge_ebb);
+}
@@ -0,0 +1,146 @@
+//! Legalization of memory accesses with a foreign byte order.
+//!
+//! This module exports the `handle_endianness` function which rewrites loads and stores that
+//! request a byte order different from the native byte order of the target. The memory access
+//! itself is changed to use the native byte order, and the bytes of the value are swapped in
+//! registers.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::ir::{self, InstBuilder, Opcode, Type, Value};
+use crate::isa::TargetIsa;
+
+/// Rewrite `inst` if it is a load or store with a foreign byte order.
+///
+/// Return true if the instruction was changed.
+pub fn handle_endianness(inst: ir::Inst, func: &mut ir::Function, isa: &TargetIsa) -> bool {
+    let native = isa.endianness();
+    let opcode = match func.dfg[inst] {
+        ir::InstructionData::Load { opcode, flags, .. }
+        | ir::InstructionData::LoadComplex { opcode, flags, .. }
+        | ir::InstructionData::Store { opcode, flags, .. }
+        | ir::InstructionData::StoreComplex { opcode, flags, .. } => {
+            if flags.endianness(native) == native {
+                return false;
+            }
+            opcode
+        }
+        _ => return false,
+    };
+    let ty = if opcode.can_load() {
+        func.dfg.value_type(func.dfg.first_result(inst))
+    } else {
+        func.dfg.value_type(func.dfg.inst_args(inst)[0])
+    };
+
+    // Integers wider than 64 bits are narrowed into two accesses that keep the byte order.
+    if ty.is_int() && ty.bits() > 64 {
+        return false;
+    }
+
+    match func.dfg[inst] {
+        ir::InstructionData::Load { ref mut flags, .. }
+        | ir::InstructionData::LoadComplex { ref mut flags, .. }
+        | ir::InstructionData::Store { ref mut flags, .. }
+        | ir::InstructionData::StoreComplex { ref mut flags, .. } => flags.set_endianness(native),
+        _ => unreachable!(),
+    }
+    if !needs_swap(ty, opcode) {
+        return true;
+    }
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    if opcode.can_load() {
+        // Swap the loaded value after the load.
+        let result = pos.func.dfg.first_result(inst);
+        let loaded = pos.func.dfg.replace_result(result, ty);
+        pos.goto_after_inst(inst);
+        let swapped = swap_loaded(&mut pos, loaded, opcode);
+        pos.func.dfg.change_to_alias(result, swapped);
+    } else {
+        // Swap the stored value before the store.
+        let arg = pos.func.dfg.inst_args(inst)[0];
+        let swapped = swap_stored(&mut pos, arg, opcode);
+        pos.func.dfg.inst_args_mut(inst)[0] = swapped;
+    }
+    true
+}
+
+/// Get the number of bytes accessed by a load or store of a `ty` value.
+fn access_bytes(ty: Type, opcode: Opcode) -> u32 {
+    match opcode {
+        Opcode::Uload8
+        | Opcode::Sload8
+        | Opcode::Istore8
+        | Opcode::Uload8Complex
+        | Opcode::Sload8Complex
+        | Opcode::Istore8Complex => 1,
+        Opcode::Uload16
+        | Opcode::Sload16
+        | Opcode::Istore16
+        | Opcode::Uload16Complex
+        | Opcode::Sload16Complex
+        | Opcode::Istore16Complex => 2,
+        Opcode::Uload32
+        | Opcode::Sload32
+        | Opcode::Istore32
+        | Opcode::Uload32Complex
+        | Opcode::Sload32Complex
+        | Opcode::Istore32Complex => 4,
+        _ => ty.bytes(),
+    }
+}
+
+/// Does accessing a `ty` value in a foreign byte order require swapping bytes?
+///
+/// Boolean values are all zeros or all ones, so they look the same in either byte order. SIMD
+/// vectors are always accessed in the native byte order.
+fn needs_swap(ty: Type, opcode: Opcode) -> bool {
+    !ty.is_bool() && !ty.is_vector() && access_bytes(ty, opcode) > 1
+}
+
+/// Swap the bytes of a value that was loaded by `opcode`.
+fn swap_loaded(pos: &mut FuncCursor, loaded: Value, opcode: Opcode) -> Value {
+    let ty = pos.func.dfg.value_type(loaded);
+    let swapped = swap_bytes(pos, loaded);
+
+    // An extending load has the interesting bytes in the low part of the register. After the
+    // swap, they are in the high part, and a shift moves them back while extending.
+    let shift = i64::from(ty.bytes() - access_bytes(ty, opcode)) * 8;
+    match opcode {
+        _ if shift == 0 => swapped,
+        Opcode::Sload16 | Opcode::Sload32 | Opcode::Sload16Complex | Opcode::Sload32Complex => {
+            pos.ins().sshr_imm(swapped, shift)
+        }
+        _ => pos.ins().ushr_imm(swapped, shift),
+    }
+}
+
+/// Swap the bytes of a value that is about to be stored by `opcode`.
+fn swap_stored(pos: &mut FuncCursor, arg: Value, opcode: Opcode) -> Value {
+    let ty = pos.func.dfg.value_type(arg);
+    let swapped = swap_bytes(pos, arg);
+
+    // A truncating store only writes the low part of the register, so move the swapped bytes
+    // there.
+    let shift = i64::from(ty.bytes() - access_bytes(ty, opcode)) * 8;
+    if shift == 0 {
+        swapped
+    } else {
+        pos.ins().ushr_imm(swapped, shift)
+    }
+}
+
+/// Reverse the order of the bytes in the scalar value `x`.
+fn swap_bytes(pos: &mut FuncCursor, x: Value) -> Value {
+    let ty = pos.func.dfg.value_type(x);
+    if ty.is_float() {
+        let int_ty = Type::int(ty.bits()).expect("float type with no integer equivalent");
+        let int = pos.ins().bitcast(int_ty, x);
+        let swapped = pos.ins().bswap(int);
+        pos.ins().bitcast(ty, swapped)
+    } else {
+        pos.ins().bswap(x)
+    }
+}
@@ -5,6 +5,7 @@
 
 use crate::cursor::{Cursor, FuncCursor};
 use crate::flowgraph::ControlFlowGraph;
+use crate::ir::immediates::Imm64;
 use crate::ir::{self, InstBuilder};
 use crate::isa::TargetIsa;
 
@@ -123,7 +124,76 @@ fn load_addr(
 }
 
 /// Expand a `global_value` instruction for a symbolic name global.
+///
+/// The symbol's offset is normally folded into the relocation addend. That isn't possible when
+/// the address is loaded from the GOT or computed by a TLS access sequence, so the address of the
+/// symbol itself is computed first, and the offset is added to it.
 fn symbol(inst: ir::Inst, func: &mut ir::Function, gv: ir::GlobalValue, isa: &TargetIsa) {
     let ptr_ty = isa.pointer_type();
-    func.dfg.replace(inst).symbol_value(ptr_ty, gv);
+    let (offset, colocated, tls): (i64, bool, bool) = match func.global_values[gv] {
+        ir::GlobalValueData::Symbol {
+            offset,
+            colocated,
+            tls,
+            ..
+        } => (offset.into(), colocated, tls),
+        _ => panic!("Wanted a symbol global value"),
+    };
+
+    if offset == 0 || !(tls || (isa.flags().is_pic() && !colocated)) {
+        if tls {
+            func.dfg.replace(inst).tls_value(ptr_ty, gv);
+        } else {
+            func.dfg.replace(inst).symbol_value(ptr_ty, gv);
+        }
+        return;
+    }
+
+    let base_gv = base_symbol(func, gv);
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let base = if tls {
+        pos.ins().tls_value(ptr_ty, base_gv)
+    } else {
+        pos.ins().symbol_value(ptr_ty, base_gv)
+    };
+    pos.func.dfg.replace(inst).iadd_imm(base, offset);
+}
+
+/// Get a global value for the symbol `gv` refers to, without its offset.
+///
+/// If there is an existing one, use it, otherwise make a new one.
+fn base_symbol(func: &mut ir::Function, gv: ir::GlobalValue) -> ir::GlobalValue {
+    let (name, colocated, tls) = match func.global_values[gv] {
+        ir::GlobalValueData::Symbol {
+            ref name,
+            colocated,
+            tls,
+            ..
+        } => (name.clone(), colocated, tls),
+        _ => panic!("Wanted a symbol global value"),
+    };
+    for (base_gv, data) in func.global_values.iter() {
+        if let ir::GlobalValueData::Symbol {
+            name: ref base_name,
+            offset,
+            colocated: base_colocated,
+            tls: base_tls,
+        } = *data
+        {
+            if *base_name == name
+                && offset == Imm64::new(0)
+                && base_colocated == colocated
+                && base_tls == tls
+            {
+                return base_gv;
+            }
+        }
+    }
+    func.create_global_value(ir::GlobalValueData::Symbol {
+        name,
+        offset: Imm64::new(0),
+        colocated,
+        tls,
+    })
 }
@@ -149,9 +149,12 @@ fn compute_addr(
     let mut pos = FuncCursor::new(func).at_inst(inst);
     pos.use_srcloc(inst);
 
-    // Convert `offset` to `addr_ty`.
-    if offset_ty != addr_ty {
+    // Convert `offset` to `addr_ty`. A 64-bit offset into a heap on a 32-bit target has passed
+    // the bounds check, so it fits in `addr_ty`.
+    if offset_ty.bits() < addr_ty.bits() {
         offset = pos.ins().uextend(addr_ty, offset);
+    } else if offset_ty.bits() > addr_ty.bits() {
+        offset = pos.ins().ireduce(addr_ty, offset);
     }
 
     // Add the heap base address base
@@ -15,24 +15,34 @@
 
 use crate::bitset::BitSet;
 use crate::cursor::{Cursor, FuncCursor};
+use crate::ebb_order::cold_insertion_point;
 use crate::flowgraph::ControlFlowGraph;
+use crate::ir::condcodes::CondCode;
 use crate::ir::types::I32;
-use crate::ir::{self, InstBuilder, MemFlags};
+use crate::ir::{self, InstBuilder, MemFlags, ProgramOrder};
 use crate::isa::TargetIsa;
+use crate::result::{CodegenError, CodegenResult};
 use crate::timing;
+use core::cmp;
 
 mod boundary;
 mod call;
+mod critical_edges;
+mod endian;
 mod globalvalue;
 mod heap;
 mod libcall;
+mod simd;
 mod split;
 mod table;
 
 use self::call::expand_call;
+use self::critical_edges::split_critical_edges;
+use self::endian::handle_endianness;
 use self::globalvalue::expand_global_value;
 use self::heap::expand_heap_addr;
 use self::libcall::expand_as_libcall;
+use self::simd::{expand_lane_access, expand_shuffle, expand_vector_test};
 use self::table::expand_table_addr;
 
 /// Legalize `inst` for `isa`. Return true if any changes to the code were
@@ -56,6 +66,11 @@ fn legalize_inst(
         }
     } else if opcode.is_branch() {
         split::simplify_branch_arguments(&mut pos.func.dfg, inst);
+    } else if opcode.can_load() || opcode.can_store() {
+        // Memory accesses with a foreign byte order become native accesses plus a byte swap.
+        if handle_endianness(inst, pos.func, isa) {
+            return true;
+        }
     }
 
     match pos.func.update_encoding(inst, isa) {
@@ -66,7 +81,7 @@ fn legalize_inst(
             // the expanded sequence. This is both to assign encodings and possible to
             // expand further.
             // There's a risk of infinite looping here if the legalization patterns are
-            // unsound. Should we attempt to detect that?
+            // unsound. `legalize_function()` gives up after `MAX_EXPANSIONS` attempts.
             if action(inst, pos.func, cfg, isa) {
                 return true;
             }
@@ -78,12 +93,25 @@ fn legalize_inst(
     }
 }
 
+/// The number of times in a row the legalizer may rewrite the instructions at a position without
+/// reaching a legal instruction. Deeply nested expansions, like narrowing an `i128` operation to
+/// `i32` operations, take a handful of steps, so this is only reached by patterns that keep
+/// rewriting an instruction into something that needs to be legalized again.
+const MAX_EXPANSIONS: usize = 1000;
+
 /// Legalize `func` for `isa`.
 ///
 /// - Transform any instructions that don't have a legal representation in `isa`.
 /// - Fill out `func.encodings`.
+/// - Split critical edges if `enable_critical_edge_splitting` is set.
 ///
-pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, isa: &TargetIsa) {
+/// Instructions that can't be transformed are left without an encoding, see `check_encodings()`.
+/// An `Unsupported` error is returned if the legalization of an instruction doesn't terminate.
+pub fn legalize_function(
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    isa: &TargetIsa,
+) -> CodegenResult<()> {
     let _tt = timing::legalize();
     debug_assert!(cfg.is_valid());
 
@@ -99,14 +127,25 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
         // Keep track of the cursor position before the instruction being processed, so we can
         // double back when replacing instructions.
         let mut prev_pos = pos.position();
+        let mut expansions = 0;
 
         while let Some(inst) = pos.next_inst() {
             if legalize_inst(inst, &mut pos, cfg, isa) {
+                expansions += 1;
+                if expansions > MAX_EXPANSIONS {
+                    return Err(unsupported(
+                        pos.func,
+                        inst,
+                        isa,
+                        "legalization doesn't terminate",
+                    ));
+                }
                 // Go back and legalize the inserted return value conversion instructions.
                 pos.set_position(prev_pos);
             } else {
                 // Remember this position in case we need to double back.
                 prev_pos = pos.position();
+                expansions = 0;
             }
         }
     }
@@ -115,6 +154,54 @@ pub fn legalize_function(func: &mut ir::Function, cfg: &mut ControlFlowGraph, is
     if !isa.flags().jump_tables_enabled() {
         pos.func.jump_tables.clear();
     }
+
+    // Legalization can create new branches, so split the critical edges last.
+    if isa.flags().enable_critical_edge_splitting() {
+        split_critical_edges(pos.func, cfg, isa);
+    }
+
+    Ok(())
+}
+
+/// Check that every instruction in `func` has been given an encoding by `legalize_function`.
+///
+/// Instructions which the legalizer couldn't rewrite into supported instructions are left
+/// without an encoding. Report the first one as an `Unsupported` error instead of failing later
+/// during code emission. Ghost instructions don't need an encoding.
+pub fn check_encodings(func: &ir::Function, isa: &TargetIsa) -> CodegenResult<()> {
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            if !func.encodings[inst].is_legal() && !func.dfg[inst].opcode().is_ghost() {
+                return Err(unsupported(func, inst, isa, "no encoding or expansion"));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Build the error reporting that `inst` is not supported by `isa` for the given `reason`.
+///
+/// The message names the opcode and the controlling type of the instruction, like `iadd.i8`, so
+/// it is clear which combination the target is missing.
+fn unsupported(func: &ir::Function, inst: ir::Inst, isa: &TargetIsa, reason: &str) -> CodegenError {
+    let opcode = func.dfg[inst].opcode();
+    let ctrl_type = func.dfg.ctrl_typevar(inst);
+    let name = if ctrl_type.is_invalid() {
+        format!("{}", opcode)
+    } else {
+        format!("{}.{}", opcode, ctrl_type)
+    };
+    CodegenError::Unsupported {
+        inst,
+        srcloc: func.srclocs[inst],
+        message: format!(
+            "{} is not supported by {} ({}): {}",
+            name,
+            isa.name(),
+            reason,
+            func.dfg.display_inst(inst, isa)
+        ),
+    }
 }
 
 // Include legalization patterns that were generated by `gen_legalizer.py` from the `XForms` in
@@ -129,22 +216,82 @@ fn expand_cond_trap(
     inst: ir::Inst,
     func: &mut ir::Function,
     cfg: &mut ControlFlowGraph,
-    _isa: &TargetIsa,
+    isa: &TargetIsa,
 ) {
-    // Parse the instruction.
-    let trapz;
-    let (arg, code) = match func.dfg[inst] {
-        ir::InstructionData::CondTrap { opcode, arg, code } => {
-            // We want to branch *over* an unconditional trap.
-            trapz = match opcode {
-                ir::Opcode::Trapz => true,
-                ir::Opcode::Trapnz => false,
-                _ => panic!("Expected cond trap: {}", func.dfg.display_inst(inst, None)),
-            };
-            (arg, code)
+    let old_ebb = func.layout.pp_ebb(inst);
+
+    // The EBBs which must stay at the end of the function come after the trap EBBs, so a trap in
+    // one of them is kept inline: the legalizer has already visited the place of its trap EBB.
+    let before = cold_insertion_point(func);
+    let in_tail = before.map_or(false, |before| {
+        func.layout.cmp(before, old_ebb) != cmp::Ordering::Greater
+    });
+
+    if isa.flags().out_of_line_traps() && !in_tail {
+        // Branch to a trap at the end of the function:
+        //
+        //     trapnz arg
+        //
+        // Becomes:
+        //
+        //     brnz arg, trap_ebb
+        //     ...
+        //   trap_ebb:
+        //     trap
+        //
+        let trap_ebb = func.dfg.make_ebb();
+        let code = match func.dfg[inst] {
+            ir::InstructionData::CondTrap {
+                opcode: ir::Opcode::Trapz,
+                arg,
+                code,
+            } => {
+                func.dfg.replace(inst).brz(arg, trap_ebb, &[]);
+                code
+            }
+            ir::InstructionData::CondTrap {
+                opcode: ir::Opcode::Trapnz,
+                arg,
+                code,
+            } => {
+                func.dfg.replace(inst).brnz(arg, trap_ebb, &[]);
+                code
+            }
+            ir::InstructionData::IntCondTrap {
+                opcode: ir::Opcode::Trapif,
+                cond,
+                arg,
+                code,
+            } => {
+                func.dfg.replace(inst).brif(cond, arg, trap_ebb, &[]);
+                code
+            }
+            ir::InstructionData::FloatCondTrap {
+                opcode: ir::Opcode::Trapff,
+                cond,
+                arg,
+                code,
+            } => {
+                func.dfg.replace(inst).brff(cond, arg, trap_ebb, &[]);
+                code
+            }
+            _ => panic!("Expected cond trap: {}", func.dfg.display_inst(inst, None)),
+        };
+        func.branch_probabilities[inst] = ir::BranchProbability::from_percent(0);
+
+        match before {
+            Some(before) => func.layout.insert_ebb(trap_ebb, before),
+            None => func.layout.append_ebb(trap_ebb),
         }
-        _ => panic!("Expected cond trap: {}", func.dfg.display_inst(inst, None)),
-    };
+        func.layout.set_cold(trap_ebb, true);
+        let mut pos = FuncCursor::new(func).at_bottom(trap_ebb);
+        pos.use_srcloc(inst);
+        pos.ins().trap(code);
+
+        cfg.recompute_ebb(pos.func, old_ebb);
+        cfg.recompute_ebb(pos.func, trap_ebb);
+        return;
+    }
 
     // Split the EBB after `inst`:
     //
@@ -156,13 +303,49 @@ fn expand_cond_trap(
     //     trap
     //   new_ebb:
     //
-    let old_ebb = func.layout.pp_ebb(inst);
     let new_ebb = func.dfg.make_ebb();
-    if trapz {
-        func.dfg.replace(inst).brnz(arg, new_ebb, &[]);
-    } else {
-        func.dfg.replace(inst).brz(arg, new_ebb, &[]);
-    }
+    let code = match func.dfg[inst] {
+        ir::InstructionData::CondTrap {
+            opcode: ir::Opcode::Trapz,
+            arg,
+            code,
+        } => {
+            // We want to branch *over* an unconditional trap.
+            func.dfg.replace(inst).brnz(arg, new_ebb, &[]);
+            code
+        }
+        ir::InstructionData::CondTrap {
+            opcode: ir::Opcode::Trapnz,
+            arg,
+            code,
+        } => {
+            func.dfg.replace(inst).brz(arg, new_ebb, &[]);
+            code
+        }
+        ir::InstructionData::IntCondTrap {
+            opcode: ir::Opcode::Trapif,
+            cond,
+            arg,
+            code,
+        } => {
+            func.dfg
+                .replace(inst)
+                .brif(cond.inverse(), arg, new_ebb, &[]);
+            code
+        }
+        ir::InstructionData::FloatCondTrap {
+            opcode: ir::Opcode::Trapff,
+            cond,
+            arg,
+            code,
+        } => {
+            func.dfg
+                .replace(inst)
+                .brff(cond.inverse(), arg, new_ebb, &[]);
+            code
+        }
+        _ => panic!("Expected cond trap: {}", func.dfg.display_inst(inst, None)),
+    };
 
     let mut pos = FuncCursor::new(func).after_inst(inst);
     pos.use_srcloc(inst);
@@ -373,6 +556,40 @@ fn expand_fconst(
     pos.func.dfg.replace(inst).bitcast(ty, ival);
 }
 
+/// Expand an `fma` instruction that the target can't encode directly.
+///
+/// With `enable_strict_fp`, this becomes a library call that rounds only once. Otherwise it
+/// becomes a multiplication followed by an addition.
+fn expand_fma(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    isa: &TargetIsa,
+) {
+    if isa.flags().enable_strict_fp() {
+        let expanded = expand_as_libcall(inst, func, isa);
+        debug_assert!(
+            expanded,
+            "No libcall for fma: {}",
+            func.dfg.display_inst(inst, None)
+        );
+        return;
+    }
+
+    let (x, y, z) = match func.dfg[inst] {
+        ir::InstructionData::Ternary {
+            opcode: ir::Opcode::Fma,
+            args,
+        } => (args[0], args[1], args[2]),
+        _ => panic!("Expected fma: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let product = pos.ins().fmul(x, y);
+    pos.func.dfg.replace(inst).fadd(product, z);
+}
+
 /// Expand illegal `stack_load` instructions.
 fn expand_stack_load(
     inst: ir::Inst,
@@ -438,3 +655,322 @@ fn expand_stack_store(
     mflags.set_aligned();
     pos.func.dfg.replace(inst).store(mflags, val, addr, 0);
 }
+
+/// Expand an `atomic_rmw` instruction that the target can't encode directly.
+///
+/// Subtraction becomes an atomic addition of the negated argument. All other operations become a
+/// loop that retries an `atomic_cas` until the memory location wasn't modified between reading
+/// the old value and writing the new one.
+fn expand_atomic_rmw(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    use crate::ir::condcodes::IntCC;
+    use crate::ir::AtomicRmwOp;
+
+    let (flags, op, addr, arg) = match func.dfg[inst] {
+        ir::InstructionData::AtomicRmw {
+            opcode: ir::Opcode::AtomicRmw,
+            flags,
+            op,
+            args,
+        } => (flags, op, args[0], args[1]),
+        _ => panic!("Expected atomic_rmw: {}", func.dfg.display_inst(inst, None)),
+    };
+    let result = func.dfg.first_result(inst);
+    let ty = func.dfg.value_type(result);
+
+    if op == AtomicRmwOp::Sub {
+        let mut pos = FuncCursor::new(func).at_inst(inst);
+        pos.use_srcloc(inst);
+        let neg = pos.ins().irsub_imm(arg, 0);
+        pos.func
+            .dfg
+            .replace(inst)
+            .atomic_rmw(ty, flags, AtomicRmwOp::Add, addr, neg);
+        return;
+    }
+
+    // Split the EBB around `inst`:
+    //
+    //     v = atomic_rmw.ty op p, x
+    //
+    // Becomes:
+    //
+    //     v0 = atomic_load.ty p
+    //     jump loop_ebb(v0)
+    //   loop_ebb(old: ty):
+    //     new = op old, x
+    //     prev = atomic_cas p, old, new
+    //     failed = icmp ne prev, old
+    //     brnz failed, loop_ebb(prev)
+    //     jump done(old)
+    //   done(v: ty):
+    //
+    let old_ebb = func.layout.pp_ebb(inst);
+    let loop_ebb = func.dfg.make_ebb();
+    let old = func.dfg.append_ebb_param(loop_ebb, ty);
+    let done = func.dfg.make_ebb();
+
+    // Move the `inst` result value onto the `done` EBB.
+    func.dfg.clear_results(inst);
+    func.dfg.attach_ebb_param(done, result);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let initial = pos.ins().atomic_load(ty, flags, addr);
+    pos.ins().jump(loop_ebb, &[initial]);
+
+    pos.insert_ebb(loop_ebb);
+    let new = match op {
+        AtomicRmwOp::Add => pos.ins().iadd(old, arg),
+        AtomicRmwOp::And => pos.ins().band(old, arg),
+        AtomicRmwOp::Or => pos.ins().bor(old, arg),
+        AtomicRmwOp::Xor => pos.ins().bxor(old, arg),
+        AtomicRmwOp::Xchg => arg,
+        AtomicRmwOp::Sub => unreachable!(),
+    };
+    let prev = pos.ins().atomic_cas(ty, flags, addr, old, new);
+    let failed = pos.ins().icmp(IntCC::NotEqual, prev, old);
+    pos.ins().brnz(failed, loop_ebb, &[prev]);
+
+    // Recycle the original instruction as a jump.
+    pos.func.dfg.replace(inst).jump(done, &[old]);
+
+    // Finally insert a label for the completion.
+    pos.next_inst();
+    pos.insert_ebb(done);
+
+    cfg.recompute_ebb(pos.func, old_ebb);
+    cfg.recompute_ebb(pos.func, loop_ebb);
+    cfg.recompute_ebb(pos.func, done);
+}
+
+/// Narrow an `iconst` of a wide integer type into two half-width constants.
+///
+/// The immediate is sign-extended to the full width of the result type, so the high half of an
+/// `iconst.i128` holds copies of the immediate's sign bit.
+fn narrow_iconst(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    let imm: i64 = match func.dfg[inst] {
+        ir::InstructionData::UnaryImm {
+            opcode: ir::Opcode::Iconst,
+            imm,
+        } => imm.into(),
+        _ => panic!("Expected iconst: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let ty = func.dfg.ctrl_typevar(inst);
+    let half_ty = ty
+        .half_width()
+        .unwrap_or_else(|| panic!("Can't narrow iconst.{}", ty));
+    let half_bits = half_ty.bits();
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let (lo, hi) = if half_bits >= 64 {
+        (imm, imm >> 63)
+    } else {
+        (imm & ((1 << half_bits) - 1), imm >> half_bits)
+    };
+    let lo = pos.ins().iconst(half_ty, lo);
+    let hi = pos.ins().iconst(half_ty, hi);
+    pos.func.dfg.replace(inst).iconcat(lo, hi);
+}
+
+/// Narrow a `load` of a wide integer type into two half-width loads.
+///
+/// The half that is stored at the lower address depends on the byte order of the access.
+fn narrow_load(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    isa: &TargetIsa,
+) {
+    let (ptr, flags, offset) = match func.dfg[inst] {
+        ir::InstructionData::Load {
+            opcode: ir::Opcode::Load,
+            arg,
+            flags,
+            offset,
+        } => (arg, flags, offset),
+        _ => panic!("Expected load: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let ty = func.dfg.ctrl_typevar(inst);
+    let half_ty = ty
+        .half_width()
+        .unwrap_or_else(|| panic!("Can't narrow load.{}", ty));
+    let hi_offset = offset
+        .try_add_i64(i64::from(half_ty.bytes()))
+        .expect("load offset overflow");
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let (lo_offset, hi_offset) = match flags.endianness(isa.endianness()) {
+        ir::Endianness::Little => (offset, hi_offset),
+        ir::Endianness::Big => (hi_offset, offset),
+    };
+    let lo = pos.ins().load(half_ty, flags, ptr, lo_offset);
+    let hi = pos.ins().load(half_ty, flags, ptr, hi_offset);
+    pos.func.dfg.replace(inst).iconcat(lo, hi);
+}
+
+/// Narrow a `store` of a wide integer type into two half-width stores.
+fn narrow_store(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    isa: &TargetIsa,
+) {
+    let (val, ptr, flags, offset) = match func.dfg[inst] {
+        ir::InstructionData::Store {
+            opcode: ir::Opcode::Store,
+            args,
+            flags,
+            offset,
+        } => (args[0], args[1], flags, offset),
+        _ => panic!("Expected store: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let ty = func.dfg.value_type(val);
+    let half_ty = ty
+        .half_width()
+        .unwrap_or_else(|| panic!("Can't narrow store.{}", ty));
+    let hi_offset = offset
+        .try_add_i64(i64::from(half_ty.bytes()))
+        .expect("store offset overflow");
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let curpos = pos.position();
+    let srcloc = pos.srcloc();
+    let (lo, hi) = split::isplit(pos.func, cfg, curpos, srcloc, val);
+    let (lo_offset, hi_offset) = match flags.endianness(isa.endianness()) {
+        ir::Endianness::Little => (offset, hi_offset),
+        ir::Endianness::Big => (hi_offset, offset),
+    };
+    pos.ins().store(flags, lo, ptr, lo_offset);
+    pos.ins().store(flags, hi, ptr, hi_offset);
+    pos.remove_inst();
+}
+
+/// Narrow a shift or rotate of a wide integer type into operations on the two halves.
+///
+/// The shift amount is taken modulo the width of the type. The results for amounts below and
+/// above the half width are both computed and the right ones are picked with `select`, so the
+/// expansion doesn't need any branches.
+fn narrow_shift(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    use crate::ir::condcodes::IntCC;
+
+    let (opcode, x, amt) = match func.dfg[inst] {
+        ir::InstructionData::Binary { opcode, args } => (opcode, args[0], args[1]),
+        _ => panic!("Expected shift: {}", func.dfg.display_inst(inst, None)),
+    };
+
+    let ty = func.dfg.ctrl_typevar(inst);
+    let half_ty = ty
+        .half_width()
+        .unwrap_or_else(|| panic!("Can't narrow {}.{}", opcode, ty));
+    let half_bits = i64::from(half_ty.bits());
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    let curpos = pos.position();
+    let srcloc = pos.srcloc();
+    let (xl, xh) = split::isplit(pos.func, cfg, curpos, srcloc, x);
+
+    // Only the low bits of the amount matter, so a wide amount is narrowed to its low half.
+    let mut amt = amt;
+    while pos.func.dfg.value_type(amt).bits() > half_ty.bits() {
+        amt = split::isplit(pos.func, cfg, curpos, srcloc, amt).0;
+    }
+
+    // `big` is set when the amount moves bits across the full half width. The halves are then
+    // shifted or rotated by the remaining `amt_lo` bits.
+    let amt_mod = pos.ins().band_imm(amt, 2 * half_bits - 1);
+    let big = pos
+        .ins()
+        .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, amt_mod, half_bits);
+    let amt_lo = pos.ins().band_imm(amt, half_bits - 1);
+
+    // The bits moved from one half into the other are shifted by `half_bits - amt_lo`, which is
+    // out of range when `amt_lo` is 0. Shifting by one first and then by `half_bits - 1 - amt_lo`
+    // gives the right result for all amounts.
+    let amt_inv = pos.ins().bxor_imm(amt_lo, half_bits - 1);
+
+    let (lo, hi) = match opcode {
+        ir::Opcode::Ishl => {
+            let lo = pos.ins().ishl(xl, amt_lo);
+            let hi = pos.ins().ishl(xh, amt_lo);
+            let carry = pos.ins().ushr_imm(xl, 1);
+            let carry = pos.ins().ushr(carry, amt_inv);
+            let hi = pos.ins().bor(hi, carry);
+            let zero = pos.ins().iconst(half_ty, 0);
+            (
+                pos.ins().select(big, zero, lo),
+                pos.ins().select(big, lo, hi),
+            )
+        }
+        ir::Opcode::Ushr | ir::Opcode::Sshr => {
+            let hi = if opcode == ir::Opcode::Ushr {
+                pos.ins().ushr(xh, amt_lo)
+            } else {
+                pos.ins().sshr(xh, amt_lo)
+            };
+            let lo = pos.ins().ushr(xl, amt_lo);
+            let carry = pos.ins().ishl_imm(xh, 1);
+            let carry = pos.ins().ishl(carry, amt_inv);
+            let lo = pos.ins().bor(lo, carry);
+            let fill = if opcode == ir::Opcode::Ushr {
+                pos.ins().iconst(half_ty, 0)
+            } else {
+                pos.ins().sshr_imm(xh, half_bits - 1)
+            };
+            (
+                pos.ins().select(big, hi, lo),
+                pos.ins().select(big, fill, hi),
+            )
+        }
+        ir::Opcode::Rotl => {
+            // Rotating by the half width swaps the halves.
+            let rh = pos.ins().ishl(xh, amt_lo);
+            let carry = pos.ins().ushr_imm(xl, 1);
+            let carry = pos.ins().ushr(carry, amt_inv);
+            let rh = pos.ins().bor(rh, carry);
+            let rl = pos.ins().ishl(xl, amt_lo);
+            let carry = pos.ins().ushr_imm(xh, 1);
+            let carry = pos.ins().ushr(carry, amt_inv);
+            let rl = pos.ins().bor(rl, carry);
+            (pos.ins().select(big, rh, rl), pos.ins().select(big, rl, rh))
+        }
+        ir::Opcode::Rotr => {
+            let rl = pos.ins().ushr(xl, amt_lo);
+            let carry = pos.ins().ishl_imm(xh, 1);
+            let carry = pos.ins().ishl(carry, amt_inv);
+            let rl = pos.ins().bor(rl, carry);
+            let rh = pos.ins().ushr(xh, amt_lo);
+            let carry = pos.ins().ishl_imm(xl, 1);
+            let carry = pos.ins().ishl(carry, amt_inv);
+            let rh = pos.ins().bor(rh, carry);
+            (pos.ins().select(big, rh, rl), pos.ins().select(big, rl, rh))
+        }
+        _ => panic!("Expected shift: {}", pos.func.dfg.display_inst(inst, None)),
+    };
+    pos.func.dfg.replace(inst).iconcat(lo, hi);
+}
@@ -0,0 +1,156 @@
+//! Legalization of SIMD vector instructions.
+//!
+//! These expansions only use instructions on the same vector type, or scalar instructions on its
+//! lanes, so they work on any target that can load and store vectors. Targets with more direct
+//! ways of accessing lanes should provide their own encodings or legalizations.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::condcodes::IntCC;
+use crate::ir::{self, InstBuilder};
+use crate::isa::TargetIsa;
+
+/// Expand a `shuffle` instruction into two `swizzle` instructions.
+///
+/// The indices of the lanes in `y` are offset so that lanes of `x` are out of range when
+/// swizzling `y`, and vice versa.
+pub fn expand_shuffle(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    let (x, y, mask) = match func.dfg[inst] {
+        ir::InstructionData::Ternary {
+            opcode: ir::Opcode::Shuffle,
+            args,
+        } => (args[0], args[1], args[2]),
+        _ => panic!("Expected shuffle: {}", func.dfg.display_inst(inst, None)),
+    };
+    let ty = func.dfg.value_type(mask);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    let lanes = pos.ins().iconst(ty.lane_type(), i64::from(ty.lane_count()));
+    let offset = pos.ins().splat(ty, lanes);
+    let y_mask = pos.ins().isub(mask, offset);
+    let from_x = pos.ins().swizzle(x, mask);
+    let from_y = pos.ins().swizzle(y, y_mask);
+    pos.func.dfg.replace(inst).bor(from_x, from_y);
+}
+
+/// Expand a `splat`, `insertlane`, or `extractlane` instruction by going through a stack slot.
+///
+/// The vector is stored in the stack slot and its lanes are accessed individually with scalar
+/// loads and stores.
+pub fn expand_lane_access(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    let ty = match func.dfg[inst] {
+        ir::InstructionData::Unary {
+            opcode: ir::Opcode::Splat,
+            ..
+        }
+        | ir::InstructionData::InsertLane {
+            opcode: ir::Opcode::Insertlane,
+            ..
+        } => func.dfg.ctrl_typevar(inst),
+        ir::InstructionData::ExtractLane {
+            opcode: ir::Opcode::Extractlane,
+            arg,
+            ..
+        } => func.dfg.value_type(arg),
+        _ => panic!(
+            "Expected splat, insertlane, or extractlane: {}",
+            func.dfg.display_inst(inst, None)
+        ),
+    };
+    debug_assert!(
+        !ty.lane_type().is_bool(),
+        "Can't store boolean lanes: {}",
+        func.dfg.display_inst(inst, None)
+    );
+    let lane_bytes = ty.lane_type().bytes() as i32;
+    let ss = func.create_stack_slot(ir::StackSlotData::new(
+        ir::StackSlotKind::ExplicitSlot,
+        ty.bytes(),
+    ));
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+    match pos.func.dfg[inst] {
+        ir::InstructionData::Unary { arg, .. } => {
+            for lane in 0..i32::from(ty.lane_count()) {
+                pos.ins().stack_store(arg, ss, lane * lane_bytes);
+            }
+            pos.func.dfg.replace(inst).stack_load(ty, ss, 0);
+        }
+        ir::InstructionData::InsertLane { args, lane, .. } => {
+            pos.ins().stack_store(args[0], ss, 0);
+            pos.ins()
+                .stack_store(args[1], ss, i32::from(lane) * lane_bytes);
+            pos.func.dfg.replace(inst).stack_load(ty, ss, 0);
+        }
+        ir::InstructionData::ExtractLane { arg, lane, .. } => {
+            pos.ins().stack_store(arg, ss, 0);
+            pos.func
+                .dfg
+                .replace(inst)
+                .stack_load(ty.lane_type(), ss, i32::from(lane) * lane_bytes);
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Expand a `vany_true` or `vall_true` instruction by testing each lane.
+pub fn expand_vector_test(
+    inst: ir::Inst,
+    func: &mut ir::Function,
+    _cfg: &mut ControlFlowGraph,
+    _isa: &TargetIsa,
+) {
+    let (x, all) = match func.dfg[inst] {
+        ir::InstructionData::Unary {
+            opcode: ir::Opcode::VanyTrue,
+            arg,
+        } => (arg, false),
+        ir::InstructionData::Unary {
+            opcode: ir::Opcode::VallTrue,
+            arg,
+        } => (arg, true),
+        _ => panic!(
+            "Expected vany_true or vall_true: {}",
+            func.dfg.display_inst(inst, None)
+        ),
+    };
+    let ty = func.dfg.value_type(x);
+
+    let mut pos = FuncCursor::new(func).at_inst(inst);
+    pos.use_srcloc(inst);
+
+    // Boolean lanes can't be extracted through memory, so test the equivalent integer mask.
+    let x = if ty.lane_type().is_bool() {
+        let int_ty = ir::Type::int(u16::from(ty.lane_bits()))
+            .and_then(|t| t.by(ty.lane_count()))
+            .expect("Invalid boolean vector");
+        pos.ins().bmask(int_ty, x)
+    } else {
+        x
+    };
+
+    let mut result = None;
+    for lane in 0..ty.lane_count() {
+        let value = pos.ins().extractlane(x, lane as u8);
+        let test = pos.ins().icmp_imm(IntCC::NotEqual, value, 0);
+        result = Some(match result {
+            None => test,
+            Some(acc) if all => pos.ins().band(acc, test),
+            Some(acc) => pos.ins().bor(acc, test),
+        });
+    }
+    let result = result.expect("Vector without lanes");
+    pos.func.dfg.replace(inst).copy(result);
+}
@@ -55,15 +55,24 @@ use hashmap_core::{map as hash_map, HashMap, HashSet};
 #[cfg(feature = "std")]
 use std::collections::{hash_map, HashMap, HashSet};
 
+pub use crate::budget::CompileBudget;
 pub use crate::context::Context;
 pub use crate::legalizer::legalize_function;
 pub use crate::verifier::verify_function;
 pub use crate::write::write_function;
 
+/// The liveness analysis used by the register allocator.
+///
+/// This is under the "testing_hooks" feature, and is only suitable for use by benchmarks.
+#[cfg(feature = "testing_hooks")]
+pub use crate::regalloc::liveness::Liveness;
+
 pub use cranelift_bforest as bforest;
 pub use cranelift_entity as entity;
 
+pub mod alias_analysis;
 pub mod binemit;
+pub mod cache;
 pub mod cfg_printer;
 pub mod cursor;
 pub mod dbg;
@@ -72,8 +81,13 @@ pub mod flowgraph;
 pub mod ir;
 pub mod isa;
 pub mod loop_analysis;
+pub mod osr;
+#[cfg(feature = "parallel")]
+pub mod parallel;
 pub mod print_errors;
+pub mod serialize;
 pub mod settings;
+pub mod stats;
 pub mod timing;
 pub mod verifier;
 pub mod write;
@@ -82,24 +96,33 @@ pub use crate::entity::packed_option;
 
 mod abi;
 mod bitset;
+mod bounds_checks;
+mod budget;
 mod constant_hash;
 mod context;
 mod dce;
+mod dead_ebb_params;
 mod divconst_magic_numbers;
+mod ebb_order;
 mod fx;
+mod inline;
 mod iterators;
 mod legalizer;
 mod licm;
 mod nan_canonicalization;
+mod null_checks;
 mod partition_slice;
 mod postopt;
 mod predicates;
+mod redundant_moves;
 mod ref_slice;
 mod regalloc;
 mod result;
+mod schedule;
 mod scoped_hash_map;
 mod simple_gvn;
 mod simple_preopt;
+mod simplify_cfg;
 mod stack_layout;
 mod topo_order;
 mod unreachable_code;
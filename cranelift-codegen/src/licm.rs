@@ -1,11 +1,12 @@
 //! A Loop Invariant Code Motion optimization pass
 
+use crate::alias_analysis::{can_observe, writes_memory};
 use crate::cursor::{Cursor, EncCursor, FuncCursor};
 use crate::dominator_tree::DominatorTree;
 use crate::entity::{EntityList, ListPool};
 use crate::flowgraph::{BasicBlock, ControlFlowGraph};
 use crate::fx::FxHashSet;
-use crate::ir::{DataFlowGraph, Ebb, Function, Inst, InstBuilder, Layout, Opcode, Type, Value};
+use crate::ir::{Ebb, Function, Inst, InstBuilder, InstructionData, Layout, Opcode, Type, Value};
 use crate::isa::TargetIsa;
 use crate::loop_analysis::{Loop, LoopAnalysis};
 use crate::timing;
@@ -156,9 +157,34 @@ fn trivially_unsafe_for_licm(opcode: Opcode) -> bool {
         || opcode.writes_cpu_flags()
 }
 
+/// Test whether the given load can be moved out of a loop containing the instructions writing to
+/// memory in `loop_stores`.
+///
+/// The load must not be able to trap, since it may not have been executed in the loop, and it
+/// must not observe any of the stores.
+fn is_hoistable_load(inst: Inst, func: &Function, loop_stores: &[Inst]) -> bool {
+    match func.dfg[inst] {
+        InstructionData::Load { flags, .. } if flags.notrap() => loop_stores
+            .iter()
+            .all(|&store| !can_observe(func, inst, store)),
+        _ => false,
+    }
+}
+
 /// Test whether the given instruction is loop-invariant.
-fn is_loop_invariant(inst: Inst, dfg: &DataFlowGraph, loop_values: &FxHashSet<Value>) -> bool {
-    if trivially_unsafe_for_licm(dfg[inst].opcode()) {
+fn is_loop_invariant(
+    inst: Inst,
+    func: &Function,
+    loop_values: &FxHashSet<Value>,
+    loop_stores: &[Inst],
+) -> bool {
+    let dfg = &func.dfg;
+    let opcode = dfg[inst].opcode();
+    if opcode.can_load() {
+        if !is_hoistable_load(inst, func, loop_stores) {
+            return false;
+        }
+    } else if trivially_unsafe_for_licm(opcode) {
         return false;
     }
 
@@ -184,8 +210,20 @@ fn remove_loop_invariant_instructions(
     let mut loop_values: FxHashSet<Value> = FxHashSet();
     let mut invariant_insts: Vec<Inst> = Vec::new();
     let mut pos = FuncCursor::new(func);
+    let ebbs = postorder_ebbs_loop(loop_analysis, cfg, lp);
+
+    // Loads can only be moved if they don't observe the stores in the loop.
+    let mut loop_stores = Vec::new();
+    for &ebb in &ebbs {
+        for inst in pos.func.layout.ebb_insts(ebb) {
+            if writes_memory(pos.func, inst) {
+                loop_stores.push(inst);
+            }
+        }
+    }
+
     // We traverse the loop EBB in reverse post-order.
-    for ebb in postorder_ebbs_loop(loop_analysis, cfg, lp).iter().rev() {
+    for ebb in ebbs.iter().rev() {
         // Arguments of the EBB are loop values
         for val in pos.func.dfg.ebb_params(*ebb) {
             loop_values.insert(*val);
@@ -193,7 +231,7 @@ fn remove_loop_invariant_instructions(
         pos.goto_top(*ebb);
         #[cfg_attr(feature = "cargo-clippy", allow(clippy::block_in_if_condition_stmt))]
         while let Some(inst) = pos.next_inst() {
-            if is_loop_invariant(inst, &pos.func.dfg, &loop_values) {
+            if is_loop_invariant(inst, pos.func, &loop_values, &loop_stores) {
                 // If all the instruction's argument are defined outside the loop
                 // then this instruction is loop-invariant
                 invariant_insts.push(inst);
@@ -66,6 +66,11 @@ impl LoopAnalysis {
         self.loops[lp].header
     }
 
+    /// Returns the innermost loop containing `ebb`, if any.
+    pub fn innermost_loop(&self, ebb: Ebb) -> Option<Loop> {
+        self.ebb_loop_map[ebb].expand()
+    }
+
     /// Return the eventual parent of a loop in the loop tree.
     pub fn loop_parent(&self, lp: Loop) -> Option<Loop> {
         self.loops[lp].parent.expand()
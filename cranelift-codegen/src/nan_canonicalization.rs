@@ -44,6 +44,8 @@ fn is_fp_arith(pos: &mut FuncCursor, inst: Inst) -> bool {
                 || opcode == Opcode::Fdiv
                 || opcode == Opcode::Fmax
                 || opcode == Opcode::Fmin
+                || opcode == Opcode::FmaxPseudo
+                || opcode == Opcode::FminPseudo
                 || opcode == Opcode::Fmul
                 || opcode == Opcode::Fsub
         }
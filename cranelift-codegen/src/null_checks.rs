@@ -0,0 +1,90 @@
+//! Null check elision.
+//!
+//! A null pointer check is a `trapz p, code` instruction, which traps when the pointer `p` is
+//! zero. When it is followed by a `notrap` load or store from `p` at a small offset, the memory
+//! access faults in exactly the same cases, because the first page of the address space is never
+//! mapped. This pass removes the explicit check and records its trap code on the memory access, so
+//! the embedder's signal handler can report the same trap as the check would have.
+//!
+//! An access without the `notrap` flag can already fault for other reasons, like a heap access
+//! out of bounds, and those faults must keep reporting their own trap code. So the check is only
+//! removed when the access faults solely because the pointer is null.
+//!
+//! The check is only removed when nothing between it and the memory access can trap or have side
+//! effects, so it is not observable that the trap happens later.
+
+use crate::alias_analysis::{Base, MemoryAccess};
+use crate::cursor::{Cursor, FuncCursor};
+use crate::ir::{Function, Inst, InstructionData, Opcode, Value};
+use crate::timing;
+
+/// The size of the memory at address 0 which is never mapped.
+const NULL_PAGE_SIZE: i64 = 4096;
+
+/// Replace the null checks in `func` with faulting memory accesses.
+pub fn do_null_check_elision(func: &mut Function) {
+    let _tt = timing::null_checks();
+    let mut pos = FuncCursor::new(func);
+    while let Some(_ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            let (arg, code) = match pos.func.dfg[inst] {
+                InstructionData::CondTrap {
+                    opcode: Opcode::Trapz,
+                    arg,
+                    code,
+                } => (arg, code),
+                _ => continue,
+            };
+            if let Some(access) = find_faulting_access(pos.func, inst, arg) {
+                match pos.func.dfg[access] {
+                    InstructionData::Load { ref mut flags, .. }
+                    | InstructionData::Store { ref mut flags, .. } => flags.clear_notrap(),
+                    _ => panic!("Expected a load or store"),
+                }
+                pos.func.memory_trap_codes[access] = Some(code);
+                pos.remove_inst_and_step_back();
+            }
+        }
+    }
+}
+
+/// Find a `notrap` load or store after the null check `check` which faults only when `ptr` is
+/// zero.
+///
+/// Returns `None` if an instruction which could trap or have side effects comes first.
+fn find_faulting_access(func: &Function, check: Inst, ptr: Value) -> Option<Inst> {
+    let ptr = func.dfg.resolve_aliases(ptr);
+    let mut next = func.layout.next_inst(check);
+    while let Some(inst) = next {
+        if let Some(access) = MemoryAccess::of(func, inst) {
+            if access.base == Base::Value(ptr)
+                && access.offset >= 0
+                && access.offset <= NULL_PAGE_SIZE - i64::from(access.size)
+                && func.dfg[inst]
+                    .memflags()
+                    .map_or(false, |flags| flags.notrap())
+                && func.memory_trap_codes[inst].is_none()
+            {
+                return Some(inst);
+            }
+        }
+        if may_trap_or_have_side_effects(func, inst) {
+            return None;
+        }
+        next = func.layout.next_inst(inst);
+    }
+    None
+}
+
+/// Could `inst` trap, have side effects, or leave the EBB?
+fn may_trap_or_have_side_effects(func: &Function, inst: Inst) -> bool {
+    let data = &func.dfg[inst];
+    let opcode = data.opcode();
+    opcode.is_call()
+        || opcode.is_branch()
+        || opcode.is_terminator()
+        || opcode.can_trap()
+        || opcode.can_store()
+        || opcode.other_side_effects()
+        || (opcode.can_load() && data.memflags().map_or(true, |flags| !flags.notrap()))
+}
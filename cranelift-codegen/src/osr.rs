@@ -0,0 +1,410 @@
+//! On-stack replacement entries.
+//!
+//! A tiering JIT compiler starts running a function in baseline code, and when one of its loops
+//! turns out to be hot, it compiles optimized code and transfers execution into it in the middle
+//! of the loop. This is on-stack replacement (OSR).
+//!
+//! The loop headers where execution can be transferred are marked with `Layout::set_osr_entry`,
+//! written as `ebb3(v1: i32) osr_entry:` in the text format. The marker doesn't affect the
+//! compilation of the function itself. Instead, `make_osr_entry` creates an OSR variant of the
+//! function for one of these EBBs: its entry block is an alternative prologue which loads the
+//! parameters of the EBB from a state buffer and jumps to it. The variant is compiled like any
+//! other function, so it sets up a stack frame of its own, and the baseline code transfers
+//! execution by calling it with the live state of the loop and returning its results.
+//!
+//! The OSR variant takes a pointer to the state buffer followed by the special purpose parameters
+//! of the function, like the VM context, and it has the same return values. The parameters of the
+//! OSR entry EBB are the whole live state: the code reachable from the EBB can't use any other
+//! value defined outside of it, except the special purpose parameters of the function. The layout
+//! of the state buffer is described by `OsrStateLayout`.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::entity::EntitySet;
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::condcodes::IntCC;
+use crate::ir::types::{B1, I8};
+use crate::ir::{
+    AbiParam, ArgumentPurpose, Ebb, EntityMap, Function, InstBuilder, JumpTableData, MemFlags,
+    Signature, Type, Value, ValueDef,
+};
+use crate::timing;
+use failure_derive::Fail;
+use std::vec::Vec;
+
+/// An error found while creating an OSR variant of a function.
+#[derive(Fail, Debug, PartialEq, Eq)]
+pub enum OsrError {
+    /// The EBB isn't marked as an OSR entry.
+    #[fail(display = "{} is not an OSR entry", _0)]
+    NotAnOsrEntry(Ebb),
+
+    /// A parameter of the OSR entry EBB has a type which can't be passed in the state buffer.
+    ///
+    /// Integers, floating point numbers, vectors of them and `b1` can be passed.
+    #[fail(display = "{} can't be passed to an OSR entry", _0)]
+    UnsupportedType(Value),
+
+    /// A value defined outside of the code reachable from the OSR entry EBB is used inside it.
+    ///
+    /// It must be passed as a parameter of the EBB instead.
+    #[fail(display = "{} is live into the code of OSR entry {}", _0, _1)]
+    LiveIn(Value, Ebb),
+}
+
+/// The layout of the state buffer passed to the OSR variant of a function.
+///
+/// The parameters of the OSR entry EBB are stored in order, each at the next offset which is a
+/// multiple of its size, like the fields of a C struct. A `b1` parameter is stored as a byte which
+/// is zero for false. Values are stored in the native byte order of the target.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OsrStateLayout {
+    /// The offset of each parameter of the OSR entry EBB in the buffer, in bytes.
+    pub offsets: Vec<u32>,
+
+    /// The size of the buffer in bytes.
+    pub size: u32,
+
+    /// The alignment of the buffer in bytes.
+    pub align: u32,
+}
+
+impl OsrStateLayout {
+    /// Compute the layout of the state buffer for the OSR entry `ebb` of `func`.
+    pub fn new(func: &Function, ebb: Ebb) -> Result<Self, OsrError> {
+        let mut layout = Self {
+            offsets: Vec::new(),
+            size: 0,
+            align: 1,
+        };
+        for &param in func.dfg.ebb_params(ebb) {
+            let bytes = state_type(func.dfg.value_type(param))
+                .ok_or(OsrError::UnsupportedType(param))?
+                .bytes();
+            let offset = (layout.size + bytes - 1) / bytes * bytes;
+            layout.offsets.push(offset);
+            layout.size = offset + bytes;
+            layout.align = layout.align.max(bytes);
+        }
+        Ok(layout)
+    }
+}
+
+/// Get the type used to store a value of type `ty` in the state buffer, if it can be stored.
+fn state_type(ty: Type) -> Option<Type> {
+    let lane = ty.lane_type();
+    if ty == B1 {
+        Some(I8)
+    } else if lane.is_int() || lane.is_float() {
+        Some(ty)
+    } else {
+        None
+    }
+}
+
+/// Create the OSR variant of `func` entering at `ebb`.
+///
+/// `ebb` must be marked as an OSR entry, and `pointer_type` is the type of the pointer to the
+/// state buffer, which must be aligned as described by `OsrStateLayout`. The variant contains a
+/// copy of the code reachable from `ebb`, and the OSR entry markers are removed from it.
+pub fn make_osr_entry(func: &Function, ebb: Ebb, pointer_type: Type) -> Result<Function, OsrError> {
+    let _tt = timing::osr();
+    if !func.layout.is_osr_entry(ebb) {
+        return Err(OsrError::NotAnOsrEntry(ebb));
+    }
+    let state_layout = OsrStateLayout::new(func, ebb)?;
+    let ebbs = reachable_ebbs(func, ebb);
+
+    // The state pointer comes first, followed by the special purpose parameters.
+    let mut sig = Signature::new(func.signature.call_conv);
+    sig.params.push(AbiParam::new(pointer_type));
+    sig.params.extend(
+        func.signature
+            .params
+            .iter()
+            .filter(|param| param.purpose != ArgumentPurpose::Normal)
+            .cloned(),
+    );
+    sig.returns = func.signature.returns.clone();
+
+    let mut osr_func = Function::with_name_signature(func.name.clone(), sig);
+    let entry = osr_func.dfg.make_ebb();
+    osr_func.layout.append_ebb(entry);
+    let state = osr_func.dfg.append_ebb_param(entry, pointer_type);
+
+    // Map the special purpose parameters of the original entry block to the new ones.
+    let mut map = EntityMap::new();
+    if let Some(old_entry) = func.layout.entry_block() {
+        for (i, param) in func.signature.params.iter().enumerate() {
+            if param.purpose != ArgumentPurpose::Normal {
+                let new_param = osr_func.dfg.append_ebb_param(entry, param.value_type);
+                map.set_value(func.dfg.ebb_params(old_entry)[i], new_param);
+            }
+        }
+    }
+    check_live_ins(func, ebb, &ebbs, &map)?;
+
+    // The alternative prologue loads the state and jumps to the OSR entry.
+    let mut args = Vec::new();
+    let mut pos = FuncCursor::new(&mut osr_func).at_bottom(entry);
+    for (&param, &offset) in func.dfg.ebb_params(ebb).iter().zip(&state_layout.offsets) {
+        let ty = func.dfg.value_type(param);
+        let flags = MemFlags::trusted();
+        let value = pos
+            .ins()
+            .load(state_type(ty).unwrap(), flags, state, offset as i32);
+        args.push(if ty == B1 {
+            pos.ins().icmp_imm(IntCC::NotEqual, value, 0)
+        } else {
+            value
+        });
+    }
+
+    func.clone_ebbs_into(&ebbs, &mut osr_func, None, &mut map);
+    for (jt, data) in func.jump_tables.iter() {
+        // Jump tables branching outside of the copied EBBs are unused, and their destinations
+        // haven't been rewritten.
+        if data.iter().any(|&dest| map.ebb(dest).is_none()) {
+            osr_func.jump_tables[map.jump_table(jt)] = JumpTableData::new();
+        }
+    }
+    for &old_ebb in &ebbs {
+        osr_func
+            .layout
+            .set_osr_entry(map.ebb(old_ebb).unwrap(), false);
+    }
+
+    let target = map.ebb(ebb).unwrap();
+    FuncCursor::new(&mut osr_func)
+        .at_bottom(entry)
+        .ins()
+        .jump(target, &args);
+
+    Ok(osr_func)
+}
+
+/// Get the EBBs of `func` reachable from `ebb`, in layout order.
+fn reachable_ebbs(func: &Function, ebb: Ebb) -> Vec<Ebb> {
+    let cfg = ControlFlowGraph::with_function(func);
+    let mut reachable = EntitySet::new();
+    let mut worklist = vec![ebb];
+    reachable.insert(ebb);
+    while let Some(ebb) = worklist.pop() {
+        for succ in cfg.succ_iter(ebb) {
+            if !reachable.contains(succ) {
+                reachable.insert(succ);
+                worklist.push(succ);
+            }
+        }
+    }
+    func.layout
+        .ebbs()
+        .filter(|&ebb| reachable.contains(ebb))
+        .collect()
+}
+
+/// Check that the code in `ebbs` only uses the values defined in `ebbs` and the ones in `map`.
+fn check_live_ins(
+    func: &Function,
+    osr_entry: Ebb,
+    ebbs: &[Ebb],
+    map: &EntityMap,
+) -> Result<(), OsrError> {
+    let mut defined = EntitySet::new();
+    for &ebb in ebbs {
+        defined.insert(ebb);
+    }
+    for &ebb in ebbs {
+        for inst in func.layout.ebb_insts(ebb) {
+            for &arg in func.dfg.inst_args(inst) {
+                let arg = func.dfg.resolve_aliases(arg);
+                let def_ebb = match func.dfg.value_def(arg) {
+                    ValueDef::Result(def, _) => func.layout.inst_ebb(def),
+                    ValueDef::Param(def, _) => Some(def),
+                };
+                let is_defined = def_ebb.map_or(false, |def_ebb| defined.contains(def_ebb));
+                if !is_defined && map.value(arg).is_none() {
+                    return Err(OsrError::LiveIn(arg, osr_entry));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_osr_entry, OsrError, OsrStateLayout};
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::{B1, F64, I32, I64, I8, IFLAGS};
+    use crate::ir::{AbiParam, ArgumentPurpose, ExternalName, Function, InstBuilder, Signature};
+    use crate::isa::CallConv;
+    use crate::verifier::verify_function;
+    use std::string::ToString;
+
+    // function u0:0(i64 vmctx, i32) -> i32 {
+    // ebb0(v0: i64, v1: i32):
+    //     v2 = iconst.i32 0
+    //     jump ebb1(v1, v2)
+    //
+    // ebb1(v3: i32, v4: i32) osr_entry:
+    //     v5 = iadd v4, v3
+    //     v6 = load.i32 v0
+    //     v7 = iadd_imm v3, -1
+    //     brnz v7, ebb1(v7, v5)
+    //     v8 = iadd v5, v6
+    //     return v8
+    // }
+    fn looping_function() -> Function {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params
+            .push(AbiParam::special(I64, ArgumentPurpose::VMContext));
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), sig);
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        func.layout.set_osr_entry(ebb1, true);
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        let vmctx = pos.func.dfg.append_ebb_param(ebb0, I64);
+        let n = pos.func.dfg.append_ebb_param(ebb0, I32);
+        let zero = pos.ins().iconst(I32, 0);
+        pos.ins().jump(ebb1, &[n, zero]);
+        pos.insert_ebb(ebb1);
+        let i = pos.func.dfg.append_ebb_param(ebb1, I32);
+        let sum = pos.func.dfg.append_ebb_param(ebb1, I32);
+        let sum = pos.ins().iadd(sum, i);
+        let x = pos.ins().load(I32, Default::default(), vmctx, 0);
+        let i = pos.ins().iadd_imm(i, -1);
+        pos.ins().brnz(i, ebb1, &[i, sum]);
+        let ret = pos.ins().iadd(sum, x);
+        pos.ins().return_(&[ret]);
+        func
+    }
+
+    #[test]
+    fn osr_entry() {
+        let func = looping_function();
+        let ebb1 = func.layout.ebbs().nth(1).unwrap();
+        let osr_func = make_osr_entry(&func, ebb1, I64).unwrap();
+        verify_function(&osr_func, None).unwrap();
+        assert_eq!(
+            osr_func.to_string(),
+            "function u0:0(i64, i64 vmctx) -> i32 system_v {
+ebb0(v0: i64, v1: i64):
+    v2 = load.i32 notrap aligned v0
+    v3 = load.i32 notrap aligned v0+4
+    jump ebb1(v2, v3)
+
+ebb1(v4: i32, v5: i32):
+    v6 = iadd v5, v4
+    v7 = load.i32 v1
+    v8 = iadd_imm v4, -1
+    brnz v8, ebb1(v8, v6)
+    v9 = iadd v6, v7
+    return v9
+}
+"
+        );
+
+        let ebb0 = func.layout.entry_block().unwrap();
+        assert_eq!(
+            make_osr_entry(&func, ebb0, I64),
+            Err(OsrError::NotAnOsrEntry(ebb0))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "x86")]
+    fn compile_osr_entry() {
+        use crate::isa;
+        use crate::settings::{self, Flags};
+        use crate::Context;
+        use target_lexicon::triple;
+
+        let isa = isa::lookup(triple!("x86_64"))
+            .unwrap()
+            .finish(Flags::new(settings::builder()));
+
+        let func = looping_function();
+        let ebb1 = func.layout.ebbs().nth(1).unwrap();
+        let osr_func = make_osr_entry(&func, ebb1, isa.pointer_type()).unwrap();
+        let mut ctx = Context::for_function(osr_func);
+        let size = ctx.compile(&*isa).unwrap();
+
+        // The alternative prologue comes first, followed by the loop.
+        let offsets = ctx.code_offsets();
+        let entry = ctx.func.layout.entry_block().unwrap();
+        let header = ctx.func.layout.next_ebb(entry).unwrap();
+        let header_offset = offsets.ebb_offset(header).unwrap();
+        assert!(header_offset > 0 && header_offset < size);
+    }
+
+    #[test]
+    fn live_in() {
+        let mut func = looping_function();
+        let ebb0 = func.layout.entry_block().unwrap();
+        let ebb1 = func.layout.ebbs().nth(1).unwrap();
+        // Use the normal parameter `v1` in the loop.
+        let v1 = func.dfg.ebb_params(ebb0)[1];
+        let last = func.layout.last_inst(ebb1).unwrap();
+        func.dfg.inst_args_mut(last)[0] = v1;
+        assert_eq!(
+            make_osr_entry(&func, ebb1, I64),
+            Err(OsrError::LiveIn(v1, ebb1))
+        );
+    }
+
+    #[test]
+    fn state_layout() {
+        let mut func = Function::new();
+        let ebb = func.dfg.make_ebb();
+        func.dfg.append_ebb_param(ebb, I8);
+        func.dfg.append_ebb_param(ebb, F64);
+        func.dfg.append_ebb_param(ebb, B1);
+        func.dfg.append_ebb_param(ebb, I32);
+        assert_eq!(
+            OsrStateLayout::new(&func, ebb),
+            Ok(OsrStateLayout {
+                offsets: vec![0, 8, 16, 20],
+                size: 24,
+                align: 8,
+            })
+        );
+
+        let flags = func.dfg.append_ebb_param(ebb, IFLAGS);
+        assert_eq!(
+            OsrStateLayout::new(&func, ebb),
+            Err(OsrError::UnsupportedType(flags))
+        );
+    }
+
+    #[test]
+    fn bool_state() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.returns.push(AbiParam::new(B1));
+        let mut func = Function::with_name_signature(ExternalName::user(0, 0), sig);
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        func.layout.set_osr_entry(ebb1, true);
+        let mut pos = FuncCursor::new(&mut func);
+        pos.insert_ebb(ebb0);
+        let b = pos.ins().bconst(B1, true);
+        pos.ins().jump(ebb1, &[b]);
+        pos.insert_ebb(ebb1);
+        let b = pos.func.dfg.append_ebb_param(ebb1, B1);
+        let b = pos.ins().bnot(b);
+        pos.ins().brz(b, ebb1, &[b]);
+        pos.ins().return_(&[b]);
+
+        let osr_func = make_osr_entry(&func, ebb1, I64).unwrap();
+        verify_function(&osr_func, None).unwrap();
+        let entry = osr_func.layout.entry_block().unwrap();
+        let load = osr_func.layout.first_inst(entry).unwrap();
+        let icmp = osr_func.layout.next_inst(load).unwrap();
+        assert_eq!(
+            osr_func.dfg.display_inst(icmp, None).to_string(),
+            "v2 = icmp_imm ne v1, 0"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+//! Parallel processing of independent work items.
+//!
+//! Functions are compiled and translated independently of each other, so embedders can spread
+//! the work for a whole module over several threads. This module is only available with the
+//! "parallel" feature.
+
+use crossbeam_utils::thread;
+use std::sync::Mutex;
+use std::vec::Vec;
+
+/// Apply `f` to all the `items` using up to `num_threads` threads, and return the results in the
+/// order of `items`.
+///
+/// Each thread creates its own state by calling `init` once, and then repeatedly takes the next
+/// item until none are left. `f` is passed the state of the thread, the position of the item in
+/// `items`, and the item itself.
+///
+/// If `f` panics in one of the threads, the panic is propagated once all threads have finished.
+pub fn map_parallel<I, S, R, G, F>(items: I, num_threads: usize, init: G, f: F) -> Vec<R>
+where
+    I: Iterator + Send,
+    I::Item: Send,
+    R: Send,
+    G: Fn() -> S + Sync,
+    F: Fn(&mut S, usize, I::Item) -> R + Sync,
+{
+    let work = &Mutex::new(items.enumerate());
+    let init = &init;
+    let f = &f;
+    let mut results = thread::scope(|scope| {
+        let workers: Vec<_> = (0..num_threads.max(1))
+            .map(|_| {
+                scope.spawn(move |_| {
+                    let mut state = init();
+                    let mut done = Vec::new();
+                    loop {
+                        // Release the lock before processing the item.
+                        let next = work.lock().unwrap().next();
+                        match next {
+                            Some((pos, item)) => done.push((pos, f(&mut state, pos, item))),
+                            None => return done,
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("worker thread panicked"))
+            .collect::<Vec<_>>()
+    })
+    .expect("worker thread panicked");
+    results.sort_by_key(|&(pos, _)| pos);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::map_parallel;
+    use std::vec::Vec;
+
+    #[test]
+    fn ordered_results() {
+        let items: Vec<u32> = (0..100).collect();
+        for &num_threads in &[0, 1, 4] {
+            let results = map_parallel(
+                items.iter(),
+                num_threads,
+                || (),
+                |_, pos, &item| {
+                    assert_eq!(pos as u32, item);
+                    item * 2
+                },
+            );
+            assert_eq!(results, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn mutable_items() {
+        let mut items = vec![1, 2, 3];
+        let results = map_parallel(
+            items.iter_mut(),
+            2,
+            || (),
+            |_, _, item| {
+                *item += 1;
+                *item
+            },
+        );
+        assert_eq!(results, [2, 3, 4]);
+        assert_eq!(items, [2, 3, 4]);
+    }
+}
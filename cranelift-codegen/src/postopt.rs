@@ -8,7 +8,7 @@ use crate::ir::dfg::ValueDef;
 use crate::ir::immediates::{Imm64, Offset32};
 use crate::ir::instructions::{Opcode, ValueList};
 use crate::ir::{Ebb, Function, Inst, InstBuilder, InstructionData, MemFlags, Type, Value};
-use crate::isa::TargetIsa;
+use crate::isa::{apply_peephole_rules, TargetIsa};
 use crate::timing;
 
 /// Information collected about a compare+branch sequence.
@@ -41,12 +41,11 @@ enum CmpBrKind {
 ///
 /// For example, optimize icmp/fcmp brz/brnz sequences into ifcmp/ffcmp brif/brff
 /// sequences.
-fn optimize_cpu_flags(
-    pos: &mut EncCursor,
-    inst: Inst,
-    last_flags_clobber: Option<Inst>,
-    isa: &TargetIsa,
-) {
+///
+/// Returns true if the instructions were rewritten.
+#[cfg_attr(not(feature = "x86"), allow(dead_code))]
+pub(crate) fn fuse_compare_branch(pos: &mut EncCursor, inst: Inst) -> bool {
+    let isa = pos.isa;
     // Look for compare and branch patterns.
     // This code could be considerably simplified with non-lexical lifetimes.
     let info = match pos.func.dfg[inst] {
@@ -109,20 +108,20 @@ fn optimize_cpu_flags(
                             arg: cmp_args[1],
                         },
                     },
-                    _ => return,
+                    _ => return false,
                 }
             } else {
-                return;
+                return false;
             }
         }
         // TODO: trapif, trueif, selectif, and their ff counterparts.
-        _ => return,
+        _ => return false,
     };
 
     // If any instructions clobber the flags between the comparison and the branch,
     // don't optimize them.
-    if last_flags_clobber != Some(info.cmp_inst) {
-        return;
+    if last_flags_clobber(pos, inst) != Some(info.cmp_inst) {
+        return false;
     }
 
     // We found a compare+branch pattern. Transform it to use flags.
@@ -167,6 +166,23 @@ fn optimize_cpu_flags(
     debug_assert!(ok);
     let ok = pos.func.update_encoding(info.br_inst, isa).is_ok();
     debug_assert!(ok);
+    true
+}
+
+/// Get the last instruction before `inst` in its EBB that clobbers the CPU flags.
+fn last_flags_clobber(pos: &EncCursor, inst: Inst) -> Option<Inst> {
+    let encinfo = pos.isa.encoding_info();
+    let mut prev = pos.func.layout.prev_inst(inst);
+    while let Some(prev_inst) = prev {
+        if encinfo
+            .operand_constraints(pos.func.encodings[prev_inst])
+            .map_or(false, |constraints| constraints.clobbers_flags)
+        {
+            break;
+        }
+        prev = pos.func.layout.prev_inst(prev_inst);
+    }
+    prev
 }
 
 struct MemOpInfo {
@@ -178,7 +194,81 @@ struct MemOpInfo {
     offset: Offset32,
 }
 
-fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa) {
+/// Get the index and scale if `value` is an index shifted by an amount that an address mode can
+/// scale by.
+fn scaled_index(func: &Function, value: Value) -> Option<(Value, u8)> {
+    if let ValueDef::Result(inst, _) = func.dfg.value_def(value) {
+        if let InstructionData::BinaryImm {
+            opcode: Opcode::IshlImm,
+            arg,
+            imm,
+        } = func.dfg[inst]
+        {
+            let shift: i64 = imm.into();
+            if shift >= 1 && shift <= 3 {
+                return Some((arg, 1 << shift));
+            }
+        }
+    }
+    None
+}
+
+/// Try to fold the address `args[0] + args[1]` of a load or store into a scaled index address
+/// mode, when one of the operands is a shifted index.
+///
+/// Returns true if the instruction was rewritten.
+fn optimize_scaled_address(
+    pos: &mut EncCursor,
+    inst: Inst,
+    info: &MemOpInfo,
+    args: [Value; 2],
+    isa: &TargetIsa,
+) -> bool {
+    let (base, index, scale) = match (
+        scaled_index(&pos.func, args[1]),
+        scaled_index(&pos.func, args[0]),
+    ) {
+        (Some((index, scale)), _) => (args[0], index, scale),
+        (None, Some((index, scale))) => (args[1], index, scale),
+        (None, None) => return false,
+    };
+
+    let data = match info.opcode {
+        Opcode::Load => InstructionData::LoadScaled {
+            opcode: Opcode::X86LoadScaled,
+            flags: info.flags,
+            args: [base, index],
+            imm: scale,
+            offset: info.offset,
+        },
+        Opcode::Store => InstructionData::StoreScaled {
+            opcode: Opcode::X86StoreScaled,
+            flags: info.flags,
+            args: [info.st_arg.unwrap(), base, index],
+            imm: scale,
+            offset: info.offset,
+        },
+        _ => return false,
+    };
+
+    // Only targets with scaled index address modes can encode the new instruction.
+    match isa.encode(&pos.func, &data, info.itype) {
+        Ok(encoding) => {
+            pos.func.dfg[inst] = data;
+            pos.func.encodings[inst] = encoding;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Fold the address computation of a load or store into a complex or scaled address mode, or into
+/// its offset.
+///
+/// Returns true if the instruction was rewritten.
+#[cfg_attr(not(feature = "x86"), allow(dead_code))]
+pub(crate) fn fold_complex_address(pos: &mut EncCursor, inst: Inst) -> bool {
+    let isa = pos.isa;
     // Look for simple loads and stores we can optimize.
     let info = match pos.func.dfg[inst] {
         InstructionData::Load {
@@ -207,7 +297,7 @@ fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa)
             flags,
             offset,
         },
-        _ => return,
+        _ => return false,
     };
 
     // Examine the instruction that defines the address operand.
@@ -217,6 +307,8 @@ fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa)
                 opcode: Opcode::Iadd,
                 args,
             } => match info.opcode {
+                // Operand is an iadd of a shifted index. Fold it into a scaled address mode.
+                _ if optimize_scaled_address(pos, inst, &info, args, isa) => return true,
                 // Operand is an iadd. Fold it into a memory address with a complex address mode.
                 Opcode::Load => {
                     pos.func.dfg.replace(inst).load_complex(
@@ -302,7 +394,8 @@ fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa)
                         info.offset,
                     );
                 }
-                _ => panic!("Unsupported load or store opcode"),
+                // Other loads and stores, such as the x86 MOVBE ones, have no complex form.
+                _ => return false,
             },
             InstructionData::BinaryImm {
                 opcode: Opcode::IaddImm,
@@ -320,7 +413,7 @@ fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa)
                         *offset = imm;
                     } else {
                         // Overflow.
-                        return;
+                        return false;
                     }
                 }
                 InstructionData::Store {
@@ -333,23 +426,235 @@ fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa)
                         *offset = imm;
                     } else {
                         // Overflow.
-                        return;
+                        return false;
                     }
                 }
                 _ => panic!(),
             },
             _ => {
                 // Address value is defined by some other kind of instruction.
-                return;
+                return false;
             }
         }
     } else {
         // Address value is not the result of an instruction.
-        return;
+        return false;
     }
 
     let ok = pos.func.update_encoding(inst, isa).is_ok();
     debug_assert!(ok);
+    true
+}
+
+//----------------------------------------------------------------------
+//
+// Narrow operation folding.
+
+/// Get the narrow value that `value` was extended from, if it has type `ty`.
+fn extended_from(func: &Function, value: Value, ty: Type) -> Option<Value> {
+    let inst = match func.dfg.value_def(value) {
+        ValueDef::Result(inst, _) => inst,
+        ValueDef::Param(_, _) => return None,
+    };
+    match func.dfg[inst] {
+        InstructionData::Unary {
+            opcode: Opcode::Uextend,
+            arg,
+        }
+        | InstructionData::Unary {
+            opcode: Opcode::Sextend,
+            arg,
+        } if func.dfg.value_type(arg) == ty => Some(arg),
+        _ => None,
+    }
+}
+
+/// Fold an `ireduce` of a wide operation on extended values into a narrow operation.
+///
+/// The Wasm translator produces sequences like this when it mixes 32-bit and 64-bit arithmetic:
+///
+///     v2 = uextend.i64 v0
+///     v3 = uextend.i64 v1
+///     v4 = iadd v2, v3
+///     v5 = ireduce.i32 v4
+///
+/// The low bits of the result of `iadd`, `isub`, `imul`, and the bitwise operations only depend on
+/// the low bits of their operands, so `v5` can be computed as `iadd v0, v1` instead. The wide
+/// instructions are left for DCE to clean up if they have no other uses.
+///
+/// Returns true if `inst` was removed.
+fn fold_narrow_ops(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa) -> bool {
+    let arg = match pos.func.dfg[inst] {
+        InstructionData::Unary {
+            opcode: Opcode::Ireduce,
+            arg,
+        } => arg,
+        _ => return false,
+    };
+    let ty = pos.func.dfg.ctrl_typevar(inst);
+
+    // Reducing an extended value gives back the original value.
+    if let Some(x) = extended_from(&pos.func, arg, ty) {
+        let result = pos.func.dfg.first_result(inst);
+        pos.func.dfg.clear_results(inst);
+        pos.func.dfg.change_to_alias(result, x);
+        pos.remove_inst_and_step_back();
+        return true;
+    }
+
+    let wide_inst = match pos.func.dfg.value_def(arg) {
+        ValueDef::Result(wide_inst, _) => wide_inst,
+        ValueDef::Param(_, _) => return false,
+    };
+
+    let narrow_data = match pos.func.dfg[wide_inst] {
+        InstructionData::Binary { opcode, args } => match opcode {
+            Opcode::Iadd
+            | Opcode::Isub
+            | Opcode::Imul
+            | Opcode::Band
+            | Opcode::Bor
+            | Opcode::Bxor
+            | Opcode::BandNot
+            | Opcode::BorNot
+            | Opcode::BxorNot => {
+                match (
+                    extended_from(&pos.func, args[0], ty),
+                    extended_from(&pos.func, args[1], ty),
+                ) {
+                    (Some(x), Some(y)) => InstructionData::Binary {
+                        opcode,
+                        args: [x, y],
+                    },
+                    _ => return false,
+                }
+            }
+            _ => return false,
+        },
+        InstructionData::BinaryImm { opcode, arg, imm } => match opcode {
+            Opcode::IaddImm
+            | Opcode::IrsubImm
+            | Opcode::ImulImm
+            | Opcode::BandImm
+            | Opcode::BorImm
+            | Opcode::BxorImm => match extended_from(&pos.func, arg, ty) {
+                Some(x) => InstructionData::BinaryImm {
+                    opcode,
+                    arg: x,
+                    imm,
+                },
+                None => return false,
+            },
+            _ => return false,
+        },
+        _ => return false,
+    };
+
+    // Only fold if the target can encode the narrow operation.
+    if let Ok(encoding) = isa.encode(&pos.func, &narrow_data, ty) {
+        pos.func.dfg[inst] = narrow_data;
+        pos.func.encodings[inst] = encoding;
+    }
+    false
+}
+
+//----------------------------------------------------------------------
+//
+// Immediate operand folding.
+
+/// Get the immediate value of `value` if it is defined by an `iconst`.
+fn iconst_value(func: &Function, value: Value) -> Option<Imm64> {
+    if let ValueDef::Result(inst, _) = func.dfg.value_def(value) {
+        if let InstructionData::UnaryImm {
+            opcode: Opcode::Iconst,
+            imm,
+        } = func.dfg[inst]
+        {
+            return Some(imm);
+        }
+    }
+    None
+}
+
+/// Fold an `iconst` operand of a binary operation or an integer comparison into the immediate
+/// field of the `_imm` variant of the instruction, when the target can encode the immediate.
+///
+/// The pre-opt pass folds constants before legalization, but it only sees constants in the
+/// second operand, and it doesn't see the constants created by legalization. Constants that
+/// don't fit the target's immediate fields are left in registers.
+fn fold_immediate_operands(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa) {
+    let data = match pos.func.dfg[inst] {
+        InstructionData::Binary { opcode, args } => {
+            if let Some(imm) = iconst_value(&pos.func, args[1]) {
+                let (opcode, imm) = match opcode {
+                    Opcode::Iadd => (Opcode::IaddImm, imm),
+                    Opcode::Isub => (Opcode::IaddImm, imm.wrapping_neg()),
+                    Opcode::Imul => (Opcode::ImulImm, imm),
+                    Opcode::Band => (Opcode::BandImm, imm),
+                    Opcode::Bor => (Opcode::BorImm, imm),
+                    Opcode::Bxor => (Opcode::BxorImm, imm),
+                    Opcode::Rotl => (Opcode::RotlImm, imm),
+                    Opcode::Rotr => (Opcode::RotrImm, imm),
+                    Opcode::Ishl => (Opcode::IshlImm, imm),
+                    Opcode::Ushr => (Opcode::UshrImm, imm),
+                    Opcode::Sshr => (Opcode::SshrImm, imm),
+                    _ => return,
+                };
+                InstructionData::BinaryImm {
+                    opcode,
+                    arg: args[0],
+                    imm,
+                }
+            } else if let Some(imm) = iconst_value(&pos.func, args[0]) {
+                let opcode = match opcode {
+                    Opcode::Iadd => Opcode::IaddImm,
+                    Opcode::Isub => Opcode::IrsubImm,
+                    Opcode::Imul => Opcode::ImulImm,
+                    Opcode::Band => Opcode::BandImm,
+                    Opcode::Bor => Opcode::BorImm,
+                    Opcode::Bxor => Opcode::BxorImm,
+                    _ => return,
+                };
+                InstructionData::BinaryImm {
+                    opcode,
+                    arg: args[1],
+                    imm,
+                }
+            } else {
+                return;
+            }
+        }
+        InstructionData::IntCompare {
+            opcode: Opcode::Icmp,
+            cond,
+            args,
+        } => {
+            if let Some(imm) = iconst_value(&pos.func, args[1]) {
+                InstructionData::IntCompareImm {
+                    opcode: Opcode::IcmpImm,
+                    cond,
+                    arg: args[0],
+                    imm,
+                }
+            } else if let Some(imm) = iconst_value(&pos.func, args[0]) {
+                InstructionData::IntCompareImm {
+                    opcode: Opcode::IcmpImm,
+                    cond: cond.reverse(),
+                    arg: args[1],
+                    imm,
+                }
+            } else {
+                return;
+            }
+        }
+        _ => return,
+    };
+
+    let ctrl_type = pos.func.dfg.ctrl_typevar(inst);
+    if let Ok(encoding) = isa.encode(&pos.func, &data, ctrl_type) {
+        pos.func.dfg[inst] = data;
+        pos.func.encodings[inst] = encoding;
+    }
 }
 
 //----------------------------------------------------------------------
@@ -358,28 +663,18 @@ fn optimize_complex_addresses(pos: &mut EncCursor, inst: Inst, isa: &TargetIsa)
 
 pub fn do_postopt(func: &mut Function, isa: &TargetIsa) {
     let _tt = timing::postopt();
+    let rules = isa.peephole_rules();
     let mut pos = EncCursor::new(func, isa);
     while let Some(_ebb) = pos.next_ebb() {
-        let mut last_flags_clobber = None;
         while let Some(inst) = pos.next_inst() {
-            if isa.uses_cpu_flags() {
-                // Optimize instructions to make use of flags.
-                optimize_cpu_flags(&mut pos, inst, last_flags_clobber, isa);
-
-                // Track the most recent seen instruction that clobbers the flags.
-                if let Some(constraints) = isa
-                    .encoding_info()
-                    .operand_constraints(pos.func.encodings[inst])
-                {
-                    if constraints.clobbers_flags {
-                        last_flags_clobber = Some(inst)
-                    }
-                }
+            if fold_narrow_ops(&mut pos, inst, isa) {
+                continue;
             }
 
-            if isa.uses_complex_addresses() {
-                optimize_complex_addresses(&mut pos, inst, isa);
-            }
+            fold_immediate_operands(&mut pos, inst, isa);
+
+            // Apply the rewrites specific to the target.
+            apply_peephole_rules(&mut pos, inst, rules);
         }
     }
 }
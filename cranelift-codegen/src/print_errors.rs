@@ -218,10 +218,18 @@ fn print_error(w: &mut Write, err: VerifierError) -> fmt::Result {
 }
 
 /// Pretty-print a Cranelift error.
+///
+/// Errors about an instruction are shown next to the instruction in the function.
 pub fn pretty_error(func: &ir::Function, isa: Option<&TargetIsa>, err: CodegenError) -> String {
-    if let CodegenError::Verifier(e) = err {
-        pretty_verifier_error(func, isa, None, e)
-    } else {
-        err.to_string()
+    match err {
+        CodegenError::Verifier(e) => pretty_verifier_error(func, isa, None, e),
+        CodegenError::Unsupported { inst, message, .. } => {
+            let error = VerifierError {
+                location: inst.into(),
+                message,
+            };
+            pretty_verifier_error(func, isa, None, VerifierErrors(vec![error]))
+        }
+        _ => err.to_string(),
     }
 }
@@ -0,0 +1,55 @@
+//! Redundant move removal.
+//!
+//! The register allocator inserts `copy` and `regmove` instructions to satisfy constraints, and
+//! some of them end up moving a value to the register it already occupies. This pass runs after
+//! register allocation and deletes those no-op moves before emission.
+
+use crate::cursor::{Cursor, FuncCursor};
+use crate::ir::{Function, InstructionData, Opcode, ValueLoc};
+use crate::isa::TargetIsa;
+use crate::regalloc::RegDiversions;
+use crate::timing;
+use log::debug;
+
+/// Delete the no-op moves in `func`.
+pub fn remove_redundant_moves(func: &mut Function, isa: &TargetIsa) {
+    let _tt = timing::redundant_moves();
+    let mut divert = RegDiversions::new();
+
+    let mut pos = FuncCursor::new(func);
+    while let Some(_ebb) = pos.next_ebb() {
+        divert.clear();
+        while let Some(inst) = pos.next_inst() {
+            match pos.func.dfg[inst] {
+                InstructionData::RegMove {
+                    opcode: Opcode::Regmove,
+                    src,
+                    dst,
+                    ..
+                } if src == dst => {
+                    debug!("Removing {}", pos.func.dfg.display_inst(inst, isa));
+                    pos.remove_inst_and_step_back();
+                }
+                InstructionData::Unary {
+                    opcode: Opcode::Copy,
+                    arg,
+                } => {
+                    let dest = pos.func.dfg.first_result(inst);
+                    let src = divert.get(arg, &pos.func.locations);
+                    match (src, pos.func.locations[dest]) {
+                        (ValueLoc::Reg(src), ValueLoc::Reg(dst)) if src == dst => {
+                            // Uses of `dest` keep their own location which is the same register,
+                            // so the alias doesn't affect the encoding of later instructions.
+                            debug!("Removing {}", pos.func.dfg.display_inst(inst, isa));
+                            pos.func.dfg.clear_results(inst);
+                            pos.func.dfg.change_to_alias(dest, arg);
+                            pos.remove_inst_and_step_back();
+                        }
+                        _ => {}
+                    }
+                }
+                _ => divert.apply(&pos.func.dfg[inst]),
+            }
+        }
+    }
+}
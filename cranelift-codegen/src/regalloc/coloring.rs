@@ -44,7 +44,7 @@
 
 use crate::cursor::{Cursor, EncCursor};
 use crate::dominator_tree::DominatorTree;
-use crate::ir::{AbiParam, ArgumentLoc, InstBuilder, ValueDef};
+use crate::ir::{AbiParam, ArgumentLoc, InstBuilder, InstructionData, Opcode, ValueDef};
 use crate::ir::{Ebb, Function, Inst, Layout, SigRef, Value, ValueLoc};
 use crate::isa::{regs_overlap, RegClass, RegInfo, RegUnit};
 use crate::isa::{ConstraintKind, EncInfo, OperandConstraint, RecipeConstraints, TargetIsa};
@@ -59,6 +59,7 @@ use crate::regalloc::RegDiversions;
 use crate::timing;
 use core::mem;
 use log::debug;
+use std::vec::Vec;
 
 /// Data structures for the coloring pass.
 ///
@@ -324,6 +325,12 @@ impl<'a> Context<'a> {
                 &self.reginfo,
                 &self.divert,
             );
+            // A call with a landing pad like `try_call` is also a branch without EBB arguments.
+            // Globally live values must be in their global registers when entering the landing
+            // pad.
+            if self.cur.func.dfg[inst].opcode().is_branch() {
+                self.undivert_regs(|lr, _| !lr.is_local());
+            }
         } else if self.cur.func.dfg[inst].opcode().is_return() {
             program_input_abi(
                 &mut self.solver,
@@ -655,7 +662,11 @@ impl<'a> Context<'a> {
     where
         Pred: FnMut(&LiveRange, LiveRangeContext<Layout>) -> bool,
     {
-        for (&value, rdiv) in self.divert.iter() {
+        // Undo the diversions in value order. The iteration order of the hash map depends on the
+        // pointer width of the host, and the order of the reassignments affects the solution.
+        let mut diversions: Vec<_> = self.divert.iter().map(|(&v, &d)| (v, d)).collect();
+        diversions.sort_unstable_by_key(|&(value, _)| value);
+        for (value, rdiv) in diversions {
             let lr = self
                 .liveness
                 .get(value)
@@ -809,6 +820,18 @@ impl<'a> Context<'a> {
                 | ConstraintKind::Stack => continue,
                 ConstraintKind::Reg => {
                     self.solver.add_def(lv.value, op.regclass, !lv.is_local);
+
+                    // Bias the result of a copy towards the register holding its argument. When
+                    // the argument is killed, the copy becomes a no-op that can be deleted.
+                    if let InstructionData::Unary {
+                        opcode: Opcode::Copy,
+                        arg,
+                    } = self.cur.func.dfg[inst]
+                    {
+                        if let ValueLoc::Reg(reg) = self.divert.get(arg, &self.cur.func.locations) {
+                            self.solver.add_hint(lv.value, reg);
+                        }
+                    }
                 }
                 ConstraintKind::Tied(num) => {
                     // Find the input operand we're tied to.
@@ -923,7 +946,7 @@ impl<'a> Context<'a> {
         }
     }
 
-    /// Emit `regmove` instructions as needed to move the live registers into place before the
+    /// Emit `regmove` and `regswap` instructions as needed to move the live registers into place before the
     /// instruction. Also update `self.divert` accordingly.
     ///
     /// The `self.cur` cursor is expected to point at the instruction. The register moves are
@@ -933,7 +956,19 @@ impl<'a> Context<'a> {
     fn shuffle_inputs(&mut self, regs: &mut RegisterSet) {
         use crate::regalloc::solver::Move::*;
 
-        let spills = self.solver.schedule_moves(regs);
+        // Register cycles can be broken with a `regswap` when the ISA can encode one for the two
+        // values.
+        let isa = self.cur.isa;
+        let func = &*self.cur.func;
+        let spills = self.solver.schedule_moves(regs, |a, b| {
+            let swap = InstructionData::RegSwap {
+                opcode: Opcode::Regswap,
+                args: [a, b],
+                src: 0,
+                dst: 0,
+            };
+            isa.encode(func, &swap, func.dfg.value_type(a)).is_ok()
+        });
 
         // The move operations returned by `schedule_moves` refer to emergency spill slots by
         // consecutive indexes starting from 0. Map these to real stack slots.
@@ -950,6 +985,16 @@ impl<'a> Context<'a> {
                     self.divert.regmove(value, from, to);
                     self.cur.ins().regmove(value, from, to);
                 }
+                Swap {
+                    value,
+                    other,
+                    from,
+                    to,
+                    ..
+                } => {
+                    self.divert.regswap(value, other, from, to);
+                    self.cur.ins().regswap(value, other, from, to);
+                }
                 Spill {
                     value,
                     from,
@@ -13,9 +13,13 @@ use crate::regalloc::coloring::Coloring;
 use crate::regalloc::live_value_tracker::LiveValueTracker;
 use crate::regalloc::liveness::Liveness;
 use crate::regalloc::reload::Reload;
+use crate::regalloc::safepoint::emit_stackmaps;
+use crate::regalloc::spill_slots::SpillSlots;
 use crate::regalloc::spilling::Spilling;
+use crate::regalloc::splitting::Splitting;
 use crate::regalloc::virtregs::VirtRegs;
 use crate::result::CodegenResult;
+use crate::stats::PassStats;
 use crate::timing;
 use crate::topo_order::TopoOrder;
 use crate::verifier::{
@@ -26,10 +30,12 @@ use crate::verifier::{
 pub struct Context {
     liveness: Liveness,
     virtregs: VirtRegs,
+    splitting: Splitting,
     coalescing: Coalescing,
     topo: TopoOrder,
     tracker: LiveValueTracker,
     spilling: Spilling,
+    spill_slots: SpillSlots,
     reload: Reload,
     coloring: Coloring,
 }
@@ -43,10 +49,12 @@ impl Context {
         Self {
             liveness: Liveness::new(),
             virtregs: VirtRegs::new(),
+            splitting: Splitting::new(),
             coalescing: Coalescing::new(),
             topo: TopoOrder::new(),
             tracker: LiveValueTracker::new(),
             spilling: Spilling::new(),
+            spill_slots: SpillSlots::new(),
             reload: Reload::new(),
             coloring: Coloring::new(),
         }
@@ -56,10 +64,12 @@ impl Context {
     pub fn clear(&mut self) {
         self.liveness.clear();
         self.virtregs.clear();
+        self.splitting.clear();
         self.coalescing.clear();
         self.topo.clear();
         self.tracker.clear();
         self.spilling.clear();
+        self.spill_slots.clear();
         self.reload.clear();
         self.coloring.clear();
     }
@@ -68,12 +78,43 @@ impl Context {
     ///
     /// After register allocation, all values in `func` have been assigned to a register or stack
     /// location that is consistent with instruction encoding constraints.
+    ///
+    /// The coalesced values and the inserted spills and fills are counted in `stats`.
     pub fn run(
         &mut self,
         isa: &TargetIsa,
         func: &mut Function,
         cfg: &ControlFlowGraph,
         domtree: &mut DominatorTree,
+        stats: &mut PassStats,
+    ) -> CodegenResult<()> {
+        self.allocate(isa, func, cfg, domtree, stats, false)
+    }
+
+    /// Allocate registers in `func` as fast as possible.
+    ///
+    /// This works like `run`, but it skips the passes that only improve the generated code: live
+    /// ranges are not split around loops, and every spilled virtual register gets its own spill
+    /// slot. It is used when a function exceeds its compile budget.
+    pub fn run_baseline(
+        &mut self,
+        isa: &TargetIsa,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &mut DominatorTree,
+        stats: &mut PassStats,
+    ) -> CodegenResult<()> {
+        self.allocate(isa, func, cfg, domtree, stats, true)
+    }
+
+    fn allocate(
+        &mut self,
+        isa: &TargetIsa,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &mut DominatorTree,
+        stats: &mut PassStats,
+        baseline: bool,
     ) -> CodegenResult<()> {
         let _tt = timing::regalloc();
         debug_assert!(domtree.is_valid());
@@ -90,6 +131,19 @@ impl Context {
         // Pass: Liveness analysis.
         self.liveness.compute(isa, func, cfg);
 
+        // Pass: Live range splitting.
+        if !baseline {
+            self.splitting.run(
+                isa,
+                func,
+                cfg,
+                domtree,
+                &mut self.liveness,
+                &mut self.topo,
+                &mut self.tracker,
+            );
+        }
+
         if isa.flags().enable_verifier() {
             let ok = verify_liveness(isa, func, cfg, &self.liveness, &mut errors).is_ok();
 
@@ -107,6 +161,11 @@ impl Context {
             &mut self.liveness,
             &mut self.virtregs,
         );
+        stats.values_coalesced += self
+            .virtregs
+            .all_virtregs()
+            .map(|vreg| self.virtregs.values(vreg).len().saturating_sub(1))
+            .sum::<usize>();
 
         if isa.flags().enable_verifier() {
             let ok = verify_context(func, cfg, domtree, isa, &mut errors).is_ok()
@@ -130,6 +189,7 @@ impl Context {
         self.spilling.run(
             isa,
             func,
+            cfg,
             domtree,
             &mut self.liveness,
             &self.virtregs,
@@ -155,6 +215,22 @@ impl Context {
             }
         }
 
+        // Pass: Spill slot assignment.
+        if baseline {
+            self.spill_slots
+                .run_unshared(isa, func, &self.liveness, &self.virtregs);
+        } else {
+            self.spill_slots.run(
+                isa,
+                func,
+                domtree,
+                &self.liveness,
+                &self.virtregs,
+                &mut self.topo,
+                &mut self.tracker,
+            );
+        }
+
         // Pass: Reload.
         self.reload.run(
             isa,
@@ -163,6 +239,7 @@ impl Context {
             &mut self.liveness,
             &mut self.topo,
             &mut self.tracker,
+            stats,
         );
 
         if isa.flags().enable_verifier() {
@@ -187,6 +264,18 @@ impl Context {
         self.coloring
             .run(isa, func, domtree, &mut self.liveness, &mut self.tracker);
 
+        // Pass: Stack maps.
+        if isa.flags().enable_safepoints() {
+            emit_stackmaps(
+                isa,
+                func,
+                domtree,
+                &self.liveness,
+                &mut self.topo,
+                &mut self.tracker,
+            );
+        }
+
         if isa.flags().enable_verifier() {
             let ok = verify_context(func, cfg, domtree, isa, &mut errors).is_ok()
                 && verify_liveness(isa, func, cfg, &self.liveness, &mut errors).is_ok()
@@ -118,6 +118,13 @@ impl RegDiversions {
         self.divert(value, ValueLoc::Reg(from), ValueLoc::Reg(to));
     }
 
+    /// Record a register swap: `value` moves from `from` to `to`, and `other` moves from `to` to
+    /// `from`.
+    pub fn regswap(&mut self, value: Value, other: Value, from: RegUnit, to: RegUnit) {
+        self.regmove(value, from, to);
+        self.regmove(other, to, from);
+    }
+
     /// Record a register -> stack move.
     pub fn regspill(&mut self, value: Value, from: RegUnit, to: StackSlot) {
         self.divert(value, ValueLoc::Reg(from), ValueLoc::Stack(to));
@@ -130,7 +137,7 @@ impl RegDiversions {
 
     /// Apply the effect of `inst`.
     ///
-    /// If `inst` is a `regmove`, `regfill`, or `regspill` instruction, update the diversions to
+    /// If `inst` is a `regmove`, `regswap`, `regfill`, or `regspill` instruction, update the diversions to
     /// match.
     pub fn apply(&mut self, inst: &InstructionData) {
         match *inst {
@@ -140,6 +147,12 @@ impl RegDiversions {
                 src,
                 dst,
             } => self.regmove(arg, src, dst),
+            InstructionData::RegSwap {
+                opcode: Opcode::Regswap,
+                args,
+                src,
+                dst,
+            } => self.regswap(args[0], args[1], src, dst),
             InstructionData::RegSpill {
                 opcode: Opcode::Regspill,
                 arg,
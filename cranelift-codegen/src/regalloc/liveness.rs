@@ -346,6 +346,13 @@ impl Liveness {
         debug_assert!(old.is_none(), "{} already has a live range", value);
     }
 
+    /// Remove the live range of `value` after its definition has been deleted from the function.
+    pub fn remove(&mut self, value: Value) {
+        if let Some(mut lr) = self.ranges.remove(value) {
+            lr.clear_liveins(&mut self.forest);
+        }
+    }
+
     /// Move the definition of `value` to `def`.
     ///
     /// The old and new def points must be in the same EBB, and before the end of the live range.
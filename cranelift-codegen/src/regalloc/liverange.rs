@@ -175,7 +175,7 @@ pub struct GenLiveRange<PO: ProgramOrder> {
     /// defined.
     liveins: bforest::Map<Ebb, Inst>,
 
-    po: PhantomData<*const PO>,
+    po: PhantomData<fn() -> PO>,
 }
 
 /// Context information needed to query a `LiveRange`.
@@ -330,6 +330,11 @@ impl<PO: ProgramOrder> GenLiveRange<PO> {
         self.def_begin == self.def_end
     }
 
+    /// Free the live-in intervals of this live range before it is discarded.
+    pub fn clear_liveins(&mut self, forest: &mut bforest::MapForest<Ebb, Inst>) {
+        self.liveins.clear(forest);
+    }
+
     /// Is this a local live range?
     ///
     /// A local live range is only used in the same EBB where it was defined. It is allowed to span
@@ -15,9 +15,13 @@ mod context;
 mod diversion;
 mod pressure;
 mod reload;
+mod safepoint;
 mod solver;
+mod spill_slots;
 mod spilling;
+mod splitting;
 
+pub use self::affinity::Affinity;
 pub use self::context::Context;
 pub use self::diversion::RegDiversions;
 pub use self::register_set::RegisterSet;
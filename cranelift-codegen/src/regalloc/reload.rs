@@ -8,17 +8,23 @@
 //! The secondary responsibility of the reload pass is to reuse values in registers as much as
 //! possible to minimize the number of `fill` instructions needed. This must not cause the register
 //! pressure limits to be exceeded.
+//!
+//! Spilled values that are cheap to compute, like constants, don't get a spill slot. Instead of
+//! inserting `spill` and `fill` instructions, the reload pass repeats their defining instruction
+//! before each use.
 
 use crate::cursor::{Cursor, EncCursor};
 use crate::dominator_tree::DominatorTree;
 use crate::entity::{SparseMap, SparseMapValue};
-use crate::ir::{AbiParam, ArgumentLoc, InstBuilder};
+use crate::ir::{AbiParam, ArgumentLoc, InstBuilder, InstBuilderBase};
 use crate::ir::{Ebb, Function, Inst, InstructionData, Opcode, Value};
 use crate::isa::RegClass;
 use crate::isa::{ConstraintKind, EncInfo, Encoding, RecipeConstraints, TargetIsa};
 use crate::regalloc::affinity::Affinity;
 use crate::regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
 use crate::regalloc::liveness::Liveness;
+use crate::regalloc::spilling::rematerializable_def;
+use crate::stats::PassStats;
 use crate::timing;
 use crate::topo_order::TopoOrder;
 use log::debug;
@@ -28,6 +34,7 @@ use std::vec::Vec;
 pub struct Reload {
     candidates: Vec<ReloadCandidate>,
     reloads: SparseMap<Value, ReloadedValue>,
+    remat_defs: Vec<Inst>,
 }
 
 /// Context data structure that gets instantiated once per pass.
@@ -42,9 +49,13 @@ struct Context<'a> {
     domtree: &'a DominatorTree,
     liveness: &'a mut Liveness,
     topo: &'a mut TopoOrder,
+    stats: &'a mut PassStats,
 
     candidates: &'a mut Vec<ReloadCandidate>,
     reloads: &'a mut SparseMap<Value, ReloadedValue>,
+
+    // Original definitions of rematerialized values, to be removed when the pass is done.
+    remat_defs: &'a mut Vec<Inst>,
 }
 
 impl Reload {
@@ -53,6 +64,7 @@ impl Reload {
         Self {
             candidates: Vec::new(),
             reloads: SparseMap::new(),
+            remat_defs: Vec::new(),
         }
     }
 
@@ -60,9 +72,10 @@ impl Reload {
     pub fn clear(&mut self) {
         self.candidates.clear();
         self.reloads.clear();
+        self.remat_defs.clear();
     }
 
-    /// Run the reload algorithm over `func`.
+    /// Run the reload algorithm over `func`, and count the inserted spills and fills in `stats`.
     pub fn run(
         &mut self,
         isa: &TargetIsa,
@@ -71,6 +84,7 @@ impl Reload {
         liveness: &mut Liveness,
         topo: &mut TopoOrder,
         tracker: &mut LiveValueTracker,
+        stats: &mut PassStats,
     ) {
         let _tt = timing::ra_reload();
         debug!("Reload for:\n{}", func.display(isa));
@@ -80,8 +94,10 @@ impl Reload {
             domtree,
             liveness,
             topo,
+            stats,
             candidates: &mut self.candidates,
             reloads: &mut self.reloads,
+            remat_defs: &mut self.remat_defs,
         };
         ctx.run(tracker)
     }
@@ -117,6 +133,15 @@ impl<'a> Context<'a> {
         while let Some(ebb) = self.topo.next(&self.cur.func.layout, self.domtree) {
             self.visit_ebb(ebb, tracker);
         }
+
+        // All the uses of rematerialized values have been rewritten, so their original
+        // definitions are dead now. Remove their live ranges too, so the later passes don't see
+        // values without a definition in the layout.
+        for inst in self.remat_defs.drain(..) {
+            let value = self.cur.func.dfg.first_result(inst);
+            self.liveness.remove(value);
+            self.cur.func.layout.remove_inst(inst);
+        }
     }
 
     fn visit_ebb(&mut self, ebb: Ebb, tracker: &mut LiveValueTracker) {
@@ -245,7 +270,10 @@ impl<'a> Context<'a> {
         if let Some(constraints) = constraints {
             for (lv, op) in defs.iter().zip(constraints.outs) {
                 if lv.affinity.is_stack() && op.kind != ConstraintKind::Stack {
-                    if let InstructionData::Unary {
+                    if self.remat_def(lv.value).is_some() {
+                        // The value is recomputed before each use, so it is never spilled.
+                        self.remat_defs.push(inst);
+                    } else if let InstructionData::Unary {
                         opcode: Opcode::Copy,
                         arg,
                     } = self.cur.func.dfg[inst]
@@ -253,6 +281,7 @@ impl<'a> Context<'a> {
                         self.cur.func.dfg.replace(inst).spill(arg);
                         let ok = self.cur.func.update_encoding(inst, self.cur.isa).is_ok();
                         debug_assert!(ok);
+                        self.stats.spills += 1;
                     } else {
                         let value_type = self.cur.func.dfg.value_type(lv.value);
                         let reg = self.cur.func.dfg.replace_result(lv.value, value_type);
@@ -301,7 +330,13 @@ impl<'a> Context<'a> {
                 continue;
             }
 
-            let reg = self.cur.ins().fill(cand.value);
+            let reg = match self.remat_def(cand.value) {
+                Some(def) => self.insert_remat(def),
+                None => {
+                    self.stats.fills += 1;
+                    self.cur.ins().fill(cand.value)
+                }
+            };
             let fill = self.cur.built_inst();
 
             self.reloads.insert(ReloadedValue {
@@ -334,13 +369,22 @@ impl<'a> Context<'a> {
     // Reload the current candidates for the given copy `inst`.
     //
     // As an optimization, replace a copy instruction where the argument has been spilled with
-    // a fill instruction.
+    // a fill instruction, or with the defining instruction of a rematerialized argument.
     fn reload_copy_candidates(&mut self, inst: Inst) {
         // Copy instructions can only have one argument.
         debug_assert!(self.candidates.is_empty() || self.candidates.len() == 1);
 
         if let Some(cand) = self.candidates.pop() {
-            self.cur.func.dfg.replace(inst).fill(cand.value);
+            match self.remat_def(cand.value) {
+                Some(def) => {
+                    let data = self.cur.func.dfg[def].clone();
+                    self.cur.func.dfg[inst] = data;
+                }
+                None => {
+                    self.cur.func.dfg.replace(inst).fill(cand.value);
+                    self.stats.fills += 1;
+                }
+            }
             let ok = self.cur.func.update_encoding(inst, self.cur.isa).is_ok();
             debug_assert!(ok);
         }
@@ -393,9 +437,42 @@ impl<'a> Context<'a> {
                 self.cur.isa,
                 self.liveness,
             );
+        } else if self.cur.func.dfg[inst].opcode() == Opcode::Safepoint {
+            // Safepoint arguments can stay in their spill slots, but a rematerialized value
+            // doesn't have one, so it must be recomputed into a register.
+            for (argidx, &arg) in (offset..).zip(var_args) {
+                if self.liveness[arg].affinity.is_stack() && self.remat_def(arg).is_some() {
+                    let ty = self.cur.func.dfg.value_type(arg);
+                    self.candidates.push(ReloadCandidate {
+                        argidx,
+                        value: arg,
+                        regclass: self.cur.isa.regclass_for_abi_type(ty),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Get the definition of the spilled `value` if it should be rematerialized instead of being
+    /// reloaded.
+    ///
+    /// The spilling pass doesn't assign spill slots to rematerializable values.
+    fn remat_def(&self, value: Value) -> Option<Inst> {
+        if self.cur.func.locations[value].is_assigned() {
+            None
+        } else {
+            rematerializable_def(&self.cur.func.dfg, value)
         }
     }
 
+    /// Insert a copy of the rematerializable instruction `def` at `pos` and return its result.
+    fn insert_remat(&mut self, def: Inst) -> Value {
+        let ctrl_typevar = self.cur.func.dfg.ctrl_typevar(def);
+        let data = self.cur.func.dfg[def].clone();
+        let (inst, dfg) = self.cur.ins().build(data, ctrl_typevar);
+        dfg.first_result(inst)
+    }
+
     /// Insert a spill at `pos` and update data structures.
     ///
     /// - Insert `stack = spill reg` at `pos`, and assign an encoding.
@@ -404,6 +481,7 @@ impl<'a> Context<'a> {
     fn insert_spill(&mut self, ebb: Ebb, stack: Value, reg: Value) {
         self.cur.ins().with_result(stack).spill(reg);
         let inst = self.cur.built_inst();
+        self.stats.spills += 1;
 
         // Update live ranges.
         self.liveness.move_def_locally(stack, inst);
@@ -0,0 +1,75 @@
+//! Stack map insertion pass.
+//!
+//! A garbage collector walking the stack needs to know where each frame keeps its references, so
+//! it can mark the objects they point to and update them when objects are moved. Collections can
+//! only happen during calls, and the spilling pass has already moved every value live across a call
+//! to the stack.
+//!
+//! When the `enable_safepoints` setting is enabled, this pass runs after coloring and inserts a
+//! `stackmap` instruction right after each call with references live across it. The instruction
+//! emits no code, but it reports the stack offsets of the references at the return address of the
+//! call.
+
+use crate::cursor::{Cursor, EncCursor};
+use crate::dominator_tree::DominatorTree;
+use crate::ir::{Function, InstBuilder, Value};
+use crate::isa::TargetIsa;
+use crate::regalloc::live_value_tracker::LiveValueTracker;
+use crate::regalloc::liveness::Liveness;
+use crate::timing;
+use crate::topo_order::TopoOrder;
+use log::debug;
+use std::vec::Vec;
+
+/// Insert a `stackmap` instruction after each call in `func` with references live across it.
+pub fn emit_stackmaps(
+    isa: &TargetIsa,
+    func: &mut Function,
+    domtree: &DominatorTree,
+    liveness: &Liveness,
+    topo: &mut TopoOrder,
+    tracker: &mut LiveValueTracker,
+) {
+    let _tt = timing::ra_stackmaps();
+    debug!("Stack maps for:\n{}", func.display(isa));
+    let mut refs: Vec<Value> = Vec::new();
+
+    topo.reset(func.layout.ebbs());
+    while let Some(ebb) = topo.next(&func.layout, domtree) {
+        tracker.ebb_top(ebb, &func.dfg, liveness, &func.layout, domtree);
+        tracker.drop_dead_params();
+
+        let mut pos = func.layout.first_inst(ebb);
+        while let Some(inst) = pos {
+            if func.dfg[inst].opcode().is_ghost() {
+                tracker.process_ghost(inst);
+                tracker.drop_dead(inst);
+                pos = func.layout.next_inst(inst);
+                continue;
+            }
+
+            refs.clear();
+            {
+                let (throughs, _, _) = tracker.process_inst(inst, &func.dfg, liveness);
+                if func.dfg[inst].opcode().is_call() {
+                    refs.extend(
+                        throughs
+                            .iter()
+                            .map(|lv| lv.value)
+                            .filter(|&value| func.dfg.value_type(value).is_ref()),
+                    );
+                }
+            }
+            tracker.drop_dead(inst);
+
+            if refs.is_empty() {
+                pos = func.layout.next_inst(inst);
+            } else {
+                let mut cur = EncCursor::new(func, isa);
+                cur.goto_after_inst(inst);
+                let stackmap = cur.ins().stackmap(&refs);
+                pos = cur.func.layout.next_inst(stackmap);
+            }
+        }
+    }
+}
@@ -149,6 +149,9 @@ pub struct Variable {
 
     /// Any solution must belong to the constraint register class.
     constraint: RegClass,
+
+    /// Preferred register unit, used when it is available.
+    hint: Option<RegUnit>,
 }
 
 impl Variable {
@@ -162,6 +165,7 @@ impl Variable {
             is_global: false,
             domain: 0,
             solution: !0,
+            hint: None,
         }
     }
 
@@ -175,6 +179,7 @@ impl Variable {
             is_global,
             domain: 0,
             solution: !0,
+            hint: None,
         }
     }
 
@@ -224,6 +229,9 @@ impl fmt::Display for Variable {
         if self.domain > 0 {
             write!(f, ", {}", self.domain)?;
         }
+        if let Some(reg) = self.hint {
+            write!(f, ", hint {}", self.constraint.info.display_regunit(reg))?;
+        }
         write!(f, ")")
     }
 }
@@ -277,6 +285,15 @@ pub enum Move {
         from_slot: usize,
         to: RegUnit,
     },
+    /// Exchange two registers: `value` moves from `from` to `to`, and `other` moves from `to` to
+    /// `from`.
+    Swap {
+        value: Value,
+        other: Value,
+        rc: RegClass,
+        from: RegUnit,
+        to: RegUnit,
+    },
 }
 
 impl Move {
@@ -298,7 +315,9 @@ impl Move {
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::wrong_self_convention))]
     fn from_reg(&self) -> Option<(RegClass, RegUnit)> {
         match *self {
-            Move::Reg { rc, from, .. } | Move::Spill { rc, from, .. } => Some((rc, from)),
+            Move::Reg { rc, from, .. }
+            | Move::Spill { rc, from, .. }
+            | Move::Swap { rc, from, .. } => Some((rc, from)),
             Move::Fill { .. } => None,
         }
     }
@@ -306,7 +325,9 @@ impl Move {
     /// Get the "to" register and register class, if possible.
     fn to_reg(&self) -> Option<(RegClass, RegUnit)> {
         match *self {
-            Move::Reg { rc, to, .. } | Move::Fill { rc, to, .. } => Some((rc, to)),
+            Move::Reg { rc, to, .. } | Move::Fill { rc, to, .. } | Move::Swap { rc, to, .. } => {
+                Some((rc, to))
+            }
             Move::Spill { .. } => None,
         }
     }
@@ -317,6 +338,7 @@ impl Move {
             match *self {
                 Move::Reg { ref mut to, .. } | Move::Fill { ref mut to, .. } => to,
                 Move::Spill { .. } => panic!("No to register in a spill {}", self),
+                Move::Swap { .. } => panic!("Can't redirect a swap {}", self),
             },
             new,
         )
@@ -346,14 +368,20 @@ impl Move {
     /// Get the value being moved.
     fn value(&self) -> Value {
         match *self {
-            Move::Reg { value, .. } | Move::Fill { value, .. } | Move::Spill { value, .. } => value,
+            Move::Reg { value, .. }
+            | Move::Fill { value, .. }
+            | Move::Spill { value, .. }
+            | Move::Swap { value, .. } => value,
         }
     }
 
     /// Get the associated register class.
     fn rc(&self) -> RegClass {
         match *self {
-            Move::Reg { rc, .. } | Move::Fill { rc, .. } | Move::Spill { rc, .. } => rc,
+            Move::Reg { rc, .. }
+            | Move::Fill { rc, .. }
+            | Move::Spill { rc, .. }
+            | Move::Swap { rc, .. } => rc,
         }
     }
 }
@@ -400,6 +428,21 @@ impl fmt::Display for Move {
                 from_slot,
                 rc.info.display_regunit(to)
             ),
+            Move::Swap {
+                value,
+                other,
+                rc,
+                from,
+                to,
+            } => write!(
+                f,
+                "{}, {}:{}({} <-> {})",
+                value,
+                other,
+                rc,
+                rc.info.display_regunit(from),
+                rc.info.display_regunit(to)
+            ),
         }
     }
 }
@@ -816,6 +859,18 @@ impl Solver {
             .push(Variable::new_def(value, constraint, is_global));
     }
 
+    /// Prefer assigning `reg` to the defined output `value` if it is available.
+    ///
+    /// This is used to color the result of a `copy` to the register holding its argument, so
+    /// the copy becomes a no-op that can be deleted after register allocation.
+    pub fn add_hint(&mut self, value: Value, reg: RegUnit) {
+        debug_assert!(self.inputs_done);
+        if let Some(v) = self.vars.iter_mut().find(|v| v.value == value) {
+            debug_assert!(v.is_define(), "Hint for a live value {}", value);
+            v.hint = Some(reg);
+        }
+    }
+
     /// Clear the `is_global` flag on all solver variables.
     ///
     /// This is used when there are not enough global registers available, and global defines have
@@ -917,7 +972,10 @@ impl Solver {
 
         for v in &mut self.vars {
             let rc = v.constraint;
-            let reg = match v.iter(&iregs, &oregs, &gregs).next() {
+            let hint = v
+                .hint
+                .filter(|&hint| v.iter(&iregs, &oregs, &gregs).any(|reg| reg == hint));
+            let reg = match hint.or_else(|| v.iter(&iregs, &oregs, &gregs).next()) {
                 Some(reg) => reg,
                 None => {
                     // If `v` must avoid global interference, there is not point in requesting
@@ -990,14 +1048,15 @@ impl Solver {
     /// Try to schedule a sequence of `regmove` instructions that will shuffle registers into
     /// place.
     ///
-    /// This may require the use of additional available registers, and it can fail if no
-    /// additional registers are available.
-    ///
-    /// TODO: Handle failure by generating a sequence of register swaps, or by temporarily spilling
-    /// a register.
+    /// Cycles are broken with an available register if possible. Otherwise, two values are
+    /// exchanged with a swap if `can_swap` returns true for them, and as a last resort a value is
+    /// temporarily spilled to an emergency spill slot.
     ///
     /// Returns the number of spills that had to be emitted.
-    pub fn schedule_moves(&mut self, regs: &RegisterSet) -> usize {
+    pub fn schedule_moves<F>(&mut self, regs: &RegisterSet, can_swap: F) -> usize
+    where
+        F: Fn(Value, Value) -> bool,
+    {
         self.collect_moves();
         debug_assert!(self.fills.is_empty());
 
@@ -1034,10 +1093,8 @@ impl Solver {
             // only cycles remaining. The cycles can be broken in a few ways:
             //
             // 1. Grab an available register and use it to break a cycle.
-            // 2. Move a value temporarily into a stack slot instead of a register.
-            // 3. Use swap instructions.
-            //
-            // TODO: So far we only implement 1 and 2.
+            // 2. Use swap instructions.
+            // 3. Move a value temporarily into a stack slot instead of a register.
 
             // Pick an assignment with the largest possible width. This is more likely to break up
             // a cycle than an assignment with fewer register units. For example, it may be
@@ -1086,8 +1143,42 @@ impl Solver {
                 continue;
             }
 
-            // It was impossible to free up a register in toprc, so use an emergency spill slot as
-            // a last resort.
+            // It was impossible to free up a register in toprc. Try to exchange the value with the
+            // one occupying its destination register. That puts `m.value` in place, and the other
+            // value continues its part of the cycle from `m`'s source register.
+            if let Some(k) = self.find_swap(i, &can_swap) {
+                let (from, to) = match m {
+                    Move::Reg { from, to, .. } => (from, to),
+                    _ => unreachable!(),
+                };
+                let (other, other_to) = match self.moves[k] {
+                    Move::Reg { value, to, .. } => (value, to),
+                    _ => unreachable!(),
+                };
+                debug!("breaking cycle at {} by swapping with {}", m, self.moves[k]);
+                self.moves[i] = Move::Swap {
+                    value: m.value(),
+                    other,
+                    rc: toprc,
+                    from,
+                    to,
+                };
+                if other_to == from {
+                    // The other value is now in place too.
+                    self.moves.remove(k);
+                } else {
+                    self.moves[k] = Move::Reg {
+                        value: other,
+                        rc: self.moves[k].rc(),
+                        from,
+                        to: other_to,
+                    };
+                }
+                i += 1;
+                continue;
+            }
+
+            // As a last resort, use an emergency spill slot.
             let slot = num_spill_slots;
             num_spill_slots += 1;
             debug!("breaking cycle at {} with slot {}", m, slot);
@@ -1103,6 +1194,32 @@ impl Solver {
         num_spill_slots
     }
 
+    /// Find a register move in `moves[i+1..]` out of the destination register of the single-unit
+    /// register move `moves[i]`, such that the two values can be swapped.
+    fn find_swap<F>(&self, i: usize, can_swap: &F) -> Option<usize>
+    where
+        F: Fn(Value, Value) -> bool,
+    {
+        let (value, rc, to) = match self.moves[i] {
+            Move::Reg { value, rc, to, .. } if rc.width == 1 => (value, rc, to),
+            _ => return None,
+        };
+        self.moves[i + 1..]
+            .iter()
+            .position(|m| match *m {
+                Move::Reg {
+                    value: other,
+                    rc: orc,
+                    from,
+                    ..
+                } => {
+                    from == to && orc.width == 1 && orc.toprc == rc.toprc && can_swap(value, other)
+                }
+                _ => false,
+            })
+            .map(|k| i + 1 + k)
+    }
+
     /// Borrow the scheduled set of register moves that was computed by `schedule_moves()`.
     pub fn moves(&self) -> &[Move] {
         &self.moves
@@ -1188,6 +1305,16 @@ mod tests {
         }
     }
 
+    fn swap(value: Value, other: Value, rc: RegClass, from: RegUnit, to: RegUnit) -> Move {
+        Move::Swap {
+            value,
+            other,
+            rc,
+            from,
+            to,
+        }
+    }
+
     #[test]
     fn simple_moves() {
         let isa = arm32().expect("This test requires arm32 support");
@@ -1208,7 +1335,7 @@ mod tests {
         solver.reassign_in(v10, gpr, r1, r0);
         solver.inputs_done();
         assert!(solver.quick_solve(&gregs).is_ok());
-        assert_eq!(solver.schedule_moves(&regs), 0);
+        assert_eq!(solver.schedule_moves(&regs, |_, _| false), 0);
         assert_eq!(solver.moves(), &[mov(v10, gpr, r1, r0)]);
 
         // A bit harder: r0, r1 need to go in r1, r2.
@@ -1218,7 +1345,7 @@ mod tests {
         solver.reassign_in(v11, gpr, r1, r2);
         solver.inputs_done();
         assert!(solver.quick_solve(&gregs).is_ok());
-        assert_eq!(solver.schedule_moves(&regs), 0);
+        assert_eq!(solver.schedule_moves(&regs, |_, _| false), 0);
         assert_eq!(
             solver.moves(),
             &[mov(v11, gpr, r1, r2), mov(v10, gpr, r0, r1)]
@@ -1230,7 +1357,7 @@ mod tests {
         solver.reassign_in(v11, gpr, r1, r0);
         solver.inputs_done();
         assert!(solver.quick_solve(&gregs).is_ok());
-        assert_eq!(solver.schedule_moves(&regs), 0);
+        assert_eq!(solver.schedule_moves(&regs, |_, _| false), 0);
         assert_eq!(
             solver.moves(),
             &[
@@ -1270,7 +1397,7 @@ mod tests {
         solver.reassign_in(v12, s, s3, s1);
         solver.inputs_done();
         assert!(solver.quick_solve(&gregs).is_ok());
-        assert_eq!(solver.schedule_moves(&regs), 0);
+        assert_eq!(solver.schedule_moves(&regs, |_, _| false), 0);
         assert_eq!(
             solver.moves(),
             &[
@@ -1291,7 +1418,7 @@ mod tests {
         solver.reassign_in(v10, d, d1, d0);
         solver.inputs_done();
         assert!(solver.quick_solve(&gregs).is_ok());
-        assert_eq!(solver.schedule_moves(&regs), 0);
+        assert_eq!(solver.schedule_moves(&regs, |_, _| false), 0);
         assert_eq!(
             solver.moves(),
             &[
@@ -1336,7 +1463,7 @@ mod tests {
         solver.reassign_in(v12, gpr, r2, r0);
         solver.inputs_done();
         assert!(solver.quick_solve(&gregs).is_ok());
-        assert_eq!(solver.schedule_moves(&regs), 1);
+        assert_eq!(solver.schedule_moves(&regs, |_, _| false), 1);
         assert_eq!(
             solver.moves(),
             &[
@@ -1361,7 +1488,7 @@ mod tests {
         solver.inputs_done();
         assert!(solver.quick_solve(&gregs).is_ok());
         // We resolve two cycles with one spill.
-        assert_eq!(solver.schedule_moves(&regs), 1);
+        assert_eq!(solver.schedule_moves(&regs, |_, _| false), 1);
         assert_eq!(
             solver.moves(),
             &[
@@ -1376,4 +1503,38 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn swap_cycle() {
+        let isa = arm32().expect("This test requires arm32 support");
+        let reginfo = isa.register_info();
+        let gpr = rc_by_name(&reginfo, "GPR");
+        let r0 = gpr.unit(0);
+        let r1 = gpr.unit(1);
+        let r2 = gpr.unit(2);
+        let gregs = RegisterSet::new();
+        let mut regs = RegisterSet::new();
+        let mut solver = Solver::new();
+        let v10 = Value::new(10);
+        let v11 = Value::new(11);
+        let v12 = Value::new(12);
+
+        // Claim all the registers so the cycle can't be broken with a scratch register.
+        for i in 0..16 {
+            regs.take(gpr, gpr.unit(i));
+        }
+
+        // A permutation cycle can be resolved with two swaps instead of a spill.
+        solver.reset(&regs);
+        solver.reassign_in(v10, gpr, r0, r1);
+        solver.reassign_in(v11, gpr, r1, r2);
+        solver.reassign_in(v12, gpr, r2, r0);
+        solver.inputs_done();
+        assert!(solver.quick_solve(&gregs).is_ok());
+        assert_eq!(solver.schedule_moves(&regs, |_, _| true), 0);
+        assert_eq!(
+            solver.moves(),
+            &[swap(v10, v11, gpr, r0, r1), swap(v11, v12, gpr, r0, r2)]
+        );
+    }
 }
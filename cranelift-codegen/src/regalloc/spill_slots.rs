@@ -0,0 +1,266 @@
+//! Spill slot assignment pass.
+//!
+//! The spilling pass decides which virtual registers live on the stack, and this pass assigns
+//! stack slots to them. Virtual registers whose live ranges don't interfere can share a spill slot,
+//! which keeps the stack frame small in functions with high register pressure in disjoint regions.
+//!
+//! Two SSA values interfere if one of them is live at the definition of the other. The pass visits
+//! the EBBs in a topological order with a live value tracker, and records an interference between
+//! the virtual registers of every spilled value defined and every other spilled value live at the
+//! definition. Virtual registers are then assigned greedily to the first spill slot of the right
+//! size that doesn't hold an interfering virtual register.
+
+use crate::dominator_tree::DominatorTree;
+use crate::fx::{FxHashMap, FxHashSet};
+use crate::ir::stackslot::spill_size;
+use crate::ir::{Function, StackSlot, Value, ValueLoc};
+use crate::isa::TargetIsa;
+use crate::regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
+use crate::regalloc::liveness::Liveness;
+use crate::regalloc::spilling::{flags_clobbering_remats, is_rematerializable};
+use crate::regalloc::virtregs::VirtRegs;
+use crate::timing;
+use crate::topo_order::TopoOrder;
+use log::debug;
+use std::vec::Vec;
+
+/// Persistent data structures for the spill slot assignment pass.
+pub struct SpillSlots {
+    /// Spilled values that need a stack slot, in the order they were visited.
+    values: Vec<Value>,
+
+    /// The first value in the virtual register of each value in `values`.
+    vregs: Vec<Value>,
+
+    /// Pairs of interfering virtual registers, represented by their first value.
+    interference: FxHashSet<(Value, Value)>,
+
+    /// The spill slots created so far, with the virtual registers assigned to them.
+    slots: Vec<(StackSlot, Vec<Value>)>,
+
+    /// The spill slot assigned to each virtual register.
+    assignment: FxHashMap<Value, StackSlot>,
+
+    /// Rematerializable values that get a spill slot because they are used where CPU flags are
+    /// live.
+    flags_remats: FxHashSet<Value>,
+}
+
+/// Context data structure that gets instantiated once per pass.
+struct Context<'a> {
+    func: &'a mut Function,
+    domtree: &'a DominatorTree,
+    liveness: &'a Liveness,
+    virtregs: &'a VirtRegs,
+    topo: &'a mut TopoOrder,
+
+    values: &'a mut Vec<Value>,
+    vregs: &'a mut Vec<Value>,
+    interference: &'a mut FxHashSet<(Value, Value)>,
+    slots: &'a mut Vec<(StackSlot, Vec<Value>)>,
+    assignment: &'a mut FxHashMap<Value, StackSlot>,
+    flags_remats: &'a FxHashSet<Value>,
+}
+
+impl SpillSlots {
+    /// Create a new spill slot assignment pass.
+    pub fn new() -> Self {
+        Self {
+            values: Vec::new(),
+            vregs: Vec::new(),
+            interference: FxHashSet(),
+            slots: Vec::new(),
+            assignment: FxHashMap(),
+            flags_remats: FxHashSet(),
+        }
+    }
+
+    /// Clear all data structures in this pass.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.vregs.clear();
+        self.interference.clear();
+        self.slots.clear();
+        self.assignment.clear();
+        self.flags_remats.clear();
+    }
+
+    /// Assign spill slots to the spilled values in `func`.
+    pub fn run(
+        &mut self,
+        isa: &TargetIsa,
+        func: &mut Function,
+        domtree: &DominatorTree,
+        liveness: &Liveness,
+        virtregs: &VirtRegs,
+        topo: &mut TopoOrder,
+        tracker: &mut LiveValueTracker,
+    ) {
+        let _tt = timing::ra_spill_slots();
+        debug!("Spill slots for:\n{}", func.display(isa));
+        self.clear();
+        self.flags_remats = flags_clobbering_remats(isa, func, liveness);
+        let mut ctx = Context {
+            func,
+            domtree,
+            liveness,
+            virtregs,
+            topo,
+            values: &mut self.values,
+            vregs: &mut self.vregs,
+            interference: &mut self.interference,
+            slots: &mut self.slots,
+            assignment: &mut self.assignment,
+            flags_remats: &self.flags_remats,
+        };
+        ctx.run(tracker)
+    }
+
+    /// Assign a separate spill slot to each spilled virtual register in `func`.
+    ///
+    /// This skips the interference computation of `run`, which is faster but makes larger stack
+    /// frames.
+    pub fn run_unshared(
+        &mut self,
+        isa: &TargetIsa,
+        func: &mut Function,
+        liveness: &Liveness,
+        virtregs: &VirtRegs,
+    ) {
+        let _tt = timing::ra_spill_slots();
+        debug!("Unshared spill slots for:\n{}", func.display(isa));
+        self.clear();
+        self.flags_remats = flags_clobbering_remats(isa, func, liveness);
+        for value in func.dfg.values() {
+            let is_spilled = liveness
+                .get(value)
+                .map_or(false, |lr| lr.affinity.is_stack());
+            if !is_spilled
+                || func.locations[value].is_assigned()
+                || (is_rematerializable(&func.dfg, virtregs, value)
+                    && !self.flags_remats.contains(&value))
+            {
+                continue;
+            }
+            let vreg = virtregs.congruence_class(&value)[0];
+            let ss = match self.assignment.get(&vreg) {
+                Some(&ss) => ss,
+                None => {
+                    let ss = func.stack_slots.make_spill_slot(func.dfg.value_type(vreg));
+                    debug!("Assigned {} to {}", vreg, ss);
+                    self.assignment.insert(vreg, ss);
+                    ss
+                }
+            };
+            func.locations[value] = ValueLoc::Stack(ss);
+        }
+    }
+}
+
+impl<'a> Context<'a> {
+    fn run(&mut self, tracker: &mut LiveValueTracker) {
+        self.topo.reset(self.func.layout.ebbs());
+        while let Some(ebb) = self.topo.next(&self.func.layout, self.domtree) {
+            let (liveins, params) = tracker.ebb_top(
+                ebb,
+                &self.func.dfg,
+                self.liveness,
+                &self.func.layout,
+                self.domtree,
+            );
+            for lv in params {
+                self.visit_def(lv, &[liveins, params]);
+            }
+            tracker.drop_dead_params();
+
+            let mut pos = self.func.layout.first_inst(ebb);
+            while let Some(inst) = pos {
+                if self.func.dfg[inst].opcode().is_ghost() {
+                    tracker.process_ghost(inst);
+                } else {
+                    let (throughs, kills, defs) =
+                        tracker.process_inst(inst, &self.func.dfg, self.liveness);
+                    for lv in defs {
+                        self.visit_def(lv, &[throughs, kills, defs]);
+                    }
+                }
+                tracker.drop_dead(inst);
+                pos = self.func.layout.next_inst(inst);
+            }
+        }
+
+        self.assign_slots();
+    }
+
+    /// Get the virtual register of `lv` if it is a spilled value that needs a stack slot.
+    ///
+    /// The virtual register is represented by its first value.
+    fn spilled_vreg(&self, lv: &LiveValue) -> Option<Value> {
+        if !lv.affinity.is_stack()
+            || self.func.locations[lv.value].is_assigned()
+            || (is_rematerializable(&self.func.dfg, self.virtregs, lv.value)
+                && !self.flags_remats.contains(&lv.value))
+        {
+            None
+        } else {
+            Some(self.virtregs.congruence_class(&lv.value)[0])
+        }
+    }
+
+    /// Record the interference between the value defined by `def` and the values that are live
+    /// at its definition.
+    fn visit_def(&mut self, def: &LiveValue, live: &[&[LiveValue]]) {
+        let vreg = match self.spilled_vreg(def) {
+            Some(vreg) => vreg,
+            None => return,
+        };
+        self.values.push(def.value);
+        self.vregs.push(vreg);
+
+        for lv in live.iter().flat_map(|values| values.iter()) {
+            if let Some(other) = self.spilled_vreg(lv) {
+                if other != vreg {
+                    self.interference.insert((vreg.min(other), vreg.max(other)));
+                }
+            }
+        }
+    }
+
+    /// Assign each spilled virtual register to the first compatible spill slot, creating new slots
+    /// as needed, and update the value locations.
+    fn assign_slots(&mut self) {
+        for &vreg in self.vregs.iter() {
+            if self.assignment.contains_key(&vreg) {
+                continue;
+            }
+            let ty = self.func.dfg.value_type(vreg);
+            let size = spill_size(ty);
+            let interference = &self.interference;
+            let stack_slots = &self.func.stack_slots;
+            let found = self.slots.iter_mut().find(|(ss, assigned)| {
+                stack_slots[*ss].size == size
+                    && assigned
+                        .iter()
+                        .all(|&other| !interference.contains(&(vreg.min(other), vreg.max(other))))
+            });
+            let ss = match found {
+                Some((ss, assigned)) => {
+                    assigned.push(vreg);
+                    *ss
+                }
+                None => {
+                    let ss = self.func.stack_slots.make_spill_slot(ty);
+                    self.slots.push((ss, vec![vreg]));
+                    ss
+                }
+            };
+            debug!("Assigned {} to {}", vreg, ss);
+            self.assignment.insert(vreg, ss);
+        }
+
+        for &value in self.values.iter() {
+            let vreg = self.virtregs.congruence_class(&value)[0];
+            self.func.locations[value] = ValueLoc::Stack(self.assignment[&vreg]);
+        }
+    }
+}
@@ -17,7 +17,14 @@
 
 use crate::cursor::{Cursor, EncCursor};
 use crate::dominator_tree::DominatorTree;
-use crate::ir::{ArgumentLoc, Ebb, Function, Inst, InstBuilder, SigRef, Value, ValueLoc};
+use crate::entity::EntitySet;
+use crate::flowgraph::ControlFlowGraph;
+use crate::fx::FxHashSet;
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{
+    ArgumentLoc, DataFlowGraph, Ebb, ExpandedProgramPoint, Function, Inst, InstBuilder, Opcode,
+    SigRef, Value, ValueDef,
+};
 use crate::isa::registers::{RegClass, RegClassIndex, RegClassMask, RegUnit};
 use crate::isa::{ConstraintKind, EncInfo, RecipeConstraints, RegInfo, TargetIsa};
 use crate::regalloc::affinity::Affinity;
@@ -40,10 +47,153 @@ fn toprc_containing_regunit(unit: RegUnit, reginfo: &RegInfo) -> RegClass {
         .expect("reg unit should be in a toprc")
 }
 
+/// Get the instruction defining `value` if it is cheap enough to recompute at every use instead of
+/// reloading it from a spill slot.
+///
+/// These instructions have no value operands, so they can be repeated anywhere the original
+/// definition dominates.
+pub fn rematerializable_def(dfg: &DataFlowGraph, value: Value) -> Option<Inst> {
+    if let ValueDef::Result(inst, 0) = dfg.value_def(value) {
+        match dfg[inst].opcode() {
+            Opcode::Iconst
+            | Opcode::Bconst
+            | Opcode::F32const
+            | Opcode::F64const
+            | Opcode::StackAddr
+            | Opcode::SymbolValue
+            | Opcode::FuncAddr => return Some(inst),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Can the spilled `value` be recomputed at its uses by the reload pass instead of being reloaded?
+///
+/// All the values in a virtual register share a spill slot, so only values that are alone in their
+/// virtual register are rematerialized. Rematerialized values don't get a spill slot.
+pub fn is_rematerializable(dfg: &DataFlowGraph, virtregs: &VirtRegs, value: Value) -> bool {
+    virtregs.congruence_class(&value).len() == 1 && rematerializable_def(dfg, value).is_some()
+}
+
+/// Find the rematerializable values that need a spill slot anyway.
+///
+/// A rematerialized value is recomputed right before each use. When the defining instruction
+/// clobbers the CPU flags, doing that at a use where a flags value is live, such as between an
+/// `ifcmp` and its `brif`, would corrupt the flags. Those values are reloaded from a spill slot
+/// instead.
+pub fn flags_clobbering_remats(
+    isa: &TargetIsa,
+    func: &Function,
+    liveness: &Liveness,
+) -> FxHashSet<Value> {
+    let encinfo = isa.encoding_info();
+    let ctx = liveness.context(&func.layout);
+    let mut remats = FxHashSet();
+
+    // Mark the rematerializable arguments of the instructions from `first` to `last` inclusive, in
+    // layout order.
+    let mut mark = |first: Option<Inst>, last: Inst| {
+        let mut next = first;
+        while let Some(inst) = next {
+            for &arg in func.dfg.inst_args(inst) {
+                if let Some(def) = rematerializable_def(&func.dfg, arg) {
+                    if encinfo
+                        .operand_constraints(func.encodings[def])
+                        .map_or(true, |c| c.clobbers_flags)
+                    {
+                        remats.insert(arg);
+                    }
+                }
+            }
+            if inst == last {
+                break;
+            }
+            next = func.layout.next_inst(inst).or_else(|| {
+                let ebb = func.layout.next_ebb(func.layout.inst_ebb(inst)?)?;
+                func.layout.first_inst(ebb)
+            });
+        }
+    };
+
+    for value in func.dfg.values() {
+        if !func.dfg.value_type(value).is_flags() {
+            continue;
+        }
+        let lr = match liveness.get(value) {
+            Some(lr) => lr,
+            None => continue,
+        };
+
+        // The flags are live after their definition, up to the last local use.
+        if lr.is_dead() {
+            continue;
+        }
+        if let ExpandedProgramPoint::Inst(end) = lr.def_local_end().into() {
+            let first = match lr.def().into() {
+                ExpandedProgramPoint::Inst(def) => func.layout.next_inst(def),
+                ExpandedProgramPoint::Ebb(ebb) => func.layout.first_inst(ebb),
+            };
+            mark(first, end);
+        }
+        for (ebb, end) in lr.liveins(ctx) {
+            mark(func.layout.first_inst(ebb), end);
+        }
+    }
+    remats
+}
+
+/// Find the EBBs that are executed less often than the code dominating them.
+///
+/// An EBB is unlikely if it has been marked as cold, if its only predecessor is an unlikely branch
+/// or a call with a landing pad, or if its immediate dominator is in an unlikely EBB.
+fn find_unlikely_ebbs(
+    func: &Function,
+    cfg: &ControlFlowGraph,
+    domtree: &DominatorTree,
+    unlikely: &mut EntitySet<Ebb>,
+) {
+    unlikely.clear();
+    for &ebb in domtree.cfg_postorder().iter().rev() {
+        let mut preds = cfg.pred_iter(ebb);
+        let is_unlikely = func.layout.is_cold(ebb)
+            || match (preds.next(), preds.next()) {
+                (Some(pred), None) => is_unlikely_edge(func, pred.inst, ebb),
+                _ => false,
+            }
+            || domtree
+                .idom(ebb)
+                .map_or(false, |idom| unlikely.contains(func.layout.pp_ebb(idom)));
+        if is_unlikely {
+            unlikely.insert(ebb);
+        }
+    }
+}
+
+/// Is the branch `inst` unlikely to go to `ebb`?
+fn is_unlikely_edge(func: &Function, inst: Inst, ebb: Ebb) -> bool {
+    match func.dfg.analyze_branch(inst) {
+        BranchInfo::SingleDest(dest, _) if dest == ebb => {}
+        _ => return false,
+    }
+    if func.dfg[inst].opcode().is_call() {
+        // Exceptions are rare, so the landing pad of a `try_call` is unlikely.
+        true
+    } else if func.dfg[inst].opcode() == Opcode::Jump {
+        // A `jump` is taken when the conditional branch before it isn't.
+        func.layout.prev_inst(inst).map_or(false, |branch| {
+            func.branch_probabilities[branch].is_likely()
+        })
+    } else {
+        func.branch_probabilities[inst].is_unlikely()
+    }
+}
+
 /// Persistent data structures for the spilling pass.
 pub struct Spilling {
     spills: Vec<Value>,
     reg_uses: Vec<RegUse>,
+    unlikely: EntitySet<Ebb>,
 }
 
 /// Context data structure that gets instantiated once per pass.
@@ -58,6 +208,7 @@ struct Context<'a> {
     // References to contextual data structures we need.
     domtree: &'a DominatorTree,
     liveness: &'a mut Liveness,
+    unlikely: &'a EntitySet<Ebb>,
     virtregs: &'a VirtRegs,
     topo: &'a mut TopoOrder,
 
@@ -79,6 +230,7 @@ impl Spilling {
         Self {
             spills: Vec::new(),
             reg_uses: Vec::new(),
+            unlikely: EntitySet::new(),
         }
     }
 
@@ -86,6 +238,7 @@ impl Spilling {
     pub fn clear(&mut self) {
         self.spills.clear();
         self.reg_uses.clear();
+        self.unlikely.clear();
     }
 
     /// Run the spilling algorithm over `func`.
@@ -93,6 +246,7 @@ impl Spilling {
         &mut self,
         isa: &TargetIsa,
         func: &mut Function,
+        cfg: &ControlFlowGraph,
         domtree: &DominatorTree,
         liveness: &mut Liveness,
         virtregs: &VirtRegs,
@@ -103,12 +257,14 @@ impl Spilling {
         debug!("Spilling for:\n{}", func.display(isa));
         let reginfo = isa.register_info();
         let usable_regs = isa.allocatable_registers(func);
+        find_unlikely_ebbs(func, cfg, domtree, &mut self.unlikely);
         let mut ctx = Context {
             cur: EncCursor::new(func, isa),
             reginfo: isa.register_info(),
             encinfo: isa.encoding_info(),
             domtree,
             liveness,
+            unlikely: &self.unlikely,
             virtregs,
             topo,
             pressure: Pressure::new(&reginfo, &usable_regs),
@@ -266,8 +422,9 @@ impl<'a> Context<'a> {
 
         // If inst is a call, spill all register values that are live across the call.
         // This means that we don't currently take advantage of callee-saved registers.
+        // Instructions like `stack_switch` clobber all registers, so the same applies to them.
         // TODO: Be more sophisticated.
-        if call_sig.is_some() {
+        if call_sig.is_some() || self.cur.func.dfg[inst].opcode().clobbers_all_regs() {
             for lv in throughs {
                 if lv.affinity.is_reg() && !self.spills.contains(&lv.value) {
                     self.spill_reg(lv.value);
@@ -455,7 +612,8 @@ impl<'a> Context<'a> {
                     // at the branch destination. It is also necessary since there can be
                     // arbitrarily many EBB arguments.
                     match {
-                        let args = if self.cur.func.dfg[inst].opcode().is_branch() {
+                        let opcode = self.cur.func.dfg[inst].opcode();
+                        let args = if opcode.is_branch() && !opcode.is_call() {
                             self.cur.func.dfg.inst_fixed_args(inst)
                         } else {
                             self.cur.func.dfg.inst_args(inst)
@@ -488,7 +646,8 @@ impl<'a> Context<'a> {
         //
         // The very simple strategy implemented here is to spill the value with the earliest def in
         // the reverse post-order. This strategy depends on a good reload pass to generate good
-        // code.
+        // code. Values defined in unlikely EBBs are preferred since their spill instructions
+        // stay out of the likely path.
         //
         // We know that all candidate defs dominate the current instruction, so one of them will
         // dominate the others. That is the earliest def.
@@ -507,21 +666,37 @@ impl<'a> Context<'a> {
                 None
             })
             .min_by(|&a, &b| {
-                // Find the minimum candidate according to the RPO of their defs.
-                self.domtree.rpo_cmp(
-                    self.cur.func.dfg.value_def(a),
-                    self.cur.func.dfg.value_def(b),
-                    &self.cur.func.layout,
-                )
+                // Prefer values that can be rematerialized since they never need to be reloaded,
+                // and then values defined in unlikely EBBs. Otherwise, find the minimum candidate
+                // according to the RPO of their defs.
+                is_rematerializable(&self.cur.func.dfg, self.virtregs, b)
+                    .cmp(&is_rematerializable(&self.cur.func.dfg, self.virtregs, a))
+                    .then_with(|| self.is_unlikely_def(b).cmp(&self.is_unlikely_def(a)))
+                    .then_with(|| {
+                        self.domtree.rpo_cmp(
+                            self.cur.func.dfg.value_def(a),
+                            self.cur.func.dfg.value_def(b),
+                            &self.cur.func.layout,
+                        )
+                    })
             })
     }
 
+    /// Is `value` defined in an unlikely EBB?
+    fn is_unlikely_def(&self, value: Value) -> bool {
+        let def = self.cur.func.dfg.value_def(value);
+        self.unlikely.contains(self.cur.func.layout.pp_ebb(def))
+    }
+
     /// Spill `value` immediately by
     ///
     /// 1. Changing its affinity to `Stack` which marks the spill.
     /// 2. Removing the value from the pressure tracker.
     /// 3. Adding the value to `self.spills` for later reference by `process_spills`.
     ///
+    /// The whole virtual register is spilled, but stack slots are assigned later by the spill
+    /// slot assignment pass.
+    ///
     /// Note that this does not update the cached affinity in the live value tracker. Call
     /// `process_spills` to do that.
     fn spill_reg(&mut self, value: Value) {
@@ -534,15 +709,8 @@ impl<'a> Context<'a> {
             panic!("Cannot spill {} that was already on the stack", value);
         }
 
-        // Assign a spill slot for the whole virtual register.
-        let ss = self
-            .cur
-            .func
-            .stack_slots
-            .make_spill_slot(self.cur.func.dfg.value_type(value));
         for &v in self.virtregs.congruence_class(&value) {
             self.liveness.spill(v);
-            self.cur.func.locations[v] = ValueLoc::Stack(ss);
         }
     }
 
@@ -0,0 +1,376 @@
+//! Live range splitting pass.
+//!
+//! The spilling pass moves whole virtual registers to the stack, so a value that is live through a
+//! loop with high register pressure is spilled everywhere, even if it is only used outside the
+//! loop. This pass runs before spilling and splits such live ranges at the loop boundaries:
+//!
+//! ```clif
+//! ebb0:
+//!     v1 = ...
+//!     v10 = copy v1        ; Before the loop header.
+//!     jump ebb1
+//!
+//! ebb1:                    ; The loop doesn't use v1.
+//!     ...
+//!     brnz v5, ebb1
+//!     jump ebb2
+//!
+//! ebb2:
+//!     v11 = copy v10       ; At the loop exit.
+//!     v12 = iadd v11, v5   ; Was `iadd v1, v5`.
+//! ```
+//!
+//! The value `v10` covering the loop is assigned a stack affinity, and the reload pass turns the
+//! two copies into a `spill` and a `fill`. The original value and the value used after the loop
+//! can live in registers.
+//!
+//! Register pressure is measured for every loop, and only as many values as the pressure exceeds
+//! the number of available registers are split. The loop must have a single entry edge and a
+//! single exit EBB so the copies can be placed without rebuilding SSA form.
+
+use crate::cursor::{Cursor, EncCursor};
+use crate::dominator_tree::DominatorTree;
+use crate::entity::EntityRef;
+use crate::flowgraph::ControlFlowGraph;
+use crate::fx::{FxHashMap, FxHashSet};
+use crate::ir::{Ebb, Function, Inst, InstBuilder, InstructionData, Opcode, Value};
+use crate::isa::{RegInfo, TargetIsa};
+use crate::loop_analysis::{Loop, LoopAnalysis};
+use crate::regalloc::affinity::Affinity;
+use crate::regalloc::live_value_tracker::{LiveValue, LiveValueTracker};
+use crate::regalloc::liveness::Liveness;
+use crate::timing;
+use crate::topo_order::TopoOrder;
+use core::cmp;
+use log::debug;
+use std::vec::Vec;
+
+/// Persistent data structures for the live range splitting pass.
+pub struct Splitting {
+    loops: LoopAnalysis,
+
+    /// Number of usable registers in each top-level register class.
+    limits: Vec<u32>,
+
+    /// Number of live register values in each top-level register class.
+    counts: Vec<u32>,
+
+    /// The largest number of live register values in excess of the limit, indexed by loop and
+    /// top-level register class.
+    excess: FxHashMap<(Loop, u8), u32>,
+
+    /// Values created to cover a loop, which should be spilled.
+    spills: Vec<Value>,
+}
+
+/// Context data structure that gets instantiated once per pass.
+struct Context<'a> {
+    // Current instruction as well as reference to function and ISA.
+    cur: EncCursor<'a>,
+
+    // Cached ISA information.
+    reginfo: RegInfo,
+
+    // References to contextual data structures we need.
+    cfg: &'a ControlFlowGraph,
+    domtree: &'a DominatorTree,
+    liveness: &'a Liveness,
+    loops: &'a LoopAnalysis,
+    topo: &'a mut TopoOrder,
+
+    limits: &'a [u32],
+    counts: &'a mut Vec<u32>,
+    excess: &'a mut FxHashMap<(Loop, u8), u32>,
+    spills: &'a mut Vec<Value>,
+}
+
+impl Splitting {
+    /// Create a new live range splitting pass.
+    pub fn new() -> Self {
+        Self {
+            loops: LoopAnalysis::new(),
+            limits: Vec::new(),
+            counts: Vec::new(),
+            excess: FxHashMap(),
+            spills: Vec::new(),
+        }
+    }
+
+    /// Clear all data structures in this pass.
+    pub fn clear(&mut self) {
+        self.loops.clear();
+        self.limits.clear();
+        self.counts.clear();
+        self.excess.clear();
+        self.spills.clear();
+    }
+
+    /// Split the live ranges of values that are live through loops with high register pressure.
+    ///
+    /// If any live ranges were split, `liveness` is recomputed and the live value tracker is
+    /// cleared.
+    pub fn run(
+        &mut self,
+        isa: &TargetIsa,
+        func: &mut Function,
+        cfg: &ControlFlowGraph,
+        domtree: &DominatorTree,
+        liveness: &mut Liveness,
+        topo: &mut TopoOrder,
+        tracker: &mut LiveValueTracker,
+    ) {
+        let _tt = timing::ra_splitting();
+        self.clear();
+        self.loops.compute(func, cfg, domtree);
+        if self.loops.loops().next().is_none() {
+            return;
+        }
+        debug!("Splitting for:\n{}", func.display(isa));
+
+        let reginfo = isa.register_info();
+        let usable_regs = isa.allocatable_registers(func);
+        for rc in reginfo.classes {
+            let limit = if rc.index != rc.toprc {
+                0
+            } else if reginfo.banks[rc.bank as usize].pressure_tracking {
+                usable_regs.iter(rc).len() as u32
+            } else {
+                !0
+            };
+            self.limits.push(limit);
+        }
+        self.counts.resize(self.limits.len(), 0);
+
+        let mut ctx = Context {
+            cur: EncCursor::new(func, isa),
+            reginfo,
+            cfg,
+            domtree,
+            liveness,
+            loops: &self.loops,
+            topo,
+            limits: &self.limits,
+            counts: &mut self.counts,
+            excess: &mut self.excess,
+            spills: &mut self.spills,
+        };
+        ctx.run(tracker);
+
+        if !self.spills.is_empty() {
+            liveness.compute(isa, func, cfg);
+            for &value in self.spills.iter() {
+                liveness.spill(value);
+            }
+            tracker.clear();
+        }
+    }
+}
+
+impl<'a> Context<'a> {
+    fn run(&mut self, tracker: &mut LiveValueTracker) {
+        self.measure_pressure(tracker);
+
+        let mut excess: Vec<_> = self.excess.iter().map(|(&k, &n)| (k, n)).collect();
+        excess.sort_unstable_by_key(|&((lp, toprc), _)| (lp.index(), toprc));
+
+        // Values that have already been split. The live ranges are not updated until all the loops
+        // have been processed, so a value is only split around one loop.
+        let mut split = FxHashSet();
+        for ((lp, toprc), mut num) in excess {
+            let (entry, exit) = match self.loop_boundaries(lp) {
+                Some(b) => b,
+                None => continue,
+            };
+            debug!(
+                "{} has {} excess {} registers, entered at {}, exiting to {}",
+                lp, num, self.reginfo.classes[toprc as usize], entry, exit
+            );
+            for value in self.split_candidates(lp, toprc, &split) {
+                if num == 0 {
+                    break;
+                }
+                if self.split_value(value, entry, exit) {
+                    split.insert(value);
+                    num -= 1;
+                }
+            }
+        }
+    }
+
+    /// Record the excess register pressure in every loop.
+    fn measure_pressure(&mut self, tracker: &mut LiveValueTracker) {
+        self.topo.reset(self.cur.func.layout.ebbs());
+        while let Some(ebb) = self.topo.next(&self.cur.func.layout, self.domtree) {
+            let lp = self.loops.innermost_loop(ebb);
+            tracker.ebb_top(
+                ebb,
+                &self.cur.func.dfg,
+                self.liveness,
+                &self.cur.func.layout,
+                self.domtree,
+            );
+            if let Some(lp) = lp {
+                self.record_pressure(lp, tracker.live());
+            }
+            tracker.drop_dead_params();
+
+            let mut pos = self.cur.func.layout.first_inst(ebb);
+            while let Some(inst) = pos {
+                if self.cur.func.dfg[inst].opcode().is_ghost() {
+                    tracker.process_ghost(inst);
+                } else {
+                    tracker.process_inst(inst, &self.cur.func.dfg, self.liveness);
+                    if let Some(lp) = lp {
+                        self.record_pressure(lp, tracker.live());
+                    }
+                }
+                tracker.drop_dead(inst);
+                pos = self.cur.func.layout.next_inst(inst);
+            }
+        }
+    }
+
+    /// Count the register values in `live`, and record any excess for `lp`.
+    fn record_pressure(&mut self, lp: Loop, live: &[LiveValue]) {
+        for count in self.counts.iter_mut() {
+            *count = 0;
+        }
+        for lv in live {
+            if let Affinity::Reg(rci) = lv.affinity {
+                self.counts[self.reginfo.rc(rci).toprc as usize] += 1;
+            }
+        }
+        for (toprc, (&count, &limit)) in self.counts.iter().zip(self.limits).enumerate() {
+            if count > limit {
+                let excess = self.excess.entry((lp, toprc as u8)).or_insert(0);
+                *excess = cmp::max(*excess, count - limit);
+            }
+        }
+    }
+
+    /// Find the branch entering `lp` from outside and the EBB that `lp` exits to.
+    ///
+    /// Returns `None` unless the loop has a single entry edge and a single exit EBB whose
+    /// predecessors are all in the loop.
+    fn loop_boundaries(&self, lp: Loop) -> Option<(Inst, Ebb)> {
+        let header = self.loops.loop_header(lp);
+        let mut entry = None;
+        for pred in self.cfg.pred_iter(header) {
+            if !self.loops.is_in_loop(pred.ebb, lp) {
+                if entry.is_some() {
+                    return None;
+                }
+                entry = Some(pred.inst);
+            }
+        }
+
+        let mut exit = None;
+        for ebb in self.cur.func.layout.ebbs() {
+            if !self.loops.is_in_loop(ebb, lp) {
+                continue;
+            }
+            for succ in self.cfg.succ_iter(ebb) {
+                if !self.loops.is_in_loop(succ, lp) && exit != Some(succ) {
+                    if exit.is_some() {
+                        return None;
+                    }
+                    exit = Some(succ);
+                }
+            }
+        }
+
+        let exit = exit?;
+        if self
+            .cfg
+            .pred_iter(exit)
+            .any(|pred| !self.loops.is_in_loop(pred.ebb, lp))
+        {
+            return None;
+        }
+        Some((entry?, exit))
+    }
+
+    /// Get the values in the `toprc` register class that are live through `lp` without being used
+    /// in the loop, skipping values in `split`.
+    fn split_candidates(&self, lp: Loop, toprc: u8, split: &FxHashSet<Value>) -> Vec<Value> {
+        let dfg = &self.cur.func.dfg;
+        let layout = &self.cur.func.layout;
+        let header = self.loops.loop_header(lp);
+
+        let mut used = FxHashSet();
+        for ebb in layout.ebbs().filter(|&ebb| self.loops.is_in_loop(ebb, lp)) {
+            for inst in layout.ebb_insts(ebb) {
+                used.extend(dfg.inst_args(inst).iter().cloned());
+            }
+        }
+
+        let ctx = self.liveness.context(layout);
+        dfg.values()
+            .filter(|value| {
+                let lr = match self.liveness.get(*value) {
+                    Some(lr) => lr,
+                    None => return false,
+                };
+                match lr.affinity {
+                    Affinity::Reg(rci) if self.reginfo.rc(rci).toprc == toprc => {}
+                    _ => return false,
+                }
+                let ty = dfg.value_type(*value);
+                lr.is_livein(header, ctx)
+                    && !used.contains(value)
+                    && !split.contains(value)
+                    && !ty.is_flags()
+                    && self
+                        .cur
+                        .isa
+                        .encode(
+                            &self.cur.func,
+                            &InstructionData::Unary {
+                                opcode: Opcode::Copy,
+                                arg: *value,
+                            },
+                            ty,
+                        )
+                        .is_ok()
+            })
+            .collect()
+    }
+
+    /// Split the live range of `value` with copies before the `entry` branch and at the top of
+    /// the `exit` EBB.
+    ///
+    /// Returns `false` if `value` isn't used after the loop.
+    fn split_value(&mut self, value: Value, entry: Inst, exit: Ebb) -> bool {
+        // Find the uses after the loop. They are all dominated by the exit EBB.
+        let mut uses = Vec::new();
+        for ebb in self.cur.func.layout.ebbs() {
+            if !self.domtree.dominates(exit, ebb, &self.cur.func.layout) {
+                continue;
+            }
+            for inst in self.cur.func.layout.ebb_insts(ebb) {
+                if self.cur.func.dfg.inst_args(inst).contains(&value) {
+                    uses.push(inst);
+                }
+            }
+        }
+        if uses.is_empty() {
+            return false;
+        }
+
+        self.cur.goto_inst(entry);
+        let stack = self.cur.ins().copy(value);
+        self.cur.goto_first_inst(exit);
+        let reg = self.cur.ins().copy(stack);
+        debug!("Split {} into {} and {} around the loop", value, stack, reg);
+
+        for inst in uses {
+            for arg in self.cur.func.dfg.inst_args_mut(inst) {
+                if *arg == value {
+                    *arg = reg;
+                }
+            }
+        }
+        self.spills.push(stack);
+        true
+    }
+}
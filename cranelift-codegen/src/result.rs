@@ -1,7 +1,9 @@
 //! Result and error types representing the outcome of compiling a function.
 
+use crate::ir::{Inst, SourceLoc, StackSlot};
 use crate::verifier::VerifierErrors;
 use failure_derive::Fail;
+use std::string::String;
 
 /// A compilation error.
 ///
@@ -30,6 +32,31 @@ pub enum CodegenError {
     /// is exceeded, compilation fails.
     #[fail(display = "Code for function is too large")]
     CodeTooLarge,
+
+    /// An instruction can't be compiled for the target ISA.
+    ///
+    /// The instruction uses an operation, a type or an immediate that the target ISA has no
+    /// encoding for, and the legalizer doesn't know how to rewrite it into supported
+    /// instructions.
+    #[fail(
+        display = "Unsupported instruction {} at {}: {}",
+        inst, srcloc, message
+    )]
+    Unsupported {
+        /// The unsupported instruction.
+        inst: Inst,
+        /// The source location of `inst`.
+        srcloc: SourceLoc,
+        /// A description of the instruction.
+        message: String,
+    },
+
+    /// The stack frame of the function is too large.
+    ///
+    /// Each stack slot and the whole stack frame must fit in 2 GB, so that any offset in the frame
+    /// fits in a `StackOffset`. This holds the first stack slot that doesn't fit.
+    #[fail(display = "Stack frame too large at {}", _0)]
+    StackFrameTooLarge(StackSlot),
 }
 
 /// A convenient alias for a `Result` that uses `CodegenError` as the error type.
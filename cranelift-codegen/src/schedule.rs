@@ -0,0 +1,308 @@
+//! Instruction scheduling.
+//!
+//! This pass runs after register allocation and reorders the instructions within each EBB so the
+//! results of long-latency instructions like loads are not used right away. In-order cores stall
+//! until the operands of an instruction are available, so moving independent instructions into
+//! the gap hides the latency. The latencies come from the encoding recipes of the target ISA.
+//!
+//! Each EBB is split into regions by barrier instructions which are never moved: branches, calls,
+//! instructions with side effects, and the register moves that change the register diversions.
+//! Within a region, the instructions are ordered by a list scheduler which respects the
+//! dependencies through registers, stack slots, memory and CPU flags.
+
+use crate::fx::FxHashMap;
+use crate::ir::{Ebb, Function, Inst, InstructionData, StackSlot, ValueLoc};
+use crate::isa::{RegUnit, TargetIsa};
+use crate::regalloc::RegDiversions;
+use crate::timing;
+use log::debug;
+use std::vec::Vec;
+
+/// Reorder the instructions in each EBB of `func` to hide instruction latencies.
+pub fn schedule_function(func: &mut Function, isa: &TargetIsa) {
+    let _tt = timing::schedule();
+    let mut divert = RegDiversions::new();
+    let mut region = Vec::new();
+
+    let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+    for ebb in ebbs {
+        divert.clear();
+        let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+        for inst in insts {
+            if is_barrier(func, inst) {
+                // The diversions don't change within a region, so the locations of its values are
+                // the ones before the barrier.
+                if region.len() > 1 {
+                    schedule_region(func, isa, &divert, &region, inst);
+                }
+                region.clear();
+                divert.apply(&func.dfg[inst]);
+            } else {
+                region.push(inst);
+            }
+        }
+        debug_assert!(region.is_empty(), "{} doesn't end with a terminator", ebb);
+    }
+}
+
+/// Must `inst` stay where it is?
+fn is_barrier(func: &Function, inst: Inst) -> bool {
+    let opcode = func.dfg[inst].opcode();
+    if opcode.is_branch()
+        || opcode.is_terminator()
+        || opcode.is_call()
+        || opcode.other_side_effects()
+        || !func.encodings[inst].is_legal()
+    {
+        return true;
+    }
+    match func.dfg[inst] {
+        InstructionData::RegMove { .. }
+        | InstructionData::RegSpill { .. }
+        | InstructionData::RegFill { .. }
+        | InstructionData::CopySpecial { .. } => true,
+        _ => false,
+    }
+}
+
+/// A location that instructions can read or write.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Location {
+    Reg(RegUnit),
+    Stack(StackSlot),
+}
+
+impl Location {
+    fn new(loc: ValueLoc) -> Option<Self> {
+        match loc {
+            ValueLoc::Reg(reg) => Some(Location::Reg(reg)),
+            ValueLoc::Stack(ss) => Some(Location::Stack(ss)),
+            ValueLoc::Unassigned => None,
+        }
+    }
+}
+
+/// An instruction in the dependency graph of a region.
+struct Node {
+    inst: Inst,
+    /// Cycles before the results of `inst` are available.
+    latency: u32,
+    /// The nodes depending on this one, with the number of cycles they must wait for it.
+    succs: Vec<(usize, u32)>,
+    /// Number of nodes this one depends on that haven't been scheduled yet.
+    num_preds: usize,
+    /// Length of the longest chain of latencies from this node to the end of the region.
+    height: u32,
+    /// The earliest cycle this node can issue without stalling.
+    ready: u32,
+}
+
+/// Builder for the dependency graph of a region.
+struct Dependencies {
+    nodes: Vec<Node>,
+    /// The last node writing each location, and the nodes reading it since.
+    writers: FxHashMap<Location, usize>,
+    readers: FxHashMap<Location, Vec<usize>>,
+    /// The last node storing to memory or trapping, and the loads since.
+    last_store: Option<usize>,
+    loads: Vec<usize>,
+    /// The last node defining or using a CPU flags value, and the nodes clobbering the flags
+    /// since.
+    last_flags: Option<usize>,
+    clobbers: Vec<usize>,
+}
+
+impl Dependencies {
+    fn add_edge(&mut self, from: usize, to: usize, latency: u32) {
+        self.nodes[from].succs.push((to, latency));
+        self.nodes[to].num_preds += 1;
+    }
+
+    fn read(&mut self, node: usize, loc: Location) {
+        if let Some(&writer) = self.writers.get(&loc) {
+            let latency = self.nodes[writer].latency;
+            self.add_edge(writer, node, latency);
+        }
+        self.readers.entry(loc).or_insert_with(Vec::new).push(node);
+    }
+
+    fn write(&mut self, node: usize, loc: Location) {
+        if let Some(writer) = self.writers.insert(loc, node) {
+            self.add_edge(writer, node, 0);
+        }
+        for reader in self.readers.remove(&loc).unwrap_or_default() {
+            if reader != node {
+                self.add_edge(reader, node, 0);
+            }
+        }
+    }
+
+    fn load(&mut self, node: usize) {
+        if let Some(store) = self.last_store {
+            self.add_edge(store, node, 0);
+        }
+        self.loads.push(node);
+    }
+
+    fn store(&mut self, node: usize) {
+        if let Some(store) = self.last_store {
+            self.add_edge(store, node, 0);
+        }
+        for load in core::mem::replace(&mut self.loads, Vec::new()) {
+            if load != node {
+                self.add_edge(load, node, 0);
+            }
+        }
+        self.last_store = Some(node);
+    }
+
+    fn use_flags(&mut self, node: usize) {
+        if let Some(last) = self.last_flags {
+            self.add_edge(last, node, 0);
+        }
+        for clobber in core::mem::replace(&mut self.clobbers, Vec::new()) {
+            self.add_edge(clobber, node, 0);
+        }
+        self.last_flags = Some(node);
+    }
+
+    fn clobber_flags(&mut self, node: usize) {
+        if let Some(last) = self.last_flags {
+            self.add_edge(last, node, 0);
+        }
+        self.clobbers.push(node);
+    }
+}
+
+/// Build the dependency graph of the instructions in `region`.
+fn build_graph(
+    func: &Function,
+    isa: &TargetIsa,
+    divert: &RegDiversions,
+    region: &[Inst],
+) -> Vec<Node> {
+    let encinfo = isa.encoding_info();
+    let mut deps = Dependencies {
+        nodes: Vec::with_capacity(region.len()),
+        writers: FxHashMap(),
+        readers: FxHashMap(),
+        last_store: None,
+        loads: Vec::new(),
+        last_flags: None,
+        clobbers: Vec::new(),
+    };
+
+    for (node, &inst) in region.iter().enumerate() {
+        let enc = func.encodings[inst];
+        deps.nodes.push(Node {
+            inst,
+            latency: encinfo.latency(enc),
+            succs: Vec::new(),
+            num_preds: 0,
+            height: 0,
+            ready: 0,
+        });
+
+        let mut uses_flags = false;
+        for &arg in func.dfg.inst_args(inst) {
+            uses_flags |= func.dfg.value_type(arg).is_flags();
+            if let Some(loc) = Location::new(divert.get(arg, &func.locations)) {
+                deps.read(node, loc);
+            }
+        }
+        for &result in func.dfg.inst_results(inst) {
+            uses_flags |= func.dfg.value_type(result).is_flags();
+            if let Some(loc) = Location::new(func.locations[result]) {
+                deps.write(node, loc);
+            }
+        }
+
+        let opcode = func.dfg[inst].opcode();
+        if opcode.can_store() || opcode.can_trap() {
+            deps.store(node);
+        } else if opcode.can_load() {
+            deps.load(node);
+        }
+
+        if uses_flags {
+            deps.use_flags(node);
+        } else if opcode.writes_cpu_flags()
+            || encinfo
+                .operand_constraints(enc)
+                .map_or(true, |c| c.clobbers_flags)
+        {
+            deps.clobber_flags(node);
+        }
+    }
+
+    // The nodes only depend on earlier nodes, so the heights can be computed backwards.
+    let mut nodes = deps.nodes;
+    for node in (0..nodes.len()).rev() {
+        let height = nodes[node]
+            .succs
+            .iter()
+            .map(|&(succ, _)| nodes[succ].height)
+            .max()
+            .unwrap_or(0);
+        nodes[node].height = height + nodes[node].latency;
+    }
+    nodes
+}
+
+/// Reorder the instructions in `region`, which are all followed by `barrier`.
+fn schedule_region(
+    func: &mut Function,
+    isa: &TargetIsa,
+    divert: &RegDiversions,
+    region: &[Inst],
+    barrier: Inst,
+) {
+    let mut nodes = build_graph(func, isa, divert, region);
+    let mut ready: Vec<usize> = (0..nodes.len())
+        .filter(|&node| nodes[node].num_preds == 0)
+        .collect();
+    let mut order = Vec::with_capacity(nodes.len());
+    let mut cycle = 0;
+
+    while !ready.is_empty() {
+        // Pick the instruction that can issue first, then the one on the longest path to the end
+        // of the region. Ties keep the original order.
+        let pos = (0..ready.len())
+            .min_by_key(|&pos| {
+                let node = &nodes[ready[pos]];
+                (
+                    node.ready.max(cycle),
+                    core::u32::MAX - node.height,
+                    ready[pos],
+                )
+            })
+            .unwrap();
+        let node = ready.swap_remove(pos);
+        let issue = nodes[node].ready.max(cycle);
+        cycle = issue + 1;
+        order.push(nodes[node].inst);
+
+        for i in 0..nodes[node].succs.len() {
+            let (succ, latency) = nodes[node].succs[i];
+            let succ_node = &mut nodes[succ];
+            succ_node.ready = succ_node.ready.max(issue + latency);
+            succ_node.num_preds -= 1;
+            if succ_node.num_preds == 0 {
+                ready.push(succ);
+            }
+        }
+    }
+    debug_assert_eq!(order.len(), region.len(), "Cycle in the dependency graph");
+
+    if order[..] != region[..] {
+        debug!(
+            "Scheduling {} instructions before {}",
+            order.len(),
+            func.dfg.display_inst(barrier, isa)
+        );
+        for inst in order {
+            func.layout.remove_inst(inst);
+            func.layout.insert_inst(inst, barrier);
+        }
+    }
+}
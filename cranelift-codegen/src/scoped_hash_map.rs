@@ -93,6 +93,11 @@ where
         }
     }
 
+    /// Gets a reference to the value of `key`, if it is visible in the current scope.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key).map(|entry| &entry.value)
+    }
+
     /// Enter a new scope.
     pub fn increment_depth(&mut self) {
         // Increment the depth.
@@ -210,6 +215,8 @@ mod tests {
         map.decrement_depth();
         map.decrement_depth();
         map.decrement_depth();
+        assert_eq!(map.get(&2), Some(&8));
+        assert_eq!(map.get(&1), None);
         match map.entry(2) {
             Entry::Occupied(entry) => assert!(*entry.get() == 8),
             Entry::Vacant(_entry) => panic!(),
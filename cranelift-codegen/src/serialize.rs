@@ -0,0 +1,1179 @@
+//! Binary serialization of functions.
+//!
+//! `serialize_function` encodes a `Function` in a compact binary format, and `deserialize_function`
+//! decodes it again. This is much faster than printing and parsing the text format, so it can be
+//! used to cache IR between compiler invocations or to ship it to another process.
+//!
+//! The encoding covers the function name and signature, the preamble entities, the data flow
+//! graph, the layout, the source locations and the branch probabilities. Entity numbers are
+//! preserved, so the decoded function prints exactly like the original. Only the EBBs and
+//! instructions in the layout are encoded, and the removed entities are replaced by placeholders
+//! to keep the numbering.
+//!
+//! The results of code generation are not encoded: instruction encodings, value locations, EBB
+//! offsets and the stack frame layout. Functions should be serialized before they are compiled.
+//!
+//! The format depends on the instruction set of this version of Cranelift, so the encoding starts
+//! with the version number and data serialized by a different version is rejected.
+
+use crate::entity::{EntityRef, EntitySet};
+use crate::ir::condcodes::{FloatCC, IntCC};
+use crate::ir::immediates::{Ieee32, Ieee64, Imm64, Offset32, Uimm32, Uimm64};
+use crate::ir::{
+    AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, AtomicRmwOp, BranchProbability, Ebb,
+    ExtFuncData, ExternalName, FuncRef, Function, GlobalValue, GlobalValueData, Heap, HeapData,
+    HeapStyle, Inst, InstructionData, JumpTable, JumpTableData, LibCall, MemFlags, Opcode, SigRef,
+    Signature, SourceLoc, StackSlot, StackSlotData, StackSlotKind, Table, TableData, TrapCode,
+    Type, Value, ValueList, ValueListPool,
+};
+use crate::isa::CallConv;
+use core::str;
+use core::u16;
+use failure_derive::Fail;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// The bytes at the start of every serialized function.
+const MAGIC: &[u8] = b"clif";
+
+/// An error found while deserializing a function.
+#[derive(Fail, Debug, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// The data doesn't start with a serialized function header.
+    #[fail(display = "Not a serialized function")]
+    BadHeader,
+
+    /// The function was serialized by a different version of Cranelift.
+    #[fail(display = "Function serialized by an incompatible version of Cranelift")]
+    VersionMismatch,
+
+    /// The data ended in the middle of the function.
+    #[fail(display = "Unexpected end of data")]
+    UnexpectedEnd,
+
+    /// The data contains an invalid encoding of the named item.
+    #[fail(display = "Invalid {}", _0)]
+    Invalid(&'static str),
+}
+
+/// A convenient alias for a `Result` that uses `DeserializeError` as the error type.
+pub type DeserializeResult<T> = Result<T, DeserializeError>;
+
+/// Serialize `func` to a compact binary format.
+pub fn serialize_function(func: &Function) -> Vec<u8> {
+    let mut enc = Encoder::new();
+    enc.data.extend_from_slice(MAGIC);
+    enc.put(crate::VERSION);
+
+    enc.put(&func.name);
+    enc.put(&func.signature);
+    enc.put_iter(func.stack_slots.values());
+    enc.put_iter(func.global_values.values());
+    enc.put_iter(func.heaps.values());
+    enc.put_iter(func.tables.values());
+    enc.put_iter(func.jump_tables.values());
+    enc.put_iter(func.dfg.signatures.values());
+    enc.put_iter(func.dfg.ext_funcs.values());
+
+    let dfg = &func.dfg;
+    enc.put_len(dfg.num_ebbs());
+    enc.put_len(dfg.num_insts());
+    enc.put_len(dfg.num_values());
+
+    // The EBBs and instructions in layout order, along with the values they define.
+    let mut defined = EntitySet::new();
+    enc.put_len(func.layout.ebbs().count());
+    for ebb in func.layout.ebbs() {
+        enc.put(&ebb);
+        enc.put(&func.layout.is_cold(ebb));
+        enc.put(&func.layout.is_osr_entry(ebb));
+        enc.put_len(dfg.num_ebb_params(ebb));
+        for &param in dfg.ebb_params(ebb) {
+            enc.put(&param);
+            enc.put(&dfg.value_type(param));
+            defined.insert(param);
+        }
+        enc.put_len(func.layout.ebb_insts(ebb).count());
+        for inst in func.layout.ebb_insts(ebb) {
+            enc.put(&inst);
+            enc.put(&(dfg[inst].opcode() as u16));
+            dfg[inst].encode_operands(&mut enc, &dfg.value_lists);
+            enc.put(&dfg.ctrl_typevar(inst));
+            enc.put(dfg.inst_results(inst));
+            enc.put(&func.srclocs[inst]);
+            enc.put(&func.branch_probabilities[inst]);
+            enc.put(&func.memory_trap_codes[inst]);
+            for &result in dfg.inst_results(inst) {
+                defined.insert(result);
+            }
+        }
+    }
+
+    // The aliases of the values defined above.
+    let aliases: Vec<(Value, Value)> = dfg
+        .values()
+        .filter_map(|v| {
+            let original = dfg.value_alias_dest_for_serialization(v)?;
+            if defined.contains(resolve_alias(func, original)?) {
+                Some((v, original))
+            } else {
+                None
+            }
+        })
+        .collect();
+    enc.put_len(aliases.len());
+    for (alias, original) in aliases {
+        enc.put(&alias);
+        enc.put(&original);
+    }
+
+    enc.finish()
+}
+
+/// Get the EBB parameter or instruction result that the alias `v` refers to.
+///
+/// Returns `None` if `v` is an unresolved placeholder or part of an alias cycle.
+fn resolve_alias(func: &Function, mut v: Value) -> Option<Value> {
+    for _ in 0..func.dfg.num_values() {
+        match func.dfg.value_alias_dest_for_serialization(v) {
+            Some(original) if original == Value::reserved_value() => return None,
+            Some(original) => v = original,
+            None => return Some(v),
+        }
+    }
+    None
+}
+
+/// Deserialize a function serialized by `serialize_function`.
+///
+/// Data that wasn't produced by `serialize_function` can decode to an invalid function, so the
+/// result should be verified if the data comes from an untrusted source.
+pub fn deserialize_function(data: &[u8]) -> DeserializeResult<Function> {
+    if !data.starts_with(MAGIC) {
+        return Err(DeserializeError::BadHeader);
+    }
+    let mut dec = Decoder::new(&data[MAGIC.len()..]);
+    if dec.get_str()? != crate::VERSION {
+        return Err(DeserializeError::VersionMismatch);
+    }
+
+    let mut func = Function::with_name_signature(dec.get()?, dec.get()?);
+    for _ in 0..dec.get_len()? {
+        func.create_stack_slot(dec.get()?);
+    }
+    for _ in 0..dec.get_len()? {
+        func.create_global_value(dec.get()?);
+    }
+    for gv in func.global_values.values() {
+        match *gv {
+            GlobalValueData::Load { base, .. } | GlobalValueData::IAddImm { base, .. }
+                if !func.global_values.is_valid(base) =>
+            {
+                return Err(DeserializeError::Invalid("global value"));
+            }
+            _ => {}
+        }
+    }
+    for _ in 0..dec.get_len()? {
+        func.create_heap(dec.get()?);
+    }
+    for _ in 0..dec.get_len()? {
+        func.create_table(dec.get()?);
+    }
+    for _ in 0..dec.get_len()? {
+        func.create_jump_table(dec.get()?);
+    }
+    for _ in 0..dec.get_len()? {
+        func.import_signature(dec.get()?);
+    }
+    for _ in 0..dec.get_len()? {
+        let data: ExtFuncData = dec.get()?;
+        if !func.dfg.signatures.is_valid(data.signature) {
+            return Err(DeserializeError::Invalid("external function"));
+        }
+        func.import_function(data);
+    }
+
+    // Create all the entities up front so the numbering is preserved. The values are invalid
+    // placeholders until they are defined, and the instructions are overwritten when decoded.
+    // The counts are bounded by the remaining data like other lengths, so a corrupt count can't
+    // cause a huge allocation.
+    let num_ebbs = dec.get_len()?;
+    let num_insts = dec.get_len()?;
+    let num_values = dec.get_len()?;
+    for _ in 0..num_ebbs {
+        func.dfg.make_ebb();
+    }
+    for _ in 0..num_insts {
+        func.dfg.make_inst(InstructionData::NullAry {
+            opcode: Opcode::Nop,
+        });
+    }
+    for _ in 0..num_values {
+        func.dfg.make_invalid_value_for_parser();
+    }
+
+    let mut defined = EntitySet::new();
+    let mut define = |v: Value| {
+        if v.index() < num_values && !defined.contains(v) {
+            defined.insert(v);
+            Ok(v)
+        } else {
+            Err(DeserializeError::Invalid("value definition"))
+        }
+    };
+
+    let mut results = Vec::new();
+    for _ in 0..dec.get_len()? {
+        let ebb: Ebb = dec.get()?;
+        if ebb.index() >= num_ebbs || func.layout.is_ebb_inserted(ebb) {
+            return Err(DeserializeError::Invalid("EBB"));
+        }
+        func.layout.append_ebb(ebb);
+        func.layout.set_cold(ebb, dec.get()?);
+        func.layout.set_osr_entry(ebb, dec.get()?);
+        let num_params = dec.get_len()?;
+        if num_params > usize::from(u16::MAX) {
+            return Err(DeserializeError::Invalid("EBB parameters"));
+        }
+        for _ in 0..num_params {
+            let param = define(dec.get()?)?;
+            let ty = dec.get()?;
+            func.dfg.append_ebb_param_for_parser(ebb, ty, param);
+        }
+
+        for _ in 0..dec.get_len()? {
+            let inst: Inst = dec.get()?;
+            if inst.index() >= num_insts || func.layout.inst_ebb(inst).is_some() {
+                return Err(DeserializeError::Invalid("instruction"));
+            }
+            let opcode =
+                Opcode::from_number(dec.get()?).ok_or(DeserializeError::Invalid("opcode"))?;
+            let data =
+                InstructionData::decode_operands(opcode, &mut dec, &mut func.dfg.value_lists)?;
+            check_call(&func, &data)?;
+            func.dfg[inst] = data;
+            let ctrl_typevar = dec.get()?;
+            // The result types are computed from the controlling type, which must be one the
+            // opcode accepts.
+            if let Some(typeset) = opcode.constraints().ctrl_typeset() {
+                if !typeset.contains(ctrl_typevar) {
+                    return Err(DeserializeError::Invalid("controlling type"));
+                }
+            }
+            results.clear();
+            for _ in 0..dec.get_len()? {
+                results.push(define(dec.get()?)?);
+            }
+            let num_results = func
+                .dfg
+                .make_inst_results_for_parser(inst, ctrl_typevar, &results);
+            if num_results != results.len() {
+                return Err(DeserializeError::Invalid("instruction results"));
+            }
+            func.layout.append_inst(inst, ebb);
+            func.srclocs[inst] = dec.get()?;
+            func.branch_probabilities[inst] = dec.get()?;
+            func.memory_trap_codes[inst] = dec.get()?;
+        }
+    }
+
+    let mut aliases = Vec::new();
+    for _ in 0..dec.get_len()? {
+        let alias = define(dec.get()?)?;
+        let original: Value = dec.get()?;
+        if original.index() >= num_values {
+            return Err(DeserializeError::Invalid("alias"));
+        }
+        func.dfg.make_value_alias_for_serialization(original, alias);
+        aliases.push(alias);
+    }
+    for alias in aliases {
+        if !func.dfg.set_alias_type_for_parser(alias) {
+            return Err(DeserializeError::Invalid("alias"));
+        }
+    }
+
+    if !dec.is_done() {
+        return Err(DeserializeError::Invalid("trailing data"));
+    }
+    Ok(func)
+}
+
+/// Check the callee of a call instruction, which is needed to create the results of `data`.
+///
+/// The other entity references of an instruction aren't followed while decoding, and the verifier
+/// reports them if they are invalid.
+fn check_call(func: &Function, data: &InstructionData) -> DeserializeResult<()> {
+    let valid = match *data {
+        InstructionData::Call { func_ref, .. } | InstructionData::TryCall { func_ref, .. } => {
+            func.dfg.ext_funcs.is_valid(func_ref)
+        }
+        // The callee is the first argument.
+        InstructionData::CallIndirect {
+            sig_ref, ref args, ..
+        } => func.dfg.signatures.is_valid(sig_ref) && !args.is_empty(),
+        _ => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(DeserializeError::Invalid("call"))
+    }
+}
+
+/// Encoder for the binary format.
+///
+/// Integers are encoded as LEB128 variable-length integers, and signed integers are zigzag
+/// encoded first so small negative numbers stay small.
+pub(crate) struct Encoder {
+    data: Vec<u8>,
+}
+
+impl Encoder {
+    /// Create an encoder with no data.
+    pub fn new() -> Self {
+        Self { data: Vec::new() }
+    }
+
+    /// Get the encoded data.
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Encode `value`.
+    pub fn put<T: Encode + ?Sized>(&mut self, value: &T) {
+        value.encode(self)
+    }
+
+    /// Encode a length or count.
+    fn put_len(&mut self, len: usize) {
+        self.put_u64(len as u64)
+    }
+
+    /// Encode a sequence of items with its length.
+    fn put_iter<'b, T, I>(&mut self, iter: I)
+    where
+        T: Encode + 'b,
+        I: ExactSizeIterator<Item = &'b T>,
+    {
+        self.put_len(iter.len());
+        for item in iter {
+            self.put(item);
+        }
+    }
+
+    fn put_u8(&mut self, byte: u8) {
+        self.data.push(byte)
+    }
+
+    fn put_u64(&mut self, mut x: u64) {
+        while x >= 0x80 {
+            self.data.push(x as u8 | 0x80);
+            x >>= 7;
+        }
+        self.data.push(x as u8)
+    }
+
+    fn put_i64(&mut self, x: i64) {
+        self.put_u64(((x << 1) ^ (x >> 63)) as u64)
+    }
+}
+
+/// Decoder for the binary format.
+pub(crate) struct Decoder<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    /// Create a decoder reading `data`.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    /// Has all the data been decoded?
+    pub fn is_done(&self) -> bool {
+        self.pos == self.data.len()
+    }
+
+    /// Decode a value of type `T`.
+    pub fn get<T: Decode>(&mut self) -> DeserializeResult<T> {
+        T::decode(self)
+    }
+
+    /// Decode a value list, allocating it from `pool`.
+    pub fn get_value_list(&mut self, pool: &mut ValueListPool) -> DeserializeResult<ValueList> {
+        let mut list = ValueList::new();
+        for _ in 0..self.get_len()? {
+            list.push(self.get()?, pool);
+        }
+        Ok(list)
+    }
+
+    /// Decode a length or count.
+    fn get_len(&mut self) -> DeserializeResult<usize> {
+        let len = self.get_u64()?;
+        // Every item takes at least one byte, so a longer length can't be valid.
+        if len > (self.data.len() - self.pos) as u64 {
+            return Err(DeserializeError::UnexpectedEnd);
+        }
+        Ok(len as usize)
+    }
+
+    fn get_bytes(&mut self, len: usize) -> DeserializeResult<&'a [u8]> {
+        if len > self.data.len() - self.pos {
+            return Err(DeserializeError::UnexpectedEnd);
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn get_str(&mut self) -> DeserializeResult<&'a str> {
+        let len = self.get_len()?;
+        str::from_utf8(self.get_bytes(len)?).map_err(|_| DeserializeError::Invalid("string"))
+    }
+
+    fn get_u8(&mut self) -> DeserializeResult<u8> {
+        Ok(self.get_bytes(1)?[0])
+    }
+
+    fn get_u64(&mut self) -> DeserializeResult<u64> {
+        let mut x = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.get_u8()?;
+            if shift == 63 && byte > 1 {
+                return Err(DeserializeError::Invalid("integer"));
+            }
+            x |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(x);
+            }
+            shift += 7;
+            if shift > 63 {
+                return Err(DeserializeError::Invalid("integer"));
+            }
+        }
+    }
+
+    fn get_i64(&mut self) -> DeserializeResult<i64> {
+        let x = self.get_u64()?;
+        Ok((x >> 1) as i64 ^ -((x & 1) as i64))
+    }
+}
+
+/// A type that can be encoded in the binary format.
+pub(crate) trait Encode {
+    /// Encode `self` with `enc`.
+    fn encode(&self, enc: &mut Encoder);
+}
+
+/// A type that can be decoded from the binary format.
+pub(crate) trait Decode: Sized {
+    /// Decode a value with `dec`.
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self>;
+}
+
+impl Encode for u8 {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_u8(*self)
+    }
+}
+
+impl Decode for u8 {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        dec.get_u8()
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_u8(*self as u8)
+    }
+}
+
+impl Decode for bool {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        match dec.get_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DeserializeError::Invalid("bool")),
+        }
+    }
+}
+
+/// Implement `Encode` and `Decode` for integer types as variable-length integers.
+macro_rules! varint {
+    ($put:ident, $get:ident, $wide:ty: $($int:ty),*) => {
+        $(
+            impl Encode for $int {
+                fn encode(&self, enc: &mut Encoder) {
+                    enc.$put(<$wide>::from(*self))
+                }
+            }
+
+            impl Decode for $int {
+                fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+                    let x = dec.$get()?;
+                    if x as $int as $wide == x {
+                        Ok(x as $int)
+                    } else {
+                        Err(DeserializeError::Invalid("integer"))
+                    }
+                }
+            }
+        )*
+    };
+}
+
+varint!(put_u64, get_u64, u64: u16, u32, u64);
+varint!(put_i64, get_i64, i64: i32, i64);
+
+impl Encode for str {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_len(self.len());
+        enc.data.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_iter(self.iter())
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        let len = dec.get_len()?;
+        let mut vec = Vec::with_capacity(len);
+        for _ in 0..len {
+            vec.push(dec.get()?);
+        }
+        Ok(vec)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            None => enc.put_u8(0),
+            Some(ref x) => {
+                enc.put_u8(1);
+                enc.put(x);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        match dec.get_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(dec.get()?)),
+            _ => Err(DeserializeError::Invalid("option")),
+        }
+    }
+}
+
+/// Implement `Encode` and `Decode` for entity references as their index.
+macro_rules! entity {
+    ($($entity:ident),*) => {
+        $(
+            impl Encode for $entity {
+                fn encode(&self, enc: &mut Encoder) {
+                    enc.put_len(self.index())
+                }
+            }
+
+            impl Decode for $entity {
+                fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+                    Ok($entity::new(dec.get::<u32>()? as usize))
+                }
+            }
+        )*
+    };
+}
+
+entity!(
+    Ebb,
+    Inst,
+    Value,
+    StackSlot,
+    GlobalValue,
+    Heap,
+    Table,
+    JumpTable,
+    SigRef,
+    FuncRef
+);
+
+/// Implement `Encode` and `Decode` for types that are converted to and from the integer type
+/// `$int` with `$to` and `$from`.
+macro_rules! convert {
+    ($($ty:ty: $int:ty, $to:expr, $from:expr;)*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, enc: &mut Encoder) {
+                    let x: $int = $to(*self);
+                    enc.put(&x)
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+                    let x: $int = dec.get()?;
+                    Ok($from(x))
+                }
+            }
+        )*
+    };
+}
+
+convert! {
+    Type: u8, |ty: Type| ty.index() as u8, Type::from_index;
+    Imm64: i64, Imm64::into, Imm64::from;
+    Uimm32: u32, Uimm32::into, Uimm32::from;
+    Uimm64: u64, Uimm64::into, Uimm64::from;
+    Offset32: i32, Offset32::into, Offset32::from;
+    Ieee32: u32, Ieee32::bits, Ieee32::with_bits;
+    Ieee64: u64, Ieee64::bits, Ieee64::with_bits;
+    MemFlags: u8, MemFlags::bits, MemFlags::with_bits;
+    SourceLoc: u32, SourceLoc::bits, SourceLoc::new;
+}
+
+/// Implement `Encode` and `Decode` for types that are converted to and from strings with
+/// `Display` and `FromStr`.
+macro_rules! by_name {
+    ($($ty:ident),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode(&self, enc: &mut Encoder) {
+                    enc.put(self.to_string().as_str())
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+                    dec.get_str()?
+                        .parse()
+                        .map_err(|_| DeserializeError::Invalid(stringify!($ty)))
+                }
+            }
+        )*
+    };
+}
+
+by_name!(
+    IntCC,
+    FloatCC,
+    TrapCode,
+    AtomicRmwOp,
+    CallConv,
+    ArgumentPurpose,
+    StackSlotKind,
+    LibCall
+);
+
+impl Encode for BranchProbability {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_u8(self.percent().unwrap_or(!0))
+    }
+}
+
+impl Decode for BranchProbability {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        match dec.get_u8()? {
+            0xff => Ok(Self::default()),
+            percent if percent <= 100 => Ok(Self::from_percent(percent)),
+            _ => Err(DeserializeError::Invalid("branch probability")),
+        }
+    }
+}
+
+impl Encode for ExternalName {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            ExternalName::User { namespace, index } => {
+                enc.put_u8(0);
+                enc.put(&namespace);
+                enc.put(&index);
+            }
+            ExternalName::TestCase { length, ref ascii } => {
+                enc.put_u8(1);
+                enc.put(&ascii[..usize::from(length)]);
+            }
+            ExternalName::LibCall(libcall) => {
+                enc.put_u8(2);
+                enc.put(&libcall);
+            }
+        }
+    }
+}
+
+impl Decode for ExternalName {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        match dec.get_u8()? {
+            0 => Ok(ExternalName::user(dec.get()?, dec.get()?)),
+            1 => Ok(ExternalName::testcase(dec.get::<Vec<u8>>()?)),
+            2 => Ok(ExternalName::LibCall(dec.get()?)),
+            _ => Err(DeserializeError::Invalid("external name")),
+        }
+    }
+}
+
+impl Encode for ArgumentExtension {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put_u8(match *self {
+            ArgumentExtension::None => 0,
+            ArgumentExtension::Uext => 1,
+            ArgumentExtension::Sext => 2,
+        })
+    }
+}
+
+impl Decode for ArgumentExtension {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        match dec.get_u8()? {
+            0 => Ok(ArgumentExtension::None),
+            1 => Ok(ArgumentExtension::Uext),
+            2 => Ok(ArgumentExtension::Sext),
+            _ => Err(DeserializeError::Invalid("argument extension")),
+        }
+    }
+}
+
+impl Encode for ArgumentLoc {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            ArgumentLoc::Unassigned => enc.put_u8(0),
+            ArgumentLoc::Reg(reg) => {
+                enc.put_u8(1);
+                enc.put(&reg);
+            }
+            ArgumentLoc::Stack(offset) => {
+                enc.put_u8(2);
+                enc.put(&offset);
+            }
+        }
+    }
+}
+
+impl Decode for ArgumentLoc {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        match dec.get_u8()? {
+            0 => Ok(ArgumentLoc::Unassigned),
+            1 => Ok(ArgumentLoc::Reg(dec.get()?)),
+            2 => Ok(ArgumentLoc::Stack(dec.get()?)),
+            _ => Err(DeserializeError::Invalid("argument location")),
+        }
+    }
+}
+
+impl Encode for AbiParam {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(&self.value_type);
+        enc.put(&self.purpose);
+        enc.put(&self.extension);
+        enc.put(&self.location);
+    }
+}
+
+impl Decode for AbiParam {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        Ok(Self {
+            value_type: dec.get()?,
+            purpose: dec.get()?,
+            extension: dec.get()?,
+            location: dec.get()?,
+        })
+    }
+}
+
+impl Encode for Signature {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(&self.params[..]);
+        enc.put(&self.returns[..]);
+        enc.put(&self.call_conv);
+        enc.put(&self.variadic);
+    }
+}
+
+impl Decode for Signature {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        Ok(Self {
+            params: dec.get()?,
+            returns: dec.get()?,
+            call_conv: dec.get()?,
+            variadic: dec.get()?,
+        })
+    }
+}
+
+impl Encode for ExtFuncData {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(&self.name);
+        enc.put(&self.signature);
+        enc.put(&self.colocated);
+    }
+}
+
+impl Decode for ExtFuncData {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        Ok(Self {
+            name: dec.get()?,
+            signature: dec.get()?,
+            colocated: dec.get()?,
+        })
+    }
+}
+
+impl Encode for StackSlotData {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(&self.kind);
+        enc.put(&self.size);
+        enc.put(&self.offset);
+    }
+}
+
+impl Decode for StackSlotData {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        Ok(Self {
+            kind: dec.get()?,
+            size: dec.get()?,
+            offset: dec.get()?,
+        })
+    }
+}
+
+impl Encode for GlobalValueData {
+    fn encode(&self, enc: &mut Encoder) {
+        match *self {
+            GlobalValueData::VMContext => enc.put_u8(0),
+            GlobalValueData::Load {
+                base,
+                offset,
+                global_type,
+                readonly,
+            } => {
+                enc.put_u8(1);
+                enc.put(&base);
+                enc.put(&offset);
+                enc.put(&global_type);
+                enc.put(&readonly);
+            }
+            GlobalValueData::IAddImm {
+                base,
+                offset,
+                global_type,
+            } => {
+                enc.put_u8(2);
+                enc.put(&base);
+                enc.put(&offset);
+                enc.put(&global_type);
+            }
+            GlobalValueData::Symbol {
+                ref name,
+                offset,
+                colocated,
+                tls,
+            } => {
+                enc.put_u8(3);
+                enc.put(name);
+                enc.put(&offset);
+                enc.put(&colocated);
+                enc.put(&tls);
+            }
+        }
+    }
+}
+
+impl Decode for GlobalValueData {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        match dec.get_u8()? {
+            0 => Ok(GlobalValueData::VMContext),
+            1 => Ok(GlobalValueData::Load {
+                base: dec.get()?,
+                offset: dec.get()?,
+                global_type: dec.get()?,
+                readonly: dec.get()?,
+            }),
+            2 => Ok(GlobalValueData::IAddImm {
+                base: dec.get()?,
+                offset: dec.get()?,
+                global_type: dec.get()?,
+            }),
+            3 => Ok(GlobalValueData::Symbol {
+                name: dec.get()?,
+                offset: dec.get()?,
+                colocated: dec.get()?,
+                tls: dec.get()?,
+            }),
+            _ => Err(DeserializeError::Invalid("global value")),
+        }
+    }
+}
+
+impl Encode for HeapData {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(&self.base);
+        enc.put(&self.min_size);
+        enc.put(&self.offset_guard_size);
+        match self.style {
+            HeapStyle::Dynamic { bound_gv } => {
+                enc.put_u8(0);
+                enc.put(&bound_gv);
+            }
+            HeapStyle::Static { bound } => {
+                enc.put_u8(1);
+                enc.put(&bound);
+            }
+        }
+        enc.put(&self.index_type);
+        enc.put(&self.shared);
+    }
+}
+
+impl Decode for HeapData {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        Ok(Self {
+            base: dec.get()?,
+            min_size: dec.get()?,
+            offset_guard_size: dec.get()?,
+            style: match dec.get_u8()? {
+                0 => HeapStyle::Dynamic {
+                    bound_gv: dec.get()?,
+                },
+                1 => HeapStyle::Static { bound: dec.get()? },
+                _ => return Err(DeserializeError::Invalid("heap style")),
+            },
+            index_type: dec.get()?,
+            shared: dec.get()?,
+        })
+    }
+}
+
+impl Encode for TableData {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(&self.base_gv);
+        enc.put(&self.min_size);
+        enc.put(&self.bound_gv);
+        enc.put(&self.element_size);
+        enc.put(&self.index_type);
+    }
+}
+
+impl Decode for TableData {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        Ok(Self {
+            base_gv: dec.get()?,
+            min_size: dec.get()?,
+            bound_gv: dec.get()?,
+            element_size: dec.get()?,
+            index_type: dec.get()?,
+        })
+    }
+}
+
+impl Encode for JumpTableData {
+    fn encode(&self, enc: &mut Encoder) {
+        enc.put(self.as_slice())
+    }
+}
+
+impl Decode for JumpTableData {
+    fn decode(dec: &mut Decoder) -> DeserializeResult<Self> {
+        let len = dec.get_len()?;
+        let mut jt = Self::with_capacity(len);
+        for _ in 0..len {
+            jt.push_entry(dec.get()?);
+        }
+        Ok(jt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deserialize_function, serialize_function, DeserializeError};
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::entity::EntityRef;
+    use crate::ir::condcodes::IntCC;
+    use crate::ir::types::{I32, I64, I8};
+    use crate::ir::{
+        AbiParam, BranchProbability, ExtFuncData, ExternalName, FuncRef, Function, GlobalValueData,
+        InstBuilder, InstructionData, JumpTableData, MemFlags, Opcode, SigRef, Signature,
+        SourceLoc, StackSlotData, StackSlotKind, TrapCode, ValueList,
+    };
+    use crate::isa::CallConv;
+    use std::string::ToString;
+
+    /// Make a function using most kinds of entities.
+    fn make_function() -> Function {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.params.push(AbiParam::new(I64));
+        sig.returns.push(AbiParam::new(I32));
+        let mut func = Function::with_name_signature(ExternalName::testcase("serialize"), sig);
+
+        let ss0 = func.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 8));
+        let gv0 = func.create_global_value(GlobalValueData::VMContext);
+        func.create_global_value(GlobalValueData::Load {
+            base: gv0,
+            offset: (-8).into(),
+            global_type: I64,
+            readonly: true,
+        });
+        let callee_sig = func.import_signature(Signature::new(CallConv::Fast));
+        let fn0 = func.import_function(ExtFuncData {
+            name: ExternalName::user(1, 2),
+            signature: callee_sig,
+            colocated: false,
+        });
+
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let ebb3 = func.dfg.make_ebb();
+        let mut jt = JumpTableData::new();
+        jt.push_entry(ebb1);
+        jt.push_entry(ebb2);
+        let jt0 = func.create_jump_table(jt);
+
+        let v0 = func.dfg.append_ebb_param(ebb0, I32);
+        let v1 = func.dfg.append_ebb_param(ebb0, I64);
+        let v2 = func.dfg.append_ebb_param(ebb1, I32);
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_ebb(ebb0);
+        cur.set_srcloc(SourceLoc::new(42));
+        let v3 = cur.ins().iadd_imm(v0, -1);
+        cur.ins().stack_store(v3, ss0, 4);
+        cur.ins().store(MemFlags::trusted(), v3, v1, 16);
+        let brz = cur
+            .ins()
+            .br_icmp(IntCC::SignedLessThan, v0, v3, ebb1, &[v3]);
+        cur.func.branch_probabilities[brz] = BranchProbability::from_percent(90);
+        cur.set_srcloc(SourceLoc::default());
+        cur.ins().br_table(v0, ebb3, jt0);
+        cur.insert_ebb(ebb1);
+        cur.ins().call(fn0, &[]);
+        cur.ins().f64const(1.5);
+        let v4 = cur.ins().load(I32, MemFlags::new(), v1, 8);
+        let load = cur.func.dfg.value_def(v4).unwrap_inst();
+        cur.func.memory_trap_codes[load] = Some(TrapCode::User(5));
+        cur.ins().trapz(v3, TrapCode::User(3));
+        cur.ins().return_(&[v2]);
+        cur.insert_ebb(ebb2);
+        cur.func.layout.set_osr_entry(ebb2, true);
+        cur.ins().trap(TrapCode::HeapOutOfBounds);
+        cur.insert_ebb(ebb3);
+        cur.func.layout.set_cold(ebb3, true);
+        let v5 = cur.ins().iconst(I32, 7);
+
+        // A removed instruction whose result became an alias.
+        let v6 = cur.ins().iconst(I32, 9);
+        let dead = cur.func.layout.last_inst(ebb3).unwrap();
+        cur.func.layout.remove_inst(dead);
+        cur.func.dfg.detach_results(dead);
+        cur.func.dfg.change_to_alias(v6, v5);
+        cur.ins().return_(&[v6]);
+
+        func
+    }
+
+    #[test]
+    fn round_trip() {
+        let func = make_function();
+        let data = serialize_function(&func);
+        let copy = deserialize_function(&data).unwrap();
+        assert_eq!(
+            copy.display(None).to_string(),
+            func.display(None).to_string()
+        );
+        assert_eq!(serialize_function(&copy), data);
+    }
+
+    #[test]
+    fn errors() {
+        let data = serialize_function(&make_function());
+        assert_eq!(
+            deserialize_function(b"function").err(),
+            Some(DeserializeError::BadHeader)
+        );
+        assert_eq!(
+            deserialize_function(&data[..data.len() - 1]).err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+
+        let mut data = data;
+        data.push(0);
+        assert_eq!(
+            deserialize_function(&data).err(),
+            Some(DeserializeError::Invalid("trailing data"))
+        );
+    }
+
+    #[test]
+    fn huge_counts() {
+        // An empty function ends with the EBB, instruction and value counts, the number of EBBs in
+        // the layout and the number of aliases.
+        let mut data = serialize_function(&Function::new());
+        let tail = data.split_off(data.len() - 5);
+        assert_eq!(tail, [0, 0, 0, 0, 0]);
+
+        // 2^32 - 1 values.
+        data.extend_from_slice(&[0, 0, 0xff, 0xff, 0xff, 0xff, 0x0f, 0, 0]);
+        assert_eq!(
+            deserialize_function(&data).err(),
+            Some(DeserializeError::UnexpectedEnd)
+        );
+    }
+
+    #[test]
+    fn invalid_callee() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let call = func.dfg.make_inst(InstructionData::Call {
+            opcode: Opcode::Call,
+            func_ref: FuncRef::new(3),
+            args: ValueList::new(),
+        });
+        func.layout.append_ebb(ebb0);
+        func.layout.append_inst(call, ebb0);
+        assert_eq!(
+            deserialize_function(&serialize_function(&func)).err(),
+            Some(DeserializeError::Invalid("call"))
+        );
+
+        let call_indirect = func.dfg.make_inst(InstructionData::CallIndirect {
+            opcode: Opcode::CallIndirect,
+            sig_ref: SigRef::new(0),
+            args: ValueList::new(),
+        });
+        func.layout.remove_inst(call);
+        func.layout.append_inst(call_indirect, ebb0);
+        func.import_signature(Signature::new(CallConv::Fast));
+        assert_eq!(
+            deserialize_function(&serialize_function(&func)).err(),
+            Some(DeserializeError::Invalid("call"))
+        );
+    }
+
+    #[test]
+    fn invalid_ctrl_typevar() {
+        // An `isplit.i8` has no result types since `i8` can't be split.
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_param(ebb0, I8);
+        let isplit = func.dfg.make_inst(InstructionData::Unary {
+            opcode: Opcode::Isplit,
+            arg: v0,
+        });
+        func.layout.append_ebb(ebb0);
+        func.layout.append_inst(isplit, ebb0);
+        assert_eq!(
+            deserialize_function(&serialize_function(&func)).err(),
+            Some(DeserializeError::Invalid("controlling type"))
+        );
+    }
+}
@@ -14,10 +14,10 @@
 //! use cranelift_codegen::settings::{self, Configurable};
 //!
 //! let mut b = settings::builder();
-//! b.set("opt_level", "fastest");
+//! b.set("opt_level", "speed_and_size");
 //!
 //! let f = settings::Flags::new(b);
-//! assert_eq!(f.opt_level(), settings::OptLevel::Fastest);
+//! assert_eq!(f.opt_level(), settings::OptLevel::SpeedAndSize);
 //! ```
 
 use crate::constant_hash::{probe, simple_hash};
@@ -378,23 +378,35 @@ mod tests {
         assert_eq!(
             f.to_string(),
             "[shared]\n\
-             opt_level = \"default\"\n\
+             opt_level = \"speed\"\n\
              enable_verifier = true\n\
              is_pic = false\n\
+             tls_model = \"none\"\n\
              colocated_libcalls = false\n\
              avoid_div_traps = false\n\
+             elide_null_checks = false\n\
+             out_of_line_traps = false\n\
              enable_float = true\n\
              enable_nan_canonicalization = false\n\
+             enable_strict_fp = true\n\
              enable_simd = true\n\
              enable_atomics = true\n\
+             enable_safepoints = false\n\
+             enable_critical_edge_splitting = false\n\
+             enable_scheduling = false\n\
              baldrdash_prologue_words = 0\n\
              allones_funcaddrs = false\n\
+             patchable_calls = false\n\
+             patchable_branches = false\n\
+             function_alignment_log2 = 4\n\
+             loop_alignment_log2 = 0\n\
+             nop_style = \"multi_byte\"\n\
              probestack_enabled = true\n\
              probestack_func_adjusts_sp = false\n\
              probestack_size_log2 = 12\n\
              jump_tables_enabled = true\n"
         );
-        assert_eq!(f.opt_level(), super::OptLevel::Default);
+        assert_eq!(f.opt_level(), super::OptLevel::Speed);
         assert_eq!(f.enable_simd(), true);
         assert_eq!(f.baldrdash_prologue_words(), 0);
     }
@@ -424,13 +436,15 @@ mod tests {
         );
         assert_eq!(
             b.set("opt_level", "true"),
-            Err(BadValue("any among default, best, fastest".to_string()))
+            Err(BadValue(
+                "any among speed, none, speed_and_size".to_string()
+            ))
         );
-        assert_eq!(b.set("opt_level", "best"), Ok(()));
+        assert_eq!(b.set("opt_level", "none"), Ok(()));
         assert_eq!(b.set("enable_simd", "0"), Ok(()));
 
         let f = Flags::new(b);
         assert_eq!(f.enable_simd(), false);
-        assert_eq!(f.opt_level(), super::OptLevel::Best);
+        assert_eq!(f.opt_level(), super::OptLevel::None);
     }
 }
@@ -1,9 +1,11 @@
 //! A simple GVN pass.
 
+use crate::alias_analysis::{stored_value, LastStores};
 use crate::cursor::{Cursor, FuncCursor};
 use crate::dominator_tree::DominatorTree;
-use crate::ir::{Function, Inst, InstructionData, Opcode, Type};
+use crate::ir::{Ebb, Function, Inst, InstructionData, Opcode, Type};
 use crate::scoped_hash_map::ScopedHashMap;
+use crate::stats::PassStats;
 use crate::timing;
 use core::cell::{Ref, RefCell};
 use core::hash::{Hash, Hasher};
@@ -24,18 +26,30 @@ fn trivially_unsafe_for_gvn(opcode: Opcode) -> bool {
 /// Test that, if the specified instruction is a load, it doesn't have the `readonly` memflag.
 fn is_load_and_not_readonly(inst_data: &InstructionData) -> bool {
     match *inst_data {
-        InstructionData::Load { flags, .. } | InstructionData::LoadComplex { flags, .. } => {
-            !flags.readonly()
-        }
+        InstructionData::Load { flags, .. }
+        | InstructionData::LoadComplex { flags, .. }
+        | InstructionData::LoadScaled { flags, .. } => !flags.readonly(),
         _ => inst_data.opcode().can_load(),
     }
 }
 
+/// The state of memory observed by an instruction.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum MemoryState {
+    /// The instruction doesn't read memory that can be written.
+    Unchanged,
+    /// The memory at the start of an EBB.
+    Entry(Ebb),
+    /// The memory after an instruction writing to it.
+    After(Inst),
+}
+
 /// Wrapper around `InstructionData` which implements `Eq` and `Hash`
 #[derive(Clone)]
 struct HashKey<'a, 'f: 'a> {
     inst: InstructionData,
     ty: Type,
+    memory: MemoryState,
     pos: &'a RefCell<FuncCursor<'f>>,
 }
 impl<'a, 'f: 'a> Hash for HashKey<'a, 'f> {
@@ -43,19 +57,25 @@ impl<'a, 'f: 'a> Hash for HashKey<'a, 'f> {
         let pool = &self.pos.borrow().func.dfg.value_lists;
         self.inst.hash(state, pool);
         self.ty.hash(state);
+        self.memory.hash(state);
     }
 }
 impl<'a, 'f: 'a> PartialEq for HashKey<'a, 'f> {
     fn eq(&self, other: &Self) -> bool {
         let pool = &self.pos.borrow().func.dfg.value_lists;
-        self.inst.eq(&other.inst, pool) && self.ty == other.ty
+        self.inst.eq(&other.inst, pool) && self.ty == other.ty && self.memory == other.memory
     }
 }
 impl<'a, 'f: 'a> Eq for HashKey<'a, 'f> {}
 
 /// Perform simple GVN on `func`.
 ///
-pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree) {
+/// Loads from memory which can be written are only considered equivalent when they observe the
+/// same stores, according to the alias analysis. A load reading the value written by the last
+/// store is replaced by that value.
+///
+/// The removed instructions are counted in `stats`.
+pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree, stats: &mut PassStats) {
     let _tt = timing::gvn();
     debug_assert!(domtree.is_valid());
 
@@ -67,6 +87,7 @@ pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree) {
 
     let mut visible_values: ScopedHashMap<HashKey, Inst> = ScopedHashMap::new();
     let mut scope_stack: Vec<Inst> = Vec::new();
+    let mut last_stores = LastStores::new();
 
     for &ebb in domtree.cfg_postorder().iter().rev() {
         {
@@ -90,6 +111,7 @@ pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree) {
         }
 
         pos.borrow_mut().goto_top(ebb);
+        last_stores.clear();
         while let Some(inst) = {
             let mut pos = pos.borrow_mut();
             pos.next_inst()
@@ -100,6 +122,7 @@ pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree) {
             let func = Ref::map(pos.borrow(), |pos| &pos.func);
 
             let opcode = func.dfg[inst].opcode();
+            last_stores.update(&func, inst);
 
             if opcode.is_branch() && !opcode.is_terminator() {
                 scope_stack.push(func.layout.next_inst(inst).unwrap());
@@ -111,14 +134,37 @@ pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree) {
             }
 
             // These are split up to separate concerns.
-            if is_load_and_not_readonly(&func.dfg[inst]) {
-                continue;
-            }
+            let memory = if is_load_and_not_readonly(&func.dfg[inst]) {
+                match last_stores.last_store(&func, inst) {
+                    Some(store) => {
+                        if let Some(value) = stored_value(&func, inst, store) {
+                            // Forward the stored value to the load.
+                            let old = scope_stack.last_mut().unwrap();
+                            if *old == inst {
+                                *old = func.layout.next_inst(inst).unwrap();
+                            }
+                            drop(func);
+                            let mut pos = pos.borrow_mut();
+                            let result = pos.func.dfg.first_result(inst);
+                            pos.func.dfg.clear_results(inst);
+                            pos.func.dfg.change_to_alias(result, value);
+                            pos.remove_inst_and_step_back();
+                            stats.gvn_removed_insts += 1;
+                            continue;
+                        }
+                        MemoryState::After(store)
+                    }
+                    None => MemoryState::Entry(ebb),
+                }
+            } else {
+                MemoryState::Unchanged
+            };
 
             let ctrl_typevar = func.dfg.ctrl_typevar(inst);
             let key = HashKey {
                 inst: func.dfg[inst].clone(),
                 ty: ctrl_typevar,
+                memory,
                 pos: &pos,
             };
             use crate::scoped_hash_map::Entry::*;
@@ -136,6 +182,7 @@ pub fn do_simple_gvn(func: &mut Function, domtree: &mut DominatorTree) {
                     let mut pos = pos.borrow_mut();
                     pos.func.dfg.replace_with_aliases(inst, *entry.get());
                     pos.remove_inst_and_step_back();
+                    stats.gvn_removed_insts += 1;
                 }
                 Vacant(entry) => {
                     entry.insert(inst);
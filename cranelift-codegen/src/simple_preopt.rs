@@ -486,7 +486,13 @@ fn simplify(pos: &mut FuncCursor, inst: Inst) {
                     imm,
                 } = pos.func.dfg[iconst_inst]
                 {
+                    // Commutative operations can fold a constant on either side.
                     let new_opcode = match opcode {
+                        Opcode::Iadd => Opcode::IaddImm,
+                        Opcode::Imul => Opcode::ImulImm,
+                        Opcode::Band => Opcode::BandImm,
+                        Opcode::Bor => Opcode::BorImm,
+                        Opcode::Bxor => Opcode::BxorImm,
                         Opcode::Isub => Opcode::IrsubImm,
                         _ => return,
                     };
@@ -536,6 +542,36 @@ fn simplify(pos: &mut FuncCursor, inst: Inst) {
     }
 }
 
+/// Replace a multiplication by a power of two with a shift.
+fn simplify_mul(pos: &mut FuncCursor, inst: Inst) {
+    if let InstructionData::BinaryImm {
+        opcode: Opcode::ImulImm,
+        arg,
+        imm,
+    } = pos.func.dfg[inst]
+    {
+        // Only the low bits of the immediate matter for narrow types. For types wider than 64
+        // bits, the immediate is sign-extended, so a negative one is never a power of two.
+        let bits = pos.func.dfg.ctrl_typevar(inst).lane_bits();
+        let imm: i64 = imm.into();
+        let mul_by = if bits < 64 {
+            imm as u64 & ((1 << bits) - 1)
+        } else if bits == 64 || imm >= 0 {
+            imm as u64
+        } else {
+            return;
+        };
+        if mul_by.is_power_of_two() {
+            let k = mul_by.trailing_zeros();
+            if k == 0 {
+                pos.func.dfg.replace(inst).copy(arg);
+            } else {
+                pos.func.dfg.replace(inst).ishl_imm(arg, i64::from(k));
+            }
+        }
+    }
+}
+
 /// The main pre-opt pass.
 pub fn do_preopt(func: &mut Function) {
     let _tt = timing::preopt();
@@ -544,6 +580,7 @@ pub fn do_preopt(func: &mut Function) {
         while let Some(inst) = pos.next_inst() {
             // Apply basic simplifications.
             simplify(&mut pos, inst);
+            simplify_mul(&mut pos, inst);
 
             //-- BEGIN -- division by constants ----------------
 
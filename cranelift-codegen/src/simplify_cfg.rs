@@ -0,0 +1,252 @@
+//! Control flow graph simplification.
+//!
+//! This pass cleans up the control flow left behind by the translation of structured control flow
+//! and by other optimizations, keeping the layout compact:
+//!
+//! - A conditional branch immediately followed by a `jump` to the same EBB with the same
+//!   arguments is removed.
+//! - A branch to an EBB that contains nothing but a `jump` is retargeted to the destination of
+//!   that `jump`. The forwarding EBB is removed when it has no predecessors left.
+//! - An EBB with a single predecessor that is an unconditional `jump` is merged into the EBB
+//!   containing the `jump`.
+
+use crate::flowgraph::ControlFlowGraph;
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Ebb, Function, Inst, Opcode, Value, ValueDef};
+use crate::timing;
+use log::debug;
+use std::vec::Vec;
+
+/// Simplify the control flow graph of `func`, and update `cfg` to match.
+pub fn simplify_cfg(func: &mut Function, cfg: &mut ControlFlowGraph) {
+    let _tt = timing::simplify_cfg();
+    debug_assert!(cfg.is_valid());
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let ebbs: Vec<Ebb> = func.layout.ebbs().collect();
+        for ebb in ebbs {
+            if !func.layout.is_ebb_inserted(ebb) {
+                continue;
+            }
+            changed |= fold_redundant_branches(func, cfg, ebb);
+            changed |= thread_jumps(func, cfg, ebb);
+            while merge_successor(func, cfg, ebb) {
+                changed = true;
+            }
+        }
+    }
+}
+
+/// Remove the conditional branches in `ebb` that go to the same place as the following `jump`.
+fn fold_redundant_branches(func: &mut Function, cfg: &mut ControlFlowGraph, ebb: Ebb) -> bool {
+    let mut changed = false;
+    let mut next = func.layout.first_inst(ebb);
+    while let Some(inst) = next {
+        next = func.layout.next_inst(inst);
+        let jump = match next {
+            Some(jump) if func.dfg[jump].opcode() == Opcode::Jump => jump,
+            _ => continue,
+        };
+        if func.dfg[inst].opcode() == Opcode::Jump {
+            continue;
+        }
+        match (branch_target(func, inst), branch_target(func, jump)) {
+            (Some(a), Some(b)) if a == b => {
+                debug!("Removing redundant {}", func.dfg.display_inst(inst, None));
+                func.layout.remove_inst(inst);
+                changed = true;
+            }
+            _ => {}
+        }
+    }
+    if changed {
+        cfg.recompute_ebb(func, ebb);
+    }
+    changed
+}
+
+/// Retarget the branches in `ebb` that go to an EBB which only contains a `jump`.
+fn thread_jumps(func: &mut Function, cfg: &mut ControlFlowGraph, ebb: Ebb) -> bool {
+    let mut changed = false;
+    let insts: Vec<Inst> = func.layout.ebb_insts(ebb).collect();
+    for inst in insts {
+        let (dest, args) = match branch_target(func, inst) {
+            Some(target) => target,
+            None => continue,
+        };
+        // A `fallthrough` can't be retargeted without changing the layout.
+        if dest == ebb || func.dfg[inst].opcode() == Opcode::Fallthrough {
+            continue;
+        }
+        let (target, target_args) = match forward(func, dest, &args) {
+            Some(t) => t,
+            None => continue,
+        };
+        if forwards_to(func, target, dest) {
+            // Threading through a cycle of forwarding EBBs would never end.
+            continue;
+        }
+
+        debug!(
+            "Threading {} through {} to {}",
+            func.dfg.display_inst(inst, None),
+            dest,
+            target
+        );
+        set_branch_target(func, inst, target, &target_args);
+        changed = true;
+
+        if cfg.pred_iter(dest).all(|pred| pred.inst == inst) {
+            debug!("Removing forwarding {}", dest);
+            while let Some(jump) = func.layout.first_inst(dest) {
+                func.layout.remove_inst(jump);
+            }
+            cfg.recompute_ebb(func, dest);
+            func.layout.remove_ebb(dest);
+        }
+    }
+    if changed {
+        cfg.recompute_ebb(func, ebb);
+    }
+    changed
+}
+
+/// Merge the successor of `ebb` into it if `ebb` ends in a `jump` that is the only predecessor of
+/// its destination.
+///
+/// A successor ending in a `fallthrough_return` must stay last in the layout, so it is only merged
+/// into the EBB right before it.
+fn merge_successor(func: &mut Function, cfg: &mut ControlFlowGraph, ebb: Ebb) -> bool {
+    let jump = match func.layout.last_inst(ebb) {
+        Some(jump) if func.dfg[jump].opcode() == Opcode::Jump => jump,
+        _ => return false,
+    };
+    let (succ, args) = match branch_target(func, jump) {
+        Some(target) => target,
+        None => return false,
+    };
+    if succ == ebb
+        || func.layout.entry_block() == Some(succ)
+        || cfg.pred_iter(succ).any(|pred| pred.inst != jump)
+        || args.iter().any(|&arg| is_param_of(func, arg, succ))
+        || (ends_in_fallthrough_return(func, succ) && func.layout.next_ebb(ebb) != Some(succ))
+    {
+        return false;
+    }
+
+    debug!("Merging {} into {}", succ, ebb);
+    func.layout.remove_inst(jump);
+    let params = func.dfg.detach_ebb_params(succ);
+    for (i, &arg) in args.iter().enumerate() {
+        let param = params.get(i, &func.dfg.value_lists).unwrap();
+        func.dfg.change_to_alias(param, arg);
+    }
+    let insts: Vec<Inst> = func.layout.ebb_insts(succ).collect();
+    for inst in insts {
+        func.layout.remove_inst(inst);
+        func.layout.append_inst(inst, ebb);
+    }
+    cfg.recompute_ebb(func, succ);
+    cfg.recompute_ebb(func, ebb);
+    func.layout.remove_ebb(succ);
+    true
+}
+
+/// Get the destination and EBB arguments of `inst` if it is a branch to a single EBB.
+///
+/// The landing pad of a `try_call` is not reported, so calls are never retargeted.
+fn branch_target(func: &Function, inst: Inst) -> Option<(Ebb, Vec<Value>)> {
+    if func.dfg[inst].opcode().is_call() {
+        return None;
+    }
+    match func.dfg.analyze_branch(inst) {
+        BranchInfo::SingleDest(dest, args) => Some((
+            dest,
+            args.iter()
+                .map(|&arg| func.dfg.resolve_aliases(arg))
+                .collect(),
+        )),
+        _ => None,
+    }
+}
+
+/// Change the destination of the branch `inst` to `dest` with `args`.
+fn set_branch_target(func: &mut Function, inst: Inst, dest: Ebb, args: &[Value]) {
+    let num_fixed = func.dfg[inst]
+        .opcode()
+        .constraints()
+        .num_fixed_value_arguments();
+    let mut vlist = func.dfg[inst]
+        .take_value_list()
+        .expect("Branches must have value lists.");
+    {
+        let pool = &mut func.dfg.value_lists;
+        while vlist.len(pool) > num_fixed {
+            let last = vlist.len(pool) - 1;
+            vlist.remove(last, pool);
+        }
+        vlist.extend(args.iter().cloned(), pool);
+    }
+    func.dfg[inst].put_value_list(vlist);
+    *func.dfg[inst]
+        .branch_destination_mut()
+        .expect("Expected a branch.") = dest;
+}
+
+/// If `ebb` contains nothing but a `jump`, get the destination of the `jump` and its arguments
+/// when `ebb` is entered with `args`.
+fn forward(func: &Function, ebb: Ebb, args: &[Value]) -> Option<(Ebb, Vec<Value>)> {
+    let jump = func.layout.first_inst(ebb)?;
+    if func.dfg[jump].opcode() != Opcode::Jump || func.layout.entry_block() == Some(ebb) {
+        return None;
+    }
+    let (dest, jump_args) = branch_target(func, jump)?;
+    if dest == ebb {
+        return None;
+    }
+    let params = func.dfg.ebb_params(ebb);
+    let dest_args = jump_args
+        .iter()
+        .map(|&arg| match params.iter().position(|&param| param == arg) {
+            Some(num) => args[num],
+            None => arg,
+        })
+        .collect();
+    Some((dest, dest_args))
+}
+
+/// Does following the chain of forwarding EBBs from `ebb` lead to `target`?
+fn forwards_to(func: &Function, mut ebb: Ebb, target: Ebb) -> bool {
+    let mut seen = Vec::new();
+    while let Some(jump) = func.layout.first_inst(ebb) {
+        if ebb == target {
+            return true;
+        }
+        if func.dfg[jump].opcode() != Opcode::Jump || seen.contains(&ebb) {
+            return false;
+        }
+        seen.push(ebb);
+        ebb = match func.dfg.analyze_branch(jump) {
+            BranchInfo::SingleDest(dest, _) => dest,
+            _ => return false,
+        };
+    }
+    false
+}
+
+/// Does `ebb` end in a `fallthrough_return`?
+fn ends_in_fallthrough_return(func: &Function, ebb: Ebb) -> bool {
+    func.layout.last_inst(ebb).map_or(false, |inst| {
+        func.dfg[inst].opcode() == Opcode::FallthroughReturn
+    })
+}
+
+/// Is `value` a parameter of `ebb`?
+fn is_param_of(func: &Function, value: Value, ebb: Ebb) -> bool {
+    match func.dfg.value_def(value) {
+        ValueDef::Param(def_ebb, _) => def_ebb == ebb,
+        ValueDef::Result(..) => false,
+    }
+}
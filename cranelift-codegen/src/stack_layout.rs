@@ -1,9 +1,9 @@
 //! Computing stack layout.
 
 use crate::ir::stackslot::{StackOffset, StackSize, StackSlotKind};
-use crate::ir::StackSlots;
+use crate::ir::{StackSlot, StackSlots};
 use crate::result::{CodegenError, CodegenResult};
-use core::cmp::{max, min};
+use core::cmp::min;
 
 /// Compute the stack frame layout.
 ///
@@ -14,7 +14,8 @@ use core::cmp::{max, min};
 ///
 /// Returns the total stack frame size which is also saved in `frame.frame_size`.
 ///
-/// If the stack frame is too big, returns an `ImplLimitExceeded` error.
+/// If the stack frame is too big, returns a `StackFrameTooLarge` error with the first stack slot
+/// that doesn't fit.
 pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResult<StackSize> {
     // Each object and the whole stack frame must fit in 2 GB such that any relative offset within
     // the frame fits in a `StackOffset`.
@@ -36,12 +37,12 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
     // reserved zones by the layout algorithm.
 
     let mut incoming_min = 0;
-    let mut outgoing_max = 0;
+    let mut outgoing_max: Option<(StackOffset, StackSlot)> = None;
     let mut min_align = alignment;
 
-    for slot in frame.values() {
+    for (ss, slot) in frame.iter() {
         if slot.size > max_size {
-            return Err(CodegenError::ImplLimitExceeded);
+            return Err(CodegenError::StackFrameTooLarge(ss));
         }
 
         match slot.kind {
@@ -53,8 +54,10 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
                     .offset
                     .unwrap()
                     .checked_add(slot.size as StackOffset)
-                    .ok_or(CodegenError::ImplLimitExceeded)?;
-                outgoing_max = max(outgoing_max, offset);
+                    .ok_or(CodegenError::StackFrameTooLarge(ss))?;
+                if outgoing_max.map_or(true, |(max_offset, _)| offset > max_offset) {
+                    outgoing_max = Some((offset, ss));
+                }
             }
             StackSlotKind::SpillSlot
             | StackSlotKind::ExplicitSlot
@@ -71,7 +74,7 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
     let mut offset = incoming_min;
     debug_assert!(min_align.is_power_of_two());
     while min_align <= alignment {
-        for slot in frame.values_mut() {
+        for (ss, slot) in frame.iter_mut() {
             // Pick out explicit and spill slots with exact alignment `min_align`.
             match slot.kind {
                 StackSlotKind::SpillSlot
@@ -86,7 +89,7 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
 
             offset = offset
                 .checked_sub(slot.size as StackOffset)
-                .ok_or(CodegenError::ImplLimitExceeded)?;
+                .ok_or(CodegenError::StackFrameTooLarge(ss))?;
 
             // Aligning the negative offset can never cause overflow. We're only clearing bits.
             offset &= -(min_align as StackOffset);
@@ -98,9 +101,11 @@ pub fn layout_stack(frame: &mut StackSlots, alignment: StackSize) -> CodegenResu
     }
 
     // Finally, make room for the outgoing arguments.
-    offset = offset
-        .checked_sub(outgoing_max)
-        .ok_or(CodegenError::ImplLimitExceeded)?;
+    if let Some((outgoing_max, ss)) = outgoing_max {
+        offset = offset
+            .checked_sub(outgoing_max)
+            .ok_or(CodegenError::StackFrameTooLarge(ss))?;
+    }
     offset &= -(alignment as StackOffset);
 
     let frame_size = (offset as StackSize).wrapping_neg();
@@ -125,8 +130,8 @@ mod tests {
         assert_eq!(layout_stack(sss, 16), Ok(0));
 
         // Same for incoming arguments with non-negative offsets.
-        let in0 = sss.make_incoming_arg(types::I64, 0);
-        let in1 = sss.make_incoming_arg(types::I64, 8);
+        let in0 = sss.make_incoming_arg(types::I64.bytes(), 0);
+        let in1 = sss.make_incoming_arg(types::I64.bytes(), 8);
 
         assert_eq!(layout_stack(sss, 1), Ok(0));
         assert_eq!(layout_stack(sss, 16), Ok(0));
@@ -151,7 +156,7 @@ mod tests {
 
         // An incoming argument with negative offset counts towards the total frame size, but it
         // should still pack nicely with the spill slots.
-        let in2 = sss.make_incoming_arg(types::I32, -4);
+        let in2 = sss.make_incoming_arg(types::I32.bytes(), -4);
 
         assert_eq!(layout_stack(sss, 1), Ok(16));
         assert_eq!(sss[in0].offset, Some(0));
@@ -168,7 +173,7 @@ mod tests {
         assert_eq!(sss[ss1].offset, Some(-8));
 
         // Finally, make sure there is room for the outgoing args.
-        let out0 = sss.get_outgoing_arg(types::I32, 0);
+        let out0 = sss.get_outgoing_arg(types::I32.bytes(), 0);
 
         assert_eq!(layout_stack(sss, 1), Ok(20));
         assert_eq!(sss[in0].offset, Some(0));
@@ -187,8 +192,11 @@ mod tests {
         assert_eq!(sss[out0].offset, Some(0));
 
         // Also test that an unsupported offset is rejected.
-        sss.get_outgoing_arg(types::I8, StackOffset::max_value() - 1);
-        assert_eq!(layout_stack(sss, 1), Err(CodegenError::ImplLimitExceeded));
+        let out1 = sss.get_outgoing_arg(types::I8.bytes(), StackOffset::max_value() - 1);
+        assert_eq!(
+            layout_stack(sss, 1),
+            Err(CodegenError::StackFrameTooLarge(out1))
+        );
     }
 
     #[test]
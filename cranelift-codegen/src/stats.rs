@@ -0,0 +1,166 @@
+//! Statistics about the IR of a function and the compilation passes.
+//!
+//! The counts are useful for tracking the effectiveness of passes, by comparing the statistics of
+//! a function before and after it is compiled. The passes also count some of their
+//! transformations in a `PassStats`, which can be retrieved from the `Context` after compilation.
+
+use crate::flowgraph::ControlFlowGraph;
+use crate::fx::FxHashMap;
+use crate::ir::{Function, Opcode};
+use core::fmt;
+use std::string::ToString;
+use std::vec::Vec;
+
+/// Counts of the entities in a function.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    /// Number of EBBs in the layout.
+    pub ebbs: usize,
+    /// Number of instructions in the layout.
+    pub insts: usize,
+    /// Number of values defined by the EBB parameters and instruction results in the layout.
+    pub values: usize,
+    /// Number of instructions with each opcode, sorted by opcode name.
+    pub opcodes: Vec<(Opcode, usize)>,
+    /// Total size of the stack slots in bytes.
+    pub stack_slot_bytes: u64,
+    /// Number of CFG edges from an EBB with multiple successors to an EBB with multiple
+    /// predecessors.
+    pub critical_edges: usize,
+}
+
+impl FunctionStats {
+    /// Compute the statistics of `func`.
+    pub fn compute(func: &Function) -> Self {
+        let cfg = ControlFlowGraph::with_function(func);
+        let mut stats = Self::default();
+        let mut opcodes = FxHashMap();
+
+        for ebb in func.layout.ebbs() {
+            stats.ebbs += 1;
+            stats.values += func.dfg.num_ebb_params(ebb);
+            for inst in func.layout.ebb_insts(ebb) {
+                stats.insts += 1;
+                stats.values += func.dfg.inst_results(inst).len();
+                *opcodes.entry(func.dfg[inst].opcode()).or_insert(0) += 1;
+            }
+
+            if cfg.pred_iter(ebb).count() > 1 {
+                stats.critical_edges += cfg
+                    .pred_iter(ebb)
+                    .filter(|pred| cfg.succ_iter(pred.ebb).count() > 1)
+                    .count();
+            }
+        }
+
+        stats.opcodes = opcodes.into_iter().collect();
+        stats.opcodes.sort_by_key(|&(opcode, _)| opcode.to_string());
+        stats.stack_slot_bytes = func
+            .stack_slots
+            .values()
+            .map(|slot| u64::from(slot.size))
+            .sum();
+        stats
+    }
+}
+
+/// Counts of the transformations made by the compilation passes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PassStats {
+    /// Number of values placed in the same virtual register as an EBB parameter or argument by
+    /// the coalescing pass, so no copy is needed between them.
+    pub values_coalesced: usize,
+    /// Number of redundant instructions removed by global value numbering.
+    pub gvn_removed_insts: usize,
+    /// Number of branches changed to an encoding with a longer range by branch relaxation.
+    pub relaxed_branches: usize,
+    /// Number of `spill` instructions inserted by the register allocator.
+    pub spills: usize,
+    /// Number of `fill` instructions inserted by the register allocator.
+    pub fills: usize,
+}
+
+impl PassStats {
+    /// Add the counts of `other` to these counts, to aggregate the statistics of several
+    /// functions.
+    pub fn add(&mut self, other: &Self) {
+        self.values_coalesced += other.values_coalesced;
+        self.gvn_removed_insts += other.gvn_removed_insts;
+        self.relaxed_branches += other.relaxed_branches;
+        self.spills += other.spills;
+        self.fills += other.fills;
+    }
+}
+
+impl fmt::Display for PassStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "values coalesced: {}", self.values_coalesced)?;
+        writeln!(f, "gvn removed insts: {}", self.gvn_removed_insts)?;
+        writeln!(f, "relaxed branches: {}", self.relaxed_branches)?;
+        writeln!(f, "spills: {}", self.spills)?;
+        writeln!(f, "fills: {}", self.fills)
+    }
+}
+
+impl fmt::Display for FunctionStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "ebbs: {}", self.ebbs)?;
+        writeln!(f, "insts: {}", self.insts)?;
+        writeln!(f, "values: {}", self.values)?;
+        writeln!(f, "stack slot bytes: {}", self.stack_slot_bytes)?;
+        writeln!(f, "critical edges: {}", self.critical_edges)?;
+        writeln!(f, "opcodes:")?;
+        for &(opcode, count) in &self.opcodes {
+            writeln!(f, "    {}: {}", opcode, count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::ir::types::*;
+    use crate::ir::{InstBuilder, StackSlotData, StackSlotKind};
+
+    #[test]
+    fn critical_edge() {
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_param(ebb0, I32);
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        func.stack_slots
+            .push(StackSlotData::new(StackSlotKind::ExplicitSlot, 12));
+
+        let mut cur = FuncCursor::new(&mut func);
+
+        cur.insert_ebb(ebb0);
+        cur.ins().brz(v0, ebb2, &[]);
+        cur.ins().jump(ebb1, &[]);
+
+        cur.insert_ebb(ebb1);
+        cur.ins().iadd_imm(v0, 1);
+        cur.ins().jump(ebb2, &[]);
+
+        cur.insert_ebb(ebb2);
+        cur.ins().return_(&[]);
+
+        let stats = FunctionStats::compute(cur.func);
+        assert_eq!(stats.ebbs, 3);
+        assert_eq!(stats.insts, 5);
+        assert_eq!(stats.values, 2);
+        assert_eq!(stats.stack_slot_bytes, 12);
+        assert_eq!(stats.critical_edges, 1);
+        assert_eq!(
+            stats.opcodes,
+            [
+                (Opcode::Brz, 1),
+                (Opcode::IaddImm, 1),
+                (Opcode::Jump, 2),
+                (Opcode::Return, 1)
+            ]
+        );
+    }
+}
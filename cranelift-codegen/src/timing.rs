@@ -52,12 +52,19 @@ define_passes! {
     verify_flags: "Verify CPU flags",
 
     compile: "Compilation passes",
+    code_cache: "Code cache lookup",
     flowgraph: "Control flow graph",
+    inline: "Function inlining",
+    osr: "OSR entry creation",
     domtree: "Dominator tree",
     loop_analysis: "Loop analysis",
     postopt: "Post-legalization rewriting",
     preopt: "Pre-legalization rewriting",
+    simplify_cfg: "Control flow graph simplification",
+    bounds_checks: "Bounds check elimination",
+    null_checks: "Null check elision",
     dce: "Dead code elimination",
+    dead_ebb_params: "Dead EBB parameter removal",
     legalize: "Legalization",
     gvn: "Global value numbering",
     licm: "Loop invariant code motion",
@@ -66,12 +73,18 @@ define_passes! {
     regalloc: "Register allocation",
     ra_liveness: "RA liveness analysis",
     ra_cssa: "RA coalescing CSSA",
+    ra_splitting: "RA live range splitting",
     ra_spilling: "RA spilling",
+    ra_spill_slots: "RA spill slot assignment",
     ra_reload: "RA reloading",
     ra_coloring: "RA coloring",
+    ra_stackmaps: "RA stack map insertion",
+    redundant_moves: "Redundant move removal",
+    schedule: "Instruction scheduling",
 
     prologue_epilogue: "Prologue/epilogue insertion",
     shrink_instructions: "Instruction encoding shrinking",
+    ebb_order: "EBB ordering",
     relax_branches: "Branch relaxation",
     binemit: "Binary machine code emission",
     layout_renumber: "Layout full renumbering",
@@ -232,16 +245,24 @@ mod details {
 #[cfg(not(feature = "std"))]
 mod details {
     use super::Pass;
+    use core::fmt;
     /// Dummy `TimingToken`
     pub struct TimingToken;
     /// Dummy `PassTimes`
+    #[derive(Default)]
     pub struct PassTimes;
+    /// Displays nothing
+    impl fmt::Display for PassTimes {
+        fn fmt(&self, _f: &mut fmt::Formatter) -> fmt::Result {
+            Ok(())
+        }
+    }
     /// Returns dummy `PassTimes`
     pub fn take_current() -> PassTimes {
         PassTimes
     }
     /// does nothing
-    pub fn add_to_current(_times: PassTimes) {}
+    pub fn add_to_current(_times: &PassTimes) {}
 
     /// does nothing
     pub(super) fn start_pass(_pass: Pass) -> TimingToken {
@@ -1,14 +1,18 @@
 //! Liveness verifier.
 
+use crate::entity::SecondaryMap;
 use crate::flowgraph::{BasicBlock, ControlFlowGraph};
 use crate::ir::entities::AnyEntity;
-use crate::ir::{ExpandedProgramPoint, Function, Inst, ProgramOrder, ProgramPoint, Value};
+use crate::ir::instructions::BranchInfo;
+use crate::ir::{Ebb, ExpandedProgramPoint, Function, Inst, ProgramOrder, ProgramPoint, Value};
 use crate::isa::TargetIsa;
 use crate::regalloc::liveness::Liveness;
 use crate::regalloc::liverange::LiveRange;
 use crate::timing;
 use crate::verifier::{VerifierErrors, VerifierStepResult};
 use core::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::vec::Vec;
 
 /// Verify liveness information for `func`.
 ///
@@ -20,8 +24,9 @@ use core::cmp::Ordering;
 /// - When a live range is live-in to an EBB, it must be live at all the predecessors.
 /// - The live range affinity must be compatible with encoding constraints.
 ///
-/// We don't verify that live ranges are minimal. This would require recomputing live ranges for
-/// all values.
+/// The live-in sets are also recomputed from scratch with a backward dataflow analysis, and every
+/// value found to be live-in to an EBB must have a live range that is live-in to that EBB. We
+/// don't verify that live ranges are minimal.
 pub fn verify_liveness(
     isa: &TargetIsa,
     func: &Function,
@@ -38,6 +43,7 @@ pub fn verify_liveness(
     };
     verifier.check_ebbs(errors)?;
     verifier.check_insts(errors)?;
+    verifier.check_recomputed_liveins(errors)?;
     Ok(())
 }
 
@@ -255,4 +261,151 @@ impl<'a> LivenessVerifier<'a> {
 
         Ok(())
     }
+
+    /// Check the live-in intervals against live-in sets recomputed from scratch.
+    ///
+    /// The live ranges are updated incrementally by the register allocator passes. Here, the
+    /// values that are live-in to each EBB are computed independently by iterating a backward
+    /// dataflow analysis over the whole function until it reaches a fixed point.
+    fn check_recomputed_liveins(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
+        let ebbs: Vec<Ebb> = self.func.layout.ebbs().collect();
+        let mut liveins = SecondaryMap::<Ebb, BTreeSet<Value>>::new();
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &ebb in ebbs.iter().rev() {
+                let live = self.compute_livein(ebb, &liveins);
+                if live != liveins[ebb] {
+                    liveins[ebb] = live;
+                    changed = true;
+                }
+            }
+        }
+
+        let ctx = self.liveness.context(&self.func.layout);
+        for &ebb in &ebbs {
+            for &val in &liveins[ebb] {
+                let is_livein = match self.liveness.get(val) {
+                    Some(lr) => lr.is_livein(ebb, ctx),
+                    None => false,
+                };
+                if !is_livein {
+                    return fatal!(
+                        errors,
+                        ebb,
+                        "{} is live in to {}, but its live range isn't",
+                        val,
+                        ebb
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Compute the set of values that are live-in to `ebb`, given the current live-in sets of its
+    /// successors.
+    fn compute_livein(
+        &self,
+        ebb: Ebb,
+        liveins: &SecondaryMap<Ebb, BTreeSet<Value>>,
+    ) -> BTreeSet<Value> {
+        let dfg = &self.func.dfg;
+        let mut live = BTreeSet::new();
+
+        for inst in self.func.layout.ebb_insts(ebb).rev() {
+            for val in dfg.inst_results(inst) {
+                live.remove(val);
+            }
+            match dfg.analyze_branch(inst) {
+                BranchInfo::SingleDest(dest, _) => {
+                    live.extend(liveins[dest].iter().cloned());
+                }
+                BranchInfo::Table(jt, dest) => {
+                    if let Some(dest) = dest {
+                        live.extend(liveins[dest].iter().cloned());
+                    }
+                    for dest in self.func.jump_tables[jt].iter() {
+                        live.extend(liveins[*dest].iter().cloned());
+                    }
+                }
+                BranchInfo::NotABranch => {}
+            }
+            live.extend(dfg.inst_args(inst).iter().cloned());
+        }
+
+        for val in dfg.ebb_params(ebb) {
+            live.remove(val);
+        }
+
+        live
+    }
+}
+
+#[cfg(all(test, feature = "riscv"))]
+mod tests {
+    use super::verify_liveness;
+    use crate::cursor::{Cursor, FuncCursor};
+    use crate::flowgraph::ControlFlowGraph;
+    use crate::ir::types::I32;
+    use crate::ir::{Function, InstBuilder};
+    use crate::isa;
+    use crate::regalloc::liveness::Liveness;
+    use crate::settings;
+    use crate::verifier::VerifierErrors;
+    use core::str::FromStr;
+    use target_lexicon::triple;
+
+    #[test]
+    fn stale_livein() {
+        let isa = isa::lookup(triple!("riscv32"))
+            .unwrap()
+            .finish(settings::Flags::new(settings::builder()));
+
+        let mut func = Function::new();
+        let ebb0 = func.dfg.make_ebb();
+        let ebb1 = func.dfg.make_ebb();
+        let ebb2 = func.dfg.make_ebb();
+        let (v0, jump) = {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb0);
+            let v0 = pos.ins().iconst(I32, 1);
+            let jump = pos.ins().jump(ebb2, &[]);
+            pos.insert_ebb(ebb2);
+            pos.ins().iadd_imm(v0, 1);
+            pos.ins().return_(&[]);
+            (v0, jump)
+        };
+        let cfg = ControlFlowGraph::with_function(&func);
+        let mut liveness = Liveness::new();
+        liveness.compute(&*isa, &mut func, &cfg);
+
+        let mut errors = VerifierErrors::default();
+        assert_eq!(
+            verify_liveness(&*isa, &func, &cfg, &liveness, &mut errors),
+            Ok(())
+        );
+
+        // Route the jump through a new EBB at the end of the layout, without updating the CFG or
+        // the live range of `v0`. Only the recomputed live-in sets notice.
+        func.layout.append_ebb(ebb1);
+        FuncCursor::new(&mut func)
+            .at_bottom(ebb1)
+            .ins()
+            .jump(ebb2, &[]);
+        *func.dfg[jump].branch_destination_mut().unwrap() = ebb1;
+
+        assert_eq!(
+            verify_liveness(&*isa, &func, &cfg, &liveness, &mut errors),
+            Err(())
+        );
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].location, ebb1.into());
+        assert_eq!(
+            errors.0[0].message,
+            format!("{} is live in to {}, but its live range isn't", v0, ebb1)
+        );
+    }
 }
@@ -3,9 +3,10 @@
 use crate::ir;
 use crate::isa;
 use crate::regalloc::liveness::Liveness;
-use crate::regalloc::RegDiversions;
+use crate::regalloc::{Affinity, RegDiversions};
 use crate::timing;
 use crate::verifier::{VerifierErrors, VerifierStepResult};
+use std::vec::Vec;
 
 /// Verify value locations for `func`.
 ///
@@ -17,7 +18,8 @@ use crate::verifier::{VerifierErrors, VerifierStepResult};
 /// and `regfill` instructions, but only inside an EBB.
 ///
 /// If a liveness analysis is provided, it is used to verify that there are no active register
-/// diversions across control flow edges.
+/// diversions across control flow edges, and that no two live values share a register at any
+/// program point.
 pub fn verify_locations(
     isa: &isa::TargetIsa,
     func: &ir::Function,
@@ -33,6 +35,9 @@ pub fn verify_locations(
         liveness,
     };
     verifier.check_constraints(errors)?;
+    if let Some(liveness) = liveness {
+        verifier.check_interference(liveness, errors)?;
+    }
     Ok(())
 }
 
@@ -44,6 +49,13 @@ struct LocationVerifier<'a> {
     liveness: Option<&'a Liveness>,
 }
 
+/// A value that is live at the current program point in `check_interference`.
+struct LiveValue {
+    value: ir::Value,
+    loc: ir::ValueLoc,
+    end: ir::ProgramPoint,
+}
+
 impl<'a> LocationVerifier<'a> {
     /// Check that the assigned value locations match the operand constraints of their uses.
     fn check_constraints(&self, errors: &mut VerifierErrors) -> VerifierStepResult<()> {
@@ -58,6 +70,7 @@ impl<'a> LocationVerifier<'a> {
                 let enc = self.func.encodings[inst];
 
                 if enc.is_legal() {
+                    self.check_assigned(inst, &divert, errors)?;
                     self.check_enc_constraints(inst, enc, &divert, errors)?
                 } else {
                     self.check_ghost_results(inst, errors)?;
@@ -81,6 +94,24 @@ impl<'a> LocationVerifier<'a> {
         Ok(())
     }
 
+    /// Check that all the operands and results of an encoded instruction have a location.
+    fn check_assigned(
+        &self,
+        inst: ir::Inst,
+        divert: &RegDiversions,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let dfg = &self.func.dfg;
+
+        for &value in dfg.inst_args(inst).iter().chain(dfg.inst_results(inst)) {
+            if !divert.get(value, &self.func.locations).is_assigned() {
+                return fatal!(errors, inst, "{} has no value location", value);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Check encoding constraints against the current value locations.
     fn check_enc_constraints(
         &self,
@@ -156,6 +187,7 @@ impl<'a> LocationVerifier<'a> {
         }
 
         for (abi, &value) in sig.returns.iter().zip(results) {
+            self.check_return_location(inst, value, abi, errors)?;
             self.check_abi_location(
                 inst,
                 value,
@@ -180,6 +212,7 @@ impl<'a> LocationVerifier<'a> {
         let varargs = self.func.dfg.inst_variable_args(inst);
 
         for (abi, &value) in sig.returns.iter().zip(varargs) {
+            self.check_return_location(inst, value, abi, errors)?;
             self.check_abi_location(
                 inst,
                 value,
@@ -193,6 +226,28 @@ impl<'a> LocationVerifier<'a> {
         Ok(())
     }
 
+    /// Check that a return value is passed in a register.
+    ///
+    /// Return values that don't fit in registers are returned in memory by the legalizer.
+    fn check_return_location(
+        &self,
+        inst: ir::Inst,
+        value: ir::Value,
+        abi: &ir::AbiParam,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        if abi.location.is_stack() {
+            return fatal!(
+                errors,
+                inst,
+                "return value {} can't be passed at stack offset {}",
+                value,
+                abi.location.display(&self.reginfo)
+            );
+        }
+        Ok(())
+    }
+
     /// Check a single ABI location.
     fn check_abi_location(
         &self,
@@ -265,15 +320,37 @@ impl<'a> LocationVerifier<'a> {
         divert: &mut RegDiversions,
         errors: &mut VerifierErrors,
     ) -> VerifierStepResult<()> {
-        let (arg, src) = match self.func.dfg[inst] {
+        match self.func.dfg[inst] {
             ir::InstructionData::RegMove { arg, src, .. }
-            | ir::InstructionData::RegSpill { arg, src, .. } => (arg, ir::ValueLoc::Reg(src)),
-            ir::InstructionData::RegFill { arg, src, .. } => (arg, ir::ValueLoc::Stack(src)),
+            | ir::InstructionData::RegSpill { arg, src, .. } => {
+                self.check_diverted_loc(inst, arg, ir::ValueLoc::Reg(src), divert, errors)?
+            }
+            ir::InstructionData::RegFill { arg, src, .. } => {
+                self.check_diverted_loc(inst, arg, ir::ValueLoc::Stack(src), divert, errors)?
+            }
+            ir::InstructionData::RegSwap { args, src, dst, .. } => {
+                self.check_diverted_loc(inst, args[0], ir::ValueLoc::Reg(src), divert, errors)?;
+                self.check_diverted_loc(inst, args[1], ir::ValueLoc::Reg(dst), divert, errors)?;
+            }
             _ => return Ok(()),
-        };
+        }
+
+        divert.apply(&self.func.dfg[inst]);
 
+        Ok(())
+    }
+
+    /// Check that the current location of `arg` is `loc`.
+    fn check_diverted_loc(
+        &self,
+        inst: ir::Inst,
+        arg: ir::Value,
+        loc: ir::ValueLoc,
+        divert: &RegDiversions,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
         if let Some(d) = divert.diversion(arg) {
-            if d.to != src {
+            if d.to != loc {
                 return fatal!(
                     errors,
                     inst,
@@ -281,7 +358,7 @@ impl<'a> LocationVerifier<'a> {
                     d.to.display(&self.reginfo)
                 );
             }
-        } else if self.func.locations[arg] != src {
+        } else if self.func.locations[arg] != loc {
             return fatal!(
                 errors,
                 inst,
@@ -290,8 +367,6 @@ impl<'a> LocationVerifier<'a> {
             );
         }
 
-        divert.apply(&self.func.dfg[inst]);
-
         Ok(())
     }
 
@@ -365,4 +440,162 @@ impl<'a> LocationVerifier<'a> {
 
         Ok(())
     }
+    /// Check that no two live values share a register at any program point.
+    ///
+    /// The live values are tracked through each EBB, starting from the values that are live-in to
+    /// the EBB and its parameters in their global locations. Register diversions move values
+    /// locally, and a value is removed when its live range ends.
+    fn check_interference(
+        &self,
+        liveness: &Liveness,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let ctx = liveness.context(&self.func.layout);
+        let mut live = Vec::new();
+
+        for ebb in self.func.layout.ebbs() {
+            live.clear();
+
+            for value in self.func.dfg.values() {
+                if let Some(lr) = liveness.get(value) {
+                    if let Some(end) = lr.livein_local_end(ebb, ctx) {
+                        self.define_live(&mut live, value, end.into(), ebb, liveness, errors)?;
+                    }
+                }
+            }
+            for &value in self.func.dfg.ebb_params(ebb) {
+                let end = liveness[value].def_local_end();
+                self.define_live(&mut live, value, end, ebb, liveness, errors)?;
+            }
+            live.retain(|lv| lv.end != ebb.into());
+
+            for inst in self.func.layout.ebb_insts(ebb) {
+                match self.func.dfg[inst] {
+                    ir::InstructionData::RegMove { arg, dst, .. }
+                    | ir::InstructionData::RegFill { arg, dst, .. } => {
+                        let to = ir::ValueLoc::Reg(dst);
+                        self.divert_live(&mut live, arg, to, inst, liveness, errors)?;
+                    }
+                    ir::InstructionData::RegSpill { arg, dst, .. } => {
+                        let to = ir::ValueLoc::Stack(dst);
+                        self.divert_live(&mut live, arg, to, inst, liveness, errors)?;
+                    }
+                    ir::InstructionData::RegSwap { args, src, dst, .. } => {
+                        for lv in live.iter_mut() {
+                            if lv.value == args[0] {
+                                lv.loc = ir::ValueLoc::Reg(dst);
+                            } else if lv.value == args[1] {
+                                lv.loc = ir::ValueLoc::Reg(src);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+
+                // Values killed by `inst` free their registers for the results.
+                live.retain(|lv| lv.end != inst.into());
+
+                for &value in self.func.dfg.inst_results(inst) {
+                    let end = liveness[value].def_local_end();
+                    self.define_live(&mut live, value, end, inst, liveness, errors)?;
+                }
+
+                // Dead results only occupy their register at the definition.
+                live.retain(|lv| lv.end != inst.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add `value` in its global location to the set of live values, and check that its register
+    /// isn't used by another live value.
+    fn define_live<L: Into<ir::entities::AnyEntity>>(
+        &self,
+        live: &mut Vec<LiveValue>,
+        value: ir::Value,
+        end: ir::ProgramPoint,
+        loc_entity: L,
+        liveness: &Liveness,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let loc = self.func.locations[value];
+        if !loc.is_assigned() {
+            return Ok(());
+        }
+        self.check_register_free(live, value, loc, loc_entity, liveness, errors)?;
+        live.push(LiveValue { value, loc, end });
+        Ok(())
+    }
+
+    /// Move the live `value` to the location `to`, and check that its new register isn't used by
+    /// another live value.
+    fn divert_live(
+        &self,
+        live: &mut Vec<LiveValue>,
+        value: ir::Value,
+        to: ir::ValueLoc,
+        inst: ir::Inst,
+        liveness: &Liveness,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        self.check_register_free(live, value, to, inst, liveness, errors)?;
+        if let Some(lv) = live.iter_mut().find(|lv| lv.value == value) {
+            lv.loc = to;
+        }
+        Ok(())
+    }
+
+    /// Check that placing `value` at `loc` doesn't overlap the register of another live value.
+    fn check_register_free<L: Into<ir::entities::AnyEntity>>(
+        &self,
+        live: &[LiveValue],
+        value: ir::Value,
+        loc: ir::ValueLoc,
+        loc_entity: L,
+        liveness: &Liveness,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let (unit, width) = match self.reg_units(value, loc, liveness) {
+            Some(units) => units,
+            None => return Ok(()),
+        };
+
+        for lv in live.iter().filter(|lv| lv.value != value) {
+            if let Some((other_unit, other_width)) = self.reg_units(lv.value, lv.loc, liveness) {
+                if unit < other_unit + other_width && other_unit < unit + width {
+                    return fatal!(
+                        errors,
+                        loc_entity,
+                        "{} in {} interferes with live value {} in {}",
+                        value,
+                        loc.display(&self.reginfo),
+                        lv.value,
+                        lv.loc.display(&self.reginfo)
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the first register unit and the number of units occupied by `value` at `loc`.
+    fn reg_units(
+        &self,
+        value: ir::Value,
+        loc: ir::ValueLoc,
+        liveness: &Liveness,
+    ) -> Option<(isa::RegUnit, isa::RegUnit)> {
+        match loc {
+            ir::ValueLoc::Reg(unit) => {
+                let width = match liveness[value].affinity {
+                    Affinity::Reg(rci) => isa::RegUnit::from(self.reginfo.rc(rci).width),
+                    _ => 1,
+                };
+                Some((unit, width))
+            }
+            _ => None,
+        }
+    }
 }
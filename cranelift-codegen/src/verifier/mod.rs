@@ -8,6 +8,8 @@
 //! - Every EBB must end in a terminator instruction, and no other instruction
 //!   can be a terminator.
 //! - Every value in the `ebb_params` iterator belongs to the EBB as reported by `value_ebb`.
+//! - A `fallthrough` instruction must be followed by its destination in the layout, and a
+//!   `fallthrough_return` instruction can only appear in the last EBB.
 //!
 //! Instruction integrity
 //!
@@ -15,6 +17,7 @@
 //! - All result values must be created for multi-valued instructions.
 //! - All referenced entities must exist. (Values, EBBs, stack slots, ...)
 //! - Instructions must not reference (eg. branch to) the entry block.
+//! - Only conditional branches can have a branch probability.
 //!
 //! SSA form
 //!
@@ -410,6 +413,20 @@ impl<'a> Verifier<'a> {
                             bound_type
                         );
                     }
+
+                    // Other threads can grow a shared heap, so its bound must be reloaded.
+                    if heap_data.shared {
+                        if let ir::GlobalValueData::Load { readonly: true, .. } =
+                            self.func.global_values[bound_gv]
+                        {
+                            report!(
+                                errors,
+                                heap,
+                                "shared heap bound {} is a readonly load",
+                                bound_gv
+                            );
+                        }
+                    }
                 }
             }
         }
@@ -485,6 +502,10 @@ impl<'a> Verifier<'a> {
             );
         }
 
+        if is_last_inst {
+            self.fallthrough_integrity(ebb, inst, errors)?;
+        }
+
         // Instructions belong to the correct ebb.
         let inst_ebb = self.func.layout.inst_ebb(inst);
         if inst_ebb != Some(ebb) {
@@ -508,6 +529,48 @@ impl<'a> Verifier<'a> {
         Ok(())
     }
 
+    /// The `fallthrough` and `fallthrough_return` instructions don't emit any code, so they are
+    /// only correct if the code they fall through to comes next in the layout.
+    fn fallthrough_integrity(
+        &self,
+        ebb: Ebb,
+        inst: Inst,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        let next_ebb = self.func.layout.next_ebb(ebb);
+        match self.func.dfg[inst] {
+            ir::InstructionData::Jump {
+                opcode: Opcode::Fallthrough,
+                destination,
+                ..
+            } => {
+                if next_ebb != Some(destination) {
+                    return nonfatal!(
+                        errors,
+                        inst,
+                        "fallthrough destination {} is not the next EBB in the layout",
+                        destination
+                    );
+                }
+            }
+            ir::InstructionData::MultiAry {
+                opcode: Opcode::FallthroughReturn,
+                ..
+            } => {
+                if let Some(next_ebb) = next_ebb {
+                    return nonfatal!(
+                        errors,
+                        inst,
+                        "fallthrough_return is followed by {} in the layout",
+                        next_ebb
+                    );
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     fn instruction_integrity(
         &self,
         inst: Inst,
@@ -544,6 +607,32 @@ impl<'a> Verifier<'a> {
             );
         }
 
+        // Only conditional branches can have a branch probability.
+        let opcode = inst_data.opcode();
+        if !self.func.branch_probabilities[inst].is_default()
+            && (!opcode.is_branch() || opcode.is_terminator())
+        {
+            report!(
+                errors,
+                inst,
+                "branch probability on {} which is not a conditional branch",
+                opcode
+            );
+        }
+
+        // Only memory accesses that can fault can have a trap code.
+        if self.func.memory_trap_codes[inst].is_some() {
+            match inst_data.memflags() {
+                Some(flags) if !flags.notrap() => {}
+                _ => report!(
+                    errors,
+                    inst,
+                    "trap code on {} which is not a trapping memory access",
+                    opcode
+                ),
+            }
+        }
+
         self.verify_entity_references(inst, errors)
     }
 
@@ -618,6 +707,16 @@ impl<'a> Verifier<'a> {
                 self.verify_func_ref(inst, func_ref, errors)?;
                 self.verify_value_list(inst, args, errors)?;
             }
+            TryCall {
+                func_ref,
+                destination,
+                ref args,
+                ..
+            } => {
+                self.verify_func_ref(inst, func_ref, errors)?;
+                self.verify_ebb(inst, destination, errors)?;
+                self.verify_value_list(inst, args, errors)?;
+            }
             CallIndirect {
                 sig_ref, ref args, ..
             } => {
@@ -671,7 +770,14 @@ impl<'a> Verifier<'a> {
             | IntSelect { .. }
             | Load { .. }
             | Store { .. }
+            | LoadScaled { .. }
+            | StoreScaled { .. }
+            | LoadNoOffset { .. }
+            | StoreNoOffset { .. }
+            | AtomicRmw { .. }
+            | AtomicCas { .. }
             | RegMove { .. }
+            | RegSwap { .. }
             | CopySpecial { .. }
             | Trap { .. }
             | CondTrap { .. }
@@ -1151,6 +1257,19 @@ impl<'a> Verifier<'a> {
         errors: &mut VerifierErrors,
     ) -> VerifierStepResult<()> {
         match self.func.dfg.analyze_branch(inst) {
+            // The variable arguments of a `try_call` go to the callee, not the landing pad.
+            BranchInfo::SingleDest(ebb, _) if self.func.dfg[inst].opcode().is_call() => {
+                let arg_count = self.func.dfg.num_ebb_params(ebb);
+                if arg_count != 0 {
+                    return nonfatal!(
+                        errors,
+                        inst,
+                        "landing pad {} can't have parameters, found {}",
+                        ebb,
+                        arg_count
+                    );
+                }
+            }
             BranchInfo::SingleDest(ebb, _) => {
                 let iter = self
                     .func
@@ -1299,13 +1418,13 @@ impl<'a> Verifier<'a> {
                             slot
                         );
                     }
-                    if slot.size != abi.value_type.bytes() {
+                    if slot.size != abi.stack_size() {
                         return fatal!(
                             errors,
                             inst,
                             "Outgoing stack argument {} wrong size for {}: {} = {}",
                             arg,
-                            abi.value_type,
+                            abi,
                             ss,
                             slot
                         );
@@ -1376,7 +1495,16 @@ impl<'a> Verifier<'a> {
                                 ctrl_type
                             );
                         }
-                        if arg_type.lane_bits() >= ctrl_type.lane_bits() {
+                        if arg_type.lane_bits() == ctrl_type.lane_bits() {
+                            return nonfatal!(
+                                errors,
+                                inst,
+                                "extension from {} to {} does not change the width",
+                                arg_type,
+                                ctrl_type
+                            );
+                        }
+                        if arg_type.lane_bits() > ctrl_type.lane_bits() {
                             return nonfatal!(
                                 errors,
                                 inst,
@@ -1396,7 +1524,16 @@ impl<'a> Verifier<'a> {
                                 ctrl_type
                             );
                         }
-                        if arg_type.lane_bits() <= ctrl_type.lane_bits() {
+                        if arg_type.lane_bits() == ctrl_type.lane_bits() {
+                            return nonfatal!(
+                                errors,
+                                inst,
+                                "reduction from {} to {} does not change the width",
+                                arg_type,
+                                ctrl_type
+                            );
+                        }
+                        if arg_type.lane_bits() < ctrl_type.lane_bits() {
                             return nonfatal!(
                                 errors,
                                 inst,
@@ -1435,6 +1572,16 @@ impl<'a> Verifier<'a> {
                     );
                 }
             }
+            ir::InstructionData::LoadNoOffset { arg, .. } => {
+                self.typecheck_atomic_addr(inst, ctrl_type, arg, errors)?;
+            }
+            ir::InstructionData::StoreNoOffset { args, .. } => {
+                self.typecheck_atomic_addr(inst, ctrl_type, args[1], errors)?;
+            }
+            ir::InstructionData::AtomicRmw { args, .. }
+            | ir::InstructionData::AtomicCas { args, .. } => {
+                self.typecheck_atomic_addr(inst, ctrl_type, args[0], errors)?;
+            }
             ir::InstructionData::UnaryGlobalValue { global_value, .. } => {
                 if let Some(isa) = self.isa {
                     let inst_type = self.func.dfg.value_type(self.func.dfg.first_result(inst));
@@ -1455,6 +1602,55 @@ impl<'a> Verifier<'a> {
         Ok(())
     }
 
+    /// Check an atomic access of type `mem_type` at the address `addr`.
+    ///
+    /// Atomic accesses can't be split, so they can't be wider than a pointer. The address must be
+    /// aligned to the size of the access, which is checked when it is the address of a stack slot.
+    fn typecheck_atomic_addr(
+        &self,
+        inst: Inst,
+        mem_type: Type,
+        addr: Value,
+        errors: &mut VerifierErrors,
+    ) -> VerifierStepResult<()> {
+        if let Some(isa) = self.isa {
+            let pointer_type = isa.pointer_type();
+            if mem_type.bits() > pointer_type.bits() {
+                return nonfatal!(
+                    errors,
+                    inst,
+                    "atomic {} access is wider than the pointer type {}",
+                    mem_type,
+                    pointer_type
+                );
+            }
+        }
+
+        // Only the offset into a stack slot is checked. The alignment of the slot itself depends
+        // on its size.
+        let offset: i32 = match self.func.dfg.value_def(addr) {
+            ValueDef::Result(def_inst, _) => match self.func.dfg[def_inst] {
+                ir::InstructionData::StackLoad {
+                    opcode: Opcode::StackAddr,
+                    offset,
+                    ..
+                } => offset.into(),
+                _ => return Ok(()),
+            },
+            ValueDef::Param(_, _) => return Ok(()),
+        };
+        if offset % mem_type.bytes() as i32 != 0 {
+            return nonfatal!(
+                errors,
+                inst,
+                "atomic {} access at {} is misaligned",
+                mem_type,
+                addr
+            );
+        }
+        Ok(())
+    }
+
     fn cfg_integrity(
         &self,
         cfg: &ControlFlowGraph,
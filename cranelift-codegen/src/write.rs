@@ -224,6 +224,8 @@ fn write_arg(w: &mut Write, func: &Function, regs: Option<&RegInfo>, arg: Value)
 ///    ebb1:
 ///    ebb1(v1: i32):
 ///    ebb10(v4: f64, v5: b1):
+///    ebb11(v6: i64) cold:
+///    ebb12(v7: i32) osr_entry:
 ///
 pub fn write_ebb_header(
     w: &mut Write,
@@ -239,19 +241,24 @@ pub fn write_ebb_header(
     let regs = regs.as_ref();
 
     let mut args = func.dfg.ebb_params(ebb).iter().cloned();
-    match args.next() {
-        None => return writeln!(w, ":"),
-        Some(arg) => {
-            write!(w, "(")?;
+    if let Some(arg) = args.next() {
+        write!(w, "(")?;
+        write_arg(w, func, regs, arg)?;
+        // Remaining arguments.
+        for arg in args {
+            write!(w, ", ")?;
             write_arg(w, func, regs, arg)?;
         }
+        write!(w, ")")?;
     }
-    // Remaining arguments.
-    for arg in args {
-        write!(w, ", ")?;
-        write_arg(w, func, regs, arg)?;
+
+    if func.layout.is_cold(ebb) {
+        write!(w, " cold")?;
     }
-    writeln!(w, "):")
+    if func.layout.is_osr_entry(ebb) {
+        write!(w, " osr_entry")?;
+    }
+    writeln!(w, ":")
 }
 
 fn decorate_ebb<FW: FuncWriter>(
@@ -396,6 +403,15 @@ fn write_instruction(
     }
 
     write_operands(w, &func.dfg, isa, inst)?;
+
+    if let Some(code) = func.memory_trap_codes[inst] {
+        write!(w, " traps {}", code)?;
+    }
+
+    // Branch probability hints go last.
+    if let Some(percent) = func.branch_probabilities[inst].percent() {
+        write!(w, " prob {}", percent)?;
+    }
     writeln!(w)?;
 
     // Value aliases come out on lines after the instruction defining the referent.
@@ -515,7 +531,29 @@ pub fn write_operands(
                 DisplayValues(&args[1..])
             )
         }
+        TryCall {
+            func_ref,
+            destination,
+            ref args,
+            ..
+        } => write!(
+            w,
+            " {}({}), {}",
+            func_ref,
+            DisplayValues(args.as_slice(pool)),
+            destination
+        ),
         FuncAddr { func_ref, .. } => write!(w, " {}", func_ref),
+        LoadNoOffset { flags, arg, .. } => write!(w, "{} {}", flags, arg),
+        StoreNoOffset { flags, args, .. } => write!(w, "{} {}, {}", flags, args[0], args[1]),
+        AtomicRmw {
+            flags, op, args, ..
+        } => write!(w, "{} {} {}, {}", flags, op, args[0], args[1]),
+        AtomicCas { flags, args, .. } => write!(
+            w,
+            "{} {}, {}, {}",
+            flags, args[0], args[1], args[2]
+        ),
         StackLoad {
             stack_slot, offset, ..
         } => write!(w, " {}{}", stack_slot, offset),
@@ -567,6 +605,24 @@ pub fn write_operands(
                 offset
             )
         }
+        LoadScaled {
+            flags,
+            args,
+            imm,
+            offset,
+            ..
+        } => write!(w, "{} {}, {}, {}{}", flags, args[0], args[1], imm, offset),
+        StoreScaled {
+            flags,
+            args,
+            imm,
+            offset,
+            ..
+        } => write!(
+            w,
+            "{} {}, {}, {}, {}{}",
+            flags, args[0], args[1], args[2], imm, offset
+        ),
         RegMove { arg, src, dst, .. } => {
             if let Some(isa) = isa {
                 let regs = isa.register_info();
@@ -581,6 +637,21 @@ pub fn write_operands(
                 write!(w, " {}, %{} -> %{}", arg, src, dst)
             }
         }
+        RegSwap { args, src, dst, .. } => {
+            if let Some(isa) = isa {
+                let regs = isa.register_info();
+                write!(
+                    w,
+                    " {}, {}, {} -> {}",
+                    args[0],
+                    args[1],
+                    regs.display_regunit(src),
+                    regs.display_regunit(dst)
+                )
+            } else {
+                write!(w, " {}, {}, %{} -> %{}", args[0], args[1], src, dst)
+            }
+        }
         CopySpecial { src, dst, .. } => {
             if let Some(isa) = isa {
                 let regs = isa.register_info();
@@ -710,6 +781,18 @@ mod tests {
             f.to_string(),
             "function %foo() fast {\n    ss0 = explicit_slot 4\n\nebb0(v0: i8, v1: f32x4):\n    return\n}\n"
         );
+
+        f.layout.set_cold(ebb, true);
+        assert_eq!(
+            f.to_string(),
+            "function %foo() fast {\n    ss0 = explicit_slot 4\n\nebb0(v0: i8, v1: f32x4) cold:\n    return\n}\n"
+        );
+
+        f.layout.set_osr_entry(ebb, true);
+        assert_eq!(
+            f.to_string(),
+            "function %foo() fast {\n    ss0 = explicit_slot 4\n\nebb0(v0: i8, v1: f32x4) cold osr_entry:\n    return\n}\n"
+        );
     }
 
     #[test]
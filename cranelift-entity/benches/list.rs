@@ -0,0 +1,72 @@
+//! Benchmarks for `EntityList` operations in a `ListPool`.
+//!
+//! The lists are short, like the argument lists of instructions and the parameter lists of EBBs.
+
+use cranelift_entity::{entity_impl, EntityList, EntityRef, ListPool};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// The number of lists built in each iteration.
+const LISTS: usize = 1000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+struct E(u32);
+entity_impl!(E, "e");
+
+fn list(c: &mut Criterion) {
+    c.bench_function("ListPool push", |b| {
+        let mut pool = ListPool::new();
+        let mut lists = vec![EntityList::<E>::new(); LISTS];
+        b.iter(|| {
+            pool.clear();
+            for (i, list) in lists.iter_mut().enumerate() {
+                *list = EntityList::new();
+                for j in 0..(i % 6) {
+                    list.push(E::new(i + j), &mut pool);
+                }
+            }
+        })
+    });
+
+    c.bench_function("ListPool from_slice", |b| {
+        let mut pool = ListPool::new();
+        let mut lists = vec![EntityList::<E>::new(); LISTS];
+        let elements: Vec<E> = (0..6).map(E::new).collect();
+        b.iter(|| {
+            pool.clear();
+            for (i, list) in lists.iter_mut().enumerate() {
+                *list = EntityList::from_slice(&elements[..i % 6], &mut pool);
+            }
+        })
+    });
+
+    c.bench_function("ListPool insert remove", |b| {
+        let mut pool = ListPool::new();
+        let mut lists = vec![EntityList::<E>::new(); LISTS];
+        b.iter(|| {
+            pool.clear();
+            for list in lists.iter_mut() {
+                *list = EntityList::new();
+                for j in 0..4 {
+                    list.insert(0, E::new(j), &mut pool);
+                }
+                list.remove(1, &mut pool);
+                list.swap_remove(0, &mut pool);
+            }
+        })
+    });
+
+    c.bench_function("ListPool clear reuse", |b| {
+        let mut pool = ListPool::new();
+        let mut lists = vec![EntityList::<E>::new(); LISTS];
+        b.iter(|| {
+            // Free every list and build it again, reusing the blocks on the pool's free lists.
+            for (i, list) in lists.iter_mut().enumerate() {
+                list.clear(&mut pool);
+                list.extend((0..(i % 6)).map(E::new), &mut pool);
+            }
+        })
+    });
+}
+
+criterion_group!(benches, list);
+criterion_main!(benches);
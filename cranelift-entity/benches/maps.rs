@@ -0,0 +1,140 @@
+//! Benchmarks comparing `SparseMap`, `SecondaryMap`, and `HashMap`.
+//!
+//! Each map is filled with the same keys and then queried with them. The key distributions mimic
+//! how the code generator uses these maps:
+//!
+//! - `dense`: every entity in a function, in numbering order.
+//! - `sparse`: a small subset of a large entity space, in random order, like the set of values
+//!   live at a program point.
+//! - `local`: references to recently created entities, like the operands of an instruction. The
+//!   same key may appear more than once.
+
+use cranelift_entity::{entity_impl, EntityRef, SecondaryMap, SparseMap, SparseMapValue};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::collections::HashMap;
+
+/// The number of keys inserted into each map.
+const KEYS: usize = 1000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+struct E(u32);
+entity_impl!(E, "e");
+
+#[derive(Clone, Debug)]
+struct Item {
+    key: E,
+    value: u32,
+}
+
+impl SparseMapValue<E> for Item {
+    fn key(&self) -> E {
+        self.key
+    }
+}
+
+/// A small deterministic pseudo-random number generator, so all runs see the same keys.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next_index(&mut self) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        (self.0 >> 33) as usize
+    }
+}
+
+fn dense_keys() -> Vec<E> {
+    (0..KEYS).map(E::new).collect()
+}
+
+fn sparse_keys() -> Vec<E> {
+    let mut rng = Lcg(1);
+    let mut keys: Vec<E> = (0..KEYS)
+        .map(|_| E::new(rng.next_index() % (KEYS * 16)))
+        .collect();
+    keys.sort();
+    keys.dedup();
+    // Shuffle the keys again.
+    for i in (1..keys.len()).rev() {
+        keys.swap(i, rng.next_index() % (i + 1));
+    }
+    keys
+}
+
+fn local_keys() -> Vec<E> {
+    let mut rng = Lcg(2);
+    let mut keys = Vec::with_capacity(KEYS);
+    for i in 0..KEYS {
+        // Most references are to one of the last few entities. Some go further back.
+        let back = if rng.next_index() % 8 == 0 {
+            rng.next_index() % (i + 1)
+        } else {
+            rng.next_index() % 8
+        };
+        keys.push(E::new(i.saturating_sub(back)));
+    }
+    keys
+}
+
+fn bench_distribution(c: &mut Criterion, name: &str, keys: Vec<E>) {
+    let sparse_map_keys = keys.clone();
+    c.bench_function(&format!("SparseMap {}", name), move |b| {
+        let mut map = SparseMap::new();
+        b.iter(|| {
+            map.clear();
+            for (i, &key) in sparse_map_keys.iter().enumerate() {
+                map.insert(Item {
+                    key,
+                    value: i as u32,
+                });
+            }
+            let mut sum = 0;
+            for &key in &sparse_map_keys {
+                sum += map.get(key).map_or(0, |item| item.value);
+            }
+            sum
+        })
+    });
+
+    let secondary_map_keys = keys.clone();
+    c.bench_function(&format!("SecondaryMap {}", name), move |b| {
+        let mut map = SecondaryMap::new();
+        b.iter(|| {
+            map.clear();
+            for (i, &key) in secondary_map_keys.iter().enumerate() {
+                map[key] = i as u32;
+            }
+            let mut sum = 0;
+            for &key in &secondary_map_keys {
+                sum += map[key];
+            }
+            sum
+        })
+    });
+
+    c.bench_function(&format!("HashMap {}", name), move |b| {
+        let mut map = HashMap::new();
+        b.iter(|| {
+            map.clear();
+            for (i, &key) in keys.iter().enumerate() {
+                map.insert(key, i as u32);
+            }
+            let mut sum = 0;
+            for key in &keys {
+                sum += map.get(key).cloned().unwrap_or(0);
+            }
+            sum
+        })
+    });
+}
+
+fn maps(c: &mut Criterion) {
+    bench_distribution(c, "dense", dense_keys());
+    bench_distribution(c, "sparse", sparse_keys());
+    bench_distribution(c, "local", local_keys());
+}
+
+criterion_group!(benches, maps);
+criterion_main!(benches);
@@ -6,8 +6,8 @@ use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocS
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{self, binemit, ir};
 use cranelift_module::{
-    Backend, DataContext, DataDescription, Init, Linkage, ModuleError, ModuleNamespace,
-    ModuleResult,
+    line_table, write_debug_sections, Backend, DataContext, DataDescription, DebugRelocTarget,
+    DebugSectionId, FunctionDebugInfo, Init, Linkage, ModuleError, ModuleNamespace, ModuleResult,
 };
 use faerie;
 use failure::Error;
@@ -31,6 +31,7 @@ pub struct FaerieBuilder {
     name: String,
     collect_traps: FaerieTrapCollection,
     libcall_names: Box<Fn(ir::LibCall) -> String>,
+    debug_source: Option<String>,
 }
 
 impl FaerieBuilder {
@@ -45,8 +46,9 @@ impl FaerieBuilder {
     ///
     /// The `libcall_names` function provides a way to translate `cranelift_codegen`'s `ir::LibCall`
     /// enum to symbols. LibCalls are inserted in the IR as part of the legalization for certain
-    /// floating point instructions, and for stack probes. If you don't know what to use for this
-    /// argument, use `FaerieBuilder::default_libcall_names()`.
+    /// floating point instructions, for integer multiplications on CPUs without a multiplier, and
+    /// for stack probes. If you don't know what to use for this argument, use
+    /// `FaerieBuilder::default_libcall_names()`.
     pub fn new(
         isa: Box<TargetIsa>,
         name: String,
@@ -63,9 +65,23 @@ impl FaerieBuilder {
             name,
             collect_traps,
             libcall_names,
+            debug_source: None,
         })
     }
 
+    /// Emit DWARF debug information for the defined functions.
+    ///
+    /// The `.debug_info` section describes each function, and the `.debug_line` section maps its
+    /// code to the source locations of its instructions, which are used as line numbers in the
+    /// file `source_name`.
+    pub fn debug_info<K>(&mut self, source_name: K) -> &mut Self
+    where
+        K: Into<String>,
+    {
+        self.debug_source = Some(source_name.into());
+        self
+    }
+
     /// Default names for `ir::LibCall`s. A function by this name is imported into the object as
     /// part of the translation of a `ir::ExternalName::LibCall` variant.
     pub fn default_libcall_names() -> Box<Fn(ir::LibCall) -> String> {
@@ -77,11 +93,32 @@ impl FaerieBuilder {
             ir::LibCall::FloorF64 => "floor".to_owned(),
             ir::LibCall::TruncF32 => "truncf".to_owned(),
             ir::LibCall::TruncF64 => "trunc".to_owned(),
+            ir::LibCall::FmaF32 => "fmaf".to_owned(),
+            ir::LibCall::FmaF64 => "fma".to_owned(),
             ir::LibCall::NearestF32 => "nearbyintf".to_owned(),
             ir::LibCall::NearestF64 => "nearbyint".to_owned(),
+            ir::LibCall::FaddF32 => "__addsf3".to_owned(),
+            ir::LibCall::FaddF64 => "__adddf3".to_owned(),
+            ir::LibCall::FsubF32 => "__subsf3".to_owned(),
+            ir::LibCall::FsubF64 => "__subdf3".to_owned(),
+            ir::LibCall::FmulF32 => "__mulsf3".to_owned(),
+            ir::LibCall::FmulF64 => "__muldf3".to_owned(),
+            ir::LibCall::FdivF32 => "__divsf3".to_owned(),
+            ir::LibCall::FdivF64 => "__divdf3".to_owned(),
+            ir::LibCall::SqrtF32 => "sqrtf".to_owned(),
+            ir::LibCall::SqrtF64 => "sqrt".to_owned(),
+            ir::LibCall::ImulI32 => "__mulsi3".to_owned(),
+            ir::LibCall::ImulI64 => "__muldi3".to_owned(),
             ir::LibCall::Memcpy => "memcpy".to_owned(),
             ir::LibCall::Memset => "memset".to_owned(),
             ir::LibCall::Memmove => "memmove".to_owned(),
+            ir::LibCall::ElfTlsGetAddr => "__tls_get_addr".to_owned(),
+            ir::LibCall::WasmMemoryInit => "__wasm_memory_init".to_owned(),
+            ir::LibCall::WasmDataDrop => "__wasm_data_drop".to_owned(),
+            ir::LibCall::WasmTableCopy => "__wasm_table_copy".to_owned(),
+            ir::LibCall::WasmAtomicWait32 => "__wasm_atomic_wait32".to_owned(),
+            ir::LibCall::WasmAtomicWait64 => "__wasm_atomic_wait64".to_owned(),
+            ir::LibCall::WasmAtomicNotify => "__wasm_atomic_notify".to_owned(),
         })
     }
 }
@@ -94,6 +131,8 @@ pub struct FaerieBackend {
     artifact: faerie::Artifact,
     trap_manifest: Option<FaerieTrapManifest>,
     libcall_names: Box<Fn(ir::LibCall) -> String>,
+    debug_source: Option<String>,
+    debug_funcs: Vec<FunctionDebugInfo>,
 }
 
 pub struct FaerieCompiledFunction {}
@@ -125,6 +164,8 @@ impl Backend for FaerieBackend {
                 FaerieTrapCollection::Disabled => None,
             },
             libcall_names: builder.libcall_names,
+            debug_source: builder.debug_source,
+            debug_funcs: Vec::new(),
         }
     }
 
@@ -190,6 +231,16 @@ impl Backend for FaerieBackend {
         self.artifact
             .define(name, code)
             .expect("inconsistent declaration");
+
+        if self.debug_source.is_some() {
+            self.debug_funcs.push(FunctionDebugInfo {
+                name: name.to_string(),
+                address: 0,
+                size: code_size,
+                lines: line_table(&ctx.func, &*self.isa),
+            });
+        }
+
         Ok(FaerieCompiledFunction {})
     }
 
@@ -292,11 +343,38 @@ impl Backend for FaerieBackend {
         // Nothing to do.
     }
 
+    fn undefine_function(
+        &mut self,
+        name: &str,
+        _func: &FaerieCompiledFunction,
+    ) -> ModuleResult<()> {
+        Err(ModuleError::Backend(format!(
+            "can't redefine function {} in an object file",
+            name
+        )))
+    }
+
+    fn free_function(&mut self, name: &str, _func: &FaerieCompiledFunction) -> ModuleResult<()> {
+        Err(ModuleError::Backend(format!(
+            "can't free function {} in an object file",
+            name
+        )))
+    }
+
     fn publish(&mut self) {
         // Nothing to do.
     }
 
-    fn finish(self) -> FaerieProduct {
+    fn finish(mut self) -> FaerieProduct {
+        if let Some(ref source_name) = self.debug_source {
+            emit_debug_sections(
+                &mut self.artifact,
+                source_name,
+                &self.debug_funcs,
+                self.isa.pointer_bytes(),
+            );
+        }
+
         FaerieProduct {
             artifact: self.artifact,
             trap_manifest: self.trap_manifest,
@@ -332,6 +410,47 @@ impl FaerieProduct {
     }
 }
 
+/// Add the DWARF sections describing `funcs` to `artifact`.
+///
+/// The function addresses are relocated against the function symbols.
+fn emit_debug_sections(
+    artifact: &mut faerie::Artifact,
+    source_name: &str,
+    funcs: &[FunctionDebugInfo],
+    pointer_bytes: u8,
+) {
+    let sections = write_debug_sections(source_name, funcs, pointer_bytes);
+    for (id, data) in vec![
+        (DebugSectionId::DebugAbbrev, sections.debug_abbrev),
+        (DebugSectionId::DebugInfo, sections.debug_info),
+        (DebugSectionId::DebugLine, sections.debug_line),
+    ] {
+        artifact
+            .declare_with(id.name(), faerie::Decl::DebugSection, data)
+            .expect("faerie declaration of debug section");
+    }
+
+    for reloc in &sections.relocs {
+        let to = match reloc.target {
+            DebugRelocTarget::Function(index) => &funcs[index].name[..],
+            DebugRelocTarget::Section(id) => id.name(),
+        };
+        artifact
+            .link_with(
+                faerie::Link {
+                    from: reloc.section.name(),
+                    to,
+                    at: u64::from(reloc.offset),
+                },
+                faerie::Reloc::Debug {
+                    size: reloc.size,
+                    addend: reloc.addend as i32,
+                },
+            )
+            .expect("faerie relocation error");
+    }
+}
+
 fn translate_function_linkage(linkage: Linkage) -> faerie::Decl {
     match linkage {
         Linkage::Import => faerie::Decl::function_import().into(),
@@ -29,6 +29,8 @@ pub fn raw_relocation(reloc: Reloc, triple: &Triple) -> (u32, i64) {
                             // R_X86_64_GOTPCRELX/R_X86_64_REX_GOTPCRELX.
                             Reloc::X86CallPLTRel4 => elf::reloc::R_X86_64_PLT32,
                             Reloc::X86GOTPCRel4 => elf::reloc::R_X86_64_GOTPCREL,
+                            Reloc::ElfX86_64TlsGd => elf::reloc::R_X86_64_TLSGD,
+                            Reloc::ElfX86_64GotTpOff => elf::reloc::R_X86_64_GOTTPOFF,
                             _ => unimplemented!(),
                         }
                     }
@@ -0,0 +1,113 @@
+//! Disassembly of machine code with Capstone.
+//!
+//! This module is only available with the `disas` feature. It is used by the `test binemit`
+//! command to check `dis:` directives, and by `clif-util compile` to print the generated code.
+
+use capstone::prelude::*;
+use cranelift_codegen::binemit::CodeOffset;
+use cranelift_codegen::isa::TargetIsa;
+use std::fmt::Write;
+use target_lexicon::Architecture;
+
+/// Create a Capstone disassembler for the architecture of `isa`.
+pub fn disassembler(isa: &TargetIsa) -> Result<Capstone, String> {
+    let cs = match isa.triple().architecture {
+        Architecture::Riscv32 | Architecture::Riscv64 => {
+            return Err(String::from("No disassembler for RiscV"));
+        }
+        Architecture::I386 | Architecture::I586 | Architecture::I686 => Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode32)
+            .build(),
+        Architecture::X86_64 => Capstone::new()
+            .x86()
+            .mode(arch::x86::ArchMode::Mode64)
+            .build(),
+        Architecture::Arm
+        | Architecture::Armv4t
+        | Architecture::Armv5te
+        | Architecture::Armv7
+        | Architecture::Armv7s => Capstone::new().arm().mode(arch::arm::ArchMode::Arm).build(),
+        Architecture::Thumbv6m | Architecture::Thumbv7em | Architecture::Thumbv7m => {
+            Capstone::new()
+                .arm()
+                .mode(arch::arm::ArchMode::Thumb)
+                .build()
+        }
+        Architecture::Aarch64 => Capstone::new()
+            .arm64()
+            .mode(arch::arm64::ArchMode::Arm)
+            .build(),
+        _ => return Err(String::from("Unknown ISA")),
+    };
+
+    cs.map_err(|err| err.to_string())
+}
+
+/// Disassemble the machine code in `mem`, located at `offset` in the function.
+///
+/// Returns one line per machine instruction, with the offset and bytes of the instruction
+/// followed by its mnemonic and operands.
+pub fn disassemble(cs: &mut Capstone, mem: &[u8], offset: CodeOffset) -> Result<String, String> {
+    let insns = cs
+        .disasm_all(mem, u64::from(offset))
+        .map_err(|err| err.to_string())?;
+    let mut text = String::new();
+    for i in insns.iter() {
+        write!(&mut text, "{:4x}:\t", i.address()).unwrap();
+
+        let mut bytes_str = String::new();
+        for b in i.bytes() {
+            write!(&mut bytes_str, "{:02x} ", b).unwrap();
+        }
+        write!(&mut text, "{:21}\t", bytes_str).unwrap();
+
+        if let Some(s) = i.mnemonic() {
+            write!(&mut text, "{}\t", s).unwrap();
+        }
+
+        if let Some(s) = i.op_str() {
+            write!(&mut text, "{}", s).unwrap();
+        }
+
+        writeln!(&mut text).unwrap();
+    }
+    Ok(text)
+}
+
+/// Disassemble the machine code of a single IR instruction at `offset`.
+///
+/// Returns the mnemonics and operands of the machine instructions, separated by `; `. All of the
+/// bytes must decode into machine instructions.
+pub fn disassemble_inst(
+    cs: &mut Capstone,
+    mem: &[u8],
+    offset: CodeOffset,
+) -> Result<String, String> {
+    let insns = cs
+        .disasm_all(mem, u64::from(offset))
+        .map_err(|err| err.to_string())?;
+    let mut text = String::new();
+    let mut size = 0;
+    for i in insns.iter() {
+        if !text.is_empty() {
+            text.push_str("; ");
+        }
+        text.push_str(i.mnemonic().unwrap_or(""));
+        if let Some(s) = i.op_str() {
+            if !s.is_empty() {
+                text.push(' ');
+                text.push_str(s);
+            }
+        }
+        size += i.bytes().len();
+    }
+    if size != mem.len() {
+        return Err(format!(
+            "Can't disassemble {} of {} bytes",
+            mem.len() - size,
+            mem.len()
+        ));
+    }
+    Ok(text)
+}
@@ -30,25 +30,35 @@ use std::path::Path;
 use std::time;
 
 mod concurrent;
+#[cfg(feature = "disas")]
+pub mod disasm;
 mod match_directive;
 mod runner;
 mod runone;
 mod subtest;
 
 mod test_binemit;
+mod test_bounds_checks;
 mod test_cat;
 mod test_compile;
 mod test_dce;
+mod test_dead_ebb_params;
+mod test_deterministic;
 mod test_domtree;
+mod test_ebb_order;
 mod test_legalizer;
 mod test_licm;
+mod test_null_checks;
+mod test_osr;
 mod test_postopt;
 mod test_preopt;
 mod test_print_cfg;
 mod test_regalloc;
+mod test_schedule;
 mod test_shrink;
 mod test_simple_gvn;
 mod test_simple_preopt;
+mod test_simplify_cfg;
 mod test_verifier;
 
 /// The result of running the test in a file.
@@ -113,18 +123,26 @@ pub fn run_passes(
 fn new_subtest(parsed: &TestCommand) -> subtest::SubtestResult<Box<subtest::SubTest>> {
     match parsed.command {
         "binemit" => test_binemit::subtest(parsed),
+        "bounds-checks" => test_bounds_checks::subtest(parsed),
         "cat" => test_cat::subtest(parsed),
         "compile" => test_compile::subtest(parsed),
         "dce" => test_dce::subtest(parsed),
+        "dead-ebb-params" => test_dead_ebb_params::subtest(parsed),
+        "deterministic" => test_deterministic::subtest(parsed),
         "domtree" => test_domtree::subtest(parsed),
+        "ebb-order" => test_ebb_order::subtest(parsed),
         "legalizer" => test_legalizer::subtest(parsed),
         "licm" => test_licm::subtest(parsed),
+        "null-checks" => test_null_checks::subtest(parsed),
+        "osr" => test_osr::subtest(parsed),
         "postopt" => test_postopt::subtest(parsed),
         "simple_preopt" => test_simple_preopt::subtest(parsed),
         "print-cfg" => test_print_cfg::subtest(parsed),
         "regalloc" => test_regalloc::subtest(parsed),
+        "schedule" => test_schedule::subtest(parsed),
         "shrink" => test_shrink::subtest(parsed),
         "simple-gvn" => test_simple_gvn::subtest(parsed),
+        "simplify-cfg" => test_simplify_cfg::subtest(parsed),
         "verifier" => test_verifier::subtest(parsed),
         "preopt" => test_preopt::subtest(parsed),
         _ => Err(format!("unknown test command '{}'", parsed.command)),
@@ -44,7 +44,7 @@ pub fn run(path: &Path, passes: Option<&[String]>, target: Option<&str>) -> Test
                 );
                 return Ok(started.elapsed());
             }
-            return Err(e.to_string());
+            return Err(e.display_source(&buffer).to_string());
         }
     };
 
@@ -2,7 +2,12 @@
 //!
 //! The `binemit` test command generates binary machine code for every instruction in the input
 //! functions and compares the results to the expected output.
+//!
+//! The expected machine code is given in `bin:` directives. When the `disas` feature is enabled,
+//! the disassembly of the machine code is also compared to any `dis:` directives.
 
+#[cfg(feature = "disas")]
+use crate::disasm;
 use crate::match_directive::match_directive;
 use crate::subtest::{Context, SubTest, SubtestResult};
 use cranelift_codegen::binemit;
@@ -10,6 +15,7 @@ use cranelift_codegen::binemit::{CodeSink, RegDiversions};
 use cranelift_codegen::dbg::DisplayList;
 use cranelift_codegen::ir;
 use cranelift_codegen::ir::entities::AnyEntity;
+use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::print_errors::pretty_error;
 use cranelift_codegen::settings::OptLevel;
 use cranelift_reader::TestCommand;
@@ -33,6 +39,7 @@ struct TextSink {
     code_size: binemit::CodeOffset,
     offset: binemit::CodeOffset,
     text: String,
+    bytes: Vec<u8>,
 }
 
 impl TextSink {
@@ -42,6 +49,7 @@ impl TextSink {
             code_size: 0,
             offset: 0,
             text: String::new(),
+            bytes: Vec::new(),
         }
     }
 }
@@ -53,21 +61,25 @@ impl binemit::CodeSink for TextSink {
 
     fn put1(&mut self, x: u8) {
         write!(self.text, "{:02x} ", x).unwrap();
+        self.bytes.push(x);
         self.offset += 1;
     }
 
     fn put2(&mut self, x: u16) {
         write!(self.text, "{:04x} ", x).unwrap();
+        self.bytes.extend((0..2).map(|i| (x >> (8 * i)) as u8));
         self.offset += 2;
     }
 
     fn put4(&mut self, x: u32) {
         write!(self.text, "{:08x} ", x).unwrap();
+        self.bytes.extend((0..4).map(|i| (x >> (8 * i)) as u8));
         self.offset += 4;
     }
 
     fn put8(&mut self, x: u64) {
         write!(self.text, "{:016x} ", x).unwrap();
+        self.bytes.extend((0..8).map(|i| (x >> (8 * i)) as u8));
         self.offset += 8;
     }
 
@@ -96,6 +108,22 @@ impl binemit::CodeSink for TextSink {
         write!(self.text, "{} ", code).unwrap();
     }
 
+    fn landing_pad(&mut self, landing_pad: binemit::CodeOffset) {
+        write!(self.text, "LandingPad({}) ", landing_pad).unwrap();
+    }
+
+    fn safepoint(&mut self, _srcloc: ir::SourceLoc, locations: &[binemit::DeoptLocation]) {
+        write!(self.text, "Safepoint({}) ", DisplayList(locations)).unwrap();
+    }
+
+    fn stackmap(&mut self, ref_offsets: &[ir::StackOffset]) {
+        write!(self.text, "Stackmap({}) ", DisplayList(ref_offsets)).unwrap();
+    }
+
+    fn patchable_range(&mut self, start: binemit::CodeOffset) {
+        write!(self.text, "Patchable({}..{}) ", start, self.offset).unwrap();
+    }
+
     fn begin_rodata(&mut self) {
         self.code_size = self.offset
     }
@@ -147,7 +175,7 @@ impl SubTest for TestBinEmit {
                                 recipe_constraints.satisfied(inst, &divert, &func)
                             });
 
-                        if opt_level == OptLevel::Best {
+                        if opt_level == OptLevel::SpeedAndSize {
                             // Get the smallest legal encoding
                             legal_encodings
                                 .min_by_key(|&e| encinfo.byte_size(e, inst, &divert, &func))
@@ -167,27 +195,31 @@ impl SubTest for TestBinEmit {
         let code_size = binemit::relax_branches(&mut func, isa)
             .map_err(|e| pretty_error(&func, context.isa, e))?;
 
-        // Collect all of the 'bin:' directives on instructions.
+        // Collect all of the 'bin:' and 'dis:' directives on instructions.
         let mut bins = HashMap::new();
+        let mut diss = HashMap::new();
         for comment in &context.details.comments {
-            if let Some(want) = match_directive(comment.text, "bin:") {
-                match comment.entity {
-                    AnyEntity::Inst(inst) => {
-                        if let Some(prev) = bins.insert(inst, want) {
+            for &(directive, ref mut map) in &mut [("bin:", &mut bins), ("dis:", &mut diss)] {
+                if let Some(want) = match_directive(comment.text, directive) {
+                    match comment.entity {
+                        AnyEntity::Inst(inst) => {
+                            if let Some(prev) = map.insert(inst, want) {
+                                return Err(format!(
+                                    "multiple '{}' directives on {}: '{}' and '{}'",
+                                    directive,
+                                    func.dfg.display_inst(inst, isa),
+                                    prev,
+                                    want
+                                ));
+                            }
+                        }
+                        _ => {
                             return Err(format!(
-                                "multiple 'bin:' directives on {}: '{}' and '{}'",
-                                func.dfg.display_inst(inst, isa),
-                                prev,
-                                want
+                                "'{}' directive on non-inst {}: {}",
+                                directive, comment.entity, comment.text
                             ));
                         }
                     }
-                    _ => {
-                        return Err(format!(
-                            "'bin:' directive on non-inst {}: {}",
-                            comment.entity, comment.text
-                        ));
-                    }
                 }
             }
         }
@@ -199,6 +231,10 @@ impl SubTest for TestBinEmit {
         let mut sink = TextSink::new();
         for ebb in func.layout.ebbs() {
             divert.clear();
+            // Aligned EBBs are preceded by padding.
+            if sink.offset < func.offsets[ebb] {
+                isa.emit_padding(func.offsets[ebb] - sink.offset, &mut sink);
+            }
             // Correct header offsets should have been computed by `relax_branches()`.
             assert_eq!(
                 sink.offset, func.offsets[ebb],
@@ -208,6 +244,7 @@ impl SubTest for TestBinEmit {
             for (offset, inst, enc_bytes) in func.inst_offsets(ebb, &encinfo) {
                 assert_eq!(sink.offset, offset);
                 sink.text.clear();
+                sink.bytes.clear();
                 let enc = func.encodings[inst];
 
                 // Send legal encodings into the emitter.
@@ -285,6 +322,21 @@ impl SubTest for TestBinEmit {
                         ));
                     }
                 }
+
+                // Check against dis: directives.
+                if let Some(want) = diss.remove(&inst) {
+                    if let Some(have) = disassemble_inst(isa, &sink.bytes, offset)? {
+                        if have != want {
+                            return Err(format!(
+                                "Bad disassembly for {}: {}\nWant: {}\nGot:  {}",
+                                inst,
+                                func.dfg.display_inst(inst, isa),
+                                want,
+                                have
+                            ));
+                        }
+                    }
+                }
             }
         }
 
@@ -308,3 +360,24 @@ impl SubTest for TestBinEmit {
         Ok(())
     }
 }
+
+/// Disassemble the machine code `bytes` emitted for an instruction at `offset`.
+#[cfg(feature = "disas")]
+fn disassemble_inst(
+    isa: &TargetIsa,
+    bytes: &[u8],
+    offset: binemit::CodeOffset,
+) -> Result<Option<String>, String> {
+    let mut cs = disasm::disassembler(isa)?;
+    disasm::disassemble_inst(&mut cs, bytes, offset).map(Some)
+}
+
+/// Without a disassembler, `dis:` directives are not checked.
+#[cfg(not(feature = "disas"))]
+fn disassemble_inst(
+    _isa: &TargetIsa,
+    _bytes: &[u8],
+    _offset: binemit::CodeOffset,
+) -> Result<Option<String>, String> {
+    Ok(None)
+}
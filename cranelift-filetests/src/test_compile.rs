@@ -1,24 +1,35 @@
 //! Test command for testing the code generator pipeline
 //!
-//! The `compile` test command runs each function through the full code generator pipeline
+//! The `compile` test command runs each function through the full code generator pipeline.
+//!
+//! With the `unsupported` option, compilation is expected to fail because the target ISA doesn't
+//! support an instruction in the function, and the error is sent to filecheck instead.
 
 use crate::subtest::{run_filecheck, Context, SubTest, SubtestResult};
+use crate::test_deterministic::check_recompile;
 use cranelift_codegen;
 use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::CodegenError;
 use cranelift_codegen::{binemit, ir};
-use cranelift_reader::TestCommand;
+use cranelift_reader::{TestCommand, TestOption};
 use log::info;
 use std::borrow::Cow;
 
-struct TestCompile;
+struct TestCompile {
+    /// Expect an `Unsupported` error instead of compiled code.
+    unsupported: bool,
+}
 
 pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
     assert_eq!(parsed.command, "compile");
-    if !parsed.options.is_empty() {
-        Err(format!("No options allowed on {}", parsed))
-    } else {
-        Ok(Box::new(TestCompile))
+    let mut test = TestCompile { unsupported: false };
+    for option in &parsed.options {
+        match *option {
+            TestOption::Flag("unsupported") => test.unsupported = true,
+            _ => return Err(format!("Unknown option on {}: {}", parsed, option)),
+        }
     }
+    Ok(Box::new(test))
 }
 
 impl SubTest for TestCompile {
@@ -36,7 +47,17 @@ impl SubTest for TestCompile {
 
     fn run(&self, func: Cow<ir::Function>, context: &Context) -> SubtestResult<()> {
         let isa = context.isa.expect("compile needs an ISA");
-        let mut comp_ctx = cranelift_codegen::Context::for_function(func.into_owned());
+        let mut comp_ctx = cranelift_codegen::Context::for_function(func.clone().into_owned());
+
+        if self.unsupported {
+            return match comp_ctx.compile(isa) {
+                Ok(_) => Err("Expected an unsupported instruction error".to_string()),
+                Err(e @ CodegenError::Unsupported { .. }) => {
+                    run_filecheck(&pretty_error(&comp_ctx.func, context.isa, e), context)
+                }
+                Err(e) => Err(pretty_error(&comp_ctx.func, context.isa, e)),
+            };
+        }
 
         let code_size = comp_ctx
             .compile(isa)
@@ -53,6 +74,7 @@ impl SubTest for TestCompile {
         binemit::emit_function(
             &comp_ctx.func,
             |func, inst, div, sink| isa.emit_inst(func, inst, div, sink),
+            |size, sink| isa.emit_padding(size, sink),
             &mut sink,
         );
 
@@ -63,6 +85,18 @@ impl SubTest for TestCompile {
             ));
         }
 
+        // The size computed from the encoding recipes must match too.
+        let recipe_size = comp_ctx.code_size(isa);
+        if recipe_size != code_size {
+            return Err(format!(
+                "Expected code size {} from the recipes, got {}",
+                code_size, recipe_size
+            ));
+        }
+
+        // Compiling the function again must give the same machine code.
+        check_recompile(&func, &comp_ctx, code_size, isa)?;
+
         // Run final code through filecheck.
         let text = comp_ctx.func.display(Some(isa)).to_string();
         run_filecheck(&text, context)
@@ -105,5 +139,9 @@ impl binemit::CodeSink for SizeSink {
     }
     fn reloc_jt(&mut self, _reloc: binemit::Reloc, _jt: ir::JumpTable) {}
     fn trap(&mut self, _code: ir::TrapCode, _srcloc: ir::SourceLoc) {}
+    fn landing_pad(&mut self, _landing_pad: binemit::CodeOffset) {}
+    fn safepoint(&mut self, _srcloc: ir::SourceLoc, _locations: &[binemit::DeoptLocation]) {}
+    fn stackmap(&mut self, _ref_offsets: &[ir::StackOffset]) {}
+    fn patchable_range(&mut self, _start: binemit::CodeOffset) {}
     fn begin_rodata(&mut self) {}
 }
@@ -0,0 +1,46 @@
+//! Test command for testing the dead EBB parameter removal pass.
+//!
+//! The `dead-ebb-params` test command runs each function through the dead EBB parameter removal
+//! pass.
+//!
+//! The resulting function is sent to `filecheck`.
+
+use crate::subtest::{run_filecheck, Context, SubTest, SubtestResult};
+use cranelift_codegen;
+use cranelift_codegen::ir::Function;
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+
+struct TestDeadEbbParams;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "dead-ebb-params");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestDeadEbbParams))
+    }
+}
+
+impl SubTest for TestDeadEbbParams {
+    fn name(&self) -> &'static str {
+        "dead-ebb-params"
+    }
+
+    fn is_mutating(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> SubtestResult<()> {
+        let mut comp_ctx = cranelift_codegen::Context::for_function(func.into_owned());
+
+        comp_ctx.compute_cfg();
+        comp_ctx
+            .dead_ebb_params(context.flags_or_isa())
+            .map_err(|e| pretty_error(&comp_ctx.func, context.isa, Into::into(e)))?;
+
+        let text = comp_ctx.func.display(context.isa).to_string();
+        run_filecheck(&text, context)
+    }
+}
@@ -0,0 +1,133 @@
+//! Test command for checking that compilation is deterministic.
+//!
+//! The `deterministic` test command compiles each function twice from scratch and checks that the
+//! emitted machine code, relocations and traps are identical. The `compile` test command performs
+//! the same check with `check_recompile`.
+
+use crate::subtest::{Context, SubTest, SubtestResult};
+use cranelift_codegen;
+use cranelift_codegen::binemit::{Addend, CodeOffset, Reloc, RelocSink, TrapSink};
+use cranelift_codegen::ir::{self, ExternalName, JumpTable, SourceLoc, TrapCode};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+
+struct TestDeterministic;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "deterministic");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestDeterministic))
+    }
+}
+
+impl SubTest for TestDeterministic {
+    fn name(&self) -> &'static str {
+        "deterministic"
+    }
+
+    fn needs_isa(&self) -> bool {
+        true
+    }
+
+    fn run(&self, func: Cow<ir::Function>, context: &Context) -> SubtestResult<()> {
+        let isa = context.isa.expect("deterministic needs an ISA");
+        let first = compile(&func, isa)?;
+        let second = compile(&func, isa)?;
+        compare(&first, &second)
+    }
+}
+
+/// Check that compiling a fresh copy of `func` gives the same output as `comp_ctx`, which holds
+/// `func` compiled to `code_size` bytes.
+pub fn check_recompile(
+    func: &ir::Function,
+    comp_ctx: &cranelift_codegen::Context,
+    code_size: CodeOffset,
+    isa: &TargetIsa,
+) -> SubtestResult<()> {
+    let first = emit(comp_ctx, code_size, isa);
+    let second = compile(func, isa)?;
+    compare(&first, &second)
+}
+
+/// The machine code of a function and a description of the relocations and traps.
+struct Output {
+    code: Vec<u8>,
+    sinks: Vec<String>,
+}
+
+/// Compile a copy of `func` in a new context.
+fn compile(func: &ir::Function, isa: &TargetIsa) -> SubtestResult<Output> {
+    let mut comp_ctx = cranelift_codegen::Context::for_function(func.clone());
+    let code_size = comp_ctx
+        .compile(isa)
+        .map_err(|e| pretty_error(&comp_ctx.func, Some(isa), e))?;
+    Ok(emit(&comp_ctx, code_size, isa))
+}
+
+/// Emit the function compiled in `comp_ctx`.
+fn emit(comp_ctx: &cranelift_codegen::Context, code_size: CodeOffset, isa: &TargetIsa) -> Output {
+    let mut code = vec![0; code_size as usize];
+    let mut relocs = RecordSink(Vec::new());
+    let mut traps = RecordSink(Vec::new());
+    unsafe { comp_ctx.emit_to_memory(isa, code.as_mut_ptr(), &mut relocs, &mut traps) };
+
+    let mut sinks = relocs.0;
+    sinks.extend(traps.0);
+    Output { code, sinks }
+}
+
+/// Report any difference between the outputs of two compilations.
+fn compare(first: &Output, second: &Output) -> SubtestResult<()> {
+    if first.code != second.code {
+        return Err(format!(
+            "Machine code differs between compilations:\n{:02x?}\n{:02x?}",
+            first.code, second.code
+        ));
+    }
+    if first.sinks != second.sinks {
+        return Err(format!(
+            "Relocations or traps differ between compilations:\n{}\n{}",
+            first.sinks.join("\n"),
+            second.sinks.join("\n")
+        ));
+    }
+    Ok(())
+}
+
+/// Sink recording a line of text per relocation or trap.
+struct RecordSink(Vec<String>);
+
+impl RelocSink for RecordSink {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        self.0
+            .push(format!("reloc_ebb {} {} {}", offset, reloc, ebb_offset));
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.0.push(format!(
+            "reloc_external {} {} {} {}",
+            offset, reloc, name, addend
+        ));
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.0.push(format!("reloc_jt {} {} {}", offset, reloc, jt));
+    }
+}
+
+impl TrapSink for RecordSink {
+    fn trap(&mut self, offset: CodeOffset, srcloc: SourceLoc, code: TrapCode) {
+        self.0.push(format!("trap {} {} {}", offset, srcloc, code));
+    }
+}
@@ -0,0 +1,43 @@
+//! Test command for testing the creation of OSR entries.
+//!
+//! The `osr` test command creates the OSR variant of each function for every EBB marked as an
+//! OSR entry.
+//!
+//! The resulting functions are sent to `filecheck`.
+
+use crate::subtest::{run_filecheck, Context, SubTest, SubtestResult};
+use cranelift_codegen::ir::{types, Function};
+use cranelift_codegen::osr::make_osr_entry;
+use cranelift_reader::TestCommand;
+use std::borrow::Cow;
+
+struct TestOsr;
+
+pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
+    assert_eq!(parsed.command, "osr");
+    if !parsed.options.is_empty() {
+        Err(format!("No options allowed on {}", parsed))
+    } else {
+        Ok(Box::new(TestOsr))
+    }
+}
+
+impl SubTest for TestOsr {
+    fn name(&self) -> &'static str {
+        "osr"
+    }
+
+    fn run(&self, func: Cow<Function>, context: &Context) -> SubtestResult<()> {
+        let pointer_type = context.isa.map_or(types::I64, |isa| isa.pointer_type());
+
+        let mut text = String::new();
+        for ebb in func.layout.ebbs() {
+            if func.layout.is_osr_entry(ebb) {
+                let osr_func =
+                    make_osr_entry(&func, ebb, pointer_type).map_err(|e| e.to_string())?;
+                text += &osr_func.display(context.isa).to_string();
+            }
+        }
+        run_filecheck(&text, context)
+    }
+}
@@ -8,18 +8,30 @@ use std::borrow::Cow;
 use crate::subtest::{self, Context, SubTest, SubtestResult};
 use cranelift_codegen::cfg_printer::CFGPrinter;
 use cranelift_codegen::ir::Function;
-use cranelift_reader::TestCommand;
+use cranelift_reader::{TestCommand, TestOption};
 
 /// Object implementing the `test print-cfg` sub-test.
-struct TestPrintCfg;
+struct TestPrintCfg {
+    /// Print the dominator tree edges.
+    domtree: bool,
+    /// Group the EBBs of each loop in a cluster.
+    loops: bool,
+}
 
 pub fn subtest(parsed: &TestCommand) -> SubtestResult<Box<SubTest>> {
     assert_eq!(parsed.command, "print-cfg");
-    if !parsed.options.is_empty() {
-        Err(format!("No options allowed on {}", parsed))
-    } else {
-        Ok(Box::new(TestPrintCfg))
+    let mut test = TestPrintCfg {
+        domtree: false,
+        loops: false,
+    };
+    for option in &parsed.options {
+        match *option {
+            TestOption::Flag("domtree") => test.domtree = true,
+            TestOption::Flag("loops") => test.loops = true,
+            _ => return Err(format!("Unknown option on {}: {}", parsed, option)),
+        }
     }
+    Ok(Box::new(test))
 }
 
 impl SubTest for TestPrintCfg {
@@ -32,6 +44,13 @@ impl SubTest for TestPrintCfg {
     }
 
     fn run(&self, func: Cow<Function>, context: &Context) -> SubtestResult<()> {
-        subtest::run_filecheck(&CFGPrinter::new(&func).to_string(), context)
+        let mut printer = CFGPrinter::new(&func);
+        if self.domtree {
+            printer = printer.with_domtree();
+        }
+        if self.loops {
+            printer = printer.with_loops();
+        }
+        subtest::run_filecheck(&printer.to_string(), context)
     }
 }
@@ -8,11 +8,20 @@
 //!
 //! This annotation means that the verifier is expected to given an error for the jump instruction
 //! containing the substring "jump to non-existent EBB".
+//!
+//! When the test has a target ISA and the function has value locations, as after register
+//! allocation, the liveness and value location verifiers also run, with a liveness analysis
+//! computed for the function.
 
 use crate::match_directive::match_directive;
 use crate::subtest::{Context, SubTest, SubtestResult};
+use cranelift_codegen::flowgraph::ControlFlowGraph;
 use cranelift_codegen::ir::Function;
-use cranelift_codegen::verify_function;
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::verifier::{
+    verify_liveness, verify_locations, VerifierErrors, VerifierResult,
+};
+use cranelift_codegen::{verify_function, Liveness};
 use cranelift_reader::TestCommand;
 use std::borrow::{Borrow, Cow};
 use std::fmt::Write;
@@ -50,7 +59,13 @@ impl SubTest for TestVerifier {
             }
         }
 
-        match verify_function(func, context.flags_or_isa()) {
+        let result =
+            verify_function(func, context.flags_or_isa()).and_then(|()| match context.isa {
+                Some(isa) if has_locations(func) => verify_allocation(isa, func),
+                _ => Ok(()),
+            });
+
+        match result {
             Ok(()) if expected.is_empty() => Ok(()),
             Ok(()) => Err(format!("passed, but expected errors: {:?}", expected)),
 
@@ -92,3 +107,27 @@ impl SubTest for TestVerifier {
         }
     }
 }
+
+/// Have any values in `func` been assigned a location?
+fn has_locations(func: &Function) -> bool {
+    func.dfg
+        .values()
+        .any(|value| func.locations[value].is_assigned())
+}
+
+/// Verify the liveness and value locations of a register-allocated function.
+fn verify_allocation(isa: &TargetIsa, func: &Function) -> VerifierResult<()> {
+    let mut func = func.clone();
+    let cfg = ControlFlowGraph::with_function(&func);
+    let mut liveness = Liveness::new();
+    liveness.compute(isa, &mut func, &cfg);
+
+    let mut errors = VerifierErrors::default();
+    let _ = verify_liveness(isa, &func, &cfg, &liveness, &mut errors)
+        .and_then(|()| verify_locations(isa, &func, Some(&liveness), &mut errors));
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
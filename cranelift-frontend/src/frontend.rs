@@ -6,10 +6,10 @@ use cranelift_codegen::entity::{EntitySet, SecondaryMap};
 use cranelift_codegen::ir;
 use cranelift_codegen::ir::function::DisplayFunction;
 use cranelift_codegen::ir::{
-    types, AbiParam, DataFlowGraph, Ebb, ExtFuncData, ExternalName, FuncRef, Function, GlobalValue,
-    GlobalValueData, Heap, HeapData, Inst, InstBuilder, InstBuilderBase, InstructionData,
-    JumpTable, JumpTableData, LibCall, MemFlags, SigRef, Signature, StackSlot, StackSlotData, Type,
-    Value,
+    types, AbiParam, ArgumentPurpose, DataFlowGraph, Ebb, ExtFuncData, ExternalName, FuncRef,
+    Function, GlobalValue, GlobalValueData, Heap, HeapData, Inst, InstBuilder, InstBuilderBase,
+    InstructionData, JumpTable, JumpTableData, LibCall, MemFlags, SigRef, Signature, StackSlot,
+    StackSlotData, StackSlotKind, Type, Value,
 };
 use cranelift_codegen::isa::{TargetFrontendConfig, TargetIsa};
 use cranelift_codegen::packed_option::PackedOption;
@@ -243,6 +243,39 @@ impl<'a> FunctionBuilder<'a> {
         ebb
     }
 
+    /// Marks `ebb` as cold, i.e. rarely executed.
+    ///
+    /// This is a hint for the code layout: cold EBBs, like trap handlers and slow paths, are
+    /// placed at the end of the function, away from the frequently executed code.
+    pub fn set_cold_block(&mut self, ebb: Ebb) {
+        self.func.layout.set_cold(ebb, true);
+    }
+
+    /// Marks `ebb` as an OSR entry, i.e. a loop header where a tiering JIT can enter the
+    /// optimized code of the function.
+    ///
+    /// The variables used in `ebb` and the code reachable from it are passed as parameters of
+    /// `ebb`, even when they have the same value in all its predecessors, so that they are part of
+    /// the live state given to `cranelift_codegen::osr::make_osr_entry`. This must be called
+    /// before the variables are used in `ebb` or its successors.
+    pub fn set_osr_entry_block(&mut self, ebb: Ebb) {
+        self.func.layout.set_osr_entry(ebb, true);
+    }
+
+    /// Sets the probability that the conditional branch `inst` is taken.
+    ///
+    /// This is a hint for embedders with profile data. The code generator uses it to lay out the
+    /// likely path as fallthroughs and to keep spill code off of it.
+    pub fn set_branch_probability(&mut self, inst: Inst, probability: ir::BranchProbability) {
+        let opcode = self.func.dfg[inst].opcode();
+        debug_assert!(
+            opcode.is_branch() && !opcode.is_terminator(),
+            "{} is not a conditional branch",
+            opcode
+        );
+        self.func.branch_probabilities[inst] = probability;
+    }
+
     /// After the call to this function, new instructions will be inserted into the designated
     /// block, in the order they are declared. You must declare the types of the Ebb arguments
     /// you will use here.
@@ -514,6 +547,37 @@ impl<'a> FunctionBuilder<'a> {
             .declare_ebb_predecessor(new_dest, pred, inst);
     }
 
+    /// Turns the direct call `inst` into a `try_call` unwinding to `landing_pad`.
+    ///
+    /// This is useful when the call was inserted with a `FuncCursor`, which doesn't declare the
+    /// landing pad as a successor. The call must be the last instruction of the current `Ebb`.
+    pub fn convert_to_try_call(&mut self, inst: Inst, landing_pad: Ebb) {
+        let (func_ref, args) = match self.func.dfg[inst] {
+            InstructionData::Call {
+                opcode: ir::Opcode::Call,
+                func_ref,
+                ref args,
+            } => (func_ref, args.clone()),
+            _ => panic!(
+                "{} is not a direct call",
+                self.func.dfg.display_inst(inst, None)
+            ),
+        };
+        debug_assert_eq!(
+            self.func.layout.last_inst(self.position.ebb.unwrap()),
+            Some(inst),
+            "the call must be the last instruction of the current ebb"
+        );
+        self.func.dfg[inst] = InstructionData::TryCall {
+            opcode: ir::Opcode::TryCall,
+            func_ref,
+            destination: landing_pad,
+            args,
+        };
+        self.declare_successor(landing_pad, inst);
+        self.move_to_next_basic_block();
+    }
+
     /// Returns `true` if and only if the current `Ebb` is sealed and has no predecessors declared.
     ///
     /// The entry block of a function is never unreachable.
@@ -806,6 +870,92 @@ impl<'a> FunctionBuilder<'a> {
             self.ins().store(flags, value, dest, offset);
         }
     }
+
+    /// Calls `callee`, which returns a value in memory through a struct return pointer.
+    ///
+    /// The signature of `callee` must have an `sret` parameter. A buffer of `size` bytes is
+    /// allocated in an explicit stack slot, and its address is passed as the `sret` argument,
+    /// which is inserted among `args` at the position of the `sret` parameter.
+    ///
+    /// Returns the call instruction and the address of the buffer, which holds the returned value
+    /// after the call.
+    pub fn call_with_sret(&mut self, callee: FuncRef, args: &[Value], size: u32) -> (Inst, Value) {
+        let sig_ref = self.func.dfg.ext_funcs[callee].signature;
+        let (index, pointer_type) = {
+            let sig = &self.func.dfg.signatures[sig_ref];
+            let index = sig
+                .special_param_index(ArgumentPurpose::StructReturn)
+                .expect("the callee has no sret parameter");
+            (index, sig.params[index].value_type)
+        };
+
+        let slot = self.create_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size));
+        let buffer = self.ins().stack_addr(pointer_type, slot, 0);
+        let mut call_args = args.to_vec();
+        call_args.insert(index, buffer);
+        let call = self.ins().call(callee, &call_args);
+        (call, buffer)
+    }
+
+    /// Loads values of `types` from a struct return buffer at `buffer`.
+    ///
+    /// The values are laid out like the return area of a signature whose return values don't fit
+    /// in registers: each value is at the next offset aligned to its size.
+    pub fn load_sret_values(&mut self, buffer: Value, types: &[Type]) -> Vec<Value> {
+        let mut flags = MemFlags::new();
+        flags.set_notrap();
+        flags.set_aligned();
+
+        let (offsets, _) = sret_layout(types);
+        types
+            .iter()
+            .zip(offsets)
+            .map(|(&ty, offset)| self.ins().load(ty, flags, buffer, offset as i32))
+            .collect()
+    }
+
+    /// Stores `values` in the struct return buffer of the current function and returns.
+    ///
+    /// The function must have an `sret` parameter, and the values are laid out like
+    /// `load_sret_values` expects them.
+    pub fn return_with_sret(&mut self, values: &[Value]) -> Inst {
+        // The entry EBB holding the `sret` parameter may not be in the layout yet.
+        self.ensure_inserted_ebb();
+        let buffer = self
+            .func
+            .special_param(ArgumentPurpose::StructReturn)
+            .expect("the function has no sret parameter");
+
+        let mut flags = MemFlags::new();
+        flags.set_notrap();
+        flags.set_aligned();
+
+        let types: Vec<Type> = values
+            .iter()
+            .map(|&value| self.func.dfg.value_type(value))
+            .collect();
+        let (offsets, _) = sret_layout(&types);
+        for (&value, offset) in values.iter().zip(offsets) {
+            self.ins().store(flags, value, buffer, offset as i32);
+        }
+        self.ins().return_(&[])
+    }
+}
+
+/// Compute the offsets of values of `types` in a struct return buffer, and the size of the buffer.
+///
+/// Each value is stored at the next offset aligned to its size. This is the layout used by
+/// `FunctionBuilder::load_sret_values` and `FunctionBuilder::return_with_sret`.
+pub fn sret_layout(types: &[Type]) -> (Vec<u32>, u32) {
+    let mut offsets = Vec::with_capacity(types.len());
+    let mut size = 0;
+    for ty in types {
+        let bytes = ty.bytes();
+        let offset = (size + bytes - 1) / bytes * bytes;
+        offsets.push(offset);
+        size = offset + bytes;
+    }
+    (offsets, size)
 }
 
 fn greatest_divisible_power_of_two(size: u64) -> u64 {
@@ -846,12 +996,15 @@ impl<'a> FunctionBuilder<'a> {
 
 #[cfg(test)]
 mod tests {
-    use super::greatest_divisible_power_of_two;
+    use super::{greatest_divisible_power_of_two, sret_layout};
     use crate::frontend::{FunctionBuilder, FunctionBuilderContext};
     use crate::Variable;
     use cranelift_codegen::entity::EntityRef;
     use cranelift_codegen::ir::types::*;
-    use cranelift_codegen::ir::{AbiParam, ExternalName, Function, InstBuilder, Signature};
+    use cranelift_codegen::ir::{
+        AbiParam, ArgumentPurpose, BranchProbability, ExtFuncData, ExternalName, Function,
+        InstBuilder, Signature, TrapCode,
+    };
     use cranelift_codegen::isa::CallConv;
     use cranelift_codegen::settings;
     use cranelift_codegen::verifier::verify_function;
@@ -960,6 +1113,281 @@ mod tests {
         sample_function(true)
     }
 
+    #[test]
+    fn layout_hints() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_ebb();
+            let block1 = builder.create_ebb();
+            builder.set_cold_block(block1);
+            builder.append_ebb_params_for_function_params(block0);
+
+            builder.switch_to_block(block0);
+            let x = builder.ebb_params(block0)[0];
+            let branch = builder.ins().brnz(x, block1, &[]);
+            builder.set_branch_probability(branch, BranchProbability::from_percent(1));
+            builder.ins().return_(&[]);
+
+            builder.switch_to_block(block1);
+            builder.ins().trap(TrapCode::User(0));
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+
+        assert_eq!(
+            func.display(None).to_string(),
+            "function %sample(i32) system_v {
+ebb0(v0: i32):
+    brnz v0, ebb1 prob 1
+    return
+
+ebb1 cold:
+    trap user0
+}
+"
+        );
+    }
+
+    #[test]
+    fn osr_entry_block() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig);
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_ebb();
+            let block1 = builder.create_ebb();
+            let block2 = builder.create_ebb();
+            builder.set_osr_entry_block(block1);
+            let x = Variable::new(0);
+            let i = Variable::new(1);
+            builder.declare_var(x, I32);
+            builder.declare_var(i, I32);
+            builder.append_ebb_params_for_function_params(block0);
+
+            builder.switch_to_block(block0);
+            builder.seal_block(block0);
+            let arg = builder.ebb_params(block0)[0];
+            builder.def_var(x, arg);
+            let ten = builder.ins().iconst(I32, 10);
+            builder.def_var(i, ten);
+            builder.ins().jump(block1, &[]);
+
+            builder.switch_to_block(block1);
+            let arg1 = builder.use_var(i);
+            let arg2 = builder.use_var(x);
+            let sub = builder.ins().isub(arg1, arg2);
+            builder.def_var(i, sub);
+            builder.ins().brnz(sub, block1, &[]);
+            builder.ins().jump(block2, &[]);
+            builder.seal_block(block1);
+
+            builder.switch_to_block(block2);
+            builder.seal_block(block2);
+            let ret = builder.use_var(i);
+            builder.ins().return_(&[ret]);
+
+            builder.finalize();
+        }
+
+        // `x` is loop invariant, but it is still passed to the OSR entry.
+        assert_eq!(
+            func.display(None).to_string(),
+            "function %sample(i32) -> i32 system_v {
+ebb0(v0: i32):
+    v1 = iconst.i32 10
+    jump ebb1(v1, v0)
+
+ebb1(v2: i32, v3: i32) osr_entry:
+    v4 = isub v2, v3
+    brnz v4, ebb1(v4, v3)
+    jump ebb2
+
+ebb2:
+    return v4
+}
+"
+        );
+        verify_function(&func, &settings::Flags::new(settings::builder())).unwrap();
+    }
+
+    #[test]
+    fn multiple_returns() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        for _ in 0..4 {
+            sig.returns.push(AbiParam::new(I64));
+        }
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig.clone());
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_ebb();
+            let exit = builder.create_ebb();
+            builder.append_ebb_params_for_function_params(block0);
+            builder.append_ebb_params_for_function_returns(exit);
+
+            builder.switch_to_block(block0);
+            let x = builder.ebb_params(block0)[0];
+            let sig_ref = builder.import_signature(sig);
+            let callee = builder.import_function(ExtFuncData {
+                name: ExternalName::testcase("callee"),
+                signature: sig_ref,
+                colocated: false,
+            });
+            let call = builder.ins().call(callee, &[x]);
+            let results = builder.inst_results(call).to_vec();
+            builder.ins().jump(exit, &results);
+
+            builder.switch_to_block(exit);
+            let results = builder.ebb_params(exit).to_vec();
+            builder.ins().return_(&results);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+
+        assert_eq!(
+            func.display(None).to_string(),
+            "function %sample(i32) -> i64, i64, i64, i64 system_v {
+    sig0 = (i32) -> i64, i64, i64, i64 system_v
+    fn0 = %callee sig0
+
+ebb0(v0: i32):
+    v1, v2, v3, v4 = call fn0(v0)
+    jump ebb1(v1, v2, v3, v4)
+
+ebb1(v5: i64, v6: i64, v7: i64, v8: i64):
+    return v5, v6, v7, v8
+}
+"
+        );
+    }
+
+    #[test]
+    fn try_call() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params.push(AbiParam::new(I32));
+        sig.returns.push(AbiParam::new(I32));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig.clone());
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+            let x = Variable::new(0);
+            builder.declare_var(x, I32);
+
+            let block0 = builder.create_ebb();
+            let landing_pad = builder.create_ebb();
+            builder.append_ebb_params_for_function_params(block0);
+
+            builder.switch_to_block(block0);
+            let arg = builder.ebb_params(block0)[0];
+            builder.def_var(x, arg);
+            let sig_ref = builder.import_signature(sig);
+            let callee = builder.import_function(ExtFuncData {
+                name: ExternalName::testcase("callee"),
+                signature: sig_ref,
+                colocated: false,
+            });
+            let call = builder.cursor().ins().call(callee, &[arg]);
+            builder.convert_to_try_call(call, landing_pad);
+            let result = builder.inst_results(call)[0];
+            builder.def_var(x, result);
+            let value = builder.use_var(x);
+            builder.ins().return_(&[value]);
+
+            builder.switch_to_block(landing_pad);
+            builder.seal_block(landing_pad);
+            let value = builder.use_var(x);
+            builder.ins().return_(&[value]);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+
+        assert_eq!(
+            func.display(None).to_string(),
+            "function %sample(i32) -> i32 system_v {
+    sig0 = (i32) -> i32 system_v
+    fn0 = %callee sig0
+
+ebb0(v0: i32):
+    v1 = try_call fn0(v0), ebb1
+    return v1
+
+ebb1:
+    return v0
+}
+"
+        );
+    }
+
+    #[test]
+    fn sret() {
+        let mut sig = Signature::new(CallConv::SystemV);
+        sig.params
+            .push(AbiParam::special(I64, ArgumentPurpose::StructReturn));
+        sig.params.push(AbiParam::new(I32));
+
+        let mut fn_ctx = FunctionBuilderContext::new();
+        let mut func = Function::with_name_signature(ExternalName::testcase("sample"), sig.clone());
+        {
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_ctx);
+
+            let block0 = builder.create_ebb();
+            builder.append_ebb_params_for_function_params(block0);
+            builder.switch_to_block(block0);
+            let x = builder.ebb_params(block0)[1];
+
+            let sig_ref = builder.import_signature(sig);
+            let callee = builder.import_function(ExtFuncData {
+                name: ExternalName::testcase("callee"),
+                signature: sig_ref,
+                colocated: false,
+            });
+            let types = [I32, I64];
+            let (_, buffer) = builder.call_with_sret(callee, &[x], sret_layout(&types).1);
+            let results = builder.load_sret_values(buffer, &types);
+            builder.return_with_sret(&results);
+
+            builder.seal_all_blocks();
+            builder.finalize();
+        }
+
+        assert_eq!(
+            func.display(None).to_string(),
+            "function %sample(i64 sret, i32) system_v {
+    ss0 = explicit_slot 16
+    sig0 = (i64 sret, i32) system_v
+    fn0 = %callee sig0
+
+ebb0(v0: i64, v1: i32):
+    v2 = stack_addr.i64 ss0
+    call fn0(v2, v1)
+    v3 = load.i32 notrap aligned v2
+    v4 = load.i64 notrap aligned v2+8
+    store notrap aligned v3, v0
+    store notrap aligned v4, v0+8
+    return
+}
+"
+        );
+    }
+
     #[test]
     fn memcpy() {
         use core::str::FromStr;
@@ -547,6 +547,13 @@ impl SSABuilder {
                 ZeroOneOrMore::More => {}
             }
         }
+        // The live state of an OSR entry is passed in its parameters, so they are kept even when
+        // all the predecessors agree on the value.
+        if let ZeroOneOrMore::One(_) = pred_values {
+            if func.layout.is_osr_entry(dest_ebb) {
+                pred_values = ZeroOneOrMore::More;
+            }
+        }
         let result_val = match pred_values {
             ZeroOneOrMore::Zero => {
                 // The variable is used but never defined before. This is an irregularity in the
@@ -641,6 +648,23 @@ impl SSABuilder {
             BranchInfo::NotABranch => {
                 panic!("you have declared a non-branch instruction as a predecessor to an ebb");
             }
+            // The landing pad of a `try_call` can't take arguments, so we split the exceptional
+            // edge.
+            BranchInfo::SingleDest(_, _) if func.dfg[jump_inst].opcode().is_call() => {
+                let middle_ebb = func.dfg.make_ebb();
+                func.layout.append_ebb(middle_ebb);
+                let middle_block = self.declare_ebb_header_block(middle_ebb);
+                self.blocks[middle_block].add_predecessor(jump_inst_block, jump_inst);
+                self.mark_ebb_header_block_sealed(middle_block);
+
+                *func.dfg[jump_inst]
+                    .branch_destination_mut()
+                    .expect("should not happen") = middle_ebb;
+                let mut cur = FuncCursor::new(func).at_bottom(middle_ebb);
+                let middle_jump_inst = cur.ins().jump(dest_ebb, &[val]);
+                self.def_var(var, val, middle_block);
+                Some((middle_ebb, middle_block, middle_jump_inst))
+            }
             // For a single destination appending a jump argument to the instruction
             // is sufficient.
             BranchInfo::SingleDest(_, _) => {
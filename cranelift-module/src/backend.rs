@@ -120,6 +120,20 @@ where
     /// Return the finalized artifact from the backend, if relevant.
     fn get_finalized_data(&self, data: &Self::CompiledData) -> Self::FinalizedData;
 
+    /// Prepare to replace the definition `func` of the function `name` with a new one.
+    ///
+    /// Code which is already finalized must call the new definition once it is published, so the
+    /// backend needs to call functions through an indirection it can update. Returns an error if
+    /// the backend doesn't support this.
+    fn undefine_function(&mut self, name: &str, func: &Self::CompiledFunction) -> ModuleResult<()>;
+
+    /// Discard a function which is no longer needed, releasing the resources used by its code.
+    ///
+    /// The function may have been finalized and published. It is no longer referenced by the
+    /// `Module` once this returns `Ok`, but the caller must make sure its code isn't used anymore.
+    /// Returns an error if the backend can't remove functions.
+    fn free_function(&mut self, name: &str, func: &Self::CompiledFunction) -> ModuleResult<()>;
+
     /// "Publish" all finalized functions and data objects to their ultimate destinations.
     fn publish(&mut self);
 
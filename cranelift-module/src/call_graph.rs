@@ -0,0 +1,154 @@
+//! Defines `CallGraph`.
+
+use super::HashMap;
+use crate::module::FuncId;
+use cranelift_codegen::entity::EntitySet;
+use cranelift_codegen::ir::{self, InstructionData};
+use std::vec::Vec;
+
+/// The direct calls between a set of functions belonging to a `Module`.
+///
+/// Functions refer to each other through the `ir::ExternalName`s created by
+/// `Module::declare_func_in_func`. Calls to functions outside the set are ignored.
+pub struct CallGraph {
+    funcs: Vec<FuncId>,
+    callees: HashMap<FuncId, Vec<FuncId>>,
+}
+
+impl CallGraph {
+    /// Compute the call graph of `funcs`.
+    pub fn new<'a, I>(funcs: I) -> Self
+    where
+        I: IntoIterator<Item = (FuncId, &'a ir::Function)>,
+    {
+        let funcs: Vec<(FuncId, &ir::Function)> = funcs.into_iter().collect();
+        let mut callees = HashMap::new();
+        for &(id, _) in &funcs {
+            callees.insert(id, Vec::new());
+        }
+        for &(id, func) in &funcs {
+            let mut func_callees = Vec::new();
+            for callee in direct_callees(func) {
+                if callees.contains_key(&callee) && !func_callees.contains(&callee) {
+                    func_callees.push(callee);
+                }
+            }
+            callees.insert(id, func_callees);
+        }
+        Self {
+            funcs: funcs.into_iter().map(|(id, _)| id).collect(),
+            callees,
+        }
+    }
+
+    /// Get the functions directly called by `func`, in the order of their first call.
+    pub fn callees(&self, func: FuncId) -> &[FuncId] {
+        &self.callees[&func]
+    }
+
+    /// Get all the functions in the call graph, callees before callers.
+    ///
+    /// Functions that are part of a cycle of recursive calls are ordered as if the call closing the
+    /// cycle wasn't there.
+    pub fn bottom_up_order(&self) -> Vec<FuncId> {
+        let mut order = Vec::with_capacity(self.funcs.len());
+        let mut seen = EntitySet::new();
+        let mut stack = Vec::new();
+        for &root in &self.funcs {
+            if !seen.insert(root) {
+                continue;
+            }
+            // Depth-first post-order, keeping the next callee to visit for each function.
+            stack.push((root, 0));
+            while let Some(&(func, next)) = stack.last() {
+                match self.callees(func).get(next) {
+                    Some(&callee) => {
+                        stack.last_mut().unwrap().1 += 1;
+                        if seen.insert(callee) {
+                            stack.push((callee, 0));
+                        }
+                    }
+                    None => {
+                        order.push(func);
+                        stack.pop();
+                    }
+                }
+            }
+        }
+        order
+    }
+}
+
+/// Get the module functions called directly by `func`.
+fn direct_callees<'a>(func: &'a ir::Function) -> impl Iterator<Item = FuncId> + 'a {
+    func.layout
+        .ebbs()
+        .flat_map(move |ebb| func.layout.ebb_insts(ebb))
+        .filter_map(move |inst| match func.dfg[inst] {
+            InstructionData::Call { func_ref, .. } | InstructionData::TryCall { func_ref, .. } => {
+                match func.dfg.ext_funcs[func_ref].name {
+                    ir::ExternalName::User {
+                        namespace: 0,
+                        index,
+                    } => Some(FuncId::from_u32(index)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CallGraph;
+    use crate::module::FuncId;
+    use cranelift_codegen::cursor::{Cursor, FuncCursor};
+    use cranelift_codegen::entity::EntityRef;
+    use cranelift_codegen::ir::{ExtFuncData, ExternalName, Function, InstBuilder, Signature};
+    use cranelift_codegen::isa::CallConv;
+
+    /// Make a function calling the module functions `callees`.
+    fn make_function(callees: &[u32]) -> Function {
+        let mut func = Function::new();
+        let sig = func.import_signature(Signature::new(CallConv::SystemV));
+        let ebb0 = func.dfg.make_ebb();
+        let mut cur = FuncCursor::new(&mut func);
+        cur.insert_ebb(ebb0);
+        for &callee in callees {
+            let fn_ref = cur.func.import_function(ExtFuncData {
+                name: ExternalName::user(0, callee),
+                signature: sig,
+                colocated: true,
+            });
+            cur.ins().call(fn_ref, &[]);
+        }
+        cur.ins().return_(&[]);
+        func
+    }
+
+    #[test]
+    fn bottom_up() {
+        // f0 calls f1 and f2, f1 and f2 are mutually recursive and f2 calls f3 which isn't part
+        // of the graph.
+        let funcs = [
+            make_function(&[1, 2, 1]),
+            make_function(&[2]),
+            make_function(&[3, 1]),
+        ];
+        let graph = CallGraph::new(
+            funcs
+                .iter()
+                .enumerate()
+                .map(|(i, func)| (FuncId::new(i), func)),
+        );
+        assert_eq!(
+            graph.callees(FuncId::new(0)),
+            &[FuncId::new(1), FuncId::new(2)]
+        );
+        assert_eq!(graph.callees(FuncId::new(2)), &[FuncId::new(1)]);
+        assert_eq!(
+            graph.bottom_up_order(),
+            [FuncId::new(2), FuncId::new(1), FuncId::new(0)]
+        );
+    }
+}
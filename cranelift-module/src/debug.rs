@@ -0,0 +1,438 @@
+//! Minimal DWARF debug information for compiled functions.
+//!
+//! This describes each function with its name and address range, and maps its code to source
+//! lines. Cranelift only knows the `SourceLoc` of each instruction, so the source locations are
+//! used directly as the line numbers in a single source file named by the producer.
+//!
+//! The sections use version 2 of the DWARF format, which is understood by all debuggers, in
+//! little-endian byte order.
+//!
+//! The references to function addresses and to other sections are listed as relocations, so the
+//! sections can be written to relocatable object files. A JIT can ignore them and use the actual
+//! addresses of the functions instead.
+
+use cranelift_codegen::binemit::CodeOffset;
+use cranelift_codegen::ir::{self, SourceLoc};
+use cranelift_codegen::isa::TargetIsa;
+use std::string::String;
+use std::vec::Vec;
+
+/// Debug information about a compiled function.
+pub struct FunctionDebugInfo {
+    /// The name of the function.
+    pub name: String,
+    /// The address of the function's code.
+    pub address: u64,
+    /// The size of the function's code in bytes.
+    pub size: u32,
+    /// The source location of the code at each offset, as returned by `line_table()`.
+    pub lines: Vec<(CodeOffset, SourceLoc)>,
+}
+
+/// The contents of the DWARF sections describing a set of functions.
+pub struct DebugSections {
+    /// The `.debug_abbrev` section.
+    pub debug_abbrev: Vec<u8>,
+    /// The `.debug_info` section.
+    pub debug_info: Vec<u8>,
+    /// The `.debug_line` section.
+    pub debug_line: Vec<u8>,
+    /// The relocations in the `.debug_info` and `.debug_line` sections.
+    pub relocs: Vec<DebugReloc>,
+}
+
+/// Identifies one of the DWARF sections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugSectionId {
+    /// The `.debug_abbrev` section.
+    DebugAbbrev,
+    /// The `.debug_info` section.
+    DebugInfo,
+    /// The `.debug_line` section.
+    DebugLine,
+}
+
+impl DebugSectionId {
+    /// Get the name of the section in ELF object files.
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugSectionId::DebugAbbrev => ".debug_abbrev",
+            DebugSectionId::DebugInfo => ".debug_info",
+            DebugSectionId::DebugLine => ".debug_line",
+        }
+    }
+}
+
+/// The target of a relocation in the DWARF sections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugRelocTarget {
+    /// The address of the function with this index in the described functions.
+    Function(usize),
+    /// An offset in a section.
+    Section(DebugSectionId),
+}
+
+/// A relocation in the DWARF sections.
+///
+/// The relocated value is the address of `target` plus `addend`. The sections contain that value
+/// computed from the `address` of the functions and with section offsets starting at 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DebugReloc {
+    /// The section containing the relocated value.
+    pub section: DebugSectionId,
+    /// The offset of the relocated value in `section`.
+    pub offset: u32,
+    /// The size of the relocated value in bytes.
+    pub size: u8,
+    /// The target of the relocation.
+    pub target: DebugRelocTarget,
+    /// The addend.
+    pub addend: i64,
+}
+
+/// Get the source locations of the compiled function `func`.
+///
+/// This returns the offsets where the source location of the code changes, with the new source
+/// location. Instructions without a source location are considered part of the preceding line.
+pub fn line_table(func: &ir::Function, isa: &TargetIsa) -> Vec<(CodeOffset, SourceLoc)> {
+    let encinfo = isa.encoding_info();
+    let mut lines: Vec<(CodeOffset, SourceLoc)> = Vec::new();
+    for ebb in func.layout.ebbs() {
+        for (offset, inst, size) in func.inst_offsets(ebb, &encinfo) {
+            let srcloc = func.srclocs[inst];
+            if size == 0 || srcloc.is_default() {
+                continue;
+            }
+            if lines.last().map_or(true, |&(_, last)| last != srcloc) {
+                lines.push((offset, srcloc));
+            }
+        }
+    }
+    lines
+}
+
+// DWARF constants.
+const DW_TAG_COMPILE_UNIT: u8 = 0x11;
+const DW_TAG_SUBPROGRAM: u8 = 0x2e;
+const DW_CHILDREN_NO: u8 = 0;
+const DW_CHILDREN_YES: u8 = 1;
+const DW_AT_NAME: u8 = 0x03;
+const DW_AT_STMT_LIST: u8 = 0x10;
+const DW_AT_LOW_PC: u8 = 0x11;
+const DW_AT_HIGH_PC: u8 = 0x12;
+const DW_AT_EXTERNAL: u8 = 0x3f;
+const DW_FORM_ADDR: u8 = 0x01;
+const DW_FORM_DATA4: u8 = 0x06;
+const DW_FORM_STRING: u8 = 0x08;
+const DW_FORM_FLAG: u8 = 0x0c;
+const DW_LNS_COPY: u8 = 0x01;
+const DW_LNS_ADVANCE_PC: u8 = 0x02;
+const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+const DW_LNE_END_SEQUENCE: u8 = 0x01;
+const DW_LNE_SET_ADDRESS: u8 = 0x02;
+
+/// Abbreviation codes.
+const ABBREV_COMPILE_UNIT: u8 = 1;
+const ABBREV_SUBPROGRAM: u8 = 2;
+
+/// Standard opcode lengths in the line program header. No special opcodes are used.
+const STANDARD_OPCODE_LENGTHS: [u8; 12] = [0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1];
+
+/// Write the DWARF sections describing `funcs`, whose source locations refer to `source_name`.
+///
+/// Addresses are written with `pointer_bytes` bytes.
+pub fn write_debug_sections(
+    source_name: &str,
+    funcs: &[FunctionDebugInfo],
+    pointer_bytes: u8,
+) -> DebugSections {
+    let mut relocs = Vec::new();
+    let debug_info = write_info(source_name, funcs, pointer_bytes, &mut relocs);
+    let debug_line = write_line(source_name, funcs, pointer_bytes, &mut relocs);
+    DebugSections {
+        debug_abbrev: write_abbrev(),
+        debug_info,
+        debug_line,
+        relocs,
+    }
+}
+
+fn write_abbrev() -> Vec<u8> {
+    vec![
+        ABBREV_COMPILE_UNIT,
+        DW_TAG_COMPILE_UNIT,
+        DW_CHILDREN_YES,
+        DW_AT_NAME,
+        DW_FORM_STRING,
+        DW_AT_STMT_LIST,
+        DW_FORM_DATA4,
+        0,
+        0,
+        ABBREV_SUBPROGRAM,
+        DW_TAG_SUBPROGRAM,
+        DW_CHILDREN_NO,
+        DW_AT_NAME,
+        DW_FORM_STRING,
+        DW_AT_EXTERNAL,
+        DW_FORM_FLAG,
+        DW_AT_LOW_PC,
+        DW_FORM_ADDR,
+        DW_AT_HIGH_PC,
+        DW_FORM_ADDR,
+        0,
+        0,
+        0,
+    ]
+}
+
+/// Write the `.debug_info` section.
+///
+/// The compilation unit has no address range. Debuggers find the functions from the ranges of its
+/// children, which avoids relocations between functions that may be placed far apart.
+fn write_info(
+    source_name: &str,
+    funcs: &[FunctionDebugInfo],
+    pointer_bytes: u8,
+    relocs: &mut Vec<DebugReloc>,
+) -> Vec<u8> {
+    let mut w = Writer::new(DebugSectionId::DebugInfo, pointer_bytes, relocs);
+    // Compilation unit header, with the length patched below.
+    w.u32(0);
+    w.u16(2);
+    w.section_offset(DebugSectionId::DebugAbbrev);
+    w.u8(pointer_bytes);
+
+    w.u8(ABBREV_COMPILE_UNIT);
+    w.string(source_name);
+    w.section_offset(DebugSectionId::DebugLine);
+    for (index, func) in funcs.iter().enumerate() {
+        w.u8(ABBREV_SUBPROGRAM);
+        w.string(&func.name);
+        w.u8(1);
+        w.address(index, func, 0);
+        w.address(index, func, func.size);
+    }
+    w.u8(0);
+
+    w.finish_unit()
+}
+
+/// Write the `.debug_line` section.
+fn write_line(
+    source_name: &str,
+    funcs: &[FunctionDebugInfo],
+    pointer_bytes: u8,
+    relocs: &mut Vec<DebugReloc>,
+) -> Vec<u8> {
+    let mut w = Writer::new(DebugSectionId::DebugLine, pointer_bytes, relocs);
+    // Line program header, with the unit and header lengths patched below.
+    w.u32(0);
+    w.u16(2);
+    w.u32(0);
+    let header_start = w.data.len();
+    w.u8(1); // minimum_instruction_length
+    w.u8(1); // default_is_stmt
+    w.u8(-5i8 as u8); // line_base
+    w.u8(14); // line_range
+    w.u8(STANDARD_OPCODE_LENGTHS.len() as u8 + 1); // opcode_base
+    w.data.extend_from_slice(&STANDARD_OPCODE_LENGTHS);
+    w.u8(0); // No include directories.
+    w.string(source_name);
+    w.uleb128(0); // Directory.
+    w.uleb128(0); // Modification time.
+    w.uleb128(0); // Length.
+    w.u8(0);
+    let header_length = (w.data.len() - header_start) as u32;
+    w.data[6..10].copy_from_slice(&header_length.to_le_bytes());
+
+    // Each function is a separate sequence, starting at line 1.
+    for (index, func) in funcs.iter().enumerate() {
+        w.u8(0);
+        w.uleb128(u64::from(pointer_bytes) + 1);
+        w.u8(DW_LNE_SET_ADDRESS);
+        w.address(index, func, 0);
+
+        let mut offset = 0;
+        let mut line = 1;
+        for &(row_offset, srcloc) in &func.lines {
+            let row_line = i64::from(srcloc.bits());
+            if row_offset != offset {
+                w.u8(DW_LNS_ADVANCE_PC);
+                w.uleb128(u64::from(row_offset - offset));
+                offset = row_offset;
+            }
+            if row_line != line {
+                w.u8(DW_LNS_ADVANCE_LINE);
+                w.sleb128(row_line - line);
+                line = row_line;
+            }
+            w.u8(DW_LNS_COPY);
+        }
+
+        w.u8(DW_LNS_ADVANCE_PC);
+        w.uleb128(u64::from(func.size - offset));
+        w.u8(0);
+        w.uleb128(1);
+        w.u8(DW_LNE_END_SEQUENCE);
+    }
+
+    w.finish_unit()
+}
+
+/// Little-endian encoding of DWARF data in `section`.
+struct Writer<'a> {
+    section: DebugSectionId,
+    data: Vec<u8>,
+    pointer_bytes: u8,
+    relocs: &'a mut Vec<DebugReloc>,
+}
+
+impl<'a> Writer<'a> {
+    fn new(section: DebugSectionId, pointer_bytes: u8, relocs: &'a mut Vec<DebugReloc>) -> Self {
+        Self {
+            section,
+            data: Vec::new(),
+            pointer_bytes,
+            relocs,
+        }
+    }
+
+    fn reloc(&mut self, size: u8, target: DebugRelocTarget, addend: i64) {
+        self.relocs.push(DebugReloc {
+            section: self.section,
+            offset: self.data.len() as u32,
+            size,
+            target,
+            addend,
+        });
+    }
+
+    fn u8(&mut self, x: u8) {
+        self.data.push(x);
+    }
+
+    fn u16(&mut self, x: u16) {
+        self.data.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn u32(&mut self, x: u32) {
+        self.data.extend_from_slice(&x.to_le_bytes());
+    }
+
+    /// Write the address at `offset` in `func`, which has the index `index`.
+    fn address(&mut self, index: usize, func: &FunctionDebugInfo, offset: u32) {
+        let size = self.pointer_bytes;
+        self.reloc(size, DebugRelocTarget::Function(index), i64::from(offset));
+        let bytes = (func.address + u64::from(offset)).to_le_bytes();
+        self.data.extend_from_slice(&bytes[..size as usize]);
+    }
+
+    /// Write a reference to the start of `section`.
+    fn section_offset(&mut self, section: DebugSectionId) {
+        self.reloc(4, DebugRelocTarget::Section(section), 0);
+        self.u32(0);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.data.extend_from_slice(s.as_bytes());
+        self.data.push(0);
+    }
+
+    fn uleb128(&mut self, mut x: u64) {
+        loop {
+            let byte = x as u8 & 0x7f;
+            x >>= 7;
+            if x == 0 {
+                self.data.push(byte);
+                return;
+            }
+            self.data.push(byte | 0x80);
+        }
+    }
+
+    fn sleb128(&mut self, mut x: i64) {
+        loop {
+            let byte = x as u8 & 0x7f;
+            x >>= 7;
+            if (x == 0 && byte & 0x40 == 0) || (x == -1 && byte & 0x40 != 0) {
+                self.data.push(byte);
+                return;
+            }
+            self.data.push(byte | 0x80);
+        }
+    }
+
+    /// Patch the initial length of the unit and return the data.
+    fn finish_unit(mut self) -> Vec<u8> {
+        let length = (self.data.len() - 4) as u32;
+        self.data[0..4].copy_from_slice(&length.to_le_bytes());
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::ToString;
+
+    #[test]
+    fn leb128() {
+        let mut relocs = Vec::new();
+        let mut w = Writer::new(DebugSectionId::DebugInfo, 8, &mut relocs);
+        w.uleb128(2);
+        w.uleb128(624485);
+        w.sleb128(-1);
+        w.sleb128(63);
+        w.sleb128(64);
+        w.sleb128(-123456);
+        assert_eq!(
+            w.data,
+            [2, 0xe5, 0x8e, 0x26, 0x7f, 0x3f, 0xc0, 0x00, 0xc0, 0xbb, 0x78]
+        );
+    }
+
+    #[test]
+    fn line_program() {
+        let func = FunctionDebugInfo {
+            name: "f".to_string(),
+            address: 0x1000,
+            size: 12,
+            lines: vec![(0, SourceLoc::new(3)), (4, SourceLoc::new(2))],
+        };
+        let sections = write_debug_sections("a", &[func], 4);
+        let line = &sections.debug_line;
+        // The header is followed by the program for `f`.
+        let mut program = vec![0, 5, DW_LNE_SET_ADDRESS, 0x00, 0x10, 0, 0];
+        program.extend_from_slice(&[DW_LNS_ADVANCE_LINE, 2, DW_LNS_COPY]);
+        program.extend_from_slice(&[DW_LNS_ADVANCE_PC, 4, DW_LNS_ADVANCE_LINE, 0x7f, DW_LNS_COPY]);
+        program.extend_from_slice(&[DW_LNS_ADVANCE_PC, 8, 0, 1, DW_LNE_END_SEQUENCE]);
+        assert_eq!(&line[10 + line[6] as usize..], &program[..]);
+        assert_eq!(line[0] as usize, line.len() - 4);
+
+        let info = &sections.debug_info;
+        assert_eq!(info[0] as usize, info.len() - 4);
+        assert_eq!(&info[4..11], &[2, 0, 0, 0, 0, 0, 4]);
+
+        let reloc = |section, offset, size, target, addend| DebugReloc {
+            section,
+            offset,
+            size,
+            target,
+            addend,
+        };
+        let info_id = DebugSectionId::DebugInfo;
+        let abbrev = DebugRelocTarget::Section(DebugSectionId::DebugAbbrev);
+        let line_section = DebugRelocTarget::Section(DebugSectionId::DebugLine);
+        let func = DebugRelocTarget::Function(0);
+        assert_eq!(
+            sections.relocs,
+            [
+                reloc(info_id, 6, 4, abbrev, 0),
+                reloc(info_id, 14, 4, line_section, 0),
+                reloc(info_id, 22, 4, func, 0),
+                reloc(info_id, 26, 4, func, 12),
+                reloc(DebugSectionId::DebugLine, 37, 4, func, 0),
+            ]
+        );
+    }
+}
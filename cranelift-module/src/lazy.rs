@@ -0,0 +1,97 @@
+//! Stubs for compiling functions lazily.
+//!
+//! A lazy stub stands in for a function which hasn't been compiled yet. It has the signature of
+//! the function, and when called it calls back into the embedder with the index of the function,
+//! as a `FuncId` converted with `as_u32()`. The callback compiles the real body, typically
+//! replacing the stub with `Module::redefine_function`, and returns an address to call it at. The
+//! stub then forwards its arguments to that address and returns its return values.
+//!
+//! Later calls go directly to the real body, as long as the backend redirects calls to redefined
+//! functions.
+
+use crate::FuncId;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::{self, types, InstBuilder};
+use cranelift_codegen::isa::CallConv;
+use std::vec::Vec;
+
+/// Get the signature of the callback called by lazy stubs: `(func: i32) -> ptr`.
+pub fn lazy_callback_signature(pointer_type: ir::Type, call_conv: CallConv) -> ir::Signature {
+    let mut sig = ir::Signature::new(call_conv);
+    sig.params.push(ir::AbiParam::new(types::I32));
+    sig.returns.push(ir::AbiParam::new(pointer_type));
+    sig
+}
+
+/// Make a lazy stub for the function `func` with the signature `sig`, which calls `callback`.
+///
+/// The callback is imported with the signature returned by `lazy_callback_signature()`, using the
+/// calling convention `call_conv`.
+pub fn make_lazy_stub(
+    sig: &ir::Signature,
+    func: FuncId,
+    callback: ir::ExternalName,
+    pointer_type: ir::Type,
+    call_conv: CallConv,
+) -> ir::Function {
+    let mut stub = ir::Function::with_name_signature(ir::ExternalName::default(), sig.clone());
+    let callback_sig = stub.import_signature(lazy_callback_signature(pointer_type, call_conv));
+    let callback = stub.import_function(ir::ExtFuncData {
+        name: callback,
+        signature: callback_sig,
+        colocated: false,
+    });
+    let callee_sig = stub.import_signature(sig.clone());
+    let ebb = stub.dfg.make_ebb();
+    let params: Vec<ir::Value> = sig
+        .params
+        .iter()
+        .map(|param| stub.dfg.append_ebb_param(ebb, param.value_type))
+        .collect();
+
+    let mut pos = FuncCursor::new(&mut stub);
+    pos.insert_ebb(ebb);
+    let index = pos.ins().iconst(types::I32, i64::from(func.as_u32()));
+    let call = pos.ins().call(callback, &[index]);
+    let callee = pos.func.dfg.first_result(call);
+    let call = pos.ins().call_indirect(callee_sig, callee, &params);
+    let results = pos.func.dfg.inst_results(call).to_vec();
+    pos.ins().return_(&results);
+    stub
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::entity::EntityRef;
+    use std::string::ToString;
+
+    #[test]
+    fn lazy_stub() {
+        let mut sig = ir::Signature::new(CallConv::SystemV);
+        sig.params.push(ir::AbiParam::new(types::F32));
+        sig.returns.push(ir::AbiParam::new(types::I8));
+        let stub = make_lazy_stub(
+            &sig,
+            FuncId::new(3),
+            ir::ExternalName::user(0, 7),
+            types::I64,
+            CallConv::SystemV,
+        );
+        assert_eq!(
+            stub.to_string(),
+            "function u0:0(f32) -> i8 system_v {
+    sig0 = (i32) -> i64 system_v
+    sig1 = (f32) -> i8 system_v
+    fn0 = u0:7 sig0
+
+ebb0(v0: f32):
+    v1 = iconst.i32 3
+    v2 = call fn0(v1)
+    v3 = call_indirect sig1, v2(v0)
+    return v3
+}
+"
+        );
+    }
+}
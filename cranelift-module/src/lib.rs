@@ -34,14 +34,28 @@ use hashmap_core::{map as hash_map, HashMap};
 use std::collections::{hash_map, HashMap};
 
 mod backend;
+mod call_graph;
 mod data_context;
+mod debug;
+mod lazy;
 mod module;
+mod trampoline;
 
 pub use crate::backend::Backend;
+pub use crate::call_graph::CallGraph;
 pub use crate::data_context::{DataContext, DataDescription, Init};
+pub use crate::debug::{
+    line_table, write_debug_sections, DebugReloc, DebugRelocTarget, DebugSectionId, DebugSections,
+    FunctionDebugInfo,
+};
+pub use crate::lazy::{lazy_callback_signature, make_lazy_stub};
 pub use crate::module::{
     DataId, FuncId, FuncOrDataId, Linkage, Module, ModuleError, ModuleNamespace, ModuleResult,
 };
+pub use crate::trampoline::{
+    call_trampoline_signature, host_function_signature, make_call_trampoline, make_host_trampoline,
+    VALUE_SLOT_SIZE,
+};
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
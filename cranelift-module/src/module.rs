@@ -6,9 +6,14 @@
 // shared with `DataContext`?
 
 use super::HashMap;
+use crate::call_graph::CallGraph;
 use crate::data_context::DataContext;
+use crate::lazy::make_lazy_stub;
 use crate::Backend;
+use core::mem;
 use cranelift_codegen::entity::{entity_impl, PrimaryMap};
+#[cfg(feature = "std")]
+use cranelift_codegen::parallel;
 use cranelift_codegen::{binemit, ir, isa, CodegenError, Context};
 use failure::Fail;
 use log::info;
@@ -491,6 +496,7 @@ where
             name: ir::ExternalName::user(1, data.as_u32()),
             offset: ir::immediates::Imm64::new(0),
             colocated,
+            tls: false,
         })
     }
 
@@ -504,6 +510,47 @@ where
         ctx.import_global_value(ir::ExternalName::user(1, data.as_u32()))
     }
 
+    /// Inline the calls between functions that haven't been defined yet.
+    ///
+    /// Each entry in `contexts` holds the IR of a function that is about to be passed to
+    /// `define_function`. The functions are processed bottom-up in their `CallGraph`, so the
+    /// inlined bodies already contain the inlined bodies of their own callees. The size of the
+    /// inlined callees is limited by the `opt_level` setting of the target ISA.
+    pub fn inline_functions(&self, contexts: &mut [(FuncId, Context)]) -> ModuleResult<()> {
+        let graph = CallGraph::new(contexts.iter().map(|&(id, ref ctx)| (id, &ctx.func)));
+        let positions: HashMap<FuncId, usize> = contexts
+            .iter()
+            .enumerate()
+            .map(|(pos, &(id, _))| (id, pos))
+            .collect();
+
+        for func in graph.bottom_up_order() {
+            let pos = positions[&func];
+            // Take the caller out so its callees can be borrowed. A recursive call then finds
+            // an empty function which isn't inlined.
+            let mut ctx = mem::replace(&mut contexts[pos].1, Context::new());
+            let result = {
+                let contexts = &*contexts;
+                let positions = &positions;
+                ctx.inline(self.backend.isa(), move |name| match *name {
+                    ir::ExternalName::User {
+                        namespace: 0,
+                        index,
+                    } => positions
+                        .get(&FuncId::from_u32(index))
+                        .map(|&callee| &contexts[callee].1.func),
+                    _ => None,
+                })
+            };
+            contexts[pos].1 = ctx;
+            result.map_err(|e| {
+                info!("inlining into function {}: {}", func, e);
+                ModuleError::Compilation(e)
+            })?;
+        }
+        Ok(())
+    }
+
     /// Define a function, producing the function body from the given `Context`.
     ///
     /// Returns the size of the function's code.
@@ -514,15 +561,138 @@ where
         func: FuncId,
         ctx: &mut Context,
     ) -> ModuleResult<binemit::CodeOffset> {
-        let code_size = ctx.compile(self.backend.isa()).map_err(|e| {
+        let code_size = self.compile_function(func, ctx)?;
+        self.define_compiled_function(func, ctx, code_size)?;
+        Ok(code_size)
+    }
+
+    /// Replace the definition of `func` with the function in `ctx`, like `define_function`.
+    ///
+    /// Once the new definition is finalized, calls to `func` from code which is already finalized
+    /// go to it, as do calls through the address returned by `get_finalized_function`. The old
+    /// definition may still be running, so the backend doesn't free it. Only backends which can
+    /// redirect calls support this; others return an error.
+    ///
+    /// The old definition is only replaced once the new one is defined, so `func` keeps it if an
+    /// error is returned.
+    pub fn redefine_function(
+        &mut self,
+        func: FuncId,
+        ctx: &mut Context,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        let code_size = self.compile_function(func, ctx)?;
+        let info = &self.contents.functions[func];
+        if !info.decl.linkage.is_definable() {
+            return Err(ModuleError::InvalidImportDefinition(info.decl.name.clone()));
+        }
+
+        let compiled = self.backend.define_function(
+            &info.decl.name,
+            ctx,
+            &ModuleNamespace::<B> {
+                contents: &self.contents,
+            },
+            code_size,
+        )?;
+        if let Some(ref old) = info.compiled {
+            if let Err(err) = self.backend.undefine_function(&info.decl.name, old) {
+                // Don't leave the new definition behind.
+                self.backend.free_function(&info.decl.name, &compiled)?;
+                return Err(err);
+            }
+        }
+
+        self.contents.functions[func].compiled = Some(compiled);
+        self.functions_to_finalize.retain(|x| *x != func);
+        self.functions_to_finalize.push(func);
+        Ok(code_size)
+    }
+
+    /// Define `func` as a lazy stub, which calls the function `callback` the first time it's run.
+    ///
+    /// The callback is called with the index of `func`, and must return an address to call the
+    /// real body at. It should be declared with the signature given by `lazy_callback_signature`.
+    /// See `make_lazy_stub` for details.
+    ///
+    /// Returns the size of the stub's code.
+    pub fn define_lazy_function(
+        &mut self,
+        func: FuncId,
+        callback: FuncId,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        let stub = make_lazy_stub(
+            &self.contents.functions[func].decl.signature,
+            func,
+            ir::ExternalName::user(0, callback.as_u32()),
+            self.backend.isa().pointer_type(),
+            self.backend.isa().default_call_conv(),
+        );
+        self.define_function(func, &mut Context::for_function(stub))
+    }
+
+    /// Compile the function `func` in `ctx`, returning the size of its code.
+    fn compile_function(
+        &self,
+        func: FuncId,
+        ctx: &mut Context,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        ctx.compile(self.backend.isa()).map_err(|e| {
             info!(
                 "defining function {}: {}",
                 func,
                 ctx.func.display(self.backend.isa())
             );
             ModuleError::Compilation(e)
-        })?;
+        })
+    }
+
+    /// Compile and define all the functions in `contexts`, using up to `num_threads` threads.
+    ///
+    /// The functions are compiled in parallel, each thread taking the next uncompiled `Context`
+    /// until none are left. They are then passed to the backend in the order of `contexts`,
+    /// exactly as `define_function` would. The pass timings of the worker threads are not
+    /// included in `cranelift_codegen::timing::take_current()`.
+    ///
+    /// Returns the total size of the functions' code.
+    #[cfg(feature = "std")]
+    pub fn compile_all(
+        &mut self,
+        contexts: &mut [(FuncId, Context)],
+        num_threads: usize,
+    ) -> ModuleResult<binemit::CodeOffset> {
+        let code_sizes = {
+            let isa = self.backend.isa();
+            parallel::map_parallel(
+                contexts.iter_mut(),
+                num_threads,
+                || (),
+                |_, _, &mut (_, ref mut ctx)| ctx.compile(isa),
+            )
+        };
+
+        let mut total_size = 0;
+        for (&mut (func, ref mut ctx), code_size) in contexts.iter_mut().zip(code_sizes) {
+            let code_size = code_size.map_err(|e| {
+                info!(
+                    "defining function {}: {}",
+                    func,
+                    ctx.func.display(self.backend.isa())
+                );
+                ModuleError::Compilation(e)
+            })?;
+            self.define_compiled_function(func, ctx, code_size)?;
+            total_size += code_size;
+        }
+        Ok(total_size)
+    }
 
+    /// Pass a function compiled in `ctx` to the backend.
+    fn define_compiled_function(
+        &mut self,
+        func: FuncId,
+        ctx: &Context,
+        code_size: binemit::CodeOffset,
+    ) -> ModuleResult<()> {
         let info = &self.contents.functions[func];
         if info.compiled.is_some() {
             return Err(ModuleError::DuplicateDefinition(info.decl.name.clone()));
@@ -542,7 +712,7 @@ where
 
         self.contents.functions[func].compiled = compiled;
         self.functions_to_finalize.push(func);
-        Ok(code_size)
+        Ok(())
     }
 
     /// Define a function, producing the data contents from the given `DataContext`.
@@ -646,6 +816,22 @@ where
         self.backend.publish();
     }
 
+    /// Discard the definition of `func`, releasing the memory used by its code in backends which
+    /// support it.
+    ///
+    /// The function stays declared, and it can be defined again. Any function referring to it
+    /// must be freed or redefined too, and none of them may still be running. Returns an error,
+    /// and keeps the definition, if the backend can't remove functions.
+    pub fn free_function(&mut self, func: FuncId) -> ModuleResult<()> {
+        let info = &self.contents.functions[func];
+        if let Some(ref compiled) = info.compiled {
+            self.backend.free_function(&info.decl.name, compiled)?;
+        }
+        self.contents.functions[func].compiled = None;
+        self.functions_to_finalize.retain(|x| *x != func);
+        Ok(())
+    }
+
     /// Return the finalized artifact from the backend, if it provides one.
     pub fn get_finalized_function(&mut self, func: FuncId) -> B::FinalizedFunction {
         let info = &self.contents.functions[func];
@@ -0,0 +1,205 @@
+//! Trampolines between the native ABI and a generic array-of-values interface.
+//!
+//! An embedder calling compiled functions with arbitrary signatures would otherwise need a Rust
+//! function pointer type for each of them. The trampolines built here pass all the arguments and
+//! return values through an array of `VALUE_SLOT_SIZE`-byte slots instead, so one Rust type covers
+//! every signature:
+//!
+//! - A *call trampoline* lets the host call a compiled function. It has the signature
+//!   `(callee: ptr, values: ptr)`. It loads the arguments of `callee` from `values`, calls it, and
+//!   stores the return values back into `values`, starting from the first slot.
+//!
+//! - A *host trampoline* lets compiled code call a host function. It has the native signature of
+//!   the function being called, and it calls the host function `(values: ptr)` with the arguments
+//!   stored in an array on the stack. The host function writes its return values into the same
+//!   array.
+//!
+//! Each value occupies the start of its slot. The trampolines are plain Cranelift functions to be
+//! compiled and defined like any other function in a `Module`.
+
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::{self, InstBuilder, MemFlags};
+use cranelift_codegen::isa::CallConv;
+use std::vec::Vec;
+
+/// The size in bytes of each slot in the array of values passed through a trampoline.
+///
+/// This is large enough for all the types supported by Cranelift, so the array can be allocated as
+/// a slice of `u128`.
+pub const VALUE_SLOT_SIZE: u32 = 16;
+
+/// Get the signature of a call trampoline: `(callee: ptr, values: ptr)`.
+pub fn call_trampoline_signature(pointer_type: ir::Type, call_conv: CallConv) -> ir::Signature {
+    let mut sig = ir::Signature::new(call_conv);
+    sig.params.push(ir::AbiParam::new(pointer_type));
+    sig.params.push(ir::AbiParam::new(pointer_type));
+    sig
+}
+
+/// Get the signature of a host function called by a host trampoline: `(values: ptr)`.
+pub fn host_function_signature(pointer_type: ir::Type, call_conv: CallConv) -> ir::Signature {
+    let mut sig = ir::Signature::new(call_conv);
+    sig.params.push(ir::AbiParam::new(pointer_type));
+    sig
+}
+
+/// Make a trampoline for calling a function with the signature `sig` from the host.
+///
+/// The trampoline itself uses the calling convention `call_conv`, which should match the way the
+/// host calls it, typically `TargetIsa::default_call_conv()`. The signature `sig` must not be
+/// legalized yet.
+pub fn make_call_trampoline(
+    sig: &ir::Signature,
+    pointer_type: ir::Type,
+    call_conv: CallConv,
+) -> ir::Function {
+    let mut func = ir::Function::with_name_signature(
+        ir::ExternalName::default(),
+        call_trampoline_signature(pointer_type, call_conv),
+    );
+    let callee_sig = func.import_signature(sig.clone());
+    let ebb = func.dfg.make_ebb();
+    let callee = func.dfg.append_ebb_param(ebb, pointer_type);
+    let values = func.dfg.append_ebb_param(ebb, pointer_type);
+
+    let mut pos = FuncCursor::new(&mut func);
+    pos.insert_ebb(ebb);
+    let args: Vec<ir::Value> = sig
+        .params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            pos.ins()
+                .load(param.value_type, flags(), values, slot_offset(i))
+        })
+        .collect();
+    let call = pos.ins().call_indirect(callee_sig, callee, &args);
+    let results = pos.func.dfg.inst_results(call).to_vec();
+    for (i, &result) in results.iter().enumerate() {
+        pos.ins().store(flags(), result, values, slot_offset(i));
+    }
+    pos.ins().return_(&[]);
+    func
+}
+
+/// Make a trampoline with the signature `sig` which calls the host function `host`.
+///
+/// The host function is imported with the signature returned by `host_function_signature()`,
+/// using the calling convention `call_conv`.
+pub fn make_host_trampoline(
+    sig: &ir::Signature,
+    host: ir::ExternalName,
+    pointer_type: ir::Type,
+    call_conv: CallConv,
+) -> ir::Function {
+    let mut func = ir::Function::with_name_signature(ir::ExternalName::default(), sig.clone());
+    let host_sig = func.import_signature(host_function_signature(pointer_type, call_conv));
+    let host = func.import_function(ir::ExtFuncData {
+        name: host,
+        signature: host_sig,
+        colocated: false,
+    });
+    let slots = sig.params.len().max(sig.returns.len());
+    let ss = func.create_stack_slot(ir::StackSlotData::new(
+        ir::StackSlotKind::ExplicitSlot,
+        slot_offset(slots) as u32,
+    ));
+    let ebb = func.dfg.make_ebb();
+    let params: Vec<ir::Value> = sig
+        .params
+        .iter()
+        .map(|param| func.dfg.append_ebb_param(ebb, param.value_type))
+        .collect();
+
+    let mut pos = FuncCursor::new(&mut func);
+    pos.insert_ebb(ebb);
+    let values = pos.ins().stack_addr(pointer_type, ss, 0);
+    for (i, &param) in params.iter().enumerate() {
+        pos.ins().store(flags(), param, values, slot_offset(i));
+    }
+    pos.ins().call(host, &[values]);
+    let results: Vec<ir::Value> = sig
+        .returns
+        .iter()
+        .enumerate()
+        .map(|(i, ret)| {
+            pos.ins()
+                .load(ret.value_type, flags(), values, slot_offset(i))
+        })
+        .collect();
+    pos.ins().return_(&results);
+    func
+}
+
+/// Get the offset of the slot for value number `index`.
+fn slot_offset(index: usize) -> i32 {
+    index as i32 * VALUE_SLOT_SIZE as i32
+}
+
+/// Get the flags for accessing the array of values.
+fn flags() -> MemFlags {
+    let mut flags = MemFlags::new();
+    flags.set_notrap();
+    flags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::types;
+    use std::string::ToString;
+
+    fn signature() -> ir::Signature {
+        let mut sig = ir::Signature::new(CallConv::SystemV);
+        sig.params.push(ir::AbiParam::new(types::I32));
+        sig.params.push(ir::AbiParam::new(types::F64));
+        sig.returns.push(ir::AbiParam::new(types::I64));
+        sig
+    }
+
+    #[test]
+    fn call_trampoline() {
+        let func = make_call_trampoline(&signature(), types::I64, CallConv::SystemV);
+        assert_eq!(
+            func.to_string(),
+            "function u0:0(i64, i64) system_v {
+    sig0 = (i32, f64) -> i64 system_v
+
+ebb0(v0: i64, v1: i64):
+    v2 = load.i32 notrap v1
+    v3 = load.f64 notrap v1+16
+    v4 = call_indirect sig0, v0(v2, v3)
+    store notrap v4, v1
+    return
+}
+"
+        );
+    }
+
+    #[test]
+    fn host_trampoline() {
+        let func = make_host_trampoline(
+            &signature(),
+            ir::ExternalName::user(0, 7),
+            types::I64,
+            CallConv::SystemV,
+        );
+        assert_eq!(
+            func.to_string(),
+            "function u0:0(i32, f64) -> i64 system_v {
+    ss0 = explicit_slot 32
+    sig0 = (i64) system_v
+    fn0 = u0:7 sig0
+
+ebb0(v0: i32, v1: f64):
+    v2 = stack_addr.i64 ss0
+    store notrap v0, v2
+    store notrap v1, v2+16
+    call fn0(v2)
+    v3 = load.i64 notrap v2
+    return v3
+}
+"
+        );
+    }
+}
@@ -26,66 +26,85 @@
 #![no_std]
 
 use cranelift_codegen::isa;
+#[cfg(all(
+    any(
+        target_arch = "aarch64",
+        target_arch = "riscv32",
+        target_arch = "riscv64"
+    ),
+    target_os = "linux"
+))]
 use cranelift_codegen::settings::Configurable;
-use target_lexicon::Triple;
-
-#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-use raw_cpuid::CpuId;
 
 /// Return an `isa` builder configured for the current host
 /// machine, or `Err(())` if the host machine is not supported
 /// in the current configuration.
+///
+/// The ISA-specific settings describing the host's optional instruction set extensions are
+/// enabled according to what the CPU reports: CPUID on x86, as detected by
+/// `cranelift_codegen::isa::host()`, and the auxiliary vector's `AT_HWCAP` on AArch64 and
+/// RISC-V Linux.
 pub fn builder() -> Result<isa::Builder, &'static str> {
-    let mut isa_builder = isa::lookup(Triple::host()).map_err(|err| match err {
+    #[allow(unused_mut)]
+    let mut isa_builder = isa::host().map_err(|err| match err {
         isa::LookupError::SupportDisabled => "support for architecture disabled at compile time",
         isa::LookupError::Unsupported => "unsupported architecture",
     })?;
 
-    if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
-        parse_x86_cpuid(&mut isa_builder)?;
-    }
+    #[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+    parse_aarch64_hwcap(&mut isa_builder);
+
+    #[cfg(all(
+        any(target_arch = "riscv32", target_arch = "riscv64"),
+        target_os = "linux"
+    ))]
+    parse_riscv_hwcap(&mut isa_builder);
 
     Ok(isa_builder)
 }
 
-#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
-fn parse_x86_cpuid(isa_builder: &mut isa::Builder) -> Result<(), &'static str> {
-    let cpuid = CpuId::new();
+/// Get the `AT_HWCAP` entry of the auxiliary vector, which the kernel fills with the CPU's
+/// features.
+#[cfg(all(
+    any(
+        target_arch = "aarch64",
+        target_arch = "riscv32",
+        target_arch = "riscv64"
+    ),
+    target_os = "linux"
+))]
+fn hwcap() -> u64 {
+    u64::from(unsafe { libc::getauxval(libc::AT_HWCAP) })
+}
 
-    if let Some(info) = cpuid.get_feature_info() {
-        if !info.has_sse2() {
-            return Err("x86 support requires SSE2");
-        }
-        if info.has_sse3() {
-            isa_builder.enable("has_sse3").unwrap();
-        }
-        if info.has_sse41() {
-            isa_builder.enable("has_sse41").unwrap();
-        }
-        if info.has_sse42() {
-            isa_builder.enable("has_sse42").unwrap();
-        }
-        if info.has_popcnt() {
-            isa_builder.enable("has_popcnt").unwrap();
-        }
-        if info.has_avx() {
-            isa_builder.enable("has_avx").unwrap();
-        }
-    }
-    if let Some(info) = cpuid.get_extended_feature_info() {
-        if info.has_bmi1() {
-            isa_builder.enable("has_bmi1").unwrap();
-        }
-        if info.has_bmi2() {
-            isa_builder.enable("has_bmi2").unwrap();
-        }
+#[cfg(all(target_arch = "aarch64", target_os = "linux"))]
+fn parse_aarch64_hwcap(isa_builder: &mut isa::Builder) {
+    // HWCAP_ATOMICS in the kernel's `asm/hwcap.h`.
+    const HWCAP_ATOMICS: u64 = 1 << 8;
+
+    if hwcap() & HWCAP_ATOMICS != 0 {
+        isa_builder.enable("has_lse").unwrap();
     }
-    if let Some(info) = cpuid.get_extended_function_info() {
-        if info.has_lzcnt() {
-            isa_builder.enable("has_lzcnt").unwrap();
+}
+
+#[cfg(all(
+    any(target_arch = "riscv32", target_arch = "riscv64"),
+    target_os = "linux"
+))]
+fn parse_riscv_hwcap(isa_builder: &mut isa::Builder) {
+    // The kernel sets bit `n` of `AT_HWCAP` when the single-letter extension `'a' + n` is present.
+    let hwcap = hwcap();
+    for &(ext, name) in &[
+        (b'm', "supports_m"),
+        (b'a', "supports_a"),
+        (b'f', "supports_f"),
+        (b'd', "supports_d"),
+        (b'c', "supports_c"),
+    ] {
+        if hwcap & (1 << (ext - b'a')) != 0 {
+            isa_builder.enable(name).unwrap();
         }
     }
-    Ok(())
 }
 
 #[cfg(test)]
@@ -29,6 +29,9 @@ extern crate alloc as std;
 extern crate std;
 
 mod constant_folding;
+mod souper;
+
+pub use crate::souper::{write_souper_slices, RewriteError, Rewrites};
 
 use cranelift_codegen::{isa::TargetIsa, settings::FlagsOrIsa, CodegenResult, Context};
 
@@ -53,3 +56,19 @@ where
     ctx.verify_if(fisa)?;
     Ok(())
 }
+
+/// Apply rewrites discovered by a superoptimizer.
+///
+/// See `write_souper_slices` for extracting the instruction DAGs to superoptimize.
+pub fn apply_rewrites<'a, FOI>(
+    ctx: &mut Context,
+    rewrites: &Rewrites,
+    fisa: FOI,
+) -> CodegenResult<()>
+where
+    FOI: Into<FlagsOrIsa<'a>>,
+{
+    souper::apply_rewrites(&mut ctx.func, rewrites);
+    ctx.verify_if(fisa)?;
+    Ok(())
+}
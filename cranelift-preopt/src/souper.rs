@@ -0,0 +1,913 @@
+//! Superoptimizer harness.
+//!
+//! [Souper](https://github.com/google/souper) is a superoptimizer which looks for shorter
+//! equivalents of small integer computations. This module writes the instruction DAGs rooted at
+//! each value of a function in Souper's text format, so they can be given to the external
+//! superoptimizer. The optimizations it finds come back as rewrites in the same format, which
+//! can be applied to functions before legalization.
+//!
+//! Only the operations that have the same semantics in Cranelift and in Souper are supported:
+//! additions, subtractions, multiplications, bitwise operations, integer comparisons, `select`,
+//! extensions and bit counting. Divisions and shifts are left out because Cranelift traps on
+//! division by zero and masks shift counts, while Souper treats both as undefined behavior.
+//!
+//! A slice looks like this, with `infer` naming the root whose replacement is wanted:
+//!
+//! ```text
+//! %0:i32 = var
+//! %1:i32 = add %0, 1:i32
+//! %2:i32 = add %1, 1:i32
+//! infer %2
+//! ```
+//!
+//! A rewrite is a slice followed by the instructions computing the replacement and a `result`
+//! line naming it:
+//!
+//! ```text
+//! %3:i32 = add %0, 2:i32
+//! result %3
+//! ```
+
+use core::fmt::{self, Write};
+use core::mem;
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::dfg::ValueDef;
+use cranelift_codegen::ir::types::B1;
+use cranelift_codegen::ir::{Function, Inst, InstBuilder, InstructionData, Opcode, Type, Value};
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+/// An operation supported in slices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    And,
+    Or,
+    Xor,
+    Eq,
+    Ne,
+    Ult,
+    Ule,
+    Slt,
+    Sle,
+    Select,
+    Zext,
+    Sext,
+    Trunc,
+    Ctpop,
+    Ctlz,
+    Cttz,
+}
+
+/// All the operations, for parsing.
+const OPS: [Op; 19] = [
+    Op::Add,
+    Op::Sub,
+    Op::Mul,
+    Op::And,
+    Op::Or,
+    Op::Xor,
+    Op::Eq,
+    Op::Ne,
+    Op::Ult,
+    Op::Ule,
+    Op::Slt,
+    Op::Sle,
+    Op::Select,
+    Op::Zext,
+    Op::Sext,
+    Op::Trunc,
+    Op::Ctpop,
+    Op::Ctlz,
+    Op::Cttz,
+];
+
+impl Op {
+    fn name(self) -> &'static str {
+        match self {
+            Op::Add => "add",
+            Op::Sub => "sub",
+            Op::Mul => "mul",
+            Op::And => "and",
+            Op::Or => "or",
+            Op::Xor => "xor",
+            Op::Eq => "eq",
+            Op::Ne => "ne",
+            Op::Ult => "ult",
+            Op::Ule => "ule",
+            Op::Slt => "slt",
+            Op::Sle => "sle",
+            Op::Select => "select",
+            Op::Zext => "zext",
+            Op::Sext => "sext",
+            Op::Trunc => "trunc",
+            Op::Ctpop => "ctpop",
+            Op::Ctlz => "ctlz",
+            Op::Cttz => "cttz",
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Op::Select => 3,
+            Op::Zext | Op::Sext | Op::Trunc | Op::Ctpop | Op::Ctlz | Op::Cttz => 1,
+            _ => 2,
+        }
+    }
+
+    /// Get the operation for an integer comparison, and whether its operands must be swapped.
+    fn from_intcc(cond: IntCC) -> Option<(Self, bool)> {
+        Some(match cond {
+            IntCC::Equal => (Op::Eq, false),
+            IntCC::NotEqual => (Op::Ne, false),
+            IntCC::UnsignedLessThan => (Op::Ult, false),
+            IntCC::UnsignedLessThanOrEqual => (Op::Ule, false),
+            IntCC::SignedLessThan => (Op::Slt, false),
+            IntCC::SignedLessThanOrEqual => (Op::Sle, false),
+            IntCC::UnsignedGreaterThan => (Op::Ult, true),
+            IntCC::UnsignedGreaterThanOrEqual => (Op::Ule, true),
+            IntCC::SignedGreaterThan => (Op::Slt, true),
+            IntCC::SignedGreaterThanOrEqual => (Op::Sle, true),
+            _ => return None,
+        })
+    }
+
+    fn intcc(self) -> Option<IntCC> {
+        match self {
+            Op::Eq => Some(IntCC::Equal),
+            Op::Ne => Some(IntCC::NotEqual),
+            Op::Ult => Some(IntCC::UnsignedLessThan),
+            Op::Ule => Some(IntCC::UnsignedLessThanOrEqual),
+            Op::Slt => Some(IntCC::SignedLessThan),
+            Op::Sle => Some(IntCC::SignedLessThanOrEqual),
+            _ => None,
+        }
+    }
+}
+
+/// Get the Souper name of a supported type.
+fn type_name(ty: Type) -> Option<String> {
+    if ty == B1 {
+        Some("i1".to_string())
+    } else if ty.is_int() && !ty.is_vector() && ty.bits() <= 64 {
+        Some(format!("i{}", ty.bits()))
+    } else {
+        None
+    }
+}
+
+/// Parse a Souper type name.
+fn parse_type(name: &str) -> Option<Type> {
+    match name {
+        "i1" => Some(B1),
+        _ if name.starts_with('i') => {
+            Type::int(name[1..].parse().ok()?).filter(|ty| ty.bits() <= 64)
+        }
+        _ => None,
+    }
+}
+
+/// Sign-extend the low bits of `imm` to the width of `ty`, so constants compare equal regardless
+/// of how they were written.
+fn normalize(imm: i64, ty: Type) -> i64 {
+    let shift = 64 - u32::from(ty.bits());
+    if ty == B1 {
+        imm & 1
+    } else if shift == 0 {
+        imm
+    } else {
+        (imm << shift) >> shift
+    }
+}
+
+/// An operand of an instruction viewed as a Souper operation.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Value(Value),
+    Imm(i64, Type),
+}
+
+/// Get the constant value of `value` if it is defined by an `iconst` or `bconst`.
+fn constant(func: &Function, value: Value) -> Option<i64> {
+    if let ValueDef::Result(inst, _) = func.dfg.value_def(value) {
+        match func.dfg[inst] {
+            InstructionData::UnaryImm {
+                opcode: Opcode::Iconst,
+                imm,
+            } => return Some(normalize(imm.into(), func.dfg.value_type(value))),
+            InstructionData::UnaryBool {
+                opcode: Opcode::Bconst,
+                imm,
+            } => return Some(i64::from(imm)),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Describe the instruction defining `value` as a Souper operation, if it is supported.
+fn view(func: &Function, value: Value) -> Option<(Op, Vec<Operand>)> {
+    type_name(func.dfg.value_type(value))?;
+    let inst = match func.dfg.value_def(value) {
+        ValueDef::Result(inst, 0) if func.dfg.inst_results(inst).len() == 1 => inst,
+        _ => return None,
+    };
+    let ctrl_type = func.dfg.ctrl_typevar(inst);
+    let val = Operand::Value;
+    let (op, operands) = match func.dfg[inst] {
+        InstructionData::Binary { opcode, args } => {
+            let op = match opcode {
+                Opcode::Iadd => Op::Add,
+                Opcode::Isub => Op::Sub,
+                Opcode::Imul => Op::Mul,
+                Opcode::Band => Op::And,
+                Opcode::Bor => Op::Or,
+                Opcode::Bxor => Op::Xor,
+                _ => return None,
+            };
+            (op, vec![val(args[0]), val(args[1])])
+        }
+        InstructionData::BinaryImm { opcode, arg, imm } => {
+            let imm = Operand::Imm(normalize(imm.into(), ctrl_type), ctrl_type);
+            match opcode {
+                Opcode::IaddImm => (Op::Add, vec![val(arg), imm]),
+                Opcode::IrsubImm => (Op::Sub, vec![imm, val(arg)]),
+                Opcode::ImulImm => (Op::Mul, vec![val(arg), imm]),
+                Opcode::BandImm => (Op::And, vec![val(arg), imm]),
+                Opcode::BorImm => (Op::Or, vec![val(arg), imm]),
+                Opcode::BxorImm => (Op::Xor, vec![val(arg), imm]),
+                _ => return None,
+            }
+        }
+        InstructionData::IntCompare {
+            opcode: Opcode::Icmp,
+            cond,
+            args,
+        } => {
+            let (op, swap) = Op::from_intcc(cond)?;
+            if swap {
+                (op, vec![val(args[1]), val(args[0])])
+            } else {
+                (op, vec![val(args[0]), val(args[1])])
+            }
+        }
+        InstructionData::IntCompareImm {
+            opcode: Opcode::IcmpImm,
+            cond,
+            arg,
+            imm,
+        } => {
+            let (op, swap) = Op::from_intcc(cond)?;
+            let ty = func.dfg.value_type(arg);
+            let imm = Operand::Imm(normalize(imm.into(), ty), ty);
+            if swap {
+                (op, vec![imm, val(arg)])
+            } else {
+                (op, vec![val(arg), imm])
+            }
+        }
+        InstructionData::Ternary {
+            opcode: Opcode::Select,
+            args,
+        } if func.dfg.value_type(args[0]) == B1 => {
+            (Op::Select, vec![val(args[0]), val(args[1]), val(args[2])])
+        }
+        InstructionData::Unary { opcode, arg } => {
+            let op = match opcode {
+                Opcode::Uextend | Opcode::Bint => Op::Zext,
+                Opcode::Sextend => Op::Sext,
+                Opcode::Ireduce => Op::Trunc,
+                Opcode::Popcnt => Op::Ctpop,
+                Opcode::Clz => Op::Ctlz,
+                Opcode::Ctz => Op::Cttz,
+                _ => return None,
+            };
+            (op, vec![val(arg)])
+        }
+        _ => return None,
+    };
+
+    // The operand values must have types Souper understands too.
+    for operand in &operands {
+        if let Operand::Value(value) = *operand {
+            type_name(func.dfg.value_type(value))?;
+        }
+    }
+    Some((op, operands))
+}
+
+/// Builder for the text of one slice.
+struct Slice<'a> {
+    func: &'a Function,
+    max_depth: usize,
+    /// The values which already have a name in the slice, in the order of their names.
+    names: Vec<Value>,
+    text: String,
+}
+
+impl<'a> Slice<'a> {
+    /// Get the text for `operand`, writing the instructions defining it if needed.
+    fn operand(&mut self, operand: Operand, depth: usize) -> String {
+        let value = match operand {
+            Operand::Imm(imm, ty) => return format!("{}:{}", imm, type_name(ty).unwrap()),
+            Operand::Value(value) => self.func.dfg.resolve_aliases(value),
+        };
+        let ty = self.func.dfg.value_type(value);
+        if let Some(imm) = constant(self.func, value) {
+            return format!("{}:{}", imm, type_name(ty).unwrap());
+        }
+        if let Some(index) = self.names.iter().position(|&v| v == value) {
+            return format!("%{}", index);
+        }
+
+        let rhs = match view(self.func, value) {
+            Some((op, operands)) if depth <= self.max_depth => {
+                let args: Vec<String> = operands
+                    .into_iter()
+                    .map(|operand| self.operand(operand, depth + 1))
+                    .collect();
+                format!("{} {}", op.name(), args.join(", "))
+            }
+            _ => "var".to_string(),
+        };
+        self.names.push(value);
+        writeln!(
+            self.text,
+            "%{}:{} = {}",
+            self.names.len() - 1,
+            type_name(ty).unwrap(),
+            rhs
+        )
+        .unwrap();
+        format!("%{}", self.names.len() - 1)
+    }
+}
+
+/// Write the slice rooted at each supported instruction in `func` in Souper's syntax.
+///
+/// Operands defined more than `max_depth` instructions below the root become variables. Each
+/// slice is preceded by a comment naming the function and the root value.
+pub fn write_souper_slices(w: &mut Write, func: &Function, max_depth: usize) -> fmt::Result {
+    for ebb in func.layout.ebbs() {
+        for inst in func.layout.ebb_insts(ebb) {
+            let results = func.dfg.inst_results(inst);
+            if results.len() != 1 || view(func, results[0]).is_none() {
+                continue;
+            }
+            let mut slice = Slice {
+                func,
+                max_depth,
+                names: Vec::new(),
+                text: String::new(),
+            };
+            let root = slice.operand(Operand::Value(results[0]), 0);
+            writeln!(w, "; {} {}", func.name, results[0])?;
+            write!(w, "{}", slice.text)?;
+            writeln!(w, "infer {}", root)?;
+            writeln!(w)?;
+        }
+    }
+    Ok(())
+}
+
+/// An error found while parsing rewrites.
+#[derive(Debug)]
+pub struct RewriteError {
+    /// The line number of the error, starting from 1.
+    pub line: usize,
+    /// Description of the error.
+    pub message: String,
+}
+
+impl fmt::Display for RewriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A node in the DAGs of a rewrite.
+#[derive(Clone, Debug)]
+enum Node {
+    Var(Type),
+    Const(i64, Type),
+    Op(Op, Type, Vec<usize>),
+}
+
+impl Node {
+    fn ty(&self) -> Type {
+        match *self {
+            Node::Var(ty) | Node::Const(_, ty) | Node::Op(_, ty, _) => ty,
+        }
+    }
+}
+
+/// A rewrite of a DAG of instructions into another one.
+#[derive(Clone, Debug)]
+struct Rewrite {
+    /// The nodes of both DAGs. The first `lhs_len` nodes are the pattern.
+    nodes: Vec<Node>,
+    lhs_len: usize,
+    /// The root of the pattern.
+    root: usize,
+    /// The replacement of the root.
+    result: usize,
+}
+
+/// A set of rewrites discovered by a superoptimizer.
+#[derive(Clone, Debug, Default)]
+pub struct Rewrites {
+    rewrites: Vec<Rewrite>,
+}
+
+/// Check that the types of the operands of a `op` node with type `ty` are consistent.
+fn check_types(op: Op, ty: Type, args: &[Type]) -> Result<(), &'static str> {
+    let ok = match op {
+        Op::Add | Op::Sub | Op::Mul => ty != B1 && args.iter().all(|&arg| arg == ty),
+        Op::And | Op::Or | Op::Xor => args.iter().all(|&arg| arg == ty),
+        Op::Eq | Op::Ne | Op::Ult | Op::Ule | Op::Slt | Op::Sle => {
+            ty == B1 && args[0] != B1 && args[0] == args[1]
+        }
+        Op::Select => args[0] == B1 && args[1] == ty && args[2] == ty,
+        Op::Zext => ty != B1 && args[0].bits() < ty.bits(),
+        Op::Sext => ty != B1 && args[0] != B1 && args[0].bits() < ty.bits(),
+        Op::Trunc => ty != B1 && args[0].bits() > ty.bits(),
+        Op::Ctpop | Op::Ctlz | Op::Cttz => ty != B1 && args[0] == ty,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err("operand types don't match the operation")
+    }
+}
+
+/// Split `text` at the first `delim`, returning the text before it and the text after it if it
+/// was found.
+fn split_once(text: &str, delim: char) -> (&str, Option<&str>) {
+    match text.find(delim) {
+        Some(pos) => (&text[..pos], Some(&text[pos + delim.len_utf8()..])),
+        None => (text, None),
+    }
+}
+
+/// Parser state for one rewrite.
+#[derive(Default)]
+struct RewriteParser<'a> {
+    names: Vec<&'a str>,
+    nodes: Vec<Node>,
+    root: Option<usize>,
+}
+
+impl<'a> RewriteParser<'a> {
+    fn operand(&mut self, text: &'a str) -> Result<usize, String> {
+        let text = text.trim();
+        if text.starts_with('%') {
+            return self
+                .names
+                .iter()
+                .position(|&name| name == text)
+                .ok_or_else(|| format!("undefined name {}", text));
+        }
+        let (imm, ty) = split_once(text, ':');
+        let ty = ty
+            .and_then(parse_type)
+            .ok_or_else(|| format!("constant {} needs a type", text))?;
+        let imm: i64 = imm
+            .parse()
+            .map_err(|_| format!("invalid constant {}", text))?;
+        self.names.push("");
+        self.nodes.push(Node::Const(normalize(imm, ty), ty));
+        Ok(self.nodes.len() - 1)
+    }
+
+    fn statement(&mut self, line: &'a str) -> Result<(), String> {
+        let (lhs, rhs) = match split_once(line, '=') {
+            (lhs, Some(rhs)) if !rhs.trim().is_empty() => (lhs, rhs.trim()),
+            _ => return Err(format!("expected an instruction: {}", line)),
+        };
+        let (name, ty) = split_once(lhs.trim(), ':');
+        let name = name.trim();
+        if !name.starts_with('%') || self.names.contains(&name) {
+            return Err(format!("invalid name {}", name));
+        }
+        let ty = match ty {
+            Some(ty) => Some(parse_type(ty.trim()).ok_or_else(|| format!("bad type {}", ty))?),
+            None => None,
+        };
+
+        let (op_name, rest) = split_once(rhs, ' ');
+        let node = if op_name == "var" {
+            if self.root.is_some() {
+                return Err("variables must be defined before `infer`".to_string());
+            }
+            if rest.map_or(false, |rest| !rest.trim().is_empty()) {
+                return Err("variable attributes are not supported".to_string());
+            }
+            Node::Var(ty.ok_or("variables need a type")?)
+        } else {
+            let op = *OPS
+                .iter()
+                .find(|op| op.name() == op_name)
+                .ok_or_else(|| format!("unsupported operation {}", op_name))?;
+            let args = rest
+                .unwrap_or("")
+                .split(',')
+                .filter(|arg| !arg.trim().is_empty())
+                .map(|arg| self.operand(arg))
+                .collect::<Result<Vec<_>, _>>()?;
+            if args.len() != op.arity() {
+                return Err(format!("{} takes {} operands", op.name(), op.arity()));
+            }
+            let arg_types: Vec<Type> = args.iter().map(|&arg| self.nodes[arg].ty()).collect();
+            let ty = match (ty, op.intcc()) {
+                (Some(ty), _) => ty,
+                (None, Some(_)) => B1,
+                (None, None) if op.arity() == 2 => arg_types[0],
+                (None, None) => return Err(format!("{} needs a type", name)),
+            };
+            check_types(op, ty, &arg_types)?;
+            Node::Op(op, ty, args)
+        };
+        self.names.push(name);
+        self.nodes.push(node);
+        Ok(())
+    }
+
+    fn finish(self, result: usize) -> Result<Rewrite, String> {
+        let root = self.root.ok_or("`result` without `infer`")?;
+        if self.nodes[root].ty() != self.nodes[result].ty() {
+            return Err("the result and the root have different types".to_string());
+        }
+        match self.nodes[root] {
+            Node::Op(..) if result != root => {}
+            Node::Op(..) => return Err("the result must differ from the root".to_string()),
+            _ => return Err("the root must be an operation".to_string()),
+        }
+
+        // The replacement can only use the parts of the pattern which are bound by a match.
+        let lhs_len = root + 1;
+        let mut bound = vec![false; lhs_len];
+        bound[root] = true;
+        for node in (0..lhs_len).rev() {
+            if let Node::Op(_, _, ref args) = self.nodes[node] {
+                if bound[node] {
+                    for &arg in args {
+                        bound[arg] = true;
+                    }
+                }
+            }
+        }
+        let mut used = vec![result];
+        for node in &self.nodes[lhs_len..] {
+            if let Node::Op(_, _, ref args) = *node {
+                used.extend(args);
+            }
+        }
+        if used.iter().any(|&node| node < lhs_len && !bound[node]) {
+            return Err("the result uses values outside of the pattern".to_string());
+        }
+
+        Ok(Rewrite {
+            nodes: self.nodes,
+            lhs_len,
+            root,
+            result,
+        })
+    }
+}
+
+impl Rewrites {
+    /// Create an empty set of rewrites.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse the rewrites in `text`.
+    ///
+    /// Each rewrite is a pattern ending with an `infer` line, followed by the replacement ending
+    /// with a `result` line. Lines starting with `;` are comments.
+    pub fn parse(text: &str) -> Result<Self, RewriteError> {
+        let mut rewrites = Vec::new();
+        let mut parser = RewriteParser::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let error = |message| RewriteError {
+                line: number + 1,
+                message,
+            };
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+            if line.starts_with("infer ") {
+                let root = parser.operand(&line[6..]).map_err(error)?;
+                if parser.root.is_some() || root + 1 != parser.nodes.len() {
+                    return Err(error("`infer` must name the last instruction".to_string()));
+                }
+                parser.root = Some(root);
+            } else if line.starts_with("result ") {
+                let result = parser.operand(&line[7..]).map_err(error)?;
+                let parsed = mem::replace(&mut parser, RewriteParser::default());
+                rewrites.push(parsed.finish(result).map_err(error)?);
+            } else {
+                parser.statement(line).map_err(error)?;
+            }
+        }
+        if !parser.nodes.is_empty() {
+            return Err(RewriteError {
+                line: text.lines().count(),
+                message: "missing `result`".to_string(),
+            });
+        }
+        Ok(Self { rewrites })
+    }
+
+    /// Get the number of rewrites.
+    pub fn len(&self) -> usize {
+        self.rewrites.len()
+    }
+
+    /// Is this set empty?
+    pub fn is_empty(&self) -> bool {
+        self.rewrites.is_empty()
+    }
+}
+
+impl Rewrite {
+    /// Match the pattern node `node` against `operand`, recording the matched operands in
+    /// `bound`.
+    fn matches(
+        &self,
+        func: &Function,
+        node: usize,
+        operand: Operand,
+        bound: &mut [Option<Operand>],
+    ) -> bool {
+        let operand = match operand {
+            Operand::Value(value) => Operand::Value(func.dfg.resolve_aliases(value)),
+            imm => imm,
+        };
+        if let Some(previous) = bound[node] {
+            return previous == operand;
+        }
+        let ty = match operand {
+            Operand::Value(value) => func.dfg.value_type(value),
+            Operand::Imm(_, ty) => ty,
+        };
+        if ty != self.nodes[node].ty() {
+            return false;
+        }
+
+        let ok = match self.nodes[node] {
+            Node::Var(_) => true,
+            Node::Const(imm, _) => match operand {
+                Operand::Imm(other, _) => imm == other,
+                Operand::Value(value) => constant(func, value) == Some(imm),
+            },
+            Node::Op(op, _, ref args) => match operand {
+                Operand::Imm(..) => false,
+                Operand::Value(value) => match view(func, value) {
+                    Some((other, ref operands)) if other == op => args
+                        .iter()
+                        .zip(operands)
+                        .all(|(&arg, &operand)| self.matches(func, arg, operand, bound)),
+                    _ => false,
+                },
+            },
+        };
+        if ok {
+            bound[node] = Some(operand);
+        }
+        ok
+    }
+
+    /// Insert the instructions computing `node` before the cursor position, using the operands
+    /// `bound` by the match of the pattern.
+    fn build(
+        &self,
+        pos: &mut FuncCursor,
+        node: usize,
+        bound: &[Option<Operand>],
+        built: &mut [Option<Value>],
+    ) -> Value {
+        if let Some(value) = built[node] {
+            return value;
+        }
+        let value = if node < self.lhs_len {
+            match bound[node].expect("the parser checks the result only uses bound nodes") {
+                Operand::Value(value) => value,
+                Operand::Imm(imm, ty) => materialize(pos, imm, ty),
+            }
+        } else {
+            match self.nodes[node] {
+                Node::Var(_) => unreachable!("variables are only defined in the pattern"),
+                Node::Const(imm, ty) => materialize(pos, imm, ty),
+                Node::Op(op, ty, ref args) => {
+                    let args: Vec<Value> = args
+                        .iter()
+                        .map(|&arg| self.build(pos, arg, bound, built))
+                        .collect();
+                    match op {
+                        Op::Add => pos.ins().iadd(args[0], args[1]),
+                        Op::Sub => pos.ins().isub(args[0], args[1]),
+                        Op::Mul => pos.ins().imul(args[0], args[1]),
+                        Op::And => pos.ins().band(args[0], args[1]),
+                        Op::Or => pos.ins().bor(args[0], args[1]),
+                        Op::Xor => pos.ins().bxor(args[0], args[1]),
+                        Op::Eq | Op::Ne | Op::Ult | Op::Ule | Op::Slt | Op::Sle => {
+                            pos.ins().icmp(op.intcc().unwrap(), args[0], args[1])
+                        }
+                        Op::Select => pos.ins().select(args[0], args[1], args[2]),
+                        Op::Zext if pos.func.dfg.value_type(args[0]) == B1 => {
+                            pos.ins().bint(ty, args[0])
+                        }
+                        Op::Zext => pos.ins().uextend(ty, args[0]),
+                        Op::Sext => pos.ins().sextend(ty, args[0]),
+                        Op::Trunc => pos.ins().ireduce(ty, args[0]),
+                        Op::Ctpop => pos.ins().popcnt(args[0]),
+                        Op::Ctlz => pos.ins().clz(args[0]),
+                        Op::Cttz => pos.ins().ctz(args[0]),
+                    }
+                }
+            }
+        };
+        built[node] = Some(value);
+        value
+    }
+
+    /// Try to rewrite the instruction at the cursor position, whose result is `value`.
+    fn apply(&self, pos: &mut FuncCursor, inst: Inst, value: Value) -> bool {
+        let mut bound = vec![None; self.lhs_len];
+        if !self.matches(pos.func, self.root, Operand::Value(value), &mut bound) {
+            return false;
+        }
+        let mut built = vec![None; self.nodes.len()];
+        let replacement = self.build(pos, self.result, &bound, &mut built);
+
+        pos.func.dfg.clear_results(inst);
+        pos.func.dfg.change_to_alias(value, replacement);
+        pos.remove_inst_and_step_back();
+        true
+    }
+}
+
+/// Insert a constant before the cursor position.
+fn materialize(pos: &mut FuncCursor, imm: i64, ty: Type) -> Value {
+    if ty == B1 {
+        pos.ins().bconst(ty, imm != 0)
+    } else {
+        pos.ins().iconst(ty, imm)
+    }
+}
+
+/// Apply the first matching rewrite in `rewrites` to each instruction in `func`.
+///
+/// Returns true if any instructions were rewritten. The replaced instructions are removed, but the
+/// instructions they used are left for DCE.
+pub fn apply_rewrites(func: &mut Function, rewrites: &Rewrites) -> bool {
+    let mut changed = false;
+    let mut pos = FuncCursor::new(func);
+    while let Some(_ebb) = pos.next_ebb() {
+        while let Some(inst) = pos.next_inst() {
+            if pos.func.dfg.inst_results(inst).len() != 1 {
+                continue;
+            }
+            let value = pos.func.dfg.first_result(inst);
+            for rewrite in &rewrites.rewrites {
+                if rewrite.apply(&mut pos, inst, value) {
+                    changed = true;
+                    break;
+                }
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_rewrites, constant, write_souper_slices, Rewrites};
+    use cranelift_codegen::cursor::{Cursor, FuncCursor};
+    use cranelift_codegen::ir::types::I32;
+    use cranelift_codegen::ir::{AbiParam, Function, InstBuilder, Opcode, Value};
+    use std::string::String;
+
+    /// Build a function computing `(v0 + 1) + 1`, returning it with `v0` and the sum.
+    fn add_twice() -> (Function, Value, Value) {
+        let mut func = Function::new();
+        func.signature.params.push(AbiParam::new(I32));
+        func.signature.returns.push(AbiParam::new(I32));
+        let ebb = func.dfg.make_ebb();
+        let v0 = func.dfg.append_ebb_param(ebb, I32);
+        let v2 = {
+            let mut pos = FuncCursor::new(&mut func);
+            pos.insert_ebb(ebb);
+            let v1 = pos.ins().iadd_imm(v0, 1);
+            let v2 = pos.ins().iadd_imm(v1, 1);
+            pos.ins().return_(&[v2]);
+            v2
+        };
+        (func, v0, v2)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut func, v0, v2) = add_twice();
+        let mut text = String::new();
+        write_souper_slices(&mut text, &func, 4).unwrap();
+        assert_eq!(
+            text,
+            "; u0:0 v1\n\
+             %0:i32 = var\n\
+             %1:i32 = add %0, 1:i32\n\
+             infer %1\n\
+             \n\
+             ; u0:0 v2\n\
+             %0:i32 = var\n\
+             %1:i32 = add %0, 1:i32\n\
+             %2:i32 = add %1, 1:i32\n\
+             infer %2\n\
+             \n"
+        );
+
+        // Answer the second slice, and apply the rewrite to the function it came from.
+        let slice = &text[text.find("; u0:0 v2").unwrap()..];
+        let rewrites =
+            Rewrites::parse(&format!("{}%3:i32 = add %0, 2:i32\nresult %3\n", slice)).unwrap();
+        assert_eq!(rewrites.len(), 1);
+        assert!(apply_rewrites(&mut func, &rewrites));
+
+        let value = func.dfg.resolve_aliases(v2);
+        let inst = func.dfg.value_def(value).unwrap_inst();
+        assert_eq!(func.dfg[inst].opcode(), Opcode::Iadd);
+        let args = func.dfg.inst_args(inst);
+        assert_eq!(args[0], v0);
+        assert_eq!(constant(&func, args[1]), Some(2));
+
+        // The replacement doesn't match the pattern any more.
+        assert!(!apply_rewrites(&mut func, &rewrites));
+    }
+
+    #[test]
+    fn no_match() {
+        let (mut func, _, v2) = add_twice();
+        let rewrites = Rewrites::parse(
+            "%0:i32 = var\n\
+             %1:i32 = add %0, 2:i32\n\
+             %2:i32 = add %1, 1:i32\n\
+             infer %2\n\
+             %3:i32 = add %0, 3:i32\n\
+             result %3\n",
+        )
+        .unwrap();
+        assert!(!apply_rewrites(&mut func, &rewrites));
+        assert_eq!(func.dfg.resolve_aliases(v2), v2);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let error = |text| {
+            let error = Rewrites::parse(text).unwrap_err();
+            (error.line, error.message)
+        };
+        assert_eq!(
+            error("%0:i32"),
+            (1, "expected an instruction: %0:i32".to_string())
+        );
+        assert_eq!(
+            error("%0:i32 = var\n%1:i32 =\n"),
+            (2, "expected an instruction: %1:i32 =".to_string())
+        );
+        assert_eq!(
+            error("%0:i32 = var\n%1:i32 = add %0, 1\n"),
+            (2, "constant 1 needs a type".to_string())
+        );
+        assert_eq!(
+            error("%0:i32 = var\n%1:i32 = add %0, x:i32\n"),
+            (2, "invalid constant x:i32".to_string())
+        );
+        assert_eq!(
+            error("%0:i32 = var\n%1:i32 = add %0\n"),
+            (2, "add takes 2 operands".to_string())
+        );
+        assert_eq!(error("%0:f32 = var\n"), (1, "bad type f32".to_string()));
+        assert_eq!(error("0:i32 = var\n"), (1, "invalid name 0".to_string()));
+        assert_eq!(
+            error("%0:i32 = var\n%1:i32 = udiv %0, %0\n"),
+            (2, "unsupported operation udiv".to_string())
+        );
+        assert_eq!(
+            error("%0:i32 = var\ninfer %1\n"),
+            (2, "undefined name %1".to_string())
+        );
+        assert_eq!(
+            error("%0:i32 = var\ninfer %0\nresult 1:i32\n"),
+            (3, "the root must be an operation".to_string())
+        );
+        assert_eq!(
+            error("%0:i32 = var\n%1:i32 = add %0, 1:i32\ninfer %1\n"),
+            (3, "missing `result`".to_string())
+        );
+    }
+}
@@ -0,0 +1,43 @@
+"""
+Build a function which sums the integers below its argument, and compile it.
+
+Run this from the cranelift-py directory after building the module.
+"""
+from __future__ import print_function
+import sys
+
+sys.path.insert(0, '.')
+import cranelift_py as cl  # noqa
+
+func = cl.Function('sum', ['i32'], ['i32'])
+[n] = func.params()
+zero = func.iconst('i32', 0)
+
+loop = func.block()
+i = func.append_param(loop, 'i32')
+total = func.append_param(loop, 'i32')
+done = func.block()
+
+func.switch_to(0)
+func.jump(loop, [zero, zero])
+
+func.switch_to(loop)
+total2 = func.binary('iadd', total, i)
+i2 = func.binary_imm('iadd_imm', i, 1)
+more = func.icmp('slt', i2, n)
+func.brnz(more, loop, [i2, total2])
+func.jump(done)
+
+func.switch_to(done)
+func.return_([total2])
+
+func.verify()
+print(func)
+
+isa = cl.Isa('x86_64')
+ctx = cl.Context(func)
+size = ctx.compile(isa)
+print(ctx.func.display(isa))
+code, relocs = ctx.emit(isa)
+assert len(code) == size
+print(code.hex() if hasattr(code, 'hex') else code.encode('hex'))
@@ -0,0 +1,179 @@
+//! The `Context` class.
+
+use crate::function::Function;
+use crate::isa::Isa;
+use crate::{runtime_error, value_error};
+use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
+use cranelift_codegen::ir::{ExternalName, JumpTable};
+use cranelift_codegen::isa::TargetIsa;
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::CodegenResult;
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// The passes which can be run by `Context.run`, in the order `Context.compile` runs them.
+const PASSES: &[&str] = &[
+    "preopt",
+    "simplify_cfg",
+    "eliminate_bounds_checks",
+    "canonicalize_nans",
+    "elide_null_checks",
+    "legalize",
+    "postopt",
+    "licm",
+    "simple_gvn",
+    "eliminate_unreachable_code",
+    "dead_ebb_params",
+    "dce",
+    "regalloc",
+    "remove_redundant_moves",
+    "schedule",
+    "prologue_epilogue",
+    "shrink_instructions",
+    "order_ebbs",
+    "relax_branches",
+];
+
+/// A relocation, as a tuple of its offset, kind, symbol name, and addend.
+type PyReloc = (CodeOffset, String, String, Addend);
+
+/// A `RelocSink` collecting relocations for Python.
+struct PyRelocSink(Vec<PyReloc>);
+
+impl RelocSink for PyRelocSink {
+    fn reloc_ebb(&mut self, offset: CodeOffset, reloc: Reloc, ebb_offset: CodeOffset) {
+        let name = format!("ebb@{}", ebb_offset);
+        self.0.push((offset, reloc.to_string(), name, 0));
+    }
+
+    fn reloc_external(
+        &mut self,
+        offset: CodeOffset,
+        reloc: Reloc,
+        name: &ExternalName,
+        addend: Addend,
+    ) {
+        self.0
+            .push((offset, reloc.to_string(), name.to_string(), addend));
+    }
+
+    fn reloc_jt(&mut self, offset: CodeOffset, reloc: Reloc, jt: JumpTable) {
+        self.0.push((offset, reloc.to_string(), jt.to_string(), 0));
+    }
+}
+
+/// A function being compiled.
+///
+/// `Context(func)` starts compiling a copy of `func`. The passes of the code generator can be
+/// run one at a time with `run`, or all at once with `compile`, and the function can be inspected
+/// between passes. Once the function is compiled, `emit` produces its machine code.
+#[pyclass]
+pub struct Context {
+    ctx: cranelift_codegen::Context,
+
+    /// The size of the code, if the function has been compiled.
+    code_size: Option<CodeOffset>,
+}
+
+impl Context {
+    fn run_pass(&mut self, pass: &str, isa: &TargetIsa) -> PyResult<CodegenResult<()>> {
+        // Recompute the analyses, since the previous pass may have invalidated them.
+        let ctx = &mut self.ctx;
+        let code_size = &mut self.code_size;
+        if pass != "verify" {
+            *code_size = None;
+        }
+        ctx.flowgraph();
+        ctx.compute_loop_analysis();
+        Ok(match pass {
+            "verify" => ctx.verify_if(isa),
+            "preopt" => ctx.preopt(isa),
+            "simplify_cfg" => ctx.simplify_cfg(isa),
+            "eliminate_bounds_checks" => ctx.eliminate_bounds_checks(isa),
+            "canonicalize_nans" => ctx.canonicalize_nans(isa),
+            "elide_null_checks" => ctx.elide_null_checks(isa),
+            "legalize" => ctx.legalize(isa),
+            "postopt" => ctx.postopt(isa),
+            "licm" => ctx.licm(isa),
+            "simple_gvn" => ctx.simple_gvn(isa),
+            "eliminate_unreachable_code" => ctx.eliminate_unreachable_code(isa),
+            "dead_ebb_params" => ctx.dead_ebb_params(isa),
+            "dce" => ctx.dce(isa),
+            "regalloc" => ctx.regalloc(isa),
+            "remove_redundant_moves" => ctx.remove_redundant_moves(isa),
+            "schedule" => ctx.schedule(isa),
+            "prologue_epilogue" => ctx.prologue_epilogue(isa),
+            "shrink_instructions" => ctx.shrink_instructions(isa),
+            "order_ebbs" => ctx.order_ebbs(isa),
+            "relax_branches" => ctx.relax_branches(isa).map(|size| *code_size = Some(size)),
+            _ => return Err(value_error(format!("unknown pass '{}'", pass))),
+        })
+    }
+}
+
+#[pymethods]
+impl Context {
+    #[new]
+    fn __new__(obj: &PyRawObject, func: &Function) -> PyResult<()> {
+        obj.init(Context {
+            ctx: cranelift_codegen::Context::for_function(func.func.clone()),
+            code_size: None,
+        });
+        Ok(())
+    }
+
+    /// A copy of the function in its current state.
+    #[getter]
+    fn func(&self) -> PyResult<Function> {
+        Ok(Function::from(self.ctx.func.clone()))
+    }
+
+    /// The names of the passes accepted by `run`, in the order `compile` runs them.
+    #[staticmethod]
+    fn passes() -> PyResult<Vec<&'static str>> {
+        Ok(PASSES.to_vec())
+    }
+
+    /// Run the pass called `pass_name` on the function, raising a `RuntimeError` if it fails.
+    ///
+    /// The pass "verify" runs the verifier. Passes don't check whether the function is in the form
+    /// they expect: for example, "regalloc" must run after "legalize".
+    fn run(&mut self, pass_name: &str, isa: &Isa) -> PyResult<()> {
+        let isa = &*isa.isa;
+        let result = self.run_pass(pass_name, isa)?;
+        result.map_err(|err| runtime_error(pretty_error(&self.ctx.func, Some(isa), err)))
+    }
+
+    /// Compile the function with all of the passes, returning the size of its code.
+    fn compile(&mut self, isa: &Isa) -> PyResult<CodeOffset> {
+        let isa = &*isa.isa;
+        let size = self
+            .ctx
+            .compile(isa)
+            .map_err(|err| runtime_error(pretty_error(&self.ctx.func, Some(isa), err)))?;
+        self.code_size = Some(size);
+        Ok(size)
+    }
+
+    /// Emit the machine code of the compiled function.
+    ///
+    /// Returns the code as `bytes`, and a list of relocations as tuples of their offset, kind,
+    /// symbol name, and addend. The function must have been compiled with `compile`, or by running
+    /// all of the passes up to "relax_branches".
+    fn emit(&self, py: Python, isa: &Isa) -> PyResult<(PyObject, Vec<PyReloc>)> {
+        let size = self.code_size.ok_or_else(|| {
+            runtime_error("the function must be compiled before its code is emitted")
+        })?;
+        let mut code = vec![0; size as usize];
+        let mut relocs = PyRelocSink(Vec::new());
+        unsafe {
+            self.ctx.emit_to_memory(
+                &*isa.isa,
+                code.as_mut_ptr(),
+                &mut relocs,
+                &mut NullTrapSink {},
+            );
+        }
+        Ok((PyBytes::new(py, &code).to_object(py), relocs.0))
+    }
+}
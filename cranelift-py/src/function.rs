@@ -0,0 +1,299 @@
+//! The `Function` class.
+
+use crate::isa::Isa;
+use crate::{value_error, value_type};
+use cranelift_codegen::cursor::{Cursor, FuncCursor};
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::immediates::{Ieee32, Ieee64};
+use cranelift_codegen::ir::instructions::InstructionFormat;
+use cranelift_codegen::ir::{self, AbiParam, Ebb, ExternalName, InstBuilder, MemFlags, Opcode};
+use cranelift_codegen::isa::CallConv;
+use cranelift_codegen::print_errors::pretty_verifier_error;
+use cranelift_codegen::settings;
+use cranelift_codegen::verify_function;
+use pyo3::class::basic::PyObjectProtocol;
+use pyo3::prelude::*;
+use std::str::FromStr;
+
+/// A function in Cranelift IR.
+///
+/// `Function(name, params, returns, call_conv)` creates an empty function with an entry block
+/// whose parameters are the function's parameters. The parameter and return types are given as
+/// names like "i32".
+///
+/// Instructions are appended to the current block, which is the last block created or the block
+/// selected by `switch_to`. Values and blocks are identified by integers.
+#[pyclass]
+pub struct Function {
+    pub(crate) func: ir::Function,
+
+    /// The block where new instructions are appended.
+    block: Option<Ebb>,
+}
+
+impl From<ir::Function> for Function {
+    fn from(func: ir::Function) -> Self {
+        let block = func.layout.last_ebb();
+        Self { func, block }
+    }
+}
+
+impl Function {
+    fn value(&self, value: u32) -> PyResult<ir::Value> {
+        let v = ir::Value::with_number(value).filter(|&v| self.func.dfg.value_is_valid(v));
+        v.ok_or_else(|| value_error(format!("invalid value {}", value)))
+    }
+
+    fn values(&self, values: Vec<u32>) -> PyResult<Vec<ir::Value>> {
+        values.into_iter().map(|v| self.value(v)).collect()
+    }
+
+    fn ebb(&self, block: u32) -> PyResult<Ebb> {
+        let ebb = Ebb::with_number(block).filter(|&ebb| self.func.dfg.ebb_is_valid(ebb));
+        ebb.ok_or_else(|| value_error(format!("invalid block {}", block)))
+    }
+
+    /// Get a cursor for appending instructions to the current block.
+    fn cursor(&mut self) -> PyResult<FuncCursor> {
+        let block = self
+            .block
+            .ok_or_else(|| value_error("the function has no blocks"))?;
+        Ok(FuncCursor::new(&mut self.func).at_bottom(block))
+    }
+
+    /// Get an opcode by name, checking its instruction format.
+    fn opcode(name: &str, format: InstructionFormat) -> PyResult<Opcode> {
+        let opcode = Opcode::from_str(name).map_err(value_error)?;
+        if opcode.format() != format {
+            return Err(value_error(format!(
+                "{} is not in the {:?} instruction format",
+                opcode, format
+            )));
+        }
+        Ok(opcode)
+    }
+}
+
+#[pymethods]
+impl Function {
+    #[new]
+    #[args(
+        params = "Vec::new()",
+        returns = "Vec::new()",
+        call_conv = "\"system_v\""
+    )]
+    fn __new__(
+        obj: &PyRawObject,
+        name: &str,
+        params: Vec<&str>,
+        returns: Vec<&str>,
+        call_conv: &str,
+    ) -> PyResult<()> {
+        let call_conv = CallConv::from_str(call_conv)
+            .map_err(|()| value_error(format!("unknown calling convention '{}'", call_conv)))?;
+        let mut sig = ir::Signature::new(call_conv);
+        for ty in params {
+            sig.params.push(AbiParam::new(value_type(ty)?));
+        }
+        for ty in returns {
+            sig.returns.push(AbiParam::new(value_type(ty)?));
+        }
+        let mut func = ir::Function::with_name_signature(ExternalName::testcase(name), sig);
+        let entry = func.dfg.make_ebb();
+        for i in 0..func.signature.params.len() {
+            let ty = func.signature.params[i].value_type;
+            func.dfg.append_ebb_param(entry, ty);
+        }
+        func.layout.append_ebb(entry);
+        obj.init(Self::from(func));
+        Ok(())
+    }
+
+    /// The name of the function.
+    #[getter]
+    fn name(&self) -> PyResult<String> {
+        Ok(self.func.name.to_string())
+    }
+
+    /// Get the parameters of the function.
+    fn params(&self) -> PyResult<Vec<u32>> {
+        let entry = self
+            .func
+            .layout
+            .entry_block()
+            .ok_or_else(|| value_error("the function has no blocks"))?;
+        Ok(self
+            .func
+            .dfg
+            .ebb_params(entry)
+            .iter()
+            .map(|v| v.as_u32())
+            .collect())
+    }
+
+    /// Create a new block at the end of the function, and make it the current block.
+    fn block(&mut self) -> PyResult<u32> {
+        let ebb = self.func.dfg.make_ebb();
+        self.func.layout.append_ebb(ebb);
+        self.block = Some(ebb);
+        Ok(ebb.as_u32())
+    }
+
+    /// Append a parameter of type `ty` to `block`.
+    fn append_param(&mut self, block: u32, ty: &str) -> PyResult<u32> {
+        let ebb = self.ebb(block)?;
+        let ty = value_type(ty)?;
+        Ok(self.func.dfg.append_ebb_param(ebb, ty).as_u32())
+    }
+
+    /// Make `block` the current block.
+    fn switch_to(&mut self, block: u32) -> PyResult<()> {
+        self.block = Some(self.ebb(block)?);
+        Ok(())
+    }
+
+    /// Append an integer constant of type `ty`.
+    fn iconst(&mut self, ty: &str, imm: i64) -> PyResult<u32> {
+        let ty = value_type(ty)?;
+        Ok(self.cursor()?.ins().iconst(ty, imm).as_u32())
+    }
+
+    /// Append a 32-bit floating point constant.
+    fn f32const(&mut self, imm: f32) -> PyResult<u32> {
+        let imm = Ieee32::with_float(imm);
+        Ok(self.cursor()?.ins().f32const(imm).as_u32())
+    }
+
+    /// Append a 64-bit floating point constant.
+    fn f64const(&mut self, imm: f64) -> PyResult<u32> {
+        let imm = Ieee64::with_float(imm);
+        Ok(self.cursor()?.ins().f64const(imm).as_u32())
+    }
+
+    /// Append an instruction with one argument, like "bnot" or "uextend".
+    ///
+    /// The controlling type `ty` defaults to the type of `x`.
+    #[args(ty = "None")]
+    fn unary(&mut self, opcode: &str, x: u32, ty: Option<&str>) -> PyResult<u32> {
+        let opcode = Self::opcode(opcode, InstructionFormat::Unary)?;
+        let x = self.value(x)?;
+        let ty = match ty {
+            Some(ty) => value_type(ty)?,
+            None => self.func.dfg.value_type(x),
+        };
+        let mut pos = self.cursor()?;
+        let (inst, dfg) = pos.ins().Unary(opcode, ty, x);
+        Ok(dfg.first_result(inst).as_u32())
+    }
+
+    /// Append an instruction with two arguments, like "iadd" or "fmul".
+    fn binary(&mut self, opcode: &str, x: u32, y: u32) -> PyResult<u32> {
+        let opcode = Self::opcode(opcode, InstructionFormat::Binary)?;
+        let x = self.value(x)?;
+        let y = self.value(y)?;
+        let ty = self.func.dfg.value_type(x);
+        let mut pos = self.cursor()?;
+        let (inst, dfg) = pos.ins().Binary(opcode, ty, x, y);
+        Ok(dfg.first_result(inst).as_u32())
+    }
+
+    /// Append an instruction with an argument and an immediate, like "iadd_imm".
+    fn binary_imm(&mut self, opcode: &str, x: u32, imm: i64) -> PyResult<u32> {
+        let opcode = Self::opcode(opcode, InstructionFormat::BinaryImm)?;
+        let x = self.value(x)?;
+        let ty = self.func.dfg.value_type(x);
+        let mut pos = self.cursor()?;
+        let (inst, dfg) = pos.ins().BinaryImm(opcode, ty, imm.into(), x);
+        Ok(dfg.first_result(inst).as_u32())
+    }
+
+    /// Append an integer comparison with the condition code `cond`, like "slt".
+    fn icmp(&mut self, cond: &str, x: u32, y: u32) -> PyResult<u32> {
+        let cond = IntCC::from_str(cond)
+            .map_err(|()| value_error(format!("unknown condition code '{}'", cond)))?;
+        let x = self.value(x)?;
+        let y = self.value(y)?;
+        Ok(self.cursor()?.ins().icmp(cond, x, y).as_u32())
+    }
+
+    /// Append a load of a value of type `ty` from `addr + offset`.
+    #[args(offset = "0")]
+    fn load(&mut self, ty: &str, addr: u32, offset: i32) -> PyResult<u32> {
+        let ty = value_type(ty)?;
+        let addr = self.value(addr)?;
+        let mut pos = self.cursor()?;
+        Ok(pos.ins().load(ty, MemFlags::new(), addr, offset).as_u32())
+    }
+
+    /// Append a store of `value` to `addr + offset`.
+    #[args(offset = "0")]
+    fn store(&mut self, value: u32, addr: u32, offset: i32) -> PyResult<()> {
+        let value = self.value(value)?;
+        let addr = self.value(addr)?;
+        self.cursor()?
+            .ins()
+            .store(MemFlags::new(), value, addr, offset);
+        Ok(())
+    }
+
+    /// Append a jump to `block`, passing `args` as the block's parameters.
+    #[args(args = "Vec::new()")]
+    fn jump(&mut self, block: u32, args: Vec<u32>) -> PyResult<()> {
+        let ebb = self.ebb(block)?;
+        let args = self.values(args)?;
+        self.cursor()?.ins().jump(ebb, &args);
+        Ok(())
+    }
+
+    /// Append a branch to `block` which is taken when `cond` is zero or false.
+    #[args(args = "Vec::new()")]
+    fn brz(&mut self, cond: u32, block: u32, args: Vec<u32>) -> PyResult<()> {
+        let cond = self.value(cond)?;
+        let ebb = self.ebb(block)?;
+        let args = self.values(args)?;
+        self.cursor()?.ins().brz(cond, ebb, &args);
+        Ok(())
+    }
+
+    /// Append a branch to `block` which is taken when `cond` is non-zero or true.
+    #[args(args = "Vec::new()")]
+    fn brnz(&mut self, cond: u32, block: u32, args: Vec<u32>) -> PyResult<()> {
+        let cond = self.value(cond)?;
+        let ebb = self.ebb(block)?;
+        let args = self.values(args)?;
+        self.cursor()?.ins().brnz(cond, ebb, &args);
+        Ok(())
+    }
+
+    /// Append a return of `values` from the function.
+    #[args(values = "Vec::new()")]
+    fn return_(&mut self, values: Vec<u32>) -> PyResult<()> {
+        let values = self.values(values)?;
+        self.cursor()?.ins().return_(&values);
+        Ok(())
+    }
+
+    /// Check the function with the verifier, raising a `ValueError` describing any errors.
+    #[args(isa = "None")]
+    fn verify(&self, isa: Option<&Isa>) -> PyResult<()> {
+        let isa = isa.map(|isa| &*isa.isa);
+        let result = match isa {
+            Some(isa) => verify_function(&self.func, isa),
+            None => verify_function(&self.func, &settings::Flags::new(settings::builder())),
+        };
+        result.map_err(|errors| value_error(pretty_verifier_error(&self.func, isa, None, errors)))
+    }
+
+    /// Get the function in the text format, with the encodings and value locations of `isa`.
+    #[args(isa = "None")]
+    fn display(&self, isa: Option<&Isa>) -> PyResult<String> {
+        Ok(self.func.display(isa.map(|isa| &*isa.isa)).to_string())
+    }
+}
+
+#[pyproto]
+impl<'p> PyObjectProtocol<'p> for Function {
+    fn __str__(&self) -> PyResult<String> {
+        Ok(self.func.display(None).to_string())
+    }
+}
@@ -0,0 +1,95 @@
+//! The `Isa` class.
+
+use crate::value_error;
+use cranelift_codegen::isa::{self, TargetIsa};
+use cranelift_codegen::settings::{self, Configurable, SetError, SetResult};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::str::FromStr;
+use target_lexicon::Triple;
+
+/// A target ISA with its settings.
+///
+/// `Isa(triple, settings)` creates the ISA for the target `triple`, or for the host if `triple`
+/// is `None`. `settings` is a dictionary of shared and ISA settings, whose values are strings or
+/// booleans.
+#[pyclass]
+pub struct Isa {
+    pub(crate) isa: Box<TargetIsa>,
+}
+
+/// Change the ISA setting `name`, or the shared setting if the ISA has no setting by that name.
+///
+/// A `value` of `None` enables a boolean setting.
+fn set(
+    isa: &mut isa::Builder,
+    flags: &mut settings::Builder,
+    name: &str,
+    value: Option<&str>,
+) -> SetResult<()> {
+    match value {
+        None => match isa.enable(name) {
+            Err(SetError::BadName(_)) => flags.enable(name),
+            result => result,
+        },
+        Some(value) => match isa.set(name, value) {
+            Err(SetError::BadName(_)) => flags.set(name, value),
+            result => result,
+        },
+    }
+}
+
+#[pymethods]
+impl Isa {
+    #[new]
+    #[args(triple = "None", settings = "None")]
+    fn __new__(obj: &PyRawObject, triple: Option<&str>, settings: Option<&PyDict>) -> PyResult<()> {
+        let mut isa_builder = match triple {
+            Some(triple) => {
+                let triple = Triple::from_str(triple).map_err(value_error)?;
+                isa::lookup(triple).map_err(value_error)?
+            }
+            None => cranelift_native::builder().map_err(value_error)?,
+        };
+        let mut flag_builder = settings::builder();
+        if let Some(settings) = settings {
+            for (name, value) in settings.iter() {
+                let name: String = name.extract()?;
+                let value = match value.extract::<bool>() {
+                    Ok(true) => None,
+                    Ok(false) => Some(String::from("false")),
+                    Err(_) => Some(value.extract::<String>()?),
+                };
+                set(
+                    &mut isa_builder,
+                    &mut flag_builder,
+                    &name,
+                    value.as_ref().map(String::as_str),
+                )
+                .map_err(|err| value_error(format!("{}: {}", name, err)))?;
+            }
+        }
+        obj.init(Isa {
+            isa: isa_builder.finish(settings::Flags::new(flag_builder)),
+        });
+        Ok(())
+    }
+
+    /// The name of the ISA, like "x86".
+    #[getter]
+    fn name(&self) -> PyResult<&str> {
+        Ok(self.isa.name())
+    }
+
+    /// The target triple of the ISA.
+    #[getter]
+    fn triple(&self) -> PyResult<String> {
+        Ok(self.isa.triple().to_string())
+    }
+
+    /// The shared settings of the ISA, in TOML format.
+    #[getter]
+    fn settings(&self) -> PyResult<String> {
+        Ok(self.isa.flags().to_string())
+    }
+}
@@ -0,0 +1,72 @@
+//! Python bindings for Cranelift.
+//!
+//! This crate builds a Python extension module called `cranelift_py`, with these classes:
+//!
+//! - `Isa`: a target ISA and its settings.
+//! - `Function`: a function in Cranelift IR, which can be parsed from the text format or built
+//!   one instruction at a time.
+//! - `Context`: a function being compiled, which can run the passes of the code generator one at
+//!   a time or all at once, and emit the machine code.
+
+#![deny(missing_docs, trivial_numeric_casts, unused_extern_crates)]
+#![warn(unused_import_braces)]
+#![feature(specialization)]
+
+mod context;
+mod function;
+mod isa;
+
+use crate::context::Context;
+use crate::function::Function;
+use crate::isa::Isa;
+use cranelift_codegen::ir::{types, Type};
+use pyo3::exceptions;
+use pyo3::prelude::*;
+use pyo3::wrap_pyfunction;
+
+/// Make a Python `ValueError` from an error message.
+fn value_error<E: ToString>(err: E) -> PyErr {
+    exceptions::ValueError::py_err(err.to_string())
+}
+
+/// Make a Python `RuntimeError` from an error message.
+fn runtime_error<E: ToString>(err: E) -> PyErr {
+    exceptions::RuntimeError::py_err(err.to_string())
+}
+
+/// Get the scalar type called `name`, like "i32".
+fn value_type(name: &str) -> PyResult<Type> {
+    Ok(match name {
+        "i8" => types::I8,
+        "i16" => types::I16,
+        "i32" => types::I32,
+        "i64" => types::I64,
+        "f32" => types::F32,
+        "f64" => types::F64,
+        "b1" => types::B1,
+        "b8" => types::B8,
+        "b16" => types::B16,
+        "b32" => types::B32,
+        "b64" => types::B64,
+        _ => return Err(value_error(format!("unknown type '{}'", name))),
+    })
+}
+
+/// Parse all of the functions in `text`, which is in the Cranelift IR text format.
+#[pyfunction]
+fn parse(text: &str) -> PyResult<Vec<Function>> {
+    let funcs = cranelift_reader::parse_functions(text)
+        .map_err(|err| value_error(err.display_source(text)))?;
+    Ok(funcs.into_iter().map(Function::from).collect())
+}
+
+/// The `cranelift_py` Python module.
+#[pymodule]
+fn cranelift_py(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<Isa>()?;
+    m.add_class::<Function>()?;
+    m.add_class::<Context>()?;
+    m.add_wrapped(wrap_pyfunction!(parse))?;
+    m.add("VERSION", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
@@ -0,0 +1,89 @@
+"""
+Tests for the Python bindings. The `cranelift_py` module must be built and
+copied into the cranelift-py directory first, as described in README.md.
+"""
+from __future__ import absolute_import
+import os
+import sys
+from unittest import TestCase
+
+sys.path.insert(0, os.path.join(os.path.dirname(__file__), '..'))
+import cranelift_py as cl  # noqa
+
+ADD = """
+function %add(i32, i32) -> i32 {
+ebb0(v0: i32, v1: i32):
+    v2 = iadd v0, v1
+    return v2
+}
+"""
+
+
+class TestParse(TestCase):
+    def test_parse(self):
+        [func] = cl.parse(ADD)
+        self.assertEqual(func.name, '%add')
+        self.assertEqual(len(func.params()), 2)
+        self.assertIn('iadd v0, v1', str(func))
+
+    def test_parse_error(self):
+        with self.assertRaises(ValueError) as cm:
+            cl.parse('function %f() {\nebb0:\n    v0 = bogus\n}')
+        self.assertIn('3:', str(cm.exception))
+
+
+class TestBuild(TestCase):
+    def test_build(self):
+        func = cl.Function('f', ['i32'], ['i32'])
+        [x] = func.params()
+        y = func.binary_imm('imul_imm', x, 3)
+        func.return_([y])
+        func.verify()
+        self.assertIn('imul_imm v0, 3', str(func))
+
+    def test_invalid(self):
+        func = cl.Function('f', ['i32'], ['i32'])
+        with self.assertRaises(ValueError):
+            func.binary('iadd', 0, 7)
+        with self.assertRaises(ValueError):
+            func.binary('iadd_imm', 0, 0)
+        with self.assertRaises(ValueError):
+            func.iconst('i3', 0)
+
+    def test_verify(self):
+        func = cl.Function('f', ['i32'], ['i32'])
+        func.return_()
+        with self.assertRaises(ValueError):
+            func.verify()
+
+
+class TestCompile(TestCase):
+    def setUp(self):
+        self.isa = cl.Isa('x86_64', {'opt_level': 'speed'})
+
+    def test_isa(self):
+        self.assertEqual(self.isa.name, 'x86')
+        with self.assertRaises(ValueError):
+            cl.Isa('x86_64', {'no_such_setting': True})
+
+    def test_compile(self):
+        [func] = cl.parse(ADD)
+        ctx = cl.Context(func)
+        size = ctx.compile(self.isa)
+        code, relocs = ctx.emit(self.isa)
+        self.assertEqual(len(code), size)
+        self.assertEqual(relocs, [])
+
+    def test_passes(self):
+        [func] = cl.parse(ADD)
+        ctx = cl.Context(func)
+        with self.assertRaises(RuntimeError):
+            ctx.emit(self.isa)
+        for name in cl.Context.passes():
+            ctx.run(name, self.isa)
+        ctx.run('verify', self.isa)
+        self.assertIn('rr#01', ctx.func.display(self.isa))
+        code, relocs = ctx.emit(self.isa)
+        self.assertGreater(len(code), 0)
+        with self.assertRaises(ValueError):
+            ctx.run('no_such_pass', self.isa)
@@ -3,6 +3,7 @@
 #![macro_use]
 
 use std::fmt;
+use std::iter;
 
 /// The location of a `Token` or `Error`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -21,10 +22,29 @@ pub struct ParseError {
     pub message: String,
     /// Whether it's a warning or a plain error.
     pub is_warning: bool,
+    /// Column of the token where the error was detected, counted in bytes from 1, or 0 if it
+    /// isn't known.
+    pub column: usize,
+    /// Further errors found after the parser recovered from this one, in source order.
+    pub related: Vec<ParseError>,
 }
 
-impl fmt::Display for ParseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl ParseError {
+    /// Get all the errors, starting with this one and followed by the related errors.
+    pub fn all(&self) -> impl Iterator<Item = &ParseError> {
+        iter::once(self).chain(self.related.iter())
+    }
+
+    /// Display all the errors with their line and column, and the line of `source` they point
+    /// to.
+    pub fn display_source<'a>(&'a self, source: &'a str) -> DisplayParseError<'a> {
+        DisplayParseError {
+            error: self,
+            source,
+        }
+    }
+
+    fn fmt_message(&self, f: &mut fmt::Formatter) -> fmt::Result {
         if self.location.line_number == 0 {
             write!(f, "command-line arguments: {}", self.message)
         } else {
@@ -33,6 +53,54 @@ impl fmt::Display for ParseError {
     }
 }
 
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_message(f)?;
+        for err in &self.related {
+            writeln!(f)?;
+            err.fmt_message(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Wrapper type for displaying parse errors with source snippets.
+pub struct DisplayParseError<'a> {
+    error: &'a ParseError,
+    source: &'a str,
+}
+
+impl<'a> fmt::Display for DisplayParseError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, err) in self.error.all().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            let line_number = err.location.line_number;
+            let column = err.column;
+            let line = match line_number.checked_sub(1) {
+                Some(index) if column > 0 => self.source.lines().nth(index),
+                _ => None,
+            };
+            match line {
+                Some(line) => {
+                    writeln!(f, "{}:{}: {}", line_number, column, err.message)?;
+                    writeln!(f, "{}", line)?;
+                    // Keep the tabs in the line so the caret lines up with the column.
+                    let indent: String = line
+                        .char_indices()
+                        .take_while(|&(idx, _)| idx + 1 < column)
+                        .map(|(_, ch)| if ch == '\t' { '\t' } else { ' ' })
+                        .collect();
+                    write!(f, "{}^", indent)?;
+                }
+                None => err.fmt_message(f)?,
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Result of a parser operation. The `ParseError` variant includes a location.
 pub type ParseResult<T> = Result<T, ParseError>;
 
@@ -43,6 +111,8 @@ macro_rules! err {
             location: $loc.clone(),
             message: $msg.to_string(),
             is_warning: false,
+            column: 0,
+            related: Vec::new(),
         })
     };
 
@@ -51,6 +121,8 @@ macro_rules! err {
             location: $loc.clone(),
             message: format!( $fmt, $( $arg ),+ ),
             is_warning: false,
+            column: 0,
+            related: Vec::new(),
         })
     };
 }
@@ -61,6 +133,8 @@ macro_rules! warn {
             location: $loc.clone(),
             message: format!($fmt, $( $arg ),+ ),
             is_warning: true,
+            column: 0,
+            related: Vec::new(),
         })
     };
 }
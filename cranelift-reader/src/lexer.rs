@@ -108,7 +108,7 @@ pub fn split_entity_name(name: &str) -> Option<(&str, u32)> {
 ///
 /// A `Lexer` reads text from a `&str` and provides a sequence of tokens.
 ///
-/// Also keep track of a line number for error reporting.
+/// Also keep track of a line number and column for error reporting.
 ///
 pub struct Lexer<'a> {
     // Complete source being processed.
@@ -125,6 +125,12 @@ pub struct Lexer<'a> {
 
     // Current line number.
     line_number: usize,
+
+    // Index into `source` of the first character on the current line.
+    line_start: usize,
+
+    // Column of the token or error most recently returned by `next()`.
+    column: usize,
 }
 
 impl<'a> Lexer<'a> {
@@ -135,6 +141,8 @@ impl<'a> Lexer<'a> {
             lookahead: None,
             pos: 0,
             line_number: 1,
+            line_start: 0,
+            column: 0,
         };
         // Advance to the first char.
         lex.next_ch();
@@ -145,9 +153,7 @@ impl<'a> Lexer<'a> {
     // Return the next lookahead character, or None when the end is encountered.
     // Always update cur_ch to reflect
     fn next_ch(&mut self) -> Option<char> {
-        if self.lookahead == Some('\n') {
-            self.line_number += 1;
-        }
+        let new_line = self.lookahead == Some('\n');
         match self.chars.next() {
             Some((idx, ch)) => {
                 self.pos = idx;
@@ -158,6 +164,10 @@ impl<'a> Lexer<'a> {
                 self.lookahead = None;
             }
         }
+        if new_line {
+            self.line_number += 1;
+            self.line_start = self.pos;
+        }
         self.lookahead
     }
 
@@ -168,6 +178,12 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Get the column of the token or error most recently returned by `next()`, counted in bytes
+    /// from 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
     // Starting from `lookahead`, are we looking at `prefix`?
     fn looking_at(&self, prefix: &str) -> bool {
         self.source[self.pos..].starts_with(prefix)
@@ -363,6 +379,7 @@ impl<'a> Lexer<'a> {
             "i16" => types::I16,
             "i32" => types::I32,
             "i64" => types::I64,
+            "i128" => types::I128,
             "f32" => types::F32,
             "f64" => types::F64,
             "b1" => types::B1,
@@ -370,6 +387,9 @@ impl<'a> Lexer<'a> {
             "b16" => types::B16,
             "b32" => types::B32,
             "b64" => types::B64,
+            "b128" => types::B128,
+            "r32" => types::R32,
+            "r64" => types::R64,
             _ => return None,
         };
         if is_vector {
@@ -437,6 +457,7 @@ impl<'a> Lexer<'a> {
     pub fn next(&mut self) -> Option<Result<LocatedToken<'a>, LocatedError>> {
         loop {
             let loc = self.loc();
+            self.column = self.pos - self.line_start + 1;
             return match self.lookahead {
                 None => None,
                 Some(';') => Some(self.scan_comment()),
@@ -546,6 +567,30 @@ mod tests {
         assert_eq!(lex.next(), None);
     }
 
+    #[test]
+    fn lex_columns() {
+        let mut lex = Lexer::new("v1 = iadd\n\tv2, v3 ; c\n$");
+        let expected = [
+            (1, 1),
+            (1, 4),
+            (1, 6),
+            (2, 2),
+            (2, 4),
+            (2, 6),
+            (2, 9),
+            (3, 1),
+        ];
+        let mut columns = Vec::new();
+        while let Some(res) = lex.next() {
+            let line = match res {
+                Ok(tok) => tok.location.line_number,
+                Err(err) => err.location.line_number,
+            };
+            columns.push((line, lex.column()));
+        }
+        assert_eq!(columns, expected);
+    }
+
     #[test]
     fn lex_chars() {
         let mut lex = Lexer::new("(); hello\n = :{, }.");
@@ -580,8 +625,8 @@ mod tests {
     fn lex_identifiers() {
         let mut lex = Lexer::new(
             "v0 v00 vx01 ebb1234567890 ebb5234567890 v1x vx1 vxvx4 \
-             function0 function b1 i32x4 f32x5 \
-             iflags fflags iflagss",
+             function0 function b1 i32x4 i128 b128x2 f32x5 \
+             iflags fflags iflagss r32 r64 r64x2",
         );
         assert_eq!(
             lex.next(),
@@ -601,10 +646,15 @@ mod tests {
         assert_eq!(lex.next(), token(Token::Identifier("function"), 1));
         assert_eq!(lex.next(), token(Token::Type(types::B1), 1));
         assert_eq!(lex.next(), token(Token::Type(types::I32X4), 1));
+        assert_eq!(lex.next(), token(Token::Type(types::I128), 1));
+        assert_eq!(lex.next(), token(Token::Type(types::B128X2), 1));
         assert_eq!(lex.next(), token(Token::Identifier("f32x5"), 1));
         assert_eq!(lex.next(), token(Token::Type(types::IFLAGS), 1));
         assert_eq!(lex.next(), token(Token::Type(types::FFLAGS), 1));
         assert_eq!(lex.next(), token(Token::Identifier("iflagss"), 1));
+        assert_eq!(lex.next(), token(Token::Type(types::R32), 1));
+        assert_eq!(lex.next(), token(Token::Type(types::R64), 1));
+        assert_eq!(lex.next(), token(Token::Identifier("r64x2"), 1));
         assert_eq!(lex.next(), None);
     }
 
@@ -26,7 +26,7 @@
     )
 )]
 
-pub use crate::error::{Location, ParseError, ParseResult};
+pub use crate::error::{DisplayParseError, Location, ParseError, ParseResult};
 pub use crate::isaspec::{parse_options, IsaSpec};
 pub use crate::parser::{parse_functions, parse_test};
 pub use crate::sourcemap::SourceMap;
@@ -13,10 +13,10 @@ use cranelift_codegen::ir::immediates::{Ieee32, Ieee64, Imm64, Offset32, Uimm32,
 use cranelift_codegen::ir::instructions::{InstructionData, InstructionFormat, VariableArgs};
 use cranelift_codegen::ir::types::INVALID;
 use cranelift_codegen::ir::{
-    AbiParam, ArgumentExtension, ArgumentLoc, Ebb, ExtFuncData, ExternalName, FuncRef, Function,
-    GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle, JumpTable, JumpTableData, MemFlags,
-    Opcode, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind, Table, TableData, Type,
-    Value, ValueLoc,
+    AbiParam, ArgumentExtension, ArgumentLoc, ArgumentPurpose, Ebb, ExtFuncData, ExternalName,
+    FuncRef, Function, GlobalValue, GlobalValueData, Heap, HeapData, HeapStyle, JumpTable,
+    JumpTableData, MemFlags, Opcode, SigRef, Signature, StackSlot, StackSlotData, StackSlotKind,
+    Table, TableData, Type, Value, ValueLoc,
 };
 use cranelift_codegen::isa::{self, CallConv, Encoding, RegUnit, TargetIsa};
 use cranelift_codegen::packed_option::ReservedValue;
@@ -91,6 +91,9 @@ pub struct Parser<'a> {
     /// Location of lookahead.
     loc: Location,
 
+    /// Column of lookahead.
+    column: usize,
+
     /// Are we gathering any comments that we encounter?
     gathering_comments: bool,
 
@@ -99,6 +102,9 @@ pub struct Parser<'a> {
 
     /// Comments collected so far.
     comments: Vec<Comment<'a>>,
+
+    /// Errors recovered from while parsing the current function.
+    errors: Vec<ParseError>,
 }
 
 /// Context for resolving references when parsing a single function.
@@ -168,6 +174,7 @@ impl<'a> Context<'a> {
                 name: ExternalName::testcase(""),
                 offset: Imm64::new(0),
                 colocated: false,
+                tls: false,
             });
         }
         self.function.global_values[gv] = data;
@@ -195,6 +202,7 @@ impl<'a> Context<'a> {
                     bound: Uimm64::new(0),
                 },
                 index_type: INVALID,
+                shared: false,
             });
         }
         self.function.heaps[heap] = data;
@@ -315,9 +323,11 @@ impl<'a> Parser<'a> {
             lex_error: None,
             lookahead: None,
             loc: Location { line_number: 0 },
+            column: 0,
             gathering_comments: false,
             gathered_comments: Vec::new(),
             comments: Vec::new(),
+            errors: Vec::new(),
         }
     }
 
@@ -352,10 +362,12 @@ impl<'a> Parser<'a> {
                         _ => self.lookahead = Some(token),
                     }
                     self.loc = location;
+                    self.column = self.lex.column();
                 }
                 Some(Err(LocatedError { error, location })) => {
                     self.lex_error = Some(error);
                     self.loc = location;
+                    self.column = self.lex.column();
                     break;
                 }
                 None => break,
@@ -531,7 +543,53 @@ impl<'a> Parser<'a> {
             location: self.loc,
             message: message.to_string(),
             is_warning: false,
+            column: self.column,
+            related: Vec::new(),
+        }
+    }
+
+    // Record the error `err` found while parsing a construct that started on `line`, and skip the
+    // rest of that line so parsing can continue with the next instruction or EBB header.
+    //
+    // Warnings and errors at the end of the input can't be recovered from, so they are returned.
+    fn recover(&mut self, err: ParseError, line: usize) -> ParseResult<()> {
+        let err = self.locate(err);
+        if err.is_warning || self.token().is_none() {
+            return Err(err);
+        }
+        self.errors.push(err);
+
+        // Forget the comments gathered for the construct we are abandoning.
+        self.gathering_comments = false;
+        self.gathered_comments.clear();
+
+        while self.token().is_some() && self.loc.line_number <= line {
+            self.consume();
         }
+        Ok(())
+    }
+
+    // Give `err` the column of the lookahead token, where it was detected, unless it points to
+    // another line or already has a column.
+    fn locate(&self, mut err: ParseError) -> ParseError {
+        if err.column == 0 && err.location == self.loc {
+            err.column = self.column;
+        }
+        err
+    }
+
+    // Combine the recovered errors with `last`, which ended the parse, into a single error.
+    //
+    // Returns `None` if there are no errors.
+    fn take_errors(&mut self, last: Option<ParseError>) -> Option<ParseError> {
+        let mut errors = mem::replace(&mut self.errors, Vec::new());
+        errors.extend(last.map(|err| self.locate(err)));
+        if errors.is_empty() {
+            return None;
+        }
+        let mut first = errors.remove(0);
+        first.related = errors;
+        Some(first)
     }
 
     // Match and consume an Imm64 immediate.
@@ -895,11 +953,12 @@ impl<'a> Parser<'a> {
     ) -> ParseResult<Vec<(Function, Details<'a>)>> {
         let mut list = Vec::new();
         while self.token().is_some() {
-            list.push(self.parse_function(unique_isa)?);
+            let func = self.parse_function(unique_isa);
+            list.push(func.map_err(|err| self.locate(err))?);
         }
         if let Some(err) = self.lex_error {
             return match err {
-                LexError::InvalidChar => err!(self.loc, "invalid character"),
+                LexError::InvalidChar => Err(self.error("invalid character")),
             };
         }
         Ok(list)
@@ -940,7 +999,10 @@ impl<'a> Parser<'a> {
         // function ::= "function" name signature "{" * preamble function-body "}"
         self.parse_preamble(&mut ctx)?;
         // function ::= "function" name signature "{"  preamble * function-body "}"
-        self.parse_function_body(&mut ctx)?;
+        let body = self.parse_function_body(&mut ctx);
+        if let Some(err) = self.take_errors(body.err()) {
+            return Err(err);
+        }
         // function ::= "function" name signature "{" preamble function-body * "}"
         self.match_token(Token::RBrace, "expected '}' after function body")?;
 
@@ -1004,7 +1066,7 @@ impl<'a> Parser<'a> {
         let mut sig = Signature::new(CallConv::Fast);
 
         self.match_token(Token::LPar, "expected function signature: ( args... )")?;
-        // signature ::=  "(" * [abi-param-list] ")" ["->" retlist] [callconv]
+        // signature ::=  "(" * [abi-param-list] ")" ["->" retlist] [callconv] ["varargs"]
         if self.token() != Some(Token::RPar) {
             sig.params = self.parse_abi_param_list(unique_isa)?;
         }
@@ -1015,15 +1077,22 @@ impl<'a> Parser<'a> {
 
         // The calling convention is optional.
         if let Some(Token::Identifier(text)) = self.token() {
-            match text.parse() {
-                Ok(cc) => {
-                    self.consume();
-                    sig.call_conv = cc;
+            if text != "varargs" {
+                match text.parse() {
+                    Ok(cc) => {
+                        self.consume();
+                        sig.call_conv = cc;
+                    }
+                    _ => return err!(self.loc, "unknown calling convention: {}", text),
                 }
-                _ => return err!(self.loc, "unknown calling convention: {}", text),
             }
         }
 
+        if self.token() == Some(Token::Identifier("varargs")) {
+            self.consume();
+            sig.variadic = true;
+        }
+
         Ok(sig)
     }
 
@@ -1059,6 +1128,15 @@ impl<'a> Parser<'a> {
             match s {
                 "uext" => arg.extension = ArgumentExtension::Uext,
                 "sext" => arg.extension = ArgumentExtension::Sext,
+                "sarg" => {
+                    // flag ::= "sarg" * "(" uimm32 ")"
+                    self.consume();
+                    self.match_token(Token::LPar, "expected '(' after sarg")?;
+                    let size = self.match_uimm32("expected struct argument size")?;
+                    self.match_token(Token::RPar, "expected ')' after struct argument size")?;
+                    arg.purpose = ArgumentPurpose::StructArgument(size.into());
+                    continue;
+                }
                 _ => {
                     if let Ok(purpose) = s.parse() {
                         arg.purpose = purpose;
@@ -1217,7 +1295,7 @@ impl<'a> Parser<'a> {
     // global-val-desc ::= "vmctx"
     //                   | "load" "." type "notrap" "aligned" GlobalValue(base) [offset]
     //                   | "iadd_imm" "(" GlobalValue(base) ")" imm64
-    //                   | "symbol" ["colocated"] name + imm64
+    //                   | "symbol" ["colocated"] ["tls"] name + imm64
     //
     fn parse_global_value_decl(&mut self) -> ParseResult<(GlobalValue, GlobalValueData)> {
         let gv = self.match_gv("expected global value number: gv«n»")?;
@@ -1266,12 +1344,14 @@ impl<'a> Parser<'a> {
             }
             "symbol" => {
                 let colocated = self.optional(Token::Identifier("colocated"));
+                let tls = self.optional(Token::Identifier("tls"));
                 let name = self.parse_external_name()?;
                 let offset = self.optional_offset_imm64()?;
                 GlobalValueData::Symbol {
                     name,
                     offset,
                     colocated,
+                    tls,
                 }
             }
             other => return err!(self.loc, "Unknown global value kind '{}'", other),
@@ -1294,6 +1374,7 @@ impl<'a> Parser<'a> {
     //             | "bound" Imm64(bytes)
     //             | "offset_guard" Imm64(bytes)
     //             | "index_type" type
+    //             | "shared"
     //
     fn parse_heap_decl(&mut self) -> ParseResult<(Heap, HeapData)> {
         let heap = self.match_heap("expected heap number: heap«n»")?;
@@ -1318,6 +1399,7 @@ impl<'a> Parser<'a> {
             offset_guard_size: 0.into(),
             style: HeapStyle::Static { bound: 0.into() },
             index_type: ir::types::I32,
+            shared: false,
         };
 
         // heap-desc ::= heap-style heap-base * { "," heap-attr }
@@ -1344,6 +1426,9 @@ impl<'a> Parser<'a> {
                 "index_type" => {
                     data.index_type = self.match_type("expected index type")?;
                 }
+                "shared" => {
+                    data.shared = true;
+                }
                 t => return err!(self.loc, "unknown heap attribute '{}'", t),
             }
         }
@@ -1551,9 +1636,22 @@ impl<'a> Parser<'a> {
     //
     // function-body ::= * { extended-basic-block }
     //
+    //
+    // Errors in instructions and EBB headers are recovered from, so all of them can be reported.
     fn parse_function_body(&mut self, ctx: &mut Context) -> ParseResult<()> {
         while self.token() != Some(Token::RBrace) {
-            self.parse_extended_basic_block(ctx)?;
+            let line = self.loc.line_number;
+            if let Err(err) = self.parse_extended_basic_block(ctx) {
+                self.recover(err, line)?;
+
+                // Skip the instructions of the broken EBB.
+                while match self.token() {
+                    Some(Token::Ebb(_)) | Some(Token::RBrace) | None => false,
+                    _ => true,
+                } {
+                    self.consume();
+                }
+            }
         }
 
         // Now that we've seen all defined values in the function, ensure that
@@ -1585,7 +1683,7 @@ impl<'a> Parser<'a> {
     // Parse an extended basic block, add contents to `ctx`.
     //
     // extended-basic-block ::= * ebb-header { instruction }
-    // ebb-header           ::= Ebb(ebb) [ebb-params] ":"
+    // ebb-header           ::= Ebb(ebb) [ebb-params] ["cold"] ["osr_entry"] ":"
     //
     fn parse_extended_basic_block(&mut self, ctx: &mut Context) -> ParseResult<()> {
         // Collect comments for the next ebb.
@@ -1594,12 +1692,21 @@ impl<'a> Parser<'a> {
         let ebb_num = self.match_ebb("expected EBB header")?;
         let ebb = ctx.add_ebb(ebb_num, self.loc)?;
 
-        if !self.optional(Token::Colon) {
-            // ebb-header ::= Ebb(ebb) [ * ebb-params ] ":"
+        if self.token() == Some(Token::LPar) {
+            // ebb-header ::= Ebb(ebb) [ * ebb-params ] ["cold"] ["osr_entry"] ":"
             self.parse_ebb_params(ctx, ebb)?;
-            self.match_token(Token::Colon, "expected ':' after EBB parameters")?;
         }
 
+        // ebb-header ::= Ebb(ebb) [ebb-params] [ * "cold"] ["osr_entry"] ":"
+        if self.optional(Token::Identifier("cold")) {
+            ctx.function.layout.set_cold(ebb, true);
+        }
+        // ebb-header ::= Ebb(ebb) [ebb-params] ["cold"] [ * "osr_entry"] ":"
+        if self.optional(Token::Identifier("osr_entry")) {
+            ctx.function.layout.set_osr_entry(ebb, true);
+        }
+        self.match_token(Token::Colon, "expected ':' after EBB header")?;
+
         // Collect any trailing comments.
         self.token();
         self.claim_gathered_comments(ebb);
@@ -1612,35 +1719,47 @@ impl<'a> Parser<'a> {
             | Some(Token::SourceLoc(_)) => true,
             _ => false,
         } {
-            let srcloc = self.optional_srcloc()?;
-            let (encoding, result_locations) = self.parse_instruction_encoding(ctx)?;
+            let line = self.loc.line_number;
+            if let Err(err) = self.parse_ebb_instruction(ctx, ebb) {
+                self.recover(err, line)?;
+            }
+        }
 
-            // We need to parse instruction results here because they are shared
-            // between the parsing of value aliases and the parsing of instructions.
-            //
-            // inst-results ::= Value(v) { "," Value(v) }
-            let results = self.parse_inst_results()?;
+        Ok(())
+    }
 
-            for result in &results {
-                while ctx.function.dfg.num_values() <= result.index() {
-                    ctx.function.dfg.make_invalid_value_for_parser();
-                }
+    // Parse an instruction or a value alias in `ebb`, add it to `ctx`.
+    //
+    // instruction ::= [inst-results "="] Opcode(opc) ["." Type] ...
+    // value-alias ::= inst-results "->" Value(v)
+    //
+    fn parse_ebb_instruction(&mut self, ctx: &mut Context, ebb: Ebb) -> ParseResult<()> {
+        let srcloc = self.optional_srcloc()?;
+        let (encoding, result_locations) = self.parse_instruction_encoding(ctx)?;
+
+        // We need to parse instruction results here because they are shared
+        // between the parsing of value aliases and the parsing of instructions.
+        //
+        // inst-results ::= Value(v) { "," Value(v) }
+        let results = self.parse_inst_results()?;
+
+        for result in &results {
+            while ctx.function.dfg.num_values() <= result.index() {
+                ctx.function.dfg.make_invalid_value_for_parser();
             }
+        }
 
-            match self.token() {
-                Some(Token::Arrow) => {
-                    self.consume();
-                    self.parse_value_alias(&results, ctx)?;
-                }
-                Some(Token::Equal) => {
-                    self.consume();
-                    self.parse_instruction(&results, srcloc, encoding, result_locations, ctx, ebb)?;
-                }
-                _ if !results.is_empty() => return err!(self.loc, "expected -> or ="),
-                _ => {
-                    self.parse_instruction(&results, srcloc, encoding, result_locations, ctx, ebb)?
-                }
+        match self.token() {
+            Some(Token::Arrow) => {
+                self.consume();
+                self.parse_value_alias(&results, ctx)?;
+            }
+            Some(Token::Equal) => {
+                self.consume();
+                self.parse_instruction(&results, srcloc, encoding, result_locations, ctx, ebb)?;
             }
+            _ if !results.is_empty() => return err!(self.loc, "expected -> or ="),
+            _ => self.parse_instruction(&results, srcloc, encoding, result_locations, ctx, ebb)?,
         }
 
         Ok(())
@@ -1892,6 +2011,25 @@ impl<'a> Parser<'a> {
         // instruction ::=  [inst-results "="] Opcode(opc) ["." Type] * ...
         let inst_data = self.parse_inst_operands(ctx, opcode)?;
 
+        // instruction ::=  [inst-results "="] Opcode(opc) ["." Type] ... * ["traps" code] ...
+        let trap_code = if self.optional(Token::Identifier("traps")) {
+            Some(self.match_enum("expected trap code")?)
+        } else {
+            None
+        };
+
+        // instruction ::=  [inst-results "="] Opcode(opc) ["." Type] ... * ["prob" percent]
+        let probability = if self.optional(Token::Identifier("prob")) {
+            let loc = self.loc;
+            let percent = self.match_uimm8("expected branch probability in percent")?;
+            if percent > 100 {
+                return err!(loc, "branch probability can't be more than 100%");
+            }
+            Some(ir::BranchProbability::from_percent(percent))
+        } else {
+            None
+        };
+
         // We're done parsing the instruction now.
         //
         // We still need to check that the number of result values in the source matches the opcode
@@ -1912,6 +2050,14 @@ impl<'a> Parser<'a> {
             ctx.function.srclocs[inst] = srcloc;
         }
 
+        if trap_code.is_some() {
+            ctx.function.memory_trap_codes[inst] = trap_code;
+        }
+
+        if let Some(probability) = probability {
+            ctx.function.branch_probabilities[inst] = probability;
+        }
+
         if let Some(encoding) = encoding {
             ctx.function.encodings[inst] = encoding;
         }
@@ -2360,6 +2506,21 @@ impl<'a> Parser<'a> {
                     args: args.into_value_list(&[], &mut ctx.function.dfg.value_lists),
                 }
             }
+            InstructionFormat::TryCall => {
+                let func_ref = self.match_fn("expected function reference")?;
+                ctx.check_fn(func_ref, self.loc)?;
+                self.match_token(Token::LPar, "expected '(' before arguments")?;
+                let args = self.parse_value_list()?;
+                self.match_token(Token::RPar, "expected ')' after arguments")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let ebb_num = self.match_ebb("expected landing pad EBB")?;
+                InstructionData::TryCall {
+                    opcode,
+                    func_ref,
+                    destination: ebb_num,
+                    args: args.into_value_list(&[], &mut ctx.function.dfg.value_lists),
+                }
+            }
             InstructionFormat::CallIndirect => {
                 let sig_ref = self.match_sig("expected signature reference")?;
                 ctx.check_sig(sig_ref, self.loc)?;
@@ -2479,6 +2640,86 @@ impl<'a> Parser<'a> {
                     offset,
                 }
             }
+            InstructionFormat::LoadScaled => {
+                let flags = self.optional_memflags();
+                let base = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let index = self.match_value("expected SSA value index")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let imm = self.match_uimm8("expected scale")?;
+                let offset = self.optional_offset32()?;
+                InstructionData::LoadScaled {
+                    opcode,
+                    flags,
+                    args: [base, index],
+                    imm,
+                    offset,
+                }
+            }
+            InstructionFormat::StoreScaled => {
+                let flags = self.optional_memflags();
+                let src = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let base = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let index = self.match_value("expected SSA value index")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let imm = self.match_uimm8("expected scale")?;
+                let offset = self.optional_offset32()?;
+                InstructionData::StoreScaled {
+                    opcode,
+                    flags,
+                    args: [src, base, index],
+                    imm,
+                    offset,
+                }
+            }
+            InstructionFormat::LoadNoOffset => {
+                let flags = self.optional_memflags();
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::LoadNoOffset {
+                    opcode,
+                    flags,
+                    arg: addr,
+                }
+            }
+            InstructionFormat::StoreNoOffset => {
+                let flags = self.optional_memflags();
+                let arg = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let addr = self.match_value("expected SSA value address")?;
+                InstructionData::StoreNoOffset {
+                    opcode,
+                    flags,
+                    args: [arg, addr],
+                }
+            }
+            InstructionFormat::AtomicRmw => {
+                let flags = self.optional_memflags();
+                let op = self.match_enum("expected atomic rmw operation")?;
+                let addr = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let arg = self.match_value("expected SSA value operand")?;
+                InstructionData::AtomicRmw {
+                    opcode,
+                    flags,
+                    op,
+                    args: [addr, arg],
+                }
+            }
+            InstructionFormat::AtomicCas => {
+                let flags = self.optional_memflags();
+                let addr = self.match_value("expected SSA value address")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let expected = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let replacement = self.match_value("expected SSA value operand")?;
+                InstructionData::AtomicCas {
+                    opcode,
+                    flags,
+                    args: [addr, expected, replacement],
+                }
+            }
             InstructionFormat::RegMove => {
                 let arg = self.match_value("expected SSA value operand")?;
                 self.match_token(Token::Comma, "expected ',' between operands")?;
@@ -2492,6 +2733,21 @@ impl<'a> Parser<'a> {
                     dst,
                 }
             }
+            InstructionFormat::RegSwap => {
+                let x = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let y = self.match_value("expected SSA value operand")?;
+                self.match_token(Token::Comma, "expected ',' between operands")?;
+                let src = self.match_regunit(ctx.unique_isa)?;
+                self.match_token(Token::Arrow, "expected '->' between register units")?;
+                let dst = self.match_regunit(ctx.unique_isa)?;
+                InstructionData::RegSwap {
+                    opcode,
+                    args: [x, y],
+                    src,
+                    dst,
+                }
+            }
             InstructionFormat::CopySpecial => {
                 let src = self.match_regunit(ctx.unique_isa)?;
                 self.match_token(Token::Arrow, "expected '->' between register units")?;
@@ -2591,10 +2847,16 @@ mod tests {
             location,
             message,
             is_warning,
+            ..
         } = p.parse_abi_param(None).unwrap_err();
         assert_eq!(location.line_number, 1);
         assert_eq!(message, "expected parameter type");
         assert!(!is_warning);
+
+        let mut p = Parser::new("i64 sarg(24) [16]");
+        let arg = p.parse_abi_param(None).unwrap();
+        assert_eq!(arg.purpose, ArgumentPurpose::StructArgument(24));
+        assert_eq!(arg.location, ArgumentLoc::Stack(16));
     }
 
     #[test]
@@ -2639,6 +2901,18 @@ mod tests {
         );
         assert_eq!(sig2.call_conv, CallConv::Baldrdash);
 
+        let sig3 = Parser::new("(i64, f64) -> i32 system_v varargs")
+            .parse_signature(None)
+            .unwrap();
+        assert!(sig3.variadic);
+        assert_eq!(sig3.to_string(), "(i64, f64) -> i32 system_v varargs");
+        assert!(
+            Parser::new("() varargs")
+                .parse_signature(None)
+                .unwrap()
+                .variadic
+        );
+
         // Old-style signature without a calling convention.
         assert_eq!(
             Parser::new("()").parse_signature(None).unwrap().to_string(),
@@ -2721,6 +2995,10 @@ mod tests {
             "function %ebbs() system_v {
                                      ebb0:
                                      ebb4(v3: i32):
+                                     ebb5 cold:
+                                     ebb6(v4: i64) cold:
+                                     ebb7(v5: i32) osr_entry:
+                                     ebb8 cold osr_entry:
                                      }",
         )
         .parse_function(None)
@@ -2731,11 +3009,66 @@ mod tests {
 
         let ebb0 = ebbs.next().unwrap();
         assert_eq!(func.dfg.ebb_params(ebb0), &[]);
+        assert!(!func.layout.is_cold(ebb0));
 
         let ebb4 = ebbs.next().unwrap();
         let ebb4_args = func.dfg.ebb_params(ebb4);
         assert_eq!(ebb4_args.len(), 1);
         assert_eq!(func.dfg.value_type(ebb4_args[0]), types::I32);
+        assert!(!func.layout.is_cold(ebb4));
+
+        let ebb5 = ebbs.next().unwrap();
+        assert_eq!(func.dfg.ebb_params(ebb5), &[]);
+        assert!(func.layout.is_cold(ebb5));
+
+        let ebb6 = ebbs.next().unwrap();
+        assert_eq!(func.dfg.ebb_params(ebb6).len(), 1);
+        assert!(func.layout.is_cold(ebb6));
+        assert!(!func.layout.is_osr_entry(ebb6));
+
+        let ebb7 = ebbs.next().unwrap();
+        assert!(!func.layout.is_cold(ebb7));
+        assert!(func.layout.is_osr_entry(ebb7));
+
+        let ebb8 = ebbs.next().unwrap();
+        assert!(func.layout.is_cold(ebb8));
+        assert!(func.layout.is_osr_entry(ebb8));
+    }
+
+    #[test]
+    fn recover_errors() {
+        let err = Parser::new(
+            "function %recover() system_v {
+                ebb0(v0: i32):
+                    v1 = iadd v0, 5
+                    v2 = iconst.i32 7
+                    v3 = bogus v2
+                ebb1(v4: i32
+                    return
+                ebb2:
+                    return
+                }",
+        )
+        .parse_function(None)
+        .unwrap_err();
+
+        let lines: Vec<usize> = err.all().map(|e| e.location.line_number).collect();
+        assert_eq!(lines, [3, 5, 7]);
+        let columns: Vec<usize> = err.all().map(|e| e.column).collect();
+        assert_eq!(columns, [35, 26, 21]);
+        assert!(err.related[0].message.contains("bogus"));
+        assert_eq!(err.related[1].message, "expected ')' after EBB parameters");
+    }
+
+    #[test]
+    fn display_source() {
+        let text = "function %f() system_v {\nebb0:\n    v1 = bogus\n}";
+        let err = Parser::new(text).parse_function(None).unwrap_err();
+        let shown = err.display_source(text).to_string();
+        let lines: Vec<&str> = shown.lines().collect();
+        assert!(lines[0].starts_with("3:10: "));
+        assert_eq!(lines[1], "    v1 = bogus");
+        assert_eq!(lines[2], "         ^");
     }
 
     #[test]
@@ -2744,6 +3077,7 @@ mod tests {
             location,
             message,
             is_warning,
+            ..
         } = Parser::new(
             "function %ebbs() system_v {
                 ebb0:
@@ -2764,6 +3098,7 @@ mod tests {
             location,
             message,
             is_warning,
+            ..
         } = Parser::new(
             "function %ebbs() system_v {
                 jt0 = jump_table []
@@ -2783,6 +3118,7 @@ mod tests {
             location,
             message,
             is_warning,
+            ..
         } = Parser::new(
             "function %ebbs() system_v {
                 ss0 = explicit_slot 8
@@ -2802,6 +3138,7 @@ mod tests {
             location,
             message,
             is_warning,
+            ..
         } = Parser::new(
             "function %ebbs() system_v {
                 gv0 = vmctx
@@ -2821,6 +3158,7 @@ mod tests {
             location,
             message,
             is_warning,
+            ..
         } = Parser::new(
             "function %ebbs() system_v {
                 heap0 = static gv0, min 0x1000, bound 0x10_0000, offset_guard 0x1000
@@ -2840,6 +3178,7 @@ mod tests {
             location,
             message,
             is_warning,
+            ..
         } = Parser::new(
             "function %ebbs() system_v {
                 sig0 = ()
@@ -2859,6 +3198,7 @@ mod tests {
             location,
             message,
             is_warning,
+            ..
         } = Parser::new(
             "function %ebbs() system_v {
                 sig0 = ()
@@ -148,6 +148,12 @@ pub enum SerInstData {
         args: Vec<String>,
         sig_ref: String,
     },
+    TryCall {
+        opcode: String,
+        args: Vec<String>,
+        func_ref: String,
+        destination: String,
+    },
     FuncAddr {
         opcode: String,
         func_ref: String,
@@ -176,6 +182,41 @@ pub enum SerInstData {
         flags: String,
         offset: String,
     },
+    LoadScaled {
+        opcode: String,
+        args: [String; 2],
+        flags: String,
+        imm: String,
+        offset: String,
+    },
+    StoreScaled {
+        opcode: String,
+        args: [String; 3],
+        flags: String,
+        imm: String,
+        offset: String,
+    },
+    LoadNoOffset {
+        opcode: String,
+        arg: String,
+        flags: String,
+    },
+    StoreNoOffset {
+        opcode: String,
+        args: [String; 2],
+        flags: String,
+    },
+    AtomicRmw {
+        opcode: String,
+        args: [String; 2],
+        flags: String,
+        op: String,
+    },
+    AtomicCas {
+        opcode: String,
+        args: [String; 3],
+        flags: String,
+    },
     StackLoad {
         opcode: String,
         stack_slot: String,
@@ -205,6 +246,12 @@ pub enum SerInstData {
         src: String,
         dst: String,
     },
+    RegSwap {
+        opcode: String,
+        args: [String; 2],
+        src: String,
+        dst: String,
+    },
     CopySpecial {
         opcode: String,
         src: String,
@@ -528,6 +575,24 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
                 sig_ref: sig_ref.to_string(),
             }
         }
+        InstructionData::TryCall {
+            opcode,
+            ref args,
+            func_ref,
+            destination,
+        } => {
+            let mut hold_args = Vec::new();
+            let args_iter = args.as_slice(&func.dfg.value_lists);
+            for arg in args_iter {
+                hold_args.push(arg.to_string());
+            }
+            SerInstData::TryCall {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                func_ref: func_ref.to_string(),
+                destination: destination.to_string(),
+            }
+        }
         InstructionData::FuncAddr { opcode, func_ref } => SerInstData::FuncAddr {
             opcode: opcode.to_string(),
             func_ref: func_ref.to_string(),
@@ -593,6 +658,89 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
                 offset: offset.to_string(),
             }
         }
+        InstructionData::LoadScaled {
+            opcode,
+            args,
+            flags,
+            imm,
+            offset,
+        } => {
+            let hold_args = [args[0].to_string(), args[1].to_string()];
+            SerInstData::LoadScaled {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                flags: flags.to_string(),
+                imm: imm.to_string(),
+                offset: offset.to_string(),
+            }
+        }
+        InstructionData::StoreScaled {
+            opcode,
+            args,
+            flags,
+            imm,
+            offset,
+        } => {
+            let hold_args = [
+                args[0].to_string(),
+                args[1].to_string(),
+                args[2].to_string(),
+            ];
+            SerInstData::StoreScaled {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                flags: flags.to_string(),
+                imm: imm.to_string(),
+                offset: offset.to_string(),
+            }
+        }
+        InstructionData::LoadNoOffset { opcode, arg, flags } => SerInstData::LoadNoOffset {
+            opcode: opcode.to_string(),
+            arg: arg.to_string(),
+            flags: flags.to_string(),
+        },
+        InstructionData::StoreNoOffset {
+            opcode,
+            args,
+            flags,
+        } => {
+            let hold_args = [args[0].to_string(), args[1].to_string()];
+            SerInstData::StoreNoOffset {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                flags: flags.to_string(),
+            }
+        }
+        InstructionData::AtomicRmw {
+            opcode,
+            args,
+            flags,
+            op,
+        } => {
+            let hold_args = [args[0].to_string(), args[1].to_string()];
+            SerInstData::AtomicRmw {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                flags: flags.to_string(),
+                op: op.to_string(),
+            }
+        }
+        InstructionData::AtomicCas {
+            opcode,
+            args,
+            flags,
+        } => {
+            let hold_args = [
+                args[0].to_string(),
+                args[1].to_string(),
+                args[2].to_string(),
+            ];
+            SerInstData::AtomicCas {
+                opcode: opcode.to_string(),
+                args: hold_args,
+                flags: flags.to_string(),
+            }
+        }
         InstructionData::StackLoad {
             opcode,
             stack_slot,
@@ -646,6 +794,17 @@ pub fn get_inst_data(inst_index: Inst, func: &Function) -> SerInstData {
             src: src.to_string(),
             dst: dst.to_string(),
         },
+        InstructionData::RegSwap {
+            opcode,
+            args,
+            src,
+            dst,
+        } => SerInstData::RegSwap {
+            opcode: opcode.to_string(),
+            args: [args[0].to_string(), args[1].to_string()],
+            src: src.to_string(),
+            dst: dst.to_string(),
+        },
         InstructionData::CopySpecial { opcode, src, dst } => SerInstData::CopySpecial {
             opcode: opcode.to_string(),
             src: src.to_string(),
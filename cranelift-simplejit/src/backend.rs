@@ -1,19 +1,23 @@
 //! Defines `SimpleJITBackend`.
 
+use crate::gdb::GdbJitImage;
 use crate::memory::Memory;
+use crate::perf::{PerfFormat, PerfWriter};
 use cranelift_codegen::binemit::{Addend, CodeOffset, NullTrapSink, Reloc, RelocSink};
 use cranelift_codegen::isa::TargetIsa;
 use cranelift_codegen::{self, ir, settings};
 use cranelift_module::{
-    Backend, DataContext, DataDescription, Init, Linkage, ModuleNamespace, ModuleResult,
+    line_table, Backend, DataContext, DataDescription, FunctionDebugInfo, Init, Linkage,
+    ModuleError, ModuleNamespace, ModuleResult,
 };
 use cranelift_native;
 use libc;
+use region;
 use std::collections::HashMap;
 use std::ffi::CString;
-use std::io::Write;
 use std::ptr;
-use target_lexicon::PointerWidth;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use target_lexicon::{Architecture, PointerWidth};
 #[cfg(windows)]
 use winapi;
 
@@ -21,6 +25,9 @@ use winapi;
 pub struct SimpleJITBuilder {
     isa: Box<TargetIsa>,
     symbols: HashMap<String, *const u8>,
+    debug_source: Option<String>,
+    perf_format: Option<PerfFormat>,
+    hotswap: bool,
 }
 
 impl SimpleJITBuilder {
@@ -44,7 +51,20 @@ impl SimpleJITBuilder {
     pub fn with_isa(isa: Box<TargetIsa>) -> Self {
         debug_assert!(!isa.flags().is_pic(), "SimpleJIT requires non-PIC code");
         let symbols = HashMap::new();
-        Self { isa, symbols }
+        // Keep writing a map file when running under `perf`, as before the option existed.
+        let perf_format =
+            if cfg!(target_os = "linux") && ::std::env::var_os("PERF_BUILDID_DIR").is_some() {
+                Some(PerfFormat::Map)
+            } else {
+                None
+            };
+        Self {
+            isa,
+            symbols,
+            debug_source: None,
+            perf_format,
+            hotswap: false,
+        }
     }
 
     /// Define a symbol in the internal symbol table.
@@ -82,6 +102,43 @@ impl SimpleJITBuilder {
         }
         self
     }
+
+    /// Register the compiled functions with debuggers.
+    ///
+    /// Each function is described to gdb and lldb through the GDB JIT interface, so they can show
+    /// its name and source lines. The source locations of the instructions are used as line
+    /// numbers in the file `source_name`.
+    ///
+    /// This is only supported on 64-bit x86, ARM and RISC-V hosts.
+    pub fn debug_info<K>(&mut self, source_name: K) -> &Self
+    where
+        K: Into<String>,
+    {
+        self.debug_source = Some(source_name.into());
+        self
+    }
+
+    /// Describe the finalized functions to the Linux `perf` profiler in the given format.
+    ///
+    /// By default, a map file is written when the `PERF_BUILDID_DIR` environment variable is set,
+    /// which is the case when running under `perf`. Use `None` to disable it.
+    pub fn perf(&mut self, format: Option<PerfFormat>) -> &Self {
+        self.perf_format = format;
+        self
+    }
+
+    /// Call functions through stubs, so they can be redefined with `Module::redefine_function`.
+    ///
+    /// Each function gets a stub which jumps to the address of its current definition, loaded
+    /// from a table. Calls from compiled code and the addresses returned by
+    /// `Module::get_finalized_function` go through the stub, and redefining a function atomically
+    /// updates its table entry when the new definition is published.
+    ///
+    /// This is only supported on 64-bit x86 hosts.
+    pub fn hotswap(&mut self, enabled: bool) -> &Self {
+        self.hotswap = enabled;
+        self
+    }
 }
 
 /// A `SimpleJITBackend` implements `Backend` and emits code and data into memory where it can be
@@ -91,6 +148,11 @@ impl SimpleJITBuilder {
 pub struct SimpleJITBackend {
     isa: Box<TargetIsa>,
     symbols: HashMap<String, *const u8>,
+    debug_source: Option<String>,
+    perf: Option<PerfWriter>,
+    hotswap: bool,
+    stubs: HashMap<String, Stub>,
+    pending_entries: Vec<(Stub, *mut u8)>,
     code_memory: Memory,
     readonly_memory: Memory,
     writable_memory: Memory,
@@ -104,10 +166,33 @@ struct RelocRecord {
     addend: Addend,
 }
 
+/// A stub jumping to the current definition of a function, whose address is in `entry`.
+#[derive(Clone, Copy)]
+struct Stub {
+    code: *mut u8,
+    entry: *mut u8,
+}
+
+/// The size of a stub: `movabs r11, entry; jmp [r11]`.
+const STUB_SIZE: usize = 13;
+
 pub struct SimpleJITCompiledFunction {
+    name: String,
     code: *mut u8,
     size: usize,
     relocs: Vec<RelocRecord>,
+    stub: Option<Stub>,
+    _debug_image: Option<GdbJitImage>,
+}
+
+impl SimpleJITCompiledFunction {
+    /// Get the address used to call this function.
+    fn address(&self) -> *mut u8 {
+        match self.stub {
+            Some(stub) => stub.code,
+            None => self.code,
+        }
+    }
 }
 
 pub struct SimpleJITCompiledData {
@@ -123,6 +208,36 @@ impl SimpleJITBackend {
             None => lookup_with_dlsym(name),
         }
     }
+
+    /// Get the stub for the function `name`, creating it if needed.
+    fn get_stub(&mut self, name: &str) -> ModuleResult<Stub> {
+        if let Some(&stub) = self.stubs.get(name) {
+            return Ok(stub);
+        }
+        if self.isa.triple().architecture != Architecture::X86_64 {
+            return Err(ModuleError::Backend(
+                "function stubs are only supported on x86_64".to_string(),
+            ));
+        }
+
+        let entry = self
+            .writable_memory
+            .allocate(8, 8)
+            .map_err(ModuleError::Backend)?;
+        let code = self
+            .code_memory
+            .allocate(STUB_SIZE, 1 << self.isa.flags().function_alignment_log2())
+            .map_err(ModuleError::Backend)?;
+        let mut bytes = [0; STUB_SIZE];
+        bytes[0..2].copy_from_slice(&[0x49, 0xbb]);
+        bytes[2..10].copy_from_slice(&(entry as u64).to_le_bytes());
+        bytes[10..13].copy_from_slice(&[0x41, 0xff, 0x23]);
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), code, STUB_SIZE) };
+
+        let stub = Stub { code, entry };
+        self.stubs.insert(name.to_string(), stub);
+        Ok(stub)
+    }
 }
 
 impl<'simple_jit_backend> Backend for SimpleJITBackend {
@@ -146,12 +261,20 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
 
     /// Create a new `SimpleJITBackend`.
     fn new(builder: SimpleJITBuilder) -> Self {
+        let perf = builder.perf_format.map(|format| {
+            PerfWriter::new(format, builder.isa.triple()).expect("unable to create the perf file")
+        });
         Self {
             isa: builder.isa,
             symbols: builder.symbols,
-            code_memory: Memory::new(),
-            readonly_memory: Memory::new(),
-            writable_memory: Memory::new(),
+            debug_source: builder.debug_source,
+            perf,
+            hotswap: builder.hotswap,
+            stubs: HashMap::new(),
+            pending_entries: Vec::new(),
+            code_memory: Memory::new(region::Protection::ReadExecute),
+            readonly_memory: Memory::new(region::Protection::Read),
+            writable_memory: Memory::new(region::Protection::ReadWrite),
         }
     }
 
@@ -174,32 +297,45 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         _namespace: &ModuleNamespace<Self>,
         code_size: u32,
     ) -> ModuleResult<Self::CompiledFunction> {
+        let stub = if self.hotswap {
+            Some(self.get_stub(name)?)
+        } else {
+            None
+        };
+
         let size = code_size as usize;
+        let align = 1 << self.isa.flags().function_alignment_log2();
         let ptr = self
             .code_memory
-            .allocate(size)
+            .allocate(size, align)
             .expect("TODO: handle OOM etc.");
 
-        if cfg!(target_os = "linux") && ::std::env::var_os("PERF_BUILDID_DIR").is_some() {
-            let mut map_file = ::std::fs::OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(format!("/tmp/perf-{}.map", ::std::process::id()))
-                .unwrap();
-
-            let _ = writeln!(map_file, "{:x} {:x} {}", ptr as usize, code_size, name);
-        }
-
         let mut reloc_sink = SimpleJITRelocSink::new();
         // Ignore traps for now. For now, frontends should just avoid generating code
         // that traps.
         let mut trap_sink = NullTrapSink {};
         unsafe { ctx.emit_to_memory(&*self.isa, ptr, &mut reloc_sink, &mut trap_sink) };
 
+        let debug_image = match self.debug_source {
+            Some(ref source_name) => {
+                let info = FunctionDebugInfo {
+                    name: name.to_string(),
+                    address: ptr as u64,
+                    size: code_size,
+                    lines: line_table(&ctx.func, &*self.isa),
+                };
+                GdbJitImage::register(info, source_name, self.isa.triple())
+            }
+            None => None,
+        };
+
         Ok(Self::CompiledFunction {
+            name: name.to_string(),
             code: ptr,
             size,
             relocs: reloc_sink.relocs,
+            stub,
+            _debug_image: debug_image,
         })
     }
 
@@ -221,11 +357,11 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
         let size = init.size();
         let storage = if writable {
             self.writable_memory
-                .allocate(size)
+                .allocate(size, 1)
                 .expect("TODO: handle OOM etc.")
         } else {
             self.readonly_memory
-                .allocate(size)
+                .allocate(size, 1)
                 .expect("TODO: handle OOM etc.")
         };
 
@@ -311,7 +447,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             let base = if namespace.is_function(name) {
                 let (def, name_str, _signature) = namespace.get_function_definition(&name);
                 match def {
-                    Some(compiled) => compiled.code,
+                    Some(compiled) => compiled.address(),
                     None => self.lookup_symbol(name_str),
                 }
             } else {
@@ -346,14 +482,26 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
                     };
                 }
                 Reloc::X86GOTPCRel4 | Reloc::X86CallPLTRel4 => panic!("unexpected PIC relocation"),
+                Reloc::ElfX86_64TlsGd | Reloc::ElfX86_64GotTpOff => {
+                    panic!("thread-local storage is not supported")
+                }
                 _ => unimplemented!(),
             }
         }
-        func.code
+
+        if let Some(ref mut perf) = self.perf {
+            let _ = perf.write_function(&func.name, func.code, func.size);
+        }
+
+        if let Some(stub) = func.stub {
+            self.pending_entries.push((stub, func.code));
+        }
+
+        func.address()
     }
 
     fn get_finalized_function(&self, func: &Self::CompiledFunction) -> Self::FinalizedFunction {
-        func.code
+        func.address()
     }
 
     fn finalize_data(
@@ -376,7 +524,7 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
             let base = if namespace.is_function(name) {
                 let (def, name_str, _signature) = namespace.get_function_definition(&name);
                 match def {
-                    Some(compiled) => compiled.code,
+                    Some(compiled) => compiled.address(),
                     None => self.lookup_symbol(name_str),
                 }
             } else {
@@ -418,8 +566,34 @@ impl<'simple_jit_backend> Backend for SimpleJITBackend {
 
     fn publish(&mut self) {
         // Now that we're done patching, prepare the memory for execution!
-        self.readonly_memory.set_readonly();
-        self.code_memory.set_readable_and_executable();
+        self.readonly_memory.finalize();
+        self.code_memory.finalize();
+
+        // Redirect the stubs to the new definitions, now that they can be executed.
+        for (stub, code) in self.pending_entries.drain(..) {
+            #[cfg_attr(feature = "cargo-clippy", allow(clippy::cast_ptr_alignment))]
+            let entry = unsafe { &*(stub.entry as *const AtomicUsize) };
+            entry.store(code as usize, Ordering::Release);
+        }
+    }
+
+    fn undefine_function(&mut self, name: &str, func: &Self::CompiledFunction) -> ModuleResult<()> {
+        if func.stub.is_none() {
+            return Err(ModuleError::Backend(format!(
+                "can't redefine function {} without SimpleJITBuilder::hotswap",
+                name
+            )));
+        }
+        // The old definition may still be running, so keep its code around.
+        self.pending_entries.retain(|&(_, code)| code != func.code);
+        Ok(())
+    }
+
+    fn free_function(&mut self, _name: &str, func: &Self::CompiledFunction) -> ModuleResult<()> {
+        // Don't redirect a stub to the freed code.
+        self.pending_entries.retain(|&(_, code)| code != func.code);
+        self.code_memory.free(func.code);
+        Ok(())
     }
 
     /// SimpleJIT emits code and data into memory as it processes them, so it
@@ -0,0 +1,337 @@
+//! Registration of JIT-compiled functions with debuggers.
+//!
+//! gdb and lldb discover code generated at run time through the GDB JIT interface: the JIT links
+//! an in-memory object file describing the code into the list headed by `__jit_debug_descriptor`,
+//! and calls `__jit_debug_register_code`, on which the debugger has set a breakpoint.
+//!
+//! See <https://sourceware.org/gdb/onlinedocs/gdb/JIT-Interface.html>.
+
+use cranelift_module::{write_debug_sections, FunctionDebugInfo};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use target_lexicon::{Architecture, Triple};
+
+const JIT_NOACTION: u32 = 0;
+const JIT_REGISTER_FN: u32 = 1;
+const JIT_UNREGISTER_FN: u32 = 2;
+
+#[repr(C)]
+struct JitCodeEntry {
+    next_entry: *mut JitCodeEntry,
+    prev_entry: *mut JitCodeEntry,
+    symfile_addr: *const u8,
+    symfile_size: u64,
+}
+
+/// The list of object files registered with the debugger.
+#[repr(C)]
+pub struct JitDescriptor {
+    version: u32,
+    action_flag: u32,
+    relevant_entry: *mut JitCodeEntry,
+    first_entry: *mut JitCodeEntry,
+}
+
+/// The descriptor read by the debugger.
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+pub static mut __jit_debug_descriptor: JitDescriptor = JitDescriptor {
+    version: 1,
+    action_flag: JIT_NOACTION,
+    relevant_entry: ptr::null_mut(),
+    first_entry: ptr::null_mut(),
+};
+
+/// The function called after changing the descriptor, where the debugger sets a breakpoint.
+#[no_mangle]
+#[inline(never)]
+pub extern "C" fn __jit_debug_register_code() {
+    // Keep the calls from being optimized away.
+    unsafe {
+        ptr::read_volatile(&__jit_debug_descriptor.action_flag);
+    }
+}
+
+/// Lock protecting `__jit_debug_descriptor`.
+static DESCRIPTOR_LOCK: AtomicBool = AtomicBool::new(false);
+
+/// Update the descriptor with `action` for `entry` and notify the debugger.
+fn notify(action: u32, entry: *mut JitCodeEntry, update: impl FnOnce(&mut JitDescriptor)) {
+    while DESCRIPTOR_LOCK.compare_and_swap(false, true, Ordering::Acquire) {}
+    unsafe {
+        update(&mut __jit_debug_descriptor);
+        __jit_debug_descriptor.relevant_entry = entry;
+        __jit_debug_descriptor.action_flag = action;
+        __jit_debug_register_code();
+        __jit_debug_descriptor.action_flag = JIT_NOACTION;
+        __jit_debug_descriptor.relevant_entry = ptr::null_mut();
+    }
+    DESCRIPTOR_LOCK.store(false, Ordering::Release);
+}
+
+/// An object file registered with the debugger. It is unregistered when dropped.
+pub struct GdbJitImage {
+    entry: Box<JitCodeEntry>,
+    // The object file is referenced by `entry`.
+    _image: Vec<u8>,
+}
+
+impl GdbJitImage {
+    /// Describe `func` to the debugger, using the source file name `source_name` for its lines.
+    ///
+    /// Returns `None` if the host isn't supported.
+    pub fn register(func: FunctionDebugInfo, source_name: &str, triple: &Triple) -> Option<Self> {
+        let image = write_elf(func, source_name, triple)?;
+        let mut entry = Box::new(JitCodeEntry {
+            next_entry: ptr::null_mut(),
+            prev_entry: ptr::null_mut(),
+            symfile_addr: image.as_ptr(),
+            symfile_size: image.len() as u64,
+        });
+        let entry_ptr: *mut JitCodeEntry = &mut *entry;
+        notify(JIT_REGISTER_FN, entry_ptr, |descriptor| unsafe {
+            (*entry_ptr).next_entry = descriptor.first_entry;
+            if !descriptor.first_entry.is_null() {
+                (*descriptor.first_entry).prev_entry = entry_ptr;
+            }
+            descriptor.first_entry = entry_ptr;
+        });
+        Some(Self {
+            entry,
+            _image: image,
+        })
+    }
+}
+
+impl Drop for GdbJitImage {
+    fn drop(&mut self) {
+        let entry_ptr: *mut JitCodeEntry = &mut *self.entry;
+        notify(JIT_UNREGISTER_FN, entry_ptr, |descriptor| unsafe {
+            let next = (*entry_ptr).next_entry;
+            let prev = (*entry_ptr).prev_entry;
+            if prev.is_null() {
+                descriptor.first_entry = next;
+            } else {
+                (*prev).next_entry = next;
+            }
+            if !next.is_null() {
+                (*next).prev_entry = prev;
+            }
+        });
+    }
+}
+
+// ELF constants.
+const ET_REL: u16 = 1;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const STB_GLOBAL: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+/// Sizes of the ELF64 structures.
+const EHDR_SIZE: usize = 64;
+const SHDR_SIZE: usize = 64;
+const SYM_SIZE: usize = 24;
+
+/// Section header fields.
+struct Section {
+    name: &'static str,
+    kind: u32,
+    flags: u64,
+    addr: u64,
+    data: Vec<u8>,
+    size: u64,
+    link: u32,
+    info: u32,
+    entsize: u64,
+}
+
+impl Section {
+    fn new(name: &'static str, kind: u32, data: Vec<u8>) -> Self {
+        Self {
+            name,
+            kind,
+            flags: 0,
+            addr: 0,
+            size: data.len() as u64,
+            data,
+            link: 0,
+            info: 0,
+            entsize: 0,
+        }
+    }
+}
+
+/// Get the ELF machine number of the 64-bit architecture of `triple`.
+pub fn elf_machine(triple: &Triple) -> Option<u16> {
+    match triple.architecture {
+        Architecture::X86_64 => Some(EM_X86_64),
+        Architecture::Aarch64 => Some(EM_AARCH64),
+        Architecture::Riscv64 => Some(EM_RISCV),
+        _ => None,
+    }
+}
+
+/// Write a relocatable ELF64 object file describing `func`.
+///
+/// The code itself isn't part of the object file. The `.text` section occupies no space in the
+/// file, and its address is the address of the compiled code.
+fn write_elf(func: FunctionDebugInfo, source_name: &str, triple: &Triple) -> Option<Vec<u8>> {
+    let machine = elf_machine(triple)?;
+
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; SYM_SIZE];
+    symtab.extend_from_slice(&(strtab.len() as u32).to_le_bytes());
+    symtab.push(STB_GLOBAL << 4 | STT_FUNC);
+    symtab.push(0);
+    symtab.extend_from_slice(&1u16.to_le_bytes());
+    symtab.extend_from_slice(&0u64.to_le_bytes());
+    symtab.extend_from_slice(&u64::from(func.size).to_le_bytes());
+    strtab.extend_from_slice(func.name.as_bytes());
+    strtab.push(0);
+
+    let mut text = Section::new(".text", SHT_NOBITS, Vec::new());
+    text.flags = SHF_ALLOC | SHF_EXECINSTR;
+    text.addr = func.address;
+    text.size = u64::from(func.size);
+    let mut symtab = Section::new(".symtab", SHT_SYMTAB, symtab);
+    symtab.link = 4;
+    symtab.info = 1;
+    symtab.entsize = SYM_SIZE as u64;
+    let strtab = Section::new(".strtab", SHT_STRTAB, strtab);
+    let debug = write_debug_sections(source_name, &[func], 8);
+    let mut sections = vec![
+        Section::new("", 0, Vec::new()),
+        text,
+        Section::new(".shstrtab", SHT_STRTAB, Vec::new()),
+        symtab,
+        strtab,
+        Section::new(".debug_abbrev", SHT_PROGBITS, debug.debug_abbrev),
+        Section::new(".debug_info", SHT_PROGBITS, debug.debug_info),
+        Section::new(".debug_line", SHT_PROGBITS, debug.debug_line),
+    ];
+
+    // Section names.
+    let mut names = Vec::new();
+    let mut shstrtab = Vec::new();
+    for section in &sections {
+        names.push(shstrtab.len() as u32);
+        shstrtab.extend_from_slice(section.name.as_bytes());
+        shstrtab.push(0);
+    }
+    sections[2].size = shstrtab.len() as u64;
+    sections[2].data = shstrtab;
+
+    // Section contents follow the ELF header, and the section headers come last.
+    let mut offsets = Vec::new();
+    let mut offset = EHDR_SIZE;
+    for section in &sections {
+        offset = (offset + 7) & !7;
+        offsets.push(offset as u64);
+        offset += section.data.len();
+    }
+    let shoff = (offset + 7) & !7;
+
+    let mut elf = Vec::with_capacity(shoff + sections.len() * SHDR_SIZE);
+    elf.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    elf.extend_from_slice(&[0; 8]);
+    elf.extend_from_slice(&ET_REL.to_le_bytes());
+    elf.extend_from_slice(&machine.to_le_bytes());
+    elf.extend_from_slice(&1u32.to_le_bytes());
+    elf.extend_from_slice(&0u64.to_le_bytes());
+    elf.extend_from_slice(&0u64.to_le_bytes());
+    elf.extend_from_slice(&(shoff as u64).to_le_bytes());
+    elf.extend_from_slice(&0u32.to_le_bytes());
+    elf.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&0u16.to_le_bytes());
+    elf.extend_from_slice(&0u16.to_le_bytes());
+    elf.extend_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+    elf.extend_from_slice(&(sections.len() as u16).to_le_bytes());
+    elf.extend_from_slice(&2u16.to_le_bytes());
+    debug_assert_eq!(elf.len(), EHDR_SIZE);
+
+    for (section, &offset) in sections.iter().zip(&offsets) {
+        elf.resize(offset as usize, 0);
+        elf.extend_from_slice(&section.data);
+    }
+    elf.resize(shoff, 0);
+
+    for ((section, &offset), &name) in sections.iter().zip(&offsets).zip(&names) {
+        let (offset, align) = match section.kind {
+            0 => (0, 0),
+            SHT_SYMTAB => (offset, 8),
+            _ => (offset, 1),
+        };
+        elf.extend_from_slice(&name.to_le_bytes());
+        elf.extend_from_slice(&section.kind.to_le_bytes());
+        elf.extend_from_slice(&section.flags.to_le_bytes());
+        elf.extend_from_slice(&section.addr.to_le_bytes());
+        elf.extend_from_slice(&offset.to_le_bytes());
+        elf.extend_from_slice(&section.size.to_le_bytes());
+        elf.extend_from_slice(&section.link.to_le_bytes());
+        elf.extend_from_slice(&section.info.to_le_bytes());
+        elf.extend_from_slice(&align.to_le_bytes());
+        elf.extend_from_slice(&section.entsize.to_le_bytes());
+    }
+
+    Some(elf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cranelift_codegen::ir::SourceLoc;
+    use std::str::FromStr;
+    use target_lexicon::triple;
+
+    fn function() -> FunctionDebugInfo {
+        FunctionDebugInfo {
+            name: "answer".to_string(),
+            address: 0x1234_0000,
+            size: 16,
+            lines: vec![(0, SourceLoc::new(1))],
+        }
+    }
+
+    #[test]
+    fn elf() {
+        let elf = write_elf(function(), "answer.clif", &triple!("x86_64-unknown-linux")).unwrap();
+        assert_eq!(&elf[..4], b"\x7fELF");
+        assert_eq!(&elf[16..20], &[1, 0, 62, 0]);
+
+        // Find the `.text` section header.
+        let shoff = u64::from_le_bytes(read8(&elf, 40)) as usize;
+        let text = &elf[shoff + SHDR_SIZE..];
+        assert_eq!(&text[4..8], &SHT_NOBITS.to_le_bytes());
+        assert_eq!(read8(text, 16), 0x1234_0000u64.to_le_bytes());
+        assert_eq!(read8(text, 32), 16u64.to_le_bytes());
+
+        assert!(write_elf(function(), "answer.clif", &triple!("i686-unknown-linux")).is_none());
+    }
+
+    fn read8(data: &[u8], offset: usize) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&data[offset..offset + 8]);
+        bytes
+    }
+
+    #[test]
+    fn register() {
+        let triple = triple!("x86_64-unknown-linux");
+        let a = GdbJitImage::register(function(), "a", &triple).unwrap();
+        let b = GdbJitImage::register(function(), "b", &triple).unwrap();
+        let first = || unsafe { __jit_debug_descriptor.first_entry };
+        assert_eq!(first(), &*b.entry as *const _ as *mut _);
+        drop(b);
+        assert_eq!(first(), &*a.entry as *const _ as *mut _);
+        drop(a);
+        assert!(first().is_null());
+    }
+}
@@ -24,9 +24,12 @@
 )]
 
 mod backend;
+mod gdb;
 mod memory;
+mod perf;
 
 pub use crate::backend::{SimpleJITBackend, SimpleJITBuilder};
+pub use crate::perf::PerfFormat;
 
 /// Version number of this crate.
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
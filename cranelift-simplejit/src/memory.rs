@@ -69,81 +69,203 @@ impl PtrLen {
             Err(errno::errno().to_string())
         }
     }
+
+    /// Does this memory contain the address `ptr`?
+    fn contains(&self, ptr: *mut u8) -> bool {
+        ptr >= self.ptr && (ptr as usize) < self.ptr as usize + self.len
+    }
+
+    /// Return the memory to the system. It must be readable and writable.
+    #[cfg(not(target_os = "windows"))]
+    unsafe fn free(self) {
+        libc::free(self.ptr as *mut libc::c_void);
+    }
+
+    #[cfg(target_os = "windows")]
+    unsafe fn free(self) {
+        use winapi::um::memoryapi::VirtualFree;
+        use winapi::um::winnt::MEM_RELEASE;
+
+        VirtualFree(self.ptr as *mut _, 0, MEM_RELEASE);
+    }
+}
+
+/// A block of memory, and the number of allocations in it which haven't been freed.
+struct Block {
+    mem: PtrLen,
+    live: usize,
+}
+
+impl Block {
+    fn new(mem: PtrLen) -> Self {
+        Self { mem, live: 0 }
+    }
 }
 
 /// JIT memory manager. This manages pages of suitably aligned and
 /// accessible memory.
+///
+/// Memory is allocated readable and writable. Calling `finalize` gives everything allocated up to
+/// that point its final protection, after which it can no longer be written to. New allocations
+/// always start on fresh pages, so pages are never writable and executable at the same time.
 pub struct Memory {
-    allocations: Vec<PtrLen>,
-    executable: usize,
-    current: PtrLen,
+    protection: region::Protection,
+    allocations: Vec<Block>,
+    already_protected: usize,
+    current: Block,
     position: usize,
 }
 
 impl Memory {
-    pub fn new() -> Self {
+    /// Create a new `Memory` whose pages get the protection `protection` when finalized.
+    pub fn new(protection: region::Protection) -> Self {
         Self {
+            protection,
             allocations: Vec::new(),
-            executable: 0,
-            current: PtrLen::new(),
+            already_protected: 0,
+            current: Block::new(PtrLen::new()),
             position: 0,
         }
     }
 
     fn finish_current(&mut self) {
-        self.allocations
-            .push(mem::replace(&mut self.current, PtrLen::new()));
+        let current = mem::replace(&mut self.current, Block::new(PtrLen::new()));
         self.position = 0;
+        if current.live != 0 {
+            self.allocations.push(current);
+        } else if current.mem.len != 0 {
+            // Everything in this block was freed before it was finished.
+            unsafe { current.mem.free() };
+        }
     }
 
+    /// Allocate `size` bytes at an address which is a multiple of `align`.
+    ///
     /// TODO: Use a proper error type.
-    pub fn allocate(&mut self, size: usize) -> Result<*mut u8, String> {
-        if size <= self.current.len - self.position {
+    pub fn allocate(&mut self, size: usize, align: usize) -> Result<*mut u8, String> {
+        debug_assert!(align.is_power_of_two());
+        let start = (self.position + align - 1) & !(align - 1);
+        if start <= self.current.mem.len && size <= self.current.mem.len - start {
             // TODO: Ensure overflow is not possible.
-            let ptr = unsafe { self.current.ptr.add(self.position) };
-            self.position += size;
+            let ptr = unsafe { self.current.mem.ptr.add(start) };
+            self.position = start + size;
+            self.current.live += 1;
             return Ok(ptr);
         }
 
         self.finish_current();
 
         // TODO: Allocate more at a time.
-        self.current = PtrLen::with_size(size)?;
+        self.current = Block::new(PtrLen::with_size(size)?);
         self.position = size;
-        Ok(self.current.ptr)
+        self.current.live += 1;
+        Ok(self.current.mem.ptr)
     }
 
-    /// Set all memory allocated in this `Memory` up to now as readable and executable.
-    pub fn set_readable_and_executable(&mut self) {
+    /// Give all memory allocated in this `Memory` up to now its final protection.
+    ///
+    /// Executable memory is also flushed from the instruction cache, so the code written to it
+    /// can be run.
+    pub fn finalize(&mut self) {
         self.finish_current();
 
-        for &PtrLen { ptr, len } in &self.allocations[self.executable..] {
+        for block in &self.allocations[self.already_protected..] {
+            let (ptr, len) = (block.mem.ptr, block.mem.len);
             if len != 0 {
                 unsafe {
-                    region::protect(ptr, len, region::Protection::ReadExecute)
-                        .expect("unable to make memory readable+executable");
+                    region::protect(ptr, len, self.protection).expect("unable to protect memory");
+                }
+                if self.protection == region::Protection::ReadExecute {
+                    flush_icache(ptr, len);
                 }
             }
         }
+        self.already_protected = self.allocations.len();
     }
 
-    /// Set all memory allocated in this `Memory` up to now as readonly.
-    pub fn set_readonly(&mut self) {
-        self.finish_current();
+    /// Free the allocation at `ptr`.
+    ///
+    /// Pages are returned to the system once all the allocations in them have been freed. The
+    /// caller must make sure the memory is no longer in use.
+    pub fn free(&mut self, ptr: *mut u8) {
+        if self.current.mem.contains(ptr) {
+            self.current.live -= 1;
+            if self.current.live == 0 {
+                // The block isn't protected yet, so it can be reused right away.
+                self.position = 0;
+            }
+            return;
+        }
 
-        for &PtrLen { ptr, len } in &self.allocations[self.executable..] {
-            if len != 0 {
+        let index = self
+            .allocations
+            .iter()
+            .position(|block| block.mem.contains(ptr))
+            .expect("freeing memory which wasn't allocated here");
+        self.allocations[index].live -= 1;
+        if self.allocations[index].live != 0 {
+            return;
+        }
+
+        let block = self.allocations.remove(index);
+        if index < self.already_protected {
+            self.already_protected -= 1;
+            if block.mem.len != 0 {
                 unsafe {
-                    region::protect(ptr, len, region::Protection::Read)
-                        .expect("unable to make memory readonly");
+                    region::protect(block.mem.ptr, block.mem.len, region::Protection::ReadWrite)
+                        .expect("unable to make memory writable");
                 }
             }
         }
+        unsafe { block.mem.free() };
     }
 }
 
 // TODO: Implement Drop to unprotect and deallocate the memory?
 
+/// Make sure the instruction cache holds no stale copies of the `len` bytes at `ptr`.
+///
+/// x86 keeps its instruction cache coherent with stores, but ARM and RISC-V need an explicit
+/// flush before freshly written code can be executed.
+#[cfg(all(
+    not(target_os = "windows"),
+    any(
+        target_arch = "arm",
+        target_arch = "riscv64",
+        all(
+            target_arch = "aarch64",
+            not(any(target_os = "macos", target_os = "ios"))
+        )
+    )
+))]
+fn flush_icache(ptr: *mut u8, len: usize) {
+    extern "C" {
+        fn __clear_cache(start: *mut libc::c_char, end: *mut libc::c_char);
+    }
+    unsafe { __clear_cache(ptr as *mut libc::c_char, ptr.add(len) as *mut libc::c_char) };
+}
+
+#[cfg(all(target_arch = "aarch64", any(target_os = "macos", target_os = "ios")))]
+fn flush_icache(ptr: *mut u8, len: usize) {
+    extern "C" {
+        fn sys_icache_invalidate(start: *mut libc::c_void, len: libc::size_t);
+    }
+    unsafe { sys_icache_invalidate(ptr as *mut libc::c_void, len) };
+}
+
+#[cfg(target_os = "windows")]
+fn flush_icache(ptr: *mut u8, len: usize) {
+    use winapi::um::processthreadsapi::{FlushInstructionCache, GetCurrentProcess};
+
+    unsafe { FlushInstructionCache(GetCurrentProcess(), ptr as *const _, len) };
+}
+
+#[cfg(all(
+    not(target_os = "windows"),
+    not(any(target_arch = "aarch64", target_arch = "arm", target_arch = "riscv64"))
+))]
+fn flush_icache(_ptr: *mut u8, _len: usize) {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,4 +277,28 @@ mod tests {
         assert_eq!(round_up_to_page_size(4096, 4096), 4096);
         assert_eq!(round_up_to_page_size(4097, 4096), 8192);
     }
+
+    #[test]
+    fn free() {
+        let mut memory = Memory::new(region::Protection::Read);
+        let a = memory.allocate(16, 8).unwrap();
+        let b = memory.allocate(16, 8).unwrap();
+        memory.finalize();
+        let c = memory.allocate(16, 8).unwrap();
+        assert_eq!(memory.allocations.len(), 1);
+        assert_eq!(memory.already_protected, 1);
+
+        memory.free(a);
+        assert_eq!(memory.allocations.len(), 1);
+        memory.free(b);
+        assert_eq!(memory.allocations.len(), 0);
+        assert_eq!(memory.already_protected, 0);
+
+        // The current block is reused once it's empty.
+        memory.free(c);
+        assert_eq!(memory.allocate(16, 8).unwrap(), c);
+        memory.finalize();
+        assert_eq!(memory.allocations.len(), 1);
+        assert_eq!(memory.already_protected, 1);
+    }
 }
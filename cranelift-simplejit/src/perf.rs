@@ -0,0 +1,182 @@
+//! Describing JIT-compiled functions to the Linux `perf` profiler.
+//!
+//! `perf` can't find symbols for code generated at run time by itself. It supports two ways for a
+//! JIT to describe its code:
+//!
+//! - A map file `/tmp/perf-<pid>.map` with a line giving the address, size and name of each
+//!   function, which `perf report` reads directly.
+//!
+//! - A jitdump file `/tmp/jit-<pid>.dump` which also contains a copy of the code, so samples can
+//!   be annotated with instructions. The profile must be recorded with `perf record -k mono`, and
+//!   merged with `perf inject --jit` before reporting. The file is mapped into memory to leave a
+//!   trace in the profile, which is how `perf inject` finds it.
+//!
+//! See <https://github.com/torvalds/linux/blob/master/tools/perf/Documentation/jitdump-specification.txt>.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::process;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use target_lexicon::Triple;
+
+/// The format of the file describing the compiled functions to `perf`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PerfFormat {
+    /// A `/tmp/perf-<pid>.map` file with the names of the functions.
+    Map,
+    /// A `/tmp/jit-<pid>.dump` file with the names and code of the functions.
+    JitDump,
+}
+
+/// Writer for a map or jitdump file.
+pub struct PerfWriter {
+    format: PerfFormat,
+    file: File,
+    #[cfg(target_os = "linux")]
+    marker: *mut libc::c_void,
+}
+
+/// The index of the next function in the jitdump files of this process.
+static CODE_INDEX: AtomicUsize = AtomicUsize::new(0);
+
+const JITDUMP_MAGIC: u32 = 0x4a69_5444;
+const JITDUMP_VERSION: u32 = 1;
+const JITDUMP_HEADER_SIZE: u32 = 40;
+const JIT_CODE_LOAD: u32 = 0;
+
+impl PerfWriter {
+    /// Open the file for `format`, appending to it if it was already created by this process.
+    pub fn new(format: PerfFormat, triple: &Triple) -> io::Result<Self> {
+        let path = match format {
+            PerfFormat::Map => format!("/tmp/perf-{}.map", process::id()),
+            PerfFormat::JitDump => format!("/tmp/jit-{}.dump", process::id()),
+        };
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        match format {
+            PerfFormat::Map => Ok(Self {
+                format,
+                file,
+                #[cfg(target_os = "linux")]
+                marker: ::std::ptr::null_mut(),
+            }),
+            PerfFormat::JitDump => {
+                if file.metadata()?.len() == 0 {
+                    let machine = u32::from(crate::gdb::elf_machine(triple).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::Other, "unsupported architecture")
+                    })?);
+                    let mut header = Vec::with_capacity(JITDUMP_HEADER_SIZE as usize);
+                    header.extend_from_slice(&JITDUMP_MAGIC.to_le_bytes());
+                    header.extend_from_slice(&JITDUMP_VERSION.to_le_bytes());
+                    header.extend_from_slice(&JITDUMP_HEADER_SIZE.to_le_bytes());
+                    header.extend_from_slice(&machine.to_le_bytes());
+                    header.extend_from_slice(&0u32.to_le_bytes());
+                    header.extend_from_slice(&process::id().to_le_bytes());
+                    header.extend_from_slice(&timestamp()?.to_le_bytes());
+                    header.extend_from_slice(&0u64.to_le_bytes());
+                    file.write_all(&header)?;
+                }
+                Self::with_marker(format, file)
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn with_marker(format: PerfFormat, file: File) -> io::Result<Self> {
+        use std::os::unix::io::AsRawFd;
+        let marker = unsafe {
+            libc::mmap(
+                ::std::ptr::null_mut(),
+                region::page::size(),
+                libc::PROT_READ | libc::PROT_EXEC,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if marker == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            format,
+            file,
+            marker,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn with_marker(_format: PerfFormat, _file: File) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "jitdump files are only supported on Linux",
+        ))
+    }
+
+    /// Describe the function `name` whose `size` bytes of code are at `code`.
+    pub fn write_function(&mut self, name: &str, code: *const u8, size: usize) -> io::Result<()> {
+        match self.format {
+            PerfFormat::Map => writeln!(self.file, "{:x} {:x} {}", code as usize, size, name),
+            PerfFormat::JitDump => {
+                let code_bytes = unsafe { slice::from_raw_parts(code, size) };
+                let total_size = 56 + name.len() + 1 + size;
+                let index = CODE_INDEX.fetch_add(1, Ordering::Relaxed);
+                let mut record = Vec::with_capacity(total_size);
+                record.extend_from_slice(&JIT_CODE_LOAD.to_le_bytes());
+                record.extend_from_slice(&(total_size as u32).to_le_bytes());
+                record.extend_from_slice(&timestamp()?.to_le_bytes());
+                record.extend_from_slice(&process::id().to_le_bytes());
+                record.extend_from_slice(&thread_id().to_le_bytes());
+                record.extend_from_slice(&(code as u64).to_le_bytes());
+                record.extend_from_slice(&(code as u64).to_le_bytes());
+                record.extend_from_slice(&(size as u64).to_le_bytes());
+                record.extend_from_slice(&(index as u64).to_le_bytes());
+                record.extend_from_slice(name.as_bytes());
+                record.push(0);
+                record.extend_from_slice(code_bytes);
+                debug_assert_eq!(record.len(), total_size);
+                self.file.write_all(&record)
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for PerfWriter {
+    fn drop(&mut self) {
+        if !self.marker.is_null() {
+            unsafe { libc::munmap(self.marker, region::page::size()) };
+        }
+    }
+}
+
+/// Get the current time of the monotonic clock used by `perf record -k mono`, in nanoseconds.
+#[cfg(target_os = "linux")]
+fn timestamp() -> io::Result<u64> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn timestamp() -> io::Result<u64> {
+    Ok(0)
+}
+
+#[cfg(target_os = "linux")]
+fn thread_id() -> u32 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u32 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_id() -> u32 {
+    0
+}
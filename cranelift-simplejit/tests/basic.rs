@@ -5,6 +5,9 @@ use cranelift_entity::EntityRef;
 use cranelift_frontend::*;
 use cranelift_module::*;
 use cranelift_simplejit::*;
+use std::cell::RefCell;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[test]
 fn error_on_incompatible_sig_in_declare_function() {
@@ -13,6 +16,7 @@ fn error_on_incompatible_sig_in_declare_function() {
         params: vec![AbiParam::new(types::I64)],
         returns: vec![],
         call_conv: CallConv::SystemV,
+        variadic: false,
     };
     module
         .declare_function("abc", Linkage::Local, &sig)
@@ -29,6 +33,7 @@ fn define_simple_function(module: &mut Module<SimpleJITBackend>) -> FuncId {
         params: vec![],
         returns: vec![],
         call_conv: CallConv::SystemV,
+        variadic: false,
     };
 
     let func_id = module
@@ -72,6 +77,19 @@ fn panic_on_define_after_finalize() {
     define_simple_function(&mut module);
 }
 
+#[test]
+fn define_after_free() {
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::new());
+
+    let func_id = define_simple_function(&mut module);
+    module.finalize_definitions();
+    module.free_function(func_id).unwrap();
+
+    // The function can be defined again once its previous definition is freed.
+    define_simple_function(&mut module);
+    module.finalize_definitions();
+}
+
 #[test]
 fn switch_error() {
     use cranelift_codegen::settings;
@@ -80,6 +98,7 @@ fn switch_error() {
         params: vec![AbiParam::new(types::I32)],
         returns: vec![AbiParam::new(types::I32)],
         call_conv: CallConv::SystemV,
+        variadic: false,
     };
 
     let mut func = Function::with_name_signature(ExternalName::user(0, 0), sig);
@@ -144,3 +163,199 @@ fn switch_error() {
         }
     }
 }
+
+extern "C" fn double(values: *mut u128) {
+    unsafe {
+        *values = u128::from((*values as u32).wrapping_mul(2));
+    }
+}
+
+#[test]
+fn trampolines() {
+    let mut builder = SimpleJITBuilder::new();
+    builder.symbol("double", double as *const u8);
+    let mut module: Module<SimpleJITBackend> = Module::new(builder);
+    let pointer_type = module.target_config().pointer_type();
+    let call_conv = module.isa().default_call_conv();
+
+    let mut sig = module.make_signature();
+    sig.params.push(AbiParam::new(types::I32));
+    sig.returns.push(AbiParam::new(types::I32));
+
+    let host = module
+        .declare_function(
+            "double",
+            Linkage::Import,
+            &host_function_signature(pointer_type, call_conv),
+        )
+        .unwrap();
+    let host_trampoline = module
+        .declare_function("double_trampoline", Linkage::Local, &sig)
+        .unwrap();
+    let call_trampoline = module
+        .declare_function(
+            "call_trampoline",
+            Linkage::Local,
+            &call_trampoline_signature(pointer_type, call_conv),
+        )
+        .unwrap();
+
+    let mut ctx = Context::new();
+    ctx.func = make_host_trampoline(
+        &sig,
+        ExternalName::user(0, host.as_u32()),
+        pointer_type,
+        call_conv,
+    );
+    module.define_function(host_trampoline, &mut ctx).unwrap();
+    ctx.clear();
+    ctx.func = make_call_trampoline(&sig, pointer_type, call_conv);
+    module.define_function(call_trampoline, &mut ctx).unwrap();
+    module.finalize_definitions();
+
+    // Call the host function through both trampolines.
+    let callee = module.get_finalized_function(host_trampoline);
+    let call: extern "C" fn(*const u8, *mut u128) =
+        unsafe { mem::transmute(module.get_finalized_function(call_trampoline)) };
+    let mut values = [21u128];
+    call(callee, values.as_mut_ptr());
+    assert_eq!(values[0] as u32, 42);
+}
+
+/// Define `func` as a function returning `value`, or calling `callee` if it's given.
+fn define_constant_or_call(
+    module: &mut Module<SimpleJITBackend>,
+    func: FuncId,
+    value: i32,
+    callee: Option<FuncId>,
+    redefine: bool,
+) -> ModuleResult<u32> {
+    let mut ctx = module.make_context();
+    ctx.func.signature.returns.push(AbiParam::new(types::I32));
+    let callee = callee.map(|callee| module.declare_func_in_func(callee, &mut ctx.func));
+    let mut func_ctx = FunctionBuilderContext::new();
+    {
+        let mut bcx: FunctionBuilder = FunctionBuilder::new(&mut ctx.func, &mut func_ctx);
+        let ebb = bcx.create_ebb();
+        bcx.switch_to_block(ebb);
+        let result = match callee {
+            Some(callee) => {
+                let call = bcx.ins().call(callee, &[]);
+                bcx.inst_results(call)[0]
+            }
+            None => bcx.ins().iconst(types::I32, i64::from(value)),
+        };
+        bcx.ins().return_(&[result]);
+        bcx.seal_all_blocks();
+        bcx.finalize();
+    }
+
+    if redefine {
+        module.redefine_function(func, &mut ctx)
+    } else {
+        module.define_function(func, &mut ctx)
+    }
+}
+
+#[test]
+fn redefine() {
+    let mut builder = SimpleJITBuilder::new();
+    builder.hotswap(true);
+    let mut module: Module<SimpleJITBackend> = Module::new(builder);
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let callee = module
+        .declare_function("callee", Linkage::Local, &sig)
+        .unwrap();
+    let caller = module
+        .declare_function("caller", Linkage::Local, &sig)
+        .unwrap();
+
+    define_constant_or_call(&mut module, callee, 1, None, false).unwrap();
+    define_constant_or_call(&mut module, caller, 0, Some(callee), false).unwrap();
+    module.finalize_definitions();
+    let callee_address = module.get_finalized_function(callee);
+    let call_callee: extern "C" fn() -> i32 = unsafe { mem::transmute(callee_address) };
+    let call_caller: extern "C" fn() -> i32 =
+        unsafe { mem::transmute(module.get_finalized_function(caller)) };
+    assert_eq!(call_caller(), 1);
+
+    // Both the existing caller and the host see the new definition.
+    define_constant_or_call(&mut module, callee, 2, None, true).unwrap();
+    module.finalize_definitions();
+    assert_eq!(module.get_finalized_function(callee), callee_address);
+    assert_eq!(call_caller(), 2);
+    assert_eq!(call_callee(), 2);
+}
+
+#[test]
+fn redefine_error() {
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::new());
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let func = module
+        .declare_function("func", Linkage::Local, &sig)
+        .unwrap();
+
+    define_constant_or_call(&mut module, func, 1, None, false).unwrap();
+    module.finalize_definitions();
+
+    // Without hotswap the function can't be redefined, and it keeps its old definition.
+    assert!(define_constant_or_call(&mut module, func, 2, None, true).is_err());
+    let call: extern "C" fn() -> i32 =
+        unsafe { mem::transmute(module.get_finalized_function(func)) };
+    assert_eq!(call(), 1);
+    match define_constant_or_call(&mut module, func, 2, None, false) {
+        Err(ModuleError::DuplicateDefinition(_)) => {}
+        _ => panic!("expected a duplicate definition"),
+    }
+}
+
+thread_local! {
+    static LAZY_MODULE: RefCell<Option<Module<SimpleJITBackend>>> = RefCell::new(None);
+}
+
+static LAZY_COMPILATIONS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn compile_lazily(func: u32) -> *const u8 {
+    LAZY_COMPILATIONS.fetch_add(1, Ordering::SeqCst);
+    LAZY_MODULE.with(|module| {
+        let mut module = module.borrow_mut();
+        let module = module.as_mut().unwrap();
+        let func = FuncId::from_u32(func);
+        define_constant_or_call(module, func, 42, None, true).unwrap();
+        module.finalize_definitions();
+        module.get_finalized_function(func)
+    })
+}
+
+#[test]
+fn lazy_compilation() {
+    let mut builder = SimpleJITBuilder::new();
+    builder.hotswap(true);
+    builder.symbol("compile_lazily", compile_lazily as *const u8);
+    let mut module: Module<SimpleJITBackend> = Module::new(builder);
+    let callback_sig = lazy_callback_signature(
+        module.target_config().pointer_type(),
+        module.isa().default_call_conv(),
+    );
+    let callback = module
+        .declare_function("compile_lazily", Linkage::Import, &callback_sig)
+        .unwrap();
+    let mut sig = module.make_signature();
+    sig.returns.push(AbiParam::new(types::I32));
+    let func = module
+        .declare_function("lazy", Linkage::Local, &sig)
+        .unwrap();
+
+    module.define_lazy_function(func, callback).unwrap();
+    module.finalize_definitions();
+    let call: extern "C" fn() -> i32 =
+        unsafe { mem::transmute(module.get_finalized_function(func)) };
+    LAZY_MODULE.with(|lazy_module| *lazy_module.borrow_mut() = Some(module));
+
+    // The function is only compiled the first time it's called.
+    assert_eq!(call(), 42);
+    assert_eq!(call(), 42);
+    assert_eq!(LAZY_COMPILATIONS.load(Ordering::SeqCst), 1);
+}
@@ -17,20 +17,27 @@
 //! - the loads and stores need the memory base address;
 //! - the `get_global` et `set_global` instructions depends on how the globals are implemented;
 //! - `memory.size` and `memory.grow` are runtime functions;
+//! - `atomic.wait` and `atomic.notify` are runtime functions;
+//! - the bulk memory operators are translated to library calls or loops, or handled by the
+//!   environment for passive segments;
+//! - the exception handling operators are runtime functions, and calls in a `try` are translated
+//!   to `try_call` instructions unwinding to the `catch` clause;
 //! - `call_indirect` has to translate the function index into the address of where this
 //!    is;
 //!
 //! That is why `translate_function_body` takes an object having the `WasmRuntime` trait as
 //! argument.
 use super::{hash_map, HashMap};
-use crate::environ::{FuncEnvironment, GlobalVariable, ReturnMode, WasmError, WasmResult};
+use crate::environ::{
+    BulkMemoryStrategy, FuncEnvironment, GlobalVariable, ReturnMode, WasmError, WasmResult,
+};
 use crate::state::{ControlStackFrame, TranslationState};
 use crate::translation_utils::{f32_translation, f64_translation, num_return_values, type_to_type};
-use crate::translation_utils::{FuncIndex, MemoryIndex, SignatureIndex, TableIndex};
+use crate::translation_utils::{EventIndex, FuncIndex, MemoryIndex, SignatureIndex, TableIndex};
 use core::{i32, u32};
 use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
 use cranelift_codegen::ir::types::*;
-use cranelift_codegen::ir::{self, InstBuilder, JumpTableData, MemFlags};
+use cranelift_codegen::ir::{self, AtomicRmwOp, Endianness, InstBuilder, JumpTableData, MemFlags};
 use cranelift_codegen::packed_option::ReservedValue;
 use cranelift_frontend::{FunctionBuilder, Variable};
 use wasmparser::{MemoryImmediate, Operator};
@@ -39,8 +46,12 @@ use wasmparser::{MemoryImmediate, Operator};
 #[cfg_attr(feature = "cargo-clippy", allow(clippy::unneeded_field_pattern))]
 /// Translates wasm operators into Cranelift IR instructions. Returns `true` if it inserted
 /// a return.
+///
+/// `memory` is the index of the linear memory accessed by an operator with a `memarg`. The parser
+/// doesn't decode the memory index of the multi-memory proposal, so it is read separately.
 pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
     op: Operator,
+    memory: u32,
     builder: &mut FunctionBuilder,
     state: &mut TranslationState,
     environ: &mut FE,
@@ -123,7 +134,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
          ***********************************************************************************/
         Operator::Block { ty } => {
             let next = builder.create_ebb();
-            if let Ok(ty_cre) = type_to_type(ty) {
+            if let Ok(ty_cre) = type_to_type(ty, environ.target_config()) {
                 builder.append_ebb_param(next, ty_cre);
             }
             state.push_block(next, num_return_values(ty));
@@ -131,7 +142,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         Operator::Loop { ty } => {
             let loop_body = builder.create_ebb();
             let next = builder.create_ebb();
-            if let Ok(ty_cre) = type_to_type(ty) {
+            if let Ok(ty_cre) = type_to_type(ty, environ.target_config()) {
                 builder.append_ebb_param(next, ty_cre);
             }
             builder.ins().jump(loop_body, &[]);
@@ -149,7 +160,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             //   and we add nothing;
             // - either the If have an Else clause, in that case the destination of this jump
             //   instruction will be changed later when we translate the Else operator.
-            if let Ok(ty_cre) = type_to_type(ty) {
+            if let Ok(ty_cre) = type_to_type(ty, environ.target_config()) {
                 builder.append_ebb_param(if_not, ty_cre);
             }
             state.push_if(jump_inst, if_not, num_return_values(ty));
@@ -363,6 +374,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
                     .len(),
                 "translate_call results should match the call signature"
             );
+            translate_unwinding_call(call, builder, state)?;
             state.popn(num_args);
             state.pushn(inst_results);
         }
@@ -387,6 +399,7 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
                 builder.func.dfg.signatures[sigref].returns.len(),
                 "translate_call_indirect results should match the call signature"
             );
+            translate_unwinding_call(call, builder, state)?;
             state.popn(num_args);
             state.pushn(inst_results);
         }
@@ -395,8 +408,8 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
          * special functions.
          ************************************************************************************/
         Operator::MemoryGrow { reserved } => {
-            // The WebAssembly MVP only supports one linear memory, but we expect the reserved
-            // argument to be a memory index.
+            // The WebAssembly MVP only supports one linear memory, but the reserved argument is
+            // the memory index in the multi-memory proposal.
             let heap_index = MemoryIndex::from_u32(reserved);
             let heap = state.get_heap(builder.func, reserved, environ);
             let val = state.pop1();
@@ -407,118 +420,277 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
             let heap = state.get_heap(builder.func, reserved, environ);
             state.push1(environ.translate_memory_size(builder.cursor(), heap_index, heap)?);
         }
+        /******************************* Bulk memory operators *******************************
+         * `memory.copy` and `memory.fill` are bounds checked here, then translated to libcalls or
+         * inline loops as chosen by the environment. The operators involving passive segments
+         * are handled by the environment.
+         ************************************************************************************/
+        Operator::MemoryCopy => {
+            let (dst, src, len) = state.pop3();
+            let dst = translate_memory_range(0, dst, len, builder, state, environ)?;
+            let src = translate_memory_range(0, src, len, builder, state, environ)?;
+            let len = native_length(len, builder, environ);
+            match environ.bulk_memory_strategy() {
+                BulkMemoryStrategy::LibCall => {
+                    builder.call_memmove(environ.target_config(), dst, src, len)
+                }
+                BulkMemoryStrategy::InlineLoop => emit_memmove_loop(dst, src, len, builder),
+            }
+        }
+        Operator::MemoryFill => {
+            let (dst, val, len) = state.pop3();
+            let dst = translate_memory_range(0, dst, len, builder, state, environ)?;
+            let len = native_length(len, builder, environ);
+            match environ.bulk_memory_strategy() {
+                BulkMemoryStrategy::LibCall => {
+                    let val = builder.ins().ireduce(I8, val);
+                    builder.call_memset(environ.target_config(), dst, val, len)
+                }
+                BulkMemoryStrategy::InlineLoop => {
+                    emit_byte_loop(len, builder, |builder, i| {
+                        let addr = builder.ins().iadd(dst, i);
+                        builder.ins().istore8(MemFlags::new(), val, addr, 0);
+                    });
+                }
+            }
+        }
+        Operator::MemoryInit { segment } => {
+            let (dst, src, len) = state.pop3();
+            let heap = state.get_heap(builder.func, 0, environ);
+            environ.translate_memory_init(
+                builder.cursor(),
+                MemoryIndex::from_u32(0),
+                heap,
+                segment,
+                dst,
+                src,
+                len,
+            )?;
+        }
+        Operator::DataDrop { segment } => {
+            environ.translate_data_drop(builder.cursor(), segment)?;
+        }
+        Operator::TableCopy => {
+            let (dst, src, len) = state.pop3();
+            let table = state.get_table(builder.func, 0, environ);
+            environ.translate_table_copy(
+                builder.cursor(),
+                TableIndex::from_u32(0),
+                table,
+                dst,
+                src,
+                len,
+            )?;
+        }
+        Operator::TableGet { table } => {
+            let index = state.pop1();
+            let table_index = TableIndex::from_u32(table);
+            let table = state.get_table(builder.func, table, environ);
+            let value = environ.translate_table_get(builder.cursor(), table_index, table, index)?;
+            state.push1(value);
+        }
+        Operator::TableSet { table } => {
+            let (index, value) = state.pop2();
+            let table_index = TableIndex::from_u32(table);
+            let table = state.get_table(builder.func, table, environ);
+            environ.translate_table_set(builder.cursor(), table_index, table, value, index)?;
+        }
+        Operator::TableInit { .. }
+        | Operator::ElemDrop { .. }
+        | Operator::TableGrow { .. }
+        | Operator::TableSize { .. } => {
+            return Err(WasmError::Unsupported("proposed table operators"));
+        }
         /******************************* Load instructions ***********************************
          * Wasm specifies an integer alignment flag but we drop it in Cranelift.
          * The memory base address is provided by the environment.
          ************************************************************************************/
-        Operator::I32Load8U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Uload8, I32, builder, state, environ);
+        Operator::I32Load8U { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Uload8,
+                I32,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I32Load16U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Uload16, I32, builder, state, environ);
+        Operator::I32Load16U { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Uload16,
+                I32,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I32Load8S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Sload8, I32, builder, state, environ);
+        Operator::I32Load8S { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Sload8,
+                I32,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I32Load16S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Sload16, I32, builder, state, environ);
+        Operator::I32Load16S { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Sload16,
+                I32,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I64Load8U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Uload8, I64, builder, state, environ);
+        Operator::I64Load8U { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Uload8,
+                I64,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I64Load16U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Uload16, I64, builder, state, environ);
+        Operator::I64Load16U { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Uload16,
+                I64,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I64Load8S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Sload8, I64, builder, state, environ);
+        Operator::I64Load8S { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Sload8,
+                I64,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I64Load16S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Sload16, I64, builder, state, environ);
+        Operator::I64Load16S { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Sload16,
+                I64,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I64Load32S {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Sload32, I64, builder, state, environ);
+        Operator::I64Load32S { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Sload32,
+                I64,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I64Load32U {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Uload32, I64, builder, state, environ);
+        Operator::I64Load32U { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Uload32,
+                I64,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I32Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Load, I32, builder, state, environ);
+        Operator::I32Load { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Load,
+                I32,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::F32Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Load, F32, builder, state, environ);
+        Operator::F32Load { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Load,
+                F32,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I64Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Load, I64, builder, state, environ);
+        Operator::I64Load { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Load,
+                I64,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::F64Load {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_load(offset, ir::Opcode::Load, F64, builder, state, environ);
+        Operator::F64Load { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Load,
+                F64,
+                builder,
+                state,
+                environ,
+            );
         }
         /****************************** Store instructions ***********************************
          * Wasm specifies an integer alignment flag but we drop it in Cranelift.
          * The memory base address is provided by the environment.
          ************************************************************************************/
-        Operator::I32Store {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::I64Store {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::F32Store {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::F64Store {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(offset, ir::Opcode::Store, builder, state, environ);
-        }
-        Operator::I32Store8 {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::I64Store8 {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(offset, ir::Opcode::Istore8, builder, state, environ);
-        }
-        Operator::I32Store16 {
-            memarg: MemoryImmediate { flags: _, offset },
-        }
-        | Operator::I64Store16 {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(offset, ir::Opcode::Istore16, builder, state, environ);
+        Operator::I32Store { memarg }
+        | Operator::I64Store { memarg }
+        | Operator::F32Store { memarg }
+        | Operator::F64Store { memarg } => {
+            translate_store(memory, memarg, ir::Opcode::Store, builder, state, environ);
+        }
+        Operator::I32Store8 { memarg } | Operator::I64Store8 { memarg } => {
+            translate_store(memory, memarg, ir::Opcode::Istore8, builder, state, environ);
+        }
+        Operator::I32Store16 { memarg } | Operator::I64Store16 { memarg } => {
+            translate_store(
+                memory,
+                memarg,
+                ir::Opcode::Istore16,
+                builder,
+                state,
+                environ,
+            );
         }
-        Operator::I64Store32 {
-            memarg: MemoryImmediate { flags: _, offset },
-        } => {
-            translate_store(offset, ir::Opcode::Istore32, builder, state, environ);
+        Operator::I64Store32 { memarg } => {
+            translate_store(
+                memory,
+                memarg,
+                ir::Opcode::Istore32,
+                builder,
+                state,
+                environ,
+            );
         }
         /****************************** Nullary Operators ************************************/
         Operator::I32Const { value } => state.push1(builder.ins().iconst(I32, i64::from(value))),
@@ -815,76 +987,887 @@ pub fn translate_operator<FE: FuncEnvironment + ?Sized>(
         Operator::F32Le | Operator::F64Le => {
             translate_fcmp(FloatCC::LessThanOrEqual, builder, state)
         }
-        Operator::Wake { .. }
-        | Operator::I32Wait { .. }
-        | Operator::I64Wait { .. }
-        | Operator::I32AtomicLoad { .. }
-        | Operator::I64AtomicLoad { .. }
-        | Operator::I32AtomicLoad8U { .. }
-        | Operator::I32AtomicLoad16U { .. }
-        | Operator::I64AtomicLoad8U { .. }
-        | Operator::I64AtomicLoad16U { .. }
-        | Operator::I64AtomicLoad32U { .. }
-        | Operator::I32AtomicStore { .. }
-        | Operator::I64AtomicStore { .. }
-        | Operator::I32AtomicStore8 { .. }
-        | Operator::I32AtomicStore16 { .. }
-        | Operator::I64AtomicStore8 { .. }
-        | Operator::I64AtomicStore16 { .. }
-        | Operator::I64AtomicStore32 { .. }
-        | Operator::I32AtomicRmwAdd { .. }
-        | Operator::I64AtomicRmwAdd { .. }
-        | Operator::I32AtomicRmw8UAdd { .. }
-        | Operator::I32AtomicRmw16UAdd { .. }
-        | Operator::I64AtomicRmw8UAdd { .. }
-        | Operator::I64AtomicRmw16UAdd { .. }
-        | Operator::I64AtomicRmw32UAdd { .. }
-        | Operator::I32AtomicRmwSub { .. }
-        | Operator::I64AtomicRmwSub { .. }
-        | Operator::I32AtomicRmw8USub { .. }
-        | Operator::I32AtomicRmw16USub { .. }
-        | Operator::I64AtomicRmw8USub { .. }
-        | Operator::I64AtomicRmw16USub { .. }
-        | Operator::I64AtomicRmw32USub { .. }
-        | Operator::I32AtomicRmwAnd { .. }
-        | Operator::I64AtomicRmwAnd { .. }
-        | Operator::I32AtomicRmw8UAnd { .. }
-        | Operator::I32AtomicRmw16UAnd { .. }
-        | Operator::I64AtomicRmw8UAnd { .. }
-        | Operator::I64AtomicRmw16UAnd { .. }
-        | Operator::I64AtomicRmw32UAnd { .. }
-        | Operator::I32AtomicRmwOr { .. }
-        | Operator::I64AtomicRmwOr { .. }
-        | Operator::I32AtomicRmw8UOr { .. }
-        | Operator::I32AtomicRmw16UOr { .. }
-        | Operator::I64AtomicRmw8UOr { .. }
-        | Operator::I64AtomicRmw16UOr { .. }
-        | Operator::I64AtomicRmw32UOr { .. }
-        | Operator::I32AtomicRmwXor { .. }
-        | Operator::I64AtomicRmwXor { .. }
-        | Operator::I32AtomicRmw8UXor { .. }
-        | Operator::I32AtomicRmw16UXor { .. }
-        | Operator::I64AtomicRmw8UXor { .. }
-        | Operator::I64AtomicRmw16UXor { .. }
-        | Operator::I64AtomicRmw32UXor { .. }
-        | Operator::I32AtomicRmwXchg { .. }
-        | Operator::I64AtomicRmwXchg { .. }
-        | Operator::I32AtomicRmw8UXchg { .. }
-        | Operator::I32AtomicRmw16UXchg { .. }
-        | Operator::I64AtomicRmw8UXchg { .. }
-        | Operator::I64AtomicRmw16UXchg { .. }
-        | Operator::I64AtomicRmw32UXchg { .. }
-        | Operator::I32AtomicRmwCmpxchg { .. }
-        | Operator::I64AtomicRmwCmpxchg { .. }
-        | Operator::I32AtomicRmw8UCmpxchg { .. }
-        | Operator::I32AtomicRmw16UCmpxchg { .. }
-        | Operator::I64AtomicRmw8UCmpxchg { .. }
-        | Operator::I64AtomicRmw16UCmpxchg { .. }
-        | Operator::I64AtomicRmw32UCmpxchg { .. } => {
-            return Err(WasmError::Unsupported("proposed thread operators"));
-        }
-        Operator::RefNull | Operator::RefIsNull { .. } => {
-            return Err(WasmError::Unsupported("proposed reference-type operators"));
+        /******************************* Atomic operators ***********************************
+         * Atomic memory accesses map to the core atomic instructions, and trap when they are not
+         * aligned. Waiting and notifying are runtime functions provided by the environment.
+         ************************************************************************************/
+        Operator::I32Wait { memarg } => {
+            translate_atomic_wait(memory, memarg, I32, builder, state, environ)?;
+        }
+        Operator::I64Wait { memarg } => {
+            translate_atomic_wait(memory, memarg, I64, builder, state, environ)?;
+        }
+        Operator::Wake { memarg } => {
+            translate_atomic_notify(memory, memarg, builder, state, environ)?;
+        }
+        Operator::I32AtomicLoad { memarg } => {
+            translate_atomic_load(memory, memarg, I32, I32, builder, state, environ);
+        }
+        Operator::I64AtomicLoad { memarg } => {
+            translate_atomic_load(memory, memarg, I64, I64, builder, state, environ);
+        }
+        Operator::I32AtomicLoad8U { memarg } => {
+            translate_atomic_load(memory, memarg, I8, I32, builder, state, environ);
+        }
+        Operator::I32AtomicLoad16U { memarg } => {
+            translate_atomic_load(memory, memarg, I16, I32, builder, state, environ);
+        }
+        Operator::I64AtomicLoad8U { memarg } => {
+            translate_atomic_load(memory, memarg, I8, I64, builder, state, environ);
+        }
+        Operator::I64AtomicLoad16U { memarg } => {
+            translate_atomic_load(memory, memarg, I16, I64, builder, state, environ);
+        }
+        Operator::I64AtomicLoad32U { memarg } => {
+            translate_atomic_load(memory, memarg, I32, I64, builder, state, environ);
+        }
+        Operator::I32AtomicStore { memarg } => {
+            translate_atomic_store(memory, memarg, I32, builder, state, environ);
+        }
+        Operator::I64AtomicStore { memarg } => {
+            translate_atomic_store(memory, memarg, I64, builder, state, environ);
+        }
+        Operator::I32AtomicStore8 { memarg } => {
+            translate_atomic_store(memory, memarg, I8, builder, state, environ);
+        }
+        Operator::I32AtomicStore16 { memarg } => {
+            translate_atomic_store(memory, memarg, I16, builder, state, environ);
+        }
+        Operator::I64AtomicStore8 { memarg } => {
+            translate_atomic_store(memory, memarg, I8, builder, state, environ);
+        }
+        Operator::I64AtomicStore16 { memarg } => {
+            translate_atomic_store(memory, memarg, I16, builder, state, environ);
+        }
+        Operator::I64AtomicStore32 { memarg } => {
+            translate_atomic_store(memory, memarg, I32, builder, state, environ);
+        }
+        Operator::I32AtomicRmwAdd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Add,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmwAdd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Add,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw8UAdd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Add,
+                I8,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw16UAdd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Add,
+                I16,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw8UAdd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Add,
+                I8,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw16UAdd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Add,
+                I16,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw32UAdd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Add,
+                I32,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmwSub { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Sub,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmwSub { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Sub,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw8USub { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Sub,
+                I8,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw16USub { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Sub,
+                I16,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw8USub { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Sub,
+                I8,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw16USub { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Sub,
+                I16,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw32USub { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Sub,
+                I32,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmwAnd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::And,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmwAnd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::And,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw8UAnd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::And,
+                I8,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw16UAnd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::And,
+                I16,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw8UAnd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::And,
+                I8,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw16UAnd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::And,
+                I16,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw32UAnd { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::And,
+                I32,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmwOr { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Or,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmwOr { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Or,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw8UOr { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Or,
+                I8,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw16UOr { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Or,
+                I16,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw8UOr { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Or,
+                I8,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw16UOr { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Or,
+                I16,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw32UOr { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Or,
+                I32,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmwXor { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xor,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmwXor { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xor,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw8UXor { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xor,
+                I8,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw16UXor { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xor,
+                I16,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw8UXor { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xor,
+                I8,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw16UXor { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xor,
+                I16,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw32UXor { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xor,
+                I32,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmwXchg { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xchg,
+                I32,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmwXchg { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xchg,
+                I64,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw8UXchg { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xchg,
+                I8,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmw16UXchg { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xchg,
+                I16,
+                I32,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw8UXchg { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xchg,
+                I8,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw16UXchg { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xchg,
+                I16,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I64AtomicRmw32UXchg { memarg } => {
+            translate_atomic_rmw(
+                memory,
+                memarg,
+                AtomicRmwOp::Xchg,
+                I32,
+                I64,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::I32AtomicRmwCmpxchg { memarg } => {
+            translate_atomic_cas(memory, memarg, I32, I32, builder, state, environ);
+        }
+        Operator::I64AtomicRmwCmpxchg { memarg } => {
+            translate_atomic_cas(memory, memarg, I64, I64, builder, state, environ);
+        }
+        Operator::I32AtomicRmw8UCmpxchg { memarg } => {
+            translate_atomic_cas(memory, memarg, I8, I32, builder, state, environ);
+        }
+        Operator::I32AtomicRmw16UCmpxchg { memarg } => {
+            translate_atomic_cas(memory, memarg, I16, I32, builder, state, environ);
+        }
+        Operator::I64AtomicRmw8UCmpxchg { memarg } => {
+            translate_atomic_cas(memory, memarg, I8, I64, builder, state, environ);
+        }
+        Operator::I64AtomicRmw16UCmpxchg { memarg } => {
+            translate_atomic_cas(memory, memarg, I16, I64, builder, state, environ);
+        }
+        Operator::I64AtomicRmw32UCmpxchg { memarg } => {
+            translate_atomic_cas(memory, memarg, I32, I64, builder, state, environ);
+        }
+        /****************************** Reference types ***************************************
+         * References are opaque values of the reference type chosen by the environment, so the
+         * garbage collector can find them in the stack maps of calls.
+         ***********************************************************************************/
+        Operator::RefNull => {
+            let ref_ty = environ.reference_type();
+            state.push1(builder.ins().null(ref_ty));
+        }
+        Operator::RefIsNull { .. } => {
+            let arg = state.pop1();
+            let val = builder.ins().is_null(arg);
+            state.push1(builder.ins().bint(I32, val));
+        }
+        /******************************* SIMD Operators ***********************************
+         * `v128` values are kept as `i8x16` vectors on the value stack and in locals. Each
+         * operator reinterprets its operands as the vector type it works on with a `bitcast`,
+         * which doesn't generate any code.
+         ***********************************************************************************/
+        Operator::V128Load { memarg } => {
+            translate_load(
+                memory,
+                memarg,
+                ir::Opcode::Load,
+                I8X16,
+                builder,
+                state,
+                environ,
+            );
+        }
+        Operator::V128Store { memarg } => {
+            translate_store(memory, memarg, ir::Opcode::Store, builder, state, environ);
+        }
+        Operator::V128Const { value } => {
+            let value = translate_vector_const(value.bytes(), builder);
+            state.push1(value);
+        }
+        Operator::V8x16Shuffle { lines } => {
+            let (a, b) = state.pop2();
+            let mask = translate_vector_const(&lines, builder);
+            state.push1(builder.ins().shuffle(a, b, mask));
+        }
+        Operator::I8x16Splat => translate_splat(I8X16, builder, state),
+        Operator::I16x8Splat => translate_splat(I16X8, builder, state),
+        Operator::I32x4Splat => translate_splat(I32X4, builder, state),
+        Operator::I64x2Splat => translate_splat(I64X2, builder, state),
+        Operator::F32x4Splat => translate_splat(F32X4, builder, state),
+        Operator::F64x2Splat => translate_splat(F64X2, builder, state),
+        Operator::I8x16ExtractLaneS { line } => {
+            let vector = pop_vector(I8X16, builder, state);
+            let lane = builder.ins().extractlane(vector, line);
+            state.push1(builder.ins().sextend(I32, lane));
+        }
+        Operator::I8x16ExtractLaneU { line } => {
+            let vector = pop_vector(I8X16, builder, state);
+            let lane = builder.ins().extractlane(vector, line);
+            state.push1(builder.ins().uextend(I32, lane));
+        }
+        Operator::I16x8ExtractLaneS { line } => {
+            let vector = pop_vector(I16X8, builder, state);
+            let lane = builder.ins().extractlane(vector, line);
+            state.push1(builder.ins().sextend(I32, lane));
+        }
+        Operator::I16x8ExtractLaneU { line } => {
+            let vector = pop_vector(I16X8, builder, state);
+            let lane = builder.ins().extractlane(vector, line);
+            state.push1(builder.ins().uextend(I32, lane));
+        }
+        Operator::I32x4ExtractLane { line } => translate_extract_lane(I32X4, line, builder, state),
+        Operator::I64x2ExtractLane { line } => translate_extract_lane(I64X2, line, builder, state),
+        Operator::F32x4ExtractLane { line } => translate_extract_lane(F32X4, line, builder, state),
+        Operator::F64x2ExtractLane { line } => translate_extract_lane(F64X2, line, builder, state),
+        Operator::I8x16ReplaceLane { line } => translate_replace_lane(I8X16, line, builder, state),
+        Operator::I16x8ReplaceLane { line } => translate_replace_lane(I16X8, line, builder, state),
+        Operator::I32x4ReplaceLane { line } => translate_replace_lane(I32X4, line, builder, state),
+        Operator::I64x2ReplaceLane { line } => translate_replace_lane(I64X2, line, builder, state),
+        Operator::F32x4ReplaceLane { line } => translate_replace_lane(F32X4, line, builder, state),
+        Operator::F64x2ReplaceLane { line } => translate_replace_lane(F64X2, line, builder, state),
+        Operator::I8x16Eq => translate_vector_icmp(IntCC::Equal, I8X16, builder, state),
+        Operator::I8x16Ne => translate_vector_icmp(IntCC::NotEqual, I8X16, builder, state),
+        Operator::I8x16LtS => translate_vector_icmp(IntCC::SignedLessThan, I8X16, builder, state),
+        Operator::I8x16LtU => translate_vector_icmp(IntCC::UnsignedLessThan, I8X16, builder, state),
+        Operator::I8x16GtS => {
+            translate_vector_icmp(IntCC::SignedGreaterThan, I8X16, builder, state)
+        }
+        Operator::I8x16GtU => {
+            translate_vector_icmp(IntCC::UnsignedGreaterThan, I8X16, builder, state)
+        }
+        Operator::I8x16LeS => {
+            translate_vector_icmp(IntCC::SignedLessThanOrEqual, I8X16, builder, state)
+        }
+        Operator::I8x16LeU => {
+            translate_vector_icmp(IntCC::UnsignedLessThanOrEqual, I8X16, builder, state)
+        }
+        Operator::I8x16GeS => {
+            translate_vector_icmp(IntCC::SignedGreaterThanOrEqual, I8X16, builder, state)
+        }
+        Operator::I8x16GeU => {
+            translate_vector_icmp(IntCC::UnsignedGreaterThanOrEqual, I8X16, builder, state)
+        }
+        Operator::I16x8Eq => translate_vector_icmp(IntCC::Equal, I16X8, builder, state),
+        Operator::I16x8Ne => translate_vector_icmp(IntCC::NotEqual, I16X8, builder, state),
+        Operator::I16x8LtS => translate_vector_icmp(IntCC::SignedLessThan, I16X8, builder, state),
+        Operator::I16x8LtU => translate_vector_icmp(IntCC::UnsignedLessThan, I16X8, builder, state),
+        Operator::I16x8GtS => {
+            translate_vector_icmp(IntCC::SignedGreaterThan, I16X8, builder, state)
+        }
+        Operator::I16x8GtU => {
+            translate_vector_icmp(IntCC::UnsignedGreaterThan, I16X8, builder, state)
+        }
+        Operator::I16x8LeS => {
+            translate_vector_icmp(IntCC::SignedLessThanOrEqual, I16X8, builder, state)
+        }
+        Operator::I16x8LeU => {
+            translate_vector_icmp(IntCC::UnsignedLessThanOrEqual, I16X8, builder, state)
+        }
+        Operator::I16x8GeS => {
+            translate_vector_icmp(IntCC::SignedGreaterThanOrEqual, I16X8, builder, state)
+        }
+        Operator::I16x8GeU => {
+            translate_vector_icmp(IntCC::UnsignedGreaterThanOrEqual, I16X8, builder, state)
+        }
+        Operator::I32x4Eq => translate_vector_icmp(IntCC::Equal, I32X4, builder, state),
+        Operator::I32x4Ne => translate_vector_icmp(IntCC::NotEqual, I32X4, builder, state),
+        Operator::I32x4LtS => translate_vector_icmp(IntCC::SignedLessThan, I32X4, builder, state),
+        Operator::I32x4LtU => translate_vector_icmp(IntCC::UnsignedLessThan, I32X4, builder, state),
+        Operator::I32x4GtS => {
+            translate_vector_icmp(IntCC::SignedGreaterThan, I32X4, builder, state)
+        }
+        Operator::I32x4GtU => {
+            translate_vector_icmp(IntCC::UnsignedGreaterThan, I32X4, builder, state)
+        }
+        Operator::I32x4LeS => {
+            translate_vector_icmp(IntCC::SignedLessThanOrEqual, I32X4, builder, state)
+        }
+        Operator::I32x4LeU => {
+            translate_vector_icmp(IntCC::UnsignedLessThanOrEqual, I32X4, builder, state)
+        }
+        Operator::I32x4GeS => {
+            translate_vector_icmp(IntCC::SignedGreaterThanOrEqual, I32X4, builder, state)
+        }
+        Operator::I32x4GeU => {
+            translate_vector_icmp(IntCC::UnsignedGreaterThanOrEqual, I32X4, builder, state)
+        }
+        Operator::F32x4Eq => translate_vector_fcmp(FloatCC::Equal, F32X4, builder, state),
+        Operator::F32x4Ne => translate_vector_fcmp(FloatCC::NotEqual, F32X4, builder, state),
+        Operator::F32x4Lt => translate_vector_fcmp(FloatCC::LessThan, F32X4, builder, state),
+        Operator::F32x4Gt => translate_vector_fcmp(FloatCC::GreaterThan, F32X4, builder, state),
+        Operator::F32x4Le => translate_vector_fcmp(FloatCC::LessThanOrEqual, F32X4, builder, state),
+        Operator::F32x4Ge => {
+            translate_vector_fcmp(FloatCC::GreaterThanOrEqual, F32X4, builder, state)
+        }
+        Operator::F64x2Eq => translate_vector_fcmp(FloatCC::Equal, F64X2, builder, state),
+        Operator::F64x2Ne => translate_vector_fcmp(FloatCC::NotEqual, F64X2, builder, state),
+        Operator::F64x2Lt => translate_vector_fcmp(FloatCC::LessThan, F64X2, builder, state),
+        Operator::F64x2Gt => translate_vector_fcmp(FloatCC::GreaterThan, F64X2, builder, state),
+        Operator::F64x2Le => translate_vector_fcmp(FloatCC::LessThanOrEqual, F64X2, builder, state),
+        Operator::F64x2Ge => {
+            translate_vector_fcmp(FloatCC::GreaterThanOrEqual, F64X2, builder, state)
+        }
+        Operator::V128Not => {
+            let arg = state.pop1();
+            state.push1(builder.ins().bnot(arg));
+        }
+        Operator::V128And => {
+            let (arg1, arg2) = state.pop2();
+            state.push1(builder.ins().band(arg1, arg2));
+        }
+        Operator::V128Or => {
+            let (arg1, arg2) = state.pop2();
+            state.push1(builder.ins().bor(arg1, arg2));
+        }
+        Operator::V128Xor => {
+            let (arg1, arg2) = state.pop2();
+            state.push1(builder.ins().bxor(arg1, arg2));
+        }
+        Operator::V128Bitselect => {
+            let (arg1, arg2, cond) = state.pop3();
+            let from_arg1 = builder.ins().band(arg1, cond);
+            let from_arg2 = builder.ins().band_not(arg2, cond);
+            state.push1(builder.ins().bor(from_arg1, from_arg2));
+        }
+        Operator::I8x16Neg => translate_vector_ineg(I8X16, builder, state),
+        Operator::I16x8Neg => translate_vector_ineg(I16X8, builder, state),
+        Operator::I32x4Neg => translate_vector_ineg(I32X4, builder, state),
+        Operator::I64x2Neg => translate_vector_ineg(I64X2, builder, state),
+        Operator::I8x16AnyTrue => translate_vector_test(false, I8X16, builder, state),
+        Operator::I16x8AnyTrue => translate_vector_test(false, I16X8, builder, state),
+        Operator::I32x4AnyTrue => translate_vector_test(false, I32X4, builder, state),
+        Operator::I64x2AnyTrue => translate_vector_test(false, I64X2, builder, state),
+        Operator::I8x16AllTrue => translate_vector_test(true, I8X16, builder, state),
+        Operator::I16x8AllTrue => translate_vector_test(true, I16X8, builder, state),
+        Operator::I32x4AllTrue => translate_vector_test(true, I32X4, builder, state),
+        Operator::I64x2AllTrue => translate_vector_test(true, I64X2, builder, state),
+        Operator::I8x16Shl => translate_vector_shift(ir::Opcode::Ishl, I8X16, builder, state),
+        Operator::I16x8Shl => translate_vector_shift(ir::Opcode::Ishl, I16X8, builder, state),
+        Operator::I32x4Shl => translate_vector_shift(ir::Opcode::Ishl, I32X4, builder, state),
+        Operator::I64x2Shl => translate_vector_shift(ir::Opcode::Ishl, I64X2, builder, state),
+        Operator::I8x16ShrS => translate_vector_shift(ir::Opcode::Sshr, I8X16, builder, state),
+        Operator::I16x8ShrS => translate_vector_shift(ir::Opcode::Sshr, I16X8, builder, state),
+        Operator::I32x4ShrS => translate_vector_shift(ir::Opcode::Sshr, I32X4, builder, state),
+        Operator::I64x2ShrS => translate_vector_shift(ir::Opcode::Sshr, I64X2, builder, state),
+        Operator::I8x16ShrU => translate_vector_shift(ir::Opcode::Ushr, I8X16, builder, state),
+        Operator::I16x8ShrU => translate_vector_shift(ir::Opcode::Ushr, I16X8, builder, state),
+        Operator::I32x4ShrU => translate_vector_shift(ir::Opcode::Ushr, I32X4, builder, state),
+        Operator::I64x2ShrU => translate_vector_shift(ir::Opcode::Ushr, I64X2, builder, state),
+        Operator::I8x16Add => translate_vector_binary(ir::Opcode::Iadd, I8X16, builder, state),
+        Operator::I16x8Add => translate_vector_binary(ir::Opcode::Iadd, I16X8, builder, state),
+        Operator::I32x4Add => translate_vector_binary(ir::Opcode::Iadd, I32X4, builder, state),
+        Operator::I64x2Add => translate_vector_binary(ir::Opcode::Iadd, I64X2, builder, state),
+        Operator::I8x16Sub => translate_vector_binary(ir::Opcode::Isub, I8X16, builder, state),
+        Operator::I16x8Sub => translate_vector_binary(ir::Opcode::Isub, I16X8, builder, state),
+        Operator::I32x4Sub => translate_vector_binary(ir::Opcode::Isub, I32X4, builder, state),
+        Operator::I64x2Sub => translate_vector_binary(ir::Opcode::Isub, I64X2, builder, state),
+        Operator::I8x16Mul => translate_vector_binary(ir::Opcode::Imul, I8X16, builder, state),
+        Operator::I16x8Mul => translate_vector_binary(ir::Opcode::Imul, I16X8, builder, state),
+        Operator::I32x4Mul => translate_vector_binary(ir::Opcode::Imul, I32X4, builder, state),
+        Operator::I8x16AddSaturateS => {
+            translate_vector_binary(ir::Opcode::SaddSat, I8X16, builder, state)
+        }
+        Operator::I16x8AddSaturateS => {
+            translate_vector_binary(ir::Opcode::SaddSat, I16X8, builder, state)
+        }
+        Operator::I8x16AddSaturateU => {
+            translate_vector_binary(ir::Opcode::UaddSat, I8X16, builder, state)
+        }
+        Operator::I16x8AddSaturateU => {
+            translate_vector_binary(ir::Opcode::UaddSat, I16X8, builder, state)
+        }
+        Operator::I8x16SubSaturateS => {
+            translate_vector_binary(ir::Opcode::SsubSat, I8X16, builder, state)
+        }
+        Operator::I16x8SubSaturateS => {
+            translate_vector_binary(ir::Opcode::SsubSat, I16X8, builder, state)
+        }
+        Operator::I8x16SubSaturateU => {
+            translate_vector_binary(ir::Opcode::UsubSat, I8X16, builder, state)
+        }
+        Operator::I16x8SubSaturateU => {
+            translate_vector_binary(ir::Opcode::UsubSat, I16X8, builder, state)
+        }
+        Operator::F32x4Abs => translate_vector_unary(ir::Opcode::Fabs, F32X4, builder, state),
+        Operator::F64x2Abs => translate_vector_unary(ir::Opcode::Fabs, F64X2, builder, state),
+        Operator::F32x4Neg => translate_vector_unary(ir::Opcode::Fneg, F32X4, builder, state),
+        Operator::F64x2Neg => translate_vector_unary(ir::Opcode::Fneg, F64X2, builder, state),
+        Operator::F32x4Sqrt => translate_vector_unary(ir::Opcode::Sqrt, F32X4, builder, state),
+        Operator::F64x2Sqrt => translate_vector_unary(ir::Opcode::Sqrt, F64X2, builder, state),
+        Operator::F32x4Add => translate_vector_binary(ir::Opcode::Fadd, F32X4, builder, state),
+        Operator::F64x2Add => translate_vector_binary(ir::Opcode::Fadd, F64X2, builder, state),
+        Operator::F32x4Sub => translate_vector_binary(ir::Opcode::Fsub, F32X4, builder, state),
+        Operator::F64x2Sub => translate_vector_binary(ir::Opcode::Fsub, F64X2, builder, state),
+        Operator::F32x4Mul => translate_vector_binary(ir::Opcode::Fmul, F32X4, builder, state),
+        Operator::F64x2Mul => translate_vector_binary(ir::Opcode::Fmul, F64X2, builder, state),
+        Operator::F32x4Div => translate_vector_binary(ir::Opcode::Fdiv, F32X4, builder, state),
+        Operator::F64x2Div => translate_vector_binary(ir::Opcode::Fdiv, F64X2, builder, state),
+        Operator::F32x4Min => translate_vector_binary(ir::Opcode::Fmin, F32X4, builder, state),
+        Operator::F64x2Min => translate_vector_binary(ir::Opcode::Fmin, F64X2, builder, state),
+        Operator::F32x4Max => translate_vector_binary(ir::Opcode::Fmax, F32X4, builder, state),
+        Operator::F64x2Max => translate_vector_binary(ir::Opcode::Fmax, F64X2, builder, state),
+        Operator::I32x4TruncSF32x4Sat => {
+            let arg = pop_vector(F32X4, builder, state);
+            let result = builder.ins().fcvt_to_sint_sat(I32X4, arg);
+            push_vector(result, builder, state);
+        }
+        Operator::I32x4TruncUF32x4Sat => {
+            let arg = pop_vector(F32X4, builder, state);
+            let result = builder.ins().fcvt_to_uint_sat(I32X4, arg);
+            push_vector(result, builder, state);
+        }
+        Operator::I64x2TruncSF64x2Sat => {
+            let arg = pop_vector(F64X2, builder, state);
+            let result = builder.ins().fcvt_to_sint_sat(I64X2, arg);
+            push_vector(result, builder, state);
+        }
+        Operator::I64x2TruncUF64x2Sat => {
+            let arg = pop_vector(F64X2, builder, state);
+            let result = builder.ins().fcvt_to_uint_sat(I64X2, arg);
+            push_vector(result, builder, state);
+        }
+        Operator::F32x4ConvertSI32x4 => {
+            let arg = pop_vector(I32X4, builder, state);
+            let result = builder.ins().fcvt_from_sint(F32X4, arg);
+            push_vector(result, builder, state);
+        }
+        Operator::F32x4ConvertUI32x4 => {
+            let arg = pop_vector(I32X4, builder, state);
+            let result = builder.ins().fcvt_from_uint(F32X4, arg);
+            push_vector(result, builder, state);
+        }
+        Operator::F64x2ConvertSI64x2 => {
+            let arg = pop_vector(I64X2, builder, state);
+            let result = builder.ins().fcvt_from_sint(F64X2, arg);
+            push_vector(result, builder, state);
+        }
+        Operator::F64x2ConvertUI64x2 => {
+            let arg = pop_vector(I64X2, builder, state);
+            let result = builder.ins().fcvt_from_uint(F64X2, arg);
+            push_vector(result, builder, state);
+        }
+        // The remaining SIMD operators aren't supported yet.
+        _ => {
+            return Err(WasmError::Unsupported("proposed SIMD operators"));
         }
     };
     Ok(())
@@ -975,10 +1958,168 @@ fn translate_unreachable_operator(
     }
 }
 
+/// An operator of the exception handling proposal.
+///
+/// The parser doesn't decode these operators yet, so the function translator reads them before
+/// handing the rest of the operators to the parser.
+#[derive(Debug, PartialEq)]
+pub enum ExceptionOperator {
+    Try { ty: wasmparser::Type },
+    Catch,
+    Throw { index: u32 },
+    Rethrow,
+    BrOnExn { relative_depth: u32, index: u32 },
+}
+
+/// Translates an operator of the exception handling proposal, like `translate_operator`.
+pub fn translate_exception_operator<FE: FuncEnvironment + ?Sized>(
+    op: ExceptionOperator,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    if !state.reachable {
+        match op {
+            // Push a placeholder control stack entry, like unreachable blocks.
+            ExceptionOperator::Try { .. } => state.push_try(ir::Ebb::reserved_value(), 0),
+            ExceptionOperator::Catch => translate_catch(builder, state, environ)?,
+            // We don't translate because this is unreachable code.
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    match op {
+        ExceptionOperator::Try { ty } => {
+            let next = builder.create_ebb();
+            if let Ok(ty_cre) = type_to_type(ty, environ.target_config()) {
+                builder.append_ebb_param(next, ty_cre);
+            }
+            state.push_try(next, num_return_values(ty));
+        }
+        ExceptionOperator::Catch => translate_catch(builder, state, environ)?,
+        ExceptionOperator::Throw { index } => {
+            let index = EventIndex::from_u32(index);
+            let arity = environ.event_arity(index)?;
+            let call = environ.translate_throw(builder.cursor(), index, state.peekn(arity))?;
+            state.popn(arity);
+            translate_unwinding_call(call, builder, state)?;
+            // The runtime function never returns.
+            builder.ins().trap(ir::TrapCode::UnreachableCodeReached);
+            state.reachable = false;
+        }
+        ExceptionOperator::Rethrow => {
+            let exn = state.pop1();
+            let call = environ.translate_rethrow(builder.cursor(), exn)?;
+            translate_unwinding_call(call, builder, state)?;
+            builder.ins().trap(ir::TrapCode::UnreachableCodeReached);
+            state.reachable = false;
+        }
+        ExceptionOperator::BrOnExn {
+            relative_depth,
+            index,
+        } => {
+            let index = EventIndex::from_u32(index);
+            let exn = state.pop1();
+            let matches = environ.translate_exception_matches(builder.cursor(), exn, index)?;
+            let unpack = builder.create_ebb();
+            let next = builder.create_ebb();
+            builder.ins().brnz(matches, unpack, &[]);
+            builder.ins().jump(next, &[]);
+
+            // Branch to the label with the values of the exception when the event matches.
+            builder.switch_to_block(unpack);
+            builder.seal_block(unpack);
+            let values = environ.translate_exception_values(builder.cursor(), exn, index)?;
+            let br_destination = {
+                let i = state.control_stack.len() - 1 - (relative_depth as usize);
+                let frame = &mut state.control_stack[i];
+                frame.set_branched_to_exit();
+                frame.br_destination()
+            };
+            builder.ins().jump(br_destination, &values);
+
+            // Otherwise the exception stays on the stack.
+            builder.switch_to_block(next);
+            builder.seal_block(next);
+            state.push1(exn);
+        }
+    }
+    Ok(())
+}
+
+/// Translate the `catch` of the innermost `try`, which ends its body.
+///
+/// The `catch` clause starts at the landing pad of the `try`. It is unreachable if nothing in the
+/// body can unwind to it.
+fn translate_catch<FE: FuncEnvironment + ?Sized>(
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    let i = state.control_stack.len() - 1;
+    let (destination, return_count, landing_pad) = match state.control_stack[i] {
+        ControlStackFrame::Try {
+            destination,
+            num_return_values,
+            landing_pad,
+            ..
+        } => (destination, num_return_values, landing_pad),
+        _ => panic!("should not happen"),
+    };
+    if let ControlStackFrame::Try {
+        ref mut in_catch, ..
+    } = state.control_stack[i]
+    {
+        *in_catch = true;
+    }
+
+    if state.reachable {
+        // The end of the body jumps over the catch clause.
+        builder.ins().jump(destination, state.peekn(return_count));
+        state.control_stack[i].set_branched_to_exit();
+    }
+    state
+        .stack
+        .truncate(state.control_stack[i].original_stack_size());
+
+    match landing_pad {
+        Some(landing_pad) => {
+            builder.switch_to_block(landing_pad);
+            // All the calls unwinding to the landing pad are in the body of the try.
+            builder.seal_block(landing_pad);
+            let exn = environ.translate_catch(builder.cursor())?;
+            state.push1(exn);
+            state.reachable = true;
+        }
+        None => state.reachable = false,
+    }
+    Ok(())
+}
+
+/// Make the call `call` unwind to the landing pad of the enclosing `try`, if there is one.
+///
+/// Only direct calls can be converted to `try_call` instructions.
+fn translate_unwinding_call(
+    call: ir::Inst,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) -> WasmResult<()> {
+    if let Some(landing_pad) = state.landing_pad(|| builder.create_ebb()) {
+        if builder.func.dfg[call].opcode() != ir::Opcode::Call {
+            return Err(WasmError::Unsupported("indirect calls in try"));
+        }
+        builder.convert_to_try_call(call, landing_pad);
+    }
+    Ok(())
+}
+
 /// Get the address+offset to use for a heap access.
+///
+/// The address `addr` has the index type of the heap, which is `i64` for 64-bit memories.
 fn get_heap_addr(
     heap: ir::Heap,
-    addr32: ir::Value,
+    addr: ir::Value,
     offset: u32,
     addr_ty: Type,
     builder: &mut FunctionBuilder,
@@ -999,7 +2140,7 @@ fn get_heap_addr(
     // even if the access goes beyond the offset-guard pages. This is because the first byte
     // pointed to is inside the offset-guard pages.
     let check_size = min(u64::from(u32::MAX), 1 + adjusted_offset) as u32;
-    let base = builder.ins().heap_addr(addr_ty, heap, addr32, check_size);
+    let base = builder.ins().heap_addr(addr_ty, heap, addr, check_size);
 
     // Native load/store instructions take a signed `Offset32` immediate, so adjust the base
     // pointer if necessary.
@@ -1014,21 +2155,23 @@ fn get_heap_addr(
 
 /// Translate a load instruction.
 fn translate_load<FE: FuncEnvironment + ?Sized>(
-    offset: u32,
+    memory: u32,
+    memarg: MemoryImmediate,
     opcode: ir::Opcode,
     result_ty: Type,
     builder: &mut FunctionBuilder,
     state: &mut TranslationState,
     environ: &mut FE,
 ) {
-    let addr32 = state.pop1();
-    // We don't yet support multiple linear memories.
-    let heap = state.get_heap(builder.func, 0, environ);
-    let (base, offset) = get_heap_addr(heap, addr32, offset, environ.pointer_type(), builder);
+    let addr = state.pop1();
+    let heap = state.get_heap(builder.func, memory, environ);
+    let (base, offset) = get_heap_addr(heap, addr, memarg.offset, environ.pointer_type(), builder);
     // Note that we don't set `is_aligned` here, even if the load instruction's
     // alignment immediate says it's aligned, because WebAssembly's immediate
     // field is just a hint, while Cranelift's aligned flag needs a guarantee.
-    let flags = MemFlags::new();
+    // WebAssembly memory is always little-endian, regardless of the host.
+    let mut flags = MemFlags::new();
+    flags.set_endianness(Endianness::Little);
     let (load, dfg) = builder
         .ins()
         .Load(opcode, result_ty, flags, offset.into(), base);
@@ -1037,25 +2180,311 @@ fn translate_load<FE: FuncEnvironment + ?Sized>(
 
 /// Translate a store instruction.
 fn translate_store<FE: FuncEnvironment + ?Sized>(
-    offset: u32,
+    memory: u32,
+    memarg: MemoryImmediate,
     opcode: ir::Opcode,
     builder: &mut FunctionBuilder,
     state: &mut TranslationState,
     environ: &mut FE,
 ) {
-    let (addr32, val) = state.pop2();
+    let (addr, val) = state.pop2();
     let val_ty = builder.func.dfg.value_type(val);
 
-    // We don't yet support multiple linear memories.
-    let heap = state.get_heap(builder.func, 0, environ);
-    let (base, offset) = get_heap_addr(heap, addr32, offset, environ.pointer_type(), builder);
+    let heap = state.get_heap(builder.func, memory, environ);
+    let (base, offset) = get_heap_addr(heap, addr, memarg.offset, environ.pointer_type(), builder);
     // See the comments in `translate_load` about the flags.
-    let flags = MemFlags::new();
+    let mut flags = MemFlags::new();
+    flags.set_endianness(Endianness::Little);
     builder
         .ins()
         .Store(opcode, val_ty, flags, offset.into(), val, base);
 }
 
+/// Bounds check the address `addr` of an atomic access of type `access_ty` in the linear memory
+/// `memory`, and get the native address of the access.
+///
+/// Unlike other accesses, atomic accesses trap when their effective address is not aligned to the
+/// size of the accessed type.
+fn translate_atomic_addr<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    memarg: MemoryImmediate,
+    access_ty: Type,
+    addr: ir::Value,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> ir::Value {
+    let size = access_ty.bytes();
+    if size > 1 {
+        // Wrapping around when adding the offset doesn't change the low bits of the address.
+        let effective = builder.ins().iadd_imm(addr, i64::from(memarg.offset));
+        let misaligned = builder.ins().band_imm(effective, i64::from(size - 1));
+        builder
+            .ins()
+            .trapnz(misaligned, ir::TrapCode::HeapMisaligned);
+    }
+    let heap = state.get_heap(builder.func, memory, environ);
+    let (base, offset) = get_heap_addr(heap, addr, memarg.offset, environ.pointer_type(), builder);
+    // The atomic instructions don't have an offset immediate.
+    if offset == 0 {
+        base
+    } else {
+        builder.ins().iadd_imm(base, i64::from(offset))
+    }
+}
+
+/// Get the flags of an atomic access. WebAssembly memory is always little-endian.
+fn atomic_flags() -> MemFlags {
+    let mut flags = MemFlags::new();
+    flags.set_endianness(Endianness::Little);
+    flags
+}
+
+/// Narrow the integer `val` to `ty`, unless it already has that type.
+fn reduce_to(ty: Type, val: ir::Value, builder: &mut FunctionBuilder) -> ir::Value {
+    if builder.func.dfg.value_type(val) == ty {
+        val
+    } else {
+        builder.ins().ireduce(ty, val)
+    }
+}
+
+/// Translate an atomic load of `access_ty`, zero-extended to `result_ty`.
+fn translate_atomic_load<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    memarg: MemoryImmediate,
+    access_ty: Type,
+    result_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) {
+    let addr = state.pop1();
+    let addr = translate_atomic_addr(memory, memarg, access_ty, addr, builder, state, environ);
+    let val = builder.ins().atomic_load(access_ty, atomic_flags(), addr);
+    state.push1(extend_to(result_ty, val, builder));
+}
+
+/// Translate an atomic store of the low bits of a value to `access_ty`.
+fn translate_atomic_store<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    memarg: MemoryImmediate,
+    access_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) {
+    let (addr, val) = state.pop2();
+    let addr = translate_atomic_addr(memory, memarg, access_ty, addr, builder, state, environ);
+    let val = reduce_to(access_ty, val, builder);
+    builder.ins().atomic_store(atomic_flags(), val, addr);
+}
+
+/// Translate an atomic read-modify-write of `access_ty`, returning the old value zero-extended to
+/// `result_ty`.
+fn translate_atomic_rmw<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    memarg: MemoryImmediate,
+    op: AtomicRmwOp,
+    access_ty: Type,
+    result_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) {
+    let (addr, arg) = state.pop2();
+    let addr = translate_atomic_addr(memory, memarg, access_ty, addr, builder, state, environ);
+    let arg = reduce_to(access_ty, arg, builder);
+    let old = builder
+        .ins()
+        .atomic_rmw(access_ty, atomic_flags(), op, addr, arg);
+    state.push1(extend_to(result_ty, old, builder));
+}
+
+/// Translate an atomic compare and exchange of `access_ty`, returning the old value zero-extended
+/// to `result_ty`.
+///
+/// The expected and replacement values are wrapped to `access_ty`.
+fn translate_atomic_cas<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    memarg: MemoryImmediate,
+    access_ty: Type,
+    result_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) {
+    let (addr, expected, replacement) = state.pop3();
+    let addr = translate_atomic_addr(memory, memarg, access_ty, addr, builder, state, environ);
+    let expected = reduce_to(access_ty, expected, builder);
+    let replacement = reduce_to(access_ty, replacement, builder);
+    let old = builder
+        .ins()
+        .atomic_cas(access_ty, atomic_flags(), addr, expected, replacement);
+    state.push1(extend_to(result_ty, old, builder));
+}
+
+/// Translate an `i32.atomic.wait` or `i64.atomic.wait` instruction, waiting on a value of
+/// `access_ty`.
+fn translate_atomic_wait<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    memarg: MemoryImmediate,
+    access_ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    let (addr, expected, timeout) = state.pop3();
+    let addr = translate_atomic_addr(memory, memarg, access_ty, addr, builder, state, environ);
+    let heap = state.get_heap(builder.func, memory, environ);
+    let res = environ.translate_atomic_wait(
+        builder.cursor(),
+        MemoryIndex::from_u32(memory),
+        heap,
+        addr,
+        expected,
+        timeout,
+    )?;
+    state.push1(res);
+    Ok(())
+}
+
+/// Translate an `atomic.notify` instruction.
+fn translate_atomic_notify<FE: FuncEnvironment + ?Sized>(
+    memory: u32,
+    memarg: MemoryImmediate,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<()> {
+    let (addr, count) = state.pop2();
+    let addr = translate_atomic_addr(memory, memarg, I32, addr, builder, state, environ);
+    let heap = state.get_heap(builder.func, memory, environ);
+    let res = environ.translate_atomic_notify(
+        builder.cursor(),
+        MemoryIndex::from_u32(memory),
+        heap,
+        addr,
+        count,
+    )?;
+    state.push1(res);
+    Ok(())
+}
+
+/// Bounds check the `len` bytes starting at `addr` in the linear memory `index`, and get the
+/// native address of the first byte.
+///
+/// The range is checked against the current size of the memory, trapping with `HeapOutOfBounds`
+/// if any part of it is outside.
+fn translate_memory_range<FE: FuncEnvironment + ?Sized>(
+    index: u32,
+    addr: ir::Value,
+    len: ir::Value,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+    environ: &mut FE,
+) -> WasmResult<ir::Value> {
+    let heap = state.get_heap(builder.func, index, environ);
+    let pages =
+        environ.translate_memory_size(builder.cursor(), MemoryIndex::from_u32(index), heap)?;
+    let (end, size) = if builder.func.heaps[heap].index_type == I64 {
+        // The end of the range may overflow a 64-bit address.
+        let len = extend_to(I64, len, builder);
+        let (end, overflow) = builder.ins().iadd_cout(addr, len);
+        builder
+            .ins()
+            .trapnz(overflow, ir::TrapCode::HeapOutOfBounds);
+        (end, builder.ins().ishl_imm(pages, 16))
+    } else {
+        let addr = builder.ins().uextend(I64, addr);
+        let len = extend_to(I64, len, builder);
+        let pages = builder.ins().uextend(I64, pages);
+        (
+            builder.ins().iadd(addr, len),
+            builder.ins().ishl_imm(pages, 16),
+        )
+    };
+    let out_of_bounds = builder.ins().icmp(IntCC::UnsignedGreaterThan, end, size);
+    builder
+        .ins()
+        .trapnz(out_of_bounds, ir::TrapCode::HeapOutOfBounds);
+    Ok(builder
+        .ins()
+        .heap_addr(environ.pointer_type(), heap, addr, 0))
+}
+
+/// Zero-extend the integer `val` to `ty`, unless it already has that type.
+fn extend_to(ty: Type, val: ir::Value, builder: &mut FunctionBuilder) -> ir::Value {
+    if builder.func.dfg.value_type(val) == ty {
+        val
+    } else {
+        builder.ins().uextend(ty, val)
+    }
+}
+
+/// Convert the length of a bounds checked memory range to the native pointer type.
+fn native_length<FE: FuncEnvironment + ?Sized>(
+    len: ir::Value,
+    builder: &mut FunctionBuilder,
+    environ: &FE,
+) -> ir::Value {
+    let pointer_type = environ.pointer_type();
+    if builder.func.dfg.value_type(len).bits() > pointer_type.bits() {
+        builder.ins().ireduce(pointer_type, len)
+    } else {
+        extend_to(pointer_type, len, builder)
+    }
+}
+
+/// Emit a loop calling `body` with each index in `0..len`, in increasing order.
+///
+/// The index has the type of `len`.
+fn emit_byte_loop<F>(len: ir::Value, builder: &mut FunctionBuilder, body: F)
+where
+    F: Fn(&mut FunctionBuilder, ir::Value),
+{
+    let ty = builder.func.dfg.value_type(len);
+    let loop_ebb = builder.create_ebb();
+    let done_ebb = builder.create_ebb();
+    let i = builder.append_ebb_param(loop_ebb, ty);
+    builder.ins().brz(len, done_ebb, &[]);
+    let zero = builder.ins().iconst(ty, 0);
+    builder.ins().jump(loop_ebb, &[zero]);
+
+    builder.switch_to_block(loop_ebb);
+    body(builder, i);
+    let next = builder.ins().iadd_imm(i, 1);
+    let more = builder.ins().icmp(IntCC::UnsignedLessThan, next, len);
+    builder.ins().brnz(more, loop_ebb, &[next]);
+    builder.ins().jump(done_ebb, &[]);
+    builder.seal_block(loop_ebb);
+
+    builder.switch_to_block(done_ebb);
+    builder.seal_block(done_ebb);
+}
+
+/// Emit a loop copying `len` bytes from `src` to `dst` one at a time, like `memmove`.
+///
+/// The bytes are copied backwards when `dst` is above `src`, so overlapping ranges are handled
+/// correctly.
+fn emit_memmove_loop(
+    dst: ir::Value,
+    src: ir::Value,
+    len: ir::Value,
+    builder: &mut FunctionBuilder,
+) {
+    let backward = builder.ins().icmp(IntCC::UnsignedGreaterThan, dst, src);
+    let last = builder.ins().iadd_imm(len, -1);
+    emit_byte_loop(len, builder, |builder, i| {
+        let reversed = builder.ins().isub(last, i);
+        let j = builder.ins().select(backward, reversed, i);
+        let from = builder.ins().iadd(src, j);
+        let to = builder.ins().iadd(dst, j);
+        let byte = builder.ins().uload8(I32, MemFlags::new(), from, 0);
+        builder.ins().istore8(MemFlags::new(), byte, to, 0);
+    });
+}
+
 fn translate_icmp(cc: IntCC, builder: &mut FunctionBuilder, state: &mut TranslationState) {
     let (arg0, arg1) = state.pop2();
     let val = builder.ins().icmp(cc, arg0, arg1);
@@ -1068,6 +2497,183 @@ fn translate_fcmp(cc: FloatCC, builder: &mut FunctionBuilder, state: &mut Transl
     state.push1(builder.ins().bint(I32, val));
 }
 
+/// Reinterpret `value` as a vector of type `ty`, if it isn't one already.
+fn bitcast_vector(value: ir::Value, ty: Type, builder: &mut FunctionBuilder) -> ir::Value {
+    if builder.func.dfg.value_type(value) == ty {
+        value
+    } else {
+        builder.ins().bitcast(ty, value)
+    }
+}
+
+/// Pop a `v128` value and reinterpret it as a vector of type `ty`.
+fn pop_vector(ty: Type, builder: &mut FunctionBuilder, state: &mut TranslationState) -> ir::Value {
+    let value = state.pop1();
+    bitcast_vector(value, ty, builder)
+}
+
+/// Push the vector `value` as a `v128` value.
+fn push_vector(value: ir::Value, builder: &mut FunctionBuilder, state: &mut TranslationState) {
+    let value = bitcast_vector(value, I8X16, builder);
+    state.push1(value);
+}
+
+/// Materialize the 16 little-endian `bytes` as an `i8x16` vector.
+fn translate_vector_const(bytes: &[u8; 16], builder: &mut FunctionBuilder) -> ir::Value {
+    let mut low = [0; 8];
+    let mut high = [0; 8];
+    low.copy_from_slice(&bytes[..8]);
+    high.copy_from_slice(&bytes[8..]);
+    let low = builder.ins().iconst(I64, i64::from_le_bytes(low));
+    let high = builder.ins().iconst(I64, i64::from_le_bytes(high));
+    let vector = builder.ins().splat(I64X2, low);
+    let vector = builder.ins().insertlane(vector, 1, high);
+    bitcast_vector(vector, I8X16, builder)
+}
+
+/// Translate a splat of the scalar on top of the stack. Lanes narrower than `i32` are taken from
+/// the low bits of the `i32` operand.
+fn translate_splat(ty: Type, builder: &mut FunctionBuilder, state: &mut TranslationState) {
+    let arg = state.pop1();
+    let arg = if ty.lane_bits() < 32 {
+        builder.ins().ireduce(ty.lane_type(), arg)
+    } else {
+        arg
+    };
+    let result = builder.ins().splat(ty, arg);
+    push_vector(result, builder, state);
+}
+
+fn translate_extract_lane(
+    ty: Type,
+    lane: u8,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let vector = pop_vector(ty, builder, state);
+    state.push1(builder.ins().extractlane(vector, lane));
+}
+
+fn translate_replace_lane(
+    ty: Type,
+    lane: u8,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let (vector, arg) = state.pop2();
+    let vector = bitcast_vector(vector, ty, builder);
+    let arg = if ty.lane_bits() < 32 {
+        builder.ins().ireduce(ty.lane_type(), arg)
+    } else {
+        arg
+    };
+    let result = builder.ins().insertlane(vector, lane, arg);
+    push_vector(result, builder, state);
+}
+
+/// Translate a lane-wise integer comparison. Each lane of the result is all ones if the
+/// comparison is true, and zero otherwise.
+fn translate_vector_icmp(
+    cc: IntCC,
+    ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let (arg0, arg1) = state.pop2();
+    let arg0 = bitcast_vector(arg0, ty, builder);
+    let arg1 = bitcast_vector(arg1, ty, builder);
+    let val = builder.ins().icmp(cc, arg0, arg1);
+    let result = builder.ins().bmask(ty, val);
+    push_vector(result, builder, state);
+}
+
+/// Translate a lane-wise floating point comparison, producing a mask like
+/// `translate_vector_icmp`.
+fn translate_vector_fcmp(
+    cc: FloatCC,
+    ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let (arg0, arg1) = state.pop2();
+    let arg0 = bitcast_vector(arg0, ty, builder);
+    let arg1 = bitcast_vector(arg1, ty, builder);
+    let val = builder.ins().fcmp(cc, arg0, arg1);
+    let mask_ty = Type::int(u16::from(ty.lane_bits()))
+        .and_then(|t| t.by(ty.lane_count()))
+        .expect("invalid float vector type");
+    let result = builder.ins().bmask(mask_ty, val);
+    push_vector(result, builder, state);
+}
+
+fn translate_vector_ineg(ty: Type, builder: &mut FunctionBuilder, state: &mut TranslationState) {
+    let arg = pop_vector(ty, builder, state);
+    let zero = builder.ins().iconst(ty.lane_type(), 0);
+    let zero = builder.ins().splat(ty, zero);
+    let result = builder.ins().isub(zero, arg);
+    push_vector(result, builder, state);
+}
+
+/// Translate an `any_true` or `all_true` test, producing an `i32`.
+fn translate_vector_test(
+    all: bool,
+    ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let arg = pop_vector(ty, builder, state);
+    let val = if all {
+        builder.ins().vall_true(arg)
+    } else {
+        builder.ins().vany_true(arg)
+    };
+    state.push1(builder.ins().bint(I32, val));
+}
+
+/// Translate a shift of every lane by the same `i32` amount. Wasm takes the amount modulo the lane
+/// width.
+fn translate_vector_shift(
+    opcode: ir::Opcode,
+    ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let (vector, amount) = state.pop2();
+    let vector = bitcast_vector(vector, ty, builder);
+    let amount = builder
+        .ins()
+        .band_imm(amount, i64::from(ty.lane_bits() - 1));
+    let (inst, dfg) = builder.ins().Binary(opcode, ty, vector, amount);
+    let result = dfg.first_result(inst);
+    push_vector(result, builder, state);
+}
+
+fn translate_vector_unary(
+    opcode: ir::Opcode,
+    ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let arg = pop_vector(ty, builder, state);
+    let (inst, dfg) = builder.ins().Unary(opcode, ty, arg);
+    let result = dfg.first_result(inst);
+    push_vector(result, builder, state);
+}
+
+fn translate_vector_binary(
+    opcode: ir::Opcode,
+    ty: Type,
+    builder: &mut FunctionBuilder,
+    state: &mut TranslationState,
+) {
+    let (arg0, arg1) = state.pop2();
+    let arg0 = bitcast_vector(arg0, ty, builder);
+    let arg1 = bitcast_vector(arg1, ty, builder);
+    let (inst, dfg) = builder.ins().Binary(opcode, ty, arg0, arg1);
+    let result = dfg.first_result(inst);
+    push_vector(result, builder, state);
+}
+
 fn translate_br_if(
     relative_depth: u32,
     builder: &mut FunctionBuilder,
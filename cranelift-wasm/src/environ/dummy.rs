@@ -7,9 +7,11 @@
 
 use crate::environ::{FuncEnvironment, GlobalVariable, ModuleEnvironment, ReturnMode, WasmResult};
 use crate::func_translator::FuncTranslator;
+#[cfg(feature = "std")]
+use crate::func_translator::{translate_function_bodies, FunctionBodyData};
 use crate::translation_utils::{
-    DefinedFuncIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex, SignatureIndex, Table,
-    TableIndex,
+    call_table_element, DefinedFuncIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex,
+    SignatureIndex, Table, TableIndex,
 };
 use cast;
 use cranelift_codegen::cursor::FuncCursor;
@@ -121,6 +123,13 @@ pub struct DummyEnvironment {
 
     /// How to return from functions.
     return_mode: ReturnMode,
+
+    /// Number of threads translating the function bodies, or 1 to translate each body as soon as
+    /// it is provided.
+    translation_threads: usize,
+
+    /// Function bodies waiting to be translated by multiple threads, with their offsets.
+    pending_bodies: Vec<(Vec<u8>, usize)>,
 }
 
 impl DummyEnvironment {
@@ -131,9 +140,18 @@ impl DummyEnvironment {
             trans: FuncTranslator::new(),
             func_bytecode_sizes: Vec::new(),
             return_mode,
+            translation_threads: 1,
+            pending_bodies: Vec::new(),
         }
     }
 
+    /// Translate the function bodies with `num_threads` threads once the whole code section has
+    /// been read, instead of translating them one by one.
+    #[cfg(feature = "std")]
+    pub fn set_translation_threads(&mut self, num_threads: usize) {
+        self.translation_threads = num_threads;
+    }
+
     /// Return a `DummyFuncEnvironment` for translating functions within this
     /// `DummyEnvironment`.
     pub fn func_env(&self) -> DummyFuncEnvironment {
@@ -175,6 +193,42 @@ impl<'dummy_environment> DummyFuncEnvironment<'dummy_environment> {
         ));
         sig
     }
+
+    // Call the runtime function `libcall` with the current function's `vmctx` parameter followed
+    // by `args`, returning values of the types `returns`.
+    fn call_libcall(
+        &self,
+        pos: &mut FuncCursor,
+        libcall: ir::LibCall,
+        args: &[ir::Value],
+        returns: &[ir::Type],
+    ) -> ir::Inst {
+        let vmctx = pos
+            .func
+            .special_param(ir::ArgumentPurpose::VMContext)
+            .expect("Missing vmctx parameter");
+        let mut sig = ir::Signature::new(self.target_config().default_call_conv);
+        sig.params.push(ir::AbiParam::special(
+            self.pointer_type(),
+            ir::ArgumentPurpose::VMContext,
+        ));
+        for &arg in args {
+            sig.params
+                .push(ir::AbiParam::new(pos.func.dfg.value_type(arg)));
+        }
+        for &ty in returns {
+            sig.returns.push(ir::AbiParam::new(ty));
+        }
+        let signature = pos.func.import_signature(sig);
+        let callee = pos.func.import_function(ir::ExtFuncData {
+            name: ir::ExternalName::LibCall(libcall),
+            signature,
+            colocated: false,
+        });
+        let mut call_args = vec![vmctx];
+        call_args.extend_from_slice(args);
+        pos.ins().call(callee, &call_args)
+    }
 }
 
 impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environment> {
@@ -193,15 +247,20 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
         }
     }
 
-    fn make_heap(&mut self, func: &mut ir::Function, _index: MemoryIndex) -> ir::Heap {
-        // Create a static heap whose base address is stored at `vmctx+0`.
+    fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> ir::Heap {
+        // Create a static heap whose base address is stored at `vmctx+8*index`.
         let addr = func.create_global_value(ir::GlobalValueData::VMContext);
         let gv = func.create_global_value(ir::GlobalValueData::Load {
             base: addr,
-            offset: Offset32::new(0),
+            offset: cast::i32(index.index() * 8).unwrap().into(),
             global_type: self.pointer_type(),
             readonly: true,
         });
+        let index_type = if self.mod_info.memories[index].entity.memory64 {
+            I64
+        } else {
+            I32
+        };
 
         func.create_heap(ir::HeapData {
             base: gv,
@@ -210,7 +269,8 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
             style: ir::HeapStyle::Static {
                 bound: 0x1_0000_0000.into(),
             },
-            index_type: I32,
+            index_type,
+            shared: self.mod_info.memories[index].entity.shared,
         })
     }
 
@@ -262,8 +322,8 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
         &mut self,
         mut pos: FuncCursor,
         _table_index: TableIndex,
-        _table: ir::Table,
-        _sig_index: SignatureIndex,
+        table: ir::Table,
+        sig_index: SignatureIndex,
         sig_ref: ir::SigRef,
         callee: ir::Value,
         call_args: &[ir::Value],
@@ -274,30 +334,15 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
             .special_param(ir::ArgumentPurpose::VMContext)
             .expect("Missing vmctx parameter");
 
-        // The `callee` value is an index into a table of function pointers.
-        // Apparently, that table is stored at absolute address 0 in this dummy environment.
-        // TODO: Generate bounds checking code.
-        let ptr = self.pointer_type();
-        let callee_offset = if ptr == I32 {
-            pos.ins().imul_imm(callee, 4)
-        } else {
-            let ext = pos.ins().uextend(I64, callee);
-            pos.ins().imul_imm(ext, 4)
-        };
-        let mflags = ir::MemFlags::trusted();
-        let func_ptr = pos.ins().load(ptr, mflags, callee_offset, 0);
+        // The signature ids are simply the signature indices.
+        let sig_id = pos.ins().iconst(I32, i64::from(sig_index.as_u32()));
 
-        // Build a value list for the indirect call instruction containing the callee, call_args,
-        // and the vmctx parameter.
-        let mut args = ir::ValueList::default();
-        args.push(func_ptr, &mut pos.func.dfg.value_lists);
-        args.extend(call_args.iter().cloned(), &mut pos.func.dfg.value_lists);
-        args.push(vmctx, &mut pos.func.dfg.value_lists);
-
-        Ok(pos
-            .ins()
-            .CallIndirect(ir::Opcode::CallIndirect, INVALID, sig_ref, args)
-            .0)
+        let mut args = call_args.to_vec();
+        args.push(vmctx);
+        let ptr = self.pointer_type();
+        Ok(call_table_element(
+            &mut pos, table, callee, ptr, sig_ref, sig_id, &args,
+        ))
     }
 
     fn translate_call(
@@ -326,19 +371,130 @@ impl<'dummy_environment> FuncEnvironment for DummyFuncEnvironment<'dummy_environ
         &mut self,
         mut pos: FuncCursor,
         _index: MemoryIndex,
-        _heap: ir::Heap,
+        heap: ir::Heap,
         _val: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Ok(pos.ins().iconst(I32, -1))
+        let index_type = pos.func.heaps[heap].index_type;
+        Ok(pos.ins().iconst(index_type, -1))
     }
 
     fn translate_memory_size(
         &mut self,
         mut pos: FuncCursor,
         _index: MemoryIndex,
+        heap: ir::Heap,
+    ) -> WasmResult<ir::Value> {
+        let index_type = pos.func.heaps[heap].index_type;
+        Ok(pos.ins().iconst(index_type, -1))
+    }
+
+    fn translate_memory_init(
+        &mut self,
+        mut pos: FuncCursor,
+        index: MemoryIndex,
         _heap: ir::Heap,
+        segment: u32,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        let index = pos.ins().iconst(I32, i64::from(index.as_u32()));
+        let segment = pos.ins().iconst(I32, i64::from(segment));
+        self.call_libcall(
+            &mut pos,
+            ir::LibCall::WasmMemoryInit,
+            &[index, segment, dst, src, len],
+            &[],
+        );
+        Ok(())
+    }
+
+    fn translate_data_drop(&mut self, mut pos: FuncCursor, segment: u32) -> WasmResult<()> {
+        let segment = pos.ins().iconst(I32, i64::from(segment));
+        self.call_libcall(&mut pos, ir::LibCall::WasmDataDrop, &[segment], &[]);
+        Ok(())
+    }
+
+    fn translate_atomic_wait(
+        &mut self,
+        mut pos: FuncCursor,
+        index: MemoryIndex,
+        _heap: ir::Heap,
+        addr: ir::Value,
+        expected: ir::Value,
+        timeout: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        let libcall = if pos.func.dfg.value_type(expected) == I64 {
+            ir::LibCall::WasmAtomicWait64
+        } else {
+            ir::LibCall::WasmAtomicWait32
+        };
+        let index = pos.ins().iconst(I32, i64::from(index.as_u32()));
+        let call = self.call_libcall(&mut pos, libcall, &[index, addr, expected, timeout], &[I32]);
+        Ok(pos.func.dfg.first_result(call))
+    }
+
+    fn translate_atomic_notify(
+        &mut self,
+        mut pos: FuncCursor,
+        index: MemoryIndex,
+        _heap: ir::Heap,
+        addr: ir::Value,
+        count: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        let index = pos.ins().iconst(I32, i64::from(index.as_u32()));
+        let call = self.call_libcall(
+            &mut pos,
+            ir::LibCall::WasmAtomicNotify,
+            &[index, addr, count],
+            &[I32],
+        );
+        Ok(pos.func.dfg.first_result(call))
+    }
+
+    fn translate_table_copy(
+        &mut self,
+        mut pos: FuncCursor,
+        table_index: TableIndex,
+        _table: ir::Table,
+        dst: ir::Value,
+        src: ir::Value,
+        len: ir::Value,
+    ) -> WasmResult<()> {
+        let table_index = pos.ins().iconst(I32, i64::from(table_index.as_u32()));
+        self.call_libcall(
+            &mut pos,
+            ir::LibCall::WasmTableCopy,
+            &[table_index, dst, src, len],
+            &[],
+        );
+        Ok(())
+    }
+
+    fn translate_table_get(
+        &mut self,
+        mut pos: FuncCursor,
+        _table_index: TableIndex,
+        table: ir::Table,
+        index: ir::Value,
     ) -> WasmResult<ir::Value> {
-        Ok(pos.ins().iconst(I32, -1))
+        // The references are stored at the start of the table elements.
+        let element = pos.ins().table_addr(self.pointer_type(), table, index, 0);
+        let ref_ty = self.reference_type();
+        Ok(pos.ins().load(ref_ty, ir::MemFlags::trusted(), element, 0))
+    }
+
+    fn translate_table_set(
+        &mut self,
+        mut pos: FuncCursor,
+        _table_index: TableIndex,
+        table: ir::Table,
+        value: ir::Value,
+        index: ir::Value,
+    ) -> WasmResult<()> {
+        let element = pos.ins().table_addr(self.pointer_type(), table, index, 0);
+        pos.ins().store(ir::MemFlags::trusted(), value, element, 0);
+        Ok(())
     }
 
     fn return_mode(&self) -> ReturnMode {
@@ -463,6 +619,11 @@ impl<'data> ModuleEnvironment<'data> for DummyEnvironment {
         body_bytes: &'data [u8],
         body_offset: usize,
     ) -> WasmResult<()> {
+        if self.translation_threads > 1 {
+            self.pending_bodies.push((body_bytes.to_vec(), body_offset));
+            self.func_bytecode_sizes.push(body_bytes.len());
+            return Ok(());
+        }
         let func = {
             let mut func_environ = DummyFuncEnvironment::new(&self.info, self.return_mode);
             let func_index =
@@ -478,4 +639,31 @@ impl<'data> ModuleEnvironment<'data> for DummyEnvironment {
         self.info.function_bodies.push(func);
         Ok(())
     }
+
+    #[cfg(feature = "std")]
+    fn finish_function_bodies(&mut self) -> WasmResult<()> {
+        let bodies: Vec<_> = self
+            .pending_bodies
+            .iter()
+            .map(|&(ref data, offset)| FunctionBodyData { data, offset })
+            .collect();
+        let info = &self.info;
+        let return_mode = self.return_mode;
+        let num_imports = self.get_num_func_imports();
+        let funcs =
+            translate_function_bodies(&bodies, self.translation_threads, |trans, index, body| {
+                let mut func_environ = DummyFuncEnvironment::new(info, return_mode);
+                let func_index = FuncIndex::new(num_imports + index.index());
+                let name = get_func_name(func_index);
+                let sig = func_environ.vmctx_sig(info.functions[func_index].entity);
+                let mut func = ir::Function::with_name_signature(name, sig);
+                trans.translate(body.data, body.offset, &mut func, &mut func_environ)?;
+                Ok(func)
+            })?;
+        self.pending_bodies.clear();
+        for func in funcs {
+            self.info.function_bodies.push(func);
+        }
+        Ok(())
+    }
 }
@@ -5,5 +5,6 @@ mod spec;
 
 pub use crate::environ::dummy::DummyEnvironment;
 pub use crate::environ::spec::{
-    FuncEnvironment, GlobalVariable, ModuleEnvironment, ReturnMode, WasmError, WasmResult,
+    BulkMemoryStrategy, FuncEnvironment, GlobalVariable, ModuleEnvironment, ReturnMode, WasmError,
+    WasmResult,
 };
@@ -7,7 +7,8 @@
 //! [Wasmtime]: https://github.com/CraneStation/wasmtime
 
 use crate::translation_utils::{
-    FuncIndex, Global, GlobalIndex, Memory, MemoryIndex, SignatureIndex, Table, TableIndex,
+    reference_type, EventIndex, FuncIndex, Global, GlobalIndex, Memory, MemoryIndex,
+    SignatureIndex, Table, TableIndex,
 };
 use core::convert::From;
 use cranelift_codegen::cursor::FuncCursor;
@@ -16,6 +17,7 @@ use cranelift_codegen::ir::{self, InstBuilder};
 use cranelift_codegen::isa::TargetFrontendConfig;
 use failure_derive::Fail;
 use std::boxed::Box;
+use std::vec::Vec;
 use wasmparser::BinaryReaderError;
 
 /// The value of a WebAssembly global variable.
@@ -89,6 +91,15 @@ pub enum ReturnMode {
     FallthroughReturn,
 }
 
+/// How to translate the `memory.copy` and `memory.fill` bulk memory operators.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BulkMemoryStrategy {
+    /// Call the `memmove` and `memset` library routines.
+    LibCall,
+    /// Emit loops copying or filling one byte at a time.
+    InlineLoop,
+}
+
 /// Environment affecting the translation of a single WebAssembly function.
 ///
 /// A `FuncEnvironment` trait object is required to translate a WebAssembly function to Cranelift
@@ -110,6 +121,13 @@ pub trait FuncEnvironment {
         self.target_config().pointer_bytes()
     }
 
+    /// Get the Cranelift reference type to use for WebAssembly references.
+    ///
+    /// This returns `R64` for 64-bit architectures and `R32` for 32-bit architectures.
+    fn reference_type(&self) -> ir::Type {
+        reference_type(self.target_config())
+    }
+
     /// Set up the necessary preamble definitions in `func` to access the global variable
     /// identified by `index`.
     ///
@@ -123,6 +141,9 @@ pub trait FuncEnvironment {
     /// by `index`.
     ///
     /// The index space covers both imported and locally declared memories.
+    ///
+    /// The index type of the heap must be `i64` for 64-bit memories, and `i32` otherwise. The heap
+    /// of a shared memory must be marked as `shared`.
     fn make_heap(&mut self, func: &mut ir::Function, index: MemoryIndex) -> ir::Heap;
 
     /// Set up the necessary preamble definitions in `func` to access the table identified
@@ -163,6 +184,9 @@ pub trait FuncEnvironment {
     ///
     /// The signature `sig_ref` was previously created by `make_indirect_sig()`.
     ///
+    /// Environments whose table elements hold a function pointer and a signature id can use
+    /// `call_table_element()` to emit the bounds check, the signature check and the call.
+    ///
     /// Return the call instruction whose results are the WebAssembly return values.
     #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
     fn translate_call_indirect(
@@ -200,7 +224,7 @@ pub trait FuncEnvironment {
     ///
     /// The `val` value is the requested memory size in pages.
     ///
-    /// Returns the old size (in pages) of the memory.
+    /// Returns the old size (in pages) of the memory. Both have the index type of the heap.
     fn translate_memory_grow(
         &mut self,
         pos: FuncCursor,
@@ -214,7 +238,7 @@ pub trait FuncEnvironment {
     /// The `index` provided identifies the linear memory to query, and `heap` is the heap reference
     /// returned by `make_heap` for the same index.
     ///
-    /// Returns the size in pages of the memory.
+    /// Returns the size in pages of the memory, with the index type of the heap.
     fn translate_memory_size(
         &mut self,
         pos: FuncCursor,
@@ -222,6 +246,183 @@ pub trait FuncEnvironment {
         heap: ir::Heap,
     ) -> WasmResult<ir::Value>;
 
+    /// How should `memory.copy` and `memory.fill` be translated?
+    ///
+    /// Both operators are bounds checked against the memory size returned by
+    /// `translate_memory_size` before any byte is written.
+    fn bulk_memory_strategy(&self) -> BulkMemoryStrategy {
+        BulkMemoryStrategy::LibCall
+    }
+
+    /// Translate a `memory.init` WebAssembly instruction.
+    ///
+    /// Copy `len` bytes from offset `src` of the passive data segment `segment` to the address
+    /// `dst` in the linear memory `index`, trapping if either range is out of bounds. The `dst`
+    /// value has the index type of `heap`, while `src` and `len` have type `i32`.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    fn translate_memory_init(
+        &mut self,
+        _pos: FuncCursor,
+        _index: MemoryIndex,
+        _heap: ir::Heap,
+        _segment: u32,
+        _dst: ir::Value,
+        _src: ir::Value,
+        _len: ir::Value,
+    ) -> WasmResult<()> {
+        Err(WasmError::Unsupported("memory.init"))
+    }
+
+    /// Translate a `data.drop` WebAssembly instruction, discarding the passive data segment
+    /// `segment`.
+    fn translate_data_drop(&mut self, _pos: FuncCursor, _segment: u32) -> WasmResult<()> {
+        Err(WasmError::Unsupported("data.drop"))
+    }
+
+    /// Translate an `i32.atomic.wait` or `i64.atomic.wait` WebAssembly instruction.
+    ///
+    /// Block the current thread until it is notified on the native address `addr` in the linear
+    /// memory `index`, if the value at `addr` equals `expected`. The address is already bounds
+    /// checked and aligned to the type of `expected`, which is `i32` or `i64`. The `timeout` in
+    /// nanoseconds is an `i64`, negative for no timeout.
+    ///
+    /// Returns the `i32` result of the instruction: 0 when woken up, 1 when the value didn't
+    /// match, and 2 after a timeout.
+    #[cfg_attr(feature = "cargo-clippy", allow(clippy::too_many_arguments))]
+    fn translate_atomic_wait(
+        &mut self,
+        _pos: FuncCursor,
+        _index: MemoryIndex,
+        _heap: ir::Heap,
+        _addr: ir::Value,
+        _expected: ir::Value,
+        _timeout: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        Err(WasmError::Unsupported("atomic.wait"))
+    }
+
+    /// Translate an `atomic.notify` WebAssembly instruction.
+    ///
+    /// Wake up at most `count` threads waiting on the native address `addr` in the linear memory
+    /// `index`, and return the `i32` number of threads woken up. The address is already bounds
+    /// checked and aligned to 4 bytes.
+    fn translate_atomic_notify(
+        &mut self,
+        _pos: FuncCursor,
+        _index: MemoryIndex,
+        _heap: ir::Heap,
+        _addr: ir::Value,
+        _count: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        Err(WasmError::Unsupported("atomic.notify"))
+    }
+
+    /// Translate a `table.copy` WebAssembly instruction.
+    ///
+    /// Copy `len` elements from index `src` to index `dst` in the table `table_index`, trapping if
+    /// either range is out of bounds. The ranges may overlap. The `table` reference was returned
+    /// by `make_table` for the same index.
+    fn translate_table_copy(
+        &mut self,
+        _pos: FuncCursor,
+        _table_index: TableIndex,
+        _table: ir::Table,
+        _dst: ir::Value,
+        _src: ir::Value,
+        _len: ir::Value,
+    ) -> WasmResult<()> {
+        Err(WasmError::Unsupported("table.copy"))
+    }
+
+    /// Translate a `table.get` WebAssembly instruction.
+    ///
+    /// Return the reference at `index` in the table `table_index`, trapping if `index` is out of
+    /// bounds. The result has the type returned by `reference_type`.
+    fn translate_table_get(
+        &mut self,
+        _pos: FuncCursor,
+        _table_index: TableIndex,
+        _table: ir::Table,
+        _index: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        Err(WasmError::Unsupported("table.get"))
+    }
+
+    /// Translate a `table.set` WebAssembly instruction.
+    ///
+    /// Store the reference `value` at `index` in the table `table_index`, trapping if `index` is
+    /// out of bounds. An embedder with a garbage collector can insert its write barrier here.
+    fn translate_table_set(
+        &mut self,
+        _pos: FuncCursor,
+        _table_index: TableIndex,
+        _table: ir::Table,
+        _value: ir::Value,
+        _index: ir::Value,
+    ) -> WasmResult<()> {
+        Err(WasmError::Unsupported("table.set"))
+    }
+
+    /// Get the number of values carried by exceptions with the event `index`.
+    ///
+    /// The parser doesn't decode the event section yet, so the environment has to know the
+    /// signatures of the events of the module.
+    fn event_arity(&self, _index: EventIndex) -> WasmResult<usize> {
+        Err(WasmError::Unsupported("exception handling"))
+    }
+
+    /// Translate a `throw` WebAssembly instruction.
+    ///
+    /// Throw an exception with the event `index` carrying the values `args`. This must return a
+    /// direct `call` to a runtime function that never returns, so that it can unwind to the
+    /// landing pad of an enclosing `try`.
+    fn translate_throw(
+        &mut self,
+        _pos: FuncCursor,
+        _index: EventIndex,
+        _args: &[ir::Value],
+    ) -> WasmResult<ir::Inst> {
+        Err(WasmError::Unsupported("exception handling"))
+    }
+
+    /// Translate a `rethrow` WebAssembly instruction.
+    ///
+    /// Throw the exception `exn` again. Like `translate_throw`, this must return a direct `call`
+    /// to a runtime function that never returns.
+    fn translate_rethrow(&mut self, _pos: FuncCursor, _exn: ir::Value) -> WasmResult<ir::Inst> {
+        Err(WasmError::Unsupported("exception handling"))
+    }
+
+    /// Translate the beginning of a `catch` clause.
+    ///
+    /// This is inserted in the landing pad of a `try`, and returns the caught exception as a
+    /// reference of `reference_type()`.
+    fn translate_catch(&mut self, _pos: FuncCursor) -> WasmResult<ir::Value> {
+        Err(WasmError::Unsupported("exception handling"))
+    }
+
+    /// Test if the exception `exn` has the event `index`, for a `br_on_exn` instruction.
+    ///
+    /// The returned value is nonzero when the event matches.
+    fn translate_exception_matches(
+        &mut self,
+        _pos: FuncCursor,
+        _exn: ir::Value,
+        _index: EventIndex,
+    ) -> WasmResult<ir::Value> {
+        Err(WasmError::Unsupported("exception handling"))
+    }
+
+    /// Get the values carried by the exception `exn`, which is known to have the event `index`.
+    fn translate_exception_values(
+        &mut self,
+        _pos: FuncCursor,
+        _exn: ir::Value,
+        _index: EventIndex,
+    ) -> WasmResult<Vec<ir::Value>> {
+        Err(WasmError::Unsupported("exception handling"))
+    }
+
     /// Emit code at the beginning of every wasm loop.
     ///
     /// This can be used to insert explicit interrupt or safepoint checking at
@@ -343,12 +544,23 @@ pub trait ModuleEnvironment<'data> {
     ///
     /// Note there's no `reserve_function_bodies` function because the number of
     /// functions is already provided by `reserve_func_types`.
+    ///
+    /// Function bodies only depend on the declarations of the module, which all precede the code
+    /// section, so the environment doesn't have to translate them right away. It can keep the
+    /// bytes and translate them concurrently with `translate_function_bodies` once all of them
+    /// are provided.
     fn define_function_body(
         &mut self,
         body_bytes: &'data [u8],
         body_offset: usize,
     ) -> WasmResult<()>;
 
+    /// Indicates that all the function bodies have been provided. By default this does nothing,
+    /// but implementations can use this to translate the bodies they kept.
+    fn finish_function_bodies(&mut self) -> WasmResult<()> {
+        Ok(())
+    }
+
     /// Provides the number of data initializers up front. By default this does nothing, but
     /// implementations can use this to preallocate memory if desired.
     fn reserve_data_initializers(&mut self, _num: u32) {}
@@ -4,14 +4,19 @@
 //! function to Cranelift IR guided by a `FuncEnvironment` which provides information about the
 //! WebAssembly module and the runtime environment.
 
-use crate::code_translator::translate_operator;
+use crate::code_translator::{translate_exception_operator, translate_operator, ExceptionOperator};
 use crate::environ::{FuncEnvironment, ReturnMode, WasmResult};
 use crate::state::TranslationState;
+#[cfg(feature = "std")]
+use crate::translation_utils::DefinedFuncIndex;
 use cranelift_codegen::entity::EntityRef;
 use cranelift_codegen::ir::{self, Ebb, InstBuilder};
+#[cfg(feature = "std")]
+use cranelift_codegen::parallel;
 use cranelift_codegen::timing;
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
 use log::info;
+use std::vec::Vec;
 use wasmparser::{self, BinaryReader};
 
 /// WebAssembly to Cranelift IR function translator.
@@ -100,7 +105,7 @@ impl FuncTranslator {
         builder.append_ebb_params_for_function_returns(exit_block);
         self.state.initialize(&builder.func.signature, exit_block);
 
-        parse_local_decls(&mut reader, &mut builder, num_params)?;
+        parse_local_decls(&mut reader, &mut builder, num_params, environ)?;
         parse_function_body(reader, &mut builder, &mut self.state, environ)?;
 
         builder.finalize();
@@ -108,6 +113,50 @@ impl FuncTranslator {
     }
 }
 
+/// The code of a function body, as given to `ModuleEnvironment::define_function_body`.
+///
+/// It contains the declaration of the locals and the body, so it can be translated independently
+/// of the other function bodies of the module.
+#[derive(Clone, Copy, Debug)]
+pub struct FunctionBodyData<'data> {
+    /// The function code, see `FuncTranslator::translate`.
+    pub data: &'data [u8],
+
+    /// The offset of `data` in the module, used for source locations.
+    pub offset: usize,
+}
+
+/// Translate the function `bodies` of a module using up to `num_threads` threads.
+///
+/// Each thread has its own `FuncTranslator`, and calls `translate` with it for the next body
+/// until none are left. The bodies are numbered by their position in `bodies`, which should be
+/// their order in the code section. The results are returned in the same order, or the first
+/// error in that order is returned.
+///
+/// The `translate` function is called concurrently, so it should create its own
+/// `FuncEnvironment` for each body, sharing only immutable module information between threads.
+/// The pass timings of the worker threads are not included in
+/// `cranelift_codegen::timing::take_current()`.
+#[cfg(feature = "std")]
+pub fn translate_function_bodies<'data, T, F>(
+    bodies: &[FunctionBodyData<'data>],
+    num_threads: usize,
+    translate: F,
+) -> WasmResult<Vec<T>>
+where
+    T: Send,
+    F: Fn(&mut FuncTranslator, DefinedFuncIndex, FunctionBodyData<'data>) -> WasmResult<T> + Sync,
+{
+    parallel::map_parallel(
+        bodies.iter().cloned(),
+        num_threads,
+        FuncTranslator::new,
+        |trans, pos, body| translate(trans, DefinedFuncIndex::new(pos), body),
+    )
+    .into_iter()
+    .collect()
+}
+
 /// Declare local variables for the signature parameters that correspond to WebAssembly locals.
 ///
 /// Return the number of local variables declared.
@@ -135,10 +184,11 @@ fn declare_wasm_parameters(builder: &mut FunctionBuilder, entry_block: Ebb) -> u
 /// Parse the local variable declarations that precede the function body.
 ///
 /// Declare local variables, starting from `num_params`.
-fn parse_local_decls(
+fn parse_local_decls<FE: FuncEnvironment + ?Sized>(
     reader: &mut BinaryReader,
     builder: &mut FunctionBuilder,
     num_params: usize,
+    environ: &FE,
 ) -> WasmResult<()> {
     let mut next_local = num_params;
     let local_count = reader.read_local_count()?;
@@ -147,7 +197,7 @@ fn parse_local_decls(
     for _ in 0..local_count {
         builder.set_srcloc(cur_srcloc(reader));
         let (count, ty) = reader.read_local_decl(&mut locals_total)?;
-        declare_locals(builder, count, ty, &mut next_local);
+        declare_locals(builder, count, ty, &mut next_local, environ);
     }
 
     Ok(())
@@ -156,19 +206,25 @@ fn parse_local_decls(
 /// Declare `count` local variables of the same type, starting from `next_local`.
 ///
 /// Fail of too many locals are declared in the function, or if the type is not valid for a local.
-fn declare_locals(
+fn declare_locals<FE: FuncEnvironment + ?Sized>(
     builder: &mut FunctionBuilder,
     count: u32,
     wasm_type: wasmparser::Type,
     next_local: &mut usize,
+    environ: &FE,
 ) {
-    // All locals are initialized to 0.
+    // All locals are initialized to 0, or to a null reference.
     use wasmparser::Type::*;
     let zeroval = match wasm_type {
         I32 => builder.ins().iconst(ir::types::I32, 0),
         I64 => builder.ins().iconst(ir::types::I64, 0),
         F32 => builder.ins().f32const(ir::immediates::Ieee32::with_bits(0)),
         F64 => builder.ins().f64const(ir::immediates::Ieee64::with_bits(0)),
+        V128 => {
+            let zero = builder.ins().iconst(ir::types::I8, 0);
+            builder.ins().splat(ir::types::I8X16, zero)
+        }
+        AnyRef => builder.ins().null(environ.reference_type()),
         _ => panic!("invalid local type"),
     };
 
@@ -197,8 +253,17 @@ fn parse_function_body<FE: FuncEnvironment + ?Sized>(
     // Keep going until the final `End` operator which pops the outermost block.
     while !state.control_stack.is_empty() {
         builder.set_srcloc(cur_srcloc(&reader));
+        if let Some(op) = read_exception_operator(&mut reader)? {
+            translate_exception_operator(op, builder, state, environ)?;
+            continue;
+        }
+        if let Some((code, memory)) = read_multi_memory_operator(&mut reader)? {
+            let op = BinaryReader::new(&code).read_operator()?;
+            translate_operator(op, memory, builder, state, environ)?;
+            continue;
+        }
         let op = reader.read_operator()?;
-        translate_operator(op, builder, state, environ)?;
+        translate_operator(op, 0, builder, state, environ)?;
     }
 
     // The final `End` operator left us in the exit block where we need to manually add a return
@@ -225,6 +290,80 @@ fn parse_function_body<FE: FuncEnvironment + ?Sized>(
     Ok(())
 }
 
+/// Read the next operator in `reader` if it is an operator of the exception handling proposal.
+///
+/// The reader is left unchanged for any other operator.
+fn read_exception_operator(reader: &mut BinaryReader) -> WasmResult<Option<ExceptionOperator>> {
+    let mut peek = reader.clone();
+    let op = match peek.read_u8()? {
+        0x06 => ExceptionOperator::Try {
+            ty: peek.read_type()?,
+        },
+        0x07 => ExceptionOperator::Catch,
+        0x08 => ExceptionOperator::Throw {
+            index: peek.read_var_u32()?,
+        },
+        0x09 => ExceptionOperator::Rethrow,
+        0x0a => ExceptionOperator::BrOnExn {
+            relative_depth: peek.read_var_u32()?,
+            index: peek.read_var_u32()?,
+        },
+        _ => return Ok(None),
+    };
+    *reader = peek;
+    Ok(Some(op))
+}
+
+/// Flag in the alignment of a `memarg` indicating that a memory index follows, as proposed by
+/// multi-memory.
+const MEMORY_INDEX_FLAG: u32 = 0x40;
+
+/// Read the next operator in `reader` if its `memarg` has a memory index, as in the multi-memory
+/// proposal.
+///
+/// The parser doesn't decode the memory index, so this returns the operator encoded again without
+/// it, along with the memory index. The reader is left unchanged for any other operator.
+fn read_multi_memory_operator(reader: &mut BinaryReader) -> WasmResult<Option<(Vec<u8>, u32)>> {
+    let mut peek = reader.clone();
+    let mut code = Vec::new();
+    match peek.read_u8()? {
+        // Loads and stores.
+        opcode @ 0x28..=0x3e => code.push(opcode as u8),
+        // Atomic memory accesses.
+        0xfe => {
+            code.push(0xfe);
+            match peek.read_var_u32()? {
+                atomic @ 0x00..=0x02 | atomic @ 0x10..=0x4e => write_var_u32(&mut code, atomic),
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    }
+    let flags = peek.read_var_u32()?;
+    if flags & MEMORY_INDEX_FLAG == 0 {
+        return Ok(None);
+    }
+    let memory = peek.read_var_u32()?;
+    let offset = peek.read_var_u32()?;
+    write_var_u32(&mut code, flags & !MEMORY_INDEX_FLAG);
+    write_var_u32(&mut code, offset);
+    *reader = peek;
+    Ok(Some((code, memory)))
+}
+
+/// Append the LEB128 encoding of `value` to `code`.
+fn write_var_u32(code: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            code.push(byte);
+            return;
+        }
+        code.push(byte | 0x80);
+    }
+}
+
 /// Get the current source location from a reader.
 fn cur_srcloc(reader: &BinaryReader) -> ir::SourceLoc {
     // We record source locations as byte code offsets relative to the beginning of the file.
@@ -234,11 +373,13 @@ fn cur_srcloc(reader: &BinaryReader) -> ir::SourceLoc {
 
 #[cfg(test)]
 mod tests {
-    use super::{FuncTranslator, ReturnMode};
+    use super::{read_exception_operator, read_multi_memory_operator, FuncTranslator, ReturnMode};
+    use crate::code_translator::ExceptionOperator;
     use crate::environ::DummyEnvironment;
     use cranelift_codegen::ir::types::I32;
     use cranelift_codegen::{ir, isa, settings, Context};
     use log::debug;
+    use std::vec::Vec;
     use target_lexicon::PointerWidth;
 
     #[test]
@@ -362,4 +503,81 @@ mod tests {
         debug!("{}", ctx.func.display(None));
         ctx.verify(&flags).unwrap();
     }
+
+    #[test]
+    fn exception_operators() {
+        // (try (throw 2) (catch) (br_on_exn 1 3) (rethrow))
+        const BODY: [u8; 10] = [
+            0x06, 0x40, // try
+            0x08, 0x02, // throw 2
+            0x07, // catch
+            0x0a, 0x01, 0x03, // br_on_exn 1 3
+            0x09, // rethrow
+            0x0b, // end
+        ];
+
+        let mut reader = wasmparser::BinaryReader::new(&BODY);
+        let mut ops = Vec::new();
+        while let Some(op) = read_exception_operator(&mut reader).unwrap() {
+            ops.push(op);
+        }
+        assert_eq!(
+            ops,
+            [
+                ExceptionOperator::Try {
+                    ty: wasmparser::Type::EmptyBlockType
+                },
+                ExceptionOperator::Throw { index: 2 },
+                ExceptionOperator::Catch,
+                ExceptionOperator::BrOnExn {
+                    relative_depth: 1,
+                    index: 3
+                },
+                ExceptionOperator::Rethrow,
+            ]
+        );
+
+        // The other operators are left to the parser.
+        match reader.read_operator().unwrap() {
+            wasmparser::Operator::End => {}
+            op => panic!("unexpected operator {:?}", op),
+        }
+        assert!(reader.eof());
+    }
+
+    #[test]
+    fn multi_memory_operators() {
+        const BODY: [u8; 14] = [
+            0x28, 0x42, 0x01, 0x08, // i32.load memory=1 offset=8
+            0xfe, 0x10, 0x42, 0x80, 0x01, 0x00, // i32.atomic.load memory=128
+            0x28, 0x02, 0x04, // i32.load offset=4
+            0x0b, // end
+        ];
+
+        let mut reader = wasmparser::BinaryReader::new(&BODY);
+        let (code, memory) = read_multi_memory_operator(&mut reader).unwrap().unwrap();
+        assert_eq!((&code[..], memory), (&[0x28, 0x02, 0x08][..], 1));
+        match wasmparser::BinaryReader::new(&code)
+            .read_operator()
+            .unwrap()
+        {
+            wasmparser::Operator::I32Load { memarg } => {
+                assert_eq!((memarg.flags, memarg.offset), (2, 8));
+            }
+            op => panic!("unexpected operator {:?}", op),
+        }
+
+        let (code, memory) = read_multi_memory_operator(&mut reader).unwrap().unwrap();
+        assert_eq!((&code[..], memory), (&[0xfe, 0x10, 0x02, 0x00][..], 128));
+
+        // Accesses to the first memory without a memory index are left to the parser.
+        assert!(read_multi_memory_operator(&mut reader).unwrap().is_none());
+        match reader.read_operator().unwrap() {
+            wasmparser::Operator::I32Load { memarg } => {
+                assert_eq!((memarg.flags, memarg.offset), (2, 4));
+            }
+            op => panic!("unexpected operator {:?}", op),
+        }
+        assert!(read_multi_memory_operator(&mut reader).unwrap().is_none());
+    }
 }
@@ -51,15 +51,17 @@ mod state;
 mod translation_utils;
 
 pub use crate::environ::{
-    DummyEnvironment, FuncEnvironment, GlobalVariable, ModuleEnvironment, ReturnMode, WasmError,
-    WasmResult,
+    BulkMemoryStrategy, DummyEnvironment, FuncEnvironment, GlobalVariable, ModuleEnvironment,
+    ReturnMode, WasmError, WasmResult,
 };
-pub use crate::func_translator::FuncTranslator;
+#[cfg(feature = "std")]
+pub use crate::func_translator::translate_function_bodies;
+pub use crate::func_translator::{FuncTranslator, FunctionBodyData};
 pub use crate::module_translator::translate_module;
 pub use crate::translation_utils::{
-    DefinedFuncIndex, DefinedGlobalIndex, DefinedMemoryIndex, DefinedTableIndex, FuncIndex, Global,
-    GlobalIndex, GlobalInit, Memory, MemoryIndex, SignatureIndex, Table, TableElementType,
-    TableIndex,
+    call_table_element, DefinedFuncIndex, DefinedGlobalIndex, DefinedMemoryIndex,
+    DefinedTableIndex, EventIndex, FuncIndex, Global, GlobalIndex, GlobalInit, Memory, MemoryIndex,
+    SignatureIndex, Table, TableElementType, TableIndex,
 };
 
 /// Version number of this crate.
@@ -69,8 +69,7 @@ pub fn translate_module<'data>(
     }
 
     if let SectionCode::Memory = section.code {
-        let memories = section.get_memory_section_reader()?;
-        parse_memory_section(memories, environ)?;
+        parse_memory_section(section.get_binary_reader(), environ)?;
 
         reader.skip_custom_sections()?;
         if reader.eof() {
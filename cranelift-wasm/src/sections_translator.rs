@@ -7,7 +7,7 @@
 //! The special case of the initialize expressions for table elements offsets or global variables
 //! is handled, according to the semantics of WebAssembly, to only specific expressions that are
 //! interpreted on the fly.
-use crate::environ::{ModuleEnvironment, WasmResult};
+use crate::environ::{ModuleEnvironment, WasmError, WasmResult};
 use crate::translation_utils::{
     type_to_type, FuncIndex, Global, GlobalIndex, GlobalInit, Memory, MemoryIndex, SignatureIndex,
     Table, TableElementType, TableIndex,
@@ -17,12 +17,15 @@ use cranelift_codegen::ir::{self, AbiParam, Signature};
 use cranelift_entity::EntityRef;
 use std::vec::Vec;
 use wasmparser::{
-    self, CodeSectionReader, Data, DataSectionReader, Element, ElementSectionReader, Export,
-    ExportSectionReader, ExternalKind, FuncType, FunctionSectionReader, GlobalSectionReader,
-    GlobalType, ImportSectionEntryType, ImportSectionReader, MemorySectionReader, MemoryType,
+    self, BinaryReader, CodeSectionReader, Data, DataSectionReader, Element, ElementSectionReader,
+    Export, ExportSectionReader, ExternalKind, FuncType, FunctionSectionReader,
+    GlobalSectionReader, GlobalType, ImportSectionEntryType, ImportSectionReader, MemoryType,
     Operator, TableSectionReader, TypeSectionReader,
 };
 
+/// Flag in the limits of a memory type marking a 64-bit memory, as proposed by memory64.
+const MEMORY64_FLAG: u32 = 0x4;
+
 /// Parses the Type section of the wasm module.
 pub fn parse_type_section(
     types: TypeSectionReader,
@@ -38,13 +41,14 @@ pub fn parse_type_section(
                 ref returns,
             } => {
                 let mut sig = Signature::new(environ.target_config().default_call_conv);
+                let config = environ.target_config();
                 sig.params.extend(params.iter().map(|ty| {
-                    let cret_arg: ir::Type = type_to_type(*ty)
+                    let cret_arg: ir::Type = type_to_type(*ty, config)
                         .expect("only numeric types are supported in function signatures");
                     AbiParam::new(cret_arg)
                 }));
                 sig.returns.extend(returns.iter().map(|ty| {
-                    let cret_arg: ir::Type = type_to_type(*ty)
+                    let cret_arg: ir::Type = type_to_type(*ty, config)
                         .expect("only numeric types are supported in function signatures");
                     AbiParam::new(cret_arg)
                 }));
@@ -85,6 +89,8 @@ pub fn parse_import_section<'data>(
                         minimum: memlimits.initial,
                         maximum: memlimits.maximum,
                         shared,
+                        // The parser rejects the limits flag of 64-bit memories in imports.
+                        memory64: false,
                     },
                     module_name,
                     field_name,
@@ -93,7 +99,7 @@ pub fn parse_import_section<'data>(
             ImportSectionEntryType::Global(ref ty) => {
                 environ.declare_global_import(
                     Global {
-                        ty: type_to_type(ty.content_type).unwrap(),
+                        ty: type_to_type(ty.content_type, environ.target_config()).unwrap(),
                         mutability: ty.mutable,
                         initializer: GlobalInit::Import,
                     },
@@ -104,7 +110,7 @@ pub fn parse_import_section<'data>(
             ImportSectionEntryType::Table(ref tab) => {
                 environ.declare_table_import(
                     Table {
-                        ty: match type_to_type(tab.element_type) {
+                        ty: match type_to_type(tab.element_type, environ.target_config()) {
                             Ok(t) => TableElementType::Val(t),
                             Err(()) => TableElementType::Func,
                         },
@@ -147,7 +153,7 @@ pub fn parse_table_section(
     for entry in tables {
         let table = entry?;
         environ.declare_table(Table {
-            ty: match type_to_type(table.element_type) {
+            ty: match type_to_type(table.element_type, environ.target_config()) {
                 Ok(t) => TableElementType::Val(t),
                 Err(()) => TableElementType::Func,
             },
@@ -160,24 +166,76 @@ pub fn parse_table_section(
 }
 
 /// Parses the Memory section of the wasm module.
+///
+/// The section is decoded here rather than by the parser, which doesn't know the limits flag of
+/// 64-bit memories.
 pub fn parse_memory_section(
-    memories: MemorySectionReader,
+    mut memories: BinaryReader,
     environ: &mut ModuleEnvironment,
 ) -> WasmResult<()> {
-    environ.reserve_memories(memories.get_count());
+    let count = memories.read_var_u32()?;
+    environ.reserve_memories(count);
 
-    for entry in memories {
-        let memory = entry?;
+    for _ in 0..count {
+        let offset = memories.original_position();
+        let flags = memories.read_var_u32()?;
+        if flags & !(0x3 | MEMORY64_FLAG) != 0 {
+            return Err(WasmError::InvalidWebAssembly {
+                message: "invalid memory limits flags",
+                offset,
+            });
+        }
+        let memory64 = flags & MEMORY64_FLAG != 0;
+        let minimum = read_page_count(&mut memories, memory64)?;
+        let maximum = if flags & 0x1 != 0 {
+            Some(read_page_count(&mut memories, memory64)?)
+        } else {
+            None
+        };
         environ.declare_memory(Memory {
-            minimum: memory.limits.initial,
-            maximum: memory.limits.maximum,
-            shared: memory.shared,
+            minimum,
+            maximum,
+            shared: flags & 0x2 != 0,
+            memory64,
+        });
+    }
+
+    if !memories.eof() {
+        return Err(WasmError::InvalidWebAssembly {
+            message: "unexpected data at the end of the memory section",
+            offset: memories.original_position(),
         });
     }
 
     Ok(())
 }
 
+/// Read a page count in the limits of a memory type. It is a 64-bit integer for 64-bit memories.
+fn read_page_count(reader: &mut BinaryReader, memory64: bool) -> WasmResult<u32> {
+    if !memory64 {
+        return Ok(reader.read_var_u32()?);
+    }
+
+    let offset = reader.original_position();
+    let mut count = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = reader.read_u8()?;
+        count |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+        if shift >= 64 {
+            return Err(WasmError::InvalidWebAssembly {
+                message: "invalid var_u64",
+                offset,
+            });
+        }
+    }
+    cast::u32(count).map_err(|_| WasmError::ImplLimitExceeded)
+}
+
 /// Parses the Global section of the wasm module.
 pub fn parse_global_section(
     globals: GlobalSectionReader,
@@ -202,10 +260,11 @@ pub fn parse_global_section(
             Operator::GetGlobal { global_index } => {
                 GlobalInit::GetGlobal(GlobalIndex::from_u32(global_index))
             }
+            Operator::RefNull => GlobalInit::RefNullConst,
             ref s => panic!("unsupported init expr in global section: {:?}", s),
         };
         let global = Global {
-            ty: type_to_type(content_type).unwrap(),
+            ty: type_to_type(content_type, environ.target_config()).unwrap(),
             mutability: mutable,
             initializer,
         };
@@ -298,7 +357,7 @@ pub fn parse_code_section<'data>(
         let offset = reader.original_position();
         environ.define_function_body(reader.read_bytes(size)?, offset)?;
     }
-    Ok(())
+    environ.finish_function_bodies()
 }
 
 /// Parses the Data section of the wasm module.
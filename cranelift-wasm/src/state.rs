@@ -9,8 +9,8 @@ use crate::translation_utils::{FuncIndex, GlobalIndex, MemoryIndex, SignatureInd
 use cranelift_codegen::ir::{self, Ebb, Inst, Value};
 use std::vec::Vec;
 
-/// A control stack frame can be an `if`, a `block` or a `loop`, each one having the following
-/// fields:
+/// A control stack frame can be an `if`, a `block`, a `loop` or a `try`, each one having the
+/// following fields:
 ///
 /// - `destination`: reference to the `Ebb` that will hold the code after the control block;
 /// - `num_return_values`: number of values returned by the control block;
@@ -18,7 +18,10 @@ use std::vec::Vec;
 ///
 /// Moreover, the `if` frame has the `branch_inst` field that points to the `brz` instruction
 /// separating the `true` and `false` branch. The `loop` frame has a `header` field that references
-/// the `Ebb` that contains the beginning of the body of the loop.
+/// the `Ebb` that contains the beginning of the body of the loop. The `try` frame has a
+/// `landing_pad` field with the `Ebb` starting the `catch` clause, which is only created when a call
+/// in the body can unwind to it, and an `in_catch` field telling if the `catch` clause has been
+/// reached.
 #[derive(Debug)]
 pub enum ControlStackFrame {
     If {
@@ -41,6 +44,14 @@ pub enum ControlStackFrame {
         num_return_values: usize,
         original_stack_size: usize,
     },
+    Try {
+        destination: Ebb,
+        landing_pad: Option<Ebb>,
+        in_catch: bool,
+        num_return_values: usize,
+        original_stack_size: usize,
+        exit_is_branched_to: bool,
+    },
 }
 
 /// Helper methods for the control stack objects.
@@ -55,6 +66,9 @@ impl ControlStackFrame {
             }
             | ControlStackFrame::Loop {
                 num_return_values, ..
+            }
+            | ControlStackFrame::Try {
+                num_return_values, ..
             } => num_return_values,
         }
     }
@@ -62,13 +76,15 @@ impl ControlStackFrame {
         match *self {
             ControlStackFrame::If { destination, .. }
             | ControlStackFrame::Block { destination, .. }
-            | ControlStackFrame::Loop { destination, .. } => destination,
+            | ControlStackFrame::Loop { destination, .. }
+            | ControlStackFrame::Try { destination, .. } => destination,
         }
     }
     pub fn br_destination(&self) -> Ebb {
         match *self {
             ControlStackFrame::If { destination, .. }
-            | ControlStackFrame::Block { destination, .. } => destination,
+            | ControlStackFrame::Block { destination, .. }
+            | ControlStackFrame::Try { destination, .. } => destination,
             ControlStackFrame::Loop { header, .. } => header,
         }
     }
@@ -85,12 +101,18 @@ impl ControlStackFrame {
             | ControlStackFrame::Loop {
                 original_stack_size,
                 ..
+            }
+            | ControlStackFrame::Try {
+                original_stack_size,
+                ..
             } => original_stack_size,
         }
     }
     pub fn is_loop(&self) -> bool {
         match *self {
-            ControlStackFrame::If { .. } | ControlStackFrame::Block { .. } => false,
+            ControlStackFrame::If { .. }
+            | ControlStackFrame::Block { .. }
+            | ControlStackFrame::Try { .. } => false,
             ControlStackFrame::Loop { .. } => true,
         }
     }
@@ -104,6 +126,10 @@ impl ControlStackFrame {
             | ControlStackFrame::Block {
                 exit_is_branched_to,
                 ..
+            }
+            | ControlStackFrame::Try {
+                exit_is_branched_to,
+                ..
             } => exit_is_branched_to,
             ControlStackFrame::Loop { .. } => false,
         }
@@ -118,6 +144,10 @@ impl ControlStackFrame {
             | ControlStackFrame::Block {
                 ref mut exit_is_branched_to,
                 ..
+            }
+            | ControlStackFrame::Try {
+                ref mut exit_is_branched_to,
+                ..
             } => *exit_is_branched_to = true,
             ControlStackFrame::Loop { .. } => {}
         }
@@ -273,6 +303,39 @@ impl TranslationState {
             reachable_from_top: self.reachable,
         });
     }
+
+    // Push a try on the control stack.
+    pub fn push_try(&mut self, following_code: Ebb, num_result_types: usize) {
+        self.control_stack.push(ControlStackFrame::Try {
+            destination: following_code,
+            landing_pad: None,
+            in_catch: false,
+            original_stack_size: self.stack.len(),
+            num_return_values: num_result_types,
+            exit_is_branched_to: false,
+        });
+    }
+
+    /// Get the landing pad that calls unwind to, if they are in the body of a `try`.
+    ///
+    /// This is the landing pad of the innermost `try` whose `catch` clause hasn't been reached yet.
+    /// The landing pad is created with `create_ebb` the first time it is needed.
+    pub fn landing_pad<F>(&mut self, create_ebb: F) -> Option<Ebb>
+    where
+        F: FnOnce() -> Ebb,
+    {
+        for frame in self.control_stack.iter_mut().rev() {
+            if let ControlStackFrame::Try {
+                ref mut landing_pad,
+                in_catch: false,
+                ..
+            } = *frame
+            {
+                return Some(*landing_pad.get_or_insert_with(create_ebb));
+            }
+        }
+        None
+    }
 }
 
 /// Methods for handling entity references.
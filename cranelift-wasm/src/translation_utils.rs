@@ -1,7 +1,10 @@
 //! Helper functions and structures for the translation.
 use core::u32;
+use cranelift_codegen::cursor::FuncCursor;
 use cranelift_codegen::entity::entity_impl;
-use cranelift_codegen::ir;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{self, InstBuilder};
+use cranelift_codegen::isa::TargetFrontendConfig;
 use wasmparser;
 
 /// Index type of a function (imported or defined) inside the WebAssembly module.
@@ -34,6 +37,11 @@ entity_impl!(DefinedGlobalIndex);
 pub struct TableIndex(u32);
 entity_impl!(TableIndex);
 
+/// Index type of an exception event inside the WebAssembly module.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct EventIndex(u32);
+entity_impl!(EventIndex);
+
 /// Index type of a global variable (imported or defined) inside the WebAssembly module.
 #[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
 pub struct GlobalIndex(u32);
@@ -73,6 +81,8 @@ pub enum GlobalInit {
     F64Const(u64),
     /// A `get_global` of another global.
     GetGlobal(GlobalIndex),
+    /// A `ref.null`.
+    RefNullConst,
     ///< The global is imported from, and thus initialized by, a different module.
     Import,
 }
@@ -106,19 +116,67 @@ pub struct Memory {
     pub maximum: Option<u32>,
     /// Whether the memory may be shared between multiple threads.
     pub shared: bool,
+    /// Whether the memory is indexed with 64-bit addresses, as proposed by memory64.
+    pub memory64: bool,
+}
+
+/// Emit an indirect call through the element `callee` of the table of functions `table`.
+///
+/// Each element of the table holds a function pointer of type `pointer_type`, followed by the
+/// `i32` signature id of the function. The element is bounds checked with `table_addr`, and the
+/// call traps if the function pointer is null or if the signature id isn't `sig_id`. The ids are
+/// chosen by the environment, typically so that structurally equal signatures get the same id.
+///
+/// The `call_args` are passed to the function, which is called with the signature `sig_ref`.
+/// Returns the call instruction.
+pub fn call_table_element(
+    pos: &mut FuncCursor,
+    table: ir::Table,
+    callee: ir::Value,
+    pointer_type: ir::Type,
+    sig_ref: ir::SigRef,
+    sig_id: ir::Value,
+    call_args: &[ir::Value],
+) -> ir::Inst {
+    let element = pos.ins().table_addr(pointer_type, table, callee, 0);
+    let flags = ir::MemFlags::trusted();
+    let func_ptr = pos.ins().load(pointer_type, flags, element, 0);
+    pos.ins().trapz(func_ptr, ir::TrapCode::IndirectCallToNull);
+    let sig_id_offset = pointer_type.bytes() as i32;
+    let actual_sig_id = pos
+        .ins()
+        .load(ir::types::I32, flags, element, sig_id_offset);
+    let mismatch = pos.ins().icmp(IntCC::NotEqual, actual_sig_id, sig_id);
+    pos.ins().trapnz(mismatch, ir::TrapCode::BadSignature);
+    pos.ins().call_indirect(sig_ref, func_ptr, call_args)
 }
 
 /// Helper function translating wasmparser types to Cranelift types when possible.
-pub fn type_to_type(ty: wasmparser::Type) -> Result<ir::Type, ()> {
+///
+/// References are represented by the reference type with the pointer width of `config`.
+pub fn type_to_type(ty: wasmparser::Type, config: TargetFrontendConfig) -> Result<ir::Type, ()> {
     Ok(match ty {
         wasmparser::Type::I32 => ir::types::I32,
         wasmparser::Type::I64 => ir::types::I64,
         wasmparser::Type::F32 => ir::types::F32,
         wasmparser::Type::F64 => ir::types::F64,
+        wasmparser::Type::V128 => ir::types::I8X16,
+        wasmparser::Type::AnyRef => reference_type(config),
         _ => return Err(()),
     })
 }
 
+/// Get the Cranelift reference type used to represent WebAssembly references on the target.
+///
+/// This returns `R64` for 64-bit architectures and `R32` for 32-bit architectures.
+pub fn reference_type(config: TargetFrontendConfig) -> ir::Type {
+    match config.pointer_bits() {
+        32 => ir::types::R32,
+        64 => ir::types::R64,
+        _ => panic!("unsupported pointer width for references"),
+    }
+}
+
 /// Turns a `wasmparser` `f32` into a `Cranelift` one.
 pub fn f32_translation(x: wasmparser::Ieee32) -> ir::immediates::Ieee32 {
     ir::immediates::Ieee32::with_bits(x.bits())
@@ -136,7 +194,9 @@ pub fn num_return_values(ty: wasmparser::Type) -> usize {
         wasmparser::Type::I32
         | wasmparser::Type::F32
         | wasmparser::Type::I64
-        | wasmparser::Type::F64 => 1,
+        | wasmparser::Type::F64
+        | wasmparser::Type::V128
+        | wasmparser::Type::AnyRef => 1,
         _ => panic!("unsupported return value type"),
     }
 }
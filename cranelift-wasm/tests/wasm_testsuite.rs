@@ -7,13 +7,42 @@ use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use target_lexicon::triple;
 use wabt::wat2wasm;
 
 #[test]
 fn testsuite() {
+    let flags = Flags::new(settings::builder());
+    for path in wasmtests() {
+        handle_module(&path, &flags, ReturnMode::NormalReturns);
+    }
+}
+
+#[test]
+fn parallel_translation() {
+    let flags = Flags::new(settings::builder());
+    let isa = isa::lookup(triple!("riscv64")).unwrap().finish(flags);
+    for path in wasmtests() {
+        let data = read_module(&path);
+        let translate = |num_threads| {
+            let mut dummy_environ =
+                DummyEnvironment::new(isa.frontend_config(), ReturnMode::NormalReturns);
+            dummy_environ.set_translation_threads(num_threads);
+            translate_module(&data, &mut dummy_environ).unwrap();
+            dummy_environ
+                .info
+                .function_bodies
+                .values()
+                .map(|func| func.display(None).to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(translate(1), translate(4), "{:?}", path);
+    }
+}
+
+fn wasmtests() -> Vec<PathBuf> {
     let mut paths: Vec<_> = fs::read_dir("../wasmtests")
         .unwrap()
         .map(|r| r.unwrap())
@@ -28,11 +57,7 @@ fn testsuite() {
         })
         .collect();
     paths.sort_by_key(|dir| dir.path());
-    let flags = Flags::new(settings::builder());
-    for path in paths {
-        let path = path.path();
-        handle_module(&path, &flags, ReturnMode::NormalReturns);
-    }
+    paths.into_iter().map(|dir| dir.path()).collect()
 }
 
 #[test]
@@ -52,8 +77,8 @@ fn read_file(path: &Path) -> io::Result<Vec<u8>> {
     Ok(buf)
 }
 
-fn handle_module(path: &Path, flags: &Flags, return_mode: ReturnMode) {
-    let data = match path.extension() {
+fn read_module(path: &Path) -> Vec<u8> {
+    match path.extension() {
         None => {
             panic!("the file extension is not wasm or wat");
         }
@@ -70,7 +95,11 @@ fn handle_module(path: &Path, flags: &Flags, return_mode: ReturnMode) {
             }
             None | Some(&_) => panic!("the file extension for {:?} is not wasm or wat", path),
         },
-    };
+    }
+}
+
+fn handle_module(path: &Path, flags: &Flags, return_mode: ReturnMode) {
+    let data = read_module(path);
     let triple = triple!("riscv64");
     let isa = isa::lookup(triple).unwrap().finish(flags.clone());
     let mut dummy_environ = DummyEnvironment::new(isa.frontend_config(), return_mode);
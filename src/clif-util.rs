@@ -31,6 +31,8 @@ use std::process;
 mod cat;
 mod compile;
 mod print_cfg;
+mod souper;
+mod stats;
 mod utils;
 
 /// A command either succeeds or fails with an error message.
@@ -155,6 +157,44 @@ fn main() {
             SubCommand::with_name("print-cfg")
                 .about("Prints out cfg in dot format")
                 .arg(add_input_file_arg())
+                .arg(add_debug_flag())
+                .arg(
+                    Arg::with_name("domtree")
+                        .long("domtree")
+                        .help("Also print the dominator tree edges"),
+                )
+                .arg(
+                    Arg::with_name("loops")
+                        .long("loops")
+                        .help("Group the EBBs of each loop in a cluster"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("souper")
+                .about("Prints instruction DAGs for the Souper superoptimizer")
+                .arg(add_input_file_arg())
+                .arg(
+                    Arg::with_name("depth")
+                        .long("depth")
+                        .takes_value(true)
+                        .default_value("4")
+                        .help("Maximum depth of the DAGs"),
+                )
+                .arg(
+                    Arg::with_name("apply")
+                        .long("apply")
+                        .takes_value(true)
+                        .value_name("rewrites")
+                        .help("Apply the rewrites in a file and print the resulting IR"),
+                )
+                .arg(add_debug_flag()),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Prints statistics about the IR, before and after compilation")
+                .arg(add_input_file_arg())
+                .arg(add_set_flag())
+                .arg(add_target_flag())
                 .arg(add_debug_flag()),
         )
         .subcommand(
@@ -166,7 +206,13 @@ fn main() {
                 )
                 .arg(Arg::with_name("check-translation").short("c").help(
                     "Just checks the correctness of Cranelift IR translated from WebAssembly",
-                )),
+                ))
+                .arg(
+                    Arg::with_name("disasm")
+                        .short("D")
+                        .long("disasm")
+                        .help("Print the disassembly of the generated machine code"),
+                ),
         )
         .subcommand(add_wasm_or_compile("wasm"))
         .subcommand(
@@ -213,7 +259,33 @@ fn main() {
         }
         ("print-cfg", Some(rest_cmd)) => {
             handle_debug_flag(rest_cmd.is_present("debug"));
-            print_cfg::run(&get_vec(rest_cmd.values_of("file")))
+            print_cfg::run(
+                &get_vec(rest_cmd.values_of("file")),
+                rest_cmd.is_present("domtree"),
+                rest_cmd.is_present("loops"),
+            )
+        }
+        ("souper", Some(rest_cmd)) => {
+            handle_debug_flag(rest_cmd.is_present("debug"));
+            souper::run(
+                &get_vec(rest_cmd.values_of("file")),
+                rest_cmd.value_of("depth").unwrap(),
+                rest_cmd.value_of("apply"),
+            )
+        }
+        ("stats", Some(rest_cmd)) => {
+            handle_debug_flag(rest_cmd.is_present("debug"));
+
+            let mut target_val: &str = "";
+            if let Some(clap_target) = rest_cmd.value_of("target") {
+                target_val = clap_target;
+            }
+
+            stats::run(
+                &get_vec(rest_cmd.values_of("file")),
+                &get_vec(rest_cmd.values_of("set")),
+                target_val,
+            )
         }
         ("compile", Some(rest_cmd)) => {
             handle_debug_flag(rest_cmd.is_present("debug"));
@@ -226,6 +298,7 @@ fn main() {
             compile::run(
                 get_vec(rest_cmd.values_of("file")),
                 rest_cmd.is_present("print"),
+                rest_cmd.is_present("disasm"),
                 rest_cmd.is_present("time-passes"),
                 &get_vec(rest_cmd.values_of("set")),
                 target_val,
@@ -62,6 +62,7 @@ impl binemit::TrapSink for PrintTraps {
 pub fn run(
     files: Vec<String>,
     flag_print: bool,
+    flag_disasm: bool,
     flag_report_times: bool,
     flag_set: &[String],
     flag_isa: &str,
@@ -73,6 +74,7 @@ pub fn run(
         let name = String::from(path.as_os_str().to_string_lossy());
         handle_module(
             flag_print,
+            flag_disasm,
             flag_report_times,
             &path.to_path_buf(),
             &name,
@@ -84,6 +86,7 @@ pub fn run(
 
 fn handle_module(
     flag_print: bool,
+    flag_disasm: bool,
     flag_report_times: bool,
     path: &PathBuf,
     name: &str,
@@ -137,7 +140,13 @@ fn handle_module(
             }
 
             println!();
+        }
+
+        if flag_print || flag_disasm {
             print_disassembly(isa, &mem[0..code_sink.code_size as usize])?;
+        }
+
+        if flag_print {
             print_readonly_data(&mem[code_sink.code_size as usize..total_size as usize]);
         }
     }
@@ -173,68 +182,13 @@ fn print_readonly_data(mem: &[u8]) {
 
 cfg_if! {
     if #[cfg(feature = "disas")] {
-        use capstone::prelude::*;
-        use target_lexicon::Architecture;
-        use std::fmt::Write;
-
-        fn get_disassembler(isa: &TargetIsa) -> Result<Capstone, String> {
-            let cs = match isa.triple().architecture {
-                Architecture::Riscv32 | Architecture::Riscv64 => {
-                    return Err(String::from("No disassembler for RiscV"))
-                }
-                Architecture::I386 | Architecture::I586 | Architecture::I686 => Capstone::new()
-                    .x86()
-                    .mode(arch::x86::ArchMode::Mode32)
-                    .build(),
-                Architecture::X86_64 => Capstone::new()
-                    .x86()
-                    .mode(arch::x86::ArchMode::Mode64)
-                    .build(),
-                Architecture::Arm
-                | Architecture::Armv4t
-                | Architecture::Armv5te
-                | Architecture::Armv7
-                | Architecture::Armv7s => Capstone::new().arm().mode(arch::arm::ArchMode::Arm).build(),
-                Architecture::Thumbv6m | Architecture::Thumbv7em | Architecture::Thumbv7m => Capstone::new(
-                ).arm()
-                    .mode(arch::arm::ArchMode::Thumb)
-                    .build(),
-                Architecture::Aarch64 => Capstone::new()
-                    .arm64()
-                    .mode(arch::arm64::ArchMode::Arm)
-                    .build(),
-                _ => return Err(String::from("Unknown ISA")),
-            };
-
-            cs.map_err(|err| err.to_string())
-        }
+        use cranelift_filetests::disasm::{disassemble, disassembler};
 
         fn print_disassembly(isa: &TargetIsa, mem: &[u8]) -> Result<(), String> {
-            let mut cs = get_disassembler(isa)?;
+            let mut cs = disassembler(isa)?;
 
             println!("\nDisassembly of {} bytes:", mem.len());
-            let insns = cs.disasm_all(&mem, 0x0).unwrap();
-            for i in insns.iter() {
-                let mut line = String::new();
-
-                write!(&mut line, "{:4x}:\t", i.address()).unwrap();
-
-                let mut bytes_str = String::new();
-                for b in i.bytes() {
-                    write!(&mut bytes_str, "{:02x} ", b).unwrap();
-                }
-                write!(&mut line, "{:21}\t", bytes_str).unwrap();
-
-                if let Some(s) = i.mnemonic() {
-                    write!(&mut line, "{}\t", s).unwrap();
-                }
-
-                if let Some(s) = i.op_str() {
-                    write!(&mut line, "{}", s).unwrap();
-                }
-
-                println!("{}", line);
-            }
+            print!("{}", disassemble(&mut cs, mem, 0)?);
             Ok(())
         }
     } else {
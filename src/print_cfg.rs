@@ -1,24 +1,24 @@
 //! The `print-cfg` sub-command.
 //!
 //! Read a series of Cranelift IR files and print their control flow graphs
-//! in graphviz format.
+//! in graphviz format, optionally with dominator tree edges and loop clusters.
 
 use crate::utils::read_to_string;
 use crate::CommandResult;
 use cranelift_codegen::cfg_printer::CFGPrinter;
 use cranelift_reader::parse_functions;
 
-pub fn run(files: &[String]) -> CommandResult {
+pub fn run(files: &[String], flag_domtree: bool, flag_loops: bool) -> CommandResult {
     for (i, f) in files.into_iter().enumerate() {
         if i != 0 {
             println!();
         }
-        print_cfg(f)?
+        print_cfg(f, flag_domtree, flag_loops)?
     }
     Ok(())
 }
 
-fn print_cfg(filename: &str) -> CommandResult {
+fn print_cfg(filename: &str, flag_domtree: bool, flag_loops: bool) -> CommandResult {
     let buffer = read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
     let items = parse_functions(&buffer).map_err(|e| format!("{}: {}", filename, e))?;
 
@@ -26,7 +26,14 @@ fn print_cfg(filename: &str) -> CommandResult {
         if idx != 0 {
             println!();
         }
-        print!("{}", CFGPrinter::new(&func));
+        let mut printer = CFGPrinter::new(&func);
+        if flag_domtree {
+            printer = printer.with_domtree();
+        }
+        if flag_loops {
+            printer = printer.with_loops();
+        }
+        print!("{}", printer);
     }
 
     Ok(())
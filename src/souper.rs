@@ -0,0 +1,48 @@
+//! The `souper` sub-command.
+//!
+//! Read a series of Cranelift IR files and print the instruction DAGs in their functions in the
+//! syntax of the Souper superoptimizer, or apply rewrites found by the superoptimizer to them.
+
+use crate::utils::read_to_string;
+use crate::CommandResult;
+use cranelift_codegen::settings;
+use cranelift_codegen::Context;
+use cranelift_preopt::{apply_rewrites, write_souper_slices, Rewrites};
+use cranelift_reader::parse_functions;
+
+pub fn run(files: &[String], max_depth: &str, rewrites_file: Option<&str>) -> CommandResult {
+    let max_depth = max_depth
+        .parse()
+        .map_err(|e| format!("invalid depth {}: {}", max_depth, e))?;
+    let rewrites = match rewrites_file {
+        Some(filename) => {
+            let buffer = read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+            Some(Rewrites::parse(&buffer).map_err(|e| format!("{}: {}", filename, e))?)
+        }
+        None => None,
+    };
+
+    for filename in files {
+        let buffer = read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+        let items = parse_functions(&buffer).map_err(|e| format!("{}: {}", filename, e))?;
+
+        for func in items {
+            match rewrites {
+                Some(ref rewrites) => {
+                    let flags = settings::Flags::new(settings::builder());
+                    let mut context = Context::for_function(func);
+                    apply_rewrites(&mut context, rewrites, &flags)
+                        .map_err(|e| format!("{}: {}", filename, e))?;
+                    println!("{}", context.func.display(None));
+                }
+                None => {
+                    let mut text = String::new();
+                    write_souper_slices(&mut text, &func, max_depth)
+                        .map_err(|e| format!("{}: {}", filename, e))?;
+                    print!("{}", text);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! The `stats` sub-command.
+//!
+//! Read a series of Cranelift IR files and print statistics about each function. When a target
+//! ISA is available, the statistics are also printed after compiling the function, followed by
+//! the counts of the transformations made by the passes.
+
+use crate::utils::{parse_sets_and_triple, read_to_string};
+use crate::CommandResult;
+use cranelift_codegen::print_errors::pretty_error;
+use cranelift_codegen::settings::FlagsOrIsa;
+use cranelift_codegen::stats::FunctionStats;
+use cranelift_codegen::Context;
+use cranelift_reader::parse_test;
+use std::path::Path;
+
+pub fn run(files: &[String], flag_set: &[String], flag_isa: &str) -> CommandResult {
+    let parsed = parse_sets_and_triple(flag_set, flag_isa)?;
+
+    for (i, filename) in files.iter().enumerate() {
+        if i != 0 {
+            println!();
+        }
+        print_stats(Path::new(filename), parsed.as_fisa())?;
+    }
+    Ok(())
+}
+
+fn print_stats(path: &Path, fisa: FlagsOrIsa) -> CommandResult {
+    let name = path.as_os_str().to_string_lossy();
+    let buffer = read_to_string(path).map_err(|e| format!("{}: {}", name, e))?;
+    let test_file = parse_test(&buffer, None, None).map_err(|e| format!("{}: {}", name, e))?;
+
+    // If we have an isa from the command-line, use that. Otherwise if the
+    // file contains a unique isa, use that.
+    let isa = fisa.isa.or_else(|| test_file.isa_spec.unique_isa());
+
+    for (idx, (func, _)) in test_file.functions.into_iter().enumerate() {
+        if idx != 0 {
+            println!();
+        }
+        println!("function {}:", func.name);
+        print!("{}", FunctionStats::compute(&func));
+
+        if let Some(isa) = isa {
+            let mut context = Context::new();
+            context.func = func;
+            context
+                .compile(isa)
+                .map_err(|err| pretty_error(&context.func, Some(isa), err))?;
+
+            println!("after compilation for {}:", isa.name());
+            print!("{}", FunctionStats::compute(&context.func));
+            println!("passes:");
+            print!("{}", context.pass_stats);
+        }
+    }
+
+    Ok(())
+}
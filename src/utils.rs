@@ -64,7 +64,7 @@ pub fn parse_sets_and_triple(
     parse_options(
         flag_set.iter().map(|x| x.as_str()),
         &mut flag_builder,
-        Location { line_number: 0 },
+        Location::default(),
     )
     .map_err(|err| err.to_string())?;
 
@@ -85,7 +85,7 @@ pub fn parse_sets_and_triple(
             ),
         })?;
         // Apply the ISA-specific settings to `isa_builder`.
-        parse_options(words, &mut isa_builder, Location { line_number: 0 })
+        parse_options(words, &mut isa_builder, Location::default())
             .map_err(|err| err.to_string())?;
 
         Ok(OwnedFlagsOrIsa::Isa(
@@ -0,0 +1,32 @@
+;; Integer and floating point arithmetic.
+
+(module
+  (func (export "add") (param i32 i32) (result i32) (i32.add (get_local 0) (get_local 1)))
+  (func (export "div_s") (param i32 i32) (result i32) (i32.div_s (get_local 0) (get_local 1)))
+  (func (export "rem_u") (param i64 i64) (result i64) (i64.rem_u (get_local 0) (get_local 1)))
+  (func (export "rotl") (param i64 i64) (result i64) (i64.rotl (get_local 0) (get_local 1)))
+  (func (export "clz") (param i32) (result i32) (i32.clz (get_local 0)))
+  (func (export "wrap") (param i64) (result i32) (i32.wrap/i64 (get_local 0)))
+  (func (export "extend_s") (param i32) (result i64) (i64.extend_s/i32 (get_local 0)))
+  (func (export "fmul") (param f32 f32) (result f32) (f32.mul (get_local 0) (get_local 1)))
+  (func (export "fsqrt") (param f64) (result f64) (f64.sqrt (get_local 0)))
+  (func (export "fdiv") (param f64 f64) (result f64) (f64.div (get_local 0) (get_local 1)))
+  (func (export "trunc_s") (param f64) (result i32) (i32.trunc_s/f64 (get_local 0)))
+)
+
+(assert_return (invoke "add" (i32.const 1) (i32.const 1)) (i32.const 2))
+(assert_return (invoke "add" (i32.const 0x7fffffff) (i32.const 1)) (i32.const 0x80000000))
+(assert_return (invoke "div_s" (i32.const -7) (i32.const 2)) (i32.const -3))
+(assert_trap (invoke "div_s" (i32.const 1) (i32.const 0)) "integer divide by zero")
+(assert_return (invoke "rem_u" (i64.const -1) (i64.const 10)) (i64.const 5))
+(assert_return (invoke "rotl" (i64.const 0x8000000000000001) (i64.const 1)) (i64.const 3))
+(assert_return (invoke "clz" (i32.const 0)) (i32.const 32))
+(assert_return (invoke "clz" (i32.const 0x00008000)) (i32.const 16))
+(assert_return (invoke "wrap" (i64.const 0x100000005)) (i32.const 5))
+(assert_return (invoke "extend_s" (i32.const -1)) (i64.const -1))
+(assert_return (invoke "fmul" (f32.const 1.5) (f32.const -2)) (f32.const -3))
+(assert_return (invoke "fsqrt" (f64.const 0x1p+4)) (f64.const 4))
+(assert_return_canonical_nan (invoke "fsqrt" (f64.const -1)))
+(assert_return_canonical_nan (invoke "fdiv" (f64.const 0) (f64.const 0)))
+(assert_return (invoke "fdiv" (f64.const 1) (f64.const -0)) (f64.const -inf))
+(assert_return (invoke "trunc_s" (f64.const -3.9)) (i32.const -3))
@@ -0,0 +1,43 @@
+;; Structured control flow and calls.
+
+(module
+  (func $fac (export "fac") (param i64) (result i64)
+    (if (result i64) (i64.eqz (get_local 0))
+      (then (i64.const 1))
+      (else (i64.mul (get_local 0) (call $fac (i64.sub (get_local 0) (i64.const 1)))))
+    )
+  )
+  (func (export "sum") (param i32) (result i32) (local i32)
+    (block $done
+      (loop $again
+        (br_if $done (i32.eqz (get_local 0)))
+        (set_local 1 (i32.add (get_local 1) (get_local 0)))
+        (set_local 0 (i32.sub (get_local 0) (i32.const 1)))
+        (br $again)
+      )
+    )
+    (get_local 1)
+  )
+  (func (export "select") (param i32 i32 i32) (result i32)
+    (block $two
+      (block $one
+        (block $zero
+          (br_table $zero $one $two (get_local 0))
+        )
+        (return (get_local 1))
+      )
+      (return (get_local 2))
+    )
+    (i32.const -1)
+  )
+  (func (export "unreachable") (unreachable))
+)
+
+(assert_return (invoke "fac" (i64.const 0)) (i64.const 1))
+(assert_return (invoke "fac" (i64.const 20)) (i64.const 2432902008176640000))
+(assert_return (invoke "sum" (i32.const 0)) (i32.const 0))
+(assert_return (invoke "sum" (i32.const 100)) (i32.const 5050))
+(assert_return (invoke "select" (i32.const 0) (i32.const 10) (i32.const 20)) (i32.const 10))
+(assert_return (invoke "select" (i32.const 1) (i32.const 10) (i32.const 20)) (i32.const 20))
+(assert_return (invoke "select" (i32.const 7) (i32.const 10) (i32.const 20)) (i32.const -1))
+(assert_trap (invoke "unreachable") "unreachable")
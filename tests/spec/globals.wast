@@ -0,0 +1,25 @@
+;; Globals, start functions, and named modules.
+
+(module $counter
+  (global $count (export "count") (mut i32) (i32.const 10))
+  (global $step i64 (i64.const 5))
+  (func $start (set_global $count (i32.add (get_global $count) (i32.const 1))))
+  (func (export "bump") (result i32)
+    (set_global $count (i32.add (get_global $count) (i32.wrap/i64 (get_global $step))))
+    (get_global $count)
+  )
+  (start $start)
+)
+
+(assert_return (get "count") (i32.const 11))
+(assert_return (invoke "bump") (i32.const 16))
+(invoke "bump")
+(assert_return (get $counter "count") (i32.const 21))
+
+;; Modules with memories are only compiled.
+(module
+  (memory 1)
+  (func (export "load") (param i32) (result i32) (i32.load (get_local 0)))
+)
+(assert_return (invoke "load" (i32.const 0)) (i32.const 0))
+(assert_return (invoke $counter "bump") (i32.const 26))
@@ -0,0 +1,490 @@
+//! Run WebAssembly spec test scripts through translation, compilation, and execution.
+//!
+//! Each `.wast` script in `tests/spec`, and in the directory named by the
+//! `CRANELIFT_SPEC_TESTSUITE` environment variable if it is set, is parsed with wabt. Every module
+//! in a script is translated with the `DummyEnvironment` and compiled with the SimpleJIT backend,
+//! and the assertions on its exported functions and globals are checked by running them.
+//!
+//! The dummy environment doesn't lay out memories, tables, or imports in a way that can run, so
+//! modules using them are only translated and compiled. Traps can't be caught yet, so the
+//! `assert_trap` and `assert_exhaustion` commands are skipped, like the assertions on invalid and
+//! unlinkable modules.
+#![cfg(feature = "wasm")]
+
+use cranelift_codegen::ir::{self, types, AbiParam, InstBuilder, MemFlags};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_module::{FuncId, Linkage, Module};
+use cranelift_simplejit::{SimpleJITBackend, SimpleJITBuilder};
+use cranelift_wasm::{translate_module, DummyEnvironment, GlobalInit, ReturnMode, WasmError};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::mem;
+use std::path::{Path, PathBuf};
+use wabt::script::{Action, CommandKind, ScriptParser, Value};
+
+#[test]
+fn spec_tests() {
+    let mut dirs = vec![PathBuf::from("tests/spec")];
+    if let Some(dir) = env::var_os("CRANELIFT_SPEC_TESTSUITE") {
+        dirs.push(dir.into());
+    }
+    for dir in dirs {
+        let mut paths: Vec<_> = fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("reading {}: {}", dir.display(), e))
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "wast"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            run_script(&path);
+        }
+    }
+}
+
+/// A function exported by an instance, with the trampoline used to call it.
+struct ExportedFunc {
+    trampoline: *const u8,
+    params: Vec<ir::Type>,
+    returns: Vec<ir::Type>,
+}
+
+/// A module compiled and instantiated with the SimpleJIT backend.
+struct Instance {
+    /// The JIT module owning the compiled code.
+    _module: Module<SimpleJITBackend>,
+
+    /// Exported functions by name.
+    funcs: HashMap<String, ExportedFunc>,
+
+    /// The type and `vmctx` slot of exported globals by name.
+    globals: HashMap<String, (ir::Type, usize)>,
+
+    /// The VM context, laid out like the dummy environment expects: the base address of the first
+    /// memory followed by one 8-byte slot per global.
+    vmctx: Vec<u64>,
+}
+
+/// The signature of the trampolines, taking the `vmctx` and a buffer of 8-byte slots holding the
+/// arguments on entry and the results on exit.
+type Trampoline = extern "C" fn(*mut u64, *mut u64);
+
+/// Tally of the commands of a script.
+#[derive(Default)]
+struct Stats {
+    passed: usize,
+    skipped: usize,
+    failures: Vec<String>,
+}
+
+fn run_script(path: &Path) {
+    let source = fs::read(path).unwrap_or_else(|e| panic!("reading {}: {}", path.display(), e));
+    let mut parser =
+        ScriptParser::<f32, f64>::from_source_and_name(&source, &path.to_string_lossy())
+            .unwrap_or_else(|e| panic!("parsing {}: {:?}", path.display(), e));
+
+    // Instances are `None` for modules that could only be compiled.
+    let mut instances: Vec<Option<Instance>> = Vec::new();
+    let mut names = HashMap::new();
+    let mut stats = Stats::default();
+
+    while let Some(command) = parser
+        .next()
+        .unwrap_or_else(|e| panic!("parsing {}: {:?}", path.display(), e))
+    {
+        let line = command.line;
+        let result = match command.kind {
+            CommandKind::Module { module, name } => {
+                if let Some(name) = name {
+                    names.insert(name, instances.len());
+                }
+                match instantiate(&module.into_vec()) {
+                    Ok(instance) => {
+                        let runs = instance.is_ok();
+                        instances.push(instance.ok());
+                        Ok(runs)
+                    }
+                    Err(message) => {
+                        instances.push(None);
+                        Err(message)
+                    }
+                }
+            }
+            CommandKind::AssertReturn { action, expected } => {
+                match run_action(&mut instances, &names, action) {
+                    Some(Ok(results)) => check_results(&results, &expected).map(|()| true),
+                    Some(Err(message)) => Err(message),
+                    None => Ok(false),
+                }
+            }
+            CommandKind::AssertReturnCanonicalNan { action } => {
+                match run_action(&mut instances, &names, action) {
+                    Some(Ok(results)) => check_nan(&results, true).map(|()| true),
+                    Some(Err(message)) => Err(message),
+                    None => Ok(false),
+                }
+            }
+            CommandKind::AssertReturnArithmeticNan { action } => {
+                match run_action(&mut instances, &names, action) {
+                    Some(Ok(results)) => check_nan(&results, false).map(|()| true),
+                    Some(Err(message)) => Err(message),
+                    None => Ok(false),
+                }
+            }
+            CommandKind::PerformAction(action) => {
+                match run_action(&mut instances, &names, action) {
+                    Some(Ok(_)) => Ok(true),
+                    Some(Err(message)) => Err(message),
+                    None => Ok(false),
+                }
+            }
+            _ => Ok(false),
+        };
+        match result {
+            Ok(true) => stats.passed += 1,
+            Ok(false) => stats.skipped += 1,
+            Err(message) => {
+                stats
+                    .failures
+                    .push(format!("{}:{}: {}", path.display(), line, message))
+            }
+        }
+    }
+
+    println!(
+        "{}: {} passed, {} skipped, {} failed",
+        path.display(),
+        stats.passed,
+        stats.skipped,
+        stats.failures.len()
+    );
+    if !stats.failures.is_empty() {
+        panic!("{}", stats.failures.join("\n"));
+    }
+}
+
+/// Translate and compile the module `wasm`.
+///
+/// Returns the instance if the module can run, or the reason it was only compiled.
+fn instantiate(wasm: &[u8]) -> Result<Result<Instance, &'static str>, String> {
+    let mut module: Module<SimpleJITBackend> = Module::new(SimpleJITBuilder::new());
+    let mut environ = DummyEnvironment::new(module.target_config(), ReturnMode::NormalReturns);
+    match translate_module(wasm, &mut environ) {
+        Ok(()) => {}
+        Err(WasmError::Unsupported(feature)) => return Ok(Err(feature)),
+        Err(e) => return Err(format!("translation failed: {}", e)),
+    }
+    let info = &environ.info;
+
+    if !info.imported_funcs.is_empty()
+        || !info.imported_globals.is_empty()
+        || !info.tables.is_empty()
+        || !info.memories.is_empty()
+    {
+        for func in info.function_bodies.values() {
+            let mut ctx = Context::for_function(func.clone());
+            ctx.compile(module.isa())
+                .map_err(|e| format!("compilation failed: {}", e))?;
+        }
+        return Ok(Err("imports, tables, and memories can't run"));
+    }
+
+    // Without imports, the function indexes are the `FuncId`s the dummy environment names its
+    // callees with.
+    let mut ids = Vec::new();
+    for (index, func) in info.function_bodies.values().enumerate() {
+        let id = module
+            .declare_function(&format!("wasm{}", index), Linkage::Local, &func.signature)
+            .map_err(|e| e.to_string())?;
+        ids.push(id);
+    }
+    for (&id, func) in ids.iter().zip(info.function_bodies.values()) {
+        let mut ctx = Context::for_function(func.clone());
+        module
+            .define_function(id, &mut ctx)
+            .map_err(|e| format!("compilation failed: {}", e))?;
+    }
+
+    let mut func_ctx = FunctionBuilderContext::new();
+    let mut trampolines = Vec::new();
+    for (&id, func) in ids.iter().zip(info.function_bodies.values()) {
+        trampolines.push(make_trampoline(
+            &mut module,
+            id,
+            &func.signature,
+            &mut func_ctx,
+        )?);
+    }
+    module.finalize_definitions();
+
+    let wasm_types = |params: &[AbiParam]| -> Vec<ir::Type> {
+        params
+            .iter()
+            .filter(|p| p.purpose == ir::ArgumentPurpose::Normal)
+            .map(|p| p.value_type)
+            .collect()
+    };
+    let mut funcs = HashMap::new();
+    for ((exportable, func), &trampoline) in info
+        .functions
+        .values()
+        .zip(info.function_bodies.values())
+        .zip(&trampolines)
+    {
+        for name in &exportable.export_names {
+            funcs.insert(
+                name.clone(),
+                ExportedFunc {
+                    trampoline: module.get_finalized_function(trampoline),
+                    params: wasm_types(&func.signature.params),
+                    returns: wasm_types(&func.signature.returns),
+                },
+            );
+        }
+    }
+    let start = info
+        .start_func
+        .map(|index| module.get_finalized_function(trampolines[index.as_u32() as usize]));
+
+    let mut vmctx = vec![0; 1 + info.globals.len()];
+    let mut globals = HashMap::new();
+    for (index, exportable) in info.globals.values().enumerate() {
+        let global = &exportable.entity;
+        vmctx[1 + index] = match global.initializer {
+            GlobalInit::I32Const(x) => u64::from(x as u32),
+            GlobalInit::I64Const(x) => x as u64,
+            GlobalInit::F32Const(bits) => u64::from(bits),
+            GlobalInit::F64Const(bits) => bits,
+            GlobalInit::GetGlobal(other) => vmctx[1 + other.as_u32() as usize],
+            GlobalInit::RefNullConst => 0,
+            GlobalInit::Import => unreachable!("imported globals can't run"),
+        };
+        for name in &exportable.export_names {
+            globals.insert(name.clone(), (global.ty, 1 + index));
+        }
+    }
+
+    let mut instance = Instance {
+        _module: module,
+        funcs,
+        globals,
+        vmctx,
+    };
+    if let Some(start) = start {
+        call(&mut instance, start, &mut []);
+    }
+    Ok(Ok(instance))
+}
+
+/// Define a trampoline calling `callee` with the arguments in a buffer, and storing the results
+/// in the same buffer.
+fn make_trampoline(
+    module: &mut Module<SimpleJITBackend>,
+    callee: FuncId,
+    signature: &ir::Signature,
+    func_ctx: &mut FunctionBuilderContext,
+) -> Result<FuncId, String> {
+    let pointer_type = module.target_config().pointer_type();
+    let mut sig = module.make_signature();
+    sig.params.push(AbiParam::new(pointer_type));
+    sig.params.push(AbiParam::new(pointer_type));
+    let id = module
+        .declare_function(
+            &format!("trampoline{}", callee.as_u32()),
+            Linkage::Local,
+            &sig,
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut ctx = module.make_context();
+    ctx.func.signature = sig;
+    ctx.func.name = ir::ExternalName::user(0, id.as_u32());
+    {
+        let mut builder = FunctionBuilder::new(&mut ctx.func, func_ctx);
+        let ebb = builder.create_ebb();
+        builder.append_ebb_params_for_function_params(ebb);
+        builder.switch_to_block(ebb);
+        builder.seal_block(ebb);
+        let vmctx = builder.ebb_params(ebb)[0];
+        let values = builder.ebb_params(ebb)[1];
+
+        let mut args = Vec::new();
+        for (i, param) in signature.params.iter().enumerate() {
+            args.push(match param.purpose {
+                ir::ArgumentPurpose::VMContext => vmctx,
+                _ => builder.ins().load(
+                    param.value_type,
+                    MemFlags::trusted(),
+                    values,
+                    (i * 8) as i32,
+                ),
+            });
+        }
+        let callee = module.declare_func_in_func(callee, &mut builder.func);
+        let call = builder.ins().call(callee, &args);
+        let results = builder.inst_results(call).to_vec();
+        for (i, result) in results.into_iter().enumerate() {
+            builder
+                .ins()
+                .store(MemFlags::trusted(), result, values, (i * 8) as i32);
+        }
+        builder.ins().return_(&[]);
+        builder.finalize();
+    }
+    module
+        .define_function(id, &mut ctx)
+        .map_err(|e| format!("compiling trampoline: {}", e))?;
+    Ok(id)
+}
+
+/// Call a trampoline of `instance` with the buffer `values`.
+fn call(instance: &mut Instance, trampoline: *const u8, values: &mut [u64]) {
+    let trampoline = unsafe { mem::transmute::<_, Trampoline>(trampoline) };
+    trampoline(instance.vmctx.as_mut_ptr(), values.as_mut_ptr());
+}
+
+/// Run `action` on its module, returning the typed results.
+///
+/// Returns `None` if the module could only be compiled.
+fn run_action(
+    instances: &mut [Option<Instance>],
+    names: &HashMap<String, usize>,
+    action: Action<f32, f64>,
+) -> Option<Result<Vec<(ir::Type, u64)>, String>> {
+    let (module, field) = match action {
+        Action::Invoke {
+            ref module,
+            ref field,
+            ..
+        }
+        | Action::Get {
+            ref module,
+            ref field,
+        } => (module.clone(), field.clone()),
+    };
+    let index = match module {
+        Some(name) => match names.get(&name) {
+            Some(&index) => index,
+            None => return Some(Err(format!("unknown module {}", name))),
+        },
+        None => match instances.len() {
+            0 => return Some(Err("no module".to_string())),
+            len => len - 1,
+        },
+    };
+    let instance = instances[index].as_mut()?;
+
+    Some(match action {
+        Action::Invoke { args, .. } => invoke(instance, &field, &args),
+        Action::Get { .. } => match instance.globals.get(&field) {
+            Some(&(ty, slot)) => Ok(vec![(ty, instance.vmctx[slot])]),
+            None => Err(format!("unknown global {}", field)),
+        },
+    })
+}
+
+/// Call the exported function `field` of `instance` with `args`.
+fn invoke(
+    instance: &mut Instance,
+    field: &str,
+    args: &[Value<f32, f64>],
+) -> Result<Vec<(ir::Type, u64)>, String> {
+    let (trampoline, params, returns) = match instance.funcs.get(field) {
+        Some(func) => (func.trampoline, func.params.clone(), func.returns.clone()),
+        None => return Err(format!("unknown function {}", field)),
+    };
+    let args = args.iter().map(value_bits).collect::<Result<Vec<_>, _>>()?;
+    if args.iter().map(|&(ty, _)| ty).ne(params.iter().cloned()) {
+        return Err(format!("wrong arguments for {}", field));
+    }
+
+    let mut values = vec![0; params.len().max(returns.len())];
+    for (slot, &(_, bits)) in values.iter_mut().zip(&args) {
+        *slot = bits;
+    }
+    call(instance, trampoline, &mut values);
+    Ok(returns
+        .iter()
+        .zip(values)
+        .map(|(&ty, bits)| (ty, bits & mask(ty)))
+        .collect())
+}
+
+/// Get the type and bit pattern of a script value.
+fn value_bits(value: &Value<f32, f64>) -> Result<(ir::Type, u64), String> {
+    match *value {
+        Value::I32(x) => Ok((types::I32, u64::from(x as u32))),
+        Value::I64(x) => Ok((types::I64, x as u64)),
+        Value::F32(x) => Ok((types::F32, u64::from(x.to_bits()))),
+        Value::F64(x) => Ok((types::F64, x.to_bits())),
+        #[allow(unreachable_patterns)]
+        _ => Err("unsupported value type".to_string()),
+    }
+}
+
+/// Get the mask of the bits of a value of type `ty` in a slot.
+fn mask(ty: ir::Type) -> u64 {
+    if ty.bits() == 64 {
+        !0
+    } else {
+        (1 << ty.bits()) - 1
+    }
+}
+
+/// Check that `results` are bitwise equal to `expected`.
+fn check_results(results: &[(ir::Type, u64)], expected: &[Value<f32, f64>]) -> Result<(), String> {
+    let expected = expected
+        .iter()
+        .map(value_bits)
+        .collect::<Result<Vec<_>, _>>()?;
+    if results == &expected[..] {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {}, got {}",
+            display_values(&expected),
+            display_values(results)
+        ))
+    }
+}
+
+/// Check that the single result in `results` is a NaN.
+///
+/// A canonical NaN has only the most significant bit of its payload set. An arithmetic NaN has
+/// at least that bit set.
+fn check_nan(results: &[(ir::Type, u64)], canonical: bool) -> Result<(), String> {
+    let (ty, bits) = match results {
+        [result] => *result,
+        _ => return Err(format!("expected a NaN, got {}", display_values(results))),
+    };
+    let (quiet_bit, payload) = match ty {
+        types::F32 => (1 << 22, (1 << 23) - 1),
+        types::F64 => (1 << 51, (1 << 52) - 1),
+        _ => return Err(format!("expected a NaN, got {}", display_values(results))),
+    };
+    let exponent = !payload & (mask(ty) >> 1);
+    let is_nan = bits & exponent == exponent && bits & payload != 0;
+    let ok = if canonical {
+        is_nan && bits & payload == quiet_bit
+    } else {
+        is_nan && bits & quiet_bit != 0
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!(
+            "expected {} NaN, got {}",
+            if canonical { "canonical" } else { "arithmetic" },
+            display_values(results)
+        ))
+    }
+}
+
+fn display_values(values: &[(ir::Type, u64)]) -> String {
+    let values: Vec<_> = values
+        .iter()
+        .map(|&(ty, bits)| format!("{}:{:#x}", ty, bits))
+        .collect();
+    format!("[{}]", values.join(", "))
+}